@@ -0,0 +1,219 @@
+-- portty.nvim - answer portty file chooser sessions from Neovim
+--
+-- Install:  portty nvim > ~/.config/nvim/lua/portty.lua
+-- Enable:   require("portty").setup()
+--
+-- Talks to porttyd through `portty nvim --nvim-rpc` (JSON-RPC 2.0 over
+-- stdio). New file-chooser sessions open a Telescope picker when Telescope is
+-- installed, otherwise `vim.ui.input`. The selection is written to the
+-- session's `submission` file and submitted; closing the picker leaves the
+-- session open (use :PorttyCancel to dismiss it).
+
+local M = {}
+
+M.config = {
+  cmd = "portty",
+  -- Poll interval for new sessions, in milliseconds (0 disables auto-open)
+  poll = 1000,
+  -- "telescope", "ui", or nil to pick automatically
+  picker = nil,
+}
+
+local job
+local next_id = 0
+local callbacks = {}
+local seen = {}
+
+local function notify(msg, level)
+  vim.notify("portty: " .. msg, level or vim.log.levels.INFO)
+end
+
+local function on_stdout(_, data)
+  for _, line in ipairs(data) do
+    if line ~= "" then
+      local ok, msg = pcall(vim.json.decode, line)
+      if ok and msg.id and callbacks[msg.id] then
+        local cb = callbacks[msg.id]
+        callbacks[msg.id] = nil
+        cb(msg.error, msg.result)
+      end
+    end
+  end
+end
+
+local function ensure_job()
+  if job then
+    return true
+  end
+  job = vim.fn.jobstart({ M.config.cmd, "nvim", "--nvim-rpc" }, {
+    on_stdout = on_stdout,
+    on_exit = function()
+      job = nil
+      callbacks = {}
+    end,
+  })
+  if job <= 0 then
+    job = nil
+    notify("failed to start `" .. M.config.cmd .. " nvim --nvim-rpc`", vim.log.levels.ERROR)
+    return false
+  end
+  return true
+end
+
+--- Call a daemon method. `cb(err, result)` runs on the main loop.
+function M.request(method, params, cb)
+  if not ensure_job() then
+    return
+  end
+  next_id = next_id + 1
+  callbacks[next_id] = vim.schedule_wrap(cb or function(err)
+    if err then
+      notify(err.message, vim.log.levels.ERROR)
+    end
+  end)
+  local msg = { jsonrpc = "2.0", id = next_id, method = method, params = params or vim.empty_dict() }
+  vim.fn.chansend(job, vim.json.encode(msg) .. "\n")
+end
+
+local function read_options(session)
+  local ok, lines = pcall(vim.fn.readfile, session.dir .. "/options.json")
+  if not ok then
+    return {}
+  end
+  return vim.json.decode(table.concat(lines, "\n"))
+end
+
+local function submit(session, paths)
+  vim.fn.writefile(paths, session.dir .. "/submission")
+  M.request("submit", { session_id = session.id }, function(err)
+    if err then
+      notify(err.message, vim.log.levels.ERROR)
+    end
+  end)
+end
+
+local function pick_telescope(session, opts, multiple, directory)
+  local actions = require("telescope.actions")
+  local state = require("telescope.actions.state")
+  local builtin = require("telescope.builtin")
+  local cwd = opts.current_folder or vim.fn.getcwd()
+
+  local picker_opts = {
+    prompt_title = session.title or "portty",
+    cwd = cwd,
+    attach_mappings = function(bufnr)
+      actions.select_default:replace(function()
+        local picker = state.get_current_picker(bufnr)
+        local selected = {}
+        for _, entry in ipairs(multiple and picker:get_multi_selection() or {}) do
+          table.insert(selected, vim.fs.joinpath(cwd, entry[1]))
+        end
+        if #selected == 0 then
+          local entry = state.get_selected_entry()
+          if entry then
+            table.insert(selected, vim.fs.joinpath(cwd, entry[1]))
+          end
+        end
+        actions.close(bufnr)
+        submit(session, selected)
+      end)
+      return true
+    end,
+  }
+
+  if directory then
+    picker_opts.find_command = { "find", ".", "-mindepth", "1", "-type", "d" }
+  end
+  builtin.find_files(picker_opts)
+end
+
+local function pick_ui(session, opts, default)
+  vim.ui.input({
+    prompt = (session.title or "portty") .. ": ",
+    default = default or ((opts.current_folder or vim.fn.getcwd()) .. "/"),
+    completion = "file",
+  }, function(input)
+    if input and input ~= "" then
+      submit(session, { vim.fn.fnamemodify(input, ":p") })
+    end
+  end)
+end
+
+--- Present a session in a picker
+function M.open(session)
+  if session.portal ~= "file-chooser" then
+    notify(session.portal .. " sessions are not handled here", vim.log.levels.WARN)
+    return
+  end
+
+  local opts = read_options(session)
+  local mode = opts.mode or {}
+
+  if mode == "Save" or mode == "SaveMultiple" then
+    local folder = opts.current_folder or vim.fn.getcwd()
+    local name = (opts.candidates or {})[1]
+    pick_ui(session, opts, name and (folder .. "/" .. name) or (folder .. "/"))
+    return
+  end
+
+  local pick = mode.Pick or {}
+  local picker = M.config.picker
+  if picker == nil then
+    picker = pcall(require, "telescope") and "telescope" or "ui"
+  end
+
+  if picker == "telescope" then
+    pick_telescope(session, opts, pick.multiple, pick.directory)
+  else
+    pick_ui(session, opts)
+  end
+end
+
+--- Open the earliest active session
+function M.pick()
+  M.request("list", nil, function(err, sessions)
+    if err then
+      notify(err.message, vim.log.levels.ERROR)
+    elseif not sessions or #sessions == 0 then
+      notify("no active sessions")
+    else
+      table.sort(sessions, function(a, b)
+        return a.created < b.created
+      end)
+      M.open(sessions[1])
+    end
+  end)
+end
+
+local function poll()
+  M.request("list", nil, function(err, sessions)
+    if err or not sessions then
+      return
+    end
+    local alive = {}
+    for _, session in ipairs(sessions) do
+      alive[session.id] = true
+      if not seen[session.id] then
+        seen[session.id] = true
+        M.open(session)
+      end
+    end
+    seen = alive
+  end)
+end
+
+function M.setup(opts)
+  M.config = vim.tbl_extend("force", M.config, opts or {})
+
+  vim.api.nvim_create_user_command("PorttyPick", M.pick, {})
+  vim.api.nvim_create_user_command("PorttyCancel", function()
+    M.request("cancel")
+  end, {})
+
+  if M.config.poll > 0 then
+    local timer = vim.uv.new_timer()
+    timer:start(M.config.poll, M.config.poll, vim.schedule_wrap(poll))
+  end
+end
+
+return M
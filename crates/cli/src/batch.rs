@@ -0,0 +1,515 @@
+//! `portty batch` - run a script of queue commands
+//!
+//! Reads commands from a file (or stdin), one per line, to provision queued
+//! submissions in one go, e.g. for automated test environments:
+//!
+//! ```text
+//! # Lines starting with # are comments
+//! select /srv/fixtures/report.pdf "/srv/fixtures/with space.txt"
+//! deselect /srv/fixtures/with\ space.txt
+//! submit file-chooser --queue-policy=interactive-first
+//! set color #ff8800
+//! submit screenshot
+//! ```
+//!
+//! Commands: `select PATH...`, `deselect PATH...`, `add|set|remove FAMILY
+//! ITEM...`, `clear`, and `submit [PORTAL] [--allow-empty] [--confirm]
+//! [--queue-policy=POLICY] [--sort=ORDER]`. Words split on whitespace; quotes and backslashes
+//! work as in the shell, without expansions.
+//!
+//! The whole script is parsed and its selection changes applied in memory
+//! before anything is touched, so a bad line leaves the queue as it was. The
+//! submits then go to the daemon over one connection; if one is refused, the
+//! submissions the script already queued are withdrawn and the pending queue
+//! restored. Selections after the last `submit` stay pending.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use libportty::client::{ClientError, DaemonClient};
+use libportty::exit::ExitStatus;
+use libportty::portal::intent::queue;
+use libportty::portal::{Intent, IntentItem, MergeOp, parse_item};
+use libportty::{Portal, Request, Response, SubmitOptions, paths};
+
+/// One script command
+#[derive(Debug)]
+enum Step {
+    Add(Vec<IntentItem>),
+    Set(Vec<IntentItem>),
+    Remove(Vec<IntentItem>),
+    Clear,
+    Submit(SubmitOptions),
+}
+
+/// A `submit` with the selection it sends
+#[derive(Debug)]
+struct Submission {
+    line: usize,
+    intent: Intent,
+    options: SubmitOptions,
+}
+
+pub fn run(file: Option<&Path>, session_id: Option<String>) -> ExitCode {
+    let script = match read_script(file) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+
+    let steps = match parse_script(&script) {
+        Ok(steps) => steps,
+        Err(errors) => {
+            for e in errors {
+                eprintln!("Error: {e}");
+            }
+            return ExitStatus::Invalid.into();
+        }
+    };
+
+    let pending = paths::pending_dir();
+    let original = queue::read(&pending);
+    let (submissions, leftover) = match plan(original.clone().unwrap_or_default(), steps) {
+        Ok(plan) => plan,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Invalid.into();
+        }
+    };
+
+    if let Err((e, status)) = execute(&submissions, session_id) {
+        restore_pending(&pending, original.as_ref());
+        eprintln!("Error: {e}");
+        return status.into();
+    }
+    if let Err(e) = write_pending(&pending, &leftover) {
+        eprintln!("Error: {e}");
+        return ExitStatus::Failure.into();
+    }
+
+    println!("Batch done: {} submission(s)", submissions.len());
+    if !leftover.is_empty() {
+        println!("Left {} item(s) pending", leftover.len());
+    }
+    ExitCode::SUCCESS
+}
+
+fn read_script(file: Option<&Path>) -> io::Result<String> {
+    match file {
+        Some(path) if path != Path::new("-") => fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display()))),
+        _ => {
+            let mut script = String::new();
+            io::stdin().read_to_string(&mut script)?;
+            Ok(script)
+        }
+    }
+}
+
+/// Parse every line of a script, numbering the steps by their line. Fails
+/// with an error per bad line.
+fn parse_script(script: &str) -> Result<Vec<(usize, Step)>, Vec<String>> {
+    let mut steps = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in script.lines().enumerate() {
+        match parse_line(line) {
+            Ok(Some(step)) => steps.push((i + 1, step)),
+            Ok(None) => {}
+            Err(e) => errors.push(format!("line {}: {e}", i + 1)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(steps)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parse one script line; `None` for blank lines and comments.
+fn parse_line(line: &str) -> Result<Option<Step>, String> {
+    if line.trim_start().starts_with('#') {
+        return Ok(None);
+    }
+    let words = split_words(line)?;
+    let Some((command, args)) = words.split_first() else {
+        return Ok(None);
+    };
+
+    let items = |family: &str, values: &[String]| -> Result<Vec<IntentItem>, String> {
+        if values.is_empty() {
+            return Err(format!("{command}: no items given"));
+        }
+        values
+            .iter()
+            .map(|value| parse_item(family, value))
+            .collect()
+    };
+    let family_items = || match args.split_first() {
+        Some((family, values)) => items(family, values),
+        None => Err(format!("{command}: expected FAMILY ITEM...")),
+    };
+
+    let step = match command.as_str() {
+        "select" => Step::Add(items("path", args)?),
+        "deselect" => Step::Remove(items("path", args)?),
+        "add" => Step::Add(family_items()?),
+        "set" => Step::Set(family_items()?),
+        "remove" => Step::Remove(family_items()?),
+        "clear" if args.is_empty() => Step::Clear,
+        "clear" => return Err("clear takes no arguments".to_string()),
+        "submit" => {
+            let mut options = SubmitOptions::default();
+            for arg in args {
+                if arg.starts_with("--") {
+                    options.apply_flag(arg)?;
+                } else if options.portal.is_some() {
+                    return Err(format!("submit: unexpected argument {arg:?}"));
+                } else if arg.parse::<Portal>().is_ok() {
+                    options.portal = Some(arg.clone());
+                } else {
+                    return Err(format!("submit: unknown portal {arg:?}"));
+                }
+            }
+            Step::Submit(options)
+        }
+        other => {
+            return Err(format!(
+                "unknown command {other:?} (expected select, deselect, add, set, remove, clear, or submit)"
+            ));
+        }
+    };
+    Ok(Some(step))
+}
+
+/// Split a line into words: whitespace separates them, '…' and "…" quote,
+/// and a backslash escapes the next character (except inside '…').
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_default().push(c),
+                None => return Err("trailing backslash".to_string()),
+            },
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Apply the selection changes in memory, starting from the pending intent.
+/// Returns the submissions and the selection left after the last submit.
+fn plan(
+    mut intent: Intent,
+    steps: Vec<(usize, Step)>,
+) -> Result<(Vec<Submission>, Intent), String> {
+    let mut submissions = Vec::new();
+    for (line, step) in steps {
+        let result = match step {
+            Step::Add(items) => intent.apply(&items, MergeOp::Add),
+            Step::Set(items) => intent.apply(&items, MergeOp::Set),
+            Step::Remove(items) => intent.remove(&items).map(drop),
+            Step::Clear => {
+                intent = Intent::default();
+                Ok(())
+            }
+            Step::Submit(options) => {
+                submissions.push(Submission {
+                    line,
+                    intent: std::mem::take(&mut intent),
+                    options,
+                });
+                Ok(())
+            }
+        };
+        result.map_err(|e| format!("line {line}: {e}"))?;
+    }
+    Ok((submissions, intent))
+}
+
+/// Send the submits over one connection. A failure comes with the exit
+/// status it calls for.
+fn execute(
+    submissions: &[Submission],
+    session_id: Option<String>,
+) -> Result<(), (String, ExitStatus)> {
+    if submissions.is_empty() {
+        return Ok(());
+    }
+    let mut conn = DaemonClient::new()
+        .connect()
+        .map_err(|e| (e.to_string(), e.exit_status()))?;
+    submit_all(submissions, session_id, |req| conn.send(req))
+}
+
+/// Send the submits with `send`. On a refusal, withdraw the submissions
+/// queued so far.
+fn submit_all(
+    submissions: &[Submission],
+    session_id: Option<String>,
+    mut send: impl FnMut(&Request) -> Result<Response, ClientError>,
+) -> Result<(), (String, ExitStatus)> {
+    let pending = paths::pending_dir();
+
+    let mut queued = Vec::new();
+    let mut completed = 0;
+    for sub in submissions {
+        let before = submission_dirs();
+        let result = write_pending(&pending, &sub.intent)
+            .map_err(|e| (e.to_string(), ExitStatus::Failure))
+            .and_then(|()| {
+                let req = Request::Submit {
+                    session_id: session_id.clone(),
+                    options: sub.options.clone(),
+                };
+                match send(&req) {
+                    Ok(Response::Ok) => Ok(()),
+                    Ok(Response::Error(e)) => Err(ClientError::Server(e)),
+                    Ok(_) => Err(ClientError::UnexpectedResponse),
+                    Err(e) => Err(e),
+                }
+                .map_err(|e| (e.to_string(), e.exit_status()))
+            });
+
+        if let Err((e, status)) = result {
+            for dir in &queued {
+                let _ = fs::remove_dir_all(dir);
+            }
+            let mut msg = format!("line {}: {e}", sub.line);
+            if completed > 0 {
+                msg.push_str(&format!(
+                    " ({completed} earlier submit(s) completed an open dialog and cannot be undone)"
+                ));
+            }
+            return Err((msg, status));
+        }
+
+        let new: Vec<PathBuf> = submission_dirs()
+            .difference(&before)
+            .map(|name| paths::submissions_dir().join(name))
+            .collect();
+        if new.is_empty() {
+            completed += 1;
+        }
+        queued.extend(new);
+    }
+    Ok(())
+}
+
+/// Names of the queued submission directories
+fn submission_dirs() -> HashSet<OsString> {
+    fs::read_dir(paths::submissions_dir())
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.file_name())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn write_pending(pending: &Path, intent: &Intent) -> io::Result<()> {
+    if intent.is_empty() {
+        queue::clear(pending)
+    } else {
+        queue::write(pending, intent)
+    }
+}
+
+fn restore_pending(pending: &Path, original: Option<&Intent>) {
+    let result = match original {
+        Some(intent) => queue::write(pending, intent),
+        None => queue::clear(pending),
+    };
+    if let Err(e) = result {
+        eprintln!("Error restoring the pending queue: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libportty::protocol::{ErrorCode, ServerError};
+
+    fn paths(values: &[&str]) -> Intent {
+        let items = values.iter().map(|v| IntentItem::Path(v.to_string()));
+        Intent::multi(libportty::portal::IntentFamily::Path, items.collect()).unwrap()
+    }
+
+    #[test]
+    fn words_split_like_the_shell() {
+        let words = split_words(r#"select "a b" 'c\d' e\ f "g\"h" "i\j" '' x"y"z"#).unwrap();
+        assert_eq!(
+            words,
+            ["select", "a b", "c\\d", "e f", "g\"h", "i\\j", "", "xyz"]
+        );
+        assert_eq!(split_words("  \t ").unwrap(), Vec::<String>::new());
+
+        assert_eq!(
+            split_words("'open").unwrap_err(),
+            "unterminated single quote"
+        );
+        assert_eq!(
+            split_words("\"open").unwrap_err(),
+            "unterminated double quote"
+        );
+        assert_eq!(
+            split_words("\"open\\").unwrap_err(),
+            "unterminated double quote"
+        );
+        assert_eq!(split_words("end\\").unwrap_err(), "trailing backslash");
+    }
+
+    #[test]
+    fn scripts_parse_or_name_every_bad_line() {
+        let steps = parse_script(
+            "# provision\n\nselect /a '/b c'\n  # indented comment\nsubmit file-chooser --confirm\nclear\n",
+        )
+        .unwrap();
+        let lines: Vec<usize> = steps.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [3, 5, 6]);
+        let Step::Add(items) = &steps[0].1 else {
+            panic!("select adds: {:?}", steps[0].1);
+        };
+        assert_eq!(
+            items,
+            &[
+                IntentItem::Path("/a".into()),
+                IntentItem::Path("/b c".into())
+            ]
+        );
+        let Step::Submit(options) = &steps[1].1 else {
+            panic!("submit: {:?}", steps[1].1);
+        };
+        assert_eq!(options.portal.as_deref(), Some("file-chooser"));
+        assert!(options.confirm);
+        assert!(matches!(steps[2].1, Step::Clear));
+
+        let errors = parse_script(
+            "select /a\nfrobnicate\nclear now\nsubmit nowhere\nset\nselect 'open\nadd color\n",
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 6);
+        let prefixes: Vec<&str> = errors
+            .iter()
+            .map(|e| e.split(':').next().unwrap())
+            .collect();
+        assert_eq!(
+            prefixes,
+            ["line 2", "line 3", "line 4", "line 5", "line 6", "line 7"]
+        );
+        assert!(
+            errors[0].contains("unknown command \"frobnicate\""),
+            "{}",
+            errors[0]
+        );
+        assert!(errors[2].contains("unknown portal"), "{}", errors[2]);
+        assert!(errors[5].contains("no items given"), "{}", errors[5]);
+    }
+
+    #[test]
+    fn plans_split_the_selection_at_each_submit() {
+        let steps = parse_script(
+            "select /b /c\ndeselect /a\nsubmit\nset color #ff8800\nsubmit screenshot\nselect /d\n",
+        )
+        .unwrap();
+        let (submissions, leftover) = plan(paths(&["/a"]), steps).unwrap();
+        assert_eq!(submissions.len(), 2);
+        assert_eq!(submissions[0].line, 3);
+        assert_eq!(submissions[0].intent.values(), ["/b", "/c"]);
+        assert_eq!(submissions[1].line, 5);
+        assert_eq!(submissions[1].intent.values(), ["#ff8800"]);
+        assert_eq!(submissions[1].options.portal.as_deref(), Some("screenshot"));
+        assert_eq!(leftover.values(), ["/d"]);
+
+        // Families do not mix within a selection
+        let steps = parse_script("select /a\nadd color #ffffff\n").unwrap();
+        let err = plan(Intent::default(), steps).unwrap_err();
+        assert!(err.starts_with("line 2: cannot merge"), "{err}");
+    }
+
+    #[test]
+    fn refused_submits_undo_the_batch() {
+        let base = std::env::temp_dir().join(format!("portty-batch-test-{}", std::process::id()));
+        libportty::paths::configure_base_dir(base.clone());
+        let pending = paths::pending_dir();
+        let original = paths(&["/queued"]);
+        queue::write(&pending, &original).unwrap();
+
+        let steps =
+            parse_script("select /a\nsubmit\nselect /b\nsubmit\nselect /c\nsubmit\n").unwrap();
+        let (submissions, _) = plan(original.clone(), steps).unwrap();
+        let mut sent = Vec::new();
+        let result = submit_all(&submissions, None, |req| {
+            sent.push(queue::read(&pending).unwrap().values());
+            assert!(matches!(req, Request::Submit { .. }));
+            match sent.len() {
+                // Queued for a later dialog
+                1 => {
+                    fs::create_dir_all(paths::submissions_dir().join("first")).unwrap();
+                    Ok(Response::Ok)
+                }
+                // Completed an open dialog
+                2 => Ok(Response::Ok),
+                _ => Ok(Response::Error(ServerError::new(
+                    ErrorCode::InvalidSubmission,
+                    "Invalid submission: /queued/c does not exist",
+                ))),
+            }
+        });
+
+        let (msg, status) = result.unwrap_err();
+        assert_eq!(status, ExitStatus::Invalid);
+        assert!(msg.starts_with("line 6: Invalid submission"), "{msg}");
+        assert!(msg.contains("1 earlier submit(s) completed"), "{msg}");
+        // Each submit sent its own selection
+        assert_eq!(sent, [vec!["/queued", "/a"], vec!["/b"], vec!["/c"]]);
+        // The submission queued for later is withdrawn
+        assert!(submission_dirs().is_empty());
+
+        restore_pending(&pending, Some(&original));
+        assert_eq!(queue::read(&pending), Some(original));
+        restore_pending(&pending, None);
+        assert_eq!(queue::read(&pending), None);
+
+        let _ = fs::remove_dir_all(base);
+    }
+}
@@ -0,0 +1,149 @@
+//! `portty choices` - the combo and check boxes an app adds to a file dialog
+//!
+//! Apps put options such as a text encoding or "open read-only" next to the
+//! file list. On a terminal each choice is shown as a numbered menu in turn,
+//! so nobody needs to know the choice IDs; `portty choices ID VALUE` sets one
+//! directly and, off a terminal, `portty choices` lists them. The values go
+//! into `chosen` in `options.json` and are returned to the app with the
+//! results.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use libportty::exit::ExitStatus;
+use libportty::portal::file_chooser::{Choice, SessionOptions};
+use libportty::portal::options;
+
+/// Walk, list, or set the choices of the file chooser session in `session_dir`
+pub fn run(session_dir: &Path, id: Option<&str>, value: Option<&str>, list: bool) -> ExitCode {
+    match choices(session_dir, id, value, list) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(status) => status.into(),
+    }
+}
+
+fn choices(
+    session_dir: &Path,
+    id: Option<&str>,
+    value: Option<&str>,
+    list: bool,
+) -> Result<(), ExitStatus> {
+    let fail = |e: String| {
+        eprintln!("Error: {e}");
+        ExitStatus::Failure
+    };
+    let mut raw = options::read(session_dir).map_err(|e| fail(format!("reading options: {e}")))?;
+    let mut opts: SessionOptions = serde_json::from_value(raw.clone())
+        .map_err(|e| fail(format!("not a file chooser session: {e}")))?;
+    if opts.choices.is_empty() {
+        println!("The dialog has no choices");
+        return Ok(());
+    }
+
+    match (id, value) {
+        (Some(id), Some(value)) => {
+            opts.choose(id, value).map_err(fail)?;
+            println!("{id}: {value}");
+        }
+        (Some(id), None) => {
+            let choice = opts
+                .choice(id)
+                .ok_or_else(|| fail(format!("the dialog has no choice {id:?}")))?;
+            println!("{}", current(&opts, choice));
+            return Ok(());
+        }
+        _ if list || !io::stdin().is_terminal() => {
+            for choice in &opts.choices {
+                print_choice(&opts, choice);
+            }
+            return Ok(());
+        }
+        _ => {
+            if !prompt_all(&mut opts) {
+                println!("End of input, nothing changed");
+                return Ok(());
+            }
+        }
+    }
+
+    raw["chosen"] =
+        serde_json::to_value(&opts.chosen).map_err(|e| fail(format!("writing options: {e}")))?;
+    options::write(session_dir, &raw).map_err(|e| fail(format!("writing options: {e}")))
+}
+
+/// The value `choice` has now
+fn current<'a>(opts: &'a SessionOptions, choice: &'a Choice) -> &'a str {
+    opts.chosen
+        .get(&choice.id)
+        .map(String::as_str)
+        .filter(|value| choice.accepts(value))
+        .unwrap_or_else(|| choice.initial())
+}
+
+/// `(value, label)` of each value `choice` takes
+fn menu(choice: &Choice) -> Vec<(&str, &str)> {
+    if choice.is_check_box() {
+        return vec![("true", "yes"), ("false", "no")];
+    }
+    choice
+        .options
+        .iter()
+        .map(|(id, label)| (id.as_str(), label.as_str()))
+        .collect()
+}
+
+fn print_choice(opts: &SessionOptions, choice: &Choice) {
+    let value = current(opts, choice);
+    println!("{} ({}): {value}", choice.label, choice.id);
+    for (option, label) in menu(choice) {
+        let mark = if option == value { '*' } else { ' ' };
+        println!("  {mark} {option:<12} {label}");
+    }
+}
+
+/// Ask for each choice in turn; `false` once input ends before the last
+fn prompt_all(opts: &mut SessionOptions) -> bool {
+    let stdin = io::stdin();
+    let mut line = String::new();
+    for choice in opts.choices.clone() {
+        let value = current(opts, &choice).to_string();
+        let menu = menu(&choice);
+        println!("{} ({})", choice.label, choice.id);
+        for (n, (option, label)) in menu.iter().enumerate() {
+            let mark = if *option == value { " (current)" } else { "" };
+            println!("  {}) {label}{mark}", n + 1);
+        }
+        loop {
+            print!("Number, or Enter to keep it: ");
+            let _ = io::stdout().flush();
+            line.clear();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    println!();
+                    return false;
+                }
+                Ok(_) => {}
+            }
+            let answer = line.trim();
+            if answer.is_empty() {
+                break;
+            }
+            let picked = answer
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|n| menu.get(n))
+                .map(|(option, _)| *option)
+                .or_else(|| choice.accepts(answer).then_some(answer));
+            match picked {
+                Some(picked) => {
+                    opts.chosen.insert(choice.id.clone(), picked.to_string());
+                    break;
+                }
+                None => println!("Pick 1 to {}", menu.len()),
+            }
+        }
+    }
+    true
+}
@@ -0,0 +1,115 @@
+//! `portty config` - inspect and create the porttyd configuration
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Subcommand;
+
+use libportty::client::DaemonClient;
+use libportty::config::{Config, check, schema};
+use libportty::exit::ExitStatus;
+use libportty::files;
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Validate the config file: unknown keys, type errors, suspicious values
+    Check {
+        /// Config file to check (default: $PORTTY_CONFIG or ~/.config/portty/config.toml)
+        file: Option<PathBuf>,
+    },
+    /// Write a config file with every key commented out under its documentation
+    Init {
+        /// File to write (default: $PORTTY_CONFIG or ~/.config/portty/config.toml); - prints it
+        file: Option<PathBuf>,
+        /// Replace an existing file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+pub fn run(action: ConfigAction) -> ExitCode {
+    match action {
+        ConfigAction::Check { file } => cmd_check(file),
+        ConfigAction::Init { file, force } => cmd_init(file, force),
+    }
+}
+
+fn cmd_check(file: Option<PathBuf>) -> ExitCode {
+    let Some(path) = file.or_else(Config::path) else {
+        eprintln!("Error: no config directory found");
+        return ExitStatus::Failure.into();
+    };
+
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            println!("{}: not found, porttyd uses defaults", path.display());
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("Error: failed to read {}: {e}", path.display());
+            return ExitStatus::Failure.into();
+        }
+    };
+
+    // Plugin portals are only known to the daemon serving them
+    let portals = DaemonClient::new()
+        .status()
+        .map(|status| status.portals)
+        .unwrap_or_default();
+    let portals: Vec<&str> = portals.iter().map(String::as_str).collect();
+    let diagnostics = check::check_with(&content, &portals);
+    for diagnostic in &diagnostics {
+        println!("{}: {diagnostic}", path.display());
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    if diagnostics.is_empty() {
+        println!("{}: ok", path.display());
+    } else {
+        println!("{errors} error(s), {warnings} warning(s)");
+    }
+
+    if errors > 0 {
+        ExitStatus::Invalid.into()
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn cmd_init(file: Option<PathBuf>, force: bool) -> ExitCode {
+    let exec = Config::detected().resolve_exec("", "");
+    let content = schema::default_file(exec.as_deref());
+    if file.as_deref() == Some(Path::new("-")) {
+        print!("{content}");
+        return ExitCode::SUCCESS;
+    }
+    let Some(path) = file.or_else(Config::path) else {
+        eprintln!("Error: no config directory found");
+        return ExitStatus::Failure.into();
+    };
+    if path.exists() && !force {
+        eprintln!(
+            "Error: {} already exists; pass --force to replace it",
+            path.display()
+        );
+        return ExitStatus::Failure.into();
+    }
+
+    let written = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| files::write_atomic(&path, content));
+    match written {
+        Ok(()) => {
+            println!("Wrote {}", path.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: failed to write {}: {e}", path.display());
+            ExitStatus::Failure.into()
+        }
+    }
+}
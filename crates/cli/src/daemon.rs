@@ -0,0 +1,280 @@
+//! `portty daemon` - manage the porttyd process
+//!
+//! Prefers the systemd user unit when one is installed, otherwise spawns
+//! porttyd directly (double-forked, detached from this terminal). Liveness is
+//! always checked through the control socket.
+
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+use std::time::{Duration, Instant};
+
+use clap::Subcommand;
+
+use libportty::client::DaemonClient;
+use libportty::exit::ExitStatus;
+use libportty::paths;
+
+const SYSTEMD_UNIT: &str = "portty.service";
+const DAEMON_BIN: &str = "porttyd";
+
+/// How long to wait for the daemon to come up or go away
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Subcommand)]
+pub enum DaemonAction {
+    /// Start porttyd (no-op if already running)
+    Start,
+    /// Stop porttyd (active sessions are cancelled)
+    Stop,
+    /// Restart porttyd
+    Restart,
+    /// Show whether porttyd is running
+    Status,
+    /// Reload porttyd configuration
+    Reload,
+}
+
+pub fn run(action: DaemonAction) -> ExitCode {
+    let result = match action {
+        DaemonAction::Start => start(),
+        DaemonAction::Stop => stop(),
+        DaemonAction::Restart => restart(),
+        DaemonAction::Status => return status(),
+        DaemonAction::Reload => return reload(),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitStatus::Failure.into()
+        }
+    }
+}
+
+fn start() -> Result<(), String> {
+    let client = DaemonClient::new();
+    if let Ok(status) = client.status() {
+        println!("porttyd already running (pid {})", status.pid);
+        return Ok(());
+    }
+
+    if systemd_unit_available() {
+        systemctl("start")?;
+    } else {
+        let bin = find_daemon_binary()
+            .ok_or_else(|| format!("cannot find {DAEMON_BIN}; set PORTTY_DAEMON to its path"))?;
+        spawn_detached(&bin)?;
+    }
+
+    let status = wait_until(|| client.status().ok())
+        .ok_or("porttyd did not come up (see porttyd.log in the portty base directory)")?;
+    println!("Started porttyd (pid {})", status.pid);
+    Ok(())
+}
+
+fn stop() -> Result<(), String> {
+    let client = DaemonClient::new();
+
+    if systemd_unit_active() {
+        systemctl("stop")?;
+    } else {
+        match client.status() {
+            Ok(_) => client.shutdown().map_err(|e| e.to_string())?,
+            Err(_) => {
+                println!("porttyd is not running");
+                return Ok(());
+            }
+        }
+    }
+
+    wait_until(|| client.status().is_err().then_some(())).ok_or("porttyd did not stop in time")?;
+    println!("Stopped porttyd");
+    Ok(())
+}
+
+fn restart() -> Result<(), String> {
+    if systemd_unit_active() {
+        systemctl("restart")?;
+        let client = DaemonClient::new();
+        let status = wait_until(|| client.status().ok()).ok_or("porttyd did not come up")?;
+        println!("Restarted porttyd (pid {})", status.pid);
+        return Ok(());
+    }
+
+    stop()?;
+    start()
+}
+
+fn status() -> ExitCode {
+    let client = DaemonClient::new();
+    match client.status() {
+        Ok(status) => {
+            println!(
+                "porttyd running (pid {}, version {}, {} active session(s))",
+                status.pid, status.version, status.sessions
+            );
+            if let Some(build) = &status.build {
+                println!("Build: {build}");
+            }
+            if status.id_collisions > 0 {
+                println!(
+                    "{} session id collision(s) retried since start",
+                    status.id_collisions
+                );
+            }
+            if systemd_unit_active() {
+                println!("Managed by systemd ({SYSTEMD_UNIT})");
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            println!("porttyd is not running: {e}");
+            e.exit_status().into()
+        }
+    }
+}
+
+fn reload() -> ExitCode {
+    match DaemonClient::new().reload() {
+        Ok(()) => {
+            println!("Reloaded configuration");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_status().into()
+        }
+    }
+}
+
+/// Poll `probe` until it yields a value or the timeout expires.
+fn wait_until<T>(mut probe: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + WAIT_TIMEOUT;
+    loop {
+        if let Some(value) = probe() {
+            return Some(value);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn systemctl_user(args: &[&str]) -> Option<std::process::ExitStatus> {
+    Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .ok()
+}
+
+/// Whether the portty user unit is installed
+fn systemd_unit_available() -> bool {
+    systemctl_user(&["cat", SYSTEMD_UNIT]).is_some_and(|s| s.success())
+}
+
+/// Whether the portty user unit is currently running
+fn systemd_unit_active() -> bool {
+    systemctl_user(&["is-active", "--quiet", SYSTEMD_UNIT]).is_some_and(|s| s.success())
+}
+
+fn systemctl(verb: &str) -> Result<(), String> {
+    match systemctl_user(&[verb, SYSTEMD_UNIT]) {
+        Some(s) if s.success() => Ok(()),
+        Some(s) => Err(format!(
+            "systemctl --user {verb} {SYSTEMD_UNIT} failed ({s})"
+        )),
+        None => Err("failed to run systemctl".to_string()),
+    }
+}
+
+/// Locate the porttyd binary.
+///
+/// Order: `PORTTY_DAEMON`, next to this executable, the usual libexec
+/// locations, then `$PATH`.
+pub fn find_daemon_binary() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("PORTTY_DAEMON") {
+        return Some(PathBuf::from(path));
+    }
+
+    let sibling = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(DAEMON_BIN)));
+    let fixed = [
+        "/usr/lib/portty/porttyd",
+        "/usr/libexec/portty/porttyd",
+        "/usr/local/lib/portty/porttyd",
+    ]
+    .map(PathBuf::from);
+
+    sibling
+        .into_iter()
+        .chain(fixed)
+        .chain(
+            std::env::var_os("PATH")
+                .map(|path| {
+                    std::env::split_paths(&path)
+                        .map(|dir| dir.join(DAEMON_BIN))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default(),
+        )
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// Spawn porttyd fully detached: new session, intermediate child exits right
+/// away so the daemon is reparented and never becomes our zombie.
+/// Output goes to `<base_dir>/porttyd.log`.
+fn spawn_detached(bin: &Path) -> Result<(), String> {
+    paths::ensure_base_dir().map_err(|e| format!("failed to create base directory: {e}"))?;
+    let log_path = paths::base_dir().join("porttyd.log");
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|e| format!("failed to open {}: {e}", log_path.display()))?;
+    let log_err = log
+        .try_clone()
+        .map_err(|e| format!("failed to open {}: {e}", log_path.display()))?;
+
+    let mut cmd = Command::new(bin);
+    cmd.stdin(Stdio::null())
+        .stdout(log)
+        .stderr(log_err)
+        .current_dir("/");
+
+    // SAFETY: only async-signal-safe libc calls run between fork and exec.
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            match libc::fork() {
+                -1 => Err(std::io::Error::last_os_error()),
+                // Grandchild: continue to exec porttyd
+                0 => Ok(()),
+                // Intermediate child: exit immediately
+                _ => libc::_exit(0),
+            }
+        });
+    }
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("failed to spawn {}: {e}", bin.display()))?;
+    // Reap the intermediate process
+    let _ = child.wait();
+    Ok(())
+}
@@ -0,0 +1,426 @@
+//! `portty help [--full]` and `portty man` - documentation from the clap
+//! definitions
+//!
+//! Examples live here, one block per subcommand, and are attached to the
+//! clap command as its long help, so `--help`, `help --full`, and the man
+//! pages all show the same text.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+
+use clap::CommandFactory;
+use clap_mangen::Man;
+use clap_mangen::roff::{Roff, bold, italic, roman};
+use libportty::Portal;
+use libportty::config::schema::{KEYS, Scope};
+use libportty::exit::ExitStatus;
+
+use crate::Cli;
+
+/// Examples per subcommand, one command per line
+const EXAMPLES: &[(&str, &str)] = &[
+    (
+        "add",
+        "portty add path file1.txt file2.txt
+fd -e pdf | portty add path --stdin
+portty add directory /tmp/out-dir
+portty add color '#ff00aa'
+portty add path --keep-repeats a.txt a.txt  # queue: keep the repeat",
+    ),
+    (
+        "set",
+        "portty set path /tmp/output.txt
+portty set path ~/Documents/        # save-file: keep the suggested name
+portty set path ~/Documents/new.pdf # save-file: save under a new name",
+    ),
+    ("remove", "portty remove path file1.txt"),
+    (
+        "select",
+        "portty select --from-lf-sel
+portty select --from-ranger-copy --replace
+portty select --from-file ~/.config/nnn/.selection
+portty select @recent:3
+portty select @recent:1-5 --replace
+portty select dir/* --exclude '*.tmp' --exclude-dir .git
+portty select -r src --exclude-dir target
+portty select --edit             # curate the selection in $EDITOR",
+    ),
+    (
+        "batch",
+        "portty batch setup.txt
+printf 'select a.txt\\nsubmit file-chooser\\n' | portty batch",
+    ),
+    ("clear", "portty clear"),
+    (
+        "reset",
+        "portty reset
+portty reset --keep '*.pdf'        # revert everything but the PDFs
+portty --session <id> reset --keep '~/Downloads/**'",
+    ),
+    (
+        "join",
+        "portty join
+portty join <id>
+portty join <id> --shell   # a shell even in tabs mode",
+    ),
+    ("focus", "portty focus <id>"),
+    (
+        "wait",
+        "id=$(portty wait --portal file-chooser --timeout 60) && portty --session \"$id\" add path ~/report.pdf
+eval \"$(portty wait --new --env)\"   # then plain portty commands act on it",
+    ),
+    (
+        "refresh-shims",
+        "portty refresh-shims --reload   # after fixing a shim in config.toml
+portty refresh-shims <id>",
+    ),
+    ("show", "portty show\nportty show --problems-only\nportty --session <id> show"),
+    (
+        "submit",
+        "portty submit
+portty submit --allow-empty
+portty submit --confirm
+portty submit --review
+portty submit --portal screenshot
+portty submit --sort mtime
+portty submit --queue-policy merge
+portty submit --prefer queue",
+    ),
+    ("review", "portty review\nportty review --sort mtime"),
+    (
+        "pick",
+        "portty pick
+portty --session <id> pick",
+    ),
+    ("cancel", "portty cancel"),
+    ("info", "portty info"),
+    (
+        "get",
+        "portty get folder
+portty get filters | cut -f1
+portty get filter-hints
+folder \"$(portty get folders | fzf)\"",
+    ),
+    (
+        "filters",
+        "portty filters
+portty filters --json | jq -r '.[] | select(.current) | .globs[]'",
+    ),
+    (
+        "guide",
+        "portty guide\n?                  # the same, in a session",
+    ),
+    ("conflicts", "portty conflicts\nportty conflicts rename"),
+    (
+        "next-file",
+        "portty next-file\nportty next-file ~/Pictures\nportty next-file --skip",
+    ),
+    (
+        "choices",
+        "portty choices\nportty choices --list\nportty choices encoding utf8",
+    ),
+    ("export", "portty export ~/dialog.json\nportty export - | jq .entries"),
+    ("import", "portty import ~/dialog.json"),
+    ("diff-queue", "portty diff-queue"),
+    ("verify", "portty verify"),
+    (
+        "list",
+        "portty list\nportty list --all\nportty list --width 80 --ascii",
+    ),
+    ("queue", "portty queue"),
+    (
+        "recent",
+        "portty recent
+portty recent --app org.mozilla.firefox",
+    ),
+    (
+        "history",
+        "portty history
+portty history --app org.mozilla.firefox -n 5
+portty history --stats",
+    ),
+    (
+        "daemon",
+        "portty daemon start
+portty daemon status
+portty daemon reload",
+    ),
+    (
+        "config",
+        "portty config init
+portty config check
+portty config check ./config.toml",
+    ),
+    (
+        "permissions",
+        "portty permissions
+portty permissions set com.obsproject.Studio screenshot screenshot deny
+portty permissions clear com.obsproject.Studio",
+    ),
+    ("doctor", "portty doctor"),
+    (
+        "version",
+        "portty version
+portty version --daemon          # also porttyd's, warning when it differs",
+    ),
+    ("tutorial", "portty tutorial"),
+    (
+        "simulate",
+        "portty simulate file-chooser open-file --multiple --filter 'Images=*.png;image/jpeg'
+portty simulate file-chooser save-file --name report.pdf --folder ~/Documents
+portty simulate file-chooser open-file ~/notes.txt ~/todo.txt",
+    ),
+    (
+        "filter",
+        "portty filter create 'Images=*.png,*.jpg;image/webp' 'Text=*.txt'
+portty filter create --json 'Images=*.png'",
+    ),
+    ("gc", "portty gc --dry-run\nportty gc --max-age 600"),
+    (
+        "payload-dir",
+        "grim \"$(portty payload-dir --size 20M)/shot.png\"",
+    ),
+    (
+        "shot",
+        "portty shot
+portty shot -- grim -g \"$(slurp)\" {}
+portty shot --series 5 --interval 2   # with series_dir set",
+    ),
+    ("nvim", "portty nvim > ~/.config/nvim/lua/portty.lua"),
+    (
+        "help",
+        "portty help submit
+portty help --full | less",
+    ),
+];
+
+fn examples(name: &str) -> Option<&'static str> {
+    EXAMPLES
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map(|(_, examples)| *examples)
+}
+
+/// The `portty` command with each subcommand's examples in its long help,
+/// and the exit statuses in its own
+pub fn command() -> clap::Command {
+    let statuses = ExitStatus::ALL
+        .iter()
+        .map(|status| format!("  {:<3} {status}", status.code()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut cmd = Cli::command().after_long_help(format!("Exit status:\n{statuses}"));
+    let names: Vec<String> = cmd
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in names {
+        if let Some(examples) = examples(&name) {
+            let text = examples
+                .lines()
+                .map(|line| format!("  {line}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            cmd = cmd.mut_subcommand(name, |sub| {
+                sub.after_long_help(format!("Examples:\n{text}"))
+            });
+        }
+    }
+    cmd
+}
+
+/// Print the long help of `portty` or of the subcommand at `path`; with
+/// `full`, also of every subcommand below it
+pub fn help(path: &[String], full: bool) -> ExitCode {
+    let mut cmd = command();
+    cmd.build();
+    let mut target = &mut cmd;
+    for name in path {
+        match target.find_subcommand_mut(name) {
+            Some(sub) => target = sub,
+            None => {
+                eprintln!("Error: unknown command: {}", path.join(" "));
+                return ExitStatus::Usage.into();
+            }
+        }
+    }
+
+    let mut out = std::io::stdout().lock();
+    let title = std::iter::once("portty")
+        .chain(path.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let result = if full {
+        write_help_tree(&mut out, &title, target)
+    } else {
+        write!(out, "{}", target.render_long_help())
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        // The pager was closed
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitStatus::Failure.into()
+        }
+    }
+}
+
+fn write_help_tree(
+    out: &mut impl Write,
+    title: &str,
+    cmd: &mut clap::Command,
+) -> std::io::Result<()> {
+    writeln!(out, "{title}\n{}\n", "=".repeat(title.len()))?;
+    write!(out, "{}", cmd.render_long_help())?;
+    for sub in cmd.get_subcommands_mut().filter(|sub| !sub.is_hide_set()) {
+        writeln!(out)?;
+        let title = format!("{title} {}", sub.get_name());
+        write_help_tree(out, &title, sub)?;
+    }
+    Ok(())
+}
+
+/// Write the man pages into `dir`: portty.1, portty-COMMAND.1 for every
+/// subcommand, and portty-config.5
+pub fn man(dir: &Path) -> ExitCode {
+    let result = fs::create_dir_all(dir).and_then(|()| {
+        let mut cmd = command().disable_help_subcommand(true);
+        cmd.build();
+        write_command_pages(&cmd, dir)?;
+        let path = dir.join("portty-config.5");
+        fs::write(&path, config_page())?;
+        println!("{}", path.display());
+        Ok(())
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitStatus::Failure.into()
+        }
+    }
+}
+
+fn write_command_pages(cmd: &clap::Command, dir: &Path) -> std::io::Result<()> {
+    // Examples are per top-level subcommand: `portty-permissions-list` has
+    // none, and must not get `portty list`'s
+    let examples = cmd
+        .get_display_name()
+        .and_then(|name| name.strip_prefix("portty-"))
+        .and_then(examples);
+    // They get their own section instead of clap_mangen's EXTRA
+    let man = Man::new(cmd.clone().after_long_help(None::<&str>))
+        .source(format!("portty {}", env!("CARGO_PKG_VERSION")))
+        .manual("portty manual");
+    let path = dir.join(man.get_filename());
+    let mut page = Vec::new();
+    man.render(&mut page)?;
+
+    let mut roff = Roff::new();
+    if cmd.get_name() == "portty" {
+        roff.control("SH", ["EXIT STATUS"]);
+        for status in ExitStatus::ALL {
+            roff.control("TP", [])
+                .text([bold(status.code().to_string())])
+                .text([roman(status.description())]);
+        }
+    }
+    if let Some(examples) = examples {
+        roff.control("SH", ["EXAMPLES"]).control("nf", []);
+        for line in examples.lines() {
+            roff.text([roman(line)]);
+        }
+        roff.control("fi", []);
+    }
+    roff.control("SH", ["SEE ALSO"]).text([
+        bold("porttyd"),
+        roman("(1), "),
+        bold("portty-config"),
+        roman("(5)"),
+    ]);
+    roff.to_writer(&mut page)?;
+    fs::write(&path, page)?;
+    println!("{}", path.display());
+
+    for sub in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        write_command_pages(sub, dir)?;
+    }
+    Ok(())
+}
+
+/// portty-config(5), from the documented config keys
+fn config_page() -> String {
+    let mut roff = Roff::new();
+    let source = format!("portty {}", env!("CARGO_PKG_VERSION"));
+    roff.control("TH", ["portty-config", "5", "", &source, "portty manual"]);
+    roff.control("SH", ["NAME"])
+        .text([roman("portty-config - porttyd configuration file")]);
+    roff.control("SH", ["SYNOPSIS"]).text([
+        italic("$XDG_CONFIG_HOME/portty/config.toml"),
+        roman(", or the file named by "),
+        bold("PORTTY_CONFIG"),
+    ]);
+
+    roff.control("SH", ["DESCRIPTION"]).text([roman(
+        "A TOML file read by porttyd at startup and on portty daemon reload. \
+         Keys apply at the root, in a portal table, or in an operation table \
+         of a portal; the most specific level wins. \
+         portty config check validates the file.",
+    )]);
+    roff.control("PP", [])
+        .text([roman("Portals and their operations:")]);
+    for portal in Portal::ALL {
+        let operations: Vec<_> = portal.operations().iter().map(|op| op.as_str()).collect();
+        roff.control("TP", [])
+            .text([bold(format!("[{portal}]"))])
+            .text([roman(operations.join(", "))]);
+    }
+    roff.control("PP", []).text([
+        roman("Custom shims go in a "),
+        bold("bin"),
+        roman(" table ("),
+        bold("[file-chooser.bin]"),
+        roman("), audit lists in "),
+        bold("[PORTAL.audit]"),
+        roman(", and rewrite rules in "),
+        bold("[[PORTAL.rewrite]]"),
+        roman(" arrays."),
+    ]);
+
+    roff.control("SH", ["KEYS"]);
+    for key in KEYS {
+        let scope = match key.scope {
+            Scope::Any => "",
+            Scope::Root => " (root level only)",
+        };
+        roff.control("TP", [])
+            .text([bold(key.name), roman(" = "), italic(key.value)])
+            .text([roman(format!("{}{scope}", key.doc))]);
+    }
+
+    roff.control("SH", ["EXAMPLE"]).control("nf", []);
+    for line in [
+        "exec = \"foot\"",
+        "on_terminal_exit = \"ask\"",
+        "",
+        "[file-chooser.bin]",
+        "pick = \"fzf --multi | sel --stdin\"",
+        "",
+        "[file-chooser.save-file]",
+        "exec = \"submit\"",
+    ] {
+        roff.text([roman(line)]);
+    }
+    roff.control("fi", []);
+
+    roff.control("SH", ["SEE ALSO"]).text([
+        bold("portty"),
+        roman("(1), "),
+        bold("porttyd"),
+        roman("(1)"),
+    ]);
+    roff.render()
+}
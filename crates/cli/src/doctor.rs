@@ -0,0 +1,303 @@
+//! `portty doctor` - diagnose this shell's view of portty
+//!
+//! Checks what the CLI depends on, from the client's side: the base
+//! directory, the daemon socket, the daemon's version, and session variables
+//! inherited from a terminal whose session is gone. Each problem comes with a
+//! fix to try.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::ExitCode;
+
+use libportty::client::DaemonClient;
+use libportty::exit::ExitStatus;
+use libportty::protocol::PROTOCOL_VERSION;
+use libportty::socket::{self, SocketAddress};
+use libportty::{SessionInfo, paths};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Result of one check
+struct Check {
+    level: Level,
+    message: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn ok(message: impl Into<String>) -> Self {
+        Self {
+            level: Level::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            level: Level::Warn,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            level: Level::Fail,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+pub fn run() -> ExitCode {
+    let mut checks = vec![check_base_dir()];
+    let daemon = check_socket(&mut checks);
+    if let Some(sessions) = &daemon {
+        check_session_dirs(sessions, &mut checks);
+    }
+    check_env(daemon.as_deref(), &mut checks);
+
+    for check in &checks {
+        let tag = match check.level {
+            Level::Ok => "ok  ",
+            Level::Warn => "warn",
+            Level::Fail => "FAIL",
+        };
+        println!("{tag} {}", check.message);
+        if let Some(fix) = &check.fix {
+            println!("     fix: {fix}");
+        }
+    }
+
+    match checks.iter().map(|c| c.level).max() {
+        Some(Level::Fail) => ExitStatus::Failure.into(),
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+fn check_base_dir() -> Check {
+    let base = paths::base_dir();
+    let meta = match fs::metadata(&base) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Check::fail(
+                format!("base directory {} does not exist", base.display()),
+                "portty daemon start (porttyd creates it)",
+            );
+        }
+        Err(e) => {
+            return Check::fail(
+                format!("base directory {}: {e}", base.display()),
+                format!("check the permissions of {}", base.display()),
+            );
+        }
+    };
+    if meta.uid() != paths::current_uid() {
+        return Check::fail(
+            format!(
+                "base directory {} is owned by uid {}, not you (uid {})",
+                base.display(),
+                meta.uid(),
+                paths::current_uid()
+            ),
+            format!(
+                "remove {} as its owner, then portty daemon restart",
+                base.display()
+            ),
+        );
+    }
+    let mode = meta.mode() & 0o777;
+    if mode != 0o700 {
+        return Check::warn(
+            format!("base directory {} has mode {mode:o}", base.display()),
+            format!("chmod 700 {}", base.display()),
+        );
+    }
+    Check::ok(format!("base directory {}", base.display()))
+}
+
+/// Check the socket and the daemon behind it. Returns the active sessions
+/// when the daemon answered.
+fn check_socket(checks: &mut Vec<Check>) -> Option<Vec<SessionInfo>> {
+    let socket = paths::daemon_socket_path();
+    if let Some(value) = std::env::var_os("PORTTY_SOCK") {
+        checks.push(Check::warn(
+            format!(
+                "PORTTY_SOCK is set ({}) but portty does not read it",
+                value.to_string_lossy()
+            ),
+            "unset PORTTY_SOCK; PORTTY_SOCKET names another daemon address to try first",
+        ));
+    }
+
+    // In a container the daemon may only answer at a `listen` address
+    let primary = SocketAddress::Path(socket.clone());
+    let elsewhere = primary.connect().is_err().then(|| {
+        socket::daemon_addresses()
+            .into_iter()
+            .filter(|address| *address != primary)
+            .find(|address| address.connect().is_ok())
+    });
+    match elsewhere.flatten() {
+        Some(address) => checks.push(Check::ok(format!("daemon socket {address}"))),
+        None if !check_socket_file(&socket, checks) => return None,
+        None => {}
+    }
+
+    let client = DaemonClient::new();
+    let status = match client.status() {
+        Ok(status) => status,
+        Err(e) => {
+            checks.push(Check::fail(
+                format!("porttyd did not answer a status request: {e}"),
+                "portty daemon restart (an older porttyd may not speak this protocol)",
+            ));
+            return None;
+        }
+    };
+    let ours = env!("CARGO_PKG_VERSION");
+    if status.version == ours {
+        checks.push(Check::ok(format!(
+            "porttyd {} running (pid {})",
+            status.version, status.pid
+        )));
+    } else {
+        let protocol = match status.protocol {
+            Some(theirs) if theirs == PROTOCOL_VERSION => String::new(),
+            Some(theirs) => format!(", protocol v{theirs} vs v{PROTOCOL_VERSION}"),
+            None => format!(", protocol older than v{PROTOCOL_VERSION}"),
+        };
+        checks.push(Check::warn(
+            format!(
+                "porttyd {} (pid {}) differs from this portty {ours}{protocol}",
+                status.version, status.pid
+            ),
+            "portty daemon restart, so the daemon matches the installed CLI",
+        ));
+    }
+
+    match client.list() {
+        Ok(sessions) => Some(sessions),
+        Err(e) => {
+            checks.push(Check::fail(
+                format!("porttyd did not list its sessions: {e}"),
+                "portty daemon restart",
+            ));
+            None
+        }
+    }
+}
+
+/// Check the socket file porttyd creates in the base directory
+fn check_socket_file(socket: &Path, checks: &mut Vec<Check>) -> bool {
+    match fs::symlink_metadata(socket) {
+        Ok(meta) if !meta.file_type().is_socket() => {
+            checks.push(Check::fail(
+                format!("{} is not a socket", socket.display()),
+                format!("remove {}, then portty daemon start", socket.display()),
+            ));
+            return false;
+        }
+        Ok(_) => {}
+        Err(_) => {
+            checks.push(Check::fail(
+                format!("no daemon socket at {}", socket.display()),
+                "portty daemon start",
+            ));
+            return false;
+        }
+    }
+
+    if let Err(e) = UnixStream::connect(socket) {
+        let fix = match e.kind() {
+            ErrorKind::ConnectionRefused => {
+                "porttyd is not running and left its socket behind: portty daemon start"
+            }
+            ErrorKind::PermissionDenied => {
+                "the socket belongs to another user: check $USER and sudo"
+            }
+            _ => "portty daemon restart",
+        };
+        checks.push(Check::fail(
+            format!("cannot connect to {}: {e}", socket.display()),
+            fix,
+        ));
+        return false;
+    }
+    checks.push(Check::ok(format!("daemon socket {}", socket.display())));
+    true
+}
+
+/// Every active session's directory must exist and be ours
+fn check_session_dirs(sessions: &[SessionInfo], checks: &mut Vec<Check>) {
+    let base = paths::base_dir();
+    let mut bad = 0;
+    for session in sessions {
+        let dir = base.join(&session.id);
+        let problem = match fs::metadata(&dir) {
+            Ok(meta) if meta.uid() != paths::current_uid() => {
+                Some(format!("owned by uid {}", meta.uid()))
+            }
+            Ok(meta) if meta.mode() & 0o200 == 0 => Some("not writable".to_string()),
+            Ok(_) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        if let Some(problem) = problem {
+            bad += 1;
+            checks.push(Check::fail(
+                format!("session directory {}: {problem}", dir.display()),
+                format!("portty --session {} cancel", session.id),
+            ));
+        }
+    }
+    if bad == 0 {
+        checks.push(Check::ok(format!(
+            "{} active session(s), directories in order",
+            sessions.len()
+        )));
+    }
+}
+
+/// Session variables must name a session the daemon still has
+fn check_env(sessions: Option<&[SessionInfo]>, checks: &mut Vec<Check>) {
+    let session = std::env::var("PORTTY_SESSION").ok();
+    let dir = std::env::var_os("PORTTY_DIR");
+
+    let Some(id) = session else {
+        if let Some(dir) = dir {
+            checks.push(Check::warn(
+                format!(
+                    "PORTTY_DIR is set ({}) without PORTTY_SESSION",
+                    Path::new(&dir).display()
+                ),
+                "unset PORTTY_DIR",
+            ));
+        }
+        return;
+    };
+
+    let dir_exists = dir.as_deref().is_some_and(|dir| Path::new(dir).is_dir());
+    let known = sessions.map(|sessions| sessions.iter().any(|s| s.id == id));
+    match known {
+        Some(false) | None if !dir_exists => checks.push(Check::warn(
+            format!("PORTTY_SESSION={id} names a session that has ended"),
+            "unset PORTTY_SESSION PORTTY_DIR PORTTY_PORTAL PORTTY_OPERATION (commands here \
+             act on that session instead of the queue)",
+        )),
+        Some(false) => checks.push(Check::warn(
+            format!("PORTTY_SESSION={id} is not an active session of porttyd"),
+            "unset PORTTY_SESSION PORTTY_DIR PORTTY_PORTAL PORTTY_OPERATION, or run \
+             portty from that session's terminal",
+        )),
+        _ => checks.push(Check::ok(format!("in session {id}"))),
+    }
+}
@@ -0,0 +1,294 @@
+//! `portty select --edit` - curate the selection in `$EDITOR`
+//!
+//! Writes the selection of the session (or the pending queue outside one) to
+//! a private file, one path per line, and opens `$VISUAL` or `$EDITOR` (`vi`
+//! without either) on it. The edited list is diffed against the original:
+//! deleted lines are deselected, new lines selected as `portty add path`
+//! would (relative to the current directory, checked against the request),
+//! and kept lines left alone, so changes other commands make meanwhile
+//! survive. Blank lines and the header written above the entries are
+//! ignored; an editor exiting with an error changes nothing.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command as Process, ExitCode};
+
+use libportty::exit::ExitStatus;
+use libportty::portal::file_chooser::file_uri_to_path;
+use libportty::portal::intent::queue;
+use libportty::portal::{IntentItem, SessionContext};
+use libportty::{Portal, files, paths};
+
+use crate::{Command, Context, run_command};
+
+/// A selection entry as it appears in the editor
+struct Line {
+    /// What the user sees and edits
+    shown: String,
+    /// The entry as stored, removed when the line is deleted
+    stored: String,
+}
+
+/// Edit the selection of `ctx` (the session `session_id` given outside one,
+/// or else the pending queue)
+pub fn run(ctx: Context, session_id: Option<String>) -> ExitCode {
+    let dir = match (&ctx, &session_id) {
+        (Context::Session { session_id }, _) => Some(paths::base_dir().join(session_id)),
+        (Context::Daemon, Some(id)) => match crate::get_session_info(Some(id.clone())) {
+            Ok(session) => Some(PathBuf::from(session.dir)),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                return e.exit_status().into();
+            }
+        },
+        (Context::Daemon, None) => None,
+    };
+    let (family, lines) = match &dir {
+        Some(dir) => match session_lines(dir) {
+            Ok(lines) => ("path", lines),
+            Err((e, status)) => {
+                eprintln!("Error: {e}");
+                return status.into();
+            }
+        },
+        None => queue_lines(),
+    };
+
+    let edited = match edit(&lines, dir.is_some()) {
+        Ok(Some(edited)) => edited,
+        Ok(None) => {
+            eprintln!("Editor failed; selection unchanged");
+            return ExitStatus::Failure.into();
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+
+    let (removed, added) = changes(&lines, &edited);
+    if removed.is_empty() && added.is_empty() {
+        println!("Selection unchanged");
+        return ExitCode::SUCCESS;
+    }
+
+    if !removed.is_empty() {
+        let status = match &dir {
+            Some(dir) => {
+                let stored: Vec<String> = removed.iter().map(|line| line.stored.clone()).collect();
+                match files::remove_lines(&dir.join("submission"), &stored) {
+                    Ok(()) => ExitCode::SUCCESS,
+                    Err(e) => {
+                        eprintln!("Error: {e}");
+                        ExitStatus::Failure.into()
+                    }
+                }
+            }
+            None => run_command(
+                Context::Daemon,
+                None,
+                Command::Remove {
+                    family: family.to_string(),
+                    items: removed
+                        .iter()
+                        .map(|line| line.stored.clone().into())
+                        .collect(),
+                    stdin: false,
+                },
+            ),
+        };
+        if status != ExitCode::SUCCESS {
+            return status;
+        }
+    }
+    if !added.is_empty() {
+        let status = run_command(
+            ctx,
+            session_id,
+            Command::Add {
+                family: family.to_string(),
+                items: added.iter().map(Into::into).collect(),
+                stdin: false,
+                no_preserve: false,
+                keep_repeats: false,
+            },
+        );
+        if status != ExitCode::SUCCESS {
+            return status;
+        }
+    }
+    println!("Deselected {}, selected {}", removed.len(), added.len());
+    ExitCode::SUCCESS
+}
+
+/// A file chooser session's entries, shown as paths
+fn session_lines(dir: &Path) -> Result<Vec<Line>, (String, ExitStatus)> {
+    let ctx = SessionContext::from_session_dir(dir)
+        .map_err(|e| (format!("not a session: {e}"), ExitStatus::Failure))?;
+    if ctx.operation.portal() != Portal::FileChooser {
+        return Err((
+            format!(
+                "--edit edits file chooser selections, not {}",
+                ctx.operation
+            ),
+            ExitStatus::Invalid,
+        ));
+    }
+    Ok(files::read_lines(&ctx.submission_path())
+        .into_iter()
+        .map(|stored| {
+            // Non-UTF-8 paths stay URIs, which `add` takes as well
+            let shown = file_uri_to_path(&stored)
+                .and_then(|path| path.to_str().map(String::from))
+                .unwrap_or_else(|| stored.clone());
+            Line { shown, stored }
+        })
+        .collect())
+}
+
+/// The pending queue's items and their family
+fn queue_lines() -> (&'static str, Vec<Line>) {
+    let Some(intent) = queue::read(&paths::pending_dir()) else {
+        return ("path", Vec::new());
+    };
+    let family = match intent.items.first() {
+        Some(IntentItem::Directory(_)) => "directory",
+        Some(IntentItem::Color(_)) => "color",
+        _ => "path",
+    };
+    let lines = intent
+        .values()
+        .into_iter()
+        .map(|value| Line {
+            shown: value.clone(),
+            stored: value,
+        })
+        .collect();
+    (family, lines)
+}
+
+/// The lines written above the entries, dropped when the file is read
+/// back. Entries may start with `#` (colors are `#rrggbb`), so no other line
+/// is taken for a comment.
+fn header(session: bool) -> [String; 2] {
+    [
+        format!(
+            "# {} selection: one entry per line. Delete lines to deselect them,",
+            if session { "Session" } else { "Queued" }
+        ),
+        "# add lines to select more.".to_string(),
+    ]
+}
+
+/// The file the editor is opened on
+fn render(lines: &[Line], session: bool) -> String {
+    let mut content = String::new();
+    for line in header(session)
+        .iter()
+        .chain(lines.iter().map(|line| &line.shown))
+    {
+        content.push_str(line);
+        content.push('\n');
+    }
+    content
+}
+
+/// The entries of an edited file
+fn parse(content: &str, session: bool) -> Vec<String> {
+    let header = header(session);
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !header.iter().any(|h| h == line))
+        .map(String::from)
+        .collect()
+}
+
+/// The `lines` the `edited` entries no longer have, and the entries new to
+/// them (each once)
+fn changes<'a>(lines: &'a [Line], edited: &[String]) -> (Vec<&'a Line>, Vec<String>) {
+    let kept: HashSet<&str> = edited.iter().map(String::as_str).collect();
+    let removed = lines
+        .iter()
+        .filter(|line| !kept.contains(line.shown.as_str()))
+        .collect();
+    let shown: HashSet<&str> = lines.iter().map(|line| line.shown.as_str()).collect();
+    let mut added: Vec<String> = Vec::new();
+    for line in edited {
+        if !shown.contains(line.as_str()) && !added.contains(line) {
+            added.push(line.clone());
+        }
+    }
+    (removed, added)
+}
+
+/// Open the editor on `lines`. Returns the edited entries, or `None` when
+/// the editor failed.
+fn edit(lines: &[Line], session: bool) -> std::io::Result<Option<Vec<String>>> {
+    paths::ensure_base_dir()?;
+    let file = paths::base_dir().join(format!(".select-edit-{}", std::process::id()));
+    files::write_atomic(&file, render(lines, session))?;
+
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|e| !e.trim().is_empty()))
+        .unwrap_or_else(|| "vi".to_string());
+    // Through the shell, so `EDITOR="code --wait"` works
+    let status = Process::new("sh")
+        .args(["-c", &format!("{editor} \"$1\""), "sh"])
+        .arg(&file)
+        .status();
+    let edited = fs::read_to_string(&file);
+    let _ = fs::remove_file(&file);
+
+    let status = status.map_err(|e| std::io::Error::new(e.kind(), format!("{editor}: {e}")))?;
+    if !status.success() {
+        return Ok(None);
+    }
+    Ok(Some(parse(&edited?, session)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(entries: &[&str]) -> Vec<Line> {
+        entries
+            .iter()
+            .map(|entry| Line {
+                shown: entry.to_string(),
+                stored: entry.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn paths_round_trip() {
+        let lines = lines(&["/tmp/a b.txt", "/tmp/#notes"]);
+        let content = render(&lines, true);
+        let edited = parse(&content, true);
+        assert_eq!(edited, ["/tmp/a b.txt", "/tmp/#notes"]);
+        let (removed, added) = changes(&lines, &edited);
+        assert!(removed.is_empty() && added.is_empty());
+
+        let edited = parse(&content.replace("/tmp/a b.txt\n", "/tmp/c\n/tmp/c\n"), true);
+        let (removed, added) = changes(&lines, &edited);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].stored, "/tmp/a b.txt");
+        assert_eq!(added, ["/tmp/c"]);
+    }
+
+    #[test]
+    fn colors_round_trip() {
+        let lines = lines(&["#ff0000"]);
+        let content = render(&lines, false);
+        let edited = parse(&content, false);
+        assert_eq!(edited, ["#ff0000"]);
+        let (removed, added) = changes(&lines, &edited);
+        assert!(removed.is_empty() && added.is_empty());
+
+        let (removed, added) = changes(&lines, &parse(&format!("{content}#00ff00\n"), false));
+        assert!(removed.is_empty());
+        assert_eq!(added, ["#00ff00"]);
+    }
+}
@@ -0,0 +1,81 @@
+//! `portty filter` - file chooser filters for requests made by hand
+//!
+//! `create` turns `NAME=PATTERN[,PATTERN...]` specs, as `portty simulate`,
+//! `porttyd --oneshot`, and the `default_filters` config key take them, into
+//! the filters a FileChooser request carries: GVariant text for
+//! `gdbus call`, or JSON as a session's `options.json` holds them.
+
+use std::process::ExitCode;
+
+use clap::Subcommand;
+use libportty::exit::ExitStatus;
+use libportty::portal::file_chooser::{Filter, FilterPattern};
+
+#[derive(Subcommand)]
+pub enum FilterAction {
+    /// Build filters from NAME=PATTERN[,PATTERN...] specs; patterns are
+    /// separated by `,` or `;`, and those with a `/` are MIME types
+    Create {
+        /// Filters, e.g. 'Images=*.png,*.jpg;image/webp'
+        #[arg(required = true)]
+        filters: Vec<Filter>,
+        /// Print JSON, as in a session's options.json, instead of the
+        /// `a(sa(us))` GVariant text of the `filters` request option
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+pub fn run(action: FilterAction) -> ExitCode {
+    match action {
+        FilterAction::Create { filters, json } => {
+            if json {
+                match serde_json::to_string_pretty(&filters) {
+                    Ok(json) => println!("{json}"),
+                    Err(e) => {
+                        eprintln!("Error: {e}");
+                        return ExitStatus::Failure.into();
+                    }
+                }
+            } else {
+                println!("{}", gvariant(&filters));
+            }
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// `filters` as GVariant text: `[('Images', [(uint32 0, '*.png')])]`
+fn gvariant(filters: &[Filter]) -> String {
+    let filters: Vec<_> = filters
+        .iter()
+        .map(|filter| {
+            let patterns: Vec<_> = filter
+                .patterns
+                .iter()
+                .map(|pattern| {
+                    let kind = match pattern {
+                        FilterPattern::Glob(_) => 0,
+                        FilterPattern::MimeType(_) => 1,
+                    };
+                    format!("(uint32 {kind}, {})", quote(pattern.as_str()))
+                })
+                .collect();
+            format!("({}, [{}])", quote(&filter.name), patterns.join(", "))
+        })
+        .collect();
+    format!("[{}]", filters.join(", "))
+}
+
+/// A GVariant string literal
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in s.chars() {
+        if matches!(c, '\'' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
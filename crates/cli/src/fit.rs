@@ -0,0 +1,133 @@
+//! Width-aware output for `portty list` and `portty queue`.
+//!
+//! Long titles and paths are cut with an ellipsis so each entry stays on one
+//! line of the terminal (or `--width` columns), and `--ascii` replaces anything
+//! outside ASCII, for serial consoles and 80-column terminals. ASCII is the
+//! default when the locale's character set is not UTF-8. Widths are counted
+//! in characters.
+
+use std::io::IsTerminal;
+
+use libportty::locale;
+
+/// Narrowest width lines are cut to, so something of each part stays visible
+const MIN_WIDTH: usize = 20;
+
+/// `--width`, `--ascii`, and `--unicode`
+#[derive(Debug, Clone, clap::Args)]
+pub struct FitArgs {
+    /// Cut long lines to this many columns, at least 20 (default: the
+    /// terminal width, no limit when not writing to a terminal)
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Only print ASCII: other characters become `?`, the ellipsis `...`
+    /// (default when the locale is not UTF-8, e.g. LANG=C)
+    #[arg(long)]
+    ascii: bool,
+
+    /// Print non-ASCII names as they are, whatever the locale
+    #[arg(long, conflicts_with = "ascii")]
+    unicode: bool,
+}
+
+/// Where a line that is too long loses characters
+#[derive(Debug, Clone, Copy)]
+pub enum Cut {
+    /// Keep the start (titles)
+    End,
+    /// Keep both ends (paths: the file name matters)
+    Middle,
+}
+
+/// Output settings resolved from [`FitArgs`]
+#[derive(Debug, Clone)]
+pub struct Fit {
+    width: Option<usize>,
+    ascii: bool,
+}
+
+impl Fit {
+    pub fn new(args: &FitArgs) -> Self {
+        Self {
+            width: args.width.or_else(terminal_width).map(|w| w.max(MIN_WIDTH)),
+            ascii: args.ascii || (!args.unicode && !locale::utf8_output()),
+        }
+    }
+
+    /// `head`, `body`, and `tail` on one line, with `body` cut so the line
+    /// fits. Cuts the end of the line if `head` and `tail` alone don't fit.
+    pub fn line(&self, head: &str, body: &str, tail: &str, cut: Cut) -> String {
+        let (head, body, tail) = (self.text(head), self.text(body), self.text(tail));
+        let Some(width) = self.width else {
+            return format!("{head}{body}{tail}");
+        };
+        let fixed = head.chars().count() + tail.chars().count();
+        let line = match width.checked_sub(fixed) {
+            Some(room) if room >= self.ellipsis().len() => {
+                format!("{head}{}{tail}", self.cut(&body, room, cut))
+            }
+            _ => format!("{head}{body}{tail}"),
+        };
+        self.cut(&line, width, Cut::End)
+    }
+
+    /// `text` with non-ASCII characters replaced in `--ascii` mode, and
+    /// control characters always
+    fn text(&self, text: &str) -> String {
+        text.chars()
+            .map(|c| {
+                if c.is_control() || (self.ascii && !c.is_ascii()) {
+                    '?'
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
+
+    fn ellipsis(&self) -> &'static str {
+        if self.ascii { "..." } else { "…" }
+    }
+
+    /// `text` cut to at most `width` characters, marked with an ellipsis
+    fn cut(&self, text: &str, width: usize, cut: Cut) -> String {
+        let len = text.chars().count();
+        if len <= width {
+            return text.to_string();
+        }
+        let ellipsis = self.ellipsis();
+        let keep = width.saturating_sub(ellipsis.chars().count());
+        match cut {
+            Cut::End => {
+                let head: String = text.chars().take(keep).collect();
+                format!("{head}{ellipsis}")
+            }
+            Cut::Middle => {
+                let tail_len = keep / 2 + keep % 2;
+                let head: String = text.chars().take(keep - tail_len).collect();
+                let tail: String = text.chars().skip(len - tail_len).collect();
+                format!("{head}{ellipsis}{tail}")
+            }
+        }
+    }
+}
+
+/// Columns of the terminal on stdout, or `$COLUMNS`
+fn terminal_width() -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes a winsize into `size`
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 {
+        return Some(usize::from(size.ws_col));
+    }
+    std::env::var("COLUMNS").ok()?.parse().ok()
+}
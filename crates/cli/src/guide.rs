@@ -0,0 +1,258 @@
+//! `portty guide` - what the session's dialog asks for and how to answer it
+//!
+//! Sessions run it as the `?` and `help` shims (shells with a `help` builtin
+//! run that instead). The request is described from `options.json`, and the
+//! commands from the shims in the session's `bin/`, so custom shims show up
+//! too.
+
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use libportty::exit::ExitStatus;
+use libportty::portal::file_chooser::{self, SelectionMode};
+use libportty::portal::screenshot::{self, ScreenshotMode};
+use libportty::portal::{dynamic_launcher, options, remote_desktop};
+use libportty::{Operation, SessionInfo};
+use serde_json::Value;
+
+/// Candidates listed for save-files before the rest is counted
+const LISTED_FILES: usize = 5;
+
+/// A request, as told to the user
+struct Request {
+    /// What the app wants, completing "<app> asks you to ..."
+    ask: String,
+    details: Vec<String>,
+    examples: Vec<String>,
+}
+
+/// Print the guide of the session in `dir`
+pub fn run(dir: &Path, session: &SessionInfo) -> ExitCode {
+    let request = match Operation::of(&session.portal, &session.operation)
+        .and_then(|operation| Ok((operation, options::read(dir).map_err(|e| e.to_string())?)))
+        .and_then(|(operation, options)| describe(operation, options))
+    {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("Error reading options: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+
+    let app = session.app_id.as_deref().filter(|app| !app.is_empty());
+    println!("{} asks you to {}.", app.unwrap_or("An app"), request.ask);
+    for line in &request.details {
+        println!("  {line}");
+    }
+
+    let shims = shims(&dir.join("bin"));
+    if !shims.is_empty() {
+        println!("\nCommands:");
+        let width = shims.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        for (name, command) in &shims {
+            println!("  {name:<width$}  {command}");
+        }
+    }
+
+    println!("\nExamples:");
+    for example in &request.examples {
+        println!("  {example}");
+    }
+    println!(
+        "\nsubmit answers the dialog, cancel refuses it. portty show lists the\n\
+         selection, portty verify checks it, portty help has the rest."
+    );
+    ExitCode::SUCCESS
+}
+
+fn describe(operation: Operation, options: Value) -> Result<Request, String> {
+    match operation {
+        Operation::OpenFile | Operation::SaveFile | Operation::SaveFiles => Ok(
+            describe_file_chooser(serde_json::from_value(options).map_err(|e| e.to_string())?),
+        ),
+        Operation::Screenshot | Operation::PickColor => Ok(describe_screenshot(
+            serde_json::from_value(options).map_err(|e| e.to_string())?,
+        )),
+        Operation::Start => {
+            let options: remote_desktop::SessionOptions =
+                serde_json::from_value(options).map_err(|e| e.to_string())?;
+            let devices = match options.devices.as_slice() {
+                [] => "input devices".to_string(),
+                devices => devices.join(" and "),
+            };
+            Ok(Request {
+                ask: format!("let it control your {devices}"),
+                details: Vec::new(),
+                examples: vec![
+                    "approve        # allow".to_string(),
+                    "deny           # refuse".to_string(),
+                ],
+            })
+        }
+        Operation::PrepareInstall => {
+            let options: dynamic_launcher::SessionOptions =
+                serde_json::from_value(options).map_err(|e| e.to_string())?;
+            let mut details = vec![format!("Type: {}", options.launcher_type)];
+            details.extend(options.target.map(|target| format!("Opens: {target}")));
+            details.extend(options.icon.map(|icon| format!("Icon: {icon}")));
+            let mut examples = vec!["approve        # install it as named".to_string()];
+            if options.editable_name {
+                examples.push("rename My App  # install it under another name".to_string());
+            }
+            examples.push("deny           # refuse".to_string());
+            Ok(Request {
+                ask: format!("install a launcher named {:?}", options.name),
+                details,
+                examples,
+            })
+        }
+        Operation::RequestInstallToken => Ok(Request {
+            ask: "let it install launchers without asking".to_string(),
+            details: Vec::new(),
+            examples: vec![
+                "approve        # allow".to_string(),
+                "deny           # refuse".to_string(),
+            ],
+        }),
+    }
+}
+
+fn describe_file_chooser(options: file_chooser::SessionOptions) -> Request {
+    let mut details = Vec::new();
+    if !options.title.is_empty() {
+        details.push(format!("Dialog: {}", options.title));
+    }
+    if let Some(folder) = options.folder().filter(|f| !f.as_os_str().is_empty()) {
+        details.push(format!("Starts in: {}", folder.display()));
+    }
+    if !options.filters.is_empty() {
+        let names: Vec<_> = options.filters.iter().map(|f| f.name.as_str()).collect();
+        details.push(format!(
+            "Filters: {} (portty get filter-hints)",
+            names.join(", ")
+        ));
+    }
+    details.extend(options.hint.clone());
+    if !options.choices.is_empty() {
+        let labels: Vec<_> = options.choices.iter().map(|c| c.label.as_str()).collect();
+        details.push(format!("Choices: {} (choices)", labels.join(", ")));
+    }
+
+    let (ask, examples) = match options.mode {
+        SelectionMode::Pick {
+            directory: true,
+            multiple,
+        } => (
+            if multiple {
+                "pick one or more folders"
+            } else {
+                "pick a folder"
+            }
+            .to_string(),
+            vec![
+                "folder         # list suggested folders".to_string(),
+                "folder ~/Projects".to_string(),
+            ],
+        ),
+        SelectionMode::Pick { multiple, .. } => (
+            if multiple {
+                "pick one or more files"
+            } else {
+                "pick a file"
+            }
+            .to_string(),
+            vec![
+                "sel ~/Documents/report.pdf".to_string(),
+                "fd -e pdf | sel --stdin".to_string(),
+                "portty select @recent   # the file used last".to_string(),
+            ],
+        ),
+        SelectionMode::Save => {
+            let name = options
+                .candidates
+                .first()
+                .map_or("the file", String::as_str)
+                .to_string();
+            (
+                format!("choose where to save {name}"),
+                vec![
+                    "portty set path ~/Documents/          # keep the name".to_string(),
+                    "portty set path ~/Documents/other.txt # save under a new name".to_string(),
+                ],
+            )
+        }
+        SelectionMode::SaveMultiple => {
+            let count = options.candidates.len();
+            let mut files = options.candidates[..count.min(LISTED_FILES)].join(", ");
+            if count > LISTED_FILES {
+                files.push_str(&format!(", and {} more", count - LISTED_FILES));
+            }
+            if !files.is_empty() {
+                details.push(format!("Files: {files}"));
+            }
+            (
+                format!("choose a folder to save {count} file(s) into"),
+                vec![
+                    "folder ~/Downloads".to_string(),
+                    "conflicts      # files the folder already has".to_string(),
+                    "next-file DIR  # or one file at a time, skip with --skip".to_string(),
+                ],
+            )
+        }
+    };
+    Request {
+        ask,
+        details,
+        examples,
+    }
+}
+
+fn describe_screenshot(options: screenshot::SessionOptions) -> Request {
+    match options.mode {
+        ScreenshotMode::Screenshot { interactive } => Request {
+            ask: "provide a screenshot".to_string(),
+            details: interactive
+                .then(|| "It asked to let you choose what to capture.".to_string())
+                .into_iter()
+                .collect(),
+            examples: [
+                "portty shot".to_string(),
+                "portty shot -- grim -g \"$(slurp)\" {}".to_string(),
+            ]
+            .into_iter()
+            .chain(
+                options
+                    .series
+                    .then(|| "portty shot --series 5 --interval 2".to_string()),
+            )
+            .collect(),
+        },
+        ScreenshotMode::PickColor => Request {
+            ask: "pick a color".to_string(),
+            details: Vec::new(),
+            examples: vec![
+                "portty add color '#ff00aa'".to_string(),
+                "portty add color 'rgb(255,0,170)'".to_string(),
+            ],
+        },
+    }
+}
+
+/// The session's shims and their commands, by name
+fn shims(bin: &Path) -> Vec<(String, String)> {
+    let mut shims: Vec<_> = fs::read_dir(bin)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let script = fs::read_to_string(entry.path()).ok()?;
+            // Rendered as `#!/bin/sh` and the command
+            let command = script.lines().skip(1).collect::<Vec<_>>().join("; ");
+            Some((name, command))
+        })
+        .collect();
+    shims.sort();
+    shims
+}
@@ -0,0 +1,125 @@
+//! `portty history` - what apps got, and how it was produced
+//!
+//! Reads porttyd's results log directly, so it works without the daemon.
+//! Each request shows its source: a session (with a merged queued
+//! submission or pre-selected `defaults` noted), a queued submission with the
+//! time it was queued, or a remembered decision. `--stats` counts them.
+
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+use std::time::{Duration, UNIX_EPOCH};
+
+use libportty::exit::ExitStatus;
+use libportty::portal::history::{self, Entry, Provenance};
+use libportty::portal::utc_timestamp;
+
+use crate::fit::{Cut, Fit};
+
+/// Print the last `limit` logged requests, most recent first, or counts per
+/// source with `stats`
+pub fn run(app: Option<&str>, limit: usize, stats: bool, fit: &Fit) -> ExitCode {
+    let mut entries = match history::read() {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    if let Some(app) = app {
+        entries.retain(|entry| entry.app_id == app);
+    }
+    if entries.is_empty() {
+        println!("No results in the history");
+        return ExitCode::SUCCESS;
+    }
+
+    if stats {
+        print_stats(&entries);
+        return ExitCode::SUCCESS;
+    }
+    for entry in entries.iter().rev().take(limit) {
+        let app = if entry.app_id.is_empty() {
+            "unsandboxed app"
+        } else {
+            &entry.app_id
+        };
+        println!(
+            "{}  {} {}  {app}",
+            entry.time, entry.portal, entry.operation
+        );
+        println!("  from {}", describe(&entry.provenance));
+        if entry.results.is_empty() {
+            println!("    (no results)");
+        }
+        for result in &entry.results {
+            println!("{}", fit.line("    ", result, "", Cut::Middle));
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// The provenance, with the time a queued submission was queued
+fn describe(provenance: &Provenance) -> String {
+    let queued_at = |id: &str| {
+        let millis = id.split_once('-')?.0.parse::<u64>().ok()?;
+        Some(utc_timestamp(UNIX_EPOCH + Duration::from_millis(millis)))
+    };
+    match provenance {
+        Provenance::Queued { queue_id }
+        | Provenance::Session {
+            queue_id: Some(queue_id),
+            ..
+        } => match queued_at(queue_id) {
+            Some(at) => format!("{provenance} (queued {at})"),
+            None => provenance.to_string(),
+        },
+        _ => provenance.to_string(),
+    }
+}
+
+fn print_stats(entries: &[Entry]) {
+    let mut sources: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut apps: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+    let (mut merged, mut defaults) = (0, 0);
+    for entry in entries {
+        let source = entry.provenance.source();
+        *sources.entry(source).or_default() += 1;
+        let app = if entry.app_id.is_empty() {
+            "(unsandboxed)"
+        } else {
+            entry.app_id.as_str()
+        };
+        *apps.entry(app).or_default().entry(source).or_default() += 1;
+        match &entry.provenance {
+            Provenance::Session {
+                queue_id,
+                defaults: from_defaults,
+                ..
+            } => {
+                merged += usize::from(queue_id.is_some());
+                defaults += usize::from(*from_defaults > 0);
+            }
+            Provenance::Decision { defaults: true } => defaults += 1,
+            _ => {}
+        }
+    }
+
+    println!("Requests: {} (since {})", entries.len(), entries[0].time);
+    for (source, count) in &sources {
+        println!("  {source:<9} {count}");
+    }
+    if merged > 0 {
+        println!("  {merged} session(s) started from a merged queued submission");
+    }
+    if defaults > 0 {
+        println!("  {defaults} request(s) returned pre-selected defaults");
+    }
+    println!("By app:");
+    for (app, sources) in &apps {
+        let counts: Vec<_> = sources
+            .iter()
+            .map(|(source, count)| format!("{source} {count}"))
+            .collect();
+        println!("  {app}: {}", counts.join(", "));
+    }
+}
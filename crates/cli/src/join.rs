@@ -0,0 +1,134 @@
+//! `portty join` - a shell in an existing session
+//!
+//! Starts `$SHELL` with the environment the session's own terminal gets
+//! (`PORTTY_SESSION`, `PORTTY_DIR`, the shims on `PATH`, the configured
+//! remote tool and quotas), so a headless session can be answered from any
+//! terminal with its shims. A headless session gets its `bin/` then. A session running in a window of the tabs-mode
+//! tmux server is attached to instead, unless `--shell` is given.
+
+use std::ffi::OsString;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, ExitCode, Stdio};
+
+use libportty::SessionInfo;
+use libportty::config::Config;
+use libportty::exit::ExitStatus;
+use libportty::portal::options;
+use libportty::session_dir::ShimTemplates;
+
+/// tmux server and session of porttyd's tabs mode (`tmux -L portty`)
+const TMUX_SOCKET: &str = "portty";
+const TMUX_SESSION: &str = "portty";
+
+/// Replace this process with a shell in `session`, or with a tmux client on
+/// its window
+pub fn run(session: &SessionInfo, shell: bool) -> ExitCode {
+    if let Ok(current) = std::env::var("PORTTY_SESSION") {
+        if current == session.id {
+            eprintln!("Error: this shell already is in session {current}");
+        } else {
+            eprintln!("Error: this shell is in session {current}; exit it before joining another");
+        }
+        return ExitStatus::Failure.into();
+    }
+    let dir = Path::new(&session.dir);
+    if !dir.is_dir() {
+        eprintln!(
+            "Error: session {} has no directory at {}; it is ending",
+            session.id,
+            dir.display()
+        );
+        return ExitStatus::NotFound.into();
+    }
+
+    let e = if !shell && has_tmux_window(&session.id) {
+        println!("Attaching to the tmux window of session {}", session.id);
+        tmux()
+            .args([
+                "select-window",
+                "-t",
+                &format!("={TMUX_SESSION}:={}", session.id),
+            ])
+            .args([";", "attach-session", "-t", &format!("={TMUX_SESSION}")])
+            // Nesting in the user's own tmux is fine: it is another server
+            .env_remove("TMUX")
+            .exec()
+    } else {
+        let program = std::env::var_os("SHELL")
+            .filter(|shell| !shell.is_empty())
+            .unwrap_or_else(|| "/bin/sh".into());
+        println!(
+            "Joined session {} ({} {}); exit the shell to leave it",
+            session.id, session.portal, session.operation
+        );
+        Command::new(&program)
+            .envs(session_env(session, dir))
+            .exec()
+    };
+    eprintln!("Error: failed to join session {}: {e}", session.id);
+    ExitStatus::Failure.into()
+}
+
+/// Variables of a command the daemon runs for `session`
+fn session_env(session: &SessionInfo, dir: &Path) -> Vec<(&'static str, OsString)> {
+    let (portal, operation) = (session.portal.as_str(), session.operation.as_str());
+    let config = Config::try_load().map_or_else(|_| Config::detected(), |(config, _)| config);
+    let mut env = vec![
+        ("PORTTY_SESSION", OsString::from(&session.id)),
+        ("PORTTY_DIR", dir.into()),
+        ("PORTTY_PORTAL", portal.into()),
+        ("PORTTY_OPERATION", operation.into()),
+    ];
+
+    // Headless sessions start without shims; link the configured ones
+    let bin = dir.join("bin");
+    if !bin.is_dir()
+        && let Err(e) = ShimTemplates::default()
+            .with_cli(config.resolve_cli())
+            .link(&bin, &config.resolve_bin(portal, operation))
+    {
+        eprintln!("Warning: no shims for the session: {e}");
+    }
+    let mut path = OsString::from(bin);
+    if let Some(inherited) = std::env::var_os("PATH") {
+        path.push(":");
+        path.push(inherited);
+    }
+    env.push(("PATH", path));
+
+    let request = options::read(dir).unwrap_or_default();
+    env.extend(
+        config
+            .session_env(portal, operation, &request)
+            .into_iter()
+            .map(|(key, value)| (key, value.into())),
+    );
+    env
+}
+
+fn tmux() -> Command {
+    let mut cmd = Command::new("tmux");
+    cmd.args(["-L", TMUX_SOCKET]);
+    cmd
+}
+
+/// Whether the tabs-mode tmux server has a window for session `id`
+fn has_tmux_window(id: &str) -> bool {
+    tmux()
+        .args([
+            "list-windows",
+            "-t",
+            &format!("={TMUX_SESSION}"),
+            "-F",
+            "#{window_name}",
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|out| {
+            out.status.success()
+                && String::from_utf8_lossy(&out.stdout)
+                    .lines()
+                    .any(|name| name == id)
+        })
+}
@@ -1,13 +1,60 @@
+mod batch;
+mod choices;
+mod config;
+mod daemon;
+mod docs;
+mod doctor;
+mod edit;
+mod filter;
+mod fit;
+mod guide;
+mod history;
+mod join;
+mod nvim;
+mod permissions;
+mod pick;
+mod review;
+mod shot;
+mod simulate;
+mod storage;
+mod tutorial;
+mod wait;
+
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::io::{BufRead, BufReader, IsTerminal, Read};
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
-use clap::{Parser, Subcommand};
+use clap::{FromArgMatches, Parser, Subcommand};
+use fit::{Cut, Fit, FitArgs};
 
 use libportty::client::{ClientError, DaemonClient};
+use libportty::exit::ExitStatus;
+use libportty::portal::exclude::Excludes;
+use libportty::portal::file_chooser::{
+    ConflictStrategy, Filter, FilterPattern, PerFile, Placement, SelectionMode, SessionOptions,
+    file_uri_to_path, path_to_file_uri, save_files_conflicts, save_files_placements,
+};
+use libportty::portal::filter_hints::{self, MimeGlobs};
 use libportty::portal::intent::queue;
-use libportty::portal::{AddResult, Intent, MergeOp, SessionContext, parse_item};
-use libportty::{SessionInfo, files, paths};
+use libportty::portal::markers::{Inspector, Marker};
+use libportty::portal::options;
+use libportty::portal::recent;
+use libportty::portal::selection_file::Source;
+use libportty::portal::snapshot::Snapshot;
+use libportty::portal::{
+    AddResult, Intent, IntentItem, MergeOp, QueueDiff, SessionContext, parse_item,
+};
+use libportty::protocol::{
+    ErrorCode, MULTIPLE_SESSIONS, NO_SESSION, PROTOCOL_VERSION, ServerError,
+};
+use libportty::{
+    QueueConflict, QueuePolicy, RequestInfo, SessionInfo, SortOrder, SubmitOptions, build_info,
+    files, paths, uri,
+};
 
 /// Portty - interact with XDG portal sessions from the command line
 ///
@@ -15,7 +62,7 @@ use libportty::{SessionInfo, files, paths};
 /// - Inside terminal session (PORTTY_SESSION set): file operations on session dir
 /// - Outside: file operations on pending dir, control commands via daemon socket
 #[derive(Parser)]
-#[command(name = "portty", version, about)]
+#[command(name = "portty", version, about, disable_help_subcommand = true)]
 struct Cli {
     /// Target a specific session by ID (only used outside session)
     #[arg(short, long, global = true)]
@@ -34,11 +81,21 @@ enum Command {
 
         /// Items to add
         #[arg(trailing_var_arg = true)]
-        items: Vec<String>,
+        items: Vec<OsString>,
 
         /// Read items from stdin
         #[arg(long)]
         stdin: bool,
+
+        /// Fetch remote `host:path` entries without keeping their times,
+        /// modes, and extended attributes
+        #[arg(long)]
+        no_preserve: bool,
+
+        /// Queue items given more than once as often as given, instead of
+        /// collapsing the repeats
+        #[arg(long)]
+        keep_repeats: bool,
     },
 
     /// Replace the current queue or session with typed items
@@ -48,11 +105,21 @@ enum Command {
 
         /// Items to set
         #[arg(trailing_var_arg = true)]
-        items: Vec<String>,
+        items: Vec<OsString>,
 
         /// Read items from stdin
         #[arg(long)]
         stdin: bool,
+
+        /// Fetch remote `host:path` entries without keeping their times,
+        /// modes, and extended attributes
+        #[arg(long)]
+        no_preserve: bool,
+
+        /// Queue items given more than once as often as given, instead of
+        /// collapsing the repeats
+        #[arg(long)]
+        keep_repeats: bool,
     },
 
     /// Remove typed items from the current queue or session
@@ -62,24 +129,208 @@ enum Command {
 
         /// Items to remove
         #[arg(trailing_var_arg = true)]
-        items: Vec<String>,
+        items: Vec<OsString>,
 
         /// Read items from stdin
         #[arg(long)]
         stdin: bool,
     },
 
+    /// Add paths, or the selection a file manager saved, to the current queue
+    /// or session
+    #[command(group = clap::ArgGroup::new("source").required(true))]
+    Select {
+        /// lf's selection ($XDG_DATA_HOME/lf/files)
+        #[arg(long, group = "source")]
+        from_lf_sel: bool,
+
+        /// ranger's copy buffer ($XDG_DATA_HOME/ranger/copy_buffer)
+        #[arg(long, group = "source")]
+        from_ranger_copy: bool,
+
+        /// A selection file: one path or file:// URI per line, or
+        /// NUL-separated paths
+        #[arg(long, group = "source", value_name = "PATH")]
+        from_file: Option<PathBuf>,
+
+        /// Paths to select, or recently used files: @recent (the most
+        /// recent), @recent:N (entry N of `portty recent`), or @recent:N-M
+        #[arg(group = "source", value_name = "PATH|@recent:N")]
+        paths: Vec<OsString>,
+
+        /// Edit the current selection in $VISUAL or $EDITOR, one path per
+        /// line: deleted lines are deselected, new ones selected
+        #[arg(long, group = "source", conflicts_with = "replace")]
+        edit: bool,
+
+        /// Replace the selection instead of adding to it
+        #[arg(long)]
+        replace: bool,
+
+        /// Leave out files whose name matches GLOB (repeatable)
+        #[arg(long, value_name = "GLOB", conflicts_with = "edit")]
+        exclude: Vec<String>,
+
+        /// Leave out directories whose name matches GLOB, and with
+        /// --recursive do not enter them (repeatable)
+        #[arg(long, value_name = "GLOB", conflicts_with = "edit")]
+        exclude_dir: Vec<String>,
+
+        /// Select the files below directories instead of the directories
+        #[arg(short, long, conflicts_with = "edit")]
+        recursive: bool,
+    },
+
+    /// Run a script of select/deselect/clear/submit commands, one per line
+    ///
+    /// The script is checked in full before anything changes; errors name
+    /// their line. See the README for the command syntax.
+    Batch {
+        /// Script file (default: stdin)
+        file: Option<PathBuf>,
+    },
+
     /// Clear the current queue or session submission
     Clear,
 
-    /// Reset a live session submission to its initial state
-    Reset,
+    /// Reset a live session submission to its initial state, listing the
+    /// entries that were deselected (-) and restored (+)
+    Reset {
+        /// Leave entries matching this glob as they are (a file name like
+        /// `*.pdf`, or a path like `~/Downloads/**`; repeatable)
+        #[arg(long, value_name = "GLOB")]
+        keep: Vec<String>,
+    },
+
+    /// Open a shell in a running session, e.g. a headless one: its shims
+    /// and `portty` commands act on it. A session in a `[tabs]` window is
+    /// attached to instead.
+    Join {
+        /// Session to join (default: --session, or the only active one)
+        id: Option<String>,
+
+        /// Start a shell even when the session has a tmux window
+        #[arg(long)]
+        shell: bool,
+    },
+
+    /// Wait until a dialog opens and print its session id, so a script can
+    /// fill the selection as soon as there is one. A matching session that
+    /// is already active is returned at once, unless `--new` is given.
+    Wait {
+        /// Only sessions of this portal (e.g. file-chooser)
+        #[arg(long)]
+        portal: Option<libportty::Portal>,
+
+        /// Only sessions of this operation (e.g. save-file)
+        #[arg(long)]
+        operation: Option<String>,
+
+        /// Only sessions of this app (app id)
+        #[arg(long)]
+        app: Option<String>,
+
+        /// Ignore sessions already active when waiting starts
+        #[arg(long)]
+        new: bool,
+
+        /// Give up after this many seconds (exit status 2)
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+
+        /// Print the session's variables as `export` lines for `eval`
+        #[arg(long)]
+        env: bool,
+    },
+
+    /// Switch the shared terminal to a session's window (`[tabs]` mode)
+    Focus {
+        /// Session to show
+        id: String,
+    },
 
-    /// Show the current queue or session submission
-    Show,
+    /// Relink the shims (`bin/`) of running sessions to the ones porttyd's
+    /// config defines now, so a fixed shim is picked up without cancelling
+    /// the dialog. Shells see the new set on their next command.
+    RefreshShims {
+        /// Session to refresh (default: --session, or every session)
+        id: Option<String>,
+
+        /// Reload the config first, as `portty daemon reload` does
+        #[arg(long)]
+        reload: bool,
+    },
+
+    /// Show the current queue or session submission, marking missing
+    /// files, directories, paths outside the current folder, and duplicates
+    Show {
+        /// Only list entries with a problem marker
+        #[arg(long)]
+        problems_only: bool,
+    },
 
     /// Submit the current submission
-    Submit,
+    Submit {
+        /// Return an empty selection as success (zero results) instead of
+        /// cancelling, where the operation allows it (open-file)
+        #[arg(long)]
+        allow_empty: bool,
+
+        /// Submit even though the selection touches paths the `audit` config
+        /// asks to confirm
+        #[arg(long)]
+        confirm: bool,
+
+        /// When queued (no active session): queued-first, interactive-first,
+        /// or merge. Overrides the configured queue_policy for this submission
+        #[arg(long)]
+        queue_policy: Option<QueuePolicy>,
+
+        /// Only submit a session of this portal; when none is active, queue
+        /// for this portal instead of any
+        #[arg(long)]
+        portal: Option<String>,
+
+        /// Order of the returned files (file chooser): name, mtime (oldest
+        /// first), or selection (default)
+        #[arg(long)]
+        sort: Option<SortOrder>,
+
+        /// When entries were queued while the session has a selection: merge
+        /// them in, submit the session's, or submit the queue's. Overrides
+        /// the configured queue_conflict (ask: refuse, exit status 3)
+        #[arg(long)]
+        prefer: Option<QueueConflict>,
+
+        /// Show what the app will get, as `portty review` does, and submit
+        /// only once confirmed
+        #[arg(long)]
+        review: bool,
+    },
+
+    /// Show what submitting would return to the app, without submitting
+    Review {
+        /// Review an empty selection as returned empty (see submit)
+        #[arg(long)]
+        allow_empty: bool,
+
+        /// Review as if confirming paths the `audit` config flags
+        #[arg(long)]
+        confirm: bool,
+
+        /// Order of the returned files (file chooser): name, mtime, or
+        /// selection (default)
+        #[arg(long)]
+        sort: Option<SortOrder>,
+
+        /// Review as submitting with --prefer would return
+        #[arg(long)]
+        prefer: Option<QueueConflict>,
+    },
+
+    /// Pick files from numbered listings with plain prompts (file chooser);
+    /// for serial consoles, dumb terminals, and screen readers
+    Pick,
 
     /// Cancel the operation
     Cancel,
@@ -87,14 +338,265 @@ enum Command {
     /// Show session info (options + submission)
     Info,
 
+    /// Explain the session: what the app asks for, the session's commands,
+    /// and examples (the `?` and `help` shims)
+    Guide,
+
+    /// Print a single option field of the session
+    ///
+    /// Fields: title, mode (pick, save, save-multiple), multiple and directory
+    /// (true/false), folder, candidates (one per line), filters (one per line:
+    /// name and patterns, tab-separated), filter-hints (one per line: name,
+    /// globs, and matching files in the current folder), folders (folder
+    /// suggestions, one per line, when the dialog picks a folder). Exits
+    /// with 2 when the request does not set the field.
+    Get {
+        /// Option field to print
+        field: options::Field,
+    },
+
+    /// List the file chooser filters the app offers: index, name, and
+    /// patterns by type (glob or mime); the current filter is marked
+    Filters {
+        /// Print a JSON array of filters, with MIME types expanded to globs
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show save-files targets that already exist in the chosen folder, or
+    /// choose what happens to them: skip, overwrite (default), rename (save
+    /// as `name (N).ext`), or ask (refuse the submission while any exist)
+    Conflicts {
+        /// Strategy for existing files
+        strategy: Option<ConflictStrategy>,
+    },
+
+    /// Save-files, one file at a time: print the next file without a
+    /// folder (exit 2 once all are placed), put it in DIR, or skip it
+    NextFile {
+        /// Folder to save the next file into
+        dir: Option<String>,
+        /// Leave the next file out of the results
+        #[arg(long, conflicts_with = "dir")]
+        skip: bool,
+    },
+
+    /// Pick the values of the combo and check boxes the app adds to the
+    /// file dialog from numbered menus, list them (off a terminal, or with
+    /// --list), or set one: ID VALUE
+    Choices {
+        /// Choice to show or set
+        id: Option<String>,
+        /// Value to give it
+        #[arg(requires = "id")]
+        value: Option<String>,
+        /// List the choices instead of asking
+        #[arg(long, conflicts_with = "id")]
+        list: bool,
+    },
+
+    /// Save the session's selection and the choices made in it (conflict
+    /// strategy, files placed one at a time, dialog choices) to a JSON file
+    /// (- for stdout)
+    Export {
+        /// File to write
+        file: PathBuf,
+    },
+
+    /// Restore a file written by export into this session, e.g. after the
+    /// dialog was cancelled by accident and opened again; it must be of the
+    /// same operation (- reads stdin)
+    Import {
+        /// File to read
+        file: PathBuf,
+    },
+
+    /// Compare the entries queued outside the session with the session's
+    /// selection: what merging would add, what only the session has, and
+    /// what both have (exit 2 when nothing is queued)
+    DiffQueue,
+
     /// Validate submission against portal constraints
     Verify,
 
     /// List active sessions
-    List,
+    List {
+        /// Also list the portal requests in flight, whether or not they
+        /// have a session
+        #[arg(long)]
+        all: bool,
+
+        #[command(flatten)]
+        fit: FitArgs,
+    },
 
     /// Show pending and queued submissions
-    Queue,
+    Queue {
+        #[command(flatten)]
+        fit: FitArgs,
+    },
+
+    /// List recently used files, most recent first, numbered for
+    /// `portty select @recent:N`
+    Recent {
+        /// Only files used by this app (app id, or portty for unsandboxed
+        /// apps)
+        #[arg(long)]
+        app: Option<String>,
+
+        #[command(flatten)]
+        fit: FitArgs,
+    },
+
+    /// Show what apps got from porttyd, most recent first, and how it was
+    /// produced: a session, a queued submission, or a remembered decision
+    History {
+        /// Only requests of this app (app id)
+        #[arg(long)]
+        app: Option<String>,
+
+        /// Number of requests to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// Count the requests per source and app instead of listing them
+        #[arg(long)]
+        stats: bool,
+
+        #[command(flatten)]
+        fit: FitArgs,
+    },
+
+    /// Manage the porttyd daemon
+    Daemon {
+        #[command(subcommand)]
+        action: daemon::DaemonAction,
+    },
+
+    /// Inspect the porttyd configuration
+    Config {
+        #[command(subcommand)]
+        action: config::ConfigAction,
+    },
+
+    /// Craft file chooser filters for requests made by hand (gdbus call,
+    /// tests); `portty simulate --filter` and `default_filters` take the
+    /// same specs
+    Filter {
+        #[command(subcommand)]
+        action: filter::FilterAction,
+    },
+
+    /// Remembered per-app decisions: requests are allowed or denied without
+    /// opening a session (default: list)
+    Permissions {
+        #[command(subcommand)]
+        action: Option<permissions::PermissionsAction>,
+    },
+
+    /// Practice answering dialogs: runs made-up file chooser requests on this
+    /// terminal, independent of a running porttyd
+    Tutorial,
+
+    /// Show the command, environment, shims, and constraints a session for a
+    /// made-up request would get, without D-Bus or running anything
+    ///
+    /// Options: --title, --app-id, --folder, --multiple, --directory,
+    /// --name, --file, and --filter NAME=PATTERN[,PATTERN...] (repeatable;
+    /// patterns with a `/` are MIME types). Entries after the options (or
+    /// after `--`) are checked as a submit would check them; refused entries
+    /// exit with status 4.
+    Simulate {
+        /// Portal, e.g. file-chooser
+        portal: String,
+
+        /// Operation, e.g. open-file
+        operation: String,
+
+        /// Request options, then entries to check
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Diagnose this shell's setup: base directory, daemon socket and
+    /// version, and session variables left from ended sessions
+    Doctor,
+
+    /// Print the version, protocol version, and build of this portty
+    Version {
+        /// Also ask the running porttyd, warning when it differs
+        #[arg(long)]
+        daemon: bool,
+    },
+
+    /// Remove leftover session directories and stale payloads
+    Gc {
+        /// Only show what would be removed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Keep payload directories modified within this many seconds
+        #[arg(long, default_value_t = 3600)]
+        max_age: u64,
+    },
+
+    /// Print a directory to write a session payload into (e.g. a screenshot)
+    ///
+    /// Large payloads go to $XDG_CACHE_HOME/portty instead of /tmp. Fails if
+    /// --size would exceed the session or total quota; what is written to the
+    /// directory afterwards is not checked.
+    PayloadDir {
+        /// Expected payload size in bytes, or with a K/M/G suffix
+        #[arg(long, default_value = "0", value_parser = parse_size_arg)]
+        size: u64,
+    },
+
+    /// Capture a screenshot into the session's payload directory and select
+    /// it (screenshot sessions)
+    ///
+    /// Runs grim, or the command after `--` with `{}` as the file to write
+    /// (appended without one).
+    Shot {
+        /// Take N captures; the first goes to the app, the rest to
+        /// `series_dir` (interactive requests with `series_dir` configured)
+        #[arg(long, value_name = "N", default_value_t = 1,
+              value_parser = clap::value_parser!(u32).range(1..))]
+        series: u32,
+
+        /// Seconds between the captures of a series
+        #[arg(long, value_name = "SECS", default_value_t = 1.0)]
+        interval: f64,
+
+        /// Capture command, `{}` is the file to write
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+
+    /// Neovim integration: print the Lua plugin, or bridge JSON-RPC over stdio
+    Nvim {
+        /// Relay newline-delimited JSON-RPC between stdio and the daemon socket
+        #[arg(long)]
+        nvim_rpc: bool,
+    },
+
+    /// Print the help of portty or a subcommand, with examples
+    Help {
+        /// Also print the help of every subcommand below it
+        #[arg(long)]
+        full: bool,
+
+        /// Subcommand, e.g. `daemon start`
+        command: Vec<String>,
+    },
+
+    /// Write man pages (portty.1, portty-COMMAND.1, portty-config.5) into a
+    /// directory, for packaging
+    #[command(hide = true)]
+    Man {
+        /// Output directory
+        #[arg(default_value = "man")]
+        dir: PathBuf,
+    },
 }
 
 enum Context {
@@ -111,44 +613,367 @@ fn detect_context() -> Context {
 }
 
 fn main() -> ExitCode {
-    let cli = Cli::parse();
+    libportty::config::Config::configure_base_dir();
+    libportty::config::Config::configure_listen();
+    let cli = docs::command()
+        .try_get_matches()
+        .and_then(|matches| Cli::from_arg_matches(&matches))
+        .unwrap_or_else(|e| exit_usage(e));
     let ctx = detect_context();
 
     match cli.command {
-        Some(Command::List) => cmd_list(),
-        Some(Command::Queue) => cmd_show_queue(),
+        Some(Command::List { all, fit }) => cmd_list(&Fit::new(&fit), all),
+        Some(Command::Queue { fit }) => cmd_show_queue(&Fit::new(&fit)),
+        Some(Command::Recent { app, fit }) => cmd_recent(app.as_deref(), &Fit::new(&fit)),
+        Some(Command::History {
+            app,
+            limit,
+            stats,
+            fit,
+        }) => history::run(app.as_deref(), limit, stats, &Fit::new(&fit)),
+        Some(Command::Daemon { action }) => daemon::run(action),
+        Some(Command::Config { action }) => config::run(action),
+        Some(Command::Permissions { action }) => permissions::run(action),
+        Some(Command::Filter { action }) => filter::run(action),
+        Some(Command::Nvim { nvim_rpc }) => nvim::run(nvim_rpc),
+        Some(Command::Help { full, command }) => docs::help(&command, full),
+        Some(Command::Man { dir }) => docs::man(&dir),
+        Some(Command::Doctor) => doctor::run(),
+        Some(Command::Version { daemon }) => cmd_version(daemon),
+        Some(Command::Tutorial) => tutorial::run(),
+        Some(Command::Simulate {
+            portal,
+            operation,
+            args,
+        }) => simulate::run(&portal, &operation, &args),
+        Some(Command::Join { id, shell }) => {
+            with_session_info(id.or(cli.session), |session| join::run(session, shell))
+        }
+        Some(Command::Wait {
+            portal,
+            operation,
+            app,
+            new,
+            timeout,
+            env,
+        }) => wait::run(
+            &wait::Filter {
+                portal,
+                operation,
+                app,
+                new,
+            },
+            timeout.map(std::time::Duration::from_secs),
+            env,
+        ),
+        Some(Command::Focus { id }) => {
+            print_client_result(DaemonClient::new().focus(Some(&id)), "Focused")
+        }
+        Some(Command::RefreshShims { id, reload }) => {
+            cmd_refresh_shims(id.or(cli.session).as_deref(), reload)
+        }
+        Some(Command::Gc { dry_run, max_age }) => {
+            storage::gc(dry_run, std::time::Duration::from_secs(max_age))
+        }
+        Some(Command::PayloadDir { size }) => match (ctx, cli.session) {
+            (Context::Session { session_id }, _) | (Context::Daemon, Some(session_id)) => {
+                storage::payload_dir(&session_id, size)
+            }
+            (Context::Daemon, None) => {
+                eprintln!("payload-dir needs a session (run inside one or pass --session)");
+                ExitStatus::NotFound.into()
+            }
+        },
+        Some(Command::Shot {
+            series,
+            interval,
+            command,
+        }) => {
+            let interval = match std::time::Duration::try_from_secs_f64(interval) {
+                Ok(interval) => interval,
+                Err(_) => {
+                    eprintln!("Error: --interval must be a number of seconds, not {interval}");
+                    return ExitStatus::Usage.into();
+                }
+            };
+            match (ctx, cli.session) {
+                (Context::Session { session_id }, _) | (Context::Daemon, Some(session_id)) => {
+                    shot::run(&session_id, series, interval, &command)
+                }
+                (Context::Daemon, None) => {
+                    eprintln!("shot needs a session (run inside one or pass --session)");
+                    ExitStatus::NotFound.into()
+                }
+            }
+        }
+        Some(Command::Select { edit: true, .. }) => edit::run(ctx, cli.session),
+        Some(Command::Select {
+            from_lf_sel: _,
+            from_ranger_copy,
+            from_file,
+            paths: given,
+            edit: _,
+            replace,
+            exclude,
+            exclude_dir,
+            recursive,
+        }) => {
+            let failure = |e: std::io::Error| (e.to_string(), ExitStatus::Failure);
+            let excludes = match Excludes::new(&exclude, &exclude_dir) {
+                Ok(excludes) => excludes,
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    return ExitStatus::Usage.into();
+                }
+            };
+            let paths = match (given.as_slice(), from_file) {
+                ([spec], _) if spec.to_str().is_some_and(|s| s.starts_with("@recent")) => {
+                    let spec = spec.to_string_lossy();
+                    recent::read(None).map_err(failure).and_then(|list| {
+                        recent::pick(&spec, &list).map_err(|e| (e, ExitStatus::Invalid))
+                    })
+                }
+                ([_, ..], _) => Ok(given.iter().map(PathBuf::from).collect()),
+                ([], Some(path)) => Source::File(path).read().map_err(failure),
+                ([], None) if from_ranger_copy => Source::Ranger.read().map_err(failure),
+                // The `source` group requires one of the four
+                ([], None) => Source::Lf.read().map_err(failure),
+            };
+            let items = match paths {
+                Ok(paths) => excludes
+                    .expand(paths, recursive)
+                    .into_iter()
+                    .map(PathBuf::into_os_string)
+                    .collect(),
+                Err((e, status)) => {
+                    eprintln!("Error: {e}");
+                    return status.into();
+                }
+            };
+            let family = "path".to_string();
+            let cmd = if replace {
+                Command::Set {
+                    family,
+                    items,
+                    stdin: false,
+                    no_preserve: false,
+                    keep_repeats: false,
+                }
+            } else {
+                Command::Add {
+                    family,
+                    items,
+                    stdin: false,
+                    no_preserve: false,
+                    keep_repeats: false,
+                }
+            };
+            run_command(ctx, cli.session, cmd)
+        }
+        Some(Command::Batch { file }) => {
+            let session = match ctx {
+                Context::Session { session_id } => Some(session_id),
+                Context::Daemon => cli.session,
+            };
+            batch::run(file.as_deref(), session)
+        }
         Some(cmd) => run_command(ctx, cli.session, cmd),
-        None => run_command(ctx, cli.session, Command::Show),
+        None => run_command(
+            ctx,
+            cli.session,
+            Command::Show {
+                problems_only: false,
+            },
+        ),
+    }
+}
+
+/// Exit on a command line clap rejected, or after `--help` / `--version`
+fn exit_usage(e: clap::Error) -> ! {
+    if !e.use_stderr() {
+        e.exit();
+    }
+    let _ = e.print();
+    std::process::exit(ExitStatus::Usage.code().into())
+}
+
+fn parse_size_arg(s: &str) -> Result<u64, String> {
+    libportty::quota::parse_size(s).ok_or_else(|| format!("invalid size: {s}"))
+}
+
+fn cmd_list(fit: &Fit, all: bool) -> ExitCode {
+    let client = DaemonClient::new();
+    let listed = client.list().map(|sessions| list_sessions(fit, sessions));
+    let listed = match listed {
+        Ok(()) if all => client
+            .requests()
+            .map(|requests| list_requests(fit, requests)),
+        listed => listed,
+    };
+    match listed {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_status().into()
+        }
     }
 }
 
-fn cmd_list() -> ExitCode {
+/// `portty version`: this CLI's versions, then the daemon's with `daemon`.
+/// A daemon that differs is reported on stderr but is not an error.
+fn cmd_version(daemon: bool) -> ExitCode {
+    let ours = env!("CARGO_PKG_VERSION");
+    println!(
+        "portty {ours} (protocol v{PROTOCOL_VERSION}; {})",
+        build_info::describe(&[])
+    );
+    if !daemon {
+        return ExitCode::SUCCESS;
+    }
+    let status = match DaemonClient::new().status() {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return e.exit_status().into();
+        }
+    };
+    let protocol = status.protocol.map_or_else(
+        || "protocol unknown".to_string(),
+        |v| format!("protocol v{v}"),
+    );
+    let build = status
+        .build
+        .as_deref()
+        .map(|build| format!("; {build}"))
+        .unwrap_or_default();
+    println!(
+        "porttyd {} ({protocol}{build}), pid {}",
+        status.version, status.pid
+    );
+    if status.version != ours || status.protocol != Some(PROTOCOL_VERSION) {
+        eprintln!(
+            "warning: porttyd {} differs from this portty {ours}; \
+             `portty daemon restart` to run the installed daemon",
+            status.version
+        );
+    }
+    ExitCode::SUCCESS
+}
+
+fn cmd_refresh_shims(session_id: Option<&str>, reload: bool) -> ExitCode {
     let client = DaemonClient::new();
-    match client.list() {
+    let refreshed = if reload {
+        client
+            .reload()
+            .and_then(|()| client.refresh_shims(session_id))
+    } else {
+        client.refresh_shims(session_id)
+    };
+    match refreshed {
+        Ok(sessions) if sessions.is_empty() => {
+            println!("No sessions with shims to refresh");
+            ExitCode::SUCCESS
+        }
         Ok(sessions) => {
-            if sessions.is_empty() {
-                println!("No active sessions");
-            } else {
-                for s in sessions {
-                    println!(
-                        "{} [{}:{}] {}",
-                        s.id,
-                        s.portal,
-                        s.operation,
-                        s.title.as_deref().unwrap_or("")
-                    );
-                }
+            for s in sessions {
+                println!("Refreshed {} [{}:{}]", s.id, s.portal, s.operation);
             }
             ExitCode::SUCCESS
         }
         Err(e) => {
-            eprintln!("{e}");
-            ExitCode::from(1)
+            eprintln!("Error: {e}");
+            e.exit_status().into()
+        }
+    }
+}
+
+fn list_sessions(fit: &Fit, sessions: Vec<SessionInfo>) {
+    if sessions.is_empty() {
+        println!("No active sessions");
+        return;
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    for s in sessions {
+        let app = s
+            .app_id
+            .as_deref()
+            .map(|id| format!(" ({id})"))
+            .unwrap_or_default();
+        // Written by the daemon when no terminal could be started
+        let failed_starts = files::read_lines(&Path::new(&s.dir).join("headless"));
+        // Daemons before the mode field only tell failed starts apart
+        let mode = match s.mode {
+            Some(mode) => format!(" {mode}"),
+            None if !failed_starts.is_empty() => " headless".to_string(),
+            None => String::new(),
+        };
+        let head = format!("{} [{}:{}] ", s.id, s.portal, s.operation);
+        let title = s.title.as_deref().unwrap_or("");
+        let waiting = waiting(now.saturating_sub(s.created));
+        println!(
+            "{}",
+            fit.line(
+                &head,
+                title,
+                &format!("{app}{mode} waiting {waiting}"),
+                Cut::End
+            )
+        );
+        for failure in &failed_starts {
+            println!("{}", fit.line("  failed: ", failure, "", Cut::End));
         }
     }
 }
 
-fn cmd_show_queue() -> ExitCode {
+/// How long a dialog has been open: `42s`, `12m`, or `3h05m`
+fn waiting(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Requests in flight: the handle the app waits on, how far porttyd got,
+/// and for how long
+fn list_requests(fit: &Fit, requests: Vec<RequestInfo>) {
+    println!("\nRequests ({}):", requests.len());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    for request in requests {
+        let head = format!(
+            "  [{}:{}] {} {}s ",
+            request.operation.portal(),
+            request.operation,
+            request.state,
+            now.saturating_sub(request.received),
+        );
+        let handle = if request.handle.is_empty() {
+            "-"
+        } else {
+            &request.handle
+        };
+        let app = request
+            .app_id
+            .as_deref()
+            .map(|id| format!(" ({id})"))
+            .unwrap_or_default();
+        let session = request
+            .session_id
+            .as_deref()
+            .map(|id| format!(" session {id}"))
+            .unwrap_or_default();
+        println!(
+            "{}",
+            fit.line(&head, handle, &format!("{app}{session}"), Cut::Middle)
+        );
+    }
+}
+
+fn cmd_show_queue(fit: &Fit) -> ExitCode {
     let pending_dir = paths::pending_dir();
     let pending_intent = queue::read(&pending_dir);
 
@@ -161,15 +986,33 @@ fn cmd_show_queue() -> ExitCode {
     }
 
     if let Some(intent) = pending_intent {
-        println!("Pending intent:");
-        print!("{intent}");
+        match queue::read_failures(&pending_dir) {
+            Some(failures) => {
+                println!(
+                    "Pending intent ({}/{} failed attempts):",
+                    failures.attempts,
+                    queue::MAX_ATTEMPTS
+                );
+                println!(
+                    "{}",
+                    fit.line("  last error: ", &failures.last_error, "", Cut::End)
+                );
+            }
+            None => println!("Pending intent:"),
+        }
+        print_intent_fit(&intent, fit);
     }
 
     if !submissions.is_empty() {
         println!("Submissions ({}):", submissions.len());
-        for (i, (portal, intent)) in submissions.iter().enumerate() {
-            println!("  {}. [{}]", i + 1, portal);
-            print!("{intent}");
+        for (i, (portal, policy, intent)) in submissions.iter().enumerate() {
+            let policy = policy.map(|p| format!(" ({p})")).unwrap_or_default();
+            let head = format!("  {}. [", i + 1);
+            println!(
+                "{}",
+                fit.line(&head, portal, &format!("]{policy}"), Cut::End)
+            );
+            print_intent_fit(intent, fit);
             if i + 1 != submissions.len() {
                 println!();
             }
@@ -179,7 +1022,33 @@ fn cmd_show_queue() -> ExitCode {
     ExitCode::SUCCESS
 }
 
-fn read_submissions_dir(dir: &Path) -> Vec<(String, Intent)> {
+fn cmd_recent(app: Option<&str>, fit: &Fit) -> ExitCode {
+    let list = match recent::read(app) {
+        Ok(list) => list,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    if list.is_empty() {
+        println!("No recently used files");
+        return ExitCode::SUCCESS;
+    }
+    for (i, entry) in list.iter().enumerate() {
+        let file = entry
+            .path()
+            .map_or_else(|| entry.uri.clone(), |p| p.display().to_string());
+        let apps = match entry.apps.as_slice() {
+            [] => String::new(),
+            apps => format!(" ({})", apps.join(", ")),
+        };
+        let head = format!("{:>3}. ", i + 1);
+        println!("{}", fit.line(&head, &file, &apps, Cut::Middle));
+    }
+    ExitCode::SUCCESS
+}
+
+fn read_submissions_dir(dir: &Path) -> Vec<(String, Option<QueuePolicy>, Intent)> {
     let Ok(entries) = fs::read_dir(dir) else {
         return Vec::new();
     };
@@ -197,7 +1066,7 @@ fn read_submissions_dir(dir: &Path) -> Vec<(String, Intent)> {
             let name = name.to_string_lossy();
             let portal = name.split_once('-').map(|(_, p)| p).unwrap_or("unknown");
             let intent = queue::read(&path)?;
-            Some((portal.to_string(), intent))
+            Some((portal.to_string(), queue::read_policy(&path), intent))
         })
         .collect()
 }
@@ -207,16 +1076,21 @@ fn get_session_info(session_id: Option<String>) -> Result<SessionInfo, ClientErr
     let sessions = client.list()?;
 
     if let Some(id) = session_id {
-        sessions
-            .into_iter()
-            .find(|s| s.id == id)
-            .ok_or_else(|| ClientError::Server(format!("Session not found: {id}")))
+        sessions.into_iter().find(|s| s.id == id).ok_or_else(|| {
+            ClientError::Server(ServerError::new(
+                ErrorCode::NoSession,
+                format!("{NO_SESSION} {id}"),
+            ))
+        })
     } else if sessions.is_empty() {
-        Err(ClientError::Server("no active sessions".into()))
+        Err(ClientError::Server(ServerError::new(
+            ErrorCode::NoSession,
+            NO_SESSION,
+        )))
     } else if sessions.len() == 1 {
         Ok(sessions.into_iter().next().expect("checked len == 1"))
     } else {
-        eprintln!("Multiple sessions active, choose with --session:");
+        eprintln!("{MULTIPLE_SESSIONS}, choose with --session:");
         for s in &sessions {
             eprintln!(
                 "  {} [{}:{}] {}",
@@ -226,26 +1100,56 @@ fn get_session_info(session_id: Option<String>) -> Result<SessionInfo, ClientErr
                 s.title.as_deref().unwrap_or("")
             );
         }
-        Err(ClientError::Server("multiple sessions active".into()))
+        Err(ClientError::Server(ServerError::new(
+            ErrorCode::MultipleSessions,
+            MULTIPLE_SESSIONS,
+        )))
     }
 }
 
-fn parse_items(items: &[String], stdin: bool) -> Vec<String> {
+/// Items from the command line or stdin. Paths that are not UTF-8 are
+/// passed on escaped (see [`uri::encode_os`]), so they keep every byte.
+fn parse_items(items: &[OsString], stdin: bool) -> Vec<String> {
     if stdin {
-        use std::io::BufRead;
-        std::io::stdin()
-            .lock()
-            .lines()
-            .map_while(Result::ok)
-            .filter(|l| !l.is_empty())
+        use std::io::Read;
+        let mut input = Vec::new();
+        let _ = std::io::stdin().lock().read_to_end(&mut input);
+        input
+            .split(|&b| b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .filter(|line| !line.is_empty())
+            .map(|line| os_item(OsStr::from_bytes(line)))
             .collect()
     } else {
-        items.to_vec()
+        items.iter().map(|item| os_item(item)).collect()
     }
 }
 
-fn parse_intent(family: &str, items: &[String], stdin: bool) -> Result<Intent, String> {
-    let values = parse_items(items, stdin);
+/// `item` as it is when it is UTF-8, else escaped (see [`uri::encode_os`])
+fn os_item(item: &OsStr) -> String {
+    match item.to_str() {
+        Some(text) => text.to_string(),
+        None => uri::encode_os(item).into_owned(),
+    }
+}
+
+fn parse_intent(family: &str, items: &[OsString], stdin: bool) -> Result<Intent, String> {
+    parse_values(family, parse_items(items, stdin))
+}
+
+/// Like [`parse_intent`], with remote `host:path` entries staged locally
+fn parse_session_intent(
+    session_id: &str,
+    family: &str,
+    items: &[OsString],
+    stdin: bool,
+    preserve: bool,
+) -> Result<Intent, String> {
+    let values = storage::stage_remote(session_id, family, parse_items(items, stdin), preserve)?;
+    parse_values(family, values)
+}
+
+fn parse_values(family: &str, values: Vec<String>) -> Result<Intent, String> {
     if values.is_empty() {
         return Err("no items provided".to_string());
     }
@@ -261,27 +1165,137 @@ fn parse_intent(family: &str, items: &[String], stdin: bool) -> Result<Intent, S
     Ok(intent)
 }
 
-fn print_lines(lines: &[String]) {
-    if lines.is_empty() {
-        println!("(empty)");
-    } else {
-        for line in lines {
-            println!("{line}");
+/// Prints selection entries as they are read, marking entries that are
+/// still the configured defaults and, in file chooser sessions, the
+/// [`Marker`]s of each entry (in color on a terminal, unless `NO_COLOR`)
+struct SelectionPrinter {
+    defaults: Vec<String>,
+    inspector: Option<Inspector>,
+    problems_only: bool,
+    color: bool,
+    empty: bool,
+    problems: usize,
+}
+
+impl SelectionPrinter {
+    fn new(session_dir: &Path, problems_only: bool) -> Self {
+        let inspector = options::read(session_dir)
+            .ok()
+            .and_then(|options| serde_json::from_value::<SessionOptions>(options).ok())
+            .map(|options| Inspector::new(&options));
+        Self {
+            defaults: files::read_lines(&session_dir.join("defaults")),
+            inspector,
+            problems_only,
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            empty: true,
+            problems: 0,
+        }
+    }
+
+    fn print(&mut self, entry: &str) {
+        if entry.is_empty() {
+            return;
+        }
+        self.empty = false;
+        let (markers, problem) = match &mut self.inspector {
+            Some(inspector) => {
+                let markers = inspector.inspect(entry);
+                let problem = markers.iter().any(|&m| inspector.is_problem(m));
+                (markers, problem)
+            }
+            None => (Vec::new(), false),
+        };
+        if problem {
+            self.problems += 1;
+        } else if self.problems_only {
+            return;
+        }
+
+        let mut notes: Vec<String> = markers
+            .iter()
+            .map(|&marker| self.paint(marker.as_str(), marker_color(marker)))
+            .collect();
+        if self.defaults.iter().any(|d| d == entry) {
+            notes.insert(0, "default".to_string());
+        }
+        if notes.is_empty() {
+            println!("{entry}");
+        } else {
+            println!("{entry}  ({})", notes.join(", "));
+        }
+    }
+
+    /// `text` in the ANSI color `code`, when printing in color
+    fn paint(&self, text: &str, code: &str) -> String {
+        if self.color {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn finish(self) {
+        if self.empty {
+            println!("(empty)");
+        } else if self.problems_only && self.problems == 0 {
+            println!("(no problems)");
         }
     }
 }
 
+fn marker_color(marker: Marker) -> &'static str {
+    match marker {
+        Marker::Missing => "31",
+        Marker::Duplicate => "33",
+        Marker::Outside => "35",
+        Marker::Directory => "34",
+    }
+}
+
+/// Print a session's selection from its directory, line by line
+fn print_selection(session_dir: &Path, problems_only: bool) {
+    let mut printer = SelectionPrinter::new(session_dir, problems_only);
+    if let Ok(file) = fs::File::open(session_dir.join("submission")) {
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            printer.print(&line);
+        }
+    }
+    printer.finish();
+}
+
+/// Print a live session's selection as the daemon streams it
+fn stream_selection(session: &SessionInfo, problems_only: bool) -> Result<(), ClientError> {
+    let mut printer = SelectionPrinter::new(Path::new(&session.dir), problems_only);
+    DaemonClient::new().selection_borrowed(Some(&session.id), |entry| printer.print(entry))?;
+    printer.finish();
+    Ok(())
+}
+
 fn print_intent(intent: &Intent) {
     print!("{intent}");
 }
 
+/// Print an intent like its `Display`, with items cut to `fit`
+fn print_intent_fit(intent: &Intent, fit: &Fit) {
+    println!("family:    {}", intent.family);
+    println!("cardinality: {}", intent.cardinality);
+    println!("items ({}):", intent.items.len());
+    for item in &intent.items {
+        let (IntentItem::Path(value) | IntentItem::Directory(value) | IntentItem::Color(value)) =
+            item;
+        let head = format!("  {}: ", item.family());
+        println!("{}", fit.line(&head, value, "", Cut::Middle));
+    }
+}
+
 fn print_session_info(session_dir: &Path) -> ExitCode {
     let options_path = session_dir.join("options.json");
     match fs::read_to_string(&options_path) {
         Ok(json) => println!("{json}"),
         Err(e) => {
             eprintln!("Error reading options: {e}");
-            return ExitCode::from(1);
+            return ExitStatus::Failure.into();
         }
     }
 
@@ -299,6 +1313,350 @@ fn print_session_info(session_dir: &Path) -> ExitCode {
     ExitCode::SUCCESS
 }
 
+/// Print one option field; exits with [`ExitStatus::NotFound`] when it is
+/// unset
+fn print_option_field(session_dir: &Path, field: options::Field) -> ExitCode {
+    let options = match options::read(session_dir) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error reading options: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    match field.get(&options) {
+        Some(lines) => {
+            for line in lines {
+                println!("{line}");
+            }
+            ExitCode::SUCCESS
+        }
+        None => ExitStatus::NotFound.into(),
+    }
+}
+
+/// List the filters of the session in `session_dir`, as text or JSON
+fn cmd_filters(session_dir: &Path, json: bool) -> ExitCode {
+    let options = match options::read(session_dir) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error reading options: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    let filters: Vec<Filter> = options
+        .get("filters")
+        .and_then(|filters| serde_json::from_value(filters.clone()).ok())
+        .unwrap_or_default();
+    let current = options
+        .get("current_filter")
+        .and_then(serde_json::Value::as_u64)
+        .map(|i| i as usize);
+
+    if json {
+        println!(
+            "{}",
+            filter_hints::describe(&filters, current, &MimeGlobs::load())
+        );
+        return ExitCode::SUCCESS;
+    }
+    if filters.is_empty() {
+        eprintln!("The request sets no filters");
+        return ExitStatus::NotFound.into();
+    }
+    for (i, filter) in filters.iter().enumerate() {
+        let mark = if current == Some(i) { " [current]" } else { "" };
+        println!("{i}\t{}{mark}", filter.name);
+        for pattern in &filter.patterns {
+            match pattern {
+                FilterPattern::Glob(glob) => println!("\tglob\t{glob}"),
+                FilterPattern::MimeType(mime) => println!("\tmime\t{mime}"),
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Reset the session in `session_dir` and list what the reset changed
+fn cmd_reset(session_dir: &Path, session_id: &str, keep: &[String]) -> ExitCode {
+    if let Some(glob) = keep.iter().find(|glob| glob.contains(char::is_whitespace)) {
+        eprintln!("Error: --keep {glob:?}: globs cannot contain spaces; use ? to match one");
+        return ExitStatus::Usage.into();
+    }
+    let sub = session_dir.join("submission");
+    let before = files::read_lines(&sub);
+    if let Err(e) = DaemonClient::new().reset_keeping(Some(session_id), keep) {
+        eprintln!("Error: {e}");
+        return e.exit_status().into();
+    }
+    let after = files::read_lines(&sub);
+
+    let shown = |entry: &String| {
+        file_uri_to_path(entry)
+            .and_then(|path| path.to_str().map(String::from))
+            .unwrap_or_else(|| entry.clone())
+    };
+    let (was, is): (HashSet<_>, HashSet<_>) = (before.iter().collect(), after.iter().collect());
+    let deselected: Vec<_> = before.iter().filter(|e| !is.contains(e)).collect();
+    let restored: Vec<_> = after.iter().filter(|e| !was.contains(e)).collect();
+    if deselected.is_empty() && restored.is_empty() {
+        println!("Reset (the selection was already in its initial state)");
+        return ExitCode::SUCCESS;
+    }
+    for entry in &deselected {
+        println!("- {}", shown(entry));
+    }
+    for entry in &restored {
+        println!("+ {}", shown(entry));
+    }
+    println!(
+        "Reset: deselected {}, restored {}",
+        deselected.len(),
+        restored.len()
+    );
+    ExitCode::SUCCESS
+}
+
+/// Show or set how a save-files session handles targets that already exist
+fn cmd_conflicts(session_dir: &Path, strategy: Option<ConflictStrategy>) -> ExitCode {
+    let mut options = match options::read(session_dir) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error reading options: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    let opts: SessionOptions = match serde_json::from_value(options.clone()) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("Error: not a file chooser session: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    if opts.mode != SelectionMode::SaveMultiple || opts.candidates.is_empty() {
+        eprintln!("Error: conflicts only applies to save-files sessions with file names");
+        return ExitStatus::Failure.into();
+    }
+
+    if let Some(strategy) = strategy {
+        options["conflicts"] = serde_json::Value::String(strategy.to_string());
+        if let Err(e) = options::write(session_dir, &options) {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+        println!("Existing files: {strategy}");
+        return ExitCode::SUCCESS;
+    }
+
+    println!("Existing files: {}", opts.conflicts.unwrap_or_default());
+    let entries = files::read_lines(&session_dir.join("submission"));
+    if entries.is_empty() {
+        println!("(no folder selected)");
+        return ExitCode::SUCCESS;
+    }
+    match save_files_conflicts(&entries, &opts) {
+        Ok(existing) if existing.is_empty() => println!("None of the files exist yet"),
+        Ok(existing) => {
+            println!("Already exist:");
+            for target in existing {
+                println!("  {}", target.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Write a snapshot of the session in `session_dir` to `file`
+fn cmd_export(session_dir: &Path, file: &Path) -> ExitCode {
+    let snapshot = match SessionContext::from_session_dir(session_dir)
+        .and_then(|ctx| Snapshot::take(session_dir, ctx.operation))
+    {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    let json = snapshot.to_json();
+    if file == Path::new("-") {
+        println!("{json}");
+        return ExitCode::SUCCESS;
+    }
+    if let Err(e) = files::write_atomic(file, format!("{json}\n")) {
+        eprintln!("Error: {}: {e}", file.display());
+        return ExitStatus::Failure.into();
+    }
+    println!(
+        "Exported {} entries to {}",
+        snapshot.entries.len(),
+        file.display()
+    );
+    ExitCode::SUCCESS
+}
+
+/// Restore the snapshot in `file` into the session in `session_dir`
+fn cmd_import(session_dir: &Path, file: &Path) -> ExitCode {
+    let json = if file == Path::new("-") {
+        let mut json = String::new();
+        std::io::stdin().read_to_string(&mut json).map(|_| json)
+    } else {
+        fs::read_to_string(file)
+    };
+    let snapshot = match json {
+        Ok(json) => Snapshot::from_json(&json),
+        Err(e) => Err(format!("{}: {e}", file.display())),
+    };
+    let restored = snapshot.and_then(|snapshot| {
+        let ctx = SessionContext::from_session_dir(session_dir).map_err(|e| e.to_string())?;
+        let notes = snapshot.restore(session_dir, ctx.operation)?;
+        Ok((snapshot.entries.len(), notes))
+    });
+    match restored {
+        Ok((entries, notes)) => {
+            println!("Imported {entries} entries");
+            for note in notes {
+                println!("  {note}");
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitStatus::Invalid.into()
+        }
+    }
+}
+
+/// Show how the pending queue and a session's selection differ
+fn cmd_diff_queue(session_dir: &Path) -> ExitCode {
+    let Some(intent) = queue::read(&paths::pending_dir()) else {
+        eprintln!("Nothing queued");
+        return ExitStatus::NotFound.into();
+    };
+    let queued = match SessionContext::from_session_dir(session_dir)
+        .and_then(|ctx| ctx.materialize_intent(&intent))
+    {
+        Ok(queued) => queued,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    let diff = QueueDiff::new(&files::read_lines(&session_dir.join("submission")), &queued);
+
+    for (heading, entries) in [
+        ("Queued only (merging adds these)", &diff.queued_only),
+        ("Session only", &diff.session_only),
+        ("In both", &diff.both),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+        println!("{heading}:");
+        for entry in entries {
+            println!("  {entry}");
+        }
+    }
+    if !diff.diverges() {
+        println!("The session already has everything queued");
+    }
+    ExitCode::SUCCESS
+}
+
+/// Place the files of a save-files session one at a time: put the next one
+/// in `dir`, skip it, or just show it. The first use switches the session
+/// to per-file decisions, dropping a folder chosen for all.
+fn cmd_next_file(session_dir: &Path, dir: Option<&str>, skip: bool) -> ExitCode {
+    let mut options = match options::read(session_dir) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error reading options: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    let mut opts: SessionOptions = match serde_json::from_value(options.clone()) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("Error: not a file chooser session: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    if opts.mode != SelectionMode::SaveMultiple || opts.candidates.is_empty() {
+        eprintln!("Error: next-file only applies to save-files sessions with file names");
+        return ExitStatus::Failure.into();
+    }
+
+    let sub = session_dir.join("submission");
+    if opts.per_file.is_none() {
+        let per_file = PerFile::default();
+        options["per_file"] = serde_json::json!(per_file);
+        opts.per_file = Some(per_file);
+        if let Err(e) =
+            options::write(session_dir, &options).and_then(|()| files::write_atomic(&sub, ""))
+        {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    }
+
+    let placements = match save_files_placements(&files::read_lines(&sub), &opts) {
+        Ok(placements) => placements,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+    let total = placements.len();
+    let mut undecided = placements
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, placement))| *placement == Placement::Undecided)
+        .map(|(i, (name, _))| (i + 1, name));
+
+    if dir.is_some() || skip {
+        let Some((_, name)) = undecided.next() else {
+            eprintln!("Error: all {total} files are placed; submit to save them");
+            return ExitStatus::NotFound.into();
+        };
+        let result = match dir {
+            Some(dir) => {
+                let folder = std::path::absolute(dir).unwrap_or_default();
+                if !folder.is_dir() {
+                    eprintln!("Error: {} is not a folder", folder.display());
+                    return ExitStatus::Invalid.into();
+                }
+                let target = folder.join(SessionOptions::candidate_name(name));
+                println!("{name} -> {}", target.display());
+                files::append_lines(&sub, &[path_to_file_uri(&target)])
+            }
+            None => {
+                println!("{name}: skipped");
+                let mut per_file = opts.per_file.clone().unwrap_or_default();
+                per_file.skipped.push(name.clone());
+                options["per_file"] = serde_json::json!(per_file);
+                options::write(session_dir, &options)
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    }
+
+    match undecided.next() {
+        Some((n, name)) if dir.is_some() || skip => println!("Next ({n} of {total}): {name}"),
+        Some((_, name)) => println!("{name}"),
+        None if dir.is_some() || skip => println!("All {total} files placed; submit to save them"),
+        None => {
+            eprintln!("All {total} files placed; submit to save them");
+            return ExitStatus::NotFound.into();
+        }
+    }
+    ExitCode::SUCCESS
+}
+
 fn run_command(ctx: Context, session_id: Option<String>, cmd: Command) -> ExitCode {
     match ctx {
         Context::Session { session_id } => run_session_command(&session_id, cmd),
@@ -306,6 +1664,16 @@ fn run_command(ctx: Context, session_id: Option<String>, cmd: Command) -> ExitCo
     }
 }
 
+/// Exit status of a failed session edit: entries the session does not
+/// accept are invalid
+fn edit_status(e: &std::io::Error) -> ExitStatus {
+    if e.kind() == std::io::ErrorKind::InvalidInput {
+        ExitStatus::Invalid
+    } else {
+        ExitStatus::Failure
+    }
+}
+
 fn handle_add_result(result: AddResult) {
     match result {
         AddResult::Replaced => eprintln!("Replaced (single-select mode)"),
@@ -315,7 +1683,7 @@ fn handle_add_result(result: AddResult) {
 
 fn parse_intent_items(
     family: &str,
-    items: &[String],
+    items: &[OsString],
     stdin: bool,
 ) -> Result<Vec<libportty::portal::IntentItem>, String> {
     let values = parse_items(items, stdin);
@@ -338,10 +1706,6 @@ fn resolve_target_session_dir(session_id: Option<String>) -> Result<Option<PathB
     }
 }
 
-fn resolve_live_session_dir(session_id: Option<String>) -> Result<PathBuf, ClientError> {
-    get_session_info(session_id).map(|session| PathBuf::from(session.dir))
-}
-
 fn run_session_command(session_id: &str, cmd: Command) -> ExitCode {
     let dir = paths::base_dir().join(session_id);
     let sub = dir.join("submission");
@@ -351,26 +1715,29 @@ fn run_session_command(session_id: &str, cmd: Command) -> ExitCode {
             family,
             items,
             stdin,
+            no_preserve,
+            ..
         } => {
-            let intent = match parse_intent(&family, &items, stdin) {
-                Ok(intent) => intent,
-                Err(e) => {
-                    eprintln!("Error: {e}");
-                    return ExitCode::from(1);
-                }
-            };
+            let intent =
+                match parse_session_intent(session_id, &family, &items, stdin, !no_preserve) {
+                    Ok(intent) => intent,
+                    Err(e) => {
+                        eprintln!("Error: {e}");
+                        return ExitStatus::Invalid.into();
+                    }
+                };
 
             match SessionContext::from_session_dir(&dir) {
                 Ok(ctx) => match ctx.add_intent(&intent) {
                     Ok(result) => handle_add_result(result),
                     Err(e) => {
                         eprintln!("Error: {e}");
-                        return ExitCode::from(1);
+                        return edit_status(&e).into();
                     }
                 },
                 Err(e) => {
                     eprintln!("Error detecting session context: {e}");
-                    return ExitCode::from(1);
+                    return ExitStatus::Failure.into();
                 }
             }
 
@@ -380,25 +1747,28 @@ fn run_session_command(session_id: &str, cmd: Command) -> ExitCode {
             family,
             items,
             stdin,
+            no_preserve,
+            ..
         } => {
-            let intent = match parse_intent(&family, &items, stdin) {
-                Ok(intent) => intent,
-                Err(e) => {
-                    eprintln!("Error: {e}");
-                    return ExitCode::from(1);
-                }
-            };
+            let intent =
+                match parse_session_intent(session_id, &family, &items, stdin, !no_preserve) {
+                    Ok(intent) => intent,
+                    Err(e) => {
+                        eprintln!("Error: {e}");
+                        return ExitStatus::Invalid.into();
+                    }
+                };
 
             match SessionContext::from_session_dir(&dir) {
                 Ok(ctx) => {
                     if let Err(e) = ctx.set_intent(&intent) {
                         eprintln!("Error: {e}");
-                        return ExitCode::from(1);
+                        return edit_status(&e).into();
                     }
                 }
                 Err(e) => {
                     eprintln!("Error detecting session context: {e}");
-                    return ExitCode::from(1);
+                    return ExitStatus::Failure.into();
                 }
             }
 
@@ -413,7 +1783,7 @@ fn run_session_command(session_id: &str, cmd: Command) -> ExitCode {
                 Ok(intent) => intent,
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return ExitStatus::Invalid.into();
                 }
             };
 
@@ -421,46 +1791,113 @@ fn run_session_command(session_id: &str, cmd: Command) -> ExitCode {
                 Ok(ctx) => {
                     if let Err(e) = ctx.remove_intent(&intent) {
                         eprintln!("Error: {e}");
-                        return ExitCode::from(1);
+                        return edit_status(&e).into();
                     }
                 }
                 Err(e) => {
                     eprintln!("Error detecting session context: {e}");
-                    return ExitCode::from(1);
+                    return ExitStatus::Failure.into();
                 }
             }
 
             ExitCode::SUCCESS
         }
         Command::Clear => {
-            if let Err(e) = fs::write(&sub, "") {
+            if let Err(e) = files::write_atomic(&sub, "") {
                 eprintln!("Error: {e}");
-                return ExitCode::from(1);
+                return ExitStatus::Failure.into();
             }
             ExitCode::SUCCESS
         }
-        Command::Reset => {
-            let client = DaemonClient::new();
-            print_client_result(client.reset(Some(session_id)), "Reset")
-        }
-        Command::Show => {
-            print_lines(&files::read_lines(&sub));
+        Command::Reset { keep } => cmd_reset(&dir, session_id, &keep),
+        Command::Show { problems_only } => {
+            print_selection(&dir, problems_only);
             ExitCode::SUCCESS
         }
         Command::Info => print_session_info(&dir),
+        Command::Guide => match get_session_info(Some(session_id.to_string())) {
+            Ok(session) => guide::run(&dir, &session),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::Get { field } => print_option_field(&dir, field),
+        Command::Filters { json } => cmd_filters(&dir, json),
+        Command::Conflicts { strategy } => cmd_conflicts(&dir, strategy),
+        Command::Choices { id, value, list } => {
+            choices::run(&dir, id.as_deref(), value.as_deref(), list)
+        }
+        Command::Export { file } => cmd_export(&dir, &file),
+        Command::Import { file } => cmd_import(&dir, &file),
+        Command::NextFile { dir: folder, skip } => cmd_next_file(&dir, folder.as_deref(), skip),
+        Command::DiffQueue => cmd_diff_queue(&dir),
         Command::Verify => {
             let client = DaemonClient::new();
             print_client_result(client.verify(Some(session_id)), "Valid")
         }
-        Command::Submit => {
+        Command::Submit {
+            allow_empty,
+            confirm,
+            queue_policy,
+            portal,
+            sort,
+            prefer,
+            review,
+        } => {
             let client = DaemonClient::new();
-            print_client_result(client.submit(Some(session_id)), "Submitted")
+            let options = SubmitOptions {
+                allow_empty,
+                confirm,
+                queue_policy,
+                portal,
+                sort,
+                prefer,
+            };
+            if review {
+                return with_session_info(Some(session_id.to_string()), |session| {
+                    review::submit(session, options)
+                });
+            }
+            print_client_result(client.submit_with(Some(session_id), &options), "Submitted")
         }
+        Command::Review {
+            allow_empty,
+            confirm,
+            sort,
+            prefer,
+        } => with_session_info(Some(session_id.to_string()), |session| {
+            review::run(session, review_options(allow_empty, confirm, sort, prefer))
+        }),
+        Command::Pick => with_session_info(Some(session_id.to_string()), pick::run),
         Command::Cancel => {
             let client = DaemonClient::new();
             print_client_result(client.cancel(Some(session_id)), "Cancelled")
         }
-        Command::List | Command::Queue => unreachable!(),
+        Command::List { .. }
+        | Command::Queue { .. }
+        | Command::Recent { .. }
+        | Command::History { .. }
+        | Command::Daemon { .. }
+        | Command::Config { .. }
+        | Command::Permissions { .. }
+        | Command::Filter { .. }
+        | Command::Help { .. }
+        | Command::Man { .. }
+        | Command::Nvim { .. }
+        | Command::Gc { .. }
+        | Command::Doctor
+        | Command::Version { .. }
+        | Command::Tutorial
+        | Command::Simulate { .. }
+        | Command::Focus { .. }
+        | Command::RefreshShims { .. }
+        | Command::Join { .. }
+        | Command::Wait { .. }
+        | Command::PayloadDir { .. }
+        | Command::Shot { .. }
+        | Command::Select { .. }
+        | Command::Batch { .. } => unreachable!(),
     }
 }
 
@@ -472,12 +1909,14 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
             family,
             items,
             stdin,
+            keep_repeats,
+            ..
         } => {
             let intent = match parse_intent(&family, &items, stdin) {
                 Ok(intent) => intent,
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return ExitStatus::Invalid.into();
                 }
             };
 
@@ -485,7 +1924,7 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                 Ok(dir) => dir,
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return e.exit_status().into();
                 }
             };
 
@@ -494,7 +1933,7 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                     Ok(ctx) => ctx,
                     Err(e) => {
                         eprintln!("Error detecting session context: {e}");
-                        return ExitCode::from(1);
+                        return ExitStatus::Failure.into();
                     }
                 };
 
@@ -502,34 +1941,43 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                     Ok(result) => handle_add_result(result),
                     Err(e) => {
                         eprintln!("Error: {e}");
-                        return ExitCode::from(1);
+                        return edit_status(&e).into();
                     }
                 }
                 return ExitCode::SUCCESS;
             }
 
             let mut existing = queue::read(&pending).unwrap_or_default();
+            existing.keep_repeats |= keep_repeats;
+            existing.normalize();
+            let before = existing.len();
             if let Err(e) = existing.apply(&intent.items, MergeOp::Add) {
                 eprintln!("Error: {e}");
-                return ExitCode::from(1);
+                return ExitStatus::Invalid.into();
             }
             if let Err(e) = queue::write(&pending, &existing) {
                 eprintln!("Error: {e}");
-                return ExitCode::from(1);
+                return ExitStatus::Failure.into();
+            }
+            let queued = existing.len().saturating_sub(before);
+            match intent.items.len() - queued {
+                0 => println!("Queued {queued} item(s)"),
+                repeats => println!("Queued {queued} item(s), collapsed {repeats} repeat(s)"),
             }
-            println!("Queued {} item(s)", intent.items.len());
             ExitCode::SUCCESS
         }
         Command::Set {
             family,
             items,
             stdin,
+            keep_repeats,
+            ..
         } => {
-            let intent = match parse_intent(&family, &items, stdin) {
+            let mut intent = match parse_intent(&family, &items, stdin) {
                 Ok(intent) => intent,
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return ExitStatus::Invalid.into();
                 }
             };
 
@@ -537,7 +1985,7 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                 Ok(dir) => dir,
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return e.exit_status().into();
                 }
             };
 
@@ -546,20 +1994,22 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                     Ok(ctx) => ctx,
                     Err(e) => {
                         eprintln!("Error detecting session context: {e}");
-                        return ExitCode::from(1);
+                        return ExitStatus::Failure.into();
                     }
                 };
 
                 if let Err(e) = ctx.set_intent(&intent) {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return edit_status(&e).into();
                 }
                 return ExitCode::SUCCESS;
             }
 
+            intent.keep_repeats = keep_repeats;
+            intent.normalize();
             if let Err(e) = queue::write(&pending, &intent) {
                 eprintln!("Error: {e}");
-                return ExitCode::from(1);
+                return ExitStatus::Failure.into();
             }
             println!("Queued replacement");
             ExitCode::SUCCESS
@@ -573,7 +2023,7 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                 Ok(dir) => dir,
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return e.exit_status().into();
                 }
             };
 
@@ -582,20 +2032,20 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                     Ok(intent) => intent,
                     Err(e) => {
                         eprintln!("Error: {e}");
-                        return ExitCode::from(1);
+                        return ExitStatus::Invalid.into();
                     }
                 };
                 let ctx = match SessionContext::from_session_dir(&dir) {
                     Ok(ctx) => ctx,
                     Err(e) => {
                         eprintln!("Error detecting session context: {e}");
-                        return ExitCode::from(1);
+                        return ExitStatus::Failure.into();
                     }
                 };
 
                 if let Err(e) = ctx.remove_intent(&intent) {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return edit_status(&e).into();
                 }
                 return ExitCode::SUCCESS;
             }
@@ -604,7 +2054,7 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                 Ok(items) => items,
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return ExitStatus::Invalid.into();
                 }
             };
 
@@ -612,23 +2062,23 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                 Some(intent) => intent,
                 None => {
                     eprintln!("Error: no pending intent to remove from");
-                    return ExitCode::from(1);
+                    return ExitStatus::Failure.into();
                 }
             };
 
             if let Err(e) = existing.remove(&items) {
                 eprintln!("Error: {e}");
-                return ExitCode::from(1);
+                return ExitStatus::Invalid.into();
             }
 
             if existing.is_empty() {
                 if let Err(e) = queue::clear(&pending) {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return ExitStatus::Failure.into();
                 }
             } else if let Err(e) = queue::write(&pending, &existing) {
                 eprintln!("Error: {e}");
-                return ExitCode::from(1);
+                return ExitStatus::Failure.into();
             }
 
             ExitCode::SUCCESS
@@ -638,29 +2088,34 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                 Ok(dir) => dir,
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return e.exit_status().into();
                 }
             };
 
             if let Some(dir) = target_dir {
-                if let Err(e) = fs::write(dir.join("submission"), "") {
+                if let Err(e) = files::write_atomic(&dir.join("submission"), "") {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return ExitStatus::Failure.into();
                 }
             } else if let Err(e) = queue::clear(&pending) {
                 eprintln!("Error: {e}");
-                return ExitCode::from(1);
+                return ExitStatus::Failure.into();
             }
             ExitCode::SUCCESS
         }
-        Command::Reset => {
-            let client = DaemonClient::new();
-            print_client_result(client.reset(session_id.as_deref()), "Reset")
-        }
-        Command::Show => {
-            match resolve_live_session_dir(session_id.clone()) {
-                Ok(dir) => print_lines(&files::read_lines(&dir.join("submission"))),
-                Err(ClientError::Server(msg)) if msg == "no active sessions" => {
+        Command::Reset { keep } => match get_session_info(session_id) {
+            Ok(session) => cmd_reset(Path::new(&session.dir), &session.id, &keep),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::Show { problems_only } => {
+            match get_session_info(session_id.clone())
+                .and_then(|s| stream_selection(&s, problems_only))
+            {
+                Ok(()) => {}
+                Err(e) if session_id.is_none() && e.exit_status() == ExitStatus::NotFound => {
                     if let Some(intent) = queue::read(&pending) {
                         print_intent(&intent);
                     } else {
@@ -669,7 +2124,7 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                 }
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return e.exit_status().into();
                 }
             }
             ExitCode::SUCCESS
@@ -679,25 +2134,176 @@ fn run_daemon_command(session_id: Option<String>, cmd: Command) -> ExitCode {
                 Ok(s) => s,
                 Err(e) => {
                     eprintln!("Error: {e}");
-                    return ExitCode::from(1);
+                    return e.exit_status().into();
                 }
             };
 
             print_session_info(&PathBuf::from(&session.dir))
         }
+        Command::Get { field } => match get_session_info(session_id) {
+            Ok(session) => print_option_field(Path::new(&session.dir), field),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::Filters { json } => match get_session_info(session_id) {
+            Ok(session) => cmd_filters(Path::new(&session.dir), json),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::Guide => match get_session_info(session_id) {
+            Ok(session) => guide::run(Path::new(&session.dir), &session),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::Conflicts { strategy } => match get_session_info(session_id) {
+            Ok(session) => cmd_conflicts(Path::new(&session.dir), strategy),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::Export { file } => match get_session_info(session_id) {
+            Ok(session) => cmd_export(Path::new(&session.dir), &file),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::Import { file } => match get_session_info(session_id) {
+            Ok(session) => cmd_import(Path::new(&session.dir), &file),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::Choices { id, value, list } => match get_session_info(session_id) {
+            Ok(session) => choices::run(
+                Path::new(&session.dir),
+                id.as_deref(),
+                value.as_deref(),
+                list,
+            ),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::NextFile { dir, skip } => match get_session_info(session_id) {
+            Ok(session) => cmd_next_file(Path::new(&session.dir), dir.as_deref(), skip),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
+        Command::DiffQueue => match get_session_info(session_id) {
+            Ok(session) => cmd_diff_queue(Path::new(&session.dir)),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                e.exit_status().into()
+            }
+        },
         Command::Verify => {
             let client = DaemonClient::new();
             print_client_result(client.verify(session_id.as_deref()), "Valid")
         }
-        Command::Submit => {
+        Command::Submit {
+            allow_empty,
+            confirm,
+            queue_policy,
+            portal,
+            sort,
+            prefer,
+            review,
+        } => {
             let client = DaemonClient::new();
-            print_client_result(client.submit(session_id.as_deref()), "Submitted")
+            let options = SubmitOptions {
+                allow_empty,
+                confirm,
+                queue_policy,
+                portal,
+                sort,
+                prefer,
+            };
+            if review {
+                return with_session_info(session_id, |session| review::submit(session, options));
+            }
+            print_client_result(
+                client.submit_with(session_id.as_deref(), &options),
+                "Submitted",
+            )
         }
+        Command::Review {
+            allow_empty,
+            confirm,
+            sort,
+            prefer,
+        } => with_session_info(session_id, |session| {
+            review::run(session, review_options(allow_empty, confirm, sort, prefer))
+        }),
+        Command::Pick => with_session_info(session_id, pick::run),
         Command::Cancel => {
             let client = DaemonClient::new();
             print_client_result(client.cancel(session_id.as_deref()), "Cancelled")
         }
-        Command::List | Command::Queue => unreachable!(),
+        Command::List { .. }
+        | Command::Queue { .. }
+        | Command::Recent { .. }
+        | Command::History { .. }
+        | Command::Daemon { .. }
+        | Command::Config { .. }
+        | Command::Permissions { .. }
+        | Command::Filter { .. }
+        | Command::Help { .. }
+        | Command::Man { .. }
+        | Command::Nvim { .. }
+        | Command::Gc { .. }
+        | Command::Doctor
+        | Command::Version { .. }
+        | Command::Tutorial
+        | Command::Simulate { .. }
+        | Command::Focus { .. }
+        | Command::RefreshShims { .. }
+        | Command::Join { .. }
+        | Command::Wait { .. }
+        | Command::PayloadDir { .. }
+        | Command::Shot { .. }
+        | Command::Select { .. }
+        | Command::Batch { .. } => unreachable!(),
+    }
+}
+
+fn review_options(
+    allow_empty: bool,
+    confirm: bool,
+    sort: Option<SortOrder>,
+    prefer: Option<QueueConflict>,
+) -> SubmitOptions {
+    SubmitOptions {
+        allow_empty,
+        confirm,
+        sort,
+        prefer,
+        ..SubmitOptions::default()
+    }
+}
+
+/// Run `f` on the session `session_id` names (or the only one)
+fn with_session_info(
+    session_id: Option<String>,
+    f: impl FnOnce(&SessionInfo) -> ExitCode,
+) -> ExitCode {
+    match get_session_info(session_id) {
+        Ok(session) => f(&session),
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_status().into()
+        }
     }
 }
 
@@ -709,7 +2315,7 @@ fn print_client_result(result: Result<(), ClientError>, success_msg: &str) -> Ex
         }
         Err(e) => {
             eprintln!("Error: {e}");
-            ExitCode::from(1)
+            e.exit_status().into()
         }
     }
 }
@@ -0,0 +1,76 @@
+//! `portty nvim` - Neovim integration
+//!
+//! `portty nvim --nvim-rpc` is a stdio bridge: newline-delimited JSON-RPC 2.0
+//! on stdin is relayed to the daemon socket and responses are written to
+//! stdout, so a Neovim job (`vim.fn.jobstart`) can drive sessions without
+//! speaking Unix sockets. Without the flag, the Lua plugin template is printed.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::process::ExitCode;
+use std::thread;
+
+use libportty::exit::ExitStatus;
+use libportty::socket;
+
+/// Lua plugin template (`portty nvim > ~/.config/nvim/lua/portty.lua`)
+const PLUGIN: &str = include_str!("../nvim/portty.lua");
+
+pub fn run(nvim_rpc: bool) -> ExitCode {
+    if !nvim_rpc {
+        print!("{PLUGIN}");
+        return ExitCode::SUCCESS;
+    }
+
+    match bridge() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitStatus::Failure.into()
+        }
+    }
+}
+
+/// Relay stdin lines to the daemon socket and socket lines to stdout until
+/// either side closes.
+fn bridge() -> io::Result<()> {
+    let addresses = socket::daemon_addresses();
+    let stream = addresses
+        .iter()
+        .find_map(|address| address.connect().ok())
+        .ok_or_else(|| {
+            let tried: Vec<_> = addresses.iter().map(ToString::to_string).collect();
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("failed to connect to {}", tried.join(", ")),
+            )
+        })?;
+
+    let reader = stream.try_clone()?;
+    let responses = thread::spawn(move || -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        for line in BufReader::new(reader).lines() {
+            writeln!(stdout, "{}", line?)?;
+            stdout.flush()?;
+        }
+        Ok(())
+    });
+
+    let mut writer = &stream;
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        // The daemon picks JSON-RPC from the first byte, so never lead with
+        // blank lines
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(writer, "{line}")?;
+        writer.flush()?;
+    }
+
+    // Let in-flight responses drain before exiting
+    stream.shutdown(Shutdown::Write)?;
+    responses
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("response relay panicked")))
+}
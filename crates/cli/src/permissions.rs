@@ -0,0 +1,116 @@
+//! `portty permissions` - per-app decisions in the permission store
+//!
+//! The daemon keeps them in xdg-desktop-portal's permission store and answers
+//! requests with a decision without opening a session.
+
+use std::process::ExitCode;
+
+use clap::Subcommand;
+
+use libportty::client::DaemonClient;
+use libportty::exit::ExitStatus;
+use libportty::{Decision, Operation};
+
+#[derive(Subcommand)]
+pub enum PermissionsAction {
+    /// Show remembered decisions
+    List {
+        /// Only this app's decisions
+        app_id: Option<String>,
+    },
+    /// Remember a decision: allow (submit without a session) or deny
+    /// (cancel without a session)
+    Set {
+        /// App the decision is for (e.g. com.obsproject.Studio)
+        app_id: String,
+        /// Portal of the operation (e.g. screenshot)
+        portal: String,
+        /// Operation (e.g. screenshot)
+        operation: String,
+        /// allow or deny
+        decision: Decision,
+    },
+    /// Forget an app's decision for one operation, or all of them
+    Clear {
+        /// App whose decisions to forget
+        app_id: String,
+        /// Portal of the operation (default: all)
+        #[arg(requires = "operation")]
+        portal: Option<String>,
+        /// Operation
+        operation: Option<String>,
+    },
+}
+
+pub fn run(action: Option<PermissionsAction>) -> ExitCode {
+    let client = DaemonClient::new();
+    let result = match action.unwrap_or(PermissionsAction::List { app_id: None }) {
+        PermissionsAction::List { app_id } => return list(&client, app_id.as_deref()),
+        PermissionsAction::Set {
+            app_id,
+            portal,
+            operation,
+            decision,
+        } => parse_operation(&portal, &operation).and_then(|operation| {
+            client
+                .set_permission(&app_id, operation, decision)
+                .map_err(|e| (e.to_string(), e.exit_status()))
+                .map(|()| format!("{app_id}: {decision} {portal} {operation}"))
+        }),
+        PermissionsAction::Clear {
+            app_id,
+            portal,
+            operation,
+        } => portal
+            .zip(operation)
+            .map(|(portal, operation)| parse_operation(&portal, &operation))
+            .transpose()
+            .and_then(|operation| {
+                client
+                    .clear_permission(&app_id, operation)
+                    .map_err(|e| (e.to_string(), e.exit_status()))
+                    .map(|()| format!("Cleared decisions of {app_id}"))
+            }),
+    };
+
+    match result {
+        Ok(msg) => {
+            println!("{msg}");
+            ExitCode::SUCCESS
+        }
+        Err((e, status)) => {
+            eprintln!("Error: {e}");
+            status.into()
+        }
+    }
+}
+
+/// The operation named on the command line
+fn parse_operation(portal: &str, operation: &str) -> Result<Operation, (String, ExitStatus)> {
+    Operation::of(portal, operation).map_err(|e| (e, ExitStatus::Invalid))
+}
+
+fn list(client: &DaemonClient, app_id: Option<&str>) -> ExitCode {
+    match client.permissions(app_id) {
+        Ok(permissions) if permissions.is_empty() => {
+            println!("No remembered decisions");
+            ExitCode::SUCCESS
+        }
+        Ok(permissions) => {
+            for p in permissions {
+                println!(
+                    "{} [{}:{}] {}",
+                    p.app_id,
+                    p.operation.portal(),
+                    p.operation,
+                    p.decision
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_status().into()
+        }
+    }
+}
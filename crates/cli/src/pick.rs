@@ -0,0 +1,443 @@
+//! `portty pick` - a line-oriented file chooser
+//!
+//! Numbered listings and plain prompts in place of a shell or a full-screen
+//! picker: nothing is redrawn and no cursor addressing or color is used, so
+//! the dialog works over a serial console, on a `TERM=dumb` terminal, and
+//! with speech output, which reads each line once as it is printed. Every
+//! change to the selection is announced as a sentence.
+//!
+//! The `picker` config key runs it as a file chooser session's command
+//! (`picker = "menu"`, or `"auto"` on a dumb terminal).
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use libportty::client::DaemonClient;
+use libportty::exit::ExitStatus;
+use libportty::portal::file_chooser::{SelectionMode, SessionOptions, file_uri_to_path};
+use libportty::portal::filter_hints::{FilterMatcher, MimeGlobs};
+use libportty::portal::{AddResult, SessionContext};
+use libportty::{Portal, SessionInfo, files, uri};
+
+const HELP: &str = "\
+Commands:
+  NUMBER       select or deselect an entry; a number on its own opens a folder
+  1-4 7        select or deselect several entries
+  o NUMBER     open a folder
+  g PATH       go to a folder
+  u            go up to the parent folder
+  l            list the folder again
+  v            view the selection
+  n NAME       save under NAME in this folder (save dialogs)
+  s            submit the selection
+  c            cancel the dialog
+  h            show this help";
+
+struct Entry {
+    path: PathBuf,
+    dir: bool,
+}
+
+struct Picker<'a> {
+    session: &'a SessionInfo,
+    ctx: SessionContext,
+    options: SessionOptions,
+    filter: Option<FilterMatcher>,
+    folder: PathBuf,
+    entries: Vec<Entry>,
+}
+
+/// What a command line does to the dialog
+enum Outcome {
+    Continue,
+    Done(ExitCode),
+}
+
+/// Run the picker for a file chooser `session` on stdin and stdout
+pub fn run(session: &SessionInfo) -> ExitCode {
+    if session.portal != Portal::FileChooser.as_str() {
+        eprintln!(
+            "Error: portty pick works in file chooser sessions, not {}",
+            session.portal
+        );
+        return ExitStatus::Usage.into();
+    }
+    let mut picker = match Picker::new(session) {
+        Ok(picker) => picker,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitStatus::Failure.into();
+        }
+    };
+
+    picker.introduce();
+    picker.list();
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        line.clear();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                println!();
+                println!("End of input. The dialog stays open.");
+                return ExitCode::SUCCESS;
+            }
+            Ok(_) => {}
+        }
+        if let Outcome::Done(code) = picker.command(line.trim()) {
+            return code;
+        }
+    }
+}
+
+impl<'a> Picker<'a> {
+    fn new(session: &'a SessionInfo) -> Result<Self, String> {
+        let ctx = SessionContext::from_session_dir(&session.dir)
+            .map_err(|e| format!("failed to read session: {e}"))?;
+        let options: SessionOptions = ctx
+            .read_options()
+            .and_then(|value| serde_json::from_value(value).map_err(io::Error::other))
+            .map_err(|e| format!("failed to read options: {e}"))?;
+        let filter = options
+            .current_filter
+            .and_then(|i| options.filters.get(i))
+            .map(|filter| FilterMatcher::new(filter, &MimeGlobs::load()))
+            .filter(|matcher| !matcher.is_empty());
+        let folder = options
+            .folder()
+            .filter(|folder| folder.is_dir())
+            .or_else(|| std::env::var_os("HOME").map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from("/"));
+        Ok(Self {
+            session,
+            ctx,
+            options,
+            filter,
+            folder,
+            entries: Vec::new(),
+        })
+    }
+
+    fn introduce(&self) {
+        let app = self.session.app_id.as_deref().filter(|app| !app.is_empty());
+        let title = Some(self.options.title.as_str()).filter(|title| !title.is_empty());
+        println!(
+            "{} asks to {}{}.",
+            app.unwrap_or("An app"),
+            match self.options.mode {
+                SelectionMode::Pick {
+                    multiple: false,
+                    directory: false,
+                } => "open a file",
+                SelectionMode::Pick {
+                    multiple: true,
+                    directory: false,
+                } => "open files",
+                SelectionMode::Pick {
+                    multiple: false,
+                    directory: true,
+                } => "choose a folder",
+                SelectionMode::Pick {
+                    multiple: true,
+                    directory: true,
+                } => "choose folders",
+                SelectionMode::Save => "save a file",
+                SelectionMode::SaveMultiple => "save files into a folder",
+            },
+            title.map(|title| format!(": {title}")).unwrap_or_default()
+        );
+        if let Some(name) = self.options.candidates.first() {
+            println!("Suggested name: {name}.");
+        }
+        if let Some(filter) = &self.filter {
+            println!("Showing files matching {}.", filter.summary());
+        }
+        println!("Type h for help.");
+    }
+
+    fn command(&mut self, line: &str) -> Outcome {
+        let (cmd, arg) = line
+            .split_once(char::is_whitespace)
+            .map(|(cmd, arg)| (cmd, arg.trim()))
+            .unwrap_or((line, ""));
+        match cmd {
+            "" => {}
+            "h" | "?" | "help" => println!("{HELP}"),
+            "l" => self.list(),
+            "u" => match self.folder.parent() {
+                Some(parent) => {
+                    let parent = parent.to_path_buf();
+                    self.open(parent);
+                }
+                None => println!("Already at the top folder."),
+            },
+            "o" => match self.pick_one(arg) {
+                Some(entry) if self.entries[entry].dir => {
+                    let path = self.entries[entry].path.clone();
+                    self.open(path);
+                }
+                Some(_) => println!("That is a file, not a folder."),
+                None => {}
+            },
+            "g" => {
+                let path = self.folder.join(expand_home(arg));
+                if path.is_dir() {
+                    self.open(path);
+                } else {
+                    println!("No folder {}.", path.display());
+                }
+            }
+            "v" => self.view(),
+            "n" => self.name(arg),
+            "s" => return self.submit(),
+            "c" => {
+                return match DaemonClient::new().cancel(Some(&self.session.id)) {
+                    Ok(()) => {
+                        println!("Cancelled.");
+                        Outcome::Done(ExitStatus::Cancelled.into())
+                    }
+                    Err(e) => {
+                        println!("Could not cancel: {e}");
+                        Outcome::Done(e.exit_status().into())
+                    }
+                };
+            }
+            _ => self.numbers(line),
+        }
+        Outcome::Continue
+    }
+
+    fn open(&mut self, folder: PathBuf) {
+        self.folder = folder;
+        self.list();
+    }
+
+    /// Number and print the folder's entries: folders first, hidden ones
+    /// and files outside the current filter left out
+    fn list(&mut self) {
+        let mut entries: Vec<Entry> = match fs::read_dir(&self.folder) {
+            Ok(read) => read
+                .flatten()
+                .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+                .map(|entry| Entry {
+                    dir: entry.path().is_dir(),
+                    path: entry.path(),
+                })
+                .filter(|entry| {
+                    entry.dir
+                        || self.filter.as_ref().is_none_or(|filter| {
+                            entry
+                                .path
+                                .file_name()
+                                .is_some_and(|name| filter.is_match(name))
+                        })
+                })
+                .collect(),
+            Err(e) => {
+                println!("Cannot read {}: {e}", self.folder.display());
+                Vec::new()
+            }
+        };
+        entries.sort_by(|a, b| b.dir.cmp(&a.dir).then_with(|| a.path.cmp(&b.path)));
+        self.entries = entries;
+
+        let folders = self.entries.iter().filter(|entry| entry.dir).count();
+        println!(
+            "Folder {}: {}, {}.",
+            self.folder.display(),
+            count(folders, "folder"),
+            count(self.entries.len() - folders, "file")
+        );
+        let selection = self.selection();
+        for (i, entry) in self.entries.iter().enumerate() {
+            println!(
+                "{}. {}{}{}",
+                i + 1,
+                name(&entry.path),
+                if entry.dir { ", folder" } else { "" },
+                if selection.contains(&entry.path) {
+                    ", selected"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    /// Toggle the entries a line of numbers and ranges names. A single
+    /// folder is opened instead when the dialog picks files.
+    fn numbers(&mut self, line: &str) {
+        let mut picked = Vec::new();
+        for word in line.split([' ', ',']).filter(|word| !word.is_empty()) {
+            let range = match word.split_once('-') {
+                Some((from, to)) => from.parse().ok().zip(to.parse().ok()),
+                None => word.parse().ok().map(|n| (n, n)),
+            };
+            let Some((from, to)) = range.filter(|&(from, to): &(usize, usize)| {
+                from >= 1 && from <= to && to <= self.entries.len()
+            }) else {
+                println!("Not a command or entry number: {word}. Type h for help.");
+                return;
+            };
+            picked.extend(from - 1..to);
+        }
+
+        let picks_dirs = matches!(
+            self.options.mode,
+            SelectionMode::Pick {
+                directory: true,
+                ..
+            }
+        );
+        if let [only] = picked[..]
+            && self.entries[only].dir
+            && !picks_dirs
+        {
+            let path = self.entries[only].path.clone();
+            self.open(path);
+            return;
+        }
+        for i in picked {
+            let entry = &self.entries[i];
+            if entry.dir != picks_dirs {
+                println!(
+                    "Skipped {}: this dialog picks {}.",
+                    name(&entry.path),
+                    if picks_dirs { "folders" } else { "files" }
+                );
+                continue;
+            }
+            let path = entry.path.clone();
+            self.toggle(&path);
+        }
+    }
+
+    fn toggle(&self, path: &Path) {
+        let entry = uri::encode_os(path.as_os_str()).into_owned();
+        if self.selection().iter().any(|selected| selected == path) {
+            match files::remove_lines(&self.ctx.submission_path(), &[entry]) {
+                Ok(()) => println!("Deselected {}. {}.", name(path), self.selected()),
+                Err(e) => println!("Could not deselect {}: {e}", name(path)),
+            }
+            return;
+        }
+        match self.ctx.add_entries(&[entry]) {
+            Ok(AddResult::Appended(_)) => {
+                println!("Selected {}. {}.", name(path), self.selected())
+            }
+            Ok(AddResult::Replaced) => println!("Selected {}.", name(path)),
+            Err(e) => println!("Could not select {}: {e}", name(path)),
+        }
+    }
+
+    /// Save as `name` in the current folder
+    fn name(&self, name: &str) {
+        if self.options.mode != SelectionMode::Save {
+            println!("Names are given in save dialogs only.");
+        } else if name.is_empty() || name.contains('/') {
+            println!("Give a file name without folders, like n report.txt.");
+        } else {
+            let path = self.folder.join(name);
+            match self
+                .ctx
+                .add_entries(&[uri::encode_os(path.as_os_str()).into_owned()])
+            {
+                Ok(_) => println!("Saving as {}.", path.display()),
+                Err(e) => println!("Could not set the name: {e}"),
+            }
+        }
+    }
+
+    fn view(&self) {
+        let selection = self.selection();
+        println!("{}.", self.selected());
+        for path in selection {
+            println!("{}", path.display());
+        }
+    }
+
+    fn submit(&self) -> Outcome {
+        let saves = matches!(
+            self.options.mode,
+            SelectionMode::Save | SelectionMode::SaveMultiple
+        );
+        // A save with nothing chosen goes into the current folder, under the
+        // suggested name
+        if saves
+            && self.selection().is_empty()
+            && let Err(e) = self
+                .ctx
+                .add_entries(&[format!("{}/", self.folder.display())])
+        {
+            println!("Could not choose the folder: {e}");
+            return Outcome::Continue;
+        }
+        match DaemonClient::new().submit(Some(&self.session.id)) {
+            Ok(()) => {
+                println!("Submitted.");
+                Outcome::Done(ExitCode::SUCCESS)
+            }
+            Err(e) if e.exit_status() == ExitStatus::Invalid => {
+                println!("Not submitted: {e}");
+                Outcome::Continue
+            }
+            Err(e) => {
+                println!("Not submitted: {e}");
+                Outcome::Done(e.exit_status().into())
+            }
+        }
+    }
+
+    /// The session's selection as paths
+    fn selection(&self) -> Vec<PathBuf> {
+        files::read_lines(&self.ctx.submission_path())
+            .iter()
+            .map(|entry| file_uri_to_path(entry).unwrap_or_else(|| PathBuf::from(entry)))
+            .collect()
+    }
+
+    fn selected(&self) -> String {
+        match self.selection().len() {
+            0 => "Nothing selected".to_string(),
+            n => format!("{} selected", count(n, "entry")),
+        }
+    }
+
+    /// Index of the single entry `arg` numbers
+    fn pick_one(&self, arg: &str) -> Option<usize> {
+        match arg.parse::<usize>() {
+            Ok(n) if (1..=self.entries.len()).contains(&n) => Some(n - 1),
+            _ => {
+                println!("Give an entry number from 1 to {}.", self.entries.len());
+                None
+            }
+        }
+    }
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn count(n: usize, noun: &str) -> String {
+    match (n, noun) {
+        (1, _) => format!("1 {noun}"),
+        (_, "entry") => format!("{n} entries"),
+        _ => format!("{n} {noun}s"),
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
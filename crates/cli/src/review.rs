@@ -0,0 +1,122 @@
+//! `portty review` and `portty submit --review` - what a submit would return
+//!
+//! The daemon resolves the selection as a submit would (current folder,
+//! save names, sorting, `audit`, `rewrite`) without submitting it. The
+//! results are printed against the selection, so a save landing in an
+//! unexpected folder shows up before the app gets it.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use libportty::client::DaemonClient;
+use libportty::exit::ExitStatus;
+use libportty::portal::file_chooser::SessionOptions;
+use libportty::portal::options;
+use libportty::{Portal, SessionInfo, SubmitOptions, files, uri};
+
+/// Print what submitting `session` with `options` would return
+pub fn run(session: &SessionInfo, options: SubmitOptions) -> ExitCode {
+    match print(session, options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(status) => status.into(),
+    }
+}
+
+/// Print the review, then submit once the user confirms it
+pub fn submit(session: &SessionInfo, options: SubmitOptions) -> ExitCode {
+    if !io::stdin().is_terminal() {
+        eprintln!("Error: submit --review needs a terminal to confirm on");
+        return ExitStatus::Usage.into();
+    }
+    if let Err(status) = print(session, options.clone()) {
+        return status.into();
+    }
+
+    print!("\nSubmit this? [y/N] ");
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err()
+        || !matches!(answer.trim(), "y" | "Y" | "yes")
+    {
+        println!("Not submitted");
+        return ExitStatus::Cancelled.into();
+    }
+
+    match DaemonClient::new().submit_with(Some(&session.id), &options) {
+        Ok(()) => {
+            println!("Submitted");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_status().into()
+        }
+    }
+}
+
+fn print(session: &SessionInfo, options: SubmitOptions) -> Result<(), ExitStatus> {
+    let uris = DaemonClient::new()
+        .review(Some(&session.id), options)
+        .map_err(|e| {
+            eprintln!("Error: {e}");
+            e.exit_status()
+        })?;
+    let selected: Vec<String> = files::read_lines(&Path::new(&session.dir).join("submission"))
+        .iter()
+        .map(|entry| as_uri(entry))
+        .collect();
+    let among = |entries: &[String], entry: &str| entries.iter().any(|e| uri::same_entry(e, entry));
+
+    let app = session.app_id.as_deref().filter(|app| !app.is_empty());
+    println!(
+        "Returned to {} ({} {}), {} result(s):",
+        app.unwrap_or("the app"),
+        session.portal,
+        session.operation,
+        uris.len()
+    );
+    let mut changed = false;
+    for uri in &uris {
+        let mark = if among(&selected, uri) { ' ' } else { '+' };
+        changed |= mark == '+';
+        println!("  {mark} {uri}");
+    }
+    for entry in selected.iter().filter(|entry| !among(&uris, entry)) {
+        changed = true;
+        println!("  - {entry}");
+    }
+    if changed {
+        println!(
+            "  (+ pending, resolved, or rewritten by portty; - selected but not returned as is)"
+        );
+    }
+
+    if session.portal == Portal::FileChooser.as_str() {
+        let choices = options::read(Path::new(&session.dir))
+            .ok()
+            .and_then(|opts| serde_json::from_value::<SessionOptions>(opts).ok())
+            .map(|opts| opts.choice_values())
+            .unwrap_or_default();
+        if choices.is_empty() {
+            println!("Choices: none");
+        } else {
+            let choices: Vec<_> = choices
+                .iter()
+                .map(|(id, value)| format!("{id}={value}"))
+                .collect();
+            println!("Choices: {}", choices.join(", "));
+        }
+        println!("Writable: not set");
+    }
+    Ok(())
+}
+
+/// A selection entry as it would appear among the results
+fn as_uri(entry: &str) -> String {
+    if entry.starts_with('/') {
+        uri::from_path(Path::new(entry))
+    } else {
+        uri::normalize(entry).into_owned()
+    }
+}
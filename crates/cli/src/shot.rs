@@ -0,0 +1,121 @@
+//! `portty shot` - capture screenshots into a screenshot session
+//!
+//! Runs a capture command (`grim` unless one is given after `--`) into the
+//! session's payload directory and selects the result. `--series N` takes N
+//! captures `--interval` seconds apart, when the session accepts a series
+//! (an interactive request with `series_dir` configured): the first is
+//! returned to the app, porttyd moves the rest into `series_dir`.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libportty::exit::ExitStatus;
+use libportty::portal::screenshot::accepts_series;
+use libportty::portal::{Intent, MergeOp, SessionContext, parse_item};
+use libportty::{Operation, files, paths, uri};
+
+use crate::storage;
+
+/// Capture command used when none is given
+const DEFAULT_CAPTURE: &str = "grim";
+
+/// Take `count` captures `interval` apart with `command` and select them in
+/// `session_id`
+pub fn run(session_id: &str, count: u32, interval: Duration, command: &[String]) -> ExitCode {
+    match capture(session_id, count, interval, command) {
+        Ok(shots) => {
+            for shot in &shots {
+                println!("{}", shot.display());
+            }
+            ExitCode::SUCCESS
+        }
+        Err((e, status)) => {
+            eprintln!("Error: {e}");
+            status.into()
+        }
+    }
+}
+
+fn capture(
+    session_id: &str,
+    count: u32,
+    interval: Duration,
+    command: &[String],
+) -> Result<Vec<PathBuf>, (String, ExitStatus)> {
+    let failure = |e: String| (e, ExitStatus::Failure);
+    let ctx = SessionContext::from_session_dir(paths::base_dir().join(session_id))
+        .map_err(|e| failure(format!("cannot read session {session_id}: {e}")))?;
+    if ctx.operation != Operation::Screenshot {
+        return Err((
+            format!("shot needs a screenshot session, not {}", ctx.operation),
+            ExitStatus::Usage,
+        ));
+    }
+    let options = ctx
+        .read_options()
+        .map_err(|e| failure(format!("failed to read options: {e}")))?;
+    if count > 1 && !accepts_series(&options) {
+        return Err((
+            "this request takes a single screenshot; a series needs an interactive request \
+             and `series_dir` in the config"
+                .to_string(),
+            ExitStatus::Invalid,
+        ));
+    }
+
+    let dir = storage::create_payload_dir(session_id, 0).map_err(failure)?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let default = [DEFAULT_CAPTURE.to_string()];
+    let argv = if command.is_empty() {
+        &default[..]
+    } else {
+        command
+    };
+
+    let mut shots = Vec::new();
+    for n in 1..=count {
+        if n > 1 {
+            std::thread::sleep(interval);
+        }
+        let name = if count == 1 {
+            format!("shot-{stamp}.png")
+        } else {
+            format!("shot-{stamp}-{n}.png")
+        };
+        let shot = dir.join(name);
+        run_capture(argv, &shot).map_err(failure)?;
+        shots.push(shot);
+    }
+
+    let items = shots
+        .iter()
+        .map(|shot| parse_item("path", &uri::encode_os(shot.as_os_str())))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(failure)?;
+    let mut intent = Intent::default();
+    intent.apply(&items, MergeOp::Set).map_err(failure)?;
+    ctx.set_intent(&intent)
+        .map_err(|e| (e.to_string(), ExitStatus::Invalid))?;
+    Ok(shots)
+}
+
+/// Run `argv` with `{}` in its arguments replaced by `shot`, or `shot`
+/// appended without a placeholder
+fn run_capture(argv: &[String], shot: &Path) -> Result<(), String> {
+    let args = files::path_args(&argv[1..], shot);
+    let status = Command::new(&argv[0])
+        .args(&args)
+        .status()
+        .map_err(|e| format!("failed to run `{}`: {e}", argv[0]))?;
+    if !status.success() {
+        return Err(format!("`{}` failed ({status})", argv[0]));
+    }
+    if !shot.exists() {
+        return Err(format!("`{}` did not write {}", argv[0], shot.display()));
+    }
+    Ok(())
+}
@@ -0,0 +1,27 @@
+//! `portty simulate` - what a picker script would get for a request
+//!
+//! Runs `porttyd --simulate`, which builds the request from the arguments
+//! and resolves the session command, environment, shims, and constraints
+//! with the daemon's own code, without D-Bus and without running anything.
+//! A running porttyd is not involved.
+
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitCode};
+
+use libportty::exit::ExitStatus;
+
+use crate::daemon::find_daemon_binary;
+
+/// Replace this process with `porttyd --simulate PORTAL OPERATION ARGS...`
+pub fn run(portal: &str, operation: &str, args: &[String]) -> ExitCode {
+    let Some(porttyd) = find_daemon_binary() else {
+        eprintln!("Error: porttyd not found (set PORTTY_DAEMON to its path)");
+        return ExitStatus::Failure.into();
+    };
+    let e = Command::new(&porttyd)
+        .args(["--simulate", portal, operation])
+        .args(args)
+        .exec();
+    eprintln!("Error: failed to run {}: {e}", porttyd.display());
+    ExitStatus::Failure.into()
+}
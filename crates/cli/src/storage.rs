@@ -0,0 +1,172 @@
+//! `portty gc` and `portty payload-dir` - disk usage of session state
+//!
+//! Session directories are removed by the daemon when a session ends, but a
+//! crashed or killed daemon leaves them behind. Payload directories
+//! deliberately outlive their session (the app reads the returned URI after
+//! the dialog closes), so both are cleaned up here. Remote `host:path`
+//! entries are staged into them too.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+use libportty::client::DaemonClient;
+use libportty::exit::ExitStatus;
+use libportty::quota::{self, Quota};
+use libportty::remote::{Remote, split_remote};
+use libportty::{paths, uri};
+
+/// Remove leftover session dirs and stale payload dirs
+pub fn gc(dry_run: bool, max_age: Duration) -> ExitCode {
+    let active: HashSet<String> = match DaemonClient::new().list() {
+        Ok(sessions) => sessions.into_iter().map(|s| s.id).collect(),
+        Err(e) => {
+            eprintln!("Daemon not reachable ({e}); treating all sessions as completed");
+            HashSet::new()
+        }
+    };
+
+    let mut freed = 0;
+    let mut removed = 0;
+    let mut failed = false;
+
+    let base = paths::base_dir();
+    let stale_sessions = subdirs(&base)
+        .filter(|dir| dir.join("portal").is_file())
+        .filter(|dir| !is_active(dir, &active));
+
+    let cutoff = SystemTime::now().checked_sub(max_age);
+    let stale_payloads = [quota::payloads_dir(), quota::cache_payloads_dir()]
+        .into_iter()
+        .flat_map(|root| subdirs(&root).collect::<Vec<_>>())
+        .filter(|dir| !is_active(dir, &active))
+        .filter(|dir| {
+            let modified = fs::metadata(dir).and_then(|m| m.modified()).ok();
+            matches!((modified, cutoff), (Some(m), Some(c)) if m <= c)
+        });
+
+    for dir in stale_sessions.chain(stale_payloads) {
+        let size = quota::dir_usage(&dir);
+        if dry_run {
+            println!("Would remove {} ({size} bytes)", dir.display());
+        } else if let Err(e) = fs::remove_dir_all(&dir) {
+            eprintln!("Failed to remove {}: {e}", dir.display());
+            failed = true;
+            continue;
+        } else {
+            println!("Removed {} ({size} bytes)", dir.display());
+        }
+        freed += size;
+        removed += 1;
+    }
+
+    if removed == 0 {
+        println!("Nothing to clean up");
+    } else if dry_run {
+        println!("Would remove {removed} directories, freeing {freed} bytes");
+    } else {
+        println!("Removed {removed} directories, freed {freed} bytes");
+    }
+
+    if failed {
+        ExitStatus::Failure.into()
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Print (and create) the directory a payload of `size` bytes should go to
+pub fn payload_dir(session_id: &str, size: u64) -> ExitCode {
+    match create_payload_dir(session_id, size) {
+        Ok(dir) => {
+            println!("{}", dir.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitStatus::Failure.into()
+        }
+    }
+}
+
+/// Create the directory a payload of `size` bytes should go to, if the
+/// quota allows it
+pub fn create_payload_dir(session_id: &str, size: u64) -> Result<PathBuf, String> {
+    let quota = Quota::from_env();
+    quota
+        .check(Some(session_id), size)
+        .map_err(|e| e.to_string())?;
+    let dir = quota.payload_dir(session_id, size);
+    create_private_dir(&dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Replace `host:path` entries of the path and directory families with local
+/// copies in the session's payload directory, when the daemon enabled remote
+/// paths for the session. Each copy gets a directory of its own, so equal
+/// names from different hosts don't collide. `preserve` keeps the remote
+/// times, modes, and extended attributes (see [`Remote::fetch`]).
+pub fn stage_remote(
+    session_id: &str,
+    family: &str,
+    values: Vec<String>,
+    preserve: bool,
+) -> Result<Vec<String>, String> {
+    let Some(remote) = Remote::from_env() else {
+        return Ok(values);
+    };
+    if !matches!(family, "path" | "directory") {
+        return Ok(values);
+    }
+
+    let quota = Quota::from_env();
+    values
+        .into_iter()
+        .map(|value| {
+            let Some((host, path)) = split_remote(&value) else {
+                return Ok(value);
+            };
+            quota
+                .check(Some(session_id), 0)
+                .map_err(|e| e.to_string())?;
+            let root = quota.payload_dir(session_id, 0);
+            let dir = (0..)
+                .map(|n| root.join(format!("remote-{n}")))
+                .find(|dir| !dir.exists())
+                .expect("unbounded range");
+            create_private_dir(&dir)
+                .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+            let local = remote
+                .fetch(host, path, &dir, preserve)
+                .map_err(|e| e.to_string())?;
+            eprintln!("Fetched {value} -> {}", local.display());
+            Ok(uri::encode_os(local.as_os_str()).into_owned())
+        })
+        .collect()
+}
+
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    paths::ensure_base_dir()?;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+}
+
+fn is_active(dir: &Path, active: &HashSet<String>) -> bool {
+    dir.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| active.contains(name))
+}
+
+fn subdirs(root: &Path) -> impl Iterator<Item = PathBuf> {
+    fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+}
@@ -0,0 +1,136 @@
+//! `portty tutorial` - practice dialogs for new users
+//!
+//! Runs made-up file chooser requests on this terminal with `porttyd --inline
+//! --oneshot`, in a folder of practice files, and checks the answers. The
+//! daemon gets a base directory and an empty config of its own, so a running
+//! porttyd and the user's config are not involved.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, ExitCode, Stdio};
+
+use libportty::exit::ExitStatus;
+use libportty::uri;
+
+use crate::daemon::find_daemon_binary;
+
+/// Files of the practice folder
+const PRACTICE_FILES: &[&str] = &["notes.txt", "todo.txt", "photo.png", "Documents/report.pdf"];
+
+struct Exercise {
+    /// What to do, shown before the dialog opens
+    task: &'static str,
+    /// Commands that do it
+    steps: &'static [&'static str],
+    /// `--oneshot` arguments after the portal
+    request: &'static [&'static str],
+    /// Paths the app should get, relative to the practice folder
+    expect: &'static [&'static str],
+}
+
+const EXERCISES: &[Exercise] = &[
+    Exercise {
+        task: "An editor asks you to open files. Pick notes.txt and todo.txt.",
+        steps: &["?", "sel notes.txt todo.txt", "submit"],
+        request: &["open-file", "--multiple", "--title", "Open Files"],
+        expect: &["notes.txt", "todo.txt"],
+    },
+    Exercise {
+        task: "Now it saves letter.txt. Put it in the Documents folder.",
+        steps: &["portty set path Documents/", "submit"],
+        request: &["save-file", "--name", "letter.txt", "--title", "Save File"],
+        expect: &["Documents/letter.txt"],
+    },
+];
+
+pub fn run() -> ExitCode {
+    let Some(porttyd) = find_daemon_binary() else {
+        eprintln!("Error: porttyd not found (set PORTTY_DAEMON to its path)");
+        return ExitStatus::Failure.into();
+    };
+    let root = std::env::temp_dir().join(format!("portty-tutorial-{}", std::process::id()));
+    let result = practice(&porttyd, &root);
+    let _ = fs::remove_dir_all(&root);
+    match result {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitStatus::Failure.into()
+        }
+    }
+}
+
+fn practice(porttyd: &Path, root: &Path) -> Result<ExitCode, String> {
+    let folder = root.join("practice");
+    for file in PRACTICE_FILES {
+        let path = folder.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        fs::write(&path, "").map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+
+    println!(
+        "Apps ask for files (or screenshots, colors, ...) through portals. porttyd\n\
+         answers with a shell instead of a dialog: you pick with commands, then\n\
+         submit. Here the shell opens on this terminal, in a practice folder."
+    );
+    for (i, exercise) in EXERCISES.iter().enumerate() {
+        println!("\n{}/{}: {}", i + 1, EXERCISES.len(), exercise.task);
+        for step in exercise.steps {
+            println!("    {step}");
+        }
+
+        let output = Command::new(porttyd)
+            .args(["--inline", "--oneshot", "file-chooser"])
+            .args(exercise.request)
+            .arg("--folder")
+            .arg(&folder)
+            .env("PORTTY_BASE_DIR", root.join("run"))
+            // Defaults only: a configured exec or shim must not answer for the user
+            .env("PORTTY_CONFIG", "/dev/null")
+            .current_dir(&folder)
+            .stdin(Stdio::inherit())
+            .output()
+            .map_err(|e| format!("failed to run {}: {e}", porttyd.display()))?;
+
+        match output.status.code() {
+            Some(0) => {}
+            Some(code) if code == i32::from(ExitStatus::Cancelled.code()) => {
+                println!("Cancelled; portty tutorial starts over.");
+                return Ok(ExitStatus::Cancelled.into());
+            }
+            _ => {
+                return Err(format!(
+                    "porttyd failed:\n{}",
+                    String::from_utf8_lossy(&output.stderr).trim_end()
+                ));
+            }
+        }
+
+        let mut got: Vec<&str> = std::str::from_utf8(&output.stdout)
+            .unwrap_or_default()
+            .lines()
+            .collect();
+        got.sort_unstable();
+        let expect: Vec<String> = exercise
+            .expect
+            .iter()
+            .map(|path| uri::from_path(&folder.join(path)))
+            .collect();
+        if got == expect {
+            println!("Right, the app got:");
+        } else {
+            println!("Not quite; the app wanted {expect:?} but got:");
+        }
+        for uri in &got {
+            println!("    {uri}");
+        }
+    }
+
+    println!(
+        "\nEvery dialog works this way. In a session, ? describes the request and\n\
+         its commands; portty help has the rest."
+    );
+    Ok(ExitCode::SUCCESS)
+}
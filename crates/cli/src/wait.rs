@@ -0,0 +1,99 @@
+//! `portty wait` - block until a dialog opens
+//!
+//! Polls the daemon's session list until a session matching the filters is
+//! active, then prints its id (or, with `--env`, the variables its terminal
+//! gets, for `eval`), so a script can fill the selection of the dialog it
+//! waited for instead of queuing a submission ahead of time.
+
+use std::collections::HashSet;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use libportty::client::DaemonClient;
+use libportty::exit::ExitStatus;
+use libportty::{Portal, SessionInfo};
+
+/// How often the session list is checked
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Which sessions `portty wait` returns
+pub struct Filter {
+    pub portal: Option<Portal>,
+    pub operation: Option<String>,
+    pub app: Option<String>,
+    /// Skip sessions that were active when waiting started
+    pub new: bool,
+}
+
+impl Filter {
+    fn matches(&self, session: &SessionInfo) -> bool {
+        self.portal
+            .is_none_or(|portal| session.portal == portal.as_str())
+            && self
+                .operation
+                .as_ref()
+                .is_none_or(|operation| &session.operation == operation)
+            && self
+                .app
+                .as_ref()
+                .is_none_or(|app| session.app_id.as_ref() == Some(app))
+    }
+}
+
+/// Wait up to `timeout` (forever without one) for a session matching
+/// `filter` and print it
+pub fn run(filter: &Filter, timeout: Option<Duration>, env: bool) -> ExitCode {
+    let client = DaemonClient::new();
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut seen: Option<HashSet<String>> = None;
+    loop {
+        let sessions = match client.list() {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                return e.exit_status().into();
+            }
+        };
+        let seen = seen.get_or_insert_with(|| {
+            if filter.new {
+                sessions.iter().map(|s| s.id.clone()).collect()
+            } else {
+                HashSet::new()
+            }
+        });
+        // Oldest first, so the dialog that has waited longest is served
+        if let Some(session) = sessions
+            .iter()
+            .filter(|s| !seen.contains(&s.id) && filter.matches(s))
+            .min_by_key(|s| s.created)
+        {
+            print_session(session, env);
+            return ExitCode::SUCCESS;
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            eprintln!("No matching session opened in time");
+            return ExitStatus::NotFound.into();
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn print_session(session: &SessionInfo, env: bool) {
+    if !env {
+        println!("{}", session.id);
+        return;
+    }
+    for (name, value) in [
+        ("PORTTY_SESSION", session.id.as_str()),
+        ("PORTTY_DIR", session.dir.as_str()),
+        ("PORTTY_PORTAL", session.portal.as_str()),
+        ("PORTTY_OPERATION", session.operation.as_str()),
+        (
+            "PORTTY_APP_ID",
+            session.app_id.as_deref().unwrap_or_default(),
+        ),
+    ] {
+        println!("export {name}='{}'", value.replace('\'', r"'\''"));
+    }
+}
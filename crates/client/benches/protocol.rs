@@ -0,0 +1,167 @@
+//! Control protocol benchmarks: the selection stream, both ends, and request
+//! decoding.
+//!
+//! `cargo bench -p portty-client` first prints the allocations each path
+//! makes for a selection, counted by the global allocator, then runs the
+//! criterion timings.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::io::{BufRead, BufReader, Cursor};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group};
+use portty_client::codec::{
+    read_selection, read_selection_borrowed, write_selection, write_selection_lines,
+};
+use portty_client::protocol::Request;
+
+/// Entries per selection
+const ENTRIES: usize = 10_000;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+// SAFETY: forwards to the system allocator, only counting calls
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: same contract as the caller's
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: same contract as the caller's
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: same contract as the caller's
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// A submission file of `ENTRIES` file URIs
+fn submission() -> String {
+    (0..ENTRIES)
+        .map(|i| format!("file:///home/user/Pictures/2024/IMG_{i:05}.jpg\n"))
+        .collect()
+}
+
+/// The daemon's answer to `selection` for `submission`
+fn stream(submission: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_selection_lines(&mut out, &mut Cursor::new(submission)).unwrap();
+    out
+}
+
+/// The stream as daemons before `write_selection_lines` sent it: an owned
+/// line per entry
+fn write_owned(submission: &str, out: &mut Vec<u8>) {
+    let lines = BufReader::new(Cursor::new(submission))
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()));
+    write_selection(out, lines).unwrap();
+}
+
+fn write_borrowed(submission: &str, out: &mut Vec<u8>) {
+    write_selection_lines(out, &mut Cursor::new(submission)).unwrap();
+}
+
+fn read_owned(stream: &[u8]) -> usize {
+    let mut bytes = 0;
+    read_selection(&mut Cursor::new(stream), |entry| bytes += entry.len()).unwrap();
+    bytes
+}
+
+fn read_borrowed(stream: &[u8]) -> usize {
+    let mut bytes = 0;
+    read_selection_borrowed(&mut Cursor::new(stream), |entry| bytes += entry.len()).unwrap();
+    bytes
+}
+
+fn allocations(run: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    run();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn report_allocations() {
+    let submission = submission();
+    let stream = stream(&submission);
+    let mut out = Vec::with_capacity(stream.len());
+    println!("Allocations for a selection of {ENTRIES} entries:");
+    for (name, count) in [
+        (
+            "write (owned lines)",
+            allocations(|| write_owned(&submission, &mut out)),
+        ),
+        ("write (borrowed)", {
+            out.clear();
+            allocations(|| write_borrowed(&submission, &mut out))
+        }),
+        (
+            "read (owned entries)",
+            allocations(|| {
+                black_box(read_owned(&stream));
+            }),
+        ),
+        (
+            "read (borrowed)",
+            allocations(|| {
+                black_box(read_borrowed(&stream));
+            }),
+        ),
+    ] {
+        println!("  {name:<22} {count:>6}");
+    }
+    println!();
+}
+
+fn selection(c: &mut Criterion) {
+    let submission = submission();
+    let stream = stream(&submission);
+    let mut group = c.benchmark_group("selection");
+    group.throughput(Throughput::Elements(ENTRIES as u64));
+
+    let mut out = Vec::with_capacity(stream.len());
+    group.bench_function(BenchmarkId::new("write", "owned"), |b| {
+        b.iter(|| {
+            out.clear();
+            write_owned(black_box(&submission), &mut out);
+        })
+    });
+    group.bench_function(BenchmarkId::new("write", "borrowed"), |b| {
+        b.iter(|| {
+            out.clear();
+            write_borrowed(black_box(&submission), &mut out);
+        })
+    });
+    group.bench_function(BenchmarkId::new("read", "owned"), |b| {
+        b.iter(|| read_owned(black_box(&stream)))
+    });
+    group.bench_function(BenchmarkId::new("read", "borrowed"), |b| {
+        b.iter(|| read_borrowed(black_box(&stream)))
+    });
+    group.finish();
+}
+
+fn requests(c: &mut Criterion) {
+    let submit = "submit --allow-empty --queue-policy=merge --sort=mtime 18df3dd52700473e-2e508f657b2447d1\n";
+    c.bench_function("request/decode", |b| {
+        b.iter(|| Request::decode(black_box(submit)).unwrap())
+    });
+}
+
+criterion_group!(benches, selection, requests);
+
+fn main() {
+    report_allocations();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
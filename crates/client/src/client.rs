@@ -0,0 +1,446 @@
+use std::io::BufReader;
+#[cfg(feature = "jsonrpc")]
+use std::io::{BufRead, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use crate::codec::{self, IpcError};
+use crate::exit::ExitStatus;
+use crate::operation::Operation;
+use crate::protocol::{
+    DaemonStatus, Decision, ErrorCode, PermissionInfo, Request, RequestInfo, Response, ServerError,
+    SessionInfo, SubmitOptions,
+};
+use crate::socket::{self, SocketAddress};
+
+/// Errors from the daemon client
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ClientError {
+    #[error("connection failed: {0}")]
+    Connection(std::io::Error),
+    /// The daemon socket's directory belongs to another user: a porttyd
+    /// started as root (or with sudo) for a user CLI, or the other way round.
+    /// Each user has a daemon of their own.
+    #[error("{}", foreign_daemon_message(.dir, *.owner, *.uid))]
+    ForeignDaemon {
+        /// Directory of the daemon socket
+        dir: PathBuf,
+        /// Uid owning it
+        owner: u32,
+        /// Uid this process runs as
+        uid: u32,
+    },
+    #[error("IPC error: {0}")]
+    Codec(#[from] IpcError),
+    #[error("{0}")]
+    Server(ServerError),
+    #[error("unexpected response from daemon")]
+    UnexpectedResponse,
+}
+
+impl ClientError {
+    /// The exit status a command failing with this error reports. Daemon
+    /// errors map by their [`ErrorCode`].
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            Self::Connection(_) | Self::ForeignDaemon { .. } => ExitStatus::Unreachable,
+            Self::Server(err) => match err.code {
+                ErrorCode::NoSession => ExitStatus::NotFound,
+                ErrorCode::MultipleSessions | ErrorCode::QueueConflict => ExitStatus::Ambiguous,
+                ErrorCode::InvalidSubmission => ExitStatus::Invalid,
+                _ => ExitStatus::Failure,
+            },
+            _ => ExitStatus::Failure,
+        }
+    }
+}
+
+/// Client for communicating with the daemon control socket
+pub struct DaemonClient {
+    addresses: Vec<SocketAddress>,
+}
+
+impl DaemonClient {
+    /// Create a client for the default daemon addresses (see
+    /// [`socket::daemon_addresses`])
+    pub fn new() -> Self {
+        Self {
+            addresses: socket::daemon_addresses(),
+        }
+    }
+
+    /// Submit a session or pending entries
+    pub fn submit(&self, session_id: Option<&str>) -> Result<(), ClientError> {
+        self.submit_with(session_id, &SubmitOptions::default())
+    }
+
+    /// Submit with explicit submit flags
+    pub fn submit_with(
+        &self,
+        session_id: Option<&str>,
+        options: &SubmitOptions,
+    ) -> Result<(), ClientError> {
+        let req = Request::Submit {
+            session_id: session_id.map(String::from),
+            options: options.clone(),
+        };
+        match self.send(&req)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Cancel a session or clear pending entries
+    pub fn cancel(&self, session_id: Option<&str>) -> Result<(), ClientError> {
+        let req = Request::Cancel {
+            session_id: session_id.map(String::from),
+        };
+        match self.send(&req)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Validate submission against portal constraints
+    pub fn verify(&self, session_id: Option<&str>) -> Result<(), ClientError> {
+        let req = Request::Verify {
+            session_id: session_id.map(String::from),
+        };
+        match self.send(&req)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// The URIs a submit with `options` would return to the app: the
+    /// selection validated, sorted, and rewritten, without submitting it
+    pub fn review(
+        &self,
+        session_id: Option<&str>,
+        options: SubmitOptions,
+    ) -> Result<Vec<String>, ClientError> {
+        let req = Request::Review {
+            session_id: session_id.map(String::from),
+            options,
+        };
+        match self.send(&req)? {
+            Response::Selection(uris) => Ok(uris),
+            Response::Ok => Ok(Vec::new()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Reset submission to initial state
+    pub fn reset(&self, session_id: Option<&str>) -> Result<(), ClientError> {
+        self.reset_keeping(session_id, &[])
+    }
+
+    /// Reset submission to initial state, leaving the entries matching a
+    /// `keep` glob as they are
+    pub fn reset_keeping(
+        &self,
+        session_id: Option<&str>,
+        keep: &[String],
+    ) -> Result<(), ClientError> {
+        let req = Request::Reset {
+            session_id: session_id.map(String::from),
+            keep: keep.to_vec(),
+        };
+        match self.send(&req)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Switch the shared terminal to a session's window (`[tabs]` mode)
+    pub fn focus(&self, session_id: Option<&str>) -> Result<(), ClientError> {
+        let req = Request::Focus {
+            session_id: session_id.map(String::from),
+        };
+        match self.send(&req)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// List all active sessions
+    pub fn list(&self) -> Result<Vec<SessionInfo>, ClientError> {
+        match self.send(&Request::List)? {
+            Response::Sessions(sessions) => Ok(sessions),
+            // Empty session list encodes as "ok\n", which decodes to Response::Ok
+            Response::Ok => Ok(Vec::new()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// List the portal requests the daemon is handling, including those that
+    /// have no session (yet)
+    pub fn requests(&self) -> Result<Vec<RequestInfo>, ClientError> {
+        match self.send(&Request::Requests)? {
+            Response::Requests(requests) => Ok(requests),
+            // No requests encode as "ok\n", like an empty session list
+            Response::Ok => Ok(Vec::new()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Query daemon status (liveness check)
+    pub fn status(&self) -> Result<DaemonStatus, ClientError> {
+        match self.send(&Request::Status)? {
+            Response::Status(status) => Ok(status),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Stream a session's selection (the earliest session's without an id),
+    /// handing each entry to `on_entry` as it arrives
+    pub fn selection(
+        &self,
+        session_id: Option<&str>,
+        mut on_entry: impl FnMut(String),
+    ) -> Result<(), ClientError> {
+        self.selection_borrowed(session_id, |entry| on_entry(entry.to_string()))
+    }
+
+    /// [`selection`](Self::selection) lending each entry, without an
+    /// allocation per entry (see [`codec::read_selection_borrowed`])
+    pub fn selection_borrowed(
+        &self,
+        session_id: Option<&str>,
+        on_entry: impl FnMut(&str),
+    ) -> Result<(), ClientError> {
+        let req = Request::Selection {
+            session_id: session_id.map(String::from),
+        };
+        let stream = self.connect_stream()?;
+        let mut writer = &stream;
+        let mut reader = BufReader::new(&stream);
+        codec::write_request(&mut writer, &req)?;
+        match codec::read_selection_borrowed(&mut reader, on_entry)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Ask the daemon to reload its configuration
+    pub fn reload(&self) -> Result<(), ClientError> {
+        match self.send(&Request::Reload)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Relink the shims of a session, or of all sessions without an id, to
+    /// what the daemon's current config resolves. Returns the sessions
+    /// refreshed.
+    pub fn refresh_shims(&self, session_id: Option<&str>) -> Result<Vec<SessionInfo>, ClientError> {
+        let req = Request::RefreshShims {
+            session_id: session_id.map(String::from),
+        };
+        match self.send(&req)? {
+            Response::Sessions(sessions) => Ok(sessions),
+            // None refreshed encodes as "ok\n", like an empty session list
+            Response::Ok => Ok(Vec::new()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Ask the daemon to shut down
+    pub fn shutdown(&self) -> Result<(), ClientError> {
+        match self.send(&Request::Shutdown)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// List the decisions remembered for all apps, or for `app_id`
+    pub fn permissions(&self, app_id: Option<&str>) -> Result<Vec<PermissionInfo>, ClientError> {
+        let req = Request::Permissions {
+            app_id: app_id.map(String::from),
+        };
+        match self.send(&req)? {
+            Response::Permissions(permissions) => Ok(permissions),
+            // No decisions encode as "ok\n", like an empty session list
+            Response::Ok => Ok(Vec::new()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Remember `decision` for `app_id`'s requests of `operation`
+    pub fn set_permission(
+        &self,
+        app_id: &str,
+        operation: Operation,
+        decision: Decision,
+    ) -> Result<(), ClientError> {
+        let req = Request::SetPermission {
+            app_id: app_id.to_string(),
+            operation,
+            decision,
+        };
+        match self.send(&req)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Forget `app_id`'s decision for `operation`, or all of its decisions
+    pub fn clear_permission(
+        &self,
+        app_id: &str,
+        operation: Option<Operation>,
+    ) -> Result<(), ClientError> {
+        let req = Request::ClearPermission {
+            app_id: app_id.to_string(),
+            operation,
+        };
+        match self.send(&req)? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Send a raw request and return the raw response
+    pub fn send(&self, req: &Request) -> Result<Response, ClientError> {
+        let stream = self.connect_stream()?;
+        let mut writer = &stream;
+        let mut reader = BufReader::new(&stream);
+        codec::write_request(&mut writer, req)?;
+        let resp = codec::read_response(&mut reader)?;
+        Ok(resp)
+    }
+
+    /// Open a connection that carries several requests (as JSON-RPC), for
+    /// scripted sequences of commands
+    #[cfg(feature = "jsonrpc")]
+    pub fn connect(&self) -> Result<Connection, ClientError> {
+        let stream = self.connect_stream()?;
+        let reader = BufReader::new(stream.try_clone().map_err(ClientError::Connection)?);
+        Ok(Connection {
+            stream,
+            reader,
+            next_id: 0,
+        })
+    }
+
+    /// Connect at the first address a daemon answers on. A daemon of
+    /// another user is reported when no address answers.
+    fn connect_stream(&self) -> Result<UnixStream, ClientError> {
+        let mut refusal = None;
+        for address in &self.addresses {
+            if let SocketAddress::Path(path) = address
+                && let Err(e) = check_owner(path)
+            {
+                refusal.get_or_insert(e);
+                continue;
+            }
+            match address.connect() {
+                Ok(stream) => return Ok(stream),
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::ConnectionRefused
+                        || e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    refusal.get_or_insert(ClientError::Connection(e));
+                }
+            }
+        }
+        Err(refusal.unwrap_or_else(|| {
+            let tried: Vec<_> = self.addresses.iter().map(ToString::to_string).collect();
+            ClientError::Connection(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "cannot connect to daemon socket ({}): is porttyd running?",
+                    tried.join(", ")
+                ),
+            ))
+        }))
+    }
+}
+
+/// Refuse a socket in a directory of another user. Its daemon would run
+/// sessions and write files as that user, and does not see this user's
+/// pending entries or sessions. A missing directory is left to `connect` to
+/// report.
+fn check_owner(socket_path: &Path) -> Result<(), ClientError> {
+    use std::os::unix::fs::MetadataExt;
+
+    let Some(dir) = socket_path.parent() else {
+        return Ok(());
+    };
+    let Ok(meta) = std::fs::metadata(dir) else {
+        return Ok(());
+    };
+    let uid = crate::paths::current_uid();
+    if meta.uid() == uid {
+        return Ok(());
+    }
+    Err(ClientError::ForeignDaemon {
+        dir: dir.to_path_buf(),
+        owner: meta.uid(),
+        uid,
+    })
+}
+
+fn foreign_daemon_message(dir: &Path, owner: u32, uid: u32) -> String {
+    let hint = if uid == 0 {
+        format!("portty runs as root (sudo?); run it as uid {owner} instead")
+    } else if owner == 0 {
+        "that porttyd runs as root; start one as this user with `portty daemon start`".to_string()
+    } else {
+        "each user needs a porttyd of their own; check PORTTY_BASE_DIR".to_string()
+    };
+    format!(
+        "daemon socket directory {} belongs to uid {owner}, not to this user (uid {uid}): {hint}",
+        dir.display()
+    )
+}
+
+/// An open daemon connection, see [`DaemonClient::connect`]
+#[cfg(feature = "jsonrpc")]
+pub struct Connection {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+    next_id: u64,
+}
+
+#[cfg(feature = "jsonrpc")]
+impl Connection {
+    /// Send a request and wait for its response
+    pub fn send(&mut self, req: &Request) -> Result<Response, ClientError> {
+        self.next_id += 1;
+        let call = crate::jsonrpc::encode_call(self.next_id.into(), req);
+        writeln!(self.stream, "{call}").map_err(IpcError::Io)?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).map_err(IpcError::Io)? == 0 {
+            return Err(IpcError::Closed.into());
+        }
+        let reply: serde_json::Value =
+            serde_json::from_str(&line).map_err(|e| IpcError::Protocol(e.to_string()))?;
+        crate::jsonrpc::decode_response(req, &reply)
+            .map_err(|e| IpcError::Protocol(e.to_string()).into())
+    }
+}
+
+impl Default for DaemonClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
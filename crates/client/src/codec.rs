@@ -0,0 +1,528 @@
+use std::io::{BufRead, Write};
+use thiserror::Error;
+
+use crate::protocol::{
+    DaemonStatus, PermissionInfo, Request, RequestInfo, Response, ServerError, SessionInfo,
+};
+
+/// Write a request to a writer
+pub fn write_request(writer: &mut impl Write, req: &Request) -> Result<(), IpcError> {
+    writer.write_all(req.encode().as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Default upper bound for a single protocol line, including the newline
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Read a request from a buffered reader
+pub fn read_request(reader: &mut impl BufRead) -> Result<Request, IpcError> {
+    read_request_limited(reader, DEFAULT_MAX_MESSAGE_SIZE)
+}
+
+/// Read a request, rejecting lines longer than `max` bytes
+pub fn read_request_limited(reader: &mut impl BufRead, max: usize) -> Result<Request, IpcError> {
+    let line = read_line_limited(reader, max)?.ok_or(IpcError::Closed)?;
+    Request::decode(&line).map_err(IpcError::Protocol)
+}
+
+/// Read one `\n`-terminated line of at most `max` bytes.
+///
+/// The line is consumed incrementally, so an oversized line fails once it
+/// crosses the limit instead of being buffered whole. Returns `Ok(None)` on a
+/// clean EOF before any byte of the line. A final line without `\n` is
+/// returned as-is.
+pub fn read_line_limited(
+    reader: &mut impl BufRead,
+    max: usize,
+) -> Result<Option<String>, IpcError> {
+    let mut buf = Vec::new();
+    if read_line_into(reader, &mut buf, max)?.is_none() {
+        return Ok(None);
+    }
+    String::from_utf8(buf)
+        .map(Some)
+        .map_err(|_| IpcError::InvalidUtf8)
+}
+
+/// [`read_line_limited`] into `buf`, borrowing the line from it.
+///
+/// `buf` is cleared first and keeps its capacity, so reading many lines
+/// through one buffer allocates only while it grows.
+pub fn read_line_into<'b>(
+    reader: &mut impl BufRead,
+    buf: &'b mut Vec<u8>,
+    max: usize,
+) -> Result<Option<&'b str>, IpcError> {
+    buf.clear();
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if available.is_empty() {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            break;
+        }
+
+        let (chunk, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (&available[..=i], true),
+            None => (available, false),
+        };
+        if buf.len() + chunk.len() > max {
+            return Err(IpcError::TooLarge { limit: max });
+        }
+        buf.extend_from_slice(chunk);
+        let used = chunk.len();
+        reader.consume(used);
+        if done {
+            break;
+        }
+    }
+
+    std::str::from_utf8(buf)
+        .map(Some)
+        .map_err(|_| IpcError::InvalidUtf8)
+}
+
+/// Write a response to a writer
+pub fn write_response(writer: &mut impl Write, resp: &Response) -> Result<(), IpcError> {
+    writer.write_all(resp.encode().as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write a selection response, one `entry` line at a time as `entries`
+/// yields them.
+///
+/// An entry that fails to read ends the stream with `error: ...`; the
+/// entries already written stay sent.
+pub fn write_selection(
+    writer: &mut impl Write,
+    entries: impl IntoIterator<Item = std::io::Result<String>>,
+) -> Result<(), IpcError> {
+    for entry in entries {
+        match entry {
+            Ok(entry) => write_entry(writer, &entry)?,
+            Err(e) => return write_response(writer, &Response::Error(e.to_string().into())),
+        }
+    }
+    write_response(writer, &Response::Ok)
+}
+
+/// Write a selection response with an entry per non-empty line of `source`
+/// (e.g. a submission file), reusing one line buffer throughout.
+///
+/// Like [`write_selection`], a line that fails to read (or is not UTF-8)
+/// ends the stream with `error: ...`.
+pub fn write_selection_lines(
+    writer: &mut impl Write,
+    source: &mut impl BufRead,
+) -> Result<(), IpcError> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if let Err(e) = source.read_until(b'\n', &mut line) {
+            return write_response(writer, &Response::Error(e.to_string().into()));
+        }
+        if line.is_empty() {
+            return write_response(writer, &Response::Ok);
+        }
+        let Ok(entry) = std::str::from_utf8(&line) else {
+            let e = std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            );
+            return write_response(writer, &Response::Error(e.to_string().into()));
+        };
+        let entry = entry.strip_suffix('\n').unwrap_or(entry);
+        let entry = entry.strip_suffix('\r').unwrap_or(entry);
+        if !entry.is_empty() {
+            write_entry(writer, entry)?;
+        }
+    }
+}
+
+/// Write one `entry\t...` line, as
+/// [`encode_entry`](crate::protocol::encode_entry) encodes it but without
+/// building the line first
+fn write_entry(writer: &mut impl Write, entry: &str) -> std::io::Result<()> {
+    writer.write_all(b"entry\t")?;
+    let mut fields = entry.split(['\t', '\n', '\r']);
+    if let Some(first) = fields.next() {
+        writer.write_all(first.as_bytes())?;
+    }
+    for field in fields {
+        writer.write_all(b" ")?;
+        writer.write_all(field.as_bytes())?;
+    }
+    writer.write_all(b"\n")
+}
+
+/// Read a selection response, handing each entry to `on_entry` as it
+/// arrives instead of collecting them.
+///
+/// Returns the terminator: [`Response::Ok`] or [`Response::Error`].
+pub fn read_selection(
+    reader: &mut impl BufRead,
+    mut on_entry: impl FnMut(String),
+) -> Result<Response, IpcError> {
+    read_selection_borrowed(reader, |entry| on_entry(entry.to_string()))
+}
+
+/// [`read_selection`] lending each entry instead of handing it over: the
+/// entries are borrowed from one line buffer, so a selection of any size
+/// is read without an allocation per entry.
+pub fn read_selection_borrowed(
+    reader: &mut impl BufRead,
+    mut on_entry: impl FnMut(&str),
+) -> Result<Response, IpcError> {
+    let mut buf = Vec::new();
+    loop {
+        let line =
+            read_line_into(reader, &mut buf, DEFAULT_MAX_MESSAGE_SIZE)?.ok_or(IpcError::Closed)?;
+        let trimmed = line.trim_end_matches('\n').trim_end_matches('\r');
+
+        if trimmed == "ok" {
+            return Ok(Response::Ok);
+        }
+        if let Some(msg) = trimmed.strip_prefix("error: ") {
+            return Ok(Response::Error(ServerError::decode_fields(msg)));
+        }
+        match trimmed.strip_prefix("entry\t") {
+            Some(entry) => on_entry(entry),
+            None => return Err(IpcError::Protocol(format!("expected an entry: {trimmed}"))),
+        }
+    }
+}
+
+/// Read a response from a buffered reader
+pub fn read_response(reader: &mut impl BufRead) -> Result<Response, IpcError> {
+    let mut sessions = Vec::new();
+    let mut permissions = Vec::new();
+    let mut requests = Vec::new();
+    let mut entries = Vec::new();
+    let mut status = None;
+
+    loop {
+        let line = read_line_limited(reader, DEFAULT_MAX_MESSAGE_SIZE)?.ok_or(IpcError::Closed)?;
+
+        let trimmed = line.trim_end_matches('\n').trim_end_matches('\r');
+
+        if trimmed == "ok" {
+            return if let Some(status) = status {
+                Ok(Response::Status(status))
+            } else if !permissions.is_empty() {
+                Ok(Response::Permissions(permissions))
+            } else if !requests.is_empty() {
+                Ok(Response::Requests(requests))
+            } else if !entries.is_empty() {
+                Ok(Response::Selection(entries))
+            } else if sessions.is_empty() {
+                Ok(Response::Ok)
+            } else {
+                Ok(Response::Sessions(sessions))
+            };
+        }
+
+        if let Some(msg) = trimmed.strip_prefix("error: ") {
+            return Ok(Response::Error(ServerError::decode_fields(msg)));
+        }
+
+        if let Some(fields) = trimmed.strip_prefix("status\t") {
+            status = Some(DaemonStatus::decode_fields(fields).map_err(IpcError::Protocol)?);
+            continue;
+        }
+
+        if let Some(fields) = trimmed.strip_prefix("permission\t") {
+            permissions.push(PermissionInfo::decode_fields(fields).map_err(IpcError::Protocol)?);
+            continue;
+        }
+
+        if let Some(fields) = trimmed.strip_prefix("request\t") {
+            requests.push(RequestInfo::decode_fields(fields).map_err(IpcError::Protocol)?);
+            continue;
+        }
+
+        if let Some(entry) = trimmed.strip_prefix("entry\t") {
+            entries.push(entry.to_string());
+            continue;
+        }
+
+        // Must be a session info line
+        match SessionInfo::decode_line(trimmed) {
+            Ok(info) => sessions.push(info),
+            Err(e) => return Err(IpcError::Protocol(e)),
+        }
+    }
+}
+
+/// IPC communication errors
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum IpcError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("connection closed")]
+    Closed,
+    #[error("message exceeds {limit} bytes")]
+    TooLarge { limit: usize },
+    #[error("message is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("protocol error: {0}")]
+    Protocol(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{QueuePolicy, SessionMode, SubmitOptions};
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn request_roundtrip() {
+        let cases = vec![
+            Request::Submit {
+                session_id: None,
+                options: SubmitOptions::default(),
+            },
+            Request::Submit {
+                session_id: Some("abc".into()),
+                options: SubmitOptions {
+                    allow_empty: true,
+                    confirm: true,
+                    queue_policy: Some(QueuePolicy::InteractiveFirst),
+                    portal: None,
+                    sort: None,
+                    prefer: None,
+                },
+            },
+            Request::List,
+        ];
+
+        for req in cases {
+            let mut buf = Vec::new();
+            write_request(&mut buf, &req).unwrap();
+
+            let mut reader = BufReader::new(Cursor::new(buf));
+            let decoded = read_request(&mut reader).unwrap();
+            assert_eq!(decoded, req);
+        }
+    }
+
+    #[test]
+    fn response_ok_roundtrip() {
+        let mut buf = Vec::new();
+        write_response(&mut buf, &Response::Ok).unwrap();
+
+        let mut reader = BufReader::new(Cursor::new(buf));
+        let decoded = read_response(&mut reader).unwrap();
+        assert_eq!(decoded, Response::Ok);
+    }
+
+    #[test]
+    fn response_error_roundtrip() {
+        for resp in [
+            Response::Error("something went wrong".into()),
+            Response::Error(ServerError::new(
+                crate::protocol::ErrorCode::QueueConflict,
+                "Queue conflict: the queue adds 2 entries",
+            )),
+        ] {
+            let mut buf = Vec::new();
+            write_response(&mut buf, &resp).unwrap();
+
+            let mut reader = BufReader::new(Cursor::new(buf));
+            let decoded = read_response(&mut reader).unwrap();
+            assert_eq!(decoded, resp);
+        }
+    }
+
+    #[test]
+    fn response_sessions_roundtrip() {
+        let resp = Response::Sessions(vec![
+            SessionInfo {
+                id: "s1".into(),
+                portal: "file-chooser".into(),
+                operation: "open-file".into(),
+                title: Some("Pick".into()),
+                created: 12345,
+                dir: "/tmp/a".into(),
+                app_id: Some("org.example.App".into()),
+                mode: Some(SessionMode::Terminal),
+            },
+            SessionInfo {
+                id: "s2".into(),
+                portal: "screenshot".into(),
+                operation: "screenshot".into(),
+                title: None,
+                created: 67890,
+                dir: "/tmp/b".into(),
+                app_id: None,
+                mode: None,
+            },
+        ]);
+
+        let mut buf = Vec::new();
+        write_response(&mut buf, &resp).unwrap();
+
+        let mut reader = BufReader::new(Cursor::new(buf));
+        let decoded = read_response(&mut reader).unwrap();
+        assert_eq!(decoded, resp);
+    }
+
+    #[test]
+    fn response_status_roundtrip() {
+        let resp = Response::Status(DaemonStatus {
+            pid: 1234,
+            version: "0.3.3".into(),
+            sessions: 0,
+            id_collisions: 3,
+            protocol: Some(2),
+            build: None,
+            portals: vec!["file-chooser".into()],
+        });
+        let mut buf = Vec::new();
+        write_response(&mut buf, &resp).unwrap();
+
+        let mut reader = BufReader::new(Cursor::new(buf));
+        let decoded = read_response(&mut reader).unwrap();
+        assert_eq!(decoded, resp);
+    }
+
+    #[test]
+    fn selection_streams_entries() {
+        let entries = ["file:///tmp/a", "file:///tmp/b"].map(|e| Ok(e.to_string()));
+        let mut buf = Vec::new();
+        write_selection(&mut buf, entries).unwrap();
+
+        let mut seen = Vec::new();
+        let mut reader = BufReader::new(Cursor::new(buf.clone()));
+        let end = read_selection(&mut reader, |entry| seen.push(entry)).unwrap();
+        assert_eq!(end, Response::Ok);
+        assert_eq!(seen, ["file:///tmp/a", "file:///tmp/b"]);
+
+        // The collected form decodes from the same stream
+        let mut reader = BufReader::new(Cursor::new(buf));
+        assert_eq!(
+            read_response(&mut reader).unwrap(),
+            Response::Selection(seen)
+        );
+    }
+
+    #[test]
+    fn selection_lines_match_selection() {
+        let source = "file:///tmp/a\n\nfile:///tmp/tab\there\r\nfile:///tmp/last";
+        let mut from_lines = Vec::new();
+        write_selection_lines(&mut from_lines, &mut Cursor::new(source)).unwrap();
+
+        let entries = ["file:///tmp/a", "file:///tmp/tab\there", "file:///tmp/last"]
+            .map(|e| Ok(e.to_string()));
+        let mut from_entries = Vec::new();
+        write_selection(&mut from_entries, entries).unwrap();
+        assert_eq!(
+            String::from_utf8(from_lines.clone()).unwrap(),
+            String::from_utf8(from_entries).unwrap()
+        );
+
+        let mut seen = Vec::new();
+        let mut reader = BufReader::new(Cursor::new(from_lines));
+        let end = read_selection_borrowed(&mut reader, |entry| seen.push(entry.to_string()));
+        assert_eq!(end.unwrap(), Response::Ok);
+        assert_eq!(
+            seen,
+            ["file:///tmp/a", "file:///tmp/tab here", "file:///tmp/last"]
+        );
+
+        let mut invalid = Vec::new();
+        write_selection_lines(&mut invalid, &mut Cursor::new(b"ok\n\xff\n")).unwrap();
+        assert!(invalid.ends_with(b"error: stream did not contain valid UTF-8\n"));
+    }
+
+    #[test]
+    fn line_buffer_is_reused() {
+        let mut reader = BufReader::new(Cursor::new("first line\nsecond\n"));
+        let mut buf = Vec::new();
+        let first = read_line_into(&mut reader, &mut buf, 64).unwrap();
+        assert_eq!(first, Some("first line\n"));
+        let capacity = buf.capacity();
+        let second = read_line_into(&mut reader, &mut buf, 64).unwrap();
+        assert_eq!(second, Some("second\n"));
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(read_line_into(&mut reader, &mut buf, 64).unwrap(), None);
+    }
+
+    #[test]
+    fn selection_read_error_ends_stream() {
+        let entries = [
+            Ok("file:///tmp/a".to_string()),
+            Err(std::io::Error::other("disk on fire")),
+            Ok("file:///tmp/b".to_string()),
+        ];
+        let mut buf = Vec::new();
+        write_selection(&mut buf, entries).unwrap();
+
+        let mut seen = Vec::new();
+        let mut reader = BufReader::new(Cursor::new(buf));
+        let end = read_selection(&mut reader, |entry| seen.push(entry)).unwrap();
+        assert_eq!(end, Response::Error("disk on fire".into()));
+        assert_eq!(seen, ["file:///tmp/a"]);
+    }
+
+    #[test]
+    fn eof_returns_error() {
+        let mut reader = BufReader::new(Cursor::new(Vec::<u8>::new()));
+        assert!(read_request(&mut reader).is_err());
+        assert!(read_response(&mut reader).is_err());
+    }
+
+    #[test]
+    fn sequential_messages() {
+        let req1 = Request::Submit {
+            session_id: None,
+            options: SubmitOptions::default(),
+        };
+        let req2 = Request::List;
+
+        let mut buf = Vec::new();
+        write_request(&mut buf, &req1).unwrap();
+        write_request(&mut buf, &req2).unwrap();
+
+        let mut reader = BufReader::new(Cursor::new(buf));
+        assert_eq!(read_request(&mut reader).unwrap(), req1);
+        assert_eq!(read_request(&mut reader).unwrap(), req2);
+    }
+
+    #[test]
+    fn oversized_line_is_rejected() {
+        let mut buf = vec![b'x'; 100];
+        buf.push(b'\n');
+        // Small BufReader capacity forces several fill_buf rounds
+        let mut reader = BufReader::with_capacity(8, Cursor::new(buf));
+        assert!(matches!(
+            read_request_limited(&mut reader, 32),
+            Err(IpcError::TooLarge { limit: 32 })
+        ));
+    }
+
+    #[test]
+    fn line_at_limit_is_accepted() {
+        let mut reader = BufReader::with_capacity(4, Cursor::new(b"list\n".to_vec()));
+        assert_eq!(read_request_limited(&mut reader, 5).unwrap(), Request::List);
+    }
+
+    #[test]
+    fn garbage_is_structured_error() {
+        let mut reader = BufReader::new(Cursor::new(b"\xff\xfe\n".to_vec()));
+        assert!(matches!(
+            read_request(&mut reader),
+            Err(IpcError::InvalidUtf8)
+        ));
+
+        let mut reader = BufReader::new(Cursor::new(Vec::<u8>::new()));
+        assert!(matches!(read_request(&mut reader), Err(IpcError::Closed)));
+    }
+}
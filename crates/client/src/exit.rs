@@ -0,0 +1,147 @@
+//! Exit statuses of the portty CLI and `porttyd --oneshot`
+//!
+//! A contract for scripts: a status keeps its number across releases, and
+//! new ones get new numbers. Tools driving the daemon through
+//! [`DaemonClient`](crate::DaemonClient) can report the same statuses with
+//! [`ClientError::exit_status`](crate::ClientError::exit_status).
+//!
+//! ```text
+//! 0   success
+//! 1   failure without a more specific status
+//! 2   no session (none active, or unknown id); portty get: field unset
+//! 3   several sessions active, none chosen with --session; or the queue and
+//!     the session differ (submit --prefer chooses)
+//! 4   validation failed (submission, config, or command input)
+//! 5   daemon unreachable
+//! 6   cancelled
+//! 64  invalid command-line arguments
+//! ```
+
+use std::fmt;
+use std::process::ExitCode;
+
+/// How a portty command ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[repr(u8)]
+pub enum ExitStatus {
+    /// The command did what was asked
+    Success = 0,
+    /// A failure without a more specific status
+    Failure = 1,
+    /// No session to act on: none is active, or the given id is unknown.
+    /// `portty get` also uses it for a field the request does not set.
+    NotFound = 2,
+    /// Several sessions are active and none was chosen with `--session`, or
+    /// a submit has to choose between the pending queue and the session
+    Ambiguous = 3,
+    /// A submission failed validation or an `audit` rule, the config is
+    /// invalid, or an entry given on the command line is malformed
+    Invalid = 4,
+    /// porttyd is not running, its socket cannot be reached, or it belongs
+    /// to another user
+    Unreachable = 5,
+    /// The dialog was cancelled (`porttyd --oneshot`)
+    Cancelled = 6,
+    /// The command line could not be parsed (`EX_USAGE`)
+    Usage = 64,
+}
+
+impl ExitStatus {
+    /// Every status, in the order they are documented
+    pub const ALL: [Self; 8] = [
+        Self::Success,
+        Self::Failure,
+        Self::NotFound,
+        Self::Ambiguous,
+        Self::Invalid,
+        Self::Unreachable,
+        Self::Cancelled,
+        Self::Usage,
+    ];
+
+    /// The process exit code
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// The status a process exit code stands for
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| status.code() == code)
+    }
+
+    /// One-line description, as in the table above
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure without a more specific status",
+            Self::NotFound => "no session (none active, or unknown id); portty get: field unset",
+            Self::Ambiguous => {
+                "several sessions active, none chosen with --session; or queue and session differ"
+            }
+            Self::Invalid => "validation failed (submission, config, or command input)",
+            Self::Unreachable => "daemon unreachable",
+            Self::Cancelled => "cancelled",
+            Self::Usage => "invalid command-line arguments",
+        }
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClientError;
+    use crate::protocol::{ErrorCode, ServerError};
+
+    #[test]
+    fn codes_are_stable() {
+        let codes: Vec<u8> = ExitStatus::ALL.iter().map(|s| s.code()).collect();
+        assert_eq!(codes, [0, 1, 2, 3, 4, 5, 6, 64]);
+        for status in ExitStatus::ALL {
+            assert_eq!(ExitStatus::from_code(status.code()), Some(status));
+        }
+        assert_eq!(ExitStatus::from_code(7), None);
+    }
+
+    #[test]
+    fn client_errors() {
+        let server = |code| ClientError::Server(ServerError::new(code, "msg")).exit_status();
+        assert_eq!(server(ErrorCode::NoSession), ExitStatus::NotFound);
+        assert_eq!(server(ErrorCode::MultipleSessions), ExitStatus::Ambiguous);
+        assert_eq!(server(ErrorCode::QueueConflict), ExitStatus::Ambiguous);
+        assert_eq!(server(ErrorCode::InvalidSubmission), ExitStatus::Invalid);
+        assert_eq!(server(ErrorCode::Failed), ExitStatus::Failure);
+        // The message no longer decides
+        let worded = ClientError::Server("No active session, or so it seems".into());
+        assert_eq!(worded.exit_status(), ExitStatus::Failure);
+
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert_eq!(
+            ClientError::Connection(refused).exit_status(),
+            ExitStatus::Unreachable
+        );
+        let foreign = ClientError::ForeignDaemon {
+            dir: "/tmp/portty/0".into(),
+            owner: 0,
+            uid: 1000,
+        };
+        assert_eq!(foreign.exit_status(), ExitStatus::Unreachable);
+        assert!(foreign.to_string().contains("belongs to uid 0"));
+        assert_eq!(
+            ClientError::UnexpectedResponse.exit_status(),
+            ExitStatus::Failure
+        );
+    }
+}
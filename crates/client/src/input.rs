@@ -0,0 +1,119 @@
+//! Checks for strings that arrive from clients
+//!
+//! Session ids and portal names come in over the control socket (text and
+//! JSON-RPC) and the FIFO; entries come from session files written by shims
+//! and editors. They are checked before use so that a NUL byte or an absurdly
+//! long value fails with a clear error instead of surfacing later as a
+//! truncated D-Bus string or path.
+//!
+//! Text is also normalized to NFC: the same name typed on one keyboard and
+//! pasted from a decomposed (NFD) file listing should select the same file.
+
+use std::borrow::Cow;
+
+use unicode_normalization::{IsNormalized, UnicodeNormalization, is_nfc_quick};
+
+use crate::uri;
+
+/// Default longest accepted entry, session id, or portal name (`PATH_MAX`)
+pub const DEFAULT_MAX_ENTRY_LENGTH: usize = 4096;
+
+/// A client string that was refused
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum InputError {
+    #[error("{0} contains a NUL byte")]
+    Nul(String),
+    #[error("{what} is {len} bytes long (limit {limit})")]
+    TooLong {
+        what: String,
+        len: usize,
+        limit: usize,
+    },
+}
+
+/// Check one string: no NUL bytes, at most `limit` bytes. `what` names it
+/// in the error.
+pub fn check(what: &str, value: &str, limit: usize) -> Result<(), InputError> {
+    if value.contains('\0') {
+        return Err(InputError::Nul(what.to_string()));
+    }
+    if value.len() > limit {
+        return Err(InputError::TooLong {
+            what: what.to_string(),
+            len: value.len(),
+            limit,
+        });
+    }
+    Ok(())
+}
+
+/// Check submission entries, naming them by position (`entry 1`, ...).
+pub fn check_entries(entries: &[String], limit: usize) -> Result<(), InputError> {
+    entries
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, entry)| check(&format!("entry {}", i + 1), entry, limit))
+}
+
+/// `value` in NFC
+pub fn nfc(value: &str) -> Cow<'_, str> {
+    match is_nfc_quick(value.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(value),
+        _ => Cow::Owned(value.nfc().collect()),
+    }
+}
+
+/// A path or `file://` URI in NFC, unless that would lose an existing file:
+/// file systems compare names byte for byte, so a file actually named in
+/// another form keeps its spelling.
+pub fn normalize_path(value: &str) -> Cow<'_, str> {
+    let normalized = nfc(value);
+    if let Cow::Owned(ref composed) = normalized {
+        let on_disk = |s: &str| uri::to_path(s).unwrap_or_else(|| s.into()).exists();
+        if on_disk(value) && !on_disk(composed) {
+            return Cow::Borrowed(value);
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_nul_and_long_values() {
+        assert_eq!(check("session_id", "abc", 8), Ok(()));
+        assert_eq!(
+            check("session_id", "a\0b", 8),
+            Err(InputError::Nul("session_id".into()))
+        );
+        assert_eq!(
+            check_entries(&["/ok".into(), "/too/long".into()], 8)
+                .unwrap_err()
+                .to_string(),
+            "entry 2 is 9 bytes long (limit 8)"
+        );
+    }
+
+    #[test]
+    fn normalizes_to_nfc_but_keeps_existing_names() {
+        let decomposed = "cafe\u{301}.txt";
+        assert_eq!(nfc(decomposed), "caf\u{e9}.txt");
+        assert!(matches!(nfc("plain.txt"), Cow::Borrowed(_)));
+
+        let tmp = tempfile::tempdir().unwrap();
+        let missing = tmp.path().join(decomposed);
+        assert_eq!(
+            normalize_path(missing.to_str().unwrap()),
+            tmp.path().join("caf\u{e9}.txt").to_str().unwrap()
+        );
+
+        std::fs::write(&missing, "").unwrap();
+        assert_eq!(
+            normalize_path(missing.to_str().unwrap()),
+            missing.to_str().unwrap()
+        );
+    }
+}
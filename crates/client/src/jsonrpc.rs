@@ -0,0 +1,592 @@
+//! JSON-RPC 2.0 mapping of the daemon control protocol
+//!
+//! The daemon socket also accepts newline-delimited JSON-RPC 2.0. A connection
+//! whose first byte is `{` or `[` is treated as JSON-RPC for its whole
+//! lifetime; anything else is the plain text protocol. Methods and params
+//! mirror the text commands:
+//!
+//! ```text
+//! --> {"jsonrpc":"2.0","id":1,"method":"submit","params":{"session_id":"abc","allow_empty":true}}
+//! <-- {"jsonrpc":"2.0","id":1,"result":null}
+//! --> {"jsonrpc":"2.0","id":2,"method":"list"}
+//! <-- {"jsonrpc":"2.0","id":2,"result":[{"id":"abc","portal":"file-chooser",...}]}
+//! ```
+//!
+//! Commands that return `ok` in the text protocol return a `null` result.
+//! `selection` is not streamed here: its result is one array of entries.
+//! Daemon-side failures are reported as errors with code [`SERVER_ERROR`],
+//! their [`ErrorCode`] as `data.code` when it is not a plain failure.
+
+use serde_json::{Value, json};
+
+use crate::operation::Operation;
+use crate::protocol::{
+    DaemonStatus, ErrorCode, PermissionInfo, Request, RequestInfo, Response, ServerError,
+    SessionInfo, SubmitOptions,
+};
+
+/// Invalid JSON was received
+pub const PARSE_ERROR: i64 = -32700;
+/// The JSON sent is not a valid request object
+pub const INVALID_REQUEST: i64 = -32600;
+/// The method does not exist
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// Invalid method parameters
+pub const INVALID_PARAMS: i64 = -32602;
+/// The daemon reported an error (`error: ...` in the text protocol)
+pub const SERVER_ERROR: i64 = -32000;
+
+/// A JSON-RPC error object
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        json!({ "code": self.code, "message": self.message })
+    }
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+/// A decoded JSON-RPC call. `id` is `None` for notifications.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub id: Option<Value>,
+    pub request: Request,
+}
+
+/// Method name of a request
+pub fn method_name(req: &Request) -> &'static str {
+    match req {
+        Request::Submit { .. } => "submit",
+        Request::Review { .. } => "review",
+        Request::Cancel { .. } => "cancel",
+        Request::Verify { .. } => "verify",
+        Request::Reset { .. } => "reset",
+        Request::Focus { .. } => "focus",
+        Request::List => "list",
+        Request::Requests => "requests",
+        Request::Status => "status",
+        Request::Selection { .. } => "selection",
+        Request::Reload => "reload",
+        Request::RefreshShims { .. } => "refresh-shims",
+        Request::Shutdown => "shutdown",
+        Request::Permissions { .. } => "permissions",
+        Request::SetPermission { .. } => "set-permission",
+        Request::ClearPermission { .. } => "clear-permission",
+    }
+}
+
+/// Decode a single request object.
+///
+/// On failure, returns the error together with the request id (if one could
+/// be recovered) so the caller can still address the error response.
+pub fn decode_call(value: &Value) -> Result<Call, (Option<Value>, RpcError)> {
+    let Some(obj) = value.as_object() else {
+        return Err((
+            None,
+            RpcError::new(INVALID_REQUEST, "request must be an object"),
+        ));
+    };
+    let id = obj.get("id").cloned();
+
+    if obj.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err((
+            id,
+            RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""),
+        ));
+    }
+    let Some(method) = obj.get("method").and_then(Value::as_str) else {
+        return Err((id, RpcError::new(INVALID_REQUEST, "missing method")));
+    };
+
+    let params = obj.get("params").cloned().unwrap_or(Value::Null);
+    let params = match params {
+        Value::Null => serde_json::Map::new(),
+        Value::Object(map) => map,
+        _ => {
+            return Err((
+                id,
+                RpcError::new(INVALID_PARAMS, "params must be an object"),
+            ));
+        }
+    };
+
+    let session_id = match params.get("session_id") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(_) => {
+            return Err((
+                id,
+                RpcError::new(INVALID_PARAMS, "session_id must be a string"),
+            ));
+        }
+    };
+
+    let request = match method {
+        "submit" | "review" => {
+            let flag = |name: &str| match params.get(name) {
+                None | Some(Value::Null) => Ok(false),
+                Some(Value::Bool(b)) => Ok(*b),
+                Some(_) => Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("{name} must be a boolean"),
+                )),
+            };
+            let (allow_empty, confirm) = match (flag("allow_empty"), flag("confirm")) {
+                (Ok(allow_empty), Ok(confirm)) => (allow_empty, confirm),
+                (Err(e), _) | (_, Err(e)) => return Err((id, e)),
+            };
+            let queue_policy = match params.get("queue_policy") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(s)) => match s.parse() {
+                    Ok(policy) => Some(policy),
+                    Err(e) => return Err((id, RpcError::new(INVALID_PARAMS, e))),
+                },
+                Some(_) => {
+                    return Err((
+                        id,
+                        RpcError::new(INVALID_PARAMS, "queue_policy must be a string"),
+                    ));
+                }
+            };
+            let sort = match params.get("sort") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(s)) => match s.parse() {
+                    Ok(sort) => Some(sort),
+                    Err(e) => return Err((id, RpcError::new(INVALID_PARAMS, e))),
+                },
+                Some(_) => {
+                    return Err((id, RpcError::new(INVALID_PARAMS, "sort must be a string")));
+                }
+            };
+            let prefer = match params.get("prefer") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(s)) => match s.parse() {
+                    Ok(prefer) => Some(prefer),
+                    Err(e) => return Err((id, RpcError::new(INVALID_PARAMS, e))),
+                },
+                Some(_) => {
+                    return Err((id, RpcError::new(INVALID_PARAMS, "prefer must be a string")));
+                }
+            };
+            let portal = match params.get("portal") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(_) => {
+                    return Err((id, RpcError::new(INVALID_PARAMS, "portal must be a string")));
+                }
+            };
+            let options = SubmitOptions {
+                allow_empty,
+                confirm,
+                queue_policy,
+                portal,
+                sort,
+                prefer,
+            };
+            if method == "submit" {
+                Request::Submit {
+                    session_id,
+                    options,
+                }
+            } else {
+                Request::Review {
+                    session_id,
+                    options,
+                }
+            }
+        }
+        "cancel" => Request::Cancel { session_id },
+        "verify" => Request::Verify { session_id },
+        "reset" => {
+            let keep = match params.get("keep") {
+                None | Some(Value::Null) => Vec::new(),
+                Some(Value::Array(globs)) => {
+                    match globs
+                        .iter()
+                        .map(|glob| glob.as_str().map(String::from))
+                        .collect::<Option<Vec<_>>>()
+                    {
+                        Some(keep) => keep,
+                        None => {
+                            return Err((
+                                id,
+                                RpcError::new(INVALID_PARAMS, "keep must be an array of strings"),
+                            ));
+                        }
+                    }
+                }
+                Some(_) => {
+                    return Err((
+                        id,
+                        RpcError::new(INVALID_PARAMS, "keep must be an array of strings"),
+                    ));
+                }
+            };
+            Request::Reset { session_id, keep }
+        }
+        "focus" => Request::Focus { session_id },
+        "list" => Request::List,
+        "requests" => Request::Requests,
+        "status" => Request::Status,
+        "selection" => Request::Selection { session_id },
+        "reload" => Request::Reload,
+        "refresh-shims" => Request::RefreshShims { session_id },
+        "shutdown" => Request::Shutdown,
+        "permissions" | "set-permission" | "clear-permission" => {
+            match decode_permission(method, &params) {
+                Ok(request) => request,
+                Err(e) => return Err((id, e)),
+            }
+        }
+        other => {
+            return Err((
+                id,
+                RpcError::new(METHOD_NOT_FOUND, format!("unknown method: {other}")),
+            ));
+        }
+    };
+
+    Ok(Call { id, request })
+}
+
+/// Decode the params of a permission method: `app_id`, and `portal`,
+/// `operation`, and `decision` where the method takes them
+fn decode_permission(
+    method: &str,
+    params: &serde_json::Map<String, Value>,
+) -> Result<Request, RpcError> {
+    let string = |name: &str| match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.as_str())),
+        Some(_) => Err(RpcError::new(
+            INVALID_PARAMS,
+            format!("{name} must be a string"),
+        )),
+    };
+    let required = |name: &str| {
+        string(name)?.ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("missing {name}")))
+    };
+    let operation = || -> Result<Option<Operation>, RpcError> {
+        match (string("portal")?, string("operation")?) {
+            (None, None) => Ok(None),
+            (Some(portal), Some(operation)) => Operation::of(portal, operation)
+                .map(Some)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e)),
+            _ => Err(RpcError::new(
+                INVALID_PARAMS,
+                "portal and operation go together",
+            )),
+        }
+    };
+
+    Ok(match method {
+        "permissions" => Request::Permissions {
+            app_id: string("app_id")?.map(String::from),
+        },
+        "set-permission" => Request::SetPermission {
+            app_id: required("app_id")?.to_string(),
+            operation: operation()?
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing operation"))?,
+            decision: required("decision")?
+                .parse()
+                .map_err(|e: String| RpcError::new(INVALID_PARAMS, e))?,
+        },
+        _ => Request::ClearPermission {
+            app_id: required("app_id")?.to_string(),
+            operation: operation()?,
+        },
+    })
+}
+
+/// Encode a request as a JSON-RPC call object.
+pub fn encode_call(id: Value, req: &Request) -> Value {
+    let params = match req {
+        Request::Submit {
+            session_id,
+            options,
+        }
+        | Request::Review {
+            session_id,
+            options,
+        } => json!({
+            "session_id": session_id,
+            "allow_empty": options.allow_empty,
+            "confirm": options.confirm,
+            "queue_policy": options.queue_policy,
+            "portal": options.portal,
+            "sort": options.sort,
+            "prefer": options.prefer,
+        }),
+        Request::Reset { session_id, keep } => json!({
+            "session_id": session_id,
+            "keep": keep,
+        }),
+        Request::Cancel { session_id }
+        | Request::Verify { session_id }
+        | Request::Focus { session_id }
+        | Request::RefreshShims { session_id }
+        | Request::Selection { session_id } => json!({ "session_id": session_id }),
+        Request::List
+        | Request::Requests
+        | Request::Status
+        | Request::Reload
+        | Request::Shutdown => json!({}),
+        Request::Permissions { app_id } => json!({ "app_id": app_id }),
+        Request::SetPermission {
+            app_id,
+            operation,
+            decision,
+        } => json!({
+            "app_id": app_id,
+            "portal": operation.portal(),
+            "operation": operation,
+            "decision": decision,
+        }),
+        Request::ClearPermission { app_id, operation } => json!({
+            "app_id": app_id,
+            "portal": operation.map(Operation::portal),
+            "operation": operation,
+        }),
+    };
+    json!({ "jsonrpc": "2.0", "id": id, "method": method_name(req), "params": params })
+}
+
+/// Encode a daemon response as a JSON-RPC response object.
+pub fn encode_response(id: Value, resp: &Response) -> Value {
+    match resp {
+        Response::Error(err) => {
+            let mut error = RpcError::new(SERVER_ERROR, err.message.clone()).to_value();
+            if err.code != ErrorCode::Failed {
+                error["data"] = json!({ "code": err.code.as_str() });
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": error })
+        }
+        Response::Ok => json!({ "jsonrpc": "2.0", "id": id, "result": Value::Null }),
+        Response::Sessions(sessions) => {
+            json!({ "jsonrpc": "2.0", "id": id, "result": sessions })
+        }
+        Response::Status(status) => json!({ "jsonrpc": "2.0", "id": id, "result": status }),
+        Response::Permissions(permissions) => {
+            json!({ "jsonrpc": "2.0", "id": id, "result": permissions })
+        }
+        Response::Requests(requests) => json!({ "jsonrpc": "2.0", "id": id, "result": requests }),
+        Response::Selection(entries) => json!({ "jsonrpc": "2.0", "id": id, "result": entries }),
+    }
+}
+
+/// Encode an error response.
+pub fn encode_error(id: Value, err: &RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": err.to_value() })
+}
+
+/// Decode a JSON-RPC response to `req` back into a daemon [`Response`].
+pub fn decode_response(req: &Request, value: &Value) -> Result<Response, RpcError> {
+    if let Some(err) = value.get("error") {
+        let code = err
+            .get("code")
+            .and_then(Value::as_i64)
+            .unwrap_or(SERVER_ERROR);
+        let message = err
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown error")
+            .to_string();
+        return if code == SERVER_ERROR {
+            let kind = err
+                .pointer("/data/code")
+                .and_then(Value::as_str)
+                .and_then(|kind| kind.parse().ok())
+                .unwrap_or_default();
+            Ok(Response::Error(ServerError::new(kind, message)))
+        } else {
+            Err(RpcError::new(code, message))
+        };
+    }
+
+    let result = value
+        .get("result")
+        .ok_or_else(|| RpcError::new(INVALID_REQUEST, "response has no result"))?;
+    let invalid = |e: serde_json::Error| RpcError::new(INVALID_PARAMS, e.to_string());
+
+    match req {
+        Request::List => {
+            let sessions: Vec<SessionInfo> =
+                serde_json::from_value(result.clone()).map_err(invalid)?;
+            Ok(Response::Sessions(sessions))
+        }
+        Request::Status => {
+            let status: DaemonStatus = serde_json::from_value(result.clone()).map_err(invalid)?;
+            Ok(Response::Status(status))
+        }
+        Request::Permissions { .. } => {
+            let permissions: Vec<PermissionInfo> =
+                serde_json::from_value(result.clone()).map_err(invalid)?;
+            Ok(Response::Permissions(permissions))
+        }
+        Request::Requests => {
+            let requests: Vec<RequestInfo> =
+                serde_json::from_value(result.clone()).map_err(invalid)?;
+            Ok(Response::Requests(requests))
+        }
+        Request::Selection { .. } | Request::Review { .. } => {
+            let entries: Vec<String> = serde_json::from_value(result.clone()).map_err(invalid)?;
+            Ok(Response::Selection(entries))
+        }
+        _ => Ok(Response::Ok),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Decision, QueueConflict, QueuePolicy, SessionMode, SortOrder};
+
+    #[test]
+    fn call_roundtrip() {
+        let cases = vec![
+            Request::Submit {
+                session_id: Some("abc".into()),
+                options: SubmitOptions {
+                    allow_empty: true,
+                    confirm: true,
+                    queue_policy: Some(QueuePolicy::InteractiveFirst),
+                    portal: Some("screenshot".into()),
+                    sort: Some(SortOrder::Name),
+                    prefer: Some(QueueConflict::Session),
+                },
+            },
+            Request::Review {
+                session_id: None,
+                options: SubmitOptions {
+                    sort: Some(SortOrder::Mtime),
+                    ..Default::default()
+                },
+            },
+            Request::Cancel { session_id: None },
+            Request::Reset {
+                session_id: Some("s1".into()),
+                keep: vec!["my notes.txt".into()],
+            },
+            Request::Focus {
+                session_id: Some("s2".into()),
+            },
+            Request::RefreshShims { session_id: None },
+            Request::List,
+            Request::Requests,
+            Request::Status,
+            Request::Selection {
+                session_id: Some("abc".into()),
+            },
+            Request::SetPermission {
+                app_id: "com.obsproject.Studio".into(),
+                operation: Operation::Screenshot,
+                decision: Decision::Allow,
+            },
+            Request::ClearPermission {
+                app_id: "com.obsproject.Studio".into(),
+                operation: None,
+            },
+        ];
+
+        for req in cases {
+            let value = encode_call(json!(7), &req);
+            let call = decode_call(&value).unwrap();
+            assert_eq!(call.id, Some(json!(7)));
+            assert_eq!(call.request, req);
+        }
+    }
+
+    #[test]
+    fn params_are_optional() {
+        let value = json!({ "jsonrpc": "2.0", "id": 1, "method": "submit" });
+        let call = decode_call(&value).unwrap();
+        assert_eq!(
+            call.request,
+            Request::Submit {
+                session_id: None,
+                options: SubmitOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_method_keeps_id() {
+        let value = json!({ "jsonrpc": "2.0", "id": "x", "method": "explode" });
+        let (id, err) = decode_call(&value).unwrap_err();
+        assert_eq!(id, Some(json!("x")));
+        assert_eq!(err.code, METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn server_error_maps_back_to_response_error() {
+        let verify = Request::Verify { session_id: None };
+        let resp = Response::Error("Failed to read options: denied".into());
+        let value = encode_response(json!(3), &resp);
+        assert!(value["error"].get("data").is_none());
+        assert_eq!(decode_response(&verify, &value).unwrap(), resp);
+
+        let resp = Response::Error(ServerError::new(
+            ErrorCode::NoSession,
+            "No active session to verify",
+        ));
+        let value = encode_response(json!(3), &resp);
+        assert_eq!(value["error"]["data"]["code"], "no-session");
+        assert_eq!(decode_response(&verify, &value).unwrap(), resp);
+    }
+
+    #[test]
+    fn sessions_roundtrip() {
+        let resp = Response::Sessions(vec![SessionInfo {
+            id: "s1".into(),
+            portal: "file-chooser".into(),
+            operation: "open-file".into(),
+            title: None,
+            created: 1,
+            dir: "/tmp/s1".into(),
+            app_id: None,
+            mode: Some(SessionMode::Headless),
+        }]);
+        let value = encode_response(json!(1), &resp);
+        assert_eq!(decode_response(&Request::List, &value).unwrap(), resp);
+    }
+
+    #[test]
+    fn permissions_roundtrip() {
+        let req = Request::Permissions { app_id: None };
+        let resp = Response::Permissions(vec![PermissionInfo {
+            app_id: "com.obsproject.Studio".into(),
+            operation: Operation::Screenshot,
+            decision: Decision::Allow,
+        }]);
+        let value = encode_response(json!(1), &resp);
+        assert_eq!(
+            value["result"][0]["operation"],
+            json!("screenshot"),
+            "operations use their wire names"
+        );
+        assert_eq!(decode_response(&req, &value).unwrap(), resp);
+
+        let value = json!({
+            "jsonrpc": "2.0", "id": 2, "method": "set-permission",
+            "params": { "app_id": "a", "operation": "screenshot", "decision": "allow" },
+        });
+        let (_, err) = decode_call(&value).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+    }
+}
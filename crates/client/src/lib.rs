@@ -0,0 +1,45 @@
+//! Client for the portty daemon
+//!
+//! The control protocol spoken on `/tmp/portty/<uid>/daemon.sock` (text lines
+//! and JSON-RPC 2.0), a blocking socket client, and the paths of the session
+//! and queue directories. Enough to drive portty from other tools, such as
+//! file manager plugins or status bars, without the daemon's dependencies:
+//!
+//! ```no_run
+//! use portty_client::DaemonClient;
+//!
+//! for session in DaemonClient::new().list()? {
+//!     println!("{} {} {}", session.id, session.portal, session.operation);
+//! }
+//! # Ok::<(), portty_client::ClientError>(())
+//! ```
+//!
+//! # Stability
+//!
+//! This crate follows semver: the protocol types and the client API only
+//! change incompatibly with a new major version. Error enums are
+//! `#[non_exhaustive]`. Daemon and CLI internals live in `libportty`, which
+//! re-exports these modules.
+
+pub mod client;
+pub mod codec;
+pub mod exit;
+pub mod input;
+#[cfg(feature = "jsonrpc")]
+pub mod jsonrpc;
+pub mod operation;
+pub mod paths;
+pub mod protocol;
+#[cfg(feature = "secure")]
+pub mod secure;
+pub mod socket;
+pub mod uri;
+
+pub use client::{ClientError, DaemonClient};
+pub use exit::ExitStatus;
+pub use operation::{Operation, Portal};
+pub use protocol::{
+    DaemonStatus, Decision, ErrorCode, PermissionInfo, QueueConflict, QueuePolicy, Request,
+    RequestInfo, RequestState, Response, ServerError, SessionInfo, SessionMode, SortOrder,
+    SubmitOptions,
+};
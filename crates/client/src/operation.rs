@@ -0,0 +1,195 @@
+//! Portals and their operations
+//!
+//! The names used on the wire (`SessionInfo`, `submit --portal`), in config
+//! sections, and in session directories (`portal` file, `PORTTY_PORTAL` /
+//! `PORTTY_OPERATION`). Matching on these enums instead of the strings makes
+//! a misspelled name a parse error and a new operation a compile error at
+//! every `match` that has to handle it.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// A portal interface served by the daemon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Portal {
+    FileChooser,
+    Screenshot,
+    RemoteDesktop,
+    DynamicLauncher,
+}
+
+impl Portal {
+    /// Every portal, in the order they are documented
+    pub const ALL: [Self; 4] = [
+        Self::FileChooser,
+        Self::Screenshot,
+        Self::RemoteDesktop,
+        Self::DynamicLauncher,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::FileChooser => "file-chooser",
+            Self::Screenshot => "screenshot",
+            Self::RemoteDesktop => "remote-desktop",
+            Self::DynamicLauncher => "dynamic-launcher",
+        }
+    }
+
+    /// Operations of this portal
+    pub fn operations(self) -> &'static [Operation] {
+        match self {
+            Self::FileChooser => &[
+                Operation::OpenFile,
+                Operation::SaveFile,
+                Operation::SaveFiles,
+            ],
+            Self::Screenshot => &[Operation::Screenshot, Operation::PickColor],
+            Self::RemoteDesktop => &[Operation::Start],
+            Self::DynamicLauncher => &[Operation::PrepareInstall, Operation::RequestInstallToken],
+        }
+    }
+}
+
+impl fmt::Display for Portal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Portal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|portal| portal.as_str() == s)
+            .ok_or_else(|| {
+                let known: Vec<_> = Self::ALL.iter().map(|p| p.as_str()).collect();
+                format!(
+                    "unknown portal: {s} (expected one of: {})",
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// A portal method a session is run for. Names are unique across portals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    /// FileChooser.OpenFile
+    OpenFile,
+    /// FileChooser.SaveFile
+    SaveFile,
+    /// FileChooser.SaveFiles
+    SaveFiles,
+    /// Screenshot.Screenshot
+    Screenshot,
+    /// Screenshot.PickColor
+    PickColor,
+    /// RemoteDesktop.Start
+    Start,
+    /// DynamicLauncher.PrepareInstall
+    PrepareInstall,
+    /// DynamicLauncher.RequestInstallToken
+    RequestInstallToken,
+}
+
+impl Operation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::OpenFile => "open-file",
+            Self::SaveFile => "save-file",
+            Self::SaveFiles => "save-files",
+            Self::Screenshot => "screenshot",
+            Self::PickColor => "pick-color",
+            Self::Start => "start",
+            Self::PrepareInstall => "prepare-install",
+            Self::RequestInstallToken => "request-install-token",
+        }
+    }
+
+    /// The portal this operation belongs to
+    pub fn portal(self) -> Portal {
+        match self {
+            Self::OpenFile | Self::SaveFile | Self::SaveFiles => Portal::FileChooser,
+            Self::Screenshot | Self::PickColor => Portal::Screenshot,
+            Self::Start => Portal::RemoteDesktop,
+            Self::PrepareInstall | Self::RequestInstallToken => Portal::DynamicLauncher,
+        }
+    }
+
+    /// Parse the operation named `operation` of the portal named `portal`
+    pub fn of(portal: &str, operation: &str) -> Result<Self, String> {
+        let portal: Portal = portal.parse()?;
+        portal
+            .operations()
+            .iter()
+            .copied()
+            .find(|op| op.as_str() == operation)
+            .ok_or_else(|| {
+                let known: Vec<_> = portal.operations().iter().map(|op| op.as_str()).collect();
+                format!(
+                    "unknown {portal} operation: {operation} (expected one of: {})",
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Portal::ALL
+            .iter()
+            .flat_map(|portal| portal.operations())
+            .copied()
+            .find(|op| op.as_str() == s)
+            .ok_or_else(|| format!("unknown operation: {s}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_roundtrip() {
+        for portal in Portal::ALL {
+            assert_eq!(portal.as_str().parse::<Portal>(), Ok(portal));
+            for &op in portal.operations() {
+                assert_eq!(op.portal(), portal);
+                assert_eq!(op.as_str().parse::<Operation>(), Ok(op));
+                assert_eq!(Operation::of(portal.as_str(), op.as_str()), Ok(op));
+                // serde uses the same names
+                #[cfg(feature = "jsonrpc")]
+                assert_eq!(
+                    serde_json::to_string(&op).unwrap(),
+                    format!("\"{}\"", op.as_str())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn of_rejects_mismatches() {
+        assert_eq!(
+            Operation::of("screenshot", "pick-color"),
+            Ok(Operation::PickColor)
+        );
+        assert!(Operation::of("file-chooser", "screenshot").is_err());
+        assert!(Operation::of("file-choser", "open-file").is_err());
+        assert!("open_file".parse::<Operation>().is_err());
+    }
+}
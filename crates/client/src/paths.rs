@@ -0,0 +1,133 @@
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Base directory from the config (`base_dir`), see [`configure_base_dir`]
+static CONFIGURED: OnceLock<PathBuf> = OnceLock::new();
+
+/// Get the base directory for sessions: `PORTTY_BASE_DIR`, else the
+/// configured `base_dir`, else `portty/<uid>` in `$TMPDIR` (`/tmp` when
+/// unset)
+///
+/// The override runs a separate daemon with its own sockets and sessions,
+/// as `portty tutorial` does. porttyd passes the directory it uses on to
+/// the commands it runs as `PORTTY_BASE_DIR`, so their `portty` calls find
+/// it whatever their `$TMPDIR`.
+pub fn base_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("PORTTY_BASE_DIR").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = CONFIGURED.get() {
+        return dir.clone();
+    }
+    default_base_dir(std::env::var_os("TMPDIR"), current_uid())
+}
+
+/// Use `dir` as the base directory when `PORTTY_BASE_DIR` is unset. Called
+/// once at startup with the config's `base_dir`; later calls are ignored.
+pub fn configure_base_dir(dir: PathBuf) {
+    let _ = CONFIGURED.set(dir);
+}
+
+/// `portty/<uid>` in `tmpdir`, or in `/tmp` when it is unset or not an
+/// absolute path
+fn default_base_dir(tmpdir: Option<OsString>, uid: u32) -> PathBuf {
+    tmpdir
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(format!("portty/{uid}"))
+}
+
+/// Uid this process runs as
+pub fn current_uid() -> u32 {
+    fs::metadata("/proc/self").map(|m| m.uid()).unwrap_or(0)
+}
+
+/// Get the daemon socket path
+pub fn daemon_socket_path() -> PathBuf {
+    base_dir().join("daemon.sock")
+}
+
+/// Get the pending directory
+pub fn pending_dir() -> PathBuf {
+    base_dir().join("pending")
+}
+
+/// Get the submissions directory
+pub fn submissions_dir() -> PathBuf {
+    base_dir().join("submissions")
+}
+
+/// Get the daemon control FIFO path
+pub fn daemon_ctl_path() -> PathBuf {
+    base_dir().join("daemon.ctl")
+}
+
+/// Ensure the base directory exists with correct ownership and permissions (0o700).
+/// Returns an error if the directory is owned by another user.
+pub fn ensure_base_dir() -> std::io::Result<()> {
+    let base = base_dir();
+    let parent = base.parent().unwrap_or(Path::new("/tmp"));
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o755)
+        .create(parent)?;
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&base)
+        .or_else(|e| {
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                Ok(())
+            } else {
+                Err(e)
+            }
+        })?;
+
+    // Verify ownership
+    let meta = fs::metadata(&base)?;
+    let my_uid = current_uid();
+    if meta.uid() != my_uid {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!(
+                "base directory {} is owned by uid {}, expected {}",
+                base.display(),
+                meta.uid(),
+                my_uid
+            ),
+        ));
+    }
+
+    // Enforce mode 0o700 (fix if we own it but mode is wrong)
+    let mode = meta.mode() & 0o777;
+    if mode != 0o700 {
+        fs::set_permissions(&base, fs::Permissions::from_mode(0o700))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_base_dir_follows_tmpdir() {
+        assert_eq!(default_base_dir(None, 1000), Path::new("/tmp/portty/1000"));
+        assert_eq!(
+            default_base_dir(Some("/var/tmp".into()), 1000),
+            Path::new("/var/tmp/portty/1000")
+        );
+        assert_eq!(
+            default_base_dir(Some("relative".into()), 1000),
+            Path::new("/tmp/portty/1000")
+        );
+        assert_eq!(
+            default_base_dir(Some("".into()), 1000),
+            Path::new("/tmp/portty/1000")
+        );
+    }
+}
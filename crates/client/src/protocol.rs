@@ -0,0 +1,1701 @@
+//! IPC protocol for daemon control socket
+//!
+//! Flat protocol for CLI -> Daemon communication.
+//! Data operations (edit, clear) are file-based.
+//! Control commands (submit, review, cancel, verify, reset, focus) and management
+//! commands (list, status, selection, reload, refresh-shims, shutdown) go through
+//! the daemon socket.
+//!
+//! # Wire Format
+//!
+//! Messages are plain text lines terminated by `\n`.
+//!
+//! ## Request (single line)
+//! ```text
+//! submit [--allow-empty] [--confirm] [--queue-policy=<policy>] [--portal=<portal>] [--sort=<order>] [--prefer=<choice>] [session_id]
+//! review [--allow-empty] [--confirm] [--sort=<order>] [--prefer=<choice>] [session_id]
+//! cancel [session_id]
+//! verify [session_id]
+//! reset [--keep=<glob>]... [session_id]
+//! focus [session_id]
+//! list
+//! requests
+//! status
+//! selection [session_id]
+//! reload
+//! refresh-shims [session_id]
+//! shutdown
+//! permissions [app_id]
+//! set-permission <app_id> <portal> <operation> <allow|deny>
+//! clear-permission <app_id> [<portal> <operation>]
+//! ```
+//!
+//! ## Response (one or more lines, terminated by `ok` or `error: ...`)
+//! ```text
+//! ok
+//! error: <message>[\t<code>]
+//! <id>\t<portal>\t<operation>\t<created>\t<dir>\t<title>\t<app_id>\t<mode>\n ... ok
+//! request\t<handle>\t<portal>\t<operation>\t<state>\t<received>\t<session_id>\t<app_id>\n ... ok
+//! status\t<pid>\t<version>\t<sessions>\t<id_collisions>\t<protocol>\t<build>\n ok
+//! permission\t<app_id>\t<portal>\t<operation>\t<decision>\n ... ok
+//! entry\t<entry>\n ... ok
+//! ```
+//!
+//! An error's `code` ([`ErrorCode`]) tells clients what kind of failure it
+//! is; it is left out for plain failures.
+//! `selection` streams one `entry` line per selected entry as the daemon
+//! reads them, so neither side holds a large selection in memory (see
+//! [`crate::codec::read_selection`]). A read error part way through ends the
+//! stream with `error: ...` instead of `ok`. `review` answers with `entry`
+//! lines too: the URIs a submit with the same flags would return to the app.
+//! Lines only ever gain fields at the end: decoders take missing trailing
+//! fields as unknown (a session's `mode` from an older daemon) and ignore
+//! ones they do not know.
+//! `reset` tokens are separated by whitespace, so its `--keep` globs cannot
+//! contain any (`?` matches a space).
+//!
+//! Every change to these encodings bumps [`PROTOCOL_VERSION`], which
+//! `status` reports. The tests hold the encoding of each request and
+//! response variant per version in `snapshots/protocol-v<N>.txt`. They fail
+//! when the current encoding differs from the snapshot of its version, and
+//! when a recorded version no longer decodes.
+//!
+//! The same commands are also available as JSON-RPC 2.0 (see `jsonrpc`).
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::{self, InputError};
+use crate::operation::Operation;
+
+/// Version of the wire format, bumped whenever an encoding changes
+pub const PROTOCOL_VERSION: u32 = 5;
+
+/// Request sent to the daemon socket
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// Submit/confirm a session or pending entries
+    Submit {
+        session_id: Option<String>,
+        options: SubmitOptions,
+    },
+
+    /// The results a submit with `options` would return to the app, without
+    /// submitting
+    Review {
+        session_id: Option<String>,
+        options: SubmitOptions,
+    },
+
+    /// Cancel a session or clear pending entries
+    Cancel { session_id: Option<String> },
+
+    /// Validate submission against portal constraints
+    Verify { session_id: Option<String> },
+
+    /// Reset submission to initial state, except for entries matching a
+    /// `keep` glob (see `libportty::portal::reset`)
+    Reset {
+        session_id: Option<String>,
+        keep: Vec<String>,
+    },
+
+    /// Switch the shared terminal to a session's window (`[tabs]` mode)
+    Focus { session_id: Option<String> },
+
+    /// List all active sessions
+    List,
+
+    /// List the portal requests the daemon is handling, with a session or
+    /// not yet
+    Requests,
+
+    /// Query daemon liveness and basic runtime information
+    Status,
+
+    /// Stream a session's current selection
+    Selection { session_id: Option<String> },
+
+    /// Reload the daemon configuration from disk
+    Reload,
+
+    /// Relink the `bin/` of one running session, or of all of them, to the
+    /// shims the current config resolves. Answered with the refreshed
+    /// sessions.
+    RefreshShims { session_id: Option<String> },
+
+    /// Stop the daemon (active sessions are cancelled)
+    Shutdown,
+
+    /// List the decisions remembered in the permission store, for all apps
+    /// or one
+    Permissions { app_id: Option<String> },
+
+    /// Remember a decision for an app's requests of an operation
+    SetPermission {
+        app_id: String,
+        operation: Operation,
+        decision: Decision,
+    },
+
+    /// Forget an app's decision for an operation, or all of them
+    ClearPermission {
+        app_id: String,
+        operation: Option<Operation>,
+    },
+}
+
+/// Flags modifying a submit, encoded as `--flag` tokens before the session id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubmitOptions {
+    /// Accept an empty submission as success (zero results) instead of
+    /// cancelling, for operations where an empty result is valid
+    pub allow_empty: bool,
+    /// Submit paths the `audit` config asks to confirm
+    pub confirm: bool,
+    /// How a queued submission competes with an interactive session
+    /// (only used when the submit is queued)
+    pub queue_policy: Option<QueuePolicy>,
+    /// Only submit a session of this portal, and queue for this portal
+    /// (instead of any) when none is active. Ignored with a session id.
+    pub portal: Option<String>,
+    /// Order of the returned files (file chooser only)
+    pub sort: Option<SortOrder>,
+    /// What to submit when the pending queue and the session's selection
+    /// differ, instead of the configured `queue_conflict`
+    pub prefer: Option<QueueConflict>,
+}
+
+impl SubmitOptions {
+    fn encode(&self) -> String {
+        let mut out = String::new();
+        if self.allow_empty {
+            out.push_str(" --allow-empty");
+        }
+        if self.confirm {
+            out.push_str(" --confirm");
+        }
+        if let Some(policy) = self.queue_policy {
+            out.push_str(&format!(" --queue-policy={policy}"));
+        }
+        if let Some(portal) = &self.portal {
+            out.push_str(&format!(" --portal={portal}"));
+        }
+        if let Some(sort) = self.sort {
+            out.push_str(&format!(" --sort={sort}"));
+        }
+        if let Some(prefer) = self.prefer {
+            out.push_str(&format!(" --prefer={prefer}"));
+        }
+        out
+    }
+
+    /// Apply one `--flag` token as written in a `submit` command line (e.g.
+    /// `--allow-empty`, `--queue-policy=merge`)
+    pub fn apply_flag(&mut self, flag: &str) -> Result<(), String> {
+        match flag.split_once('=') {
+            None if flag == "--allow-empty" => self.allow_empty = true,
+            None if flag == "--confirm" => self.confirm = true,
+            Some(("--queue-policy", value)) => self.queue_policy = Some(value.parse()?),
+            Some(("--portal", value)) if !value.is_empty() => self.portal = Some(value.to_string()),
+            Some(("--sort", value)) => self.sort = Some(value.parse()?),
+            Some(("--prefer", value)) => self.prefer = Some(value.parse()?),
+            _ => return Err(format!("unknown submit flag: {flag}")),
+        }
+        Ok(())
+    }
+}
+
+/// What happens when a dialog opens while a queued submission is waiting
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueuePolicy {
+    /// Auto-apply the queued submission without starting `exec`
+    #[default]
+    QueuedFirst,
+    /// Leave the queue alone when an interactive process is started; queued
+    /// submissions only apply to headless sessions
+    InteractiveFirst,
+    /// Pre-populate the session with the queued entries and wait for an
+    /// explicit submit
+    Merge,
+}
+
+impl QueuePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::QueuedFirst => "queued-first",
+            Self::InteractiveFirst => "interactive-first",
+            Self::Merge => "merge",
+        }
+    }
+}
+
+impl std::fmt::Display for QueuePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for QueuePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued-first" => Ok(Self::QueuedFirst),
+            "interactive-first" => Ok(Self::InteractiveFirst),
+            "merge" => Ok(Self::Merge),
+            _ => Err(format!(
+                "unknown queue policy: {s} (expected queued-first, interactive-first, or merge)"
+            )),
+        }
+    }
+}
+
+/// Order of the files returned to the app. Some apps (image viewers, music
+/// players) treat it as a playlist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// In the order they were selected
+    #[default]
+    Selection,
+    /// By file name
+    Name,
+    /// By modification time, oldest first
+    Mtime,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Selection => "selection",
+            Self::Name => "name",
+            Self::Mtime => "mtime",
+        }
+    }
+}
+
+impl std::fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "selection" => Ok(Self::Selection),
+            "name" => Ok(Self::Name),
+            "mtime" => Ok(Self::Mtime),
+            _ => Err(format!(
+                "unknown sort order: {s} (expected name, mtime, or selection)"
+            )),
+        }
+    }
+}
+
+/// What a submit does when entries were queued (`portty add` outside the
+/// session) while the session has a selection of its own
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueueConflict {
+    /// Refuse the submit (see [`QUEUE_CONFLICT`]) until one is preferred
+    #[default]
+    Ask,
+    /// Add the queued entries to the session's
+    Merge,
+    /// Submit the session's selection; the queue stays pending
+    Session,
+    /// Submit the queued entries instead of the session's
+    Queue,
+}
+
+impl QueueConflict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ask => "ask",
+            Self::Merge => "merge",
+            Self::Session => "session",
+            Self::Queue => "queue",
+        }
+    }
+}
+
+impl std::fmt::Display for QueueConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for QueueConflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ask" => Ok(Self::Ask),
+            "merge" => Ok(Self::Merge),
+            "session" => Ok(Self::Session),
+            "queue" => Ok(Self::Queue),
+            _ => Err(format!(
+                "unknown queue conflict choice: {s} (expected ask, merge, session, or queue)"
+            )),
+        }
+    }
+}
+
+/// A decision remembered per app and operation. Requests with a decision
+/// are answered without opening a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Decision {
+    /// Submit right away: `approve` for approvals, else the request's own
+    /// entries or the configured `defaults`
+    Allow,
+    /// Cancel right away
+    Deny,
+}
+
+impl Decision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+impl std::fmt::Display for Decision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Decision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            _ => Err(format!("unknown decision: {s} (expected allow or deny)")),
+        }
+    }
+}
+
+/// Response from the daemon socket
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// Operation completed successfully
+    Ok,
+
+    /// Error occurred
+    Error(ServerError),
+
+    /// List of active sessions
+    Sessions(Vec<SessionInfo>),
+
+    /// Daemon runtime status
+    Status(DaemonStatus),
+
+    /// Remembered decisions
+    Permissions(Vec<PermissionInfo>),
+
+    /// Portal requests in flight
+    Requests(Vec<RequestInfo>),
+
+    /// A session's selection, one entry per line. The socket streams it;
+    /// this is the collected form.
+    Selection(Vec<String>),
+}
+
+/// A failure reported by the daemon
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message}")]
+pub struct ServerError {
+    /// What kind of failure it is, for clients to act on
+    pub code: ErrorCode,
+    /// What went wrong, for people
+    pub message: String,
+}
+
+impl ServerError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<String> for ServerError {
+    fn from(message: String) -> Self {
+        Self::new(ErrorCode::Failed, message)
+    }
+}
+
+impl From<&str> for ServerError {
+    fn from(message: &str) -> Self {
+        Self::new(ErrorCode::Failed, message)
+    }
+}
+
+/// Kind of a [`ServerError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// A failure without a more specific kind
+    #[default]
+    Failed,
+    /// No session to act on: none is active, or the given id is unknown
+    /// (see [`NO_SESSION`])
+    NoSession,
+    /// Several sessions are active and none was chosen (see
+    /// [`MULTIPLE_SESSIONS`])
+    MultipleSessions,
+    /// A submission failed validation or an `audit` rule (see
+    /// [`INVALID_SUBMISSION`])
+    InvalidSubmission,
+    /// The pending queue and the session's selection differ (see
+    /// [`QUEUE_CONFLICT`])
+    QueueConflict,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Failed => "failed",
+            Self::NoSession => "no-session",
+            Self::MultipleSessions => "multiple-sessions",
+            Self::InvalidSubmission => "invalid-submission",
+            Self::QueueConflict => "queue-conflict",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ErrorCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "failed" => Ok(Self::Failed),
+            "no-session" => Ok(Self::NoSession),
+            "multiple-sessions" => Ok(Self::MultipleSessions),
+            "invalid-submission" => Ok(Self::InvalidSubmission),
+            "queue-conflict" => Ok(Self::QueueConflict),
+            _ => Err(format!("unknown error code: {s}")),
+        }
+    }
+}
+
+/// Start of an [`Response::Error`] message when there is no session to act
+/// on: none is active, or the given id is unknown
+pub const NO_SESSION: &str = "No active session";
+
+/// Start of an error when several sessions are active and none was chosen.
+/// The daemon picks the oldest; clients resolving a session themselves (the
+/// portty CLI) report this instead.
+pub const MULTIPLE_SESSIONS: &str = "Multiple sessions active";
+
+/// Start of an [`Response::Error`] message refusing a submission that failed
+/// validation or an `audit` rule
+pub const INVALID_SUBMISSION: &str = "Invalid submission";
+
+/// Start of an [`Response::Error`] message refusing a submit because the
+/// pending queue and the session's selection differ (see [`QueueConflict`])
+pub const QUEUE_CONFLICT: &str = "Queue conflict";
+
+/// Runtime information reported by the daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    /// Process ID of the daemon
+    pub pid: u32,
+    /// Daemon version (crate version of porttyd)
+    pub version: String,
+    /// Number of active sessions
+    pub sessions: usize,
+    /// Session ids found already taken while creating a session, since the
+    /// daemon started (each retried with a fresh id)
+    #[serde(default)]
+    pub id_collisions: u64,
+    /// [`PROTOCOL_VERSION`] of the daemon, `None` from daemons older than
+    /// the field
+    #[serde(default)]
+    pub protocol: Option<u32>,
+    /// How porttyd was built (profile, features, commit), `None` from
+    /// daemons older than the field
+    #[serde(default)]
+    pub build: Option<String>,
+    /// Config tables of the portals served, plugin portals included; empty
+    /// from daemons older than the field
+    #[serde(default)]
+    pub portals: Vec<String>,
+}
+
+/// A decision remembered for an app
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PermissionInfo {
+    /// ID of the application
+    pub app_id: String,
+    /// Operation the decision applies to
+    pub operation: Operation,
+    /// What happens to the app's requests
+    pub decision: Decision,
+}
+
+/// Where a portal request is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RequestState {
+    /// Received, with no session yet: the queue, remembered decisions, and
+    /// the request itself are being checked
+    Received,
+    /// A session is open for it
+    Session,
+    /// The session ended and its results are being prepared for the app
+    /// (`post_process`, document export)
+    Delivering,
+}
+
+impl RequestState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Received => "received",
+            Self::Session => "session",
+            Self::Delivering => "delivering",
+        }
+    }
+}
+
+impl std::fmt::Display for RequestState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for RequestState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "received" => Ok(Self::Received),
+            "session" => Ok(Self::Session),
+            "delivering" => Ok(Self::Delivering),
+            _ => Err(format!("unknown request state: {s}")),
+        }
+    }
+}
+
+/// A portal request the daemon is handling
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestInfo {
+    /// D-Bus object path of the request
+    pub handle: String,
+    /// Requested operation
+    pub operation: Operation,
+    pub state: RequestState,
+    /// Unix timestamp when the request was received
+    pub received: u64,
+    /// Session opened for the request
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// ID of the application that made the request
+    #[serde(default)]
+    pub app_id: Option<String>,
+}
+
+/// How a session is shown to the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SessionMode {
+    /// A terminal window of its own (`exec`)
+    Terminal,
+    /// The daemon's terminal (`porttyd --inline`)
+    Inline,
+    /// A window of the shared tmux session (`[tabs]`)
+    Tabs,
+    /// No terminal: driven by the `portty` CLI, or by `portty join`
+    Headless,
+}
+
+impl SessionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Terminal => "terminal",
+            Self::Inline => "inline",
+            Self::Tabs => "tabs",
+            Self::Headless => "headless",
+        }
+    }
+}
+
+impl std::fmt::Display for SessionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for SessionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "terminal" => Ok(Self::Terminal),
+            "inline" => Ok(Self::Inline),
+            "tabs" => Ok(Self::Tabs),
+            "headless" => Ok(Self::Headless),
+            _ => Err(format!("unknown session mode: {s}")),
+        }
+    }
+}
+
+/// Information about a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// Unique session identifier
+    pub id: String,
+    /// Portal name (e.g. "file-chooser", "screenshot")
+    pub portal: String,
+    /// Operation name (e.g. "open-file", "screenshot")
+    pub operation: String,
+    /// Session title (from portal options)
+    pub title: Option<String>,
+    /// ID of the application that opened the dialog
+    #[serde(default)]
+    pub app_id: Option<String>,
+    /// Unix timestamp when session was created
+    pub created: u64,
+    /// Path to session directory
+    pub dir: String,
+    /// How the session is shown; `None` from daemons that do not report it
+    #[serde(default)]
+    pub mode: Option<SessionMode>,
+}
+
+impl Request {
+    /// Check the strings a client sent: no NUL bytes, at most `limit` bytes
+    pub fn check_input(&self, limit: usize) -> Result<(), InputError> {
+        let session_id = match self {
+            Request::Submit {
+                session_id,
+                options,
+            } => {
+                if let Some(portal) = &options.portal {
+                    input::check("portal", portal, limit)?;
+                }
+                session_id
+            }
+            Request::Reset { session_id, keep } => {
+                for glob in keep {
+                    input::check("keep", glob, limit)?;
+                }
+                session_id
+            }
+            Request::Review { session_id, .. }
+            | Request::Cancel { session_id }
+            | Request::Verify { session_id }
+            | Request::Focus { session_id }
+            | Request::RefreshShims { session_id }
+            | Request::Selection { session_id } => session_id,
+            Request::Permissions { app_id: None } => &None,
+            Request::Permissions {
+                app_id: Some(app_id),
+            }
+            | Request::SetPermission { app_id, .. }
+            | Request::ClearPermission { app_id, .. } => {
+                return input::check("app_id", app_id, limit);
+            }
+            Request::List
+            | Request::Requests
+            | Request::Status
+            | Request::Reload
+            | Request::Shutdown => &None,
+        };
+        match session_id {
+            Some(id) => input::check("session_id", id, limit),
+            None => Ok(()),
+        }
+    }
+
+    /// Encode request as a single newline-terminated line
+    pub fn encode(&self) -> String {
+        match self {
+            Request::Submit {
+                session_id,
+                options,
+            } => match session_id {
+                None => format!("submit{}\n", options.encode()),
+                Some(id) => format!("submit{} {id}\n", options.encode()),
+            },
+            Request::Review {
+                session_id,
+                options,
+            } => match session_id {
+                None => format!("review{}\n", options.encode()),
+                Some(id) => format!("review{} {id}\n", options.encode()),
+            },
+            Request::Cancel { session_id: None } => "cancel\n".to_string(),
+            Request::Cancel {
+                session_id: Some(id),
+            } => format!("cancel {id}\n"),
+            Request::Verify { session_id: None } => "verify\n".to_string(),
+            Request::Verify {
+                session_id: Some(id),
+            } => format!("verify {id}\n"),
+            Request::Reset { session_id, keep } => {
+                let mut line = "reset".to_string();
+                for glob in keep {
+                    line.push_str(&format!(" --keep={glob}"));
+                }
+                if let Some(id) = session_id {
+                    line.push_str(&format!(" {id}"));
+                }
+                line.push('\n');
+                line
+            }
+            Request::Focus { session_id: None } => "focus\n".to_string(),
+            Request::Focus {
+                session_id: Some(id),
+            } => format!("focus {id}\n"),
+            Request::List => "list\n".to_string(),
+            Request::Requests => "requests\n".to_string(),
+            Request::Status => "status\n".to_string(),
+            Request::Selection { session_id: None } => "selection\n".to_string(),
+            Request::Selection {
+                session_id: Some(id),
+            } => format!("selection {id}\n"),
+            Request::Reload => "reload\n".to_string(),
+            Request::RefreshShims { session_id: None } => "refresh-shims\n".to_string(),
+            Request::RefreshShims {
+                session_id: Some(id),
+            } => format!("refresh-shims {id}\n"),
+            Request::Shutdown => "shutdown\n".to_string(),
+            Request::Permissions { app_id: None } => "permissions\n".to_string(),
+            Request::Permissions {
+                app_id: Some(app_id),
+            } => format!("permissions {app_id}\n"),
+            Request::SetPermission {
+                app_id,
+                operation,
+                decision,
+            } => format!(
+                "set-permission {app_id} {} {operation} {decision}\n",
+                operation.portal()
+            ),
+            Request::ClearPermission {
+                app_id,
+                operation: None,
+            } => format!("clear-permission {app_id}\n"),
+            Request::ClearPermission {
+                app_id,
+                operation: Some(operation),
+            } => format!(
+                "clear-permission {app_id} {} {operation}\n",
+                operation.portal()
+            ),
+        }
+    }
+
+    /// Decode request from a trimmed line
+    pub fn decode(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (cmd, arg) = match line.split_once(' ') {
+            Some((cmd, arg)) => (cmd, Some(arg)),
+            None => (line, None),
+        };
+
+        match cmd {
+            "submit" | "review" => {
+                let mut options = SubmitOptions::default();
+                let mut session_id = None;
+                for token in arg.unwrap_or_default().split_whitespace() {
+                    if token.starts_with("--") {
+                        options.apply_flag(token)?;
+                    } else {
+                        session_id = Some(token.to_string());
+                    }
+                }
+                Ok(if cmd == "submit" {
+                    Request::Submit {
+                        session_id,
+                        options,
+                    }
+                } else {
+                    Request::Review {
+                        session_id,
+                        options,
+                    }
+                })
+            }
+            "cancel" => Ok(Request::Cancel {
+                session_id: arg.map(String::from),
+            }),
+            "verify" => Ok(Request::Verify {
+                session_id: arg.map(String::from),
+            }),
+            "reset" => {
+                let (mut session_id, mut keep) = (None, Vec::new());
+                for token in arg.unwrap_or_default().split_whitespace() {
+                    if let Some(glob) = token.strip_prefix("--keep=") {
+                        keep.push(glob.to_string());
+                    } else if token.starts_with("--") {
+                        return Err(format!("unknown reset flag: {token}"));
+                    } else {
+                        session_id = Some(token.to_string());
+                    }
+                }
+                Ok(Request::Reset { session_id, keep })
+            }
+            "focus" => Ok(Request::Focus {
+                session_id: arg.map(String::from),
+            }),
+            "list" => Ok(Request::List),
+            "requests" => Ok(Request::Requests),
+            "status" => Ok(Request::Status),
+            "selection" => Ok(Request::Selection {
+                session_id: arg.map(String::from),
+            }),
+            "reload" => Ok(Request::Reload),
+            "refresh-shims" => Ok(Request::RefreshShims {
+                session_id: arg.map(String::from),
+            }),
+            "shutdown" => Ok(Request::Shutdown),
+            "permissions" => Ok(Request::Permissions {
+                app_id: arg.map(String::from),
+            }),
+            "set-permission" => match *arg.unwrap_or_default().split(' ').collect::<Vec<_>>() {
+                [app_id, portal, operation, decision] if !app_id.is_empty() => {
+                    Ok(Request::SetPermission {
+                        app_id: app_id.to_string(),
+                        operation: Operation::of(portal, operation)?,
+                        decision: decision.parse()?,
+                    })
+                }
+                _ => Err("usage: set-permission <app_id> <portal> <operation> <decision>".into()),
+            },
+            "clear-permission" => match *arg.unwrap_or_default().split(' ').collect::<Vec<_>>() {
+                [app_id] if !app_id.is_empty() => Ok(Request::ClearPermission {
+                    app_id: app_id.to_string(),
+                    operation: None,
+                }),
+                [app_id, portal, operation] if !app_id.is_empty() => Ok(Request::ClearPermission {
+                    app_id: app_id.to_string(),
+                    operation: Some(Operation::of(portal, operation)?),
+                }),
+                _ => Err("usage: clear-permission <app_id> [<portal> <operation>]".into()),
+            },
+            _ => Err(format!("unknown command: {cmd}")),
+        }
+    }
+}
+
+/// Sanitize a field for the tab-separated text protocol.
+/// Replaces tabs and newlines with spaces to prevent protocol injection.
+fn sanitize_field(s: &str) -> String {
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+impl Response {
+    /// Encode response as one or more lines
+    pub fn encode(&self) -> String {
+        match self {
+            Response::Ok => "ok\n".to_string(),
+            Response::Error(err) => err.encode_line(),
+            Response::Sessions(sessions) => {
+                let mut out = String::new();
+                for s in sessions {
+                    let title = s.title.as_deref().unwrap_or("");
+                    let app_id = s.app_id.as_deref().unwrap_or("");
+                    let mode = s.mode.as_ref().map_or("", SessionMode::as_str);
+                    out.push_str(&format!(
+                        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{mode}\n",
+                        sanitize_field(&s.id),
+                        sanitize_field(&s.portal),
+                        sanitize_field(&s.operation),
+                        s.created,
+                        sanitize_field(&s.dir),
+                        sanitize_field(title),
+                        sanitize_field(app_id),
+                    ));
+                }
+                out.push_str("ok\n");
+                out
+            }
+            Response::Status(status) => format!("{}ok\n", status.encode_line()),
+            Response::Permissions(permissions) => {
+                let mut out: String = permissions
+                    .iter()
+                    .map(PermissionInfo::encode_line)
+                    .collect();
+                out.push_str("ok\n");
+                out
+            }
+            Response::Requests(requests) => {
+                let mut out: String = requests.iter().map(RequestInfo::encode_line).collect();
+                out.push_str("ok\n");
+                out
+            }
+            Response::Selection(entries) => {
+                let mut out: String = entries.iter().map(|e| encode_entry(e)).collect();
+                out.push_str("ok\n");
+                out
+            }
+        }
+    }
+}
+
+impl ServerError {
+    /// Encode as an `error: ...` line (newline-terminated)
+    pub fn encode_line(&self) -> String {
+        match self.code {
+            ErrorCode::Failed => format!("error: {}\n", sanitize_field(&self.message)),
+            code => format!("error: {}\t{code}\n", sanitize_field(&self.message)),
+        }
+    }
+
+    /// Parse the fields following the `error: ` prefix. Codes this client
+    /// does not know are plain failures.
+    pub fn decode_fields(fields: &str) -> Self {
+        match fields.split_once('\t') {
+            Some((message, code)) => Self::new(code.parse().unwrap_or_default(), message),
+            None => Self::from(fields),
+        }
+    }
+}
+
+/// Encode one selection entry as an `entry\t...` line (newline-terminated)
+pub fn encode_entry(entry: &str) -> String {
+    format!("entry\t{}\n", sanitize_field(entry))
+}
+
+impl DaemonStatus {
+    /// Encode as a `status\t...` line (newline-terminated)
+    pub fn encode_line(&self) -> String {
+        format!(
+            "status\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            self.pid,
+            sanitize_field(&self.version),
+            self.sessions,
+            self.id_collisions,
+            self.protocol.map(|v| v.to_string()).unwrap_or_default(),
+            sanitize_field(self.build.as_deref().unwrap_or_default()),
+            sanitize_field(&self.portals.join(","))
+        )
+    }
+
+    /// Parse the fields following the `status\t` prefix
+    pub fn decode_fields(fields: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = fields.split('\t').collect();
+        if parts.len() < 3 {
+            return Err(format!(
+                "expected 3 tab-separated status fields, got {}",
+                parts.len()
+            ));
+        }
+        Ok(DaemonStatus {
+            pid: parts[0].parse().map_err(|e| format!("invalid pid: {e}"))?,
+            version: parts[1].to_string(),
+            sessions: parts[2]
+                .parse()
+                .map_err(|e| format!("invalid session count: {e}"))?,
+            // Missing from older daemons
+            id_collisions: parts
+                .get(3)
+                .map(|n| n.parse())
+                .transpose()
+                .map_err(|e| format!("invalid collision count: {e}"))?
+                .unwrap_or(0),
+            protocol: parts
+                .get(4)
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| format!("invalid protocol version: {e}"))?,
+            build: parts
+                .get(5)
+                .filter(|build| !build.is_empty())
+                .map(|build| build.to_string()),
+            portals: parts
+                .get(6)
+                .map(|portals| {
+                    portals
+                        .split(',')
+                        .filter(|portal| !portal.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl PermissionInfo {
+    /// Encode as a `permission\t...` line (newline-terminated)
+    pub fn encode_line(&self) -> String {
+        format!(
+            "permission\t{}\t{}\t{}\t{}\n",
+            sanitize_field(&self.app_id),
+            self.operation.portal(),
+            self.operation,
+            self.decision
+        )
+    }
+
+    /// Parse the fields following the `permission\t` prefix
+    pub fn decode_fields(fields: &str) -> Result<Self, String> {
+        match *fields.split('\t').collect::<Vec<_>>() {
+            [app_id, portal, operation, decision] => Ok(PermissionInfo {
+                app_id: app_id.to_string(),
+                operation: Operation::of(portal, operation)?,
+                decision: decision.parse()?,
+            }),
+            ref parts => Err(format!(
+                "expected 4 tab-separated permission fields, got {}",
+                parts.len()
+            )),
+        }
+    }
+}
+
+impl RequestInfo {
+    /// Encode as a `request\t...` line (newline-terminated)
+    pub fn encode_line(&self) -> String {
+        format!(
+            "request\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            sanitize_field(&self.handle),
+            self.operation.portal(),
+            self.operation,
+            self.state,
+            self.received,
+            sanitize_field(self.session_id.as_deref().unwrap_or("")),
+            sanitize_field(self.app_id.as_deref().unwrap_or(""))
+        )
+    }
+
+    /// Parse the fields following the `request\t` prefix
+    pub fn decode_fields(fields: &str) -> Result<Self, String> {
+        match *fields.split('\t').collect::<Vec<_>>() {
+            [
+                handle,
+                portal,
+                operation,
+                state,
+                received,
+                session_id,
+                app_id,
+            ] => {
+                let optional = |v: &str| (!v.is_empty()).then(|| v.to_string());
+                Ok(RequestInfo {
+                    handle: handle.to_string(),
+                    operation: Operation::of(portal, operation)?,
+                    state: state.parse()?,
+                    received: received
+                        .parse()
+                        .map_err(|e| format!("invalid received timestamp: {e}"))?,
+                    session_id: optional(session_id),
+                    app_id: optional(app_id),
+                })
+            }
+            ref parts => Err(format!(
+                "expected 7 tab-separated request fields, got {}",
+                parts.len()
+            )),
+        }
+    }
+}
+
+impl SessionInfo {
+    /// Parse a tab-separated session info line
+    pub fn decode_line(line: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() < 5 {
+            return Err(format!(
+                "expected at least 5 tab-separated fields, got {}",
+                parts.len()
+            ));
+        }
+
+        let created: u64 = parts[3]
+            .parse()
+            .map_err(|e| format!("invalid created timestamp: {e}"))?;
+
+        let optional = |i: usize| {
+            parts
+                .get(i)
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string())
+        };
+
+        Ok(SessionInfo {
+            id: parts[0].to_string(),
+            portal: parts[1].to_string(),
+            operation: parts[2].to_string(),
+            created,
+            dir: parts[4].to_string(),
+            title: optional(5),
+            app_id: optional(6),
+            // An unknown mode from a newer daemon is as good as none
+            mode: optional(7).and_then(|mode| mode.parse().ok()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every request variant, with and without its optional parts
+    fn sample_requests() -> Vec<Request> {
+        vec![
+            Request::Submit {
+                session_id: None,
+                options: SubmitOptions::default(),
+            },
+            Request::Submit {
+                session_id: Some("abc".into()),
+                options: SubmitOptions::default(),
+            },
+            Request::Submit {
+                session_id: Some("abc".into()),
+                options: SubmitOptions {
+                    allow_empty: true,
+                    queue_policy: Some(QueuePolicy::InteractiveFirst),
+                    ..Default::default()
+                },
+            },
+            Request::Submit {
+                session_id: None,
+                options: SubmitOptions {
+                    sort: Some(SortOrder::Mtime),
+                    ..Default::default()
+                },
+            },
+            Request::Submit {
+                session_id: None,
+                options: SubmitOptions {
+                    allow_empty: true,
+                    confirm: true,
+                    queue_policy: Some(QueuePolicy::InteractiveFirst),
+                    portal: Some("file-chooser".into()),
+                    sort: Some(SortOrder::Name),
+                    prefer: Some(QueueConflict::Queue),
+                },
+            },
+            Request::Review {
+                session_id: None,
+                options: SubmitOptions::default(),
+            },
+            Request::Review {
+                session_id: Some("s1".into()),
+                options: SubmitOptions {
+                    confirm: true,
+                    sort: Some(SortOrder::Name),
+                    ..Default::default()
+                },
+            },
+            Request::Cancel { session_id: None },
+            Request::Cancel {
+                session_id: Some("xyz".into()),
+            },
+            Request::Verify { session_id: None },
+            Request::Verify {
+                session_id: Some("s1".into()),
+            },
+            Request::Reset {
+                session_id: None,
+                keep: Vec::new(),
+            },
+            Request::Reset {
+                session_id: Some("s2".into()),
+                keep: vec!["*.pdf".into(), "~/Downloads/**".into()],
+            },
+            Request::Focus { session_id: None },
+            Request::Focus {
+                session_id: Some("s3".into()),
+            },
+            Request::List,
+            Request::Requests,
+            Request::Status,
+            Request::Selection { session_id: None },
+            Request::Selection {
+                session_id: Some("abc".into()),
+            },
+            Request::Reload,
+            Request::RefreshShims { session_id: None },
+            Request::RefreshShims {
+                session_id: Some("s4".into()),
+            },
+            Request::Shutdown,
+            Request::Permissions { app_id: None },
+            Request::Permissions {
+                app_id: Some("com.obsproject.Studio".into()),
+            },
+            Request::SetPermission {
+                app_id: "com.obsproject.Studio".into(),
+                operation: Operation::Screenshot,
+                decision: Decision::Allow,
+            },
+            Request::ClearPermission {
+                app_id: "org.example.App".into(),
+                operation: Some(Operation::SaveFiles),
+            },
+            Request::ClearPermission {
+                app_id: "org.example.App".into(),
+                operation: None,
+            },
+        ]
+    }
+
+    /// Every response variant, with and without optional fields
+    fn sample_responses() -> Vec<Response> {
+        let session = SessionInfo {
+            id: "sess-1".into(),
+            portal: "file-chooser".into(),
+            operation: "open-file".into(),
+            title: Some("Pick a file".into()),
+            created: 1700000000,
+            dir: "/tmp/portty/1000/sess-1".into(),
+            app_id: Some("org.example.App".into()),
+            mode: Some(SessionMode::Tabs),
+        };
+        let bare = SessionInfo {
+            id: "sess-2".into(),
+            title: None,
+            app_id: None,
+            mode: None,
+            ..session.clone()
+        };
+        let request = RequestInfo {
+            handle: "/org/freedesktop/portal/desktop/request/1_42/t".into(),
+            operation: Operation::SaveFile,
+            state: RequestState::Session,
+            received: 1700000000,
+            session_id: Some("sess-1".into()),
+            app_id: None,
+        };
+        vec![
+            Response::Ok,
+            Response::Error("Failed to reset: denied".into()),
+            Response::Error(ServerError::new(ErrorCode::NoSession, NO_SESSION)),
+            Response::Sessions(vec![session, bare]),
+            Response::Status(DaemonStatus {
+                pid: 42,
+                version: "0.3.3".into(),
+                sessions: 2,
+                id_collisions: 1,
+                protocol: Some(PROTOCOL_VERSION),
+                build: Some("release; portals: file-chooser".into()),
+                portals: vec!["file-chooser".into(), "my-portal".into()],
+            }),
+            Response::Permissions(vec![PermissionInfo {
+                app_id: "org.example.App".into(),
+                operation: Operation::Screenshot,
+                decision: Decision::Allow,
+            }]),
+            Response::Requests(vec![request]),
+            Response::Selection(vec!["file:///tmp/a.txt".into(), "/tmp/b c.txt".into()]),
+        ]
+    }
+
+    /// The encodings of [`sample_requests`] and [`sample_responses`] in the
+    /// format of `snapshots/protocol-v<N>.txt`: `## request` precedes one
+    /// request per line, `## response` each response
+    fn snapshot() -> String {
+        let mut out = format!(
+            "# portty control protocol v{PROTOCOL_VERSION}\n\
+             # Recorded by `PORTTY_BLESS=1 cargo test -p portty-client`. Do not edit.\n\
+             ## request\n"
+        );
+        for request in sample_requests() {
+            out.push_str(&request.encode());
+        }
+        for response in sample_responses() {
+            out.push_str("## response\n");
+            out.push_str(&response.encode());
+        }
+        out
+    }
+
+    fn snapshot_dir() -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots")
+    }
+
+    /// The gate for mixed CLI and daemon versions: an encoding may only
+    /// change together with [`PROTOCOL_VERSION`]. `PORTTY_BLESS=1` records
+    /// the snapshot of a new version but never rewrites an existing one.
+    #[test]
+    fn wire_format_matches_snapshot() {
+        let path = snapshot_dir().join(format!("protocol-v{PROTOCOL_VERSION}.txt"));
+        let current = snapshot();
+        if !path.exists() && std::env::var_os("PORTTY_BLESS").is_some() {
+            std::fs::create_dir_all(snapshot_dir()).unwrap();
+            std::fs::write(&path, &current).unwrap();
+        }
+        let recorded = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+            panic!(
+                "no snapshot of protocol v{PROTOCOL_VERSION}; record it with \
+                 PORTTY_BLESS=1 cargo test -p portty-client"
+            )
+        });
+        if let Some((line, (was, is))) = recorded
+            .lines()
+            .zip(current.lines())
+            .enumerate()
+            .find(|(_, (was, is))| was != is)
+        {
+            panic!(
+                "the wire format changed at line {} of {}:\n  recorded: {was}\n  now:      {is}\n\
+                 bump PROTOCOL_VERSION and record the new snapshot with \
+                 PORTTY_BLESS=1 cargo test -p portty-client",
+                line + 1,
+                path.display()
+            );
+        }
+        assert_eq!(
+            recorded.lines().count(),
+            current.lines().count(),
+            "requests or responses were added or removed: bump PROTOCOL_VERSION and record the \
+             new snapshot with PORTTY_BLESS=1 cargo test -p portty-client"
+        );
+    }
+
+    /// Clients and daemons of every recorded version can still talk to this
+    /// one: their requests and responses decode. A deliberately incompatible
+    /// version removes the snapshots it no longer reads.
+    #[test]
+    fn recorded_protocol_versions_still_decode() {
+        let snapshots = std::fs::read_dir(snapshot_dir()).unwrap();
+        for file in snapshots {
+            let path = file.unwrap().path();
+            let content = std::fs::read_to_string(&path).unwrap();
+            let mut sections = content.split("## ").skip(1);
+            let requests = sections.next().unwrap().strip_prefix("request\n").unwrap();
+            for line in requests.lines() {
+                Request::decode(line)
+                    .unwrap_or_else(|e| panic!("{}: request {line:?}: {e}", path.display()));
+            }
+            for section in sections {
+                let response = section.strip_prefix("response\n").unwrap();
+                let mut reader = std::io::Cursor::new(response.as_bytes());
+                crate::codec::read_response(&mut reader)
+                    .unwrap_or_else(|e| panic!("{}: response {response:?}: {e}", path.display()));
+            }
+        }
+    }
+
+    #[test]
+    fn request_encode_decode_roundtrip() {
+        for req in sample_requests() {
+            let encoded = req.encode();
+            assert!(encoded.ends_with('\n'));
+            let decoded = Request::decode(&encoded).unwrap();
+            assert_eq!(decoded, req);
+        }
+    }
+
+    #[test]
+    fn response_ok_encode() {
+        assert_eq!(Response::Ok.encode(), "ok\n");
+    }
+
+    #[test]
+    fn response_error_encode() {
+        assert_eq!(
+            Response::Error("bad thing".into()).encode(),
+            "error: bad thing\n"
+        );
+        let coded = ServerError::new(ErrorCode::InvalidSubmission, "Invalid submission: no");
+        assert_eq!(
+            Response::Error(coded.clone()).encode(),
+            "error: Invalid submission: no\tinvalid-submission\n"
+        );
+        assert_eq!(
+            ServerError::decode_fields("Invalid submission: no\tinvalid-submission"),
+            coded
+        );
+        // Codes from a newer daemon are plain failures
+        assert_eq!(
+            ServerError::decode_fields("Overheated\ttoo-hot"),
+            ServerError::new(ErrorCode::Failed, "Overheated")
+        );
+    }
+
+    #[test]
+    fn response_sessions_roundtrip() {
+        let info = SessionInfo {
+            id: "sess-1".into(),
+            portal: "file-chooser".into(),
+            operation: "open-file".into(),
+            title: Some("Pick a file".into()),
+            created: 1234567890,
+            dir: "/tmp/test".into(),
+            app_id: None,
+            mode: Some(SessionMode::Tabs),
+        };
+        let resp = Response::Sessions(vec![info.clone()]);
+        let encoded = resp.encode();
+
+        // Should have session line + ok
+        let lines: Vec<&str> = encoded.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], "ok");
+
+        let decoded = SessionInfo::decode_line(lines[0]).unwrap();
+        assert_eq!(decoded, info);
+    }
+
+    #[test]
+    fn session_info_no_title() {
+        let info = SessionInfo {
+            id: "s1".into(),
+            portal: "screenshot".into(),
+            operation: "screenshot".into(),
+            title: None,
+            created: 999,
+            dir: "/tmp/x".into(),
+            app_id: None,
+            mode: None,
+        };
+        let resp = Response::Sessions(vec![info.clone()]);
+        let encoded = resp.encode();
+        let lines: Vec<&str> = encoded.lines().collect();
+
+        let decoded = SessionInfo::decode_line(lines[0]).unwrap();
+        assert_eq!(decoded, info);
+    }
+
+    #[test]
+    fn response_status_encode() {
+        let resp = Response::Status(DaemonStatus {
+            pid: 42,
+            version: "0.3.3".into(),
+            sessions: 2,
+            id_collisions: 1,
+            protocol: Some(2),
+            build: Some("release".into()),
+            portals: vec!["file-chooser".into(), "screenshot".into()],
+        });
+        assert_eq!(
+            resp.encode(),
+            "status\t42\t0.3.3\t2\t1\t2\trelease\tfile-chooser,screenshot\nok\n"
+        );
+
+        // Older daemons send three fields
+        let old = DaemonStatus::decode_fields("42\t0.3.3\t2").unwrap();
+        assert_eq!(old.id_collisions, 0);
+        assert_eq!(old.protocol, None);
+        assert_eq!(old.build, None);
+        assert!(old.portals.is_empty());
+    }
+
+    #[test]
+    fn decode_unknown_command() {
+        assert!(Request::decode("foobar").is_err());
+    }
+
+    #[test]
+    fn response_permissions_roundtrip() {
+        let info = PermissionInfo {
+            app_id: "org.example.App".into(),
+            operation: Operation::Start,
+            decision: Decision::Deny,
+        };
+        let encoded = Response::Permissions(vec![info.clone()]).encode();
+        assert_eq!(
+            encoded,
+            "permission\torg.example.App\tremote-desktop\tstart\tdeny\nok\n"
+        );
+        let fields = encoded.lines().next().unwrap();
+        let decoded = PermissionInfo::decode_fields(fields.strip_prefix("permission\t").unwrap());
+        assert_eq!(decoded, Ok(info));
+    }
+
+    #[test]
+    fn response_requests_roundtrip() {
+        let pending = RequestInfo {
+            handle: "/org/freedesktop/portal/desktop/request/1_42/t".into(),
+            operation: Operation::SaveFile,
+            state: RequestState::Received,
+            received: 1700000000,
+            session_id: None,
+            app_id: Some("org.example.App".into()),
+        };
+        let open = RequestInfo {
+            state: RequestState::Session,
+            session_id: Some("s1".into()),
+            app_id: None,
+            ..pending.clone()
+        };
+        let encoded = Response::Requests(vec![pending.clone(), open.clone()]).encode();
+        let lines: Vec<&str> = encoded.lines().collect();
+        assert_eq!(
+            lines[0],
+            "request\t/org/freedesktop/portal/desktop/request/1_42/t\tfile-chooser\tsave-file\treceived\t1700000000\t\torg.example.App"
+        );
+        assert_eq!(lines[2], "ok");
+        for (line, info) in lines.iter().zip([pending, open]) {
+            let fields = line.strip_prefix("request\t").unwrap();
+            assert_eq!(RequestInfo::decode_fields(fields), Ok(info));
+        }
+    }
+
+    #[test]
+    fn response_selection_encode() {
+        let resp = Response::Selection(vec![
+            "file:///tmp/a.txt".into(),
+            "file:///tmp/tab\tname".into(),
+        ]);
+        assert_eq!(
+            resp.encode(),
+            "entry\tfile:///tmp/a.txt\nentry\tfile:///tmp/tab name\nok\n"
+        );
+    }
+
+    #[test]
+    fn decode_permission_commands() {
+        assert!(Request::decode("set-permission org.example.App screenshot screenshot").is_err());
+        assert!(Request::decode("set-permission org.example.App screenshot start allow").is_err());
+        assert!(
+            Request::decode("set-permission org.example.App screenshot pick-color maybe").is_err()
+        );
+        assert!(Request::decode("clear-permission").is_err());
+        assert!(Request::decode("clear-permission org.example.App screenshot").is_err());
+    }
+
+    #[test]
+    fn check_input_refuses_nul_and_long_ids() {
+        let verify = |id: &str| Request::Verify {
+            session_id: Some(id.into()),
+        };
+        assert_eq!(verify("s1").check_input(16), Ok(()));
+        assert!(verify("s\0x").check_input(16).is_err());
+        assert!(verify(&"s".repeat(17)).check_input(16).is_err());
+
+        let submit = Request::decode("submit --portal=file-chooser\0x").unwrap();
+        assert_eq!(
+            submit.check_input(64).unwrap_err().to_string(),
+            "portal contains a NUL byte"
+        );
+    }
+
+    #[test]
+    fn decode_submit_flags() {
+        assert_eq!(
+            Request::decode("submit --allow-empty s1").unwrap(),
+            Request::Submit {
+                session_id: Some("s1".into()),
+                options: SubmitOptions {
+                    allow_empty: true,
+                    ..Default::default()
+                },
+            }
+        );
+        assert_eq!(
+            Request::decode("submit --queue-policy=merge").unwrap(),
+            Request::Submit {
+                session_id: None,
+                options: SubmitOptions {
+                    queue_policy: Some(QueuePolicy::Merge),
+                    ..Default::default()
+                },
+            }
+        );
+        assert_eq!(
+            Request::decode("submit --confirm --allow-empty").unwrap(),
+            Request::Submit {
+                session_id: None,
+                options: SubmitOptions {
+                    allow_empty: true,
+                    confirm: true,
+                    ..Default::default()
+                },
+            }
+        );
+        assert_eq!(
+            Request::decode("submit --portal=file-chooser").unwrap(),
+            Request::Submit {
+                session_id: None,
+                options: SubmitOptions {
+                    portal: Some("file-chooser".into()),
+                    ..Default::default()
+                },
+            }
+        );
+        assert_eq!(
+            Request::decode("submit --prefer=session").unwrap(),
+            Request::Submit {
+                session_id: None,
+                options: SubmitOptions {
+                    prefer: Some(QueueConflict::Session),
+                    ..Default::default()
+                },
+            }
+        );
+        assert!(Request::decode("submit --bogus").is_err());
+        assert!(Request::decode("submit --portal=").is_err());
+        assert!(Request::decode("submit --queue-policy=never").is_err());
+        assert!(Request::decode("submit --prefer=both").is_err());
+        assert!(Request::decode("submit --allow-empty=yes").is_err());
+    }
+
+    #[test]
+    fn decode_session_info_too_few_fields() {
+        assert!(SessionInfo::decode_line("a\tb\tc").is_err());
+    }
+
+    #[test]
+    fn decode_session_info_without_app_id() {
+        // Lines from daemons predating the app_id field
+        let info =
+            SessionInfo::decode_line("s1\tscreenshot\tscreenshot\t1\t/tmp/s1\tTitle").unwrap();
+        assert_eq!(info.title.as_deref(), Some("Title"));
+        assert_eq!(info.app_id, None);
+        assert_eq!(info.mode, None);
+
+        // Lines from newer daemons, with a mode or fields this one lacks
+        let line = "s1\tscreenshot\tscreenshot\t1\t/tmp/s1\t\t\tinline\tlater";
+        let info = SessionInfo::decode_line(line).unwrap();
+        assert_eq!(info.mode, Some(SessionMode::Inline));
+        let line = "s1\tscreenshot\tscreenshot\t1\t/tmp/s1\t\t\tkiosk";
+        assert_eq!(SessionInfo::decode_line(line).unwrap().mode, None);
+    }
+
+    #[test]
+    fn sanitize_title_with_tabs_and_newlines() {
+        let info = SessionInfo {
+            id: "s1".into(),
+            portal: "file-chooser".into(),
+            operation: "open-file".into(),
+            title: Some("evil\ttitle\nhere".into()),
+            created: 100,
+            dir: "/tmp/x".into(),
+            app_id: None,
+            mode: None,
+        };
+        let resp = Response::Sessions(vec![info]);
+        let encoded = resp.encode();
+        // Should produce exactly 2 lines: one session + ok
+        let lines: Vec<&str> = encoded.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], "ok");
+        // Tabs/newlines in title should be replaced with spaces
+        let decoded = SessionInfo::decode_line(lines[0]).unwrap();
+        assert_eq!(decoded.title.as_deref(), Some("evil title here"));
+    }
+
+    #[test]
+    fn sanitize_error_with_newline() {
+        let resp = Response::Error("line1\nline2".into());
+        let encoded = resp.encode();
+        // Should be a single line
+        assert_eq!(encoded.lines().count(), 1);
+        assert_eq!(encoded, "error: line1 line2\n");
+    }
+}
@@ -0,0 +1,413 @@
+//! Authenticated, encrypted transport for the control protocol
+//!
+//! `daemon.sock` is reachable only by its user. To expose the control
+//! endpoint further (a TCP port, an SSH-forwarded socket, ...), both ends of
+//! the transport are wrapped in a [`SecureStream`], which speaks the
+//! `Noise_IK_25519_ChaChaPoly_BLAKE2s` handshake:
+//!
+//! - the connecting side knows the endpoint's public key up front, like an
+//!   SSH known host, and fails against any other endpoint
+//! - the endpoint learns the client's key in the first message and accepts
+//!   only authorized keys, like SSH's `authorized_keys`
+//! - every connection derives its own session keys from fresh ephemeral
+//!   keys, so a recorded connection stays unreadable if a key leaks later
+//!
+//! After the handshake the stream carries the protocol as usual, so the
+//! [`codec`](crate::codec) functions work over it. A relay exposing the
+//! daemon on TCP:
+//!
+//! ```no_run
+//! use std::io::BufReader;
+//! use std::net::TcpListener;
+//!
+//! use portty_client::secure::{Keypair, PublicKey, SecureStream};
+//! use portty_client::{DaemonClient, codec};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let keypair = Keypair::generate()?;
+//! let authorized: Vec<PublicKey> = vec!["<client key in hex>".parse()?];
+//! for tcp in TcpListener::bind("0.0.0.0:7290")?.incoming() {
+//!     let mut remote = BufReader::new(SecureStream::accept(tcp?, &keypair, &authorized)?);
+//!     let req = codec::read_request(&mut remote)?;
+//!     let resp = DaemonClient::new().send(&req)?;
+//!     codec::write_response(remote.get_mut(), &resp)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! On the wire every message is a 2-byte big-endian length and a Noise
+//! message of at most [`MAX_FRAME`] bytes.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use snow::resolvers::{CryptoResolver, DefaultResolver};
+use snow::{Builder, HandshakeState, TransportState};
+
+/// Noise protocol of the handshake
+const PATTERN: &str = "Noise_IK_25519_ChaChaPoly_BLAKE2s";
+
+/// Largest Noise message on the wire
+pub const MAX_FRAME: usize = 65535;
+
+/// Authentication tag added to every encrypted message
+const TAG_LEN: usize = 16;
+
+/// Errors setting up a secure stream
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum SecureError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("handshake failed: {0}")]
+    Handshake(String),
+    #[error("key {0} is not authorized")]
+    Unauthorized(PublicKey),
+}
+
+impl From<snow::Error> for SecureError {
+    fn from(e: snow::Error) -> Self {
+        Self::Handshake(e.to_string())
+    }
+}
+
+/// A Curve25519 public key, written as 64 hex digits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PublicKey(pub [u8; 32]);
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        decode_key(s).map(Self)
+    }
+}
+
+/// A Curve25519 key pair identifying one end of a secure stream
+#[derive(Clone)]
+pub struct Keypair {
+    public: PublicKey,
+    private: [u8; 32],
+}
+
+impl Keypair {
+    /// A new random key pair
+    pub fn generate() -> Result<Self, SecureError> {
+        let keypair = Builder::new(pattern()).generate_keypair()?;
+        let private = keypair
+            .private
+            .try_into()
+            .map_err(|_| SecureError::Handshake("bad private key length".to_string()))?;
+        Self::from_private(private)
+    }
+
+    /// The key pair of a private key
+    pub fn from_private(private: [u8; 32]) -> Result<Self, SecureError> {
+        let mut dh = DefaultResolver
+            .resolve_dh(&pattern().dh)
+            .ok_or_else(|| SecureError::Handshake("no Curve25519 support".to_string()))?;
+        dh.set(&private);
+        let public = dh
+            .pubkey()
+            .try_into()
+            .map_err(|_| SecureError::Handshake("bad public key length".to_string()))?;
+        Ok(Self {
+            public: PublicKey(public),
+            private,
+        })
+    }
+
+    pub fn public(&self) -> PublicKey {
+        self.public
+    }
+
+    /// The private key, to store the key pair
+    pub fn private(&self) -> [u8; 32] {
+        self.private
+    }
+
+    /// The private key as 64 hex digits
+    pub fn private_hex(&self) -> String {
+        PublicKey(self.private).to_string()
+    }
+
+    /// The key pair of a private key written as 64 hex digits
+    pub fn from_private_hex(s: &str) -> Result<Self, SecureError> {
+        Self::from_private(decode_key(s).map_err(SecureError::Handshake)?)
+    }
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair")
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+fn pattern() -> snow::params::NoiseParams {
+    PATTERN.parse().expect("valid Noise pattern")
+}
+
+fn decode_key(s: &str) -> Result<[u8; 32], String> {
+    let s = s.trim();
+    if s.len() != 64 || !s.is_ascii() {
+        return Err(format!("expected 64 hex digits, got {s:?}"));
+    }
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16)
+            .map_err(|_| format!("expected 64 hex digits, got {s:?}"))?;
+    }
+    Ok(key)
+}
+
+/// A transport carrying encrypted, authenticated frames after a Noise
+/// handshake
+pub struct SecureStream<S> {
+    inner: S,
+    noise: TransportState,
+    peer: PublicKey,
+    /// Decrypted bytes not read yet
+    incoming: Vec<u8>,
+    pos: usize,
+}
+
+impl<S: Read + Write> SecureStream<S> {
+    /// Connect to the endpoint whose public key is `remote`
+    pub fn connect(mut inner: S, local: &Keypair, remote: &PublicKey) -> Result<Self, SecureError> {
+        let mut handshake = Builder::new(pattern())
+            .local_private_key(&local.private)
+            .remote_public_key(&remote.0)
+            .build_initiator()?;
+        let mut buf = vec![0; MAX_FRAME];
+        let len = handshake.write_message(&[], &mut buf)?;
+        write_frame(&mut inner, &buf[..len])?;
+        let reply = read_frame(&mut inner)?.ok_or_else(closed)?;
+        handshake.read_message(&reply, &mut buf)?;
+        Self::established(inner, handshake)
+    }
+
+    /// Accept a connection from a client whose key is in `authorized`
+    pub fn accept(
+        mut inner: S,
+        local: &Keypair,
+        authorized: &[PublicKey],
+    ) -> Result<Self, SecureError> {
+        let mut handshake = Builder::new(pattern())
+            .local_private_key(&local.private)
+            .build_responder()?;
+        let mut buf = vec![0; MAX_FRAME];
+        let hello = read_frame(&mut inner)?.ok_or_else(closed)?;
+        handshake.read_message(&hello, &mut buf)?;
+        let peer = remote_static(&handshake)?;
+        if !authorized.contains(&peer) {
+            return Err(SecureError::Unauthorized(peer));
+        }
+        let len = handshake.write_message(&[], &mut buf)?;
+        write_frame(&mut inner, &buf[..len])?;
+        Self::established(inner, handshake)
+    }
+
+    fn established(inner: S, handshake: HandshakeState) -> Result<Self, SecureError> {
+        let peer = remote_static(&handshake)?;
+        Ok(Self {
+            inner,
+            noise: handshake.into_transport_mode()?,
+            peer,
+            incoming: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    /// The authenticated key of the other end
+    pub fn peer(&self) -> PublicKey {
+        self.peer
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+fn remote_static(handshake: &HandshakeState) -> Result<PublicKey, SecureError> {
+    handshake
+        .get_remote_static()
+        .and_then(|key| key.try_into().ok())
+        .map(PublicKey)
+        .ok_or_else(|| SecureError::Handshake("peer sent no static key".to_string()))
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")
+}
+
+fn invalid(e: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn write_frame(writer: &mut impl Write, frame: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(frame.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(frame)?;
+    writer.flush()
+}
+
+/// Read one frame; `None` when the stream ends between frames
+fn read_frame(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 2];
+    match reader.read(&mut len[..1])? {
+        0 => return Ok(None),
+        _ => reader.read_exact(&mut len[1..])?,
+    }
+    let mut frame = vec![0; usize::from(u16::from_be_bytes(len))];
+    reader.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+impl<S: Read + Write> Read for SecureStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.incoming.len() {
+            let Some(frame) = read_frame(&mut self.inner)? else {
+                return Ok(0);
+            };
+            self.incoming.resize(frame.len(), 0);
+            let len = self
+                .noise
+                .read_message(&frame, &mut self.incoming)
+                .map_err(invalid)?;
+            self.incoming.truncate(len);
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.incoming.len() - self.pos);
+        buf[..n].copy_from_slice(&self.incoming[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<S: Read + Write> Write for SecureStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(MAX_FRAME - TAG_LEN);
+        let mut frame = vec![0; n + TAG_LEN];
+        let len = self
+            .noise
+            .write_message(&buf[..n], &mut frame)
+            .map_err(invalid)?;
+        write_frame(&mut self.inner, &frame[..len])?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::protocol::{Request, Response};
+    use std::io::BufReader;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    fn serve(
+        server: Keypair,
+        authorized: Vec<PublicKey>,
+    ) -> (
+        UnixStream,
+        thread::JoinHandle<Result<PublicKey, SecureError>>,
+    ) {
+        let (client, endpoint) = UnixStream::pair().unwrap();
+        let handle = thread::spawn(move || {
+            let stream = SecureStream::accept(endpoint, &server, &authorized)?;
+            let peer = stream.peer();
+            let mut reader = BufReader::new(stream);
+            let req = codec::read_request(&mut reader).map_err(io::Error::other)?;
+            assert_eq!(req, Request::List);
+            codec::write_response(reader.get_mut(), &Response::Ok).map_err(io::Error::other)?;
+            Ok(peer)
+        });
+        (client, handle)
+    }
+
+    #[test]
+    fn authorized_client_talks_protocol() {
+        let (server, client) = (Keypair::generate().unwrap(), Keypair::generate().unwrap());
+        let (stream, handle) = serve(server.clone(), vec![client.public()]);
+
+        let stream = SecureStream::connect(stream, &client, &server.public()).unwrap();
+        assert_eq!(stream.peer(), server.public());
+        let mut reader = BufReader::new(stream);
+        codec::write_request(reader.get_mut(), &Request::List).unwrap();
+        assert_eq!(codec::read_response(&mut reader).unwrap(), Response::Ok);
+        assert_eq!(handle.join().unwrap().unwrap(), client.public());
+    }
+
+    #[test]
+    fn unauthorized_client_is_refused() {
+        let (server, client) = (Keypair::generate().unwrap(), Keypair::generate().unwrap());
+        let other = Keypair::generate().unwrap();
+        let (stream, handle) = serve(server.clone(), vec![other.public()]);
+
+        assert!(SecureStream::connect(stream, &client, &server.public()).is_err());
+        assert!(matches!(
+            handle.join().unwrap(),
+            Err(SecureError::Unauthorized(key)) if key == client.public()
+        ));
+    }
+
+    #[test]
+    fn impostor_endpoint_is_refused() {
+        let (server, client) = (Keypair::generate().unwrap(), Keypair::generate().unwrap());
+        let expected = Keypair::generate().unwrap();
+        let (stream, handle) = serve(server, vec![client.public()]);
+
+        // The endpoint cannot read a handshake meant for another key
+        assert!(SecureStream::connect(stream, &client, &expected.public()).is_err());
+        assert!(matches!(
+            handle.join().unwrap(),
+            Err(SecureError::Handshake(_))
+        ));
+    }
+
+    #[test]
+    fn large_writes_span_frames() {
+        let (server, client) = (Keypair::generate().unwrap(), Keypair::generate().unwrap());
+        let (a, b) = UnixStream::pair().unwrap();
+        let (server_public, client_public) = (server.public(), client.public());
+        let handle = thread::spawn(move || {
+            let mut stream = SecureStream::accept(b, &server, &[client_public]).unwrap();
+            let mut data = Vec::new();
+            stream.read_to_end(&mut data).unwrap();
+            data
+        });
+
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let mut stream = SecureStream::connect(a, &client, &server_public).unwrap();
+        stream.write_all(&data).unwrap();
+        drop(stream);
+        assert_eq!(handle.join().unwrap(), data);
+    }
+
+    #[test]
+    fn keys_roundtrip_as_hex() {
+        let keypair = Keypair::generate().unwrap();
+        let public: PublicKey = keypair.public().to_string().parse().unwrap();
+        assert_eq!(public, keypair.public());
+        let restored = Keypair::from_private_hex(&keypair.private_hex()).unwrap();
+        assert_eq!(restored.public(), keypair.public());
+        assert!("abcd".parse::<PublicKey>().is_err());
+        assert!("zz".repeat(32).parse::<PublicKey>().is_err());
+    }
+}
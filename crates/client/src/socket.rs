@@ -0,0 +1,162 @@
+//! Addresses the daemon listens on
+//!
+//! porttyd always listens on `daemon.sock` in the base directory (see
+//! [`paths::daemon_socket_path`]). The `listen` config key adds addresses
+//! for environments that share the runtime dir or the network namespace but
+//! not `/tmp`, such as toolbox and distrobox containers: another socket
+//! file, or `@NAME`, a Linux abstract socket. Clients try
+//! [`daemon_addresses`] in order and use the first that answers.
+
+use std::fmt;
+use std::io;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::paths;
+
+/// Longest abstract socket name: `sun_path` less its leading NUL
+const MAX_ABSTRACT_NAME: usize = 107;
+
+/// Extra addresses from the config (`listen`), see [`configure_listen`]
+static CONFIGURED: OnceLock<Vec<SocketAddress>> = OnceLock::new();
+
+/// Where a daemon socket lives
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SocketAddress {
+    /// A socket file, by absolute path
+    Path(PathBuf),
+    /// A Linux abstract socket, written `@NAME`. It has no file and no
+    /// permissions, so porttyd only serves its own uid on it.
+    Abstract(String),
+}
+
+impl SocketAddress {
+    fn to_std(&self) -> io::Result<std::os::unix::net::SocketAddr> {
+        match self {
+            Self::Path(path) => std::os::unix::net::SocketAddr::from_pathname(path),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::Abstract(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                std::os::unix::net::SocketAddr::from_abstract_name(name)
+            }
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Self::Abstract(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets are Linux-only",
+            )),
+        }
+    }
+
+    /// Connect to a daemon listening here
+    pub fn connect(&self) -> io::Result<UnixStream> {
+        UnixStream::connect_addr(&self.to_std()?)
+    }
+
+    /// Listen here. A socket file is left to the caller to remove first.
+    pub fn bind(&self) -> io::Result<UnixListener> {
+        UnixListener::bind_addr(&self.to_std()?)
+    }
+}
+
+impl fmt::Display for SocketAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "{}", path.display()),
+            Self::Abstract(name) => write!(f, "@{name}"),
+        }
+    }
+}
+
+impl FromStr for SocketAddress {
+    type Err = String;
+
+    /// `@NAME` for an abstract socket, else an absolute path
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = s.strip_prefix('@') {
+            if name.is_empty() {
+                return Err("abstract socket name is empty".to_string());
+            }
+            if name.len() > MAX_ABSTRACT_NAME {
+                return Err(format!(
+                    "abstract socket name is longer than {MAX_ABSTRACT_NAME} bytes"
+                ));
+            }
+            return Ok(Self::Abstract(name.to_string()));
+        }
+        let path = PathBuf::from(s);
+        if !path.is_absolute() {
+            return Err(format!("{s} is neither @NAME nor an absolute path"));
+        }
+        Ok(Self::Path(path))
+    }
+}
+
+/// Listen on `addresses` too (the config's `listen`). Called once at
+/// startup; later calls are ignored.
+pub fn configure_listen(addresses: Vec<SocketAddress>) {
+    let _ = CONFIGURED.set(addresses);
+}
+
+/// The addresses porttyd listens on besides `daemon.sock`
+pub fn listen_addresses() -> &'static [SocketAddress] {
+    CONFIGURED.get().map_or(&[], Vec::as_slice)
+}
+
+/// Addresses to reach the daemon at, in the order clients try them:
+/// `PORTTY_SOCKET` when set, `daemon.sock` in the base directory, then the
+/// [`listen_addresses`]
+pub fn daemon_addresses() -> Vec<SocketAddress> {
+    let mut addresses: Vec<SocketAddress> = std::env::var("PORTTY_SOCKET")
+        .ok()
+        .and_then(|address| address.parse().ok())
+        .into_iter()
+        .collect();
+    addresses.push(SocketAddress::Path(paths::daemon_socket_path()));
+    for address in listen_addresses() {
+        if !addresses.contains(address) {
+            addresses.push(address.clone());
+        }
+    }
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_parse() {
+        assert_eq!(
+            "@portty-1000".parse(),
+            Ok(SocketAddress::Abstract("portty-1000".into()))
+        );
+        assert_eq!(
+            "/run/user/1000/portty.sock".parse(),
+            Ok(SocketAddress::Path("/run/user/1000/portty.sock".into()))
+        );
+        assert!("@".parse::<SocketAddress>().is_err());
+        assert!("portty.sock".parse::<SocketAddress>().is_err());
+        assert!(
+            format!("@{}", "x".repeat(108))
+                .parse::<SocketAddress>()
+                .is_err()
+        );
+        for address in ["@portty", "/tmp/portty.sock"] {
+            let parsed: SocketAddress = address.parse().unwrap();
+            assert_eq!(parsed.to_string(), address);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn abstract_sockets_connect() {
+        let address = SocketAddress::Abstract(format!("portty-test-{}", std::process::id()));
+        let listener = address.bind().unwrap();
+        address.connect().unwrap();
+        assert!(listener.accept().is_ok());
+        drop(listener);
+        assert!(address.connect().is_err());
+    }
+}
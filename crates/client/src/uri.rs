@@ -0,0 +1,213 @@
+//! `file://` URIs, spelled one way
+//!
+//! Entries reach a selection as paths and as URIs from many writers: shims,
+//! editors, file managers, the app's own options. The same file can be
+//! `/a b`, `file:///a b`, `file:///a%20b`, or `file://localhost/a%20b`.
+//! [`from_path`] writes the one spelling portty uses (RFC 3986 path
+//! characters, as GLib does), [`to_path`] reads any of them, and [`key`] is
+//! what entries are compared by, so a file selected in one spelling is
+//! deselected, deduplicated, and recognized in another.
+//!
+//! Paths are bytes, not text. Where only UTF-8 fits (submission lines,
+//! options.json, the queue), a path that is not UTF-8 is written as its
+//! URI, and a file name as a `file:` escape (see [`encode_os`]), so every
+//! byte survives.
+
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use crate::input;
+
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Bytes kept as they are in a path: unreserved characters, sub-delimiters,
+/// `:`, `@`, and `/`
+fn is_path_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@/".contains(&byte)
+}
+
+/// Append `bytes` to `out`, percent-encoding every byte outside the path
+/// characters
+fn percent_encode(bytes: &[u8], out: &mut String) {
+    for &byte in bytes {
+        if is_path_char(byte) {
+            out.push(byte as char);
+        } else {
+            out.push('%');
+            out.push(HEX[usize::from(byte >> 4)] as char);
+            out.push(HEX[usize::from(byte & 0xf)] as char);
+        }
+    }
+}
+
+/// `text` with percent-escapes decoded; characters that should have been
+/// escaped are taken as they are
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// The `file://` URI of an absolute `path`, percent-encoding every byte
+/// outside the path characters
+pub fn from_path(path: &Path) -> String {
+    let bytes = path.as_os_str().as_bytes();
+    let mut uri = String::with_capacity("file://".len() + bytes.len());
+    uri.push_str("file://");
+    percent_encode(bytes, &mut uri);
+    uri
+}
+
+/// The local path of a `file://` URI, `None` for other entries and for URIs
+/// naming another host. Percent-escapes are decoded; characters that should
+/// have been escaped are taken as they are.
+pub fn to_path(entry: &str) -> Option<PathBuf> {
+    let rest = entry.strip_prefix("file://")?;
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+    Some(PathBuf::from(OsString::from_vec(percent_decode(path))))
+}
+
+/// A path or file name as a string: as it is when it is UTF-8, else an
+/// absolute path as its URI and anything else as `file:` and the
+/// percent-encoded bytes. UTF-8 text starting with `file:` is escaped too,
+/// so [`decode_os`] gives back exactly `s`.
+pub fn encode_os(s: &OsStr) -> Cow<'_, str> {
+    if let Some(text) = s.to_str()
+        && !text.starts_with("file:")
+    {
+        return Cow::Borrowed(text);
+    }
+    if s.as_bytes().starts_with(b"/") {
+        return Cow::Owned(from_path(Path::new(s)));
+    }
+    let mut escaped = String::from("file:");
+    percent_encode(s.as_bytes(), &mut escaped);
+    Cow::Owned(escaped)
+}
+
+/// The path or file name [`encode_os`] wrote as `s`. A `file://` URI gives
+/// its path, so entries in either spelling decode.
+pub fn decode_os(s: &str) -> Cow<'_, OsStr> {
+    if let Some(path) = to_path(s) {
+        return Cow::Owned(path.into_os_string());
+    }
+    match s.strip_prefix("file:") {
+        Some(escaped) => Cow::Owned(OsString::from_vec(percent_decode(escaped))),
+        None => Cow::Borrowed(OsStr::new(s)),
+    }
+}
+
+/// `entry` with a `file://` URI respelled as [`from_path`] writes it; other
+/// entries are returned as they are
+pub fn normalize(entry: &str) -> Cow<'_, str> {
+    match to_path(entry).map(|path| from_path(&path)) {
+        Some(uri) if uri != entry => Cow::Owned(uri),
+        _ => Cow::Borrowed(entry),
+    }
+}
+
+/// What selection entries are compared by: the path of a `file://` URI or
+/// an absolute path, in NFC (see [`input::normalize_path`]); anything else
+/// (relative paths, colors, other URIs) as written
+pub fn key(entry: &str) -> Cow<'_, OsStr> {
+    let path = match to_path(entry) {
+        Some(path) => Cow::Owned(path.into_os_string()),
+        None if entry.starts_with('/') => Cow::Borrowed(OsStr::new(entry)),
+        None => return Cow::Borrowed(OsStr::new(entry)),
+    };
+    match path.to_str().map(input::normalize_path) {
+        Some(Cow::Owned(normalized)) => Cow::Owned(normalized.into()),
+        _ => path,
+    }
+}
+
+/// Whether `a` and `b` name the same entry (see [`key`])
+pub fn same_entry(a: &str, b: &str) -> bool {
+    a == b || key(a) == key(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spellings_of_one_file_agree() {
+        let uri = from_path(Path::new("/tmp/a b/naïve#1?.txt"));
+        assert_eq!(uri, "file:///tmp/a%20b/na%C3%AFve%231%3F.txt");
+        assert_eq!(to_path(&uri).unwrap(), Path::new("/tmp/a b/naïve#1?.txt"));
+
+        let spellings = [
+            "/tmp/a b/naïve#1?.txt",
+            "file:///tmp/a b/naïve#1?.txt",
+            "file:///tmp/a%20b/na%c3%afve%231%3f.txt",
+            "file://localhost/tmp/a%20b/na%C3%AFve%231%3F.txt",
+            // NFD
+            "file:///tmp/a%20b/nai%CC%88ve%231%3F.txt",
+        ];
+        for spelling in spellings {
+            assert!(same_entry(spelling, &uri), "{spelling}");
+            if spelling.starts_with("file://") {
+                assert_eq!(
+                    normalize(spelling),
+                    from_path(&to_path(spelling).unwrap()),
+                    "{spelling}"
+                );
+            }
+        }
+        assert_eq!(normalize(&uri), uri);
+        assert_eq!(normalize("#ff0000"), "#ff0000");
+    }
+
+    #[test]
+    fn non_local_entries_are_not_paths() {
+        assert_eq!(to_path("file://host/tmp/a"), None);
+        assert_eq!(to_path("https://example.org/a"), None);
+        assert_eq!(to_path("/tmp/a"), None);
+        assert_eq!(to_path("file:///100%"), Some(PathBuf::from("/100%")));
+        assert!(!same_entry("a.txt", "/a.txt"));
+        assert!(same_entry("a.txt", "a.txt"));
+    }
+
+    #[test]
+    fn non_utf8_paths_round_trip() {
+        let path = PathBuf::from(OsString::from_vec(b"/tmp/\xff".to_vec()));
+        assert_eq!(from_path(&path), "file:///tmp/%FF");
+        assert_eq!(to_path(&from_path(&path)), Some(path.clone()));
+
+        let name = OsString::from_vec(b"caf\xe9.txt".to_vec());
+        for s in [
+            path.as_os_str(),
+            &name,
+            OsStr::new("/tmp/a b"),
+            OsStr::new("a.txt"),
+            OsStr::new("file:a"),
+        ] {
+            assert_eq!(decode_os(&encode_os(s)), s, "{s:?}");
+        }
+        assert_eq!(encode_os(&name), "file:caf%E9.txt");
+        assert_eq!(encode_os(path.as_os_str()), "file:///tmp/%FF");
+        assert_eq!(encode_os(OsStr::new("/tmp/a b")), "/tmp/a b");
+    }
+}
@@ -0,0 +1,241 @@
+//! Attention cues and notices written to a session's terminal.
+//!
+//! porttyd does not own the terminal of a session: `exec` is usually a
+//! terminal emulator that allocates its own pty. The pty is found through the
+//! session's processes instead — any process whose environment carries the
+//! session's `PORTTY_SESSION` and whose stdio is a terminal device (the shell
+//! inside the emulator).
+//!
+//! A headless session has no terminal of its own. With `headless =
+//! "announce"` it is announced on the user's login terminals instead, found
+//! through utmp like `write(1)` finds them, skipping those with `mesg n`.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tracing::debug;
+
+use crate::config::Attention;
+
+/// How long reverse video stays on for [`Attention::Flash`]
+const FLASH_DURATION: Duration = Duration::from_millis(150);
+
+/// How long [`notice_when_ready`] waits for a new terminal
+const NOTICE_WAIT: Duration = Duration::from_secs(5);
+
+/// Cue the terminal of session `session_id`. Does nothing if no terminal is
+/// found, e.g. for headless sessions or `exec` commands without a tty.
+pub fn cue(session_id: &str, attention: Attention, message: &str) {
+    if attention == Attention::None {
+        return;
+    }
+    let Some(tty) = session_tty(session_id) else {
+        debug!(session_id, "No terminal found for attention cue");
+        return;
+    };
+    let Ok(mut out) = open_tty(&tty) else {
+        return;
+    };
+    debug!(session_id, tty = %tty.display(), ?attention, "Cueing session terminal");
+
+    // A dumb terminal (serial console, speech output) would print escape
+    // sequences as text
+    let attention = match attention {
+        Attention::Notify | Attention::Flash if dumb_terminal() => Attention::Bell,
+        attention => attention,
+    };
+    let _ = match attention {
+        Attention::None => Ok(()),
+        Attention::Bell => out.write_all(b"\x07"),
+        Attention::Notify => {
+            // OSC 9 payloads end at BEL/ST, so strip control characters
+            let message: String = message.chars().filter(|c| !c.is_control()).collect();
+            write!(out, "\x1b]9;{message}\x07")
+        }
+        Attention::Flash => {
+            // Restore normal video from a thread instead of blocking the caller
+            let _ = out.write_all(b"\x1b[?5h");
+            std::thread::spawn(move || {
+                std::thread::sleep(FLASH_DURATION);
+                let _ = out.write_all(b"\x1b[?5l");
+            });
+            Ok(())
+        }
+    };
+}
+
+/// Heading announcing a session on its terminal
+pub fn heading(portal: &str, operation: &str, title: Option<&str>) -> String {
+    title.map_or_else(
+        || format!("portty: {portal} {operation}"),
+        |title| format!("portty: {title}"),
+    )
+}
+
+/// Tell the terminal of an adopted standby session what it is for now: set
+/// the window title and print `heading` on a line of its own.
+pub fn announce(session_id: &str, heading: &str) {
+    let Some(tty) = session_tty(session_id) else {
+        debug!(session_id, "No terminal found to announce adoption");
+        return;
+    };
+    let Ok(mut out) = open_tty(&tty) else {
+        return;
+    };
+    let heading: String = heading.chars().filter(|c| !c.is_control()).collect();
+    let _ = if dumb_terminal() {
+        write!(out, "\r\n{heading}\r\n")
+    } else {
+        write!(out, "\x1b]2;{heading}\x07\r\n{heading}\r\n")
+    };
+}
+
+/// Open `tty` for writing, never blocking on a terminal that stopped reading
+/// (writes fail with `WouldBlock` instead, and cues are best-effort) nor
+/// making it porttyd's controlling terminal
+fn open_tty(tty: &Path) -> io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+        .open(tty)
+}
+
+/// porttyd runs with `TERM=dumb`, so session terminals get no escape
+/// sequences
+pub fn dumb_terminal() -> bool {
+    std::env::var("TERM").is_ok_and(|term| term == "dumb")
+}
+
+/// Print `lines` on the terminal of session `session_id`. Returns whether a
+/// terminal was found.
+pub fn notice(session_id: &str, lines: &[impl AsRef<str>]) -> bool {
+    let Some(tty) = session_tty(session_id) else {
+        debug!(session_id, "No terminal found for notice");
+        return false;
+    };
+    let Ok(mut out) = open_tty(&tty) else {
+        return false;
+    };
+    let mut text = String::from("\r\n");
+    for line in lines {
+        text.extend(line.as_ref().chars().filter(|c| !c.is_control()));
+        text.push_str("\r\n");
+    }
+    // A terminal that stopped reading still got what fit
+    match out.write_all(text.as_bytes()) {
+        Err(e) => e.kind() == io::ErrorKind::WouldBlock,
+        Ok(()) => true,
+    }
+}
+
+/// [`notice`] for a process that was just started: retries from a thread
+/// until its terminal shows up, for at most [`NOTICE_WAIT`].
+pub fn notice_when_ready(session_id: &str, lines: Vec<String>) {
+    let session_id = session_id.to_string();
+    std::thread::spawn(move || {
+        let deadline = std::time::Instant::now() + NOTICE_WAIT;
+        while !notice(&session_id, &lines) && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    });
+}
+
+/// Announce headless session `session_id` (`headless = "announce"`) on the
+/// user's login terminals, with the command joining it. Returns how many
+/// terminals got it.
+pub fn broadcast(session_id: &str, heading: &str) -> usize {
+    let heading: String = heading.chars().filter(|c| !c.is_control()).collect();
+    let text = format!(
+        "\r\n\x07{heading}: waiting for an answer, no terminal was started for it\r\n\
+         Join it from a shell: portty join {session_id}\r\n"
+    );
+    let mut sent = 0;
+    for tty in login_ttys() {
+        if let Ok(mut out) = open_tty(&tty)
+            && out.write_all(text.as_bytes()).is_ok()
+        {
+            debug!(session_id, tty = %tty.display(), "Announced headless session");
+            sent += 1;
+        }
+    }
+    sent
+}
+
+/// Terminals the user is logged in on and accepts messages on (`mesg y`),
+/// from utmp
+fn login_ttys() -> Vec<PathBuf> {
+    // The utmp cursor is process-wide state
+    static UTMP: Mutex<()> = Mutex::new(());
+    let _guard = UTMP.lock().unwrap_or_else(|e| e.into_inner());
+
+    let uid = libportty::paths::current_uid();
+    let mut ttys = Vec::new();
+    // SAFETY: the utmp functions are only called under UTMP, and each entry
+    // is read before the next getutxent call replaces it
+    unsafe {
+        libc::setutxent();
+        loop {
+            let entry = libc::getutxent();
+            if entry.is_null() {
+                break;
+            }
+            let entry = &*entry;
+            if entry.ut_type != libc::USER_PROCESS {
+                continue;
+            }
+            let line: Vec<u8> = entry
+                .ut_line
+                .iter()
+                .take_while(|&&c| c != 0)
+                .map(|&c| c as u8)
+                .collect();
+            if !line.is_empty() {
+                ttys.push(Path::new("/dev").join(OsStr::from_bytes(&line)));
+            }
+        }
+        libc::endutxent();
+    }
+
+    ttys.sort();
+    ttys.dedup();
+    ttys.retain(|tty| {
+        fs::metadata(tty).is_ok_and(|meta| {
+            meta.file_type().is_char_device() && meta.uid() == uid && meta.mode() & 0o020 != 0
+        })
+    });
+    ttys
+}
+
+/// Terminal device used by a process of the session
+fn session_tty(session_id: &str) -> Option<PathBuf> {
+    let marker = format!("PORTTY_SESSION={session_id}");
+    fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_str().is_some_and(is_pid))
+        .filter(|entry| {
+            fs::read(entry.path().join("environ")).is_ok_and(|environ| {
+                environ
+                    .split(|&b| b == 0)
+                    .any(|var| var == marker.as_bytes())
+            })
+        })
+        .find_map(|entry| {
+            (0..3)
+                .filter_map(|fd| fs::read_link(entry.path().join(format!("fd/{fd}"))).ok())
+                .find(|target| {
+                    target.starts_with("/dev/pts/")
+                        || target.to_str().is_some_and(|t| t.starts_with("/dev/tty"))
+                })
+        })
+}
+
+fn is_pid(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit())
+}
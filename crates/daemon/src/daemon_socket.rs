@@ -1,68 +1,145 @@
 //! Daemon control socket and FIFO for CLI communication
 //!
-//! Listens on /tmp/portty/<uid>/daemon.sock for CLI requests.
-//! Listens on /tmp/portty/<uid>/daemon.ctl for fire-and-forget commands.
+//! Listens on <base dir>/daemon.sock for CLI requests.
+//! Listens on <base dir>/daemon.ctl for fire-and-forget commands.
 //! Owns the session registry. Data operations (edit, clear) are file-based (CLI handles directly).
-//! This socket handles control commands: submit, cancel, verify, reset, list.
+//! This socket handles control commands: submit, cancel, verify, reset, focus,
+//! list, selection, plus daemon management: status, reload, refresh-shims,
+//! shutdown, and the per-app decisions in the permission store.
+//! Connections starting with `{` or `[` speak newline-delimited JSON-RPC 2.0.
 
 use std::collections::HashMap;
 use std::fs;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
 use std::os::unix::fs::PermissionsExt;
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 
-use libportty::codec::{read_request, write_response};
+use futures_lite::future;
+use libportty::codec::{
+    IpcError, read_line_limited, read_request_limited, write_response, write_selection_lines,
+};
+use libportty::jsonrpc::{self, RpcError};
+use libportty::portal::QueueDiff;
 use libportty::portal::intent::queue;
-use libportty::{Request, Response, SessionInfo};
+use libportty::portal::reset::{self, KeepPatterns};
+use libportty::protocol::{
+    ErrorCode, INVALID_SUBMISSION, NO_SESSION, PROTOCOL_VERSION, QUEUE_CONFLICT, ServerError,
+};
+use libportty::remote::Remote;
+use libportty::session_dir::{SessionDirs, ShimTemplates};
+use libportty::socket::{self, SocketAddress};
+use libportty::{
+    DaemonStatus, Decision, Operation, Portal, QueueConflict, Request, Response, SessionInfo,
+    SessionMode, SubmitOptions,
+};
 use libportty::{files, paths};
 use tracing::{debug, info, warn};
 
+use crate::attention;
+use crate::config::{Config, ConfigHandle};
+use crate::dbus::events::{EventEmitter, SessionOutcome};
+use crate::inflight::InFlightRequests;
+use crate::inline::InlineTty;
+use crate::permissions;
 use crate::portal;
-use crate::session::{Session, SessionControl, drain_pending_to};
+use crate::session::{Session, SessionControl, queue_conflict, record_event, settle_pending};
+use crate::shutdown::ShutdownHandle;
+use crate::tabs;
+use crate::umask;
 
 /// Registry of active portal sessions
 #[derive(Debug, Default)]
 pub struct SessionRegistry {
     sessions: HashMap<String, RegisteredSession>,
+    /// Idle sessions waiting to be adopted, by portal (behind a mutex only
+    /// to make the registry `Sync`; accessed through `&mut self`)
+    standby: Mutex<HashMap<String, Session>>,
+    dirs: Arc<SessionDirs>,
+    /// Terminal sessions run on in inline mode
+    inline: Option<Arc<InlineTty>>,
+    /// Session ids found taken on creation or registration (`portty daemon
+    /// status`)
+    id_collisions: u64,
+    /// Session lifecycle signals on the bus
+    events: EventEmitter,
 }
 
+/// Attempts at a fresh session id before giving up
+const MAX_ID_ATTEMPTS: u32 = 4;
+
 /// Metadata about a registered session
 pub struct RegisteredSession {
     pub id: String,
-    pub portal: String,
-    pub operation: String,
+    pub operation: Operation,
     pub title: Option<String>,
+    pub app_id: Option<String>,
     pub created: u64,
     pub dir: PathBuf,
     pub control: Arc<SessionControl>,
     pub initial_entries: Vec<String>,
+    /// How the session is shown, as far as the daemon decided it; a
+    /// terminal that failed to start is found by the `headless` file
+    pub mode: SessionMode,
 }
 
 impl std::fmt::Debug for RegisteredSession {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RegisteredSession")
             .field("id", &self.id)
-            .field("portal", &self.portal)
             .field("operation", &self.operation)
             .field("title", &self.title)
+            .field("app_id", &self.app_id)
             .field("created", &self.created)
             .field("dir", &self.dir)
+            .field("mode", &self.mode)
             .finish()
     }
 }
 
+impl RegisteredSession {
+    fn new(
+        session: &Session,
+        operation: Operation,
+        options: &serde_json::Value,
+        initial_entries: &[String],
+        title: Option<&str>,
+        mode: SessionMode,
+    ) -> Self {
+        Self {
+            id: session.id().to_string(),
+            operation,
+            title: title.map(String::from),
+            app_id: options
+                .get("app_id")
+                .and_then(serde_json::Value::as_str)
+                .filter(|id| !id.is_empty())
+                .map(String::from),
+            created: session.created(),
+            dir: session.dir().to_path_buf(),
+            control: Arc::new(session.control()),
+            initial_entries: initial_entries.to_vec(),
+            mode,
+        }
+    }
+}
+
 impl From<&RegisteredSession> for SessionInfo {
     fn from(s: &RegisteredSession) -> Self {
         SessionInfo {
             id: s.id.clone(),
-            portal: s.portal.clone(),
-            operation: s.operation.clone(),
+            portal: s.operation.portal().to_string(),
+            operation: s.operation.to_string(),
             title: s.title.clone(),
             created: s.created,
             dir: s.dir.to_string_lossy().into_owned(),
+            app_id: s.app_id.clone(),
+            mode: Some(match s.mode {
+                SessionMode::Terminal if s.dir.join("headless").exists() => SessionMode::Headless,
+                mode => mode,
+            }),
         }
     }
 }
@@ -73,79 +150,366 @@ impl SessionRegistry {
     /// Returns the session (for spawning + running) and the session ID.
     pub fn create_session(
         &mut self,
-        portal: &str,
-        operation: &str,
+        operation: Operation,
         options: &serde_json::Value,
         initial_entries: &[String],
-        custom_bins: &HashMap<String, String>,
+        custom_bins: Option<&HashMap<String, String>>,
         title: Option<&str>,
     ) -> std::io::Result<Session> {
-        let (sender, receiver) = std::sync::mpsc::channel();
-        let control = SessionControl::new(sender.clone());
+        let mut attempt = 1;
+        let session = loop {
+            match Session::new(
+                operation.portal().as_str(),
+                operation.as_str(),
+                options,
+                initial_entries,
+                custom_bins,
+                &self.dirs,
+                std::sync::mpsc::channel(),
+            ) {
+                // The session dir exists: another session got the same id
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    self.id_collisions += 1;
+                    warn!(
+                        attempt,
+                        "Session id already taken, retrying with a fresh one"
+                    );
+                    if attempt == MAX_ID_ATTEMPTS {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                }
+                result => break result?,
+            }
+        };
+        self.refill_dirs();
 
-        let session = Session::new(
-            portal,
+        let mode = if custom_bins.is_some() {
+            SessionMode::Terminal
+        } else {
+            SessionMode::Headless
+        };
+        self.register(RegisteredSession::new(
+            &session,
             operation,
             options,
             initial_entries,
+            title,
+            mode,
+        ))?;
+        Ok(session)
+    }
+
+    /// Take the standby session of `portal` if it runs `exec` and is still
+    /// alive. A dead one is dropped (and replaced by [`Self::ensure_standby`]
+    /// later).
+    pub fn take_standby(&mut self, portal: &str, exec: &[String]) -> Option<Session> {
+        let standby = self.standby.get_mut().unwrap_or_else(|e| e.into_inner());
+        let mut session = standby.remove(portal)?;
+        if session.standby_exec() != Some(exec) {
+            standby.insert(portal.to_string(), session);
+            return None;
+        }
+        if !session.is_running() {
+            info!(id = %session.id(), portal, "Standby session exited, not adopting");
+            return None;
+        }
+        Some(session)
+    }
+
+    /// Adopt a standby session for a request and register it.
+    pub fn adopt(
+        &mut self,
+        mut session: Session,
+        operation: Operation,
+        options: &serde_json::Value,
+        initial_entries: &[String],
+        custom_bins: &HashMap<String, String>,
+        title: Option<&str>,
+    ) -> std::io::Result<Session> {
+        if let Err(e) = session.adopt(
+            operation.as_str(),
+            options,
+            initial_entries,
             custom_bins,
-            sender,
-            receiver,
-        )?;
+            &self.dirs,
+        ) {
+            session.terminate();
+            return Err(e);
+        }
 
-        self.register(RegisteredSession {
-            id: session.id().to_string(),
-            portal: portal.to_string(),
-            operation: operation.to_string(),
-            title: title.map(String::from),
-            created: session.created(),
-            dir: session.dir().to_path_buf(),
-            control: Arc::new(control),
-            initial_entries: initial_entries.to_vec(),
+        let registered = RegisteredSession::new(
+            &session,
+            operation,
+            options,
+            initial_entries,
+            title,
+            SessionMode::Terminal,
+        );
+        info!(id = %registered.id, portal = %operation.portal(), %operation, "Adopting standby session");
+        let heading = attention::heading(operation.portal().as_str(), operation.as_str(), title);
+        if let Err(e) = self.register(registered) {
+            session.terminate();
+            return Err(e);
+        }
+        attention::announce(session.id().as_str(), &heading);
+        Ok(session)
+    }
+
+    /// Start a standby session for every portal with `standby` enabled that
+    /// has none yet.
+    pub fn ensure_standby(&mut self, config: &Config) {
+        // Inline sessions take turns on one terminal; an idle one would hold
+        // it. In tabs mode sessions open windows in the shared terminal.
+        let portals: &[Portal] = if self.inline.is_some() || config.tabs() {
+            &[]
+        } else {
+            &Portal::ALL
+        };
+        for portal in portals.iter().map(|portal| portal.as_str()) {
+            let standby = self.standby.get_mut().unwrap_or_else(|e| e.into_inner());
+            if standby.contains_key(portal) || !config.resolve_standby(portal, "") {
+                continue;
+            }
+            let Some(exec) = config.resolve_exec(portal, "") else {
+                warn!(portal, "standby is set but there is no exec command to run");
+                continue;
+            };
+            let bin = config.resolve_bin(portal, "");
+            let mut env = config.quota().env();
+            env.extend(config.resolve_remote(portal, "").map(Remote::env));
+            match Session::standby(
+                portal,
+                &exec,
+                &bin,
+                env,
+                (
+                    umask::for_commands(config.resolve_umask(portal, "")),
+                    config.resolve_priority(portal, ""),
+                    config.resolve_inherit_env(portal, "").cloned(),
+                ),
+                &self.dirs,
+                std::sync::mpsc::channel(),
+            ) {
+                Ok(session) => {
+                    info!(id = %session.id(), portal, ?exec, "Started standby session");
+                    standby.insert(portal.to_string(), session);
+                }
+                Err(e) => warn!(portal, "Failed to start standby session: {e}"),
+            }
+        }
+        self.refill_dirs();
+    }
+
+    /// Stop standby sessions that no longer match `config` (all of them
+    /// without one), then start missing ones.
+    pub fn reconcile_standby(&mut self, config: Option<&Config>) {
+        let standby = self.standby.get_mut().unwrap_or_else(|e| e.into_inner());
+        standby.retain(|portal, session| {
+            let keep = config.is_some_and(|config| {
+                config.resolve_standby(portal, "")
+                    && !config.tabs()
+                    && config.resolve_exec(portal, "").as_deref() == session.standby_exec()
+                    && umask::for_commands(config.resolve_umask(portal, "")) == session.umask()
+                    && config.resolve_priority(portal, "") == *session.priority()
+                    && config.resolve_inherit_env(portal, "") == session.inherit_env()
+                    && session.is_running()
+            });
+            if !keep {
+                info!(id = %session.id(), portal, "Stopping standby session");
+                session.terminate();
+            }
+            keep
         });
+        if let Some(config) = config {
+            self.ensure_standby(config);
+        }
+    }
 
-        Ok(session)
+    /// Relink the `bin/` of session `id`, or of every session with one, to
+    /// the shims `config` resolves for it. Headless sessions have no `bin/`
+    /// and are left alone; standby sessions get theirs relinked on adoption.
+    pub fn refresh_shims(
+        &self,
+        config: &Config,
+        id: Option<&str>,
+    ) -> Result<Vec<SessionInfo>, ServerError> {
+        let sessions: Vec<&RegisteredSession> = match id {
+            Some(id) => {
+                let session = self.get(id).ok_or_else(|| {
+                    ServerError::new(ErrorCode::NoSession, format!("{NO_SESSION} with id {id}"))
+                })?;
+                if session.dir.join("bin").symlink_metadata().is_err() {
+                    return Err(format!(
+                        "session {id} is headless and has no shims (`portty join` links them)"
+                    )
+                    .into());
+                }
+                vec![session]
+            }
+            None => self
+                .iter()
+                .filter(|s| s.dir.join("bin").symlink_metadata().is_ok())
+                .collect(),
+        };
+
+        let mut refreshed = Vec::new();
+        let mut failed = Vec::new();
+        for session in sessions {
+            let (portal, operation) = (session.operation.portal(), session.operation);
+            let bins = config.resolve_bin(portal.as_str(), operation.as_str());
+            match self.dirs.shims.link(&session.dir.join("bin"), &bins) {
+                Ok(()) => {
+                    info!(id = %session.id, %portal, %operation, "Refreshed session shims");
+                    refreshed.push(SessionInfo::from(session));
+                }
+                Err(e) => {
+                    warn!(id = %session.id, "Failed to refresh session shims: {e}");
+                    failed.push(format!("{}: {e}", session.id));
+                }
+            }
+        }
+        if failed.is_empty() {
+            Ok(refreshed)
+        } else {
+            Err(format!(
+                "Failed to refresh shims ({} refreshed): {}",
+                refreshed.len(),
+                failed.join("; ")
+            )
+            .into())
+        }
     }
 
-    fn register(&mut self, session: RegisteredSession) {
-        info!(id = %session.id, portal = %session.portal, operation = %session.operation, "Registering session");
+    /// Terminal of inline mode, if the daemon runs in it
+    pub fn inline(&self) -> Option<Arc<InlineTty>> {
+        self.inline.clone()
+    }
+
+    /// Top the session dir pool up again, off the request path.
+    pub fn refill_dirs(&self) {
+        let dirs = Arc::clone(&self.dirs);
+        blocking::unblock(move || {
+            if let Err(e) = dirs.pool.refill() {
+                warn!("Failed to refill session dir pool: {e}");
+            }
+        })
+        .detach();
+    }
+
+    /// Register a session. Fails if its id is already registered, which
+    /// would leave two sessions sharing one control handle.
+    fn register(&mut self, session: RegisteredSession) -> std::io::Result<()> {
+        if self.sessions.contains_key(&session.id) {
+            self.id_collisions += 1;
+            warn!(id = %session.id, "Refusing duplicate session registration");
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("session {} is already registered", session.id),
+            ));
+        }
+        info!(id = %session.id, portal = %session.operation.portal(), operation = %session.operation, "Registering session");
+        self.events.session_started(
+            &session.id,
+            session.operation.portal().as_str(),
+            session.operation.as_str(),
+            session.app_id.as_deref().unwrap_or_default(),
+        );
         self.sessions.insert(session.id.clone(), session);
+        Ok(())
     }
 
-    pub fn unregister(&mut self, id: &str) {
-        info!(id, "Unregistering session");
-        self.sessions.remove(id);
+    /// Unregister a session that ended with `outcome`.
+    pub fn unregister(&mut self, id: &str, outcome: SessionOutcome) {
+        info!(id, outcome = outcome.as_str(), "Unregistering session");
+        if self.sessions.remove(id).is_some() {
+            self.events.session_ended(id, outcome);
+        }
     }
 
     pub fn get(&self, id: &str) -> Option<&RegisteredSession> {
         self.sessions.get(id)
     }
 
+    /// Record that session `id` runs in the inline terminal or a tabs window
+    pub fn set_mode(&mut self, id: &str, mode: SessionMode) {
+        if let Some(session) = self.sessions.get_mut(id) {
+            session.mode = mode;
+        }
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &RegisteredSession> {
         self.sessions.values()
     }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Emitter of the session event signals
+    pub fn events(&self) -> &EventEmitter {
+        &self.events
+    }
+
+    /// Session ids found taken since the daemon started
+    pub fn id_collisions(&self) -> u64 {
+        self.id_collisions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
 }
 
 /// Shared daemon state
 #[derive(Default)]
 pub struct DaemonState {
     pub sessions: SessionRegistry,
+    /// Portal requests being handled, with a session or not
+    pub requests: InFlightRequests,
+    /// Set by the `Shutdown` request, or by the embedding process
+    pub shutdown: ShutdownHandle,
 }
 
 impl DaemonState {
-    pub fn new() -> Self {
-        Self::default()
+    /// `inline`: run sessions on this terminal (`porttyd --inline`); `cli`:
+    /// the portty CLI shims and `portty` commands run (see
+    /// [`Config::resolve_cli`](crate::config::Config::resolve_cli))
+    pub fn new(inline: Option<Arc<InlineTty>>, cli: Option<PathBuf>) -> Self {
+        if cli.is_none() {
+            warn!(
+                "portty CLI not found next to porttyd or in PATH (set `cli`); shims call `portty` from the session's PATH"
+            );
+        }
+        Self {
+            sessions: SessionRegistry {
+                inline,
+                dirs: Arc::new(SessionDirs {
+                    shims: ShimTemplates::default().with_cli(cli),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            requests: InFlightRequests::default(),
+            shutdown: ShutdownHandle::default(),
+        }
     }
 }
 
 /// Daemon control socket server
 pub struct DaemonSocket {
     state: Arc<RwLock<DaemonState>>,
+    config: Arc<ConfigHandle>,
     listener: UnixListener,
+    /// The `listen` addresses, which only serve this uid
+    extra: Vec<UnixListener>,
 }
 
 impl DaemonSocket {
-    pub fn new(state: Arc<RwLock<DaemonState>>) -> std::io::Result<Self> {
+    pub fn new(
+        state: Arc<RwLock<DaemonState>>,
+        config: Arc<ConfigHandle>,
+    ) -> std::io::Result<Self> {
         paths::ensure_base_dir()?;
 
         let sock_path = paths::daemon_socket_path();
@@ -154,37 +518,133 @@ impl DaemonSocket {
         let listener = UnixListener::bind(&sock_path)?;
         info!(?sock_path, "Daemon socket listening");
 
-        Ok(Self { state, listener })
+        let extra = socket::listen_addresses()
+            .iter()
+            .filter_map(|address| match bind_extra(address) {
+                Ok(listener) => {
+                    info!(%address, "Daemon socket listening");
+                    Some(listener)
+                }
+                Err(e) => {
+                    warn!(%address, "Not listening: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            state,
+            config,
+            listener,
+            extra,
+        })
     }
 
     pub fn spawn(self) -> thread::JoinHandle<()> {
-        thread::spawn(move || {
-            for stream in self.listener.incoming() {
-                match stream {
-                    Ok(stream) => {
-                        let state = Arc::clone(&self.state);
-                        thread::spawn(move || {
-                            if let Err(e) = handle_connection(stream, state) {
-                                warn!("Connection error: {e}");
-                            }
-                        });
-                    }
-                    Err(e) => {
-                        warn!("Accept error: {e}");
-                    }
+        for listener in self.extra {
+            serve(
+                listener,
+                true,
+                Arc::clone(&self.state),
+                Arc::clone(&self.config),
+            );
+        }
+        serve(self.listener, false, self.state, self.config)
+    }
+}
+
+/// Listen at a `listen` address. A socket file another daemon answers on is
+/// left alone; a stale one is replaced.
+fn bind_extra(address: &SocketAddress) -> std::io::Result<UnixListener> {
+    let SocketAddress::Path(path) = address else {
+        return address.bind();
+    };
+    if address.connect().is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            "another daemon answers there",
+        ));
+    }
+    let _ = fs::remove_file(path);
+    let listener = address.bind()?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Accept connections on `listener` until shutdown, refusing other uids when
+/// `own_uid_only` (abstract sockets have no permissions to do it)
+fn serve(
+    listener: UnixListener,
+    own_uid_only: bool,
+    state: Arc<RwLock<DaemonState>>,
+    config: Arc<ConfigHandle>,
+) -> thread::JoinHandle<()> {
+    let uid = paths::current_uid();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if state
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .shutdown
+                .is_requested()
+            {
+                break;
+            }
+            match stream {
+                Ok(stream) if own_uid_only && peer_uid(&stream) != Some(uid) => {
+                    warn!(peer_uid = ?peer_uid(&stream), "Refused a connection from another user");
+                }
+                Ok(stream) => {
+                    let state = Arc::clone(&state);
+                    let config = Arc::clone(&config);
+                    thread::spawn(move || {
+                        if let Err(e) = handle_connection(stream, state, config) {
+                            warn!("Connection error: {e}");
+                        }
+                    });
+                }
+                Err(e) => {
+                    warn!("Accept error: {e}");
                 }
             }
-        })
-    }
+        }
+    })
+}
+
+/// Uid of the process at the other end of `stream` (`SO_PEERCRED`)
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    use std::os::fd::AsRawFd;
+
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes and `len` is its size
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&raw mut cred).cast(),
+            &mut len,
+        )
+    };
+    (ret == 0).then_some(cred.uid)
 }
 
 /// Daemon control FIFO for fire-and-forget commands
 pub struct DaemonCtl {
     state: Arc<RwLock<DaemonState>>,
+    config: Arc<ConfigHandle>,
 }
 
 impl DaemonCtl {
-    pub fn new(state: Arc<RwLock<DaemonState>>) -> std::io::Result<Self> {
+    pub fn new(
+        state: Arc<RwLock<DaemonState>>,
+        config: Arc<ConfigHandle>,
+    ) -> std::io::Result<Self> {
         paths::ensure_base_dir()?;
 
         let ctl_path = paths::daemon_ctl_path();
@@ -194,7 +654,15 @@ impl DaemonCtl {
 
         info!(?ctl_path, "Daemon FIFO created");
 
-        Ok(Self { state })
+        Ok(Self { state, config })
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        self.state
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .shutdown
+            .is_requested()
     }
 
     pub fn spawn(self) -> thread::JoinHandle<()> {
@@ -213,32 +681,54 @@ impl DaemonCtl {
                     return;
                 }
             };
-            let reader = BufReader::new(file);
+            let mut reader = BufReader::new(file);
+            let max = self.config.get().max_message_size();
 
             info!("Daemon FIFO listening");
 
-            for line in reader.lines() {
-                match line {
-                    Ok(line) => {
-                        let line = line.trim().to_string();
-                        if line.is_empty() {
-                            continue;
-                        }
-                        match Request::decode(&line) {
-                            Ok(req) => {
-                                debug!(?req, "FIFO request");
-                                let resp = handle_request(req, &self.state);
-                                debug!(?resp, "FIFO response (discarded)");
-                            }
-                            Err(e) => {
-                                warn!("FIFO parse error: {e}");
-                            }
+            loop {
+                let line = match read_line_limited(&mut reader, max) {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(IpcError::TooLarge { limit }) => {
+                        warn!(limit, "FIFO message too large, discarding");
+                        // Resynchronize on the next newline
+                        if let Err(e) = reader.skip_until(b'\n') {
+                            warn!("FIFO read error: {e}");
+                            break;
                         }
+                        continue;
                     }
-                    Err(e) => {
+                    Err(IpcError::Io(e)) => {
                         warn!("FIFO read error: {e}");
                         break;
                     }
+                    Err(e) => {
+                        warn!("FIFO parse error: {e}");
+                        continue;
+                    }
+                };
+
+                if self.shutdown_requested() {
+                    break;
+                }
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                match Request::decode(line) {
+                    Ok(req) => {
+                        debug!(?req, "FIFO request");
+                        let shutdown = req == Request::Shutdown;
+                        let resp = handle_request(req, &self.state, &self.config);
+                        debug!(?resp, "FIFO response (discarded)");
+                        if shutdown {
+                            request_shutdown(&self.state);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("FIFO parse error: {e}");
+                    }
                 }
             }
         })
@@ -248,52 +738,378 @@ impl DaemonCtl {
 fn handle_connection(
     stream: UnixStream,
     state: Arc<RwLock<DaemonState>>,
-) -> Result<(), libportty::codec::IpcError> {
+    config: Arc<ConfigHandle>,
+) -> Result<(), IpcError> {
     let mut reader = BufReader::new(&stream);
     let mut writer = &stream;
 
-    let req = read_request(&mut reader)?;
+    // JSON-RPC clients are detected by their first byte
+    if matches!(reader.fill_buf()?.first(), Some(b'{' | b'[')) {
+        return handle_jsonrpc_connection(reader, writer, &state, &config);
+    }
+
+    let max = config.get().max_message_size();
+    let req = match read_request_limited(&mut reader, max) {
+        Ok(req) => req,
+        // Tell the client why its request was rejected before hanging up
+        Err(e @ (IpcError::TooLarge { .. } | IpcError::InvalidUtf8 | IpcError::Protocol(_))) => {
+            write_response(&mut writer, &Response::Error(e.to_string().into()))?;
+            return Err(e);
+        }
+        Err(e) => return Err(e),
+    };
     debug!(?req, "Received daemon request");
 
-    let resp = handle_request(req, &state);
+    // Streamed straight from the submission file, however large it is
+    if let Request::Selection { session_id } = &req {
+        if let Err(e) = req.check_input(config.get().max_entry_length()) {
+            let resp = Response::Error(format!("invalid request: {e}").into());
+            return write_response(&mut writer, &resp);
+        }
+        return match selection_file(session_id.as_deref(), &state) {
+            Ok(Some(file)) => write_selection_lines(
+                &mut std::io::BufWriter::new(writer),
+                &mut BufReader::new(file),
+            ),
+            Ok(None) => write_response(&mut writer, &Response::Ok),
+            Err(resp) => write_response(&mut writer, &resp),
+        };
+    }
+
+    let shutdown = req == Request::Shutdown;
+    let resp = handle_request(req, &state, &config);
     write_response(&mut writer, &resp)?;
 
+    if shutdown {
+        request_shutdown(&state);
+    }
+
     Ok(())
 }
 
-fn handle_request(req: Request, state: &Arc<RwLock<DaemonState>>) -> Response {
+/// Serve newline-delimited JSON-RPC 2.0 until the client hangs up.
+fn handle_jsonrpc_connection(
+    mut reader: BufReader<&UnixStream>,
+    mut writer: &UnixStream,
+    state: &Arc<RwLock<DaemonState>>,
+    config: &Arc<ConfigHandle>,
+) -> Result<(), IpcError> {
+    let max = config.get().max_message_size();
+    loop {
+        let line = match read_line_limited(&mut reader, max) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e @ (IpcError::TooLarge { .. } | IpcError::InvalidUtf8)) => {
+                let code = match e {
+                    IpcError::TooLarge { .. } => jsonrpc::INVALID_REQUEST,
+                    _ => jsonrpc::PARSE_ERROR,
+                };
+                let reply = jsonrpc::encode_error(
+                    serde_json::Value::Null,
+                    &RpcError::new(code, e.to_string()),
+                );
+                writeln!(writer, "{reply}")?;
+                writer.flush()?;
+                // An oversized line stops short of its newline; skip the
+                // rest of it. A line that is not UTF-8 was read whole.
+                if matches!(e, IpcError::TooLarge { .. }) {
+                    reader.skip_until(b'\n')?;
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut shutdown = false;
+        let reply = match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(serde_json::Value::Array(batch)) if !batch.is_empty() => {
+                let replies: Vec<_> = batch
+                    .iter()
+                    .filter_map(|call| handle_jsonrpc_call(call, state, config, &mut shutdown))
+                    .collect();
+                (!replies.is_empty()).then_some(serde_json::Value::Array(replies))
+            }
+            Ok(serde_json::Value::Array(_)) => Some(jsonrpc::encode_error(
+                serde_json::Value::Null,
+                &RpcError::new(jsonrpc::INVALID_REQUEST, "empty batch"),
+            )),
+            Ok(call) => handle_jsonrpc_call(&call, state, config, &mut shutdown),
+            Err(e) => Some(jsonrpc::encode_error(
+                serde_json::Value::Null,
+                &RpcError::new(jsonrpc::PARSE_ERROR, e.to_string()),
+            )),
+        };
+
+        if let Some(reply) = reply {
+            writeln!(writer, "{reply}")?;
+            writer.flush()?;
+        }
+
+        if shutdown {
+            request_shutdown(state);
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle one JSON-RPC call. Returns `None` for notifications.
+fn handle_jsonrpc_call(
+    call: &serde_json::Value,
+    state: &Arc<RwLock<DaemonState>>,
+    config: &Arc<ConfigHandle>,
+    shutdown: &mut bool,
+) -> Option<serde_json::Value> {
+    match jsonrpc::decode_call(call) {
+        Ok(call) => {
+            debug!(?call, "Received JSON-RPC request");
+            if let Err(e) = call.request.check_input(config.get().max_entry_length()) {
+                let err = RpcError::new(jsonrpc::INVALID_PARAMS, e.to_string());
+                return call.id.map(|id| jsonrpc::encode_error(id, &err));
+            }
+            *shutdown |= call.request == Request::Shutdown;
+            let resp = handle_request(call.request, state, config);
+            call.id.map(|id| jsonrpc::encode_response(id, &resp))
+        }
+        Err((id, err)) => {
+            debug!(%err, "Invalid JSON-RPC request");
+            Some(jsonrpc::encode_error(
+                id.unwrap_or(serde_json::Value::Null),
+                &err,
+            ))
+        }
+    }
+}
+
+fn handle_request(
+    req: Request,
+    state: &Arc<RwLock<DaemonState>>,
+    config: &Arc<ConfigHandle>,
+) -> Response {
+    // Socket and FIFO requests; JSON-RPC calls are checked when decoded
+    if let Err(e) = req.check_input(config.get().max_entry_length()) {
+        return Response::Error(format!("invalid request: {e}").into());
+    }
     match req {
-        Request::Submit { session_id } => handle_submit(session_id, state),
+        Request::Submit {
+            session_id,
+            options,
+        } => handle_submit(session_id, &options, state, config),
         Request::Cancel { session_id } => handle_cancel(session_id, state),
-        Request::Verify { session_id } => handle_verify(session_id, state),
-        Request::Reset { session_id } => handle_reset(session_id, state),
+        Request::Review {
+            session_id,
+            options,
+        } => handle_review(session_id, &options, state, config),
+        Request::Verify { session_id } => handle_verify(session_id, state, config),
+        Request::Reset { session_id, keep } => handle_reset(session_id, &keep, state),
+        Request::Focus { session_id } => handle_focus(session_id, state, config),
         Request::List => handle_list(state),
+        Request::Requests => handle_requests(state),
+        Request::Status => handle_status(state, config),
+        Request::Selection { session_id } => handle_selection(session_id.as_deref(), state),
+        Request::Reload => handle_reload(state, config),
+        Request::RefreshShims { session_id } => {
+            handle_refresh_shims(session_id.as_deref(), state, config)
+        }
+        // The actual exit happens after the response has been delivered
+        Request::Shutdown => Response::Ok,
+        Request::Permissions { app_id } => handle_permissions(app_id.as_deref()),
+        Request::SetPermission {
+            app_id,
+            operation,
+            decision,
+        } => handle_set_permission(&app_id, operation, decision),
+        Request::ClearPermission { app_id, operation } => {
+            handle_clear_permission(&app_id, operation)
+        }
     }
 }
 
 /// Submit: resolve session (by id or earliest), drain pending, signal submitted.
 /// No session -> queue to submissions dir.
-fn handle_submit(session_id: Option<String>, state: &Arc<RwLock<DaemonState>>) -> Response {
+fn handle_submit(
+    session_id: Option<String>,
+    submit: &SubmitOptions,
+    state: &Arc<RwLock<DaemonState>>,
+    config: &Arc<ConfigHandle>,
+) -> Response {
+    let portal = match submit
+        .portal
+        .as_deref()
+        .map(str::parse::<Portal>)
+        .transpose()
+    {
+        Ok(portal) => portal,
+        Err(e) => return Response::Error(e.into()),
+    };
+
     let st = state.read().unwrap_or_else(|e| e.into_inner());
 
-    let session = resolve_session(&st, session_id.as_deref());
+    let session = match (&session_id, portal) {
+        (None, Some(portal)) => st
+            .sessions
+            .iter()
+            .filter(|s| s.operation.portal() == portal)
+            .min_by_key(|s| s.created),
+        _ => resolve_session(&st, session_id.as_deref()),
+    };
 
     if let Some(session) = session {
-        let options: serde_json::Value = match fs::read_to_string(session.dir.join("options.json"))
-        {
-            Ok(json) => match serde_json::from_str(&json) {
-                Ok(v) => v,
-                Err(e) => return Response::Error(format!("Failed to parse options: {e}")),
-            },
-            Err(e) => return Response::Error(format!("Failed to read options: {e}")),
+        let operation = session.operation;
+        let (portal, op) = (operation.portal().as_str(), operation.as_str());
+        if submit.allow_empty && !libportty::portal::allows_empty(operation) {
+            return Response::Error(
+                format!("{portal} {op} does not accept an empty submission").into(),
+            );
+        }
+        if submit.sort.is_some() && operation.portal() != Portal::FileChooser {
+            return Response::Error(
+                format!("--sort only applies to file-chooser, not {portal}").into(),
+            );
+        }
+
+        let options = match libportty::portal::options::read(&session.dir) {
+            Ok(v) => v,
+            Err(e) => return Response::Error(format!("Failed to read options: {e}").into()),
+        };
+        let config = config.get();
+        let prefer = submit
+            .prefer
+            .unwrap_or_else(|| config.resolve_queue_conflict(portal, op));
+        let drained = match settle_pending(&session.dir, operation, &options, prefer) {
+            Ok(drained) => drained,
+            Err(msg) => {
+                return Response::Error(ServerError::new(
+                    ErrorCode::QueueConflict,
+                    format!("{QUEUE_CONFLICT}: {msg}"),
+                ));
+            }
+        };
+
+        // Refuse flagged paths here, while the session is still open to fix
+        // the selection; the session re-checks its final result. Invalid
+        // submissions fail the request, unless `debug.keep_session` keeps
+        // the session open.
+        let entries = files::read_lines(&session.dir.join("submission"));
+        if drained > 0 {
+            st.sessions
+                .events()
+                .selection_changed(&session.id, entries.len());
+        }
+        let refusal = match portal::validate(&config, operation, &entries, &options) {
+            Ok(uris) => portal::screen(&config, operation, &options, uris)
+                .and_then(|uris| {
+                    portal::audit(&config, portal, op, &options, &uris, submit.confirm)
+                })
+                .err(),
+            Err(msg) => (config.keep_session() && !entries.is_empty()).then_some(msg),
         };
-        drain_pending_to(&session.dir, &session.portal, &session.operation, &options);
-        session.control.submit();
+        if let Some(msg) = refusal {
+            if config.keep_session() {
+                record_event(&session.dir, &format!("Submission refused: {msg}"));
+            }
+            let cue = config.resolve_attention(portal, op);
+            attention::cue(&session.id, cue, &format!("portty: {msg}"));
+            return Response::Error(ServerError::new(
+                ErrorCode::InvalidSubmission,
+                format!("{INVALID_SUBMISSION}: {msg}"),
+            ));
+        }
+
+        session
+            .control
+            .submit(submit.allow_empty, submit.confirm, submit.sort);
         info!(session_id = %session.id, "Signalled submit");
         Response::Ok
     } else {
         drop(st);
-        move_pending_to_submissions()
+        move_pending_to_submissions(submit)
+    }
+}
+
+/// Review: what submitting the session now would return to the app,
+/// counting the pending entries a submit drains into it. Nothing changes.
+fn handle_review(
+    session_id: Option<String>,
+    submit: &SubmitOptions,
+    state: &Arc<RwLock<DaemonState>>,
+    config: &Arc<ConfigHandle>,
+) -> Response {
+    let st = state.read().unwrap_or_else(|e| e.into_inner());
+    let session = match resolve_session(&st, session_id.as_deref()) {
+        Some(s) => s,
+        None => {
+            return Response::Error(ServerError::new(
+                ErrorCode::NoSession,
+                format!("{NO_SESSION} to review"),
+            ));
+        }
+    };
+    let dir = session.dir.clone();
+    let operation = session.operation;
+    drop(st);
+
+    let (portal, op) = (operation.portal().as_str(), operation.as_str());
+    if submit.sort.is_some() && operation.portal() != Portal::FileChooser {
+        return Response::Error(
+            format!("--sort only applies to file-chooser, not {portal}").into(),
+        );
+    }
+    let options = match libportty::portal::options::read(&dir) {
+        Ok(v) => v,
+        Err(e) => return Response::Error(format!("Failed to read options: {e}").into()),
+    };
+    let config = config.get();
+    let mut entries = files::read_lines(&dir.join("submission"));
+    if let Some(intent) = queue::read(&paths::pending_dir())
+        && let Ok(queued) = libportty::portal::materialize_intent(operation, &intent, &options)
+    {
+        let diff = QueueDiff::new(&entries, &queued);
+        let prefer = submit
+            .prefer
+            .unwrap_or_else(|| config.resolve_queue_conflict(portal, op));
+        match prefer {
+            _ if !diff.diverges() => entries.extend(queued),
+            QueueConflict::Merge => entries.extend(queued),
+            QueueConflict::Session => {}
+            QueueConflict::Queue => entries = queued,
+            QueueConflict::Ask => {
+                return Response::Error(ServerError::new(
+                    ErrorCode::QueueConflict,
+                    format!("{QUEUE_CONFLICT}: {}", queue_conflict(&diff)),
+                ));
+            }
+        }
+    }
+
+    if entries.is_empty() {
+        let allow_empty = submit.allow_empty || config.resolve_allow_empty(portal, op);
+        return if allow_empty && libportty::portal::allows_empty(operation) {
+            Response::Selection(Vec::new())
+        } else {
+            Response::Error(
+                "nothing selected: submitting cancels the dialog"
+                    .to_string()
+                    .into(),
+            )
+        };
+    }
+    match portal::review(
+        &config,
+        operation,
+        &options,
+        &entries,
+        submit.sort,
+        submit.confirm,
+    ) {
+        Ok(uris) => Response::Selection(uris),
+        Err(msg) => Response::Error(ServerError::new(
+            ErrorCode::InvalidSubmission,
+            format!("{INVALID_SUBMISSION}: {msg}"),
+        )),
     }
 }
 
@@ -315,57 +1131,128 @@ fn handle_cancel(session_id: Option<String>, state: &Arc<RwLock<DaemonState>>) -
 }
 
 /// Verify: resolve session, read submission + options.json, validate.
-fn handle_verify(session_id: Option<String>, state: &Arc<RwLock<DaemonState>>) -> Response {
+fn handle_verify(
+    session_id: Option<String>,
+    state: &Arc<RwLock<DaemonState>>,
+    config: &Arc<ConfigHandle>,
+) -> Response {
     let st = state.read().unwrap_or_else(|e| e.into_inner());
 
     let session = match resolve_session(&st, session_id.as_deref()) {
         Some(s) => s,
-        None => return Response::Error("No active session to verify".to_string()),
+        None => {
+            return Response::Error(ServerError::new(
+                ErrorCode::NoSession,
+                format!("{NO_SESSION} to verify"),
+            ));
+        }
     };
 
     let session_dir = session.dir.clone();
-    let portal = session.portal.clone();
-    let operation = session.operation.clone();
+    let operation = session.operation;
+    let id = session.id.clone();
     drop(st);
 
     // Read submission
     let entries = files::read_lines(&session_dir.join("submission"));
 
     // Read options.json
-    let options: serde_json::Value = match fs::read_to_string(session_dir.join("options.json")) {
-        Ok(json) => match serde_json::from_str(&json) {
-            Ok(v) => v,
-            Err(e) => return Response::Error(format!("Failed to parse options: {e}")),
-        },
-        Err(e) => return Response::Error(format!("Failed to read options: {e}")),
+    let options = match libportty::portal::options::read(&session_dir) {
+        Ok(v) => v,
+        Err(e) => return Response::Error(format!("Failed to read options: {e}").into()),
     };
 
-    match portal::validate(&portal, &operation, &entries, &options) {
+    let config = config.get();
+    let checked = portal::validate(&config, operation, &entries, &options)
+        .and_then(|uris| portal::screen(&config, operation, &options, uris));
+    match checked {
         Ok(_) => Response::Ok,
-        Err(msg) => Response::Error(msg),
+        Err(msg) => {
+            let cue = config.resolve_attention(operation.portal().as_str(), operation.as_str());
+            attention::cue(&id, cue, &format!("portty: {msg}"));
+            Response::Error(ServerError::new(
+                ErrorCode::InvalidSubmission,
+                format!("{INVALID_SUBMISSION}: {msg}"),
+            ))
+        }
     }
 }
 
-/// Reset: resolve session, rewrite submission with initial entries.
-fn handle_reset(session_id: Option<String>, state: &Arc<RwLock<DaemonState>>) -> Response {
+/// Reset: resolve session, rewrite submission with initial entries, leaving
+/// the entries matching a `keep` glob as they are.
+fn handle_reset(
+    session_id: Option<String>,
+    keep: &[String],
+    state: &Arc<RwLock<DaemonState>>,
+) -> Response {
+    let keep = match KeepPatterns::new(keep) {
+        Ok(keep) => keep,
+        Err(e) => return Response::Error(format!("invalid request: {e}").into()),
+    };
     let st = state.read().unwrap_or_else(|e| e.into_inner());
 
     let session = match resolve_session(&st, session_id.as_deref()) {
         Some(s) => s,
-        None => return Response::Error("No active session to reset".to_string()),
+        None => {
+            return Response::Error(ServerError::new(
+                ErrorCode::NoSession,
+                format!("{NO_SESSION} to reset"),
+            ));
+        }
     };
 
     let sub_path = session.dir.join("submission");
-    let entries = session.initial_entries.clone();
+    let entries = reset::reset(
+        &session.initial_entries,
+        &files::read_lines(&sub_path),
+        &keep,
+    );
     let sid = session.id.clone();
+    let events = st.sessions.events().clone();
     drop(st);
 
     match files::write_lines(&sub_path, &entries) {
         Ok(()) => {
             info!(session_id = %sid, "Reset submission to initial state");
+            events.selection_changed(&sid, entries.len());
             Response::Ok
         }
-        Err(e) => Response::Error(format!("Failed to reset: {e}")),
+        Err(e) => Response::Error(format!("Failed to reset: {e}").into()),
+    }
+}
+
+/// Focus: select the session's window in the shared terminal (tabs mode).
+fn handle_focus(
+    session_id: Option<String>,
+    state: &Arc<RwLock<DaemonState>>,
+    config: &Arc<ConfigHandle>,
+) -> Response {
+    if !config.get().tabs() {
+        return Response::Error(
+            "focus needs tabs mode ([tabs] enabled = true)"
+                .to_string()
+                .into(),
+        );
+    }
+    let st = state.read().unwrap_or_else(|e| e.into_inner());
+    let session = match resolve_session(&st, session_id.as_deref()) {
+        Some(s) => s,
+        None => {
+            return Response::Error(ServerError::new(
+                ErrorCode::NoSession,
+                format!("{NO_SESSION} to focus"),
+            ));
+        }
+    };
+    let sid = session.id.clone();
+    drop(st);
+
+    match tabs::focus(&sid) {
+        Ok(()) => {
+            info!(session_id = %sid, "Focused session window");
+            Response::Ok
+        }
+        Err(e) => Response::Error(format!("Failed to focus session {sid}: {e}").into()),
     }
 }
 
@@ -376,6 +1263,142 @@ fn handle_list(state: &Arc<RwLock<DaemonState>>) -> Response {
     Response::Sessions(sessions)
 }
 
+fn handle_requests(state: &Arc<RwLock<DaemonState>>) -> Response {
+    let st = state.read().unwrap_or_else(|e| e.into_inner());
+    Response::Requests(st.requests.iter().cloned().collect())
+}
+
+/// Selection: the session's entries, collected (JSON-RPC and FIFO; the
+/// socket streams them instead).
+fn handle_selection(session_id: Option<&str>, state: &Arc<RwLock<DaemonState>>) -> Response {
+    let entries: std::io::Result<Vec<String>> = match selection_file(session_id, state) {
+        Ok(file) => file
+            .into_iter()
+            .flat_map(|file| BufReader::new(file).lines())
+            .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+            .collect(),
+        Err(resp) => return resp,
+    };
+    match entries {
+        Ok(entries) => Response::Selection(entries),
+        Err(e) => Response::Error(format!("Failed to read selection: {e}").into()),
+    }
+}
+
+/// Open a session's submission file, to read its entries a line at a time.
+/// An absent file (`None`) is an empty selection.
+fn selection_file(
+    session_id: Option<&str>,
+    state: &Arc<RwLock<DaemonState>>,
+) -> Result<Option<fs::File>, Response> {
+    let st = state.read().unwrap_or_else(|e| e.into_inner());
+    let session = resolve_session(&st, session_id)
+        .ok_or_else(|| Response::Error(ServerError::new(ErrorCode::NoSession, NO_SESSION)))?;
+    let path = session.dir.join("submission");
+    drop(st);
+
+    match fs::File::open(&path) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Response::Error(
+            format!("Failed to read selection: {e}").into(),
+        )),
+    }
+}
+
+/// Status: report pid, version, active session count, protocol version,
+/// build info, and the portals served.
+fn handle_status(state: &Arc<RwLock<DaemonState>>, config: &Arc<ConfigHandle>) -> Response {
+    let st = state.read().unwrap_or_else(|e| e.into_inner());
+    Response::Status(DaemonStatus {
+        pid: std::process::id(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        sessions: st.sessions.len(),
+        id_collisions: st.sessions.id_collisions(),
+        protocol: Some(PROTOCOL_VERSION),
+        build: Some(crate::build_info()),
+        portals: config.portals().to_vec(),
+    })
+}
+
+/// Reload: re-read the config file. Running sessions keep their config;
+/// standby sessions started with an outdated one are replaced.
+fn handle_reload(state: &Arc<RwLock<DaemonState>>, config: &Arc<ConfigHandle>) -> Response {
+    match config.reload() {
+        Ok(()) => {
+            let config = config.get();
+            info!(?config, "Config reloaded");
+            state
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .sessions
+                .reconcile_standby(Some(&config));
+            Response::Ok
+        }
+        Err(e) => {
+            warn!("Config reload failed: {e}");
+            Response::Error(e.into())
+        }
+    }
+}
+
+/// Refresh shims: relink the `bin/` of running sessions to the shims of the
+/// current config, so a fixed shim reaches them without restarting.
+fn handle_refresh_shims(
+    session_id: Option<&str>,
+    state: &Arc<RwLock<DaemonState>>,
+    config: &Arc<ConfigHandle>,
+) -> Response {
+    let st = state.read().unwrap_or_else(|e| e.into_inner());
+    match st.sessions.refresh_shims(&config.get(), session_id) {
+        Ok(sessions) => Response::Sessions(sessions),
+        Err(e) => Response::Error(e),
+    }
+}
+
+/// List the decisions in the permission store.
+fn handle_permissions(app_id: Option<&str>) -> Response {
+    match future::block_on(permissions::list(app_id)) {
+        Ok(permissions) => Response::Permissions(permissions),
+        Err(e) => Response::Error(format!("Permission store unavailable: {e}").into()),
+    }
+}
+
+/// Remember a decision in the permission store.
+fn handle_set_permission(app_id: &str, operation: Operation, decision: Decision) -> Response {
+    if app_id.is_empty() {
+        return Response::Error("Decisions need an app id".to_string().into());
+    }
+    match future::block_on(permissions::set(app_id, operation, decision)) {
+        Ok(()) => {
+            info!(app_id, %operation, %decision, "Decision remembered");
+            Response::Ok
+        }
+        Err(e) => Response::Error(format!("Failed to store decision: {e}").into()),
+    }
+}
+
+/// Forget decisions in the permission store.
+fn handle_clear_permission(app_id: &str, operation: Option<Operation>) -> Response {
+    match future::block_on(permissions::clear(app_id, operation)) {
+        Ok(0) => Response::Error(format!("No decision stored for {app_id}").into()),
+        Ok(removed) => {
+            info!(app_id, removed, "Decisions cleared");
+            Response::Ok
+        }
+        Err(e) => Response::Error(format!("Failed to clear decisions: {e}").into()),
+    }
+}
+
+/// Have the daemon shut down once the response is written
+fn request_shutdown(state: &Arc<RwLock<DaemonState>>) {
+    state
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .shutdown
+        .shutdown();
+}
+
 /// Resolve a session: by ID, or earliest if None.
 fn resolve_session<'a>(
     state: &'a DaemonState,
@@ -387,32 +1410,92 @@ fn resolve_session<'a>(
     }
 }
 
-/// Move pending queue state into submissions/<ts>-any/.
-fn move_pending_to_submissions() -> Response {
+/// Move pending queue state into submissions/<ts>-<portal>/ (`any` unless a
+/// portal is given), recording the queue policy override and sort order if
+/// given.
+fn move_pending_to_submissions(submit: &SubmitOptions) -> Response {
+    let portal = submit.portal.as_deref();
     let pending_dir = paths::pending_dir();
     let pending_intent = queue::read(&pending_dir);
 
     if pending_intent.is_none() {
-        return Response::Error("No pending entries to submit".to_string());
+        return Response::Error("No pending entries to submit".to_string().into());
     }
 
-    let ts = std::time::SystemTime::now()
+    let mut ts = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_millis();
 
-    let sub_dir = paths::submissions_dir().join(format!("{}-any", ts));
-    if let Err(e) = fs::create_dir_all(&sub_dir) {
-        return Response::Error(format!("Failed to create submission dir: {e}"));
+    let subs_dir = paths::submissions_dir();
+    if let Err(e) = fs::create_dir_all(&subs_dir) {
+        return Response::Error(format!("Failed to create submission dir: {e}").into());
+    }
+    // Submissions queued within the same millisecond (e.g. by `portty batch`)
+    // keep their order instead of overwriting each other
+    let sub_dir = loop {
+        let dir = subs_dir.join(format!("{}-{}", ts, portal.unwrap_or("any")));
+        match fs::create_dir(&dir) {
+            Ok(()) => break dir,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => ts += 1,
+            Err(e) => {
+                return Response::Error(format!("Failed to create submission dir: {e}").into());
+            }
+        }
+    };
+
+    if let Some(mut intent) = pending_intent {
+        let dropped = intent.normalize();
+        if dropped > 0 {
+            info!(dropped, "Collapsed items queued more than once");
+        }
+        if let Err(e) = queue::write(&sub_dir, &intent) {
+            return Response::Error(format!("Failed to write pending intent: {e}").into());
+        }
     }
 
-    if let Some(intent) = pending_intent
-        && let Err(e) = queue::write(&sub_dir, &intent)
+    if let Some(policy) = submit.queue_policy
+        && let Err(e) = queue::write_policy(&sub_dir, policy)
+    {
+        return Response::Error(format!("Failed to write queue policy: {e}").into());
+    }
+    if let Some(sort) = submit.sort
+        && let Err(e) = queue::write_sort(&sub_dir, sort)
     {
-        return Response::Error(format!("Failed to write pending intent: {e}"));
+        return Response::Error(format!("Failed to write sort order: {e}").into());
     }
 
     let _ = queue::clear(&pending_dir);
     info!("Created submission");
     Response::Ok
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jsonrpc_connections_survive_lines_that_are_not_utf8() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let state = Arc::new(RwLock::new(DaemonState::default()));
+        let config = Arc::new(ConfigHandle::new(Config::default()));
+        let served = thread::spawn(move || handle_connection(server, state, config));
+
+        let mut writer = &client;
+        writer.write_all(b"{\"method\":\"\xff\"}\n").unwrap();
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"list\"}\n")
+            .unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let replies: Vec<serde_json::Value> = BufReader::new(&client)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        served.join().unwrap().unwrap();
+        assert_eq!(replies.len(), 2, "{replies:?}");
+        assert_eq!(replies[0]["error"]["code"], jsonrpc::PARSE_ERROR);
+        assert_eq!(replies[1]["id"], 1);
+        assert!(replies[1].get("result").is_some(), "{replies:?}");
+    }
+}
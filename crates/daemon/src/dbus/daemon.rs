@@ -0,0 +1,20 @@
+//! Daemon information over D-Bus, for tools that do not speak the socket
+//! protocol (`busctl --user call ... GetVersion`)
+
+use libportty::protocol::PROTOCOL_VERSION;
+
+/// `org.werdxz.portty1.Daemon`, served next to the events
+pub struct DaemonInfo;
+
+#[zbus::interface(name = "org.werdxz.portty1.Daemon")]
+impl DaemonInfo {
+    /// porttyd's version, control protocol version, and build info (as in
+    /// `status`)
+    fn get_version(&self) -> (String, u32, String) {
+        (
+            env!("CARGO_PKG_VERSION").to_string(),
+            PROTOCOL_VERSION,
+            crate::build_info(),
+        )
+    }
+}
@@ -0,0 +1,210 @@
+use futures_util::future::abortable;
+use zbus::zvariant::{DeserializeDict, ObjectPath, OwnedValue, SerializeDict, Type, Value};
+
+use crate::dbus::request::{CancelToken, Request};
+
+/// Options for PrepareInstall request
+#[derive(Debug, Clone, Default, DeserializeDict, Type)]
+#[zvariant(signature = "dict")]
+#[allow(unused)]
+pub struct PrepareInstallOptions {
+    modal: Option<bool>,
+    launcher_type: Option<u32>,
+    target: Option<String>,
+    editable_name: Option<bool>,
+    editable_icon: Option<bool>,
+}
+
+impl PrepareInstallOptions {
+    pub fn launcher_type(&self) -> Option<u32> {
+        self.launcher_type
+    }
+
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    pub fn editable_name(&self) -> Option<bool> {
+        self.editable_name
+    }
+}
+
+/// Options for RequestInstallToken request
+#[derive(Debug, Clone, Default, DeserializeDict, Type)]
+#[zvariant(signature = "dict")]
+pub struct RequestInstallTokenOptions {}
+
+/// Response codes from portal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ResponseCode {
+    Success = 0,
+    Cancelled = 1,
+    Other = 2,
+}
+
+impl From<ResponseCode> for u32 {
+    fn from(v: ResponseCode) -> Self {
+        v as u32
+    }
+}
+
+/// Result from PrepareInstall: the chosen name and the (unchanged) icon
+#[derive(Debug, SerializeDict, Type)]
+#[zvariant(signature = "dict")]
+pub struct PrepareInstallResult {
+    name: Option<String>,
+    icon: Option<OwnedValue>,
+}
+
+/// Error type for DynamicLauncher operations
+#[derive(Debug, Clone)]
+pub enum DynamicLauncherError {
+    Cancelled,
+    Other(String),
+}
+
+/// Handler trait for DynamicLauncher operations
+pub trait DynamicLauncherHandler: Send + Sync + 'static {
+    /// Launcher types this backend accepts (bitmask of application/webapp)
+    fn supported_launcher_types(&self) -> u32;
+
+    /// Handle a PrepareInstall request, returning the chosen launcher name.
+    ///
+    /// `icon` holds the image data when the app sent a bytes icon.
+    #[allow(clippy::too_many_arguments)]
+    fn prepare_install(
+        &self,
+        handle: String,
+        cancel: CancelToken,
+        app_id: String,
+        parent_window: String,
+        name: String,
+        icon: Option<Vec<u8>>,
+        options: PrepareInstallOptions,
+    ) -> impl std::future::Future<Output = Result<String, DynamicLauncherError>> + Send;
+
+    /// Handle a RequestInstallToken request (install without a dialog)
+    fn request_install_token(
+        &self,
+        app_id: String,
+        options: RequestInstallTokenOptions,
+    ) -> impl std::future::Future<Output = Result<(), DynamicLauncherError>> + Send;
+}
+
+/// Image data of a serialized `GIcon` of the form `('bytes', <ay>)`
+fn icon_bytes(icon: &Value<'_>) -> Option<Vec<u8>> {
+    let Value::Structure(icon) = icon else {
+        return None;
+    };
+    let [Value::Str(kind), Value::Value(data)] = icon.fields() else {
+        return None;
+    };
+    if kind.as_str() != "bytes" {
+        return None;
+    }
+    let Value::Array(bytes) = &**data else {
+        return None;
+    };
+    bytes
+        .iter()
+        .map(|byte| match byte {
+            Value::U8(byte) => Some(*byte),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The DynamicLauncher portal implementation wrapper
+pub struct DynamicLauncherPortal<H> {
+    handler: H,
+}
+
+impl<H> DynamicLauncherPortal<H> {
+    pub fn new(handler: H) -> Self {
+        Self { handler }
+    }
+}
+
+impl<H: DynamicLauncherHandler> From<H> for DynamicLauncherPortal<H> {
+    fn from(handler: H) -> Self {
+        Self::new(handler)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.DynamicLauncher")]
+impl<H: DynamicLauncherHandler> DynamicLauncherPortal<H> {
+    #[allow(clippy::too_many_arguments)]
+    async fn prepare_install(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        handle: ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        name: &str,
+        icon: OwnedValue,
+        options: PrepareInstallOptions,
+    ) -> zbus::fdo::Result<(u32, PrepareInstallResult)> {
+        let cancel = CancelToken::default();
+        let fut = self.handler.prepare_install(
+            handle.to_string(),
+            cancel.clone(),
+            app_id.to_string(),
+            parent_window.to_string(),
+            name.to_string(),
+            icon_bytes(&icon),
+            options,
+        );
+        let (abortable_fut, abort_handle) = abortable(fut);
+
+        let request = Request::new(abort_handle, cancel);
+        server.at(handle.as_ref(), request).await?;
+
+        let result = abortable_fut.await;
+        let _ = server.remove::<Request, _>(handle.as_ref()).await;
+
+        let empty = || PrepareInstallResult {
+            name: None,
+            icon: None,
+        };
+        match result {
+            Ok(Ok(name)) => Ok((
+                ResponseCode::Success.into(),
+                PrepareInstallResult {
+                    name: Some(name),
+                    icon: Some(icon),
+                },
+            )),
+            Ok(Err(DynamicLauncherError::Cancelled)) | Err(_) => {
+                Ok((ResponseCode::Cancelled.into(), empty()))
+            }
+            Ok(Err(DynamicLauncherError::Other(msg))) => Err(zbus::fdo::Error::Failed(msg)),
+        }
+    }
+
+    async fn request_install_token(
+        &self,
+        app_id: &str,
+        options: RequestInstallTokenOptions,
+    ) -> zbus::fdo::Result<u32> {
+        match self
+            .handler
+            .request_install_token(app_id.to_string(), options)
+            .await
+        {
+            Ok(()) => Ok(ResponseCode::Success.into()),
+            Err(DynamicLauncherError::Cancelled) => Ok(ResponseCode::Cancelled.into()),
+            Err(DynamicLauncherError::Other(msg)) => Err(zbus::fdo::Error::Failed(msg)),
+        }
+    }
+
+    #[zbus(property)]
+    fn supported_launcher_types(&self) -> u32 {
+        self.handler.supported_launcher_types()
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
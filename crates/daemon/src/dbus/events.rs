@@ -0,0 +1,136 @@
+use std::sync::{Arc, OnceLock};
+
+use tracing::debug;
+use zbus::Connection;
+use zbus::object_server::SignalEmitter;
+
+/// Path the events object is served at
+pub const EVENTS_PATH: &str = "/org/werdxz/portty1";
+
+/// How a session ended, as sent in `SessionEnded`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionOutcome {
+    Submitted,
+    Cancelled,
+    /// The app closed the request
+    Closed,
+    /// The session could not be spawned or run
+    Failed,
+}
+
+impl SessionOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Submitted => "submitted",
+            Self::Cancelled => "cancelled",
+            Self::Closed => "closed",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// Session events mirrored as signals, for `busctl --user monitor` and
+/// scripts using dbus-send/gdbus instead of the daemon socket
+pub struct Events;
+
+#[zbus::interface(name = "org.werdxz.portty1.Events")]
+impl Events {
+    /// A session was registered for a request
+    #[zbus(signal)]
+    async fn session_started(
+        emitter: &SignalEmitter<'_>,
+        id: &str,
+        portal: &str,
+        operation: &str,
+        app_id: &str,
+    ) -> zbus::Result<()>;
+
+    /// A session ended; `outcome` is `submitted`, `cancelled`, `closed`, or
+    /// `failed`
+    #[zbus(signal)]
+    async fn session_ended(
+        emitter: &SignalEmitter<'_>,
+        id: &str,
+        outcome: &str,
+    ) -> zbus::Result<()>;
+
+    /// The daemon changed the selection of a session (`reset`, or pending
+    /// entries drained on submit); `entries` is its new length
+    #[zbus(signal)]
+    async fn selection_changed(
+        emitter: &SignalEmitter<'_>,
+        id: &str,
+        entries: u32,
+    ) -> zbus::Result<()>;
+}
+
+/// Handle emitting [`Events`] signals. Events before the daemon is on the
+/// bus are dropped.
+#[derive(Debug, Clone, Default)]
+pub struct EventEmitter {
+    connection: Arc<OnceLock<Connection>>,
+}
+
+impl EventEmitter {
+    /// Start emitting on `connection`
+    pub fn connect(&self, connection: Connection) {
+        let _ = self.connection.set(connection);
+    }
+
+    pub fn session_started(&self, id: &str, portal: &str, operation: &str, app_id: &str) {
+        let (id, portal, operation, app_id) = (
+            id.to_string(),
+            portal.to_string(),
+            operation.to_string(),
+            app_id.to_string(),
+        );
+        self.emit("SessionStarted", move |emitter| async move {
+            Events::session_started(&emitter, &id, &portal, &operation, &app_id).await
+        });
+    }
+
+    pub fn session_ended(&self, id: &str, outcome: SessionOutcome) {
+        let id = id.to_string();
+        self.emit("SessionEnded", move |emitter| async move {
+            Events::session_ended(&emitter, &id, outcome.as_str()).await
+        });
+    }
+
+    pub fn selection_changed(&self, id: &str, entries: usize) {
+        let id = id.to_string();
+        let entries = u32::try_from(entries).unwrap_or(u32::MAX);
+        self.emit("SelectionChanged", move |emitter| async move {
+            Events::selection_changed(&emitter, &id, entries).await
+        });
+    }
+
+    /// Send a signal from the connection's executor, so callers holding the
+    /// state lock (or running outside the async runtime) don't block on it.
+    fn emit<F>(&self, name: &'static str, send: impl FnOnce(SignalEmitter<'static>) -> F)
+    where
+        F: Future<Output = zbus::Result<()>> + Send + 'static,
+    {
+        let Some(connection) = self.connection.get() else {
+            return;
+        };
+        let emitter = match SignalEmitter::new(connection, EVENTS_PATH) {
+            Ok(emitter) => emitter.into_owned(),
+            Err(e) => {
+                debug!(signal = name, "Failed to create signal emitter: {e}");
+                return;
+            }
+        };
+        let signal = send(emitter);
+        connection
+            .executor()
+            .spawn(
+                async move {
+                    if let Err(e) = signal.await {
+                        debug!(signal = name, "Failed to emit signal: {e}");
+                    }
+                },
+                name,
+            )
+            .detach();
+    }
+}
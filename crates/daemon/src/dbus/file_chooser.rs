@@ -1,15 +1,16 @@
 #![allow(dead_code)]
 
 use futures_util::future::abortable;
+use libportty::portal::file_chooser;
 use zbus::zvariant::{DeserializeDict, ObjectPath, SerializeDict, Type};
 
-use crate::dbus::request::Request;
+use crate::dbus::request::{CancelToken, Request};
 
 /// File filter: (name, patterns)
 /// D-Bus signature: (sa(us))
 /// Example: ("Images", [(0, "*.png"), (1, "image/png")])
 /// Pattern type: 0 = glob, 1 = mime type
-#[derive(Debug, Clone, Type, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Type, serde::Serialize, serde::Deserialize)]
 #[zvariant(signature = "(sa(us))")]
 pub struct FileFilter(String, Vec<(u32, String)>);
 
@@ -46,6 +47,29 @@ pub enum FilterPattern<'a> {
     MimeType(&'a str),
 }
 
+impl From<&FileFilter> for file_chooser::Filter {
+    fn from(filter: &FileFilter) -> Self {
+        filter
+            .patterns()
+            .fold(Self::new(filter.name()), |f, pattern| match pattern {
+                FilterPattern::Glob(glob) => f.glob(glob),
+                FilterPattern::MimeType(mime) => f.mime_type(mime),
+            })
+    }
+}
+
+impl From<&file_chooser::Filter> for FileFilter {
+    fn from(filter: &file_chooser::Filter) -> Self {
+        filter
+            .patterns
+            .iter()
+            .fold(Self::new(&filter.name), |f, pattern| match pattern {
+                file_chooser::FilterPattern::Glob(glob) => f.glob(glob),
+                file_chooser::FilterPattern::MimeType(mime) => f.mime_type(mime),
+            })
+    }
+}
+
 /// Choice option for dialogs
 /// D-Bus signature: (ssa(ss)s)
 /// (id, label, [(option_id, option_label), ...], default_option_id)
@@ -85,6 +109,17 @@ impl Choice {
     }
 }
 
+impl From<&Choice> for file_chooser::Choice {
+    fn from(choice: &Choice) -> Self {
+        Self {
+            id: choice.0.clone(),
+            label: choice.1.clone(),
+            options: choice.2.clone(),
+            default: choice.3.clone(),
+        }
+    }
+}
+
 /// Options for OpenFile request
 #[derive(Debug, Clone, Default, DeserializeDict, Type)]
 #[zvariant(signature = "dict")]
@@ -286,11 +321,13 @@ impl FileChooserResult {
 ///
 /// Implement this trait to provide the actual file choosing logic.
 /// The types crate handles D-Bus serialization, you handle the UI/interaction.
+/// `cancel` is set when the app closes the request.
 pub trait FileChooserHandler: Send + Sync + 'static {
     /// Handle an OpenFile request
     fn open_file(
         &self,
         handle: String,
+        cancel: CancelToken,
         app_id: String,
         parent_window: String,
         title: String,
@@ -301,6 +338,7 @@ pub trait FileChooserHandler: Send + Sync + 'static {
     fn save_file(
         &self,
         handle: String,
+        cancel: CancelToken,
         app_id: String,
         parent_window: String,
         title: String,
@@ -311,6 +349,7 @@ pub trait FileChooserHandler: Send + Sync + 'static {
     fn save_files(
         &self,
         handle: String,
+        cancel: CancelToken,
         app_id: String,
         parent_window: String,
         title: String,
@@ -355,9 +394,11 @@ impl<H: FileChooserHandler> FileChooserPortal<H> {
         title: &str,
         options: OpenFileOptions,
     ) -> zbus::fdo::Result<(u32, FileChooserResult)> {
+        let cancel = CancelToken::default();
         // Create abortable future
         let fut = self.handler.open_file(
             handle.to_string(),
+            cancel.clone(),
             app_id.to_string(),
             parent_window.to_string(),
             title.to_string(),
@@ -366,7 +407,7 @@ impl<H: FileChooserHandler> FileChooserPortal<H> {
         let (abortable_fut, abort_handle) = abortable(fut);
 
         // Register Request object for cancellation
-        let request = Request::new(abort_handle);
+        let request = Request::new(abort_handle, cancel);
         server.at(handle.as_ref(), request).await?;
 
         // Run the handler
@@ -394,8 +435,10 @@ impl<H: FileChooserHandler> FileChooserPortal<H> {
         title: &str,
         options: SaveFileOptions,
     ) -> zbus::fdo::Result<(u32, FileChooserResult)> {
+        let cancel = CancelToken::default();
         let fut = self.handler.save_file(
             handle.to_string(),
+            cancel.clone(),
             app_id.to_string(),
             parent_window.to_string(),
             title.to_string(),
@@ -403,7 +446,7 @@ impl<H: FileChooserHandler> FileChooserPortal<H> {
         );
         let (abortable_fut, abort_handle) = abortable(fut);
 
-        let request = Request::new(abort_handle);
+        let request = Request::new(abort_handle, cancel);
         server.at(handle.as_ref(), request).await?;
 
         let result = abortable_fut.await;
@@ -427,8 +470,10 @@ impl<H: FileChooserHandler> FileChooserPortal<H> {
         title: &str,
         options: SaveFilesOptions,
     ) -> zbus::fdo::Result<(u32, FileChooserResult)> {
+        let cancel = CancelToken::default();
         let fut = self.handler.save_files(
             handle.to_string(),
+            cancel.clone(),
             app_id.to_string(),
             parent_window.to_string(),
             title.to_string(),
@@ -436,7 +481,7 @@ impl<H: FileChooserHandler> FileChooserPortal<H> {
         );
         let (abortable_fut, abort_handle) = abortable(fut);
 
-        let request = Request::new(abort_handle);
+        let request = Request::new(abort_handle, cancel);
         server.at(handle.as_ref(), request).await?;
 
         let result = abortable_fut.await;
@@ -450,4 +495,10 @@ impl<H: FileChooserHandler> FileChooserPortal<H> {
             Ok(Err(FileChooserError::Other(msg))) => Err(zbus::fdo::Error::Failed(msg)),
         }
     }
+
+    /// Interface version: OpenFile, SaveFile, and SaveFiles with `current_folder`
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        4
+    }
 }
@@ -1,3 +1,7 @@
+pub mod daemon;
+pub mod dynamic_launcher;
+pub mod events;
 pub mod file_chooser;
+pub mod remote_desktop;
 pub mod request;
 pub mod screenshot;
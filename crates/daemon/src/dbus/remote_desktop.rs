@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures_util::future::abortable;
+use tracing::debug;
+use zbus::zvariant::{DeserializeDict, ObjectPath, OwnedValue, SerializeDict, Type};
+
+use crate::dbus::request::{CancelToken, Request};
+
+/// Options for CreateSession request
+#[derive(Debug, Clone, Default, DeserializeDict, Type)]
+#[zvariant(signature = "dict")]
+pub struct CreateSessionOptions {}
+
+/// Options for SelectDevices request
+#[derive(Debug, Clone, Default, DeserializeDict, Type)]
+#[zvariant(signature = "dict")]
+#[allow(unused)]
+pub struct SelectDevicesOptions {
+    types: Option<u32>,
+    persist_mode: Option<u32>,
+}
+
+/// Options for Start request
+#[derive(Debug, Clone, Default, DeserializeDict, Type)]
+#[zvariant(signature = "dict")]
+pub struct StartOptions {}
+
+/// Response codes from portal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ResponseCode {
+    Success = 0,
+    Cancelled = 1,
+    Other = 2,
+}
+
+impl From<ResponseCode> for u32 {
+    fn from(v: ResponseCode) -> Self {
+        v as u32
+    }
+}
+
+/// Result from Start: the granted devices and screen cast streams
+#[derive(Debug, Clone, Default, SerializeDict, Type)]
+#[zvariant(signature = "dict")]
+pub struct StartResult {
+    devices: u32,
+    clipboard_enabled: bool,
+    streams: Vec<(u32, HashMap<String, OwnedValue>)>,
+}
+
+impl StartResult {
+    /// A started session with no devices and no streams
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Error type for RemoteDesktop operations
+#[derive(Debug, Clone)]
+pub enum RemoteDesktopError {
+    Cancelled,
+    Other(String),
+}
+
+/// Handler trait for RemoteDesktop operations
+pub trait RemoteDesktopHandler: Send + Sync + 'static {
+    /// Device types this backend can provide (bitmask of keyboard/pointer/touchscreen)
+    fn available_device_types(&self) -> u32;
+
+    /// Handle a Start request for a session whose devices were selected
+    fn start(
+        &self,
+        handle: String,
+        cancel: CancelToken,
+        session_handle: String,
+        app_id: String,
+        parent_window: String,
+        devices: u32,
+    ) -> impl std::future::Future<Output = Result<StartResult, RemoteDesktopError>> + Send;
+}
+
+/// Device types selected per session handle
+type Sessions = Arc<Mutex<HashMap<String, u32>>>;
+
+/// The RemoteDesktop portal implementation wrapper
+pub struct RemoteDesktopPortal<H> {
+    handler: H,
+    sessions: Sessions,
+}
+
+impl<H> RemoteDesktopPortal<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            sessions: Sessions::default(),
+        }
+    }
+}
+
+impl<H: RemoteDesktopHandler> From<H> for RemoteDesktopPortal<H> {
+    fn from(handler: H) -> Self {
+        Self::new(handler)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.RemoteDesktop")]
+impl<H: RemoteDesktopHandler> RemoteDesktopPortal<H> {
+    async fn create_session(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        _handle: ObjectPath<'_>,
+        session_handle: ObjectPath<'_>,
+        app_id: &str,
+        _options: CreateSessionOptions,
+    ) -> zbus::fdo::Result<(u32, HashMap<String, OwnedValue>)> {
+        debug!(%session_handle, app_id, "RemoteDesktop CreateSession");
+        let session = Session::new(session_handle.to_string(), Arc::clone(&self.sessions));
+        server.at(session_handle.as_ref(), session).await?;
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(session_handle.to_string(), 0);
+        Ok((ResponseCode::Success.into(), HashMap::new()))
+    }
+
+    async fn select_devices(
+        &self,
+        _handle: ObjectPath<'_>,
+        session_handle: ObjectPath<'_>,
+        _app_id: &str,
+        options: SelectDevicesOptions,
+    ) -> zbus::fdo::Result<(u32, HashMap<String, OwnedValue>)> {
+        let available = self.handler.available_device_types();
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(devices) = sessions.get_mut(session_handle.as_str()) else {
+            return Ok((ResponseCode::Other.into(), HashMap::new()));
+        };
+        *devices = options.types.unwrap_or(available) & available;
+        Ok((ResponseCode::Success.into(), HashMap::new()))
+    }
+
+    async fn start(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        handle: ObjectPath<'_>,
+        session_handle: ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        _options: StartOptions,
+    ) -> zbus::fdo::Result<(u32, StartResult)> {
+        let devices = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(session_handle.as_str())
+            .copied();
+        let Some(devices) = devices else {
+            return Ok((ResponseCode::Other.into(), StartResult::default()));
+        };
+
+        let cancel = CancelToken::default();
+        let fut = self.handler.start(
+            handle.to_string(),
+            cancel.clone(),
+            session_handle.to_string(),
+            app_id.to_string(),
+            parent_window.to_string(),
+            devices,
+        );
+        let (abortable_fut, abort_handle) = abortable(fut);
+
+        let request = Request::new(abort_handle, cancel);
+        server.at(handle.as_ref(), request).await?;
+
+        let result = abortable_fut.await;
+        let _ = server.remove::<Request, _>(handle.as_ref()).await;
+
+        match result {
+            Ok(Ok(result)) => Ok((ResponseCode::Success.into(), result)),
+            Ok(Err(RemoteDesktopError::Cancelled)) | Err(_) => {
+                Ok((ResponseCode::Cancelled.into(), StartResult::default()))
+            }
+            Ok(Err(RemoteDesktopError::Other(msg))) => Err(zbus::fdo::Error::Failed(msg)),
+        }
+    }
+
+    #[zbus(property)]
+    fn available_device_types(&self) -> u32 {
+        self.handler.available_device_types()
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
+
+/// A portal Session object, exported at the session handle from CreateSession
+///
+/// The frontend calls Close when the app ends the session.
+pub struct Session {
+    handle: String,
+    sessions: Sessions,
+}
+
+impl Session {
+    fn new(handle: String, sessions: Sessions) -> Self {
+        Self { handle, sessions }
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.Session")]
+impl Session {
+    /// Close the session
+    async fn close(&self, #[zbus(object_server)] server: &zbus::ObjectServer) {
+        debug!(session_handle = %self.handle, "RemoteDesktop session closed");
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.handle);
+        if let Ok(path) = ObjectPath::try_from(self.handle.as_str()) {
+            let _ = server.remove::<Session, _>(&path).await;
+        }
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
@@ -1,5 +1,14 @@
+use std::future::poll_fn;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
 use futures_util::future::AbortHandle;
 
+/// How long a closed request may take to tear down before it is aborted
+const CLOSE_DEADLINE: Duration = Duration::from_secs(2);
+
 /// A portal Request object
 ///
 /// Each portal request creates a Request object at a unique path.
@@ -7,11 +16,15 @@ use futures_util::future::AbortHandle;
 /// by calling the Close method.
 pub struct Request {
     abort_handle: AbortHandle,
+    cancel: CancelToken,
 }
 
 impl Request {
-    pub fn new(abort_handle: AbortHandle) -> Self {
-        Self { abort_handle }
+    pub fn new(abort_handle: AbortHandle, cancel: CancelToken) -> Self {
+        Self {
+            abort_handle,
+            cancel,
+        }
     }
 }
 
@@ -19,8 +32,58 @@ impl Request {
 impl Request {
     /// Close the request
     ///
-    /// Called by the application to cancel an in-progress request.
+    /// Called by the application to cancel an in-progress request. The
+    /// handler is told through its [`CancelToken`] so it can end the session
+    /// and remove what it staged; one still running after [`CLOSE_DEADLINE`]
+    /// is aborted.
     async fn close(&self) {
-        self.abort_handle.abort();
+        self.cancel.cancel();
+        let abort_handle = self.abort_handle.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(CLOSE_DEADLINE);
+            abort_handle.abort();
+        });
+    }
+}
+
+/// Cancellation of a portal request, set when the app closes it.
+///
+/// Clones share the same state. The default token is never cancelled, for
+/// requests without a Request object.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<CancelState>);
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        let wakers = std::mem::take(&mut *self.0.wakers.lock().unwrap_or_else(|e| e.into_inner()));
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the request is cancelled
+    pub async fn cancelled(&self) {
+        poll_fn(|cx| {
+            let mut wakers = self.0.wakers.lock().unwrap_or_else(|e| e.into_inner());
+            if self.is_cancelled() {
+                return Poll::Ready(());
+            }
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+        .await
     }
 }
@@ -1,7 +1,7 @@
 use futures_util::future::abortable;
 use zbus::zvariant::{DeserializeDict, ObjectPath, SerializeDict, Type};
 
-use crate::dbus::request::Request;
+use crate::dbus::request::{CancelToken, Request};
 
 /// Options for Screenshot request
 #[derive(Debug, Clone, Default, DeserializeDict, Type)]
@@ -82,6 +82,7 @@ pub trait ScreenshotHandler: Send + Sync + 'static {
     fn screenshot(
         &self,
         handle: String,
+        cancel: CancelToken,
         app_id: String,
         parent_window: String,
         options: ScreenshotOptions,
@@ -91,6 +92,7 @@ pub trait ScreenshotHandler: Send + Sync + 'static {
     fn pick_color(
         &self,
         handle: String,
+        cancel: CancelToken,
         app_id: String,
         parent_window: String,
         options: PickColorOptions,
@@ -124,15 +126,17 @@ impl<H: ScreenshotHandler> ScreenshotPortal<H> {
         parent_window: &str,
         options: ScreenshotOptions,
     ) -> zbus::fdo::Result<(u32, ScreenshotResult)> {
+        let cancel = CancelToken::default();
         let fut = self.handler.screenshot(
             handle.to_string(),
+            cancel.clone(),
             app_id.to_string(),
             parent_window.to_string(),
             options,
         );
         let (abortable_fut, abort_handle) = abortable(fut);
 
-        let request = Request::new(abort_handle);
+        let request = Request::new(abort_handle, cancel);
         server.at(handle.as_ref(), request).await?;
 
         let result = abortable_fut.await;
@@ -155,15 +159,17 @@ impl<H: ScreenshotHandler> ScreenshotPortal<H> {
         parent_window: &str,
         options: PickColorOptions,
     ) -> zbus::fdo::Result<(u32, PickColorResult)> {
+        let cancel = CancelToken::default();
         let fut = self.handler.pick_color(
             handle.to_string(),
+            cancel.clone(),
             app_id.to_string(),
             parent_window.to_string(),
             options,
         );
         let (abortable_fut, abort_handle) = abortable(fut);
 
-        let request = Request::new(abort_handle);
+        let request = Request::new(abort_handle, cancel);
         server.at(handle.as_ref(), request).await?;
 
         let result = abortable_fut.await;
@@ -177,4 +183,10 @@ impl<H: ScreenshotHandler> ScreenshotPortal<H> {
             Ok(Err(ScreenshotError::Other(msg))) => Err(zbus::fdo::Error::Failed(msg)),
         }
     }
+
+    /// Interface version: Screenshot and PickColor
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
 }
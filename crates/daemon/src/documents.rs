@@ -0,0 +1,221 @@
+//! Export of result files through the document portal.
+//!
+//! A sandboxed app only sees the host files its permissions allow. With
+//! `export_documents` enabled, files outside them are registered with
+//! `org.freedesktop.portal.Documents` for the requesting app and their
+//! document-portal path (`/run/user/<uid>/doc/<id>/<name>`) is returned
+//! instead. The document portal decides whether the app already has access
+//! (`as-needed-by-app`), so files it can see are returned unchanged.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use libportty::portal::file_chooser::path_to_file_uri;
+use libportty::portal::screenshot::screenshot_path;
+use tracing::{debug, info, warn};
+use zbus::zvariant::{Fd, OwnedValue};
+
+use crate::dbus::request::CancelToken;
+
+/// Reuse an existing document for the same file
+const REUSE_EXISTING: u32 = 1;
+/// Keep the document after the app exits
+const PERSISTENT: u32 = 2;
+/// Only export if the app cannot already access the file
+const AS_NEEDED_BY_APP: u32 = 4;
+
+const FLAGS: u32 = REUSE_EXISTING | PERSISTENT | AS_NEEDED_BY_APP;
+
+#[zbus::proxy(
+    interface = "org.freedesktop.portal.Documents",
+    default_service = "org.freedesktop.portal.Documents",
+    default_path = "/org/freedesktop/portal/documents"
+)]
+trait Documents {
+    fn get_mount_point(&self) -> zbus::Result<Vec<u8>>;
+
+    fn add_full(
+        &self,
+        o_path_fds: &[Fd<'_>],
+        flags: u32,
+        app_id: &str,
+        permissions: &[&str],
+    ) -> zbus::Result<(Vec<String>, HashMap<String, OwnedValue>)>;
+
+    fn add_named_full(
+        &self,
+        o_path_fd: Fd<'_>,
+        filename: &[u8],
+        flags: u32,
+        app_id: &str,
+        permissions: &[&str],
+    ) -> zbus::Result<(String, HashMap<String, OwnedValue>)>;
+}
+
+/// What the app needs to do with the exported files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Open existing files
+    Read,
+    /// Save to files that may not exist yet
+    Write,
+}
+
+impl Access {
+    fn permissions(self) -> &'static [&'static str] {
+        match self {
+            Self::Read => &["read", "grant-permissions"],
+            Self::Write => &["read", "write", "grant-permissions"],
+        }
+    }
+}
+
+/// Whether `app_id` belongs to a sandboxed (Flatpak or Snap) app.
+///
+/// Host apps have an empty app id, or one derived from their systemd unit or
+/// desktop file that is not an installed Flatpak.
+pub fn is_sandboxed(app_id: &str) -> bool {
+    if app_id.is_empty() {
+        return false;
+    }
+    if app_id.starts_with("snap.") {
+        return true;
+    }
+    flatpak_dirs()
+        .iter()
+        .any(|dir| dir.join("app").join(app_id).is_dir())
+}
+
+/// Flatpak installations: per-user first, then system-wide
+fn flatpak_dirs() -> Vec<PathBuf> {
+    let env_dir = |var: &str| std::env::var_os(var).map(PathBuf::from);
+    let user = env_dir("FLATPAK_USER_DIR").or_else(|| {
+        env_dir("XDG_DATA_HOME")
+            .or_else(|| env_dir("HOME").map(|home| home.join(".local/share")))
+            .map(|data| data.join("flatpak"))
+    });
+    let system = env_dir("FLATPAK_SYSTEM_DIR").unwrap_or_else(|| PathBuf::from("/var/lib/flatpak"));
+    user.into_iter().chain([system]).collect()
+}
+
+/// Export the files behind `uris` for a sandboxed app, returning
+/// document-portal URIs where the app needs them.
+///
+/// Non-file URIs and files the app can already access pass through. Export
+/// failures are logged and the original URI is kept, so the app at least gets
+/// an answer. Once the app closed the request, the remaining files are not
+/// exported: the answer will not reach it.
+pub async fn export_uris(
+    app_id: &str,
+    uris: Vec<String>,
+    access: Access,
+    cancel: &CancelToken,
+) -> Vec<String> {
+    if !is_sandboxed(app_id) {
+        return uris;
+    }
+
+    let proxy = match documents_proxy().await {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            warn!(
+                app_id,
+                "Document portal unavailable, returning host paths: {e}"
+            );
+            return uris;
+        }
+    };
+
+    let mut out = Vec::with_capacity(uris.len());
+    for uri in uris {
+        let Some(path) = screenshot_path(&uri)
+            .filter(|p| p.is_absolute())
+            .filter(|_| !cancel.is_cancelled())
+        else {
+            out.push(uri);
+            continue;
+        };
+        match export(&proxy, app_id, &path, access).await {
+            Ok(Some(doc_path)) => {
+                info!(app_id, path = %path.display(), doc = %doc_path.display(), "Exported through document portal");
+                out.push(path_to_file_uri(&doc_path));
+            }
+            Ok(None) => {
+                debug!(app_id, path = %path.display(), "App can access file, not exporting");
+                out.push(uri);
+            }
+            Err(e) => {
+                warn!(app_id, path = %path.display(), "Failed to export through document portal: {e}");
+                out.push(uri);
+            }
+        }
+    }
+    out
+}
+
+async fn documents_proxy() -> zbus::Result<DocumentsProxy<'static>> {
+    let conn = zbus::Connection::session().await?;
+    DocumentsProxy::new(&conn).await
+}
+
+/// Register one file, returning its document-portal path (`None` if the app
+/// does not need it).
+///
+/// Existing files are added directly. Save targets that do not exist yet are
+/// added by name relative to their (existing) parent directory.
+async fn export(
+    proxy: &DocumentsProxy<'_>,
+    app_id: &str,
+    path: &Path,
+    access: Access,
+) -> Result<Option<PathBuf>, String> {
+    let permissions = access.permissions();
+    let (doc_id, name) = if path.exists() {
+        let fd = open_path(path)?;
+        let (ids, _) = proxy
+            .add_full(&[Fd::from(&fd)], FLAGS, app_id, permissions)
+            .await
+            .map_err(|e| e.to_string())?;
+        let id = ids.into_iter().next().unwrap_or_default();
+        (id, path.file_name())
+    } else {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err("not a file path".to_string());
+        };
+        let fd = open_path(parent)?;
+        let mut filename = name.as_bytes().to_vec();
+        filename.push(0);
+        let (id, _) = proxy
+            .add_named_full(Fd::from(&fd), &filename, FLAGS, app_id, permissions)
+            .await
+            .map_err(|e| e.to_string())?;
+        (id, Some(name))
+    };
+
+    // An empty id means the app can already access the file
+    if doc_id.is_empty() {
+        return Ok(None);
+    }
+    let Some(name) = name else {
+        return Err("file has no name".to_string());
+    };
+
+    let mount = proxy.get_mount_point().await.map_err(|e| e.to_string())?;
+    let mount = mount.strip_suffix(&[0]).unwrap_or(&mount);
+    Ok(Some(
+        Path::new(OsStr::from_bytes(mount)).join(doc_id).join(name),
+    ))
+}
+
+/// Open `path` with `O_PATH`, as the document portal requires
+fn open_path(path: &Path) -> Result<File, String> {
+    File::options()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+        .open(path)
+        .map_err(|e| format!("cannot open {}: {e}", path.display()))
+}
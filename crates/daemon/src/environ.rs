@@ -0,0 +1,40 @@
+//! Environment of the commands run for a session.
+//!
+//! They start from porttyd's environment, less the variables the
+//! `inherit_env` config drops (see [`EnvFilter`]), and get the session's own
+//! `PORTTY_*` variables and `PATH` on top.
+
+use std::ffi::OsString;
+use std::process::Command;
+
+use crate::config::EnvFilter;
+
+/// Make `cmd` run without the inherited variables `filter` drops. Call it
+/// before setting the session's variables, which are never dropped.
+pub fn apply(cmd: &mut Command, filter: Option<&EnvFilter>) {
+    let Some(filter) = filter else {
+        return;
+    };
+    for name in filter.dropped(std::env::vars_os()) {
+        cmd.env_remove(name);
+    }
+}
+
+/// `argv` wrapped to run without the variables removed from `cmd`, for
+/// commands started by another process (a tmux server) that passes on its
+/// own environment. Only the names end up on the command line.
+pub fn wrap(cmd: &Command, argv: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut wrapped: Vec<OsString> = Vec::new();
+    for (name, _) in cmd.get_envs().filter(|(_, value)| value.is_none()) {
+        if wrapped.is_empty() {
+            wrapped.push("env".into());
+        }
+        wrapped.push("-u".into());
+        wrapped.push(name.into());
+    }
+    if !wrapped.is_empty() {
+        wrapped.push("--".into());
+    }
+    wrapped.extend(argv);
+    wrapped
+}
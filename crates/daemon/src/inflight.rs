@@ -0,0 +1,156 @@
+//! Portal requests in flight.
+//!
+//! A request is tracked from the portal handler receiving it to the reply,
+//! whether or not it becomes a session: a queued submission or a remembered
+//! decision answers without one, and a request may be refused before its
+//! session starts. `portty list --all` shows them, so an app waiting on
+//! porttyd can be told apart from one whose call never reached it.
+//!
+//! A request answered with results is added to the results log
+//! (`portty history`) with how they were produced.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+use libportty::portal::history::{self, Provenance};
+use libportty::portal::utc_timestamp;
+use libportty::{Operation, RequestInfo, RequestState};
+use tracing::{info, warn};
+
+use crate::daemon_socket::DaemonState;
+use crate::dbus::request::CancelToken;
+use crate::session::unix_now;
+
+/// Requests being handled, in the order they were received
+#[derive(Debug, Default)]
+pub struct InFlightRequests {
+    requests: BTreeMap<u64, RequestInfo>,
+    next: u64,
+}
+
+impl InFlightRequests {
+    pub fn iter(&self) -> impl Iterator<Item = &RequestInfo> {
+        self.requests.values()
+    }
+
+    fn update(&mut self, key: u64, change: impl FnOnce(&mut RequestInfo)) {
+        if let Some(request) = self.requests.get_mut(&key) {
+            change(request);
+        }
+    }
+}
+
+/// A tracked request, listed until it is dropped with the handler's reply
+pub struct InFlight {
+    state: Arc<RwLock<DaemonState>>,
+    key: u64,
+    cancel: CancelToken,
+    operation: Operation,
+    app_id: String,
+    /// How the results were produced, set by [`InFlight::produced`]
+    provenance: Mutex<Option<Provenance>>,
+}
+
+impl InFlight {
+    /// Track a request received at `handle` (empty for methods without a
+    /// Request object). `cancel` is set when the app closes it.
+    pub fn start(
+        state: &Arc<RwLock<DaemonState>>,
+        handle: String,
+        operation: Operation,
+        app_id: &str,
+        cancel: CancelToken,
+    ) -> Self {
+        let mut st = state.write().unwrap_or_else(|e| e.into_inner());
+        let key = st.requests.next;
+        st.requests.next += 1;
+        st.requests.requests.insert(
+            key,
+            RequestInfo {
+                handle,
+                operation,
+                state: RequestState::Received,
+                received: unix_now(),
+                session_id: None,
+                app_id: (!app_id.is_empty()).then(|| app_id.to_string()),
+            },
+        );
+        Self {
+            state: Arc::clone(state),
+            key,
+            cancel,
+            operation,
+            app_id: app_id.to_string(),
+            provenance: Mutex::new(None),
+        }
+    }
+
+    pub fn cancel(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    /// The request got session `session_id`
+    pub fn opened(&self, session_id: &str) {
+        self.update(|request| {
+            request.state = RequestState::Session;
+            request.session_id = Some(session_id.to_string());
+        });
+    }
+
+    /// The request's results are being prepared for the app
+    pub fn delivering(&self) {
+        self.update(|request| request.state = RequestState::Delivering);
+    }
+
+    /// The request's results come from `provenance`
+    pub fn produced(&self, provenance: Provenance) {
+        *self.provenance.lock().unwrap_or_else(|e| e.into_inner()) = Some(provenance);
+    }
+
+    /// `results` are returned to the app: log them with their provenance.
+    /// Only the first call after [`InFlight::produced`] is logged.
+    pub fn delivered(&self, results: &[String]) {
+        let provenance = self
+            .provenance
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        let Some(provenance) = provenance else {
+            return;
+        };
+        let (portal, operation) = (self.operation.portal().as_str(), self.operation.as_str());
+        info!(
+            portal,
+            operation,
+            app_id = self.app_id,
+            source = provenance.source(),
+            %provenance,
+            results = results.len(),
+            "Results delivered"
+        );
+        let entry = history::Entry {
+            time: utc_timestamp(SystemTime::now()),
+            portal: portal.to_string(),
+            operation: operation.to_string(),
+            app_id: self.app_id.clone(),
+            provenance,
+            results: results.to_vec(),
+        };
+        if let Err(e) = history::append(&entry) {
+            warn!("Failed to add the results to the history: {e}");
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut RequestInfo)) {
+        let mut st = self.state.write().unwrap_or_else(|e| e.into_inner());
+        st.requests.update(self.key, change);
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut st = self.state.write().unwrap_or_else(|e| e.into_inner());
+        st.requests.requests.remove(&self.key);
+    }
+}
@@ -0,0 +1,236 @@
+//! Inline mode: sessions run on the daemon's terminal.
+//!
+//! `porttyd --inline` runs each session's `inline_exec` (the user's shell by
+//! default) on the terminal the daemon was started from, or on the TTY device
+//! given with `--inline <tty>`, instead of spawning a terminal emulator. This
+//! suits single-console machines.
+//!
+//! Sessions take turns: one [`Lease`] holds the terminal at a time. The session
+//! process gets its own process group in the terminal's foreground, and when
+//! it ends the terminal settings and foreground group are restored.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::MaybeUninit;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+
+use tracing::{debug, warn};
+
+use crate::attention;
+
+/// Written when a session gives the terminal back: leave the alternate
+/// screen, show the cursor, and reset attributes (only the line break on a
+/// dumb terminal)
+const RESET: &[u8] = b"\x1b[?1049l\x1b[?25h\x1b[0m\r\n";
+
+/// Run `f` with SIGTTOU blocked on the calling thread.
+///
+/// The daemon hands the foreground over and back, and restores terminal
+/// settings, while it may be in the background itself; the terminal lets a
+/// process that blocks SIGTTOU do so instead of stopping it. Blocking it on
+/// one thread leaves the rest of the process, and an embedding program's
+/// own handling of the signal, alone. Async-signal-safe, for `pre_exec`.
+fn without_sigttou<T>(f: impl FnOnce() -> T) -> T {
+    let mut block = MaybeUninit::<libc::sigset_t>::uninit();
+    let mut previous = MaybeUninit::<libc::sigset_t>::uninit();
+    // SAFETY: sigemptyset initializes `block`, and pthread_sigmask fills
+    // `previous` before it is restored
+    unsafe {
+        libc::sigemptyset(block.as_mut_ptr());
+        libc::sigaddset(block.as_mut_ptr(), libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, block.as_ptr(), previous.as_mut_ptr());
+    }
+    let result = f();
+    // SAFETY: `previous` was filled by the pthread_sigmask call above
+    unsafe { libc::pthread_sigmask(libc::SIG_SETMASK, previous.as_ptr(), std::ptr::null_mut()) };
+    result
+}
+
+/// The terminal sessions run on in inline mode
+#[derive(Debug)]
+pub struct InlineTty {
+    path: PathBuf,
+    tty: File,
+    /// The terminal is the daemon's controlling terminal
+    controlling: bool,
+    busy: Mutex<bool>,
+    free: Condvar,
+}
+
+impl InlineTty {
+    /// Open the TTY device `path`, or the daemon's controlling terminal.
+    pub fn open(path: Option<&Path>) -> io::Result<Self> {
+        let path = path.unwrap_or(Path::new("/dev/tty")).to_path_buf();
+        let tty = File::options()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+
+        let fd = tty.as_raw_fd();
+        // SAFETY: `fd` is a valid open file descriptor
+        if unsafe { libc::isatty(fd) } != 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a terminal", path.display()),
+            ));
+        }
+        // SAFETY: plain syscalls on a valid fd and the calling process
+        let controlling = unsafe { libc::tcgetsid(fd) == libc::getsid(0) };
+
+        Ok(Self {
+            path,
+            tty,
+            controlling,
+            busy: Mutex::new(false),
+            free: Condvar::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Wait until no other session holds the terminal, then take it.
+    ///
+    /// Blocks; call from a blocking thread.
+    pub fn acquire(self: Arc<Self>) -> Lease {
+        let mut busy = self.busy.lock().unwrap_or_else(|e| e.into_inner());
+        while *busy {
+            busy = self.free.wait(busy).unwrap_or_else(|e| e.into_inner());
+        }
+        *busy = true;
+        drop(busy);
+
+        let fd = self.tty.as_raw_fd();
+        let mut termios = MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: `termios` is only read if tcgetattr filled it
+        let termios = (unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) } == 0)
+            .then(|| unsafe { termios.assume_init() });
+        // SAFETY: plain syscall on a valid fd
+        let foreground = unsafe { libc::tcgetpgrp(fd) };
+
+        Lease {
+            tty: self,
+            termios,
+            foreground,
+            group: None,
+        }
+    }
+}
+
+/// A session's hold on the inline terminal. Restores the terminal and lets
+/// the next session have it when dropped.
+pub struct Lease {
+    tty: Arc<InlineTty>,
+    /// Terminal settings before the session
+    termios: Option<libc::termios>,
+    /// Foreground process group before the session
+    foreground: libc::pid_t,
+    /// Process group of the running session process
+    group: Option<libc::pid_t>,
+}
+
+impl std::fmt::Debug for Lease {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Lease")
+            .field("tty", &self.tty.path)
+            .field("foreground", &self.foreground)
+            .field("group", &self.group)
+            .finish()
+    }
+}
+
+impl Lease {
+    /// Print a heading for the session about to start.
+    pub fn announce(&self, heading: &str) {
+        let heading: String = heading.chars().filter(|c| !c.is_control()).collect();
+        without_sigttou(|| write!(&self.tty.tty, "\r\n{heading}\r\n")).ok();
+    }
+
+    /// Run `cmd` on the terminal, as its foreground process group.
+    ///
+    /// On the daemon's own terminal the process gets a new group in the
+    /// daemon's session; on another TTY it starts a session that takes the TTY
+    /// as its controlling terminal.
+    pub fn prepare(&self, cmd: &mut Command) -> io::Result<()> {
+        cmd.stdin(Stdio::from(self.tty.tty.try_clone()?))
+            .stdout(Stdio::from(self.tty.tty.try_clone()?))
+            .stderr(Stdio::from(self.tty.tty.try_clone()?));
+
+        let controlling = self.tty.controlling;
+        // SAFETY: only async-signal-safe syscalls between fork and exec
+        unsafe {
+            cmd.pre_exec(move || {
+                if controlling {
+                    // A new group is in the background until it takes the
+                    // foreground; the exec'd process gets the usual mask back
+                    if libc::setpgid(0, 0) != 0
+                        || without_sigttou(|| libc::tcsetpgrp(0, libc::getpid())) != 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                } else if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Record the process started with [`Lease::prepare`].
+    pub fn started(&mut self, pid: u32) {
+        self.group = Some(pid as libc::pid_t);
+    }
+
+    /// Take the terminal back from the session process: hang up what is left
+    /// of its process group and restore the terminal as it was before.
+    pub fn restore(&mut self) {
+        let Some(group) = self.group.take() else {
+            return;
+        };
+        // SAFETY: plain syscalls; `group` is the session's own process group
+        unsafe { libc::killpg(group, libc::SIGHUP) };
+
+        let fd = self.tty.tty.as_raw_fd();
+        let reset = if attention::dumb_terminal() {
+            &RESET[RESET.len() - 2..]
+        } else {
+            RESET
+        };
+        without_sigttou(|| {
+            let _ = (&self.tty.tty).write_all(reset);
+            if let Some(termios) = &self.termios {
+                // SAFETY: `termios` was filled by tcgetattr on the same fd
+                if unsafe { libc::tcsetattr(fd, libc::TCSANOW, termios) } != 0 {
+                    warn!(tty = %self.tty.path.display(), "Failed to restore terminal settings: {}", io::Error::last_os_error());
+                }
+            }
+            if self.tty.controlling && self.foreground > 0 {
+                // SAFETY: plain syscall on a valid fd
+                unsafe { libc::tcsetpgrp(fd, self.foreground) };
+            }
+        });
+        debug!(tty = %self.tty.path.display(), "Restored inline terminal");
+    }
+
+    /// The terminal, for prompting on it after [`Lease::restore`]
+    pub fn tty(&self) -> &File {
+        &self.tty.tty
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.restore();
+        *self.tty.busy.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        self.tty.free.notify_one();
+    }
+}
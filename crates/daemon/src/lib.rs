@@ -0,0 +1,255 @@
+//! porttyd, the xdg-desktop-portal backend running portal requests in
+//! terminals
+//!
+//! The `porttyd` binary is [`main_with`] and the built-in portals. A crate
+//! adding a portal implements [`registry::PortalPlugin`] and builds its own
+//! binary the same way:
+//!
+//! ```ignore
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let mut portals = porttyd::registry::Registry::builtin();
+//!     portals.register(MyPortal);
+//!     porttyd::main_with(portals)
+//! }
+//! ```
+//!
+//! A process serving the portals itself, without the command line or the
+//! logging setup of `porttyd`, builds a [`Daemon`] and stops it with its
+//! [`ShutdownHandle`].
+
+#![feature(linux_pidfd)]
+#![feature(unix_mkfifo)]
+
+mod attention;
+mod config;
+mod daemon_socket;
+mod dbus;
+mod documents;
+mod environ;
+mod inflight;
+mod inline;
+mod oneshot;
+mod permissions;
+mod portal;
+mod priority;
+pub mod registry;
+mod server;
+mod session;
+mod shutdown;
+mod simulate;
+mod tabs;
+mod umask;
+mod validator;
+
+use std::path::PathBuf;
+
+use futures_lite::future;
+use libportty::ExitStatus;
+use registry::Registry;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+pub use inline::InlineTty;
+pub use libportty::config::Config;
+pub use server::{Daemon, DaemonBuilder};
+pub use shutdown::ShutdownHandle;
+
+const SYNOPSIS: &str = "porttyd [--inline [TTY]] [--oneshot PORTAL OPERATION [OPTION...]]
+       porttyd --simulate PORTAL OPERATION [OPTION...] [--] [ENTRY...]";
+
+fn options() -> String {
+    format!(
+        "  --inline [TTY]  run sessions on this terminal (or the TTY device) instead of
+                  spawning a terminal emulator
+{}
+{}",
+        oneshot::USAGE,
+        simulate::USAGE
+    )
+}
+
+/// How this porttyd was built, reported by `status` and `GetVersion`
+pub(crate) fn build_info() -> String {
+    let features: &[&str] = if cfg!(feature = "wayland-picker") {
+        &["wayland-picker"]
+    } else {
+        &[]
+    };
+    libportty::build_info::describe(features)
+}
+
+fn usage() -> String {
+    format!("usage: {SYNOPSIS}\n\n{}", options())
+}
+
+/// porttyd(1), from the usage text (`--man`, for packaging)
+fn man_page() -> String {
+    // Keep text from being read as roff requests or escapes
+    let escape = |text: &str| {
+        text.lines()
+            .map(|line| format!("\\&{}\n", line.replace('\\', "\\e").replace('-', "\\-")))
+            .collect::<String>()
+    };
+    format!(
+        r#".TH porttyd 1 "" "porttyd {version}" "portty manual"
+.SH NAME
+porttyd \- terminal backend for xdg\-desktop\-portal
+.SH SYNOPSIS
+.nf
+{synopsis}.fi
+.SH DESCRIPTION
+Serves the xdg\-desktop\-portal backend interfaces
+(org.freedesktop.impl.portal.desktop.tty) on the session bus. Each portal
+request runs as a session in a terminal, driven with \fBportty\fR(1) and the
+shims on its PATH. Usually started by D\-Bus activation or the portty.service
+user unit.
+.SH OPTIONS
+.nf
+{options}.fi
+.SH "EXIT STATUS"
+0 on success; 1 on failure; 4 when \fB\-\-simulate\fR entries are refused;
+6 when the \fB\-\-oneshot\fR dialog was cancelled;
+64 for invalid arguments. See \fBportty\fR(1) for the full list.
+.SH ENVIRONMENT
+.TP
+\fBPORTTY_CONFIG\fR
+Config file to read instead of $XDG_CONFIG_HOME/portty/config.toml.
+.TP
+\fBPORTTY_BASE_DIR\fR
+Directory for the socket, sessions, and queues, instead of the config's
+\fBbase_dir\fR or \fI$TMPDIR/portty/UID\fR.
+.TP
+\fBRUST_LOG\fR
+Log filter (default porttyd=info).
+.SH FILES
+.TP
+\fI$XDG_CONFIG_HOME/portty/config.toml\fR
+Configuration, see \fBportty\-config\fR(5).
+.TP
+\fI$TMPDIR/portty/UID/daemon.sock\fR
+Control socket used by \fBportty\fR(1) (\fI/tmp\fR when \fBTMPDIR\fR is
+unset; \fBbase_dir\fR in the config or \fBPORTTY_BASE_DIR\fR moves it).
+.SH "SEE ALSO"
+\fBportty\fR(1), \fBportty\-config\fR(5)
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+        synopsis = escape(SYNOPSIS),
+        options = escape(&options()),
+    )
+}
+
+/// Command-line options
+#[derive(Debug, Default)]
+struct Args {
+    /// `--inline`, with its TTY device if given
+    inline: Option<Option<PathBuf>>,
+    /// `--oneshot`: run this request instead of serving D-Bus
+    oneshot: Option<oneshot::Request>,
+    /// `--simulate`: describe this request and check the entries after it
+    simulate: Option<(oneshot::Request, Vec<String>)>,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Self::default();
+        let mut argv = std::env::args().skip(1).peekable();
+        while let Some(arg) = argv.next() {
+            match arg.as_str() {
+                "--inline" => {
+                    let tty = argv.next_if(|next| !next.starts_with('-'));
+                    args.inline = Some(tty.map(PathBuf::from));
+                }
+                "--oneshot" => {
+                    args.oneshot = Some(oneshot::Request::parse("--oneshot", &mut argv)?)
+                }
+                "--simulate" => {
+                    let request = oneshot::Request::parse("--simulate", &mut argv)?;
+                    args.simulate = Some((request, argv.by_ref().collect()));
+                }
+                "-h" | "--help" => {
+                    println!("{}", usage());
+                    std::process::exit(0);
+                }
+                "--man" => {
+                    print!("{}", man_page());
+                    std::process::exit(0);
+                }
+                _ => match arg.strip_prefix("--inline=") {
+                    Some(tty) => args.inline = Some(Some(PathBuf::from(tty))),
+                    None => return Err(format!("unexpected argument {arg:?}\n\n{}", usage())),
+                },
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Run porttyd with the command line of the process, serving `portals`
+pub fn main_with(portals: Registry) -> Result<(), Box<dyn std::error::Error>> {
+    umask::restrict();
+    config::Config::configure_base_dir();
+    config::Config::configure_listen();
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(ExitStatus::Usage.code().into());
+        }
+    };
+
+    // In oneshot and simulate mode stdout carries the result
+    let writer = if args.oneshot.is_some() || args.simulate.is_some() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env().add_directive("porttyd=info".parse()?))
+        .with_writer(writer)
+        .init();
+
+    if let Some((request, entries)) = args.simulate {
+        if !simulate::run(request, &entries, &config::load(&portals.config_sections()))? {
+            std::process::exit(ExitStatus::Invalid.code().into());
+        }
+        return Ok(());
+    }
+
+    let inline = args
+        .inline
+        .map(|tty| InlineTty::open(tty.as_deref()))
+        .transpose()
+        .map_err(|e| format!("--inline: {e}"))?;
+
+    if let Some(request) = args.oneshot {
+        let result =
+            future::block_on(request.run(config::load(&portals.config_sections()), inline))?;
+        match result {
+            Some(uris) => uris.iter().for_each(|uri| println!("{uri}")),
+            None => {
+                eprintln!("Cancelled");
+                std::process::exit(ExitStatus::Cancelled.code().into());
+            }
+        }
+        return Ok(());
+    }
+
+    future::block_on(async {
+        info!(
+            version = env!("CARGO_PKG_VERSION"),
+            build = %build_info(),
+            "Starting xdg-desktop-portal-tty..."
+        );
+
+        let config = config::load(&portals.config_sections());
+        info!(?config, "Config loaded");
+        if let Some(tty) = &inline {
+            info!(tty = %tty.path().display(), "Inline mode: sessions run on this terminal");
+        }
+
+        Daemon::new(config, inline, portals).run().await?;
+
+        Ok(())
+    })
+}
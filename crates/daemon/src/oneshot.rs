@@ -0,0 +1,192 @@
+//! `porttyd --oneshot`: one simulated request, without D-Bus.
+//!
+//! Builds the request options from the command line, runs a session the way
+//! a portal request would (configured `exec` or `--inline`, shims, queued
+//! submissions, validation, `rewrite` rules), prints the resulting URIs to
+//! stdout, and exits. Meant for iterating on picker scripts without a portal
+//! stack; apps are not involved.
+//!
+//! The daemon socket is served for the session's `portty` commands, so a
+//! running porttyd has to be stopped first.
+
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, RwLock};
+
+use futures_lite::future;
+use libportty::portal::file_chooser::{SelectionMode, SessionOptions, save_file_hint};
+use libportty::{Operation, paths};
+use tracing::warn;
+
+use crate::config::{Config, ConfigHandle};
+use crate::daemon_socket::{DaemonCtl, DaemonSocket, DaemonState};
+use crate::dbus::request::CancelToken;
+use crate::inflight::InFlight;
+use crate::inline::InlineTty;
+use crate::portal::{self, SessionError};
+use crate::shutdown;
+
+pub const USAGE: &str = "  --oneshot PORTAL OPERATION [OPTION...]
+                  run one request without D-Bus and print the resulting URIs
+                  (file-chooser open-file, save-file, or save-files):
+      --title TEXT      dialog title
+      --app-id ID       requesting app id
+      --folder DIR      current folder
+      --multiple        open-file: allow several files
+      --directory       open-file: pick directories
+      --name NAME       save-file: proposed file name
+      --file NAME       save-files: a file to save (repeatable)
+      --filter NAME=PATTERN[,PATTERN...]
+                        a file filter (repeatable); patterns with a `/`
+                        are MIME types, others globs";
+
+/// A request given on the command line
+#[derive(Debug)]
+pub struct Request {
+    pub operation: Operation,
+    pub options: SessionOptions,
+}
+
+impl Request {
+    /// Parse `PORTAL OPERATION [OPTION...]`, the arguments after `flag`
+    /// (`--oneshot` or `--simulate`). Options end at `--` (consumed) or the
+    /// first argument that is not an option (left in `argv`).
+    pub fn parse<I: Iterator<Item = String>>(
+        flag: &str,
+        argv: &mut Peekable<I>,
+    ) -> Result<Self, String> {
+        let portal = argv
+            .next()
+            .ok_or_else(|| format!("{flag}: missing portal"))?;
+        let operation = argv
+            .next()
+            .ok_or_else(|| format!("{flag}: missing operation"))?;
+        let operation = Operation::of(&portal, &operation).map_err(|e| format!("{flag}: {e}"))?;
+        let mode = match operation {
+            Operation::OpenFile => SelectionMode::Pick {
+                multiple: false,
+                directory: false,
+            },
+            Operation::SaveFile => SelectionMode::Save,
+            Operation::SaveFiles => SelectionMode::SaveMultiple,
+            _ => {
+                return Err(format!(
+                    "{flag}: unsupported portal {portal:?} (only file-chooser)"
+                ));
+            }
+        };
+
+        let mut options = SessionOptions {
+            title: format!("portty {} {operation}", flag.trim_start_matches('-')),
+            mode,
+            current_folder: None,
+            candidates: Vec::new(),
+            filters: Vec::new(),
+            current_filter: None,
+            app_id: String::new(),
+            parent_window: String::new(),
+            hint: None,
+            conflicts: None,
+            per_file: None,
+            restrict_to: None,
+            choices: Vec::new(),
+            chosen: BTreeMap::new(),
+        };
+        while let Some(arg) = argv.next_if(|arg| arg.starts_with('-')) {
+            if arg == "--" {
+                break;
+            }
+            let mut value = || {
+                argv.next()
+                    .ok_or_else(|| format!("{flag}: {arg} needs a value"))
+            };
+            match (arg.as_str(), &mut options.mode) {
+                ("--title", _) => options.title = value()?,
+                ("--app-id", _) => options.app_id = value()?,
+                ("--folder", _) => options.current_folder = Some(value()?),
+                ("--multiple", SelectionMode::Pick { multiple, .. }) => *multiple = true,
+                ("--directory", SelectionMode::Pick { directory, .. }) => *directory = true,
+                ("--name", SelectionMode::Save) => options.candidates = vec![value()?],
+                ("--file", SelectionMode::SaveMultiple) => options.candidates.push(value()?),
+                ("--filter", _) => {
+                    let filter = value()?;
+                    options
+                        .filters
+                        .push(filter.parse().map_err(|e| format!("{flag}: {e}"))?);
+                }
+                ("--multiple" | "--directory" | "--name" | "--file", _) => {
+                    return Err(format!("{flag}: {arg} does not apply to {operation}"));
+                }
+                _ => return Err(format!("{flag}: unexpected argument {arg:?}")),
+            }
+        }
+        if options.mode == SelectionMode::Save {
+            options.hint = Some(save_file_hint(
+                options.candidates.first().map(String::as_str),
+            ));
+        }
+
+        Ok(Self { operation, options })
+    }
+
+    /// Run the request, returning the URIs the app would receive. `Ok(None)`
+    /// means the session was cancelled.
+    pub async fn run(
+        self,
+        config: Config,
+        inline: Option<InlineTty>,
+    ) -> Result<Option<Vec<String>>, String> {
+        if UnixStream::connect(paths::daemon_socket_path()).is_ok() {
+            return Err("porttyd is already running; stop it before using --oneshot".into());
+        }
+
+        let cli = config.resolve_cli();
+        let config = Arc::new(ConfigHandle::new(config));
+        let state = Arc::new(RwLock::new(DaemonState::new(inline.map(Arc::new), cli)));
+        DaemonSocket::new(Arc::clone(&state), Arc::clone(&config))
+            .map_err(|e| format!("failed to create daemon socket: {e}"))?
+            .spawn();
+        match DaemonCtl::new(Arc::clone(&state), Arc::clone(&config)) {
+            Ok(daemon_ctl) => {
+                daemon_ctl.spawn();
+            }
+            Err(e) => warn!("Failed to create daemon FIFO: {e}"),
+        }
+
+        let request = InFlight::start(
+            &state,
+            String::new(),
+            self.operation,
+            &self.options.app_id,
+            CancelToken::default(),
+        );
+        // `portty daemon stop` cancels the session
+        let shutdown = state
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .shutdown
+            .clone();
+        let result = future::or(
+            portal::file_chooser::run(self.operation, &self.options, &config, &state, &request),
+            async {
+                shutdown.wait().await;
+                shutdown::cancel_sessions(&state);
+                std::future::pending().await
+            },
+        )
+        .await;
+        drop(request);
+        if shutdown.is_requested() {
+            shutdown::wind_down(&state).await;
+        }
+        // Nothing serves the socket once this process exits
+        let _ = std::fs::remove_file(paths::daemon_socket_path());
+        shutdown::remove_socket_files();
+        match result {
+            Ok((uris, _)) => Ok(Some(uris)),
+            Err(SessionError::Cancelled) => Ok(None),
+            Err(SessionError::Other(msg)) => Err(msg),
+        }
+    }
+}
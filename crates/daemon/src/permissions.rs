@@ -0,0 +1,168 @@
+//! Per-app decisions kept in the permission store.
+//!
+//! Decisions made with `portty permissions set` live in the `portty` table of
+//! `org.freedesktop.impl.portal.PermissionStore`, where the other portal
+//! backends keep theirs: one entry per operation (`screenshot.screenshot`)
+//! holding `yes` (allow) or `no` (deny) for each app. Requests are checked
+//! against it before a session is opened.
+
+use std::collections::HashMap;
+
+use libportty::{Decision, Operation, PermissionInfo, Portal};
+use tracing::debug;
+use zbus::zvariant::OwnedValue;
+
+/// Permission store table holding portty's decisions
+const TABLE: &str = "portty";
+
+/// Error for a table or entry that does not exist (yet)
+const NOT_FOUND: &str = "org.freedesktop.portal.Error.NotFound";
+
+#[zbus::proxy(
+    interface = "org.freedesktop.impl.portal.PermissionStore",
+    default_service = "org.freedesktop.impl.portal.PermissionStore",
+    default_path = "/org/freedesktop/impl/portal/PermissionStore"
+)]
+trait PermissionStore {
+    fn lookup(
+        &self,
+        table: &str,
+        id: &str,
+    ) -> zbus::Result<(HashMap<String, Vec<String>>, OwnedValue)>;
+
+    fn set_permission(
+        &self,
+        table: &str,
+        create: bool,
+        id: &str,
+        app: &str,
+        permissions: &[&str],
+    ) -> zbus::Result<()>;
+
+    fn delete_permission(&self, table: &str, id: &str, app: &str) -> zbus::Result<()>;
+}
+
+/// Store entry of an operation
+fn entry_id(operation: Operation) -> String {
+    format!("{}.{operation}", operation.portal())
+}
+
+fn decode(permissions: &[String]) -> Option<Decision> {
+    match permissions.first()?.as_str() {
+        "yes" => Some(Decision::Allow),
+        "no" => Some(Decision::Deny),
+        _ => None,
+    }
+}
+
+fn encode(decision: Decision) -> &'static str {
+    match decision {
+        Decision::Allow => "yes",
+        Decision::Deny => "no",
+    }
+}
+
+async fn store() -> zbus::Result<PermissionStoreProxy<'static>> {
+    let conn = zbus::Connection::session().await?;
+    PermissionStoreProxy::new(&conn).await
+}
+
+/// Decisions of every app for `operation`. A missing entry has none.
+async fn lookup(
+    store: &PermissionStoreProxy<'_>,
+    operation: Operation,
+) -> zbus::Result<HashMap<String, Vec<String>>> {
+    match store.lookup(TABLE, &entry_id(operation)).await {
+        Ok((apps, _)) => Ok(apps),
+        Err(zbus::Error::MethodError(name, _, _)) if name.as_str() == NOT_FOUND => {
+            Ok(HashMap::new())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The decision remembered for `app_id`'s requests of `operation`.
+///
+/// Unsandboxed apps (empty `app_id`) have none, and an unreachable store
+/// means no decision: the request opens a session as usual.
+pub async fn decision(app_id: &str, operation: Operation) -> Option<Decision> {
+    if app_id.is_empty() {
+        return None;
+    }
+    let apps = match store().await {
+        Ok(store) => lookup(&store, operation).await,
+        Err(e) => Err(e),
+    };
+    match apps {
+        Ok(apps) => decode(apps.get(app_id)?),
+        Err(e) => {
+            debug!("Permission store unavailable: {e}");
+            None
+        }
+    }
+}
+
+/// Decisions of all apps, or of `app_id`, by app and then operation
+pub async fn list(app_id: Option<&str>) -> Result<Vec<PermissionInfo>, String> {
+    let store = store().await.map_err(|e| e.to_string())?;
+    let mut permissions = Vec::new();
+    for &operation in Portal::ALL.iter().flat_map(|portal| portal.operations()) {
+        let apps = lookup(&store, operation).await.map_err(|e| e.to_string())?;
+        for (app, decision) in apps {
+            if app_id.is_some_and(|app_id| app_id != app) {
+                continue;
+            }
+            if let Some(decision) = decode(&decision) {
+                permissions.push(PermissionInfo {
+                    app_id: app,
+                    operation,
+                    decision,
+                });
+            }
+        }
+    }
+    permissions.sort_by(|a, b| a.app_id.cmp(&b.app_id));
+    Ok(permissions)
+}
+
+/// Remember `decision` for `app_id`'s requests of `operation`
+pub async fn set(app_id: &str, operation: Operation, decision: Decision) -> Result<(), String> {
+    let store = store().await.map_err(|e| e.to_string())?;
+    store
+        .set_permission(
+            TABLE,
+            true,
+            &entry_id(operation),
+            app_id,
+            &[encode(decision)],
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Forget `app_id`'s decision for `operation`, or all of them. Returns how
+/// many were removed.
+pub async fn clear(app_id: &str, operation: Option<Operation>) -> Result<usize, String> {
+    let store = store().await.map_err(|e| e.to_string())?;
+    let operations = match operation {
+        Some(operation) => vec![operation],
+        None => Portal::ALL
+            .iter()
+            .flat_map(|portal| portal.operations())
+            .copied()
+            .collect(),
+    };
+    let mut removed = 0;
+    for operation in operations {
+        let apps = lookup(&store, operation).await.map_err(|e| e.to_string())?;
+        if !apps.contains_key(app_id) {
+            continue;
+        }
+        store
+            .delete_permission(TABLE, &entry_id(operation), app_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        removed += 1;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use libportty::{Operation, paths};
+use tracing::{info, instrument, warn};
+
+use crate::config::ConfigHandle;
+use crate::daemon_socket::DaemonState;
+use crate::dbus::dynamic_launcher::{
+    DynamicLauncherError, DynamicLauncherHandler, PrepareInstallOptions, RequestInstallTokenOptions,
+};
+use crate::dbus::request::CancelToken;
+use crate::inflight::InFlight;
+
+pub use libportty::portal::dynamic_launcher::{
+    APPLICATION, SessionOptions, WEBAPP, launcher_type_name,
+};
+
+/// Dynamic launcher handler that confirms installs in a terminal.
+///
+/// The backend only decides whether (and under which name) a launcher may be
+/// installed; the portal frontend writes the `.desktop` file and icon itself
+/// when the app calls `Install`.
+pub struct TtyDynamicLauncher {
+    config: Arc<ConfigHandle>,
+    state: Arc<RwLock<DaemonState>>,
+}
+
+impl TtyDynamicLauncher {
+    pub fn new(config: Arc<ConfigHandle>, state: Arc<RwLock<DaemonState>>) -> Self {
+        Self { config, state }
+    }
+}
+
+/// Copy of a launcher icon shown to the user during review, removed on drop
+struct ReviewIcon(PathBuf);
+
+impl ReviewIcon {
+    /// Write `data` under the base dir if it is a PNG, JPEG, or SVG image
+    fn write(data: &[u8]) -> Option<Self> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        let ext = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            "png"
+        } else if data.starts_with(b"\xff\xd8\xff") {
+            "jpg"
+        } else if data.starts_with(b"<svg") || data.starts_with(b"<?xml") {
+            "svg"
+        } else {
+            warn!("Launcher icon is not PNG, JPEG, or SVG, not showing it");
+            return None;
+        };
+
+        let dir = paths::base_dir().join("icons");
+        let path = dir.join(format!(
+            "launcher-{}-{}.{ext}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = paths::ensure_base_dir()
+            .and_then(|()| std::fs::create_dir_all(&dir))
+            .and_then(|()| std::fs::write(&path, data));
+        match written {
+            Ok(()) => Some(Self(path)),
+            Err(e) => {
+                warn!(path = %path.display(), "Failed to write launcher icon: {e}");
+                None
+            }
+        }
+    }
+}
+
+impl Drop for ReviewIcon {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+impl DynamicLauncherHandler for TtyDynamicLauncher {
+    fn supported_launcher_types(&self) -> u32 {
+        APPLICATION | WEBAPP
+    }
+
+    #[instrument(skip(self, parent_window, icon, options))]
+    async fn prepare_install(
+        &self,
+        handle: String,
+        cancel: CancelToken,
+        app_id: String,
+        parent_window: String,
+        name: String,
+        icon: Option<Vec<u8>>,
+        options: PrepareInstallOptions,
+    ) -> Result<String, DynamicLauncherError> {
+        let launcher_type = launcher_type_name(options.launcher_type().unwrap_or(APPLICATION));
+        info!(launcher_type, target = ?options.target(), "PrepareInstall request");
+
+        let request = InFlight::start(
+            &self.state,
+            handle,
+            Operation::PrepareInstall,
+            &app_id,
+            cancel,
+        );
+        let icon = icon.as_deref().and_then(ReviewIcon::write);
+        let title = format!("Install launcher: {name}");
+        let session_options = SessionOptions {
+            app_id,
+            parent_window,
+            name: name.clone(),
+            launcher_type: launcher_type.to_string(),
+            target: options.target().map(String::from),
+            editable_name: options.editable_name().unwrap_or(true),
+            icon: icon
+                .as_ref()
+                .map(|icon| icon.0.to_string_lossy().into_owned()),
+        };
+
+        let options_json = serde_json::to_value(&session_options).map_err(|e| {
+            DynamicLauncherError::Other(format!("failed to serialize options: {e}"))
+        })?;
+
+        let entries = super::run_session(
+            Operation::PrepareInstall,
+            &options_json,
+            &[name],
+            Some(&title),
+            &self.config,
+            &self.state,
+            &request,
+        )
+        .await?;
+
+        request.delivered(&entries);
+        entries
+            .into_iter()
+            .next()
+            .ok_or_else(|| DynamicLauncherError::Other("no name returned from session".to_string()))
+    }
+
+    #[instrument(skip(self, _options))]
+    async fn request_install_token(
+        &self,
+        app_id: String,
+        _options: RequestInstallTokenOptions,
+    ) -> Result<(), DynamicLauncherError> {
+        info!("RequestInstallToken request");
+
+        // A plain method call: there is no Request object for the app to close
+        let request = InFlight::start(
+            &self.state,
+            String::new(),
+            Operation::RequestInstallToken,
+            &app_id,
+            CancelToken::default(),
+        );
+        let title = format!("Allow {app_id} to install launchers without asking");
+        let session_options = SessionOptions {
+            app_id,
+            ..Default::default()
+        };
+
+        let options_json = serde_json::to_value(&session_options).map_err(|e| {
+            DynamicLauncherError::Other(format!("failed to serialize options: {e}"))
+        })?;
+
+        let entries = super::run_session(
+            Operation::RequestInstallToken,
+            &options_json,
+            &[],
+            Some(&title),
+            &self.config,
+            &self.state,
+            &request,
+        )
+        .await?;
+        request.delivered(&entries);
+
+        Ok(())
+    }
+}
@@ -1,29 +1,41 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
-use tracing::{info, instrument};
+use tracing::{info, instrument, warn};
 
-use crate::config::Config;
+use super::SessionError;
+use crate::config::ConfigHandle;
 use crate::daemon_socket::DaemonState;
 use crate::dbus::file_chooser::{
-    FileChooserError, FileChooserHandler, FileChooserResult, FileFilter,
-    FilterPattern as PortalFilterPattern, OpenFileOptions, SaveFileOptions, SaveFilesOptions,
+    FileChooserError, FileChooserHandler, FileChooserResult, FileFilter, OpenFileOptions,
+    SaveFileOptions, SaveFilesOptions,
 };
+use crate::dbus::request::CancelToken;
+use crate::documents::Access;
+use crate::inflight::InFlight;
+use libportty::Operation;
+use libportty::portal::recent;
+use libportty::uri;
 
-pub use libportty::portal::file_chooser::{Filter, FilterPattern, SelectionMode, SessionOptions};
+pub use libportty::portal::file_chooser::{
+    Choice, FOLDER_HINT, Filter, SelectionMode, SessionOptions, check_within, save_file_hint,
+};
 
 /// Build initial submission entries from file chooser options
 fn build_initial_entries(options: &SessionOptions) -> Vec<String> {
     let mut entries = Vec::new();
-    if let Some(ref folder) = options.current_folder {
+    if let Some(folder) = options.folder() {
         match options.mode {
             SelectionMode::SaveMultiple if !options.candidates.is_empty() => {
-                entries.push(format!("file://{}", folder));
+                entries.push(uri::from_path(&folder));
             }
             SelectionMode::Save => {
                 if let Some(name) = options.candidates.first() {
-                    let path = Path::new(folder).join(name);
-                    entries.push(format!("file://{}", path.display()));
+                    let path = folder.join(SessionOptions::candidate_name(name));
+                    entries.push(uri::from_path(&path));
                 }
             }
             _ => {}
@@ -32,52 +44,167 @@ fn build_initial_entries(options: &SessionOptions) -> Vec<String> {
     entries
 }
 
-/// Convert a null-terminated D-Bus byte array to a String, stripping trailing nulls.
+/// The options a session is created with (`options` with the folder hint
+/// when it picks folders, and `default_filters` when it brings no filters),
+/// and the entries it starts with
+pub fn prepare(
+    options: &SessionOptions,
+    default_filters: Vec<Filter>,
+) -> (SessionOptions, Vec<String>) {
+    let hint =
+        (options.hint.is_none() && options.mode.picks_folder()).then(|| FOLDER_HINT.to_string());
+    let filters = if options.filters.is_empty() {
+        default_filters
+    } else {
+        options.filters.clone()
+    };
+    let options = SessionOptions {
+        hint: hint.or_else(|| options.hint.clone()),
+        filters,
+        ..options.clone()
+    };
+    let initial_entries = build_initial_entries(&options);
+    (options, initial_entries)
+}
+
+/// `options` limited to the `restrict_to` folder `root`: results outside it
+/// are refused, and a session outside it starts in it instead. A missing
+/// folder refuses the request rather than allowing any path.
+fn restrict(
+    options: &SessionOptions,
+    root: Option<PathBuf>,
+) -> Result<SessionOptions, SessionError> {
+    let mut options = options.clone();
+    let Some(root) = root else {
+        return Ok(options);
+    };
+    let root = root
+        .canonicalize()
+        .map_err(|e| SessionError::Other(format!("restrict_to {}: {e}", root.display())))?;
+    let outside = options
+        .folder()
+        .is_none_or(|folder| check_within(&folder, &root).is_err());
+    let root_option = uri::encode_os(root.as_os_str()).into_owned();
+    if outside {
+        info!(folder = %root.display(), "Starting in the restrict_to folder");
+        options.current_folder = Some(root_option.clone());
+    }
+    options.restrict_to = Some(root_option);
+    Ok(options)
+}
+
+/// The D-Bus answer to a file chooser request: the result `uris`, and the
+/// value of each of the dialog's `choices`
+fn answer(uris: Vec<String>, choices: Vec<(String, String)>) -> FileChooserResult {
+    choices.into_iter().fold(
+        FileChooserResult::new().uris(uris),
+        |result, (id, value)| result.choice(id, value),
+    )
+}
+
+/// Run a file chooser session for `operation` and prepare its result URIs
+/// for the app, along with the value of each of the dialog's choices.
+/// Shared by the D-Bus handlers and `porttyd --oneshot`.
+pub async fn run(
+    operation: Operation,
+    options: &SessionOptions,
+    config: &Arc<ConfigHandle>,
+    state: &Arc<RwLock<DaemonState>>,
+    request: &InFlight,
+) -> Result<(Vec<String>, Vec<(String, String)>), SessionError> {
+    let (portal, op) = (operation.portal().as_str(), operation.as_str());
+    let restricted = restrict(options, config.get().resolve_restrict_to(portal, op))?;
+    let default_filters = config.get().resolve_default_filters(portal, op);
+    let (options, initial_entries) = &prepare(&restricted, default_filters);
+    let options_json = serde_json::to_value(options)
+        .map_err(|e| SessionError::Other(format!("failed to serialize options: {e}")))?;
+
+    let (entries, chosen) = super::run_session_with_choices(
+        operation,
+        &options_json,
+        initial_entries,
+        Some(&options.title),
+        config,
+        state,
+        request,
+    )
+    .await?;
+
+    if config.get().resolve_record_recent(portal, op) {
+        let app = match options.app_id.as_str() {
+            "" => recent::DEFAULT_APP,
+            app_id => app_id,
+        };
+        if let Err(e) = recent::record(&entries, app) {
+            warn!("Failed to record recently used files: {e}");
+        }
+    }
+
+    let access = if operation == Operation::OpenFile {
+        Access::Read
+    } else {
+        Access::Write
+    };
+    // The choices picked in the session, else the defaults
+    let choices = serde_json::from_value::<SessionOptions>(chosen)
+        .map_or_else(|_| options.choice_values(), |chosen| chosen.choice_values());
+    let uris =
+        super::deliver_uris(config, operation, &options_json, entries, access, request).await;
+    Ok((uris, choices))
+}
+
+/// Convert a null-terminated D-Bus byte array to a String, stripping trailing
+/// nulls. A path or name that is not UTF-8 is escaped (see
+/// [`uri::encode_os`]) rather than losing bytes.
 fn bytes_to_string(b: &[u8]) -> String {
     let b = if b.last() == Some(&0) {
         &b[..b.len() - 1]
     } else {
         b
     };
-    String::from_utf8_lossy(b).into_owned()
+    uri::encode_os(OsStr::from_bytes(b)).into_owned()
 }
 
-/// Convert D-Bus filters to our filter type
-fn convert_filters(filters: &[FileFilter]) -> Vec<Filter> {
-    filters
-        .iter()
-        .map(|f| Filter {
-            name: f.name().to_string(),
-            patterns: f
-                .patterns()
-                .map(|p| match p {
-                    PortalFilterPattern::Glob(s) => FilterPattern::Glob(s.to_string()),
-                    PortalFilterPattern::MimeType(s) => FilterPattern::MimeType(s.to_string()),
-                })
-                .collect(),
-        })
-        .collect()
+/// The request's filters and the index of its current filter. A current
+/// filter the app does not list is added after the others.
+fn convert_filters(
+    filters: &[FileFilter],
+    current: Option<&FileFilter>,
+) -> (Vec<Filter>, Option<usize>) {
+    let mut filters = filters.to_vec();
+    let current = current.map(|current| {
+        filters
+            .iter()
+            .position(|filter| filter == current)
+            .unwrap_or_else(|| {
+                filters.push(current.clone());
+                filters.len() - 1
+            })
+    });
+    let filters = filters.iter().map(Filter::from).collect();
+    (filters, current)
 }
 
 /// File chooser handler that spawns terminals
 pub struct TtyFileChooser {
-    config: Arc<Config>,
+    config: Arc<ConfigHandle>,
     state: Arc<RwLock<DaemonState>>,
 }
 
 impl TtyFileChooser {
-    pub fn new(config: Arc<Config>, state: Arc<RwLock<DaemonState>>) -> Self {
+    pub fn new(config: Arc<ConfigHandle>, state: Arc<RwLock<DaemonState>>) -> Self {
         Self { config, state }
     }
 }
 
 impl FileChooserHandler for TtyFileChooser {
-    #[instrument(skip(self, _parent_window, options))]
+    #[instrument(skip(self, parent_window, options))]
     async fn open_file(
         &self,
-        _handle: String,
-        _app_id: String,
-        _parent_window: String,
+        handle: String,
+        cancel: CancelToken,
+        app_id: String,
+        parent_window: String,
         title: String,
         options: OpenFileOptions,
     ) -> Result<FileChooserResult, FileChooserError> {
@@ -87,49 +214,59 @@ impl FileChooserHandler for TtyFileChooser {
             "OpenFile request"
         );
 
+        let request = InFlight::start(&self.state, handle, Operation::OpenFile, &app_id, cancel);
+        let (filters, current_filter) =
+            convert_filters(options.filters(), options.current_filter());
+
         let session_options = SessionOptions {
-            title: title.clone(),
+            title,
             mode: SelectionMode::Pick {
                 multiple: options.multiple().unwrap_or(false),
                 directory: options.directory().unwrap_or(false),
             },
             current_folder: options.current_folder().map(bytes_to_string),
             candidates: vec![],
-            filters: convert_filters(options.filters()),
-            current_filter: None,
+            filters,
+            current_filter,
+            app_id,
+            parent_window,
+            hint: None,
+            conflicts: None,
+            per_file: None,
+            restrict_to: None,
+            choices: options.choices().iter().map(Choice::from).collect(),
+            chosen: BTreeMap::new(),
         };
 
-        let initial_entries = build_initial_entries(&session_options);
-        let options_json = serde_json::to_value(&session_options)
-            .map_err(|e| FileChooserError::Other(format!("failed to serialize options: {e}")))?;
-
-        let entries = super::run_session(
-            "file-chooser",
-            "open-file",
-            &options_json,
-            &initial_entries,
-            Some(&title),
+        let (uris, choices) = run(
+            Operation::OpenFile,
+            &session_options,
             &self.config,
             &self.state,
+            &request,
         )
         .await?;
-
-        Ok(FileChooserResult::new().uris(entries))
+        Ok(answer(uris, choices))
     }
 
-    #[instrument(skip(self, _parent_window, options))]
+    #[instrument(skip(self, parent_window, options))]
     async fn save_file(
         &self,
-        _handle: String,
-        _app_id: String,
-        _parent_window: String,
+        handle: String,
+        cancel: CancelToken,
+        app_id: String,
+        parent_window: String,
         title: String,
         options: SaveFileOptions,
     ) -> Result<FileChooserResult, FileChooserError> {
         info!(current_name = ?options.current_name(), "SaveFile request");
 
+        let request = InFlight::start(&self.state, handle, Operation::SaveFile, &app_id, cancel);
+        let (filters, current_filter) =
+            convert_filters(options.filters(), options.current_filter());
+
         let session_options = SessionOptions {
-            title: title.clone(),
+            title,
             mode: SelectionMode::Save,
             current_folder: options.current_folder().map(bytes_to_string),
             candidates: options
@@ -137,65 +274,70 @@ impl FileChooserHandler for TtyFileChooser {
                 .map(String::from)
                 .into_iter()
                 .collect(),
-            filters: convert_filters(options.filters()),
-            current_filter: None,
+            filters,
+            current_filter,
+            app_id,
+            parent_window,
+            hint: Some(save_file_hint(options.current_name())),
+            conflicts: None,
+            per_file: None,
+            restrict_to: None,
+            choices: options.choices().iter().map(Choice::from).collect(),
+            chosen: BTreeMap::new(),
         };
 
-        let initial_entries = build_initial_entries(&session_options);
-        let options_json = serde_json::to_value(&session_options)
-            .map_err(|e| FileChooserError::Other(format!("failed to serialize options: {e}")))?;
-
-        let entries = super::run_session(
-            "file-chooser",
-            "save-file",
-            &options_json,
-            &initial_entries,
-            Some(&title),
+        let (uris, choices) = run(
+            Operation::SaveFile,
+            &session_options,
             &self.config,
             &self.state,
+            &request,
         )
         .await?;
-
-        Ok(FileChooserResult::new().uris(entries))
+        Ok(answer(uris, choices))
     }
 
-    #[instrument(skip(self, _parent_window, options))]
+    #[instrument(skip(self, parent_window, options))]
     async fn save_files(
         &self,
-        _handle: String,
-        _app_id: String,
-        _parent_window: String,
+        handle: String,
+        cancel: CancelToken,
+        app_id: String,
+        parent_window: String,
         title: String,
         options: SaveFilesOptions,
     ) -> Result<FileChooserResult, FileChooserError> {
         let files: Vec<String> = options.files().iter().map(|f| bytes_to_string(f)).collect();
 
-        info!(?files, "SaveFiles request");
+        info!(files = files.len(), "SaveFiles request");
+
+        let request = InFlight::start(&self.state, handle, Operation::SaveFiles, &app_id, cancel);
 
         let session_options = SessionOptions {
-            title: title.clone(),
+            title,
             mode: SelectionMode::SaveMultiple,
             current_folder: options.current_folder().map(bytes_to_string),
-            candidates: files.clone(),
+            candidates: files,
             filters: Vec::new(),
             current_filter: None,
+            app_id,
+            parent_window,
+            hint: None,
+            conflicts: None,
+            per_file: None,
+            restrict_to: None,
+            choices: options.choices().iter().map(Choice::from).collect(),
+            chosen: BTreeMap::new(),
         };
 
-        let initial_entries = build_initial_entries(&session_options);
-        let options_json = serde_json::to_value(&session_options)
-            .map_err(|e| FileChooserError::Other(format!("failed to serialize options: {e}")))?;
-
-        let entries = super::run_session(
-            "file-chooser",
-            "save-files",
-            &options_json,
-            &initial_entries,
-            Some(&title),
+        let (uris, choices) = run(
+            Operation::SaveFiles,
+            &session_options,
             &self.config,
             &self.state,
+            &request,
         )
         .await?;
-
-        Ok(FileChooserResult::new().uris(entries))
+        Ok(answer(uris, choices))
     }
 }
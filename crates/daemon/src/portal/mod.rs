@@ -1,24 +1,57 @@
+pub mod dynamic_launcher;
 pub mod file_chooser;
+pub mod remote_desktop;
 pub mod screenshot;
+#[cfg(feature = "wayland-picker")]
+mod wayland_picker;
 
-use std::path::Path;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
-use tracing::{debug, info};
 
+use futures_lite::future;
+use tracing::{debug, info, warn};
+
+use crate::dbus::dynamic_launcher::DynamicLauncherError;
+use crate::dbus::events::SessionOutcome;
 use crate::dbus::file_chooser::FileChooserError;
+use crate::dbus::remote_desktop::RemoteDesktopError;
 use crate::dbus::screenshot::ScreenshotError;
 
-use crate::config::Config;
+use libportty::config::{AuditAction, rewrite};
+use libportty::portal::APPROVE;
+use libportty::portal::history::Provenance;
+use libportty::portal::options::SESSION_CHOICES;
+use libportty::portal::screenshot::screenshot_path;
+use libportty::{Decision, Operation, QueuePolicy, SessionMode, SortOrder};
+use libportty::{files, input, quota, uri};
+
+use crate::attention;
+use crate::config::{Config, ConfigHandle, Headless};
 use crate::daemon_socket::DaemonState;
-use crate::session::{SessionResult, drain_pending_to, pop_queued_submission};
+use crate::documents::{self, Access};
+use crate::inflight::InFlight;
+use crate::permissions;
+use crate::session::{
+    SessionControl, SessionPolicy, SessionResult, StaleReport, drain_pending_to,
+    pop_queued_submission, record_event,
+};
+use crate::tabs::Tabs;
+use crate::umask;
+use crate::validator;
 
+pub use dynamic_launcher::TtyDynamicLauncher;
 pub use file_chooser::TtyFileChooser;
+pub use remote_desktop::TtyRemoteDesktop;
 pub use screenshot::TtyScreenshot;
 
 /// Error from running a session
 #[derive(Debug)]
 pub enum SessionError {
+    /// The user or the app cancelled the request
     Cancelled,
+    /// The request failed, with why
     Other(String),
 }
 
@@ -40,64 +73,470 @@ impl From<SessionError> for ScreenshotError {
     }
 }
 
+impl From<SessionError> for DynamicLauncherError {
+    fn from(e: SessionError) -> Self {
+        match e {
+            SessionError::Cancelled => Self::Cancelled,
+            SessionError::Other(msg) => Self::Other(msg),
+        }
+    }
+}
+
+impl From<SessionError> for RemoteDesktopError {
+    fn from(e: SessionError) -> Self {
+        match e {
+            SessionError::Cancelled => Self::Cancelled,
+            SessionError::Other(msg) => Self::Other(msg),
+        }
+    }
+}
+
 /// Validate and transform a submission.
 ///
-/// Dispatches to per-portal validate functions via libportty.
+/// Refuses entries with NUL bytes or over `max_entry_length`, then dispatches
+/// to per-portal validate functions via libportty.
 pub fn validate(
-    portal: &str,
-    operation: &str,
+    config: &Config,
+    operation: Operation,
     entries: &[String],
     options: &serde_json::Value,
 ) -> Result<Vec<String>, String> {
-    libportty::portal::validate(portal, operation, entries, options)
+    input::check_entries(entries, config.max_entry_length()).map_err(|e| e.to_string())?;
+    libportty::portal::validate(operation, entries, options)
 }
 
-fn session_cwd<'a>(portal: &str, options: &'a serde_json::Value) -> Option<&'a Path> {
+/// Hand validated results to the configured `validator`, which may refuse
+/// or replace them. Replacements go through [`validate`] again.
+pub fn screen(
+    config: &Config,
+    operation: Operation,
+    options: &serde_json::Value,
+    uris: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let (portal, op) = (operation.portal().as_str(), operation.as_str());
+    let Some(argv) = config.resolve_validator(portal, op) else {
+        return Ok(uris);
+    };
+    let priority = config.resolve_priority(portal, op);
+    let mask = config.resolve_umask(portal, op);
+    let checked = validator::run(&argv, portal, op, options, uris.clone(), mask, &priority)?;
+    if checked == uris {
+        return Ok(uris);
+    }
+    validate(config, operation, &checked, options)
+        .map_err(|e| format!("validator result invalid: {e}"))
+}
+
+/// Put file chooser results in the order given to `portty submit --sort`.
+/// Other portals return a single result or no files.
+fn sort_results(portal: &str, uris: &mut [String], sort: Option<SortOrder>) {
+    if let Some(order) = sort
+        && portal == "file-chooser"
+    {
+        libportty::portal::file_chooser::sort_uris(uris, order);
+    }
+}
+
+pub fn session_cwd(portal: &str, options: &serde_json::Value) -> Option<PathBuf> {
     match portal {
         "file-chooser" => options
             .get("current_folder")
             .and_then(serde_json::Value::as_str)
             .filter(|folder| !folder.is_empty())
-            .map(Path::new),
+            .map(|folder| PathBuf::from(uri::decode_os(folder).into_owned())),
         _ => None,
     }
 }
 
+/// `options` with the [`SESSION_CHOICES`] the session made in `edited`; the
+/// rest, `restrict_to` among them, stays as the request set it
+fn with_session_choices(
+    options: &serde_json::Value,
+    edited: Option<serde_json::Value>,
+) -> serde_json::Value {
+    let mut options = options.clone();
+    let (Some(options_map), Some(serde_json::Value::Object(edited))) =
+        (options.as_object_mut(), edited)
+    else {
+        return options;
+    };
+    for key in SESSION_CHOICES {
+        match edited.get(*key) {
+            Some(value) => options_map.insert(key.to_string(), value.clone()),
+            None => options_map.remove(*key),
+        };
+    }
+    options
+}
+
+/// Prepare result URIs for the app: export files a sandboxed app cannot see
+/// through the document portal (`export_documents`), then apply the
+/// configured `rewrite` rules. Nothing more is exported once the request is
+/// cancelled.
+pub async fn deliver_uris(
+    config: &ConfigHandle,
+    op: Operation,
+    options: &serde_json::Value,
+    uris: Vec<String>,
+    access: Access,
+    request: &InFlight,
+) -> Vec<String> {
+    request.delivering();
+    let (portal, operation) = (op.portal().as_str(), op.as_str());
+    let config = config.get();
+    let app_id = options
+        .get("app_id")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+
+    let uris = if config.resolve_export_documents(portal, operation) {
+        documents::export_uris(app_id, uris, access, request.cancel()).await
+    } else {
+        uris
+    };
+
+    let rules = config.resolve_rewrite(portal, operation);
+    let uris = if rules.is_empty() {
+        uris
+    } else {
+        let rewritten = rewrite::rewrite(rules.iter().copied(), app_id, uris);
+        debug!(portal, operation, ?rewritten, "Applied rewrite rules");
+        rewritten
+    };
+    request.delivered(&uris);
+    uris
+}
+
+/// The URIs a submit of `entries` would return to the app: validated,
+/// sorted, checked against `audit`, and rewritten. The document portal
+/// export is left out, as it registers the files with the app.
+pub fn review(
+    config: &Config,
+    op: Operation,
+    options: &serde_json::Value,
+    entries: &[String],
+    sort: Option<SortOrder>,
+    confirmed: bool,
+) -> Result<Vec<String>, String> {
+    let (portal, operation) = (op.portal().as_str(), op.as_str());
+    let mut uris = validate(config, op, entries, options)?;
+    sort_results(portal, &mut uris, sort);
+    let uris = screen(config, op, options, uris)?;
+    audit(config, portal, operation, options, &uris, confirmed)?;
+    let rules = config.resolve_rewrite(portal, operation);
+    if rules.is_empty() {
+        return Ok(uris);
+    }
+    let app_id = options
+        .get("app_id")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+    Ok(rewrite::rewrite(rules.iter().copied(), app_id, uris))
+}
+
+/// Check result URIs against the configured `audit` lists, logging every
+/// match.
+///
+/// Fails if a result is denied, or needs a confirmation that was not given.
+pub fn audit(
+    config: &Config,
+    portal: &str,
+    operation: &str,
+    options: &serde_json::Value,
+    uris: &[String],
+    confirmed: bool,
+) -> Result<(), String> {
+    let Some(rules) = config.resolve_audit(portal, operation) else {
+        return Ok(());
+    };
+    let app_id = options
+        .get("app_id")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+    let paths: Vec<_> = uris.iter().filter_map(|uri| screenshot_path(uri)).collect();
+
+    let mut refusal = None;
+    for finding in rules.audit(paths.iter().map(PathBuf::as_path)) {
+        let path = finding.path.display();
+        let pattern = &finding.pattern;
+        warn!(
+            app_id,
+            portal,
+            operation,
+            %path,
+            pattern,
+            action = finding.action.as_str(),
+            confirmed,
+            "Result matches audit list"
+        );
+        let message = match finding.action {
+            AuditAction::Deny => format!("{path} is denied by audit pattern {pattern:?}"),
+            AuditAction::Confirm if !confirmed => format!(
+                "{path} needs confirmation (audit pattern {pattern:?}); submit again with `portty submit --confirm`"
+            ),
+            AuditAction::Confirm | AuditAction::Log => continue,
+        };
+        // Report the strictest finding
+        if refusal
+            .as_ref()
+            .is_none_or(|(action, _)| finding.action > *action)
+        {
+            refusal = Some((finding.action, message));
+        }
+    }
+    refusal.map_or(Ok(()), |(_, message)| Err(message))
+}
+
+/// How many of the validated `results` are among the pre-selected
+/// `defaults` (paths or URIs)
+fn from_defaults(defaults: &[String], results: &[String]) -> usize {
+    let defaults: HashSet<_> = defaults
+        .iter()
+        .map(|entry| uri::key(entry.trim_end_matches('/')))
+        .collect();
+    results
+        .iter()
+        .filter(|result| defaults.contains(&uri::key(result.trim_end_matches('/'))))
+        .count()
+}
+
+/// Remove the payloads a session staged (remote copies, screenshots) when
+/// its result is not delivered. The portal frontend drops the reply to a
+/// closed request, so nothing is returned in part.
+fn discard_payloads(session_id: &str) {
+    for dir in [quota::payloads_dir(), quota::cache_payloads_dir()] {
+        let dir = dir.join(session_id);
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => debug!(session = %session_id, dir = %dir.display(), "Removed payloads"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!(session = %session_id, dir = %dir.display(), "Failed to remove payloads: {e}")
+            }
+        }
+    }
+}
+
+/// Ends a session whose request went away first: the app closed the request
+/// (`Request.Close`) while the session still runs. The session is told and
+/// unregistered, and its payloads are removed.
+struct CloseGuard<'a> {
+    state: &'a Arc<RwLock<DaemonState>>,
+    session_id: String,
+    /// Taken once the session ended on its own
+    control: Option<SessionControl>,
+}
+
+impl Drop for CloseGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(control) = self.control.take() {
+            info!(session = %self.session_id, "Request closed by the app");
+            control.close();
+            let mut st = self.state.write().unwrap_or_else(|e| e.into_inner());
+            st.sessions
+                .unregister(&self.session_id, SessionOutcome::Closed);
+            drop(st);
+            discard_payloads(&self.session_id);
+        }
+    }
+}
+
 /// Generic session runner shared by all portal handlers.
 ///
-/// Handles: queued submission check -> config resolution -> session creation ->
+/// Handles: config resolution -> queued submission check (per queue policy) -> session creation ->
 /// registration -> drain pending -> spawn -> poll -> unregister -> return entries.
+///
+/// Returns [`SessionError::Cancelled`] as soon as the request is cancelled,
+/// without waiting for the session process to exit.
 pub async fn run_session(
-    portal: &str,
-    operation: &str,
+    op: Operation,
     options: &serde_json::Value,
     initial_entries: &[String],
     title: Option<&str>,
-    config: &Arc<Config>,
+    config: &Arc<ConfigHandle>,
     state: &Arc<RwLock<DaemonState>>,
+    request: &InFlight,
 ) -> Result<Vec<String>, SessionError> {
+    run_session_with_choices(op, options, initial_entries, title, config, state, request)
+        .await
+        .map(|(entries, _)| entries)
+}
+
+/// [`run_session`], also returning `options` with the [`SESSION_CHOICES`]
+/// the session made. A request answered without a session returns `options`
+/// as they are.
+pub async fn run_session_with_choices(
+    op: Operation,
+    options: &serde_json::Value,
+    initial_entries: &[String],
+    title: Option<&str>,
+    config: &Arc<ConfigHandle>,
+    state: &Arc<RwLock<DaemonState>>,
+    request: &InFlight,
+) -> Result<(Vec<String>, serde_json::Value), SessionError> {
+    let cancel = request.cancel();
+    // Resolve config (snapshot, so a concurrent reload doesn't affect this session)
+    let (portal, operation) = (op.portal().as_str(), op.as_str());
+    let config = config.get();
+    let exec = config.resolve_exec(portal, operation);
+    let queue_policy = config.resolve_queue_policy(portal, operation);
+
     // Check for queued submission on disk first
-    if let Some(entries) = pop_queued_submission(portal, operation, options)
+    let mut initial_entries = Cow::Borrowed(initial_entries);
+    let mut merged = None;
+    if let Some(queued) = pop_queued_submission(op, options, queue_policy, exec.is_some())
         .map_err(|e| SessionError::Other(format!("queued submission invalid: {e}")))?
     {
-        info!(
-            portal,
-            operation,
-            entries = entries.len(),
-            "Found queued submission on disk, auto-applying"
-        );
+        if queued.policy == QueuePolicy::Merge {
+            // Pre-populate the session in place of the request defaults and
+            // wait for an explicit submit
+            info!(
+                portal,
+                operation,
+                entries = queued.entries.len(),
+                "Merging queued submission into session for review"
+            );
+            initial_entries = Cow::Owned(queued.entries);
+            merged = Some(queued.id);
+        } else {
+            info!(
+                portal,
+                operation,
+                entries = queued.entries.len(),
+                "Found queued submission on disk, auto-applying"
+            );
 
-        if entries.is_empty() {
-            info!("Queued submission was empty, cancelling");
+            if queued.entries.is_empty() {
+                info!("Queued submission was empty, cancelling");
+                return Err(SessionError::Cancelled);
+            }
+            input::check_entries(&queued.entries, config.max_entry_length())
+                .map_err(|e| SessionError::Other(format!("queued submission invalid: {e}")))?;
+            // Nobody is there to confirm a queued submission
+            let mut entries = queued.entries;
+            sort_results(portal, &mut entries, queued.sort);
+            let entries = screen(&config, op, options, entries)
+                .and_then(|entries| {
+                    audit(&config, portal, operation, options, &entries, false)?;
+                    Ok(entries)
+                })
+                .map_err(|e| SessionError::Other(format!("queued submission refused: {e}")))?;
+            info!(?entries, id = queued.id, "Queued submission applied");
+            request.produced(Provenance::Queued {
+                queue_id: queued.id,
+            });
+            return Ok((entries, options.clone()));
+        }
+    }
+
+    // A remembered decision answers without a session
+    let app_id = options
+        .get("app_id")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+    match permissions::decision(app_id, op).await {
+        Some(Decision::Deny) => {
+            info!(portal, operation, app_id, "Denied by a remembered decision");
             return Err(SessionError::Cancelled);
         }
-        info!(?entries, "Queued submission applied");
-        return Ok(entries);
+        Some(Decision::Allow) => {
+            let from_defaults = !matches!(op, Operation::Start | Operation::RequestInstallToken)
+                && initial_entries.is_empty();
+            let entries = match op {
+                Operation::Start | Operation::RequestInstallToken => vec![APPROVE.to_string()],
+                _ if !initial_entries.is_empty() => initial_entries.into_owned(),
+                _ => config.resolve_defaults(portal, operation),
+            };
+            if entries.is_empty() {
+                info!(
+                    portal,
+                    operation, app_id, "Allowed by a remembered decision, but nothing to submit"
+                );
+                return Err(SessionError::Other(
+                    "allowed by a remembered decision, but there is nothing to submit (set `defaults`)"
+                        .to_string(),
+                ));
+            }
+            let entries = validate(&config, op, &entries, options)
+                .and_then(|uris| screen(&config, op, options, uris))
+                .and_then(|uris| {
+                    audit(&config, portal, operation, options, &uris, false)?;
+                    Ok(uris)
+                })
+                .map_err(|e| SessionError::Other(format!("remembered decision refused: {e}")))?;
+            info!(
+                ?entries,
+                portal, operation, app_id, "Allowed by a remembered decision"
+            );
+            request.produced(Provenance::Decision {
+                defaults: from_defaults,
+            });
+            return Ok((entries, options.clone()));
+        }
+        None => {}
+    }
+
+    // The built-in picker answers before a session is started, which is
+    // left as the fallback
+    #[cfg(feature = "wayland-picker")]
+    if op == Operation::PickColor {
+        let cancel = cancel.clone();
+        match blocking::unblock(move || wayland_picker::pick(|| cancel.is_cancelled())).await {
+            Ok(color) => {
+                info!(color, "Color picked on screen");
+                request.produced(Provenance::Picked);
+                return Ok((vec![color], options.clone()));
+            }
+            Err(wayland_picker::PickError::Cancelled) => return Err(SessionError::Cancelled),
+            Err(e) => info!("Built-in color picker gave no color ({e}), starting a session"),
+        }
+    }
+
+    // Pre-select the configured defaults when the request brings nothing
+    let mut defaults = Vec::new();
+    if initial_entries.is_empty() {
+        defaults = config.resolve_defaults(portal, operation);
+        if !defaults.is_empty() {
+            debug!(
+                portal,
+                operation,
+                ?defaults,
+                "Pre-selecting default entries"
+            );
+            initial_entries = Cow::Owned(defaults.clone());
+        }
     }
 
-    // Resolve config
-    let exec = config.resolve_exec(portal, operation);
     let bin = config.resolve_bin(portal, operation);
+    let allow_empty = config.resolve_allow_empty(portal, operation);
+    if allow_empty && !libportty::portal::allows_empty(op) {
+        warn!(
+            portal,
+            operation, "allow_empty is set but empty results are not valid here, ignoring"
+        );
+    }
+    let policy = SessionPolicy {
+        on_exit: config.resolve_on_terminal_exit(portal, operation),
+        confirm_timeout: config.resolve_exit_confirm_timeout(portal, operation),
+        allow_empty: allow_empty && libportty::portal::allows_empty(op),
+        attention: config.resolve_attention(portal, operation),
+        attention_delay: config.resolve_attention_delay(portal, operation),
+        stale: config
+            .resolve_stale_after(portal, operation)
+            .map(|after| StaleReport {
+                after,
+                command: config.resolve_on_stale(portal, operation),
+                operation: operation.to_string(),
+                app_id: Some(app_id).filter(|id| !id.is_empty()).map(String::from),
+            }),
+        headless_timeout: config.resolve_headless_timeout(portal, operation),
+    };
+
+    // Refuse new sessions while over the global disk quota
+    let quota = config.quota();
+    if let Err(e) = quota.check(None, 0) {
+        warn!(portal, operation, "{e}");
+        return Err(SessionError::Other(e.to_string()));
+    }
 
     let headless = exec.is_none();
     if headless {
@@ -111,49 +550,254 @@ pub async fn run_session(
 
     let mut session = {
         let mut st = state.write().unwrap_or_else(|e| e.into_inner());
-        st.sessions
-            .create_session(portal, operation, options, initial_entries, &bin, title)
+        let standby = exec
+            .as_deref()
+            .filter(|_| config.resolve_standby(portal, operation) && !config.tabs())
+            .and_then(|exec| st.sessions.take_standby(portal, exec));
+        match standby {
+            Some(standby) => st
+                .sessions
+                .adopt(standby, op, options, &initial_entries, &bin, title),
+            None => st.sessions.create_session(
+                op,
+                options,
+                &initial_entries,
+                exec.is_some().then_some(&bin),
+                title,
+            ),
+        }
     }
     .map_err(|e| SessionError::Other(format!("failed to create session: {e}")))?;
 
     let session_id = session.id().to_string();
-    drain_pending_to(session.dir(), portal, operation, options);
+    request.opened(&session_id);
+    if config.resolve_headless(portal, operation) == Headless::Announce {
+        let heading = attention::heading(portal, operation, title);
+        if headless {
+            let terminals = attention::broadcast(&session_id, &heading);
+            info!(session = %session_id, terminals, "Announced headless session");
+        } else {
+            session.announce_headless(heading);
+        }
+    }
+    if !defaults.is_empty() {
+        // Lets `portty show` mark the entries the user did not pick
+        if let Err(e) = files::write_lines(&session.dir().join("defaults"), &defaults) {
+            warn!(session = %session_id, "Failed to record default entries: {e}");
+        }
+    }
+    drain_pending_to(session.dir(), op, options);
+
+    if config.keep_session() {
+        let app_id = options
+            .get("app_id")
+            .and_then(serde_json::Value::as_str)
+            .filter(|app_id| !app_id.is_empty())
+            .unwrap_or("an unsandboxed app");
+        record_event(
+            session.dir(),
+            &format!("{portal} {operation} requested by {app_id}"),
+        );
+        let check = {
+            let (config, portal, operation) = (
+                Arc::clone(&config),
+                portal.to_string(),
+                operation.to_string(),
+            );
+            let options = options.clone();
+            move |entries: &[String], confirmed: bool| {
+                let uris = validate(&config, op, entries, &options)?;
+                let uris = screen(&config, op, &options, uris)?;
+                audit(&config, &portal, &operation, &options, &uris, confirmed)
+            }
+        };
+        session.keep_open(Box::new(check));
+    }
 
     // Spawn process
     let cwd = session_cwd(portal, options);
+    let env = config.session_env(portal, operation, options);
 
+    let inline = state
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .sessions
+        .inline();
+    let exec = match (exec, inline) {
+        // Inline mode: wait for the daemon's terminal instead of opening one
+        (Some(_), Some(tty)) => {
+            debug!(tty = %tty.path().display(), portal, operation, "Waiting for the inline terminal");
+            let lease = blocking::unblock(move || tty.acquire()).await;
+            lease.announce(&attention::heading(portal, operation, title));
+            session.attach_inline(lease);
+            state
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .sessions
+                .set_mode(&session_id, SessionMode::Inline);
+            Some(config.resolve_inline_exec(portal, operation))
+        }
+        // Tabs mode: a window in the shared terminal instead of a terminal
+        (Some(_), None) if config.tabs() => {
+            let terminal = config.resolve_tabs_terminal(portal, operation);
+            session.attach_tabs(Tabs::new(terminal));
+            state
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .sessions
+                .set_mode(&session_id, SessionMode::Tabs);
+            Some(config.resolve_inline_exec(portal, operation))
+        }
+        (exec, _) => {
+            if let Some(exec) = &exec {
+                let fallback = config
+                    .resolve_exec_fallback(portal, operation)
+                    .into_iter()
+                    .filter(|command| command != exec)
+                    .collect();
+                session.fall_back(fallback);
+            }
+            exec
+        }
+    };
+    if cancel.is_cancelled() {
+        info!(
+            portal,
+            operation, "Request closed by the app before the session started"
+        );
+        let mut st = state.write().unwrap_or_else(|e| e.into_inner());
+        st.sessions.unregister(&session_id, SessionOutcome::Closed);
+        return Err(SessionError::Cancelled);
+    }
+    session.set_umask(umask::for_commands(config.resolve_umask(portal, operation)));
+    session.set_priority(config.resolve_priority(portal, operation));
+    session.set_inherit_env(config.resolve_inherit_env(portal, operation).cloned());
     if let Some(ref exec) = exec
-        && let Err(e) = session.spawn(exec, portal, operation, cwd)
+        && let Err(e) = session.spawn(exec, portal, operation, cwd.as_deref(), env)
     {
         let mut st = state.write().unwrap_or_else(|e| e.into_inner());
-        st.sessions.unregister(&session_id);
+        st.sessions.unregister(&session_id, SessionOutcome::Failed);
         return Err(SessionError::Other(format!("failed to spawn: {e}")));
     }
 
+    let mut guard = CloseGuard {
+        state,
+        session_id: session_id.clone(),
+        control: Some(session.control()),
+    };
+
     // Run session on blocking thread pool (properly bridges sync → async)
-    let run_result = blocking::unblock(move || session.run()).await;
+    let run = async {
+        Some(
+            blocking::unblock(move || {
+                let result = session.run(policy);
+                // Read before the session dir is removed with the session
+                (result, libportty::portal::options::read(session.dir()).ok())
+            })
+            .await,
+        )
+    };
+    let closed = async {
+        cancel.cancelled().await;
+        None
+    };
+    let Some((run_result, edited)) = future::or(run, closed).await else {
+        // The guard ends the session
+        return Err(SessionError::Cancelled);
+    };
+    guard.control = None;
+    let options = &with_session_choices(options, edited);
 
-    // Always unregister session, even if run() errored
+    // Always unregister session, even if run() errored, and replace an
+    // adopted standby session
     {
+        let outcome = match &run_result {
+            Ok(SessionResult::Success { .. }) => SessionOutcome::Submitted,
+            Ok(SessionResult::Cancelled) => SessionOutcome::Cancelled,
+            Ok(SessionResult::ClosedByApp) => SessionOutcome::Closed,
+            Ok(SessionResult::Unattended { .. }) | Err(_) => SessionOutcome::Failed,
+        };
         let mut st = state.write().unwrap_or_else(|e| e.into_inner());
-        st.sessions.unregister(&session_id);
+        st.sessions.unregister(&session_id, outcome);
+        st.sessions.ensure_standby(&config);
     }
 
+    if !matches!(run_result, Ok(SessionResult::Success { .. })) {
+        discard_payloads(&session_id);
+    }
     let result = run_result.map_err(|e| SessionError::Other(format!("session failed: {e}")))?;
 
     match result {
-        SessionResult::Success { entries } => {
-            let entries = validate(portal, operation, &entries, options)
+        SessionResult::Success { entries, .. } if entries.is_empty() => {
+            if !libportty::portal::allows_empty(op) {
+                info!(
+                    portal,
+                    operation, "Empty submission not valid here, cancelling"
+                );
+                return Err(SessionError::Cancelled);
+            }
+            info!(
+                portal,
+                operation, "Session completed with an empty selection"
+            );
+            request.produced(Provenance::Session {
+                session_id,
+                queue_id: merged,
+                defaults: 0,
+            });
+            Ok((entries, options.clone()))
+        }
+        SessionResult::Success {
+            entries,
+            confirmed,
+            sort,
+        } => {
+            let mut entries = validate(&config, op, &entries, options)
                 .map_err(|e| SessionError::Other(format!("submission invalid: {e}")))?;
+            sort_results(portal, &mut entries, sort);
+            let entries = screen(&config, op, options, entries)
+                .and_then(|entries| {
+                    audit(&config, portal, operation, options, &entries, confirmed)?;
+                    Ok(entries)
+                })
+                .map_err(|e| SessionError::Other(format!("submission refused: {e}")))?;
+            if cancel.is_cancelled() {
+                info!(
+                    portal,
+                    operation, "Request closed by the app, dropping the submission"
+                );
+                discard_payloads(&session_id);
+                return Err(SessionError::Cancelled);
+            }
             info!(
                 ?entries,
                 portal, operation, "Session completed successfully"
             );
-            Ok(entries)
+            request.produced(Provenance::Session {
+                session_id,
+                queue_id: merged,
+                defaults: from_defaults(&defaults, &entries),
+            });
+            Ok((entries, options.clone()))
         }
         SessionResult::Cancelled => {
             info!(portal, operation, "Session cancelled");
             Err(SessionError::Cancelled)
         }
+        SessionResult::ClosedByApp => {
+            info!(portal, operation, "Request closed by the app");
+            Err(SessionError::Cancelled)
+        }
+        SessionResult::Unattended { waited } => {
+            let why = if headless {
+                "no terminal configured"
+            } else {
+                "no terminal could be started"
+            };
+            Err(SessionError::Other(format!(
+                "portty: {why} and no interactive submission within {}s — see portty(1)",
+                waited.as_secs()
+            )))
+        }
     }
 }
@@ -0,0 +1,82 @@
+use std::sync::{Arc, RwLock};
+
+use libportty::Operation;
+use tracing::{info, instrument};
+
+use crate::config::ConfigHandle;
+use crate::daemon_socket::DaemonState;
+use crate::dbus::remote_desktop::{RemoteDesktopError, RemoteDesktopHandler, StartResult};
+use crate::dbus::request::CancelToken;
+use crate::inflight::InFlight;
+
+pub use libportty::portal::remote_desktop::{
+    KEYBOARD, POINTER, SessionOptions, TOUCHSCREEN, device_names,
+};
+
+/// Remote desktop handler that asks for approval in a terminal.
+///
+/// Input can't be injected from a TTY, so an approved session is started
+/// without devices or streams. This gives apps probing the portal an answer
+/// (approved or denied) instead of a hang.
+pub struct TtyRemoteDesktop {
+    config: Arc<ConfigHandle>,
+    state: Arc<RwLock<DaemonState>>,
+}
+
+impl TtyRemoteDesktop {
+    pub fn new(config: Arc<ConfigHandle>, state: Arc<RwLock<DaemonState>>) -> Self {
+        Self { config, state }
+    }
+}
+
+impl RemoteDesktopHandler for TtyRemoteDesktop {
+    fn available_device_types(&self) -> u32 {
+        // Advertised so requests reach the approval session; none are granted
+        KEYBOARD | POINTER | TOUCHSCREEN
+    }
+
+    #[instrument(skip(self, parent_window))]
+    async fn start(
+        &self,
+        handle: String,
+        cancel: CancelToken,
+        _session_handle: String,
+        app_id: String,
+        parent_window: String,
+        devices: u32,
+    ) -> Result<StartResult, RemoteDesktopError> {
+        let devices = device_names(devices);
+        info!(?devices, "RemoteDesktop start request");
+
+        let title = if devices.is_empty() {
+            "Remote desktop".to_string()
+        } else {
+            format!("Remote desktop: {}", devices.join(", "))
+        };
+        let request = InFlight::start(&self.state, handle, Operation::Start, &app_id, cancel);
+
+        let session_options = SessionOptions {
+            app_id,
+            parent_window,
+            devices,
+        };
+
+        let options_json = serde_json::to_value(&session_options)
+            .map_err(|e| RemoteDesktopError::Other(format!("failed to serialize options: {e}")))?;
+
+        let entries = super::run_session(
+            Operation::Start,
+            &options_json,
+            &[],
+            Some(&title),
+            &self.config,
+            &self.state,
+            &request,
+        )
+        .await?;
+        request.delivered(&entries);
+
+        info!("RemoteDesktop session approved (no devices granted)");
+        Ok(StartResult::new())
+    }
+}
@@ -1,95 +1,142 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::sync::{Arc, RwLock};
 
-use tracing::{info, instrument};
+use tracing::{info, instrument, warn};
 
-use crate::config::Config;
+use crate::config::{ConfigHandle, Priority};
 use crate::daemon_socket::DaemonState;
+use crate::dbus::request::CancelToken;
 use crate::dbus::screenshot::{
     PickColorOptions, PickColorResult, ScreenshotError, ScreenshotHandler, ScreenshotOptions,
     ScreenshotResult,
 };
+use crate::documents::Access;
+use crate::inflight::InFlight;
+use crate::{priority, umask};
+use libportty::{Operation, files};
 
-pub use libportty::portal::screenshot::{ScreenshotMode, SessionOptions, parse_color};
+pub use libportty::portal::screenshot::{
+    ScreenshotMode, SessionOptions, parse_color, screenshot_path,
+};
 
 /// Screenshot handler that spawns terminals
 pub struct TtyScreenshot {
-    config: Arc<Config>,
+    config: Arc<ConfigHandle>,
     state: Arc<RwLock<DaemonState>>,
 }
 
 impl TtyScreenshot {
-    pub fn new(config: Arc<Config>, state: Arc<RwLock<DaemonState>>) -> Self {
+    pub fn new(config: Arc<ConfigHandle>, state: Arc<RwLock<DaemonState>>) -> Self {
         Self { config, state }
     }
 }
 
 impl ScreenshotHandler for TtyScreenshot {
-    #[instrument(skip(self, _parent_window, options))]
+    #[instrument(skip(self, parent_window, options))]
     async fn screenshot(
         &self,
-        _handle: String,
+        handle: String,
+        cancel: CancelToken,
         app_id: String,
-        _parent_window: String,
+        parent_window: String,
         options: ScreenshotOptions,
     ) -> Result<ScreenshotResult, ScreenshotError> {
         let interactive = options.interactive().unwrap_or(false);
         info!(interactive, "Screenshot request");
 
+        let request = InFlight::start(&self.state, handle, Operation::Screenshot, &app_id, cancel);
+
+        // Only a user choosing what to capture can take a series
+        let series_dir = self
+            .config
+            .get()
+            .resolve_series_dir("screenshot", "screenshot")
+            .filter(|_| interactive);
         let session_options = SessionOptions {
             mode: ScreenshotMode::Screenshot { interactive },
             app_id,
             modal: options.modal().unwrap_or(false),
+            parent_window,
+            series: series_dir.is_some(),
         };
 
         let options_json = serde_json::to_value(&session_options)
             .map_err(|e| ScreenshotError::Other(format!("failed to serialize options: {e}")))?;
 
         let entries = super::run_session(
-            "screenshot",
-            "screenshot",
+            Operation::Screenshot,
             &options_json,
             &[],
             None,
             &self.config,
             &self.state,
+            &request,
         )
         .await?;
 
+        let mut entries = entries.into_iter();
         let uri = entries
-            .into_iter()
             .next()
             .ok_or_else(|| ScreenshotError::Other("no URI returned from session".to_string()))?;
-
+        if let Some(dir) = &series_dir {
+            keep_series(entries.as_slice(), dir);
+        }
+
+        request.delivering();
+        let config = self.config.get();
+        if let Some(argv) = config.resolve_post_process("screenshot", "screenshot") {
+            let mask = umask::for_commands(config.resolve_umask("screenshot", "screenshot"));
+            let priority = config.resolve_priority("screenshot", "screenshot");
+            post_process(argv, &uri, mask, &priority).await?;
+        }
+
+        let uri = super::deliver_uris(
+            &self.config,
+            Operation::Screenshot,
+            &options_json,
+            vec![uri],
+            Access::Read,
+            &request,
+        )
+        .await
+        .remove(0);
         Ok(ScreenshotResult::new(uri))
     }
 
-    #[instrument(skip(self, _parent_window, _options))]
+    #[instrument(skip(self, parent_window, _options))]
     async fn pick_color(
         &self,
-        _handle: String,
+        handle: String,
+        cancel: CancelToken,
         app_id: String,
-        _parent_window: String,
+        parent_window: String,
         _options: PickColorOptions,
     ) -> Result<PickColorResult, ScreenshotError> {
         info!("PickColor request");
 
+        let request = InFlight::start(&self.state, handle, Operation::PickColor, &app_id, cancel);
+
         let session_options = SessionOptions {
             mode: ScreenshotMode::PickColor,
             app_id,
             modal: false,
+            parent_window,
+            series: false,
         };
 
         let options_json = serde_json::to_value(&session_options)
             .map_err(|e| ScreenshotError::Other(format!("failed to serialize options: {e}")))?;
 
         let entries = super::run_session(
-            "screenshot",
-            "pick-color",
+            Operation::PickColor,
             &options_json,
             &[],
             None,
             &self.config,
             &self.state,
+            &request,
         )
         .await?;
 
@@ -102,7 +149,91 @@ impl ScreenshotHandler for TtyScreenshot {
         let color = parse_color(&color_str).ok_or_else(|| {
             ScreenshotError::Other(format!("invalid color format: '{}'", color_str))
         })?;
+        request.delivered(&[color_str]);
 
         Ok(PickColorResult::new(color))
     }
 }
+
+/// Move the captures of a series after the first into `dir`, renaming
+/// any whose name is taken there.
+///
+/// Failures are logged but do not fail the request: the app gets the first
+/// capture either way, and the others stay in the session's payload
+/// directory until `portty gc` removes them.
+fn keep_series(rest: &[String], dir: &Path) {
+    if rest.is_empty() {
+        return;
+    }
+    if let Err(e) = fs::create_dir_all(dir) {
+        warn!(dir = %dir.display(), "Cannot create series_dir: {e}");
+        return;
+    }
+    for entry in rest {
+        let Some(path) = screenshot_path(entry) else {
+            warn!(entry, "Cannot keep non-local series capture");
+            continue;
+        };
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let target = free_name(&dir.join(name));
+        // Payloads may live on another file system ($XDG_CACHE_HOME)
+        let moved = fs::rename(&path, &target)
+            .or_else(|_| files::copy_atomic(&path, &target).and_then(|()| fs::remove_file(&path)));
+        match moved {
+            Ok(()) => info!(target = %target.display(), "Kept series capture"),
+            Err(e) => warn!(path = %path.display(), "Cannot keep series capture: {e}"),
+        }
+    }
+}
+
+/// `path`, or `stem-N.ext` with the first N not taken
+fn free_name(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    (1..)
+        .map(|n| files::with_stem_suffix(path, &format!("-{n}")))
+        .find(|candidate| !candidate.exists())
+        .expect("some name is free")
+}
+
+/// Run the configured `post_process` command (e.g. swappy, satty) on a
+/// screenshot before its URI is returned.
+///
+/// `{}` in any argument is replaced with the file path; without a placeholder
+/// the path is appended. It runs with `umask` and `priority`. A nonzero exit
+/// cancels the request.
+async fn post_process(
+    argv: Vec<String>,
+    uri: &str,
+    umask: u32,
+    priority: &Priority,
+) -> Result<(), ScreenshotError> {
+    let path = screenshot_path(uri).ok_or_else(|| {
+        ScreenshotError::Other(format!("cannot post-process non-local screenshot: {uri}"))
+    })?;
+    let args = files::path_args(&argv[1..], &path);
+
+    info!(program = %argv[0], ?args, "Running screenshot post-process");
+    let exec = priority::scoped(priority, &argv[..1]);
+    let mut cmd = Command::new(&exec[0]);
+    cmd.args(&exec[1..])
+        .args(&args)
+        .env("PORTTY_SCREENSHOT", &path)
+        .stdin(Stdio::null());
+    umask::apply(&mut cmd, umask);
+    priority::apply(&mut cmd, priority);
+
+    let status = blocking::unblock(move || cmd.status())
+        .await
+        .map_err(|e| ScreenshotError::Other(format!("failed to run post_process: {e}")))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        warn!(%status, "Screenshot post-process failed, cancelling");
+        Err(ScreenshotError::Cancelled)
+    }
+}
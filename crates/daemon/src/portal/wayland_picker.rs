@@ -0,0 +1,621 @@
+//! Built-in color picker for wlroots compositors (`wayland-picker` feature).
+//!
+//! A small Wayland client on `wayland-client`: it captures every output with
+//! `zwlr_screencopy_manager_v1`, covers the outputs with transparent
+//! `zwlr_layer_shell_v1` overlays showing a crosshair, and answers with the
+//! captured pixel under the pointer when the left button is pressed. The
+//! right button gives up. Any failure (no Wayland display, a compositor
+//! without the two protocols, an unsupported pixel format) is an error, on
+//! which the pick-color request falls back to a session.
+
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsFd, FromRawFd, OwnedFd};
+use std::os::unix::fs::FileExt;
+use std::time::Duration;
+
+use wayland_client::backend::WaylandError;
+use wayland_client::globals::{GlobalError, GlobalListContents, registry_queue_init};
+use wayland_client::protocol::wl_buffer::WlBuffer;
+use wayland_client::protocol::wl_compositor::WlCompositor;
+use wayland_client::protocol::wl_output::WlOutput;
+use wayland_client::protocol::wl_pointer::{self, ButtonState, WlPointer};
+use wayland_client::protocol::wl_registry::WlRegistry;
+use wayland_client::protocol::wl_seat::{self, Capability, WlSeat};
+use wayland_client::protocol::wl_shm::{Format, WlShm};
+use wayland_client::protocol::wl_shm_pool::WlShmPool;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{
+    ConnectError, Connection, Dispatch, DispatchError, EventQueue, QueueHandle, WEnum,
+    delegate_noop,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::{
+    Layer, ZwlrLayerShellV1,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::{
+    self, Anchor, ZwlrLayerSurfaceV1,
+};
+use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_frame_v1::{
+    self, ZwlrScreencopyFrameV1,
+};
+use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1;
+
+/// Linux input event codes of the buttons
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+
+/// Side of the crosshair cursor, in pixels
+const CURSOR_SIZE: u32 = 15;
+
+/// How often a wait for input checks whether the request was cancelled
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+/// Why no color was picked
+#[derive(Debug)]
+pub enum PickError {
+    /// The right button was pressed or an overlay was closed
+    Dismissed,
+    /// The request was cancelled meanwhile
+    Cancelled,
+    /// The picker cannot run here
+    Failed(String),
+}
+
+impl std::fmt::Display for PickError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dismissed => f.write_str("dismissed"),
+            Self::Cancelled => f.write_str("cancelled"),
+            Self::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+impl From<io::Error> for PickError {
+    fn from(e: io::Error) -> Self {
+        Self::Failed(format!("Wayland connection: {e}"))
+    }
+}
+
+impl From<ConnectError> for PickError {
+    fn from(e: ConnectError) -> Self {
+        Self::Failed(format!("cannot connect to Wayland: {e}"))
+    }
+}
+
+impl From<WaylandError> for PickError {
+    fn from(e: WaylandError) -> Self {
+        Self::Failed(format!("Wayland connection: {e}"))
+    }
+}
+
+impl From<DispatchError> for PickError {
+    fn from(e: DispatchError) -> Self {
+        Self::Failed(format!("Wayland connection: {e}"))
+    }
+}
+
+impl From<GlobalError> for PickError {
+    fn from(e: GlobalError) -> Self {
+        Self::Failed(format!("Wayland registry: {e}"))
+    }
+}
+
+/// Let the user click a pixel on screen. Returns its color as `#rrggbb`.
+/// `cancelled` is checked while waiting for the click.
+pub fn pick(cancelled: impl Fn() -> bool) -> Result<String, PickError> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init::<Picker>(&conn)?;
+    let qh = queue.handle();
+
+    let missing =
+        |interface: &str| PickError::Failed(format!("the compositor does not offer {interface}"));
+    let compositor: WlCompositor = globals
+        .bind(&qh, 1..=1, ())
+        .map_err(|_| missing("wl_compositor"))?;
+    let shm: WlShm = globals
+        .bind(&qh, 1..=1, ())
+        .map_err(|_| missing("wl_shm"))?;
+    let seat: WlSeat = globals
+        .bind(&qh, 1..=1, ())
+        .map_err(|_| missing("wl_seat"))?;
+    let layer_shell: ZwlrLayerShellV1 = globals
+        .bind(&qh, 1..=1, ())
+        .map_err(|_| missing("zwlr_layer_shell_v1"))?;
+    let screencopy: ZwlrScreencopyManagerV1 = globals
+        .bind(&qh, 1..=1, ())
+        .map_err(|_| missing("zwlr_screencopy_manager_v1"))?;
+    let outputs: Vec<WlOutput> = globals.contents().with_list(|list| {
+        list.iter()
+            .filter(|global| global.interface == "wl_output")
+            .map(|global| globals.registry().bind(global.name, 1, &qh, ()))
+            .collect()
+    });
+    if outputs.is_empty() {
+        return Err(PickError::Failed("no outputs".to_string()));
+    }
+
+    let mut picker = Picker {
+        shm,
+        captures: Vec::new(),
+        overlays: Vec::new(),
+        has_pointer: false,
+        cursor: None,
+        position: None,
+        outcome: None,
+    };
+    for (i, output) in outputs.iter().enumerate() {
+        screencopy.capture_output(0, output, &qh, i);
+        picker.captures.push(Capture::default());
+    }
+    queue.roundtrip(&mut picker)?;
+    if !picker.has_pointer {
+        return Err(PickError::Failed("the seat has no pointer".to_string()));
+    }
+    while !picker.captures.iter().all(|capture| capture.done) {
+        if let Some(outcome) = picker.outcome.take() {
+            return outcome;
+        }
+        queue.blocking_dispatch(&mut picker)?;
+    }
+
+    let cursor = compositor.create_surface(&qh, ());
+    let (mut file, buffer) = shm_buffer(
+        &picker.shm,
+        &qh,
+        Format::Argb8888,
+        CURSOR_SIZE,
+        CURSOR_SIZE,
+        CURSOR_SIZE * 4,
+    )?;
+    file.write_all(&crosshair(CURSOR_SIZE))?;
+    cursor.attach(Some(&buffer), 0, 0);
+    cursor.commit();
+    picker.cursor = Some(cursor);
+    seat.get_pointer(&qh, ());
+
+    for (i, output) in outputs.iter().enumerate() {
+        let surface = compositor.create_surface(&qh, ());
+        let layer = layer_shell.get_layer_surface(
+            &surface,
+            Some(output),
+            Layer::Overlay,
+            "portty-picker".to_string(),
+            &qh,
+            i,
+        );
+        layer.set_anchor(Anchor::all());
+        layer.set_exclusive_zone(-1);
+        surface.commit();
+        picker.overlays.push(Overlay {
+            surface,
+            size: (1, 1),
+        });
+    }
+
+    loop {
+        if cancelled() {
+            return Err(PickError::Cancelled);
+        }
+        if let Some(outcome) = picker.outcome.take() {
+            return outcome;
+        }
+        dispatch_for(&mut queue, &mut picker, CANCEL_POLL)?;
+    }
+}
+
+/// Dispatch the events that arrive within `timeout`
+fn dispatch_for(
+    queue: &mut EventQueue<Picker>,
+    picker: &mut Picker,
+    timeout: Duration,
+) -> Result<(), PickError> {
+    queue.flush()?;
+    if let Some(guard) = queue.prepare_read()
+        && crate::session::wait_readable(&guard.connection_fd(), timeout)
+    {
+        guard.read()?;
+    }
+    queue.dispatch_pending(picker)?;
+    Ok(())
+}
+
+/// State of a pick, updated by the events of its objects
+struct Picker {
+    shm: WlShm,
+    /// Per output, in the order of their `wl_output` globals
+    captures: Vec<Capture>,
+    overlays: Vec<Overlay>,
+    has_pointer: bool,
+    cursor: Option<WlSurface>,
+    /// Overlay under the pointer, and the pointer's position on it
+    position: Option<(usize, f64, f64)>,
+    /// The answer, once there is one
+    outcome: Option<Result<String, PickError>>,
+}
+
+/// A screenshot of one output
+#[derive(Default)]
+struct Capture {
+    pixels: Option<Pixels>,
+    done: bool,
+}
+
+/// Contents of a screencopy buffer
+struct Pixels {
+    file: File,
+    format: Format,
+    width: u32,
+    height: u32,
+    stride: u32,
+    y_invert: bool,
+}
+
+impl Pixels {
+    /// Color of the pixel at (`x`, `y`), fractions of the output's size
+    fn color_at(&self, x: f64, y: f64) -> Result<String, PickError> {
+        let px = ((x * self.width as f64) as u32).min(self.width - 1);
+        let mut py = ((y * self.height as f64) as u32).min(self.height - 1);
+        if self.y_invert {
+            py = self.height - 1 - py;
+        }
+        let mut pixel = [0u8; 4];
+        self.file.read_exact_at(
+            &mut pixel,
+            u64::from(py) * u64::from(self.stride) + u64::from(px) * 4,
+        )?;
+        pixel_color(self.format, pixel).ok_or_else(|| {
+            PickError::Failed(format!("unsupported screencopy format {:?}", self.format))
+        })
+    }
+}
+
+/// `#rrggbb` of a 32-bit pixel in `format`, when it is one this picker reads
+fn pixel_color(format: Format, pixel: [u8; 4]) -> Option<String> {
+    // Formats are little-endian words: ARGB8888 is B, G, R, A in memory
+    let [r, g, b] = match format {
+        Format::Argb8888 | Format::Xrgb8888 => [pixel[2], pixel[1], pixel[0]],
+        Format::Abgr8888 | Format::Xbgr8888 => [pixel[0], pixel[1], pixel[2]],
+        _ => return None,
+    };
+    Some(format!("#{r:02x}{g:02x}{b:02x}"))
+}
+
+/// A transparent overlay covering one output
+struct Overlay {
+    surface: WlSurface,
+    /// Size in surface coordinates, once configured
+    size: (u32, u32),
+}
+
+impl Picker {
+    /// Show a transparent buffer of `width`x`height` on overlay `i`
+    fn fill_overlay(
+        &mut self,
+        i: usize,
+        width: u32,
+        height: u32,
+        qh: &QueueHandle<Self>,
+    ) -> Result<(), PickError> {
+        let overlay = &mut self.overlays[i];
+        if (width, height) == overlay.size || width == 0 || height == 0 {
+            return Ok(());
+        }
+        overlay.size = (width, height);
+        // A new memfd is all zeros: fully transparent
+        let (_, buffer) = shm_buffer(&self.shm, qh, Format::Argb8888, width, height, width * 4)?;
+        overlay.surface.attach(Some(&buffer), 0, 0);
+        overlay.surface.damage(0, 0, width as i32, height as i32);
+        overlay.surface.commit();
+        Ok(())
+    }
+
+    /// The color under the pointer on overlay `i`
+    fn color_at(&self, i: usize, x: f64, y: f64) -> Result<String, PickError> {
+        let (width, height) = self.overlays[i].size;
+        self.captures[i]
+            .pixels
+            .as_ref()
+            .ok_or_else(|| PickError::Failed("screencopy finished without a buffer".to_string()))?
+            .color_at(x / width as f64, y / height as f64)
+    }
+}
+
+/// A `wl_buffer` backed by a new memfd, and the memfd
+fn shm_buffer(
+    shm: &WlShm,
+    qh: &QueueHandle<Picker>,
+    format: Format,
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Result<(File, WlBuffer), PickError> {
+    let size = u64::from(stride) * u64::from(height);
+    let size_arg = i32::try_from(size)
+        .map_err(|_| PickError::Failed(format!("buffer of {width}x{height} is too large")))?;
+    let file = memfd()?;
+    file.set_len(size)?;
+    let pool = shm.create_pool(file.as_fd(), size_arg, qh, ());
+    let buffer = pool.create_buffer(
+        0,
+        width as i32,
+        height as i32,
+        stride as i32,
+        format,
+        qh,
+        (),
+    );
+    pool.destroy();
+    Ok((file, buffer))
+}
+
+fn memfd() -> io::Result<File> {
+    let name = CString::new("portty-picker").expect("no NUL");
+    // SAFETY: memfd_create takes a valid C string and returns a new fd or -1
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fd` was just created and is owned by nothing else
+    Ok(File::from(unsafe { OwnedFd::from_raw_fd(fd) }))
+}
+
+/// Pixels of a `size`x`size` ARGB8888 crosshair: a white cross outlined in
+/// black
+fn crosshair(size: u32) -> Vec<u8> {
+    let mid = size / 2;
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let (dx, dy) = (x.abs_diff(mid), y.abs_diff(mid));
+            let argb: u32 = if dx == 0 || dy == 0 {
+                0xffff_ffff
+            } else if dx <= 1 || dy <= 1 {
+                0xff00_0000
+            } else {
+                0
+            };
+            pixels.extend_from_slice(&argb.to_le_bytes());
+        }
+    }
+    pixels
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Picker {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as wayland_client::Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // Outputs plugged in meanwhile are not covered
+    }
+}
+
+impl Dispatch<WlSeat, ()> for Picker {
+    fn event(
+        picker: &mut Self,
+        _: &WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            picker.has_pointer = capabilities.contains(Capability::Pointer);
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, usize> for Picker {
+    fn event(
+        picker: &mut Self,
+        frame: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        &i: &usize,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            // The buffer the client has to provide
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => {
+                let format = match format {
+                    WEnum::Value(format) => format,
+                    WEnum::Unknown(format) => {
+                        picker.outcome = Some(Err(PickError::Failed(format!(
+                            "unsupported screencopy format {format:#x}"
+                        ))));
+                        return;
+                    }
+                };
+                let (file, buffer) =
+                    match shm_buffer(&picker.shm, qh, format, width, height, stride) {
+                        Ok(buffer) => buffer,
+                        Err(e) => {
+                            picker.outcome = Some(Err(e));
+                            return;
+                        }
+                    };
+                frame.copy(&buffer);
+                picker.captures[i].pixels = Some(Pixels {
+                    file,
+                    format,
+                    width,
+                    height,
+                    stride,
+                    y_invert: false,
+                });
+            }
+            zwlr_screencopy_frame_v1::Event::Flags {
+                flags: WEnum::Value(flags),
+            } => {
+                if let Some(pixels) = &mut picker.captures[i].pixels {
+                    pixels.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert);
+                }
+            }
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => {
+                frame.destroy();
+                picker.captures[i].done = true;
+            }
+            zwlr_screencopy_frame_v1::Event::Failed => {
+                picker.outcome = Some(Err(PickError::Failed("screencopy failed".to_string())));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, usize> for Picker {
+    fn event(
+        picker: &mut Self,
+        layer: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        &i: &usize,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure {
+                serial,
+                width,
+                height,
+            } => {
+                layer.ack_configure(serial);
+                if let Err(e) = picker.fill_overlay(i, width, height, qh) {
+                    picker.outcome = Some(Err(e));
+                }
+            }
+            zwlr_layer_surface_v1::Event::Closed => {
+                picker.outcome = Some(Err(PickError::Dismissed));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlPointer, ()> for Picker {
+    fn event(
+        picker: &mut Self,
+        pointer: &WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_pointer::Event::Enter {
+                serial,
+                surface,
+                surface_x,
+                surface_y,
+            } => {
+                picker.position = picker
+                    .overlays
+                    .iter()
+                    .position(|overlay| overlay.surface == surface)
+                    .map(|i| (i, surface_x, surface_y));
+                let hotspot = (CURSOR_SIZE / 2) as i32;
+                pointer.set_cursor(serial, picker.cursor.as_ref(), hotspot, hotspot);
+            }
+            wl_pointer::Event::Leave { .. } => picker.position = None,
+            wl_pointer::Event::Motion {
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                if let Some((i, _, _)) = picker.position {
+                    picker.position = Some((i, surface_x, surface_y));
+                }
+            }
+            wl_pointer::Event::Button {
+                button,
+                state: WEnum::Value(ButtonState::Pressed),
+                ..
+            } => match (button, picker.position) {
+                (BTN_RIGHT, _) => picker.outcome = Some(Err(PickError::Dismissed)),
+                (BTN_LEFT, Some((i, x, y))) => picker.outcome = Some(picker.color_at(i, x, y)),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(Picker: WlCompositor);
+delegate_noop!(Picker: WlShmPool);
+delegate_noop!(Picker: ZwlrLayerShellV1);
+delegate_noop!(Picker: ZwlrScreencopyManagerV1);
+delegate_noop!(Picker: ignore WlShm);
+delegate_noop!(Picker: ignore WlBuffer);
+delegate_noop!(Picker: ignore WlSurface);
+delegate_noop!(Picker: ignore WlOutput);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_decode_by_format() {
+        // Memory order of the same orange-ish pixel in each layout
+        let bgra = [0x30, 0x80, 0xf0, 0xff];
+        assert_eq!(pixel_color(Format::Argb8888, bgra).unwrap(), "#f08030");
+        assert_eq!(pixel_color(Format::Xrgb8888, bgra).unwrap(), "#f08030");
+        let rgba = [0xf0, 0x80, 0x30, 0xff];
+        assert_eq!(pixel_color(Format::Abgr8888, rgba).unwrap(), "#f08030");
+        assert_eq!(pixel_color(Format::Xbgr8888, rgba).unwrap(), "#f08030");
+        assert_eq!(pixel_color(Format::Rgb565, rgba), None);
+    }
+
+    #[test]
+    fn captures_are_read_at_the_pointer() {
+        // 2x2 XRGB8888 with a padded stride: red, green / blue, white
+        let mut file = memfd().unwrap();
+        let rows: [&[u8]; 2] = [
+            &[0, 0, 0xff, 0, 0, 0xff, 0, 0, 9, 9, 9, 9],
+            &[0xff, 0, 0, 0, 0xff, 0xff, 0xff, 0, 9, 9, 9, 9],
+        ];
+        file.write_all(&rows.concat()).unwrap();
+        let mut pixels = Pixels {
+            file,
+            format: Format::Xrgb8888,
+            width: 2,
+            height: 2,
+            stride: 12,
+            y_invert: false,
+        };
+        assert_eq!(pixels.color_at(0.0, 0.0).unwrap(), "#ff0000");
+        assert_eq!(pixels.color_at(0.9, 0.1).unwrap(), "#00ff00");
+        assert_eq!(pixels.color_at(0.2, 0.7).unwrap(), "#0000ff");
+        // The far edge stays on the last pixel
+        assert_eq!(pixels.color_at(1.0, 1.0).unwrap(), "#ffffff");
+        pixels.y_invert = true;
+        assert_eq!(pixels.color_at(0.0, 0.0).unwrap(), "#0000ff");
+    }
+
+    #[test]
+    fn crosshair_is_a_white_cross_outlined_in_black() {
+        let size = 5;
+        let pixels = crosshair(size);
+        assert_eq!(pixels.len(), (size * size * 4) as usize);
+        let at = |x: u32, y: u32| {
+            let i = ((y * size + x) * 4) as usize;
+            u32::from_le_bytes(pixels[i..i + 4].try_into().unwrap())
+        };
+        assert_eq!(at(2, 0), 0xffff_ffff);
+        assert_eq!(at(0, 2), 0xffff_ffff);
+        assert_eq!(at(1, 0), 0xff00_0000);
+        assert_eq!(at(3, 3), 0xff00_0000);
+        assert_eq!(at(0, 0), 0);
+    }
+}
@@ -0,0 +1,112 @@
+//! Scheduling of the commands a session runs.
+//!
+//! A heavy picker preview or screenshot editor competes with the app that
+//! asked for it. `nice` and `ionice` lower the priority of the session's
+//! commands (applied between fork and exec, like the umask), and
+//! `systemd_scope` runs them through `systemd-run --user --scope` with cgroup
+//! weights and a memory limit. `systemd-run --scope` execs the command
+//! itself, so the process porttyd watches stays the command.
+
+use std::ffi::OsString;
+use std::process::Command;
+
+use libportty::config::{IoNice, Priority};
+
+/// `ioprio_set` target: a single process
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+const IOPRIO_CLASS_BE: u32 = 2;
+const IOPRIO_CLASS_IDLE: u32 = 3;
+
+fn ioprio(ionice: IoNice) -> u32 {
+    match ionice {
+        IoNice::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        IoNice::BestEffort(level) => {
+            IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT | u32::from(level.min(7))
+        }
+    }
+}
+
+/// `argv` run in a transient systemd scope, when `priority` has one
+pub fn scoped(priority: &Priority, argv: &[String]) -> Vec<String> {
+    let Some(scope) = &priority.scope else {
+        return argv.to_vec();
+    };
+    let mut scoped: Vec<String> = ["systemd-run", "--user", "--scope", "--quiet", "--collect"]
+        .map(String::from)
+        .into();
+    for property in scope.properties() {
+        scoped.extend(["--property".to_string(), property]);
+    }
+    scoped.push("--".to_string());
+    scoped.extend_from_slice(argv);
+    scoped
+}
+
+/// Make `cmd` run with the niceness and I/O class of `priority`.
+///
+/// Failures are ignored: the command runs at porttyd's priority then,
+/// which is no worse than without the setting.
+pub fn apply(cmd: &mut Command, priority: &Priority) {
+    use std::os::unix::process::CommandExt as _;
+
+    let (nice, ionice) = (priority.nice, priority.ionice);
+    if nice.is_none() && ionice.is_none() {
+        return;
+    }
+    // SAFETY: nice and ioprio_set are plain syscalls, async-signal-safe as
+    // required between fork and exec
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(nice) = nice {
+                libc::nice(nice);
+            }
+            if let Some(ionice) = ionice {
+                libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio(ionice));
+            }
+            Ok(())
+        });
+    }
+}
+
+/// `argv` wrapped with nice(1) and ionice(1), for commands started by another
+/// process (a tmux server) that keeps its own priority
+pub fn wrap(priority: &Priority, argv: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut wrapped: Vec<OsString> = Vec::new();
+    if let Some(nice) = priority.nice {
+        wrapped.extend(["nice".into(), "-n".into(), nice.to_string().into()]);
+    }
+    match priority.ionice {
+        Some(IoNice::Idle) => wrapped.extend(["ionice".into(), "-c".into(), "3".into()]),
+        Some(IoNice::BestEffort(level)) => wrapped.extend([
+            "ionice".into(),
+            "-c".into(),
+            "2".into(),
+            "-n".into(),
+            level.min(7).to_string().into(),
+        ]),
+        None => {}
+    }
+    wrapped.extend(argv);
+    wrapped
+}
+
+/// One line describing `priority`, for `porttyd --simulate`
+pub fn describe(priority: &Priority) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(nice) = priority.nice {
+        parts.push(format!("nice {nice}"));
+    }
+    if let Some(ionice) = priority.ionice {
+        parts.push(format!("ionice {ionice}"));
+    }
+    if let Some(scope) = &priority.scope {
+        let properties = scope.properties();
+        if properties.is_empty() {
+            parts.push("systemd scope".to_string());
+        } else {
+            parts.push(format!("systemd scope ({})", properties.join(", ")));
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
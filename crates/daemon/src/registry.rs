@@ -0,0 +1,342 @@
+//! Portals served on the bus, registered by name
+//!
+//! Every portal, built in or not, is a [`PortalPlugin`]: it names itself and
+//! the config table it reads, and adds its D-Bus interface to the connection
+//! being built. A crate adding a portal implements the trait, registers it on
+//! [`Registry::builtin`], and hands the registry to [`main_with`](crate::main_with).
+//! Its handler answers a request through [`PortalContext::run_session`], as
+//! the built-in portals do.
+
+use std::sync::{Arc, RwLock};
+
+use libportty::config::Config;
+use libportty::{Operation, Portal};
+use tracing::info;
+use zbus::connection::Builder;
+
+use crate::config::ConfigHandle;
+use crate::daemon_socket::DaemonState;
+use crate::dbus::dynamic_launcher::DynamicLauncherPortal;
+use crate::dbus::file_chooser::FileChooserPortal;
+use crate::dbus::remote_desktop::RemoteDesktopPortal;
+use crate::dbus::screenshot::ScreenshotPortal;
+use crate::inflight::InFlight;
+use crate::portal::{TtyDynamicLauncher, TtyFileChooser, TtyRemoteDesktop, TtyScreenshot};
+
+pub use crate::dbus::request::CancelToken;
+pub use crate::portal::SessionError;
+
+/// A request a portal answers with a session
+pub struct SessionRequest<'a> {
+    pub operation: Operation,
+    /// Object path of the request's Request object, empty without one
+    pub handle: String,
+    pub app_id: &'a str,
+    /// Session options, as the operation's `SessionOptions` serialize
+    pub options: serde_json::Value,
+    /// Entries the session starts with
+    pub entries: &'a [String],
+    pub title: Option<&'a str>,
+    /// Set when the app closes the request
+    pub cancel: CancelToken,
+}
+
+/// What a portal gets from the daemon when it is served
+#[derive(Clone)]
+pub struct PortalContext {
+    config: Arc<ConfigHandle>,
+    state: Arc<RwLock<DaemonState>>,
+}
+
+impl PortalContext {
+    pub(crate) fn new(config: Arc<ConfigHandle>, state: Arc<RwLock<DaemonState>>) -> Self {
+        Self { config, state }
+    }
+
+    /// Snapshot of the current config; take one per request, so a reload
+    /// applies to the next.
+    ///
+    /// A portal's table is read with the `resolve_*` methods, passing its
+    /// [`config_section`](PortalPlugin::config_section) as the portal name.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.get()
+    }
+
+    /// Answer `request` as the built-in portals do: a queued submission or a
+    /// session, listed by `portty list --all` while it runs. The returned
+    /// entries are validated for the operation and added to the history.
+    pub async fn run_session(
+        &self,
+        request: SessionRequest<'_>,
+    ) -> Result<Vec<String>, SessionError> {
+        let in_flight = InFlight::start(
+            &self.state,
+            request.handle,
+            request.operation,
+            request.app_id,
+            request.cancel,
+        );
+        let entries = crate::portal::run_session(
+            request.operation,
+            &request.options,
+            request.entries,
+            request.title,
+            &self.config,
+            &self.state,
+            &in_flight,
+        )
+        .await?;
+        in_flight.delivered(&entries);
+        Ok(entries)
+    }
+
+    pub(crate) fn config_handle(&self) -> Arc<ConfigHandle> {
+        Arc::clone(&self.config)
+    }
+
+    pub(crate) fn state(&self) -> Arc<RwLock<DaemonState>> {
+        Arc::clone(&self.state)
+    }
+}
+
+/// A portal porttyd serves
+pub trait PortalPlugin: Send + Sync {
+    /// Name in logs, and the key [`Registry::register`] replaces by
+    /// (`FileChooser`)
+    fn name(&self) -> &str;
+
+    /// Config table the portal reads (`file-chooser`)
+    fn config_section(&self) -> &str;
+
+    /// Add the portal's interface at `path`, the portal object path
+    fn serve(
+        &self,
+        builder: Builder<'static>,
+        path: &str,
+        ctx: &PortalContext,
+    ) -> zbus::Result<Builder<'static>>;
+}
+
+/// A portal shipped with porttyd
+struct Builtin {
+    portal: Portal,
+    name: &'static str,
+    serve: fn(Builder<'static>, &str, &PortalContext) -> zbus::Result<Builder<'static>>,
+}
+
+impl PortalPlugin for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn config_section(&self) -> &str {
+        self.portal.as_str()
+    }
+
+    fn serve(
+        &self,
+        builder: Builder<'static>,
+        path: &str,
+        ctx: &PortalContext,
+    ) -> zbus::Result<Builder<'static>> {
+        (self.serve)(builder, path, ctx)
+    }
+}
+
+/// The portals to serve, in registration order
+#[derive(Default)]
+pub struct Registry {
+    portals: Vec<Box<dyn PortalPlugin>>,
+}
+
+impl Registry {
+    /// No portals
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The portals shipped with porttyd
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(Builtin {
+            portal: Portal::FileChooser,
+            name: "FileChooser",
+            serve: |builder, path, ctx| {
+                let portal = TtyFileChooser::new(ctx.config_handle(), ctx.state());
+                builder.serve_at(path.to_string(), FileChooserPortal::from(portal))
+            },
+        });
+        registry.register(Builtin {
+            portal: Portal::Screenshot,
+            name: "Screenshot",
+            serve: |builder, path, ctx| {
+                let portal = TtyScreenshot::new(ctx.config_handle(), ctx.state());
+                builder.serve_at(path.to_string(), ScreenshotPortal::from(portal))
+            },
+        });
+        registry.register(Builtin {
+            portal: Portal::RemoteDesktop,
+            name: "RemoteDesktop",
+            serve: |builder, path, ctx| {
+                let portal = TtyRemoteDesktop::new(ctx.config_handle(), ctx.state());
+                builder.serve_at(path.to_string(), RemoteDesktopPortal::from(portal))
+            },
+        });
+        registry.register(Builtin {
+            portal: Portal::DynamicLauncher,
+            name: "DynamicLauncher",
+            serve: |builder, path, ctx| {
+                let portal = TtyDynamicLauncher::new(ctx.config_handle(), ctx.state());
+                builder.serve_at(path.to_string(), DynamicLauncherPortal::from(portal))
+            },
+        });
+        registry
+    }
+
+    /// Add a portal. One with the name of a registered portal replaces it,
+    /// so a plugin can override a built-in.
+    pub fn register(&mut self, plugin: impl PortalPlugin + 'static) -> &mut Self {
+        match self.portals.iter().position(|p| p.name() == plugin.name()) {
+            Some(i) => self.portals[i] = Box::new(plugin),
+            None => self.portals.push(Box::new(plugin)),
+        }
+        self
+    }
+
+    /// Config tables of the registered portals
+    pub fn config_sections(&self) -> Vec<&str> {
+        self.portals
+            .iter()
+            .map(|portal| portal.config_section())
+            .collect()
+    }
+
+    /// Serve every portal at `path`
+    pub(crate) fn serve(
+        &self,
+        mut builder: Builder<'static>,
+        path: &str,
+        ctx: &PortalContext,
+    ) -> zbus::Result<Builder<'static>> {
+        for portal in &self.portals {
+            info!(
+                "Registering {} portal ([{}])",
+                portal.name(),
+                portal.config_section()
+            );
+            builder = portal.serve(builder, path, ctx)?;
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use libportty::paths;
+    use libportty::portal::screenshot::{ScreenshotMode, SessionOptions};
+
+    use super::*;
+
+    /// A portal from outside porttyd, picking a color through a session
+    struct Picker {
+        ctx: PortalContext,
+    }
+
+    #[zbus::interface(name = "org.example.Picker")]
+    impl Picker {
+        async fn pick(&self, app_id: String) -> zbus::fdo::Result<String> {
+            let options = SessionOptions {
+                mode: ScreenshotMode::PickColor,
+                app_id: app_id.clone(),
+                modal: false,
+                parent_window: String::new(),
+                series: false,
+            };
+            let request = SessionRequest {
+                operation: Operation::PickColor,
+                handle: String::new(),
+                app_id: &app_id,
+                options: serde_json::to_value(options).unwrap(),
+                entries: &[],
+                title: Some("Pick a color"),
+                cancel: CancelToken::default(),
+            };
+            match self.ctx.run_session(request).await {
+                Ok(entries) => Ok(entries.concat()),
+                Err(SessionError::Cancelled) => Err(zbus::fdo::Error::Failed("cancelled".into())),
+                Err(SessionError::Other(e)) => Err(zbus::fdo::Error::Failed(e)),
+            }
+        }
+    }
+
+    struct PickerPlugin;
+
+    impl PortalPlugin for PickerPlugin {
+        fn name(&self) -> &str {
+            "Picker"
+        }
+
+        fn config_section(&self) -> &str {
+            "picker"
+        }
+
+        fn serve(
+            &self,
+            builder: Builder<'static>,
+            path: &str,
+            ctx: &PortalContext,
+        ) -> zbus::Result<Builder<'static>> {
+            builder.serve_at(path.to_string(), Picker { ctx: ctx.clone() })
+        }
+    }
+
+    #[test]
+    fn plugins_answer_requests_with_sessions() {
+        let root = std::env::temp_dir().join(format!("porttyd-test-{}", std::process::id()));
+        paths::configure_base_dir(root.clone());
+        // SAFETY: the daemon's tests read the environment only through std,
+        // which serializes access to it
+        unsafe { std::env::set_var("XDG_STATE_HOME", root.join("state")) };
+
+        let mut registry = Registry::builtin();
+        registry.register(PickerPlugin);
+        assert!(registry.config_sections().contains(&"picker"));
+        let ctx = PortalContext::new(
+            Arc::new(ConfigHandle::new(Config::default())),
+            Arc::new(RwLock::new(DaemonState::default())),
+        );
+        let builder = Builder::address("unix:path=/nonexistent").unwrap();
+        let path = "/org/freedesktop/portal/desktop";
+        assert!(registry.serve(builder, path, &ctx).is_ok());
+
+        let picker = Picker { ctx: ctx.clone() };
+        let pick = std::thread::spawn(move || {
+            futures_lite::future::block_on(picker.pick("org.example.App".into()))
+        });
+
+        // No exec is configured: the session waits headless for the CLI
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let session = loop {
+            let state = ctx.state();
+            let st = state.read().unwrap();
+            if let Some(session) = st.sessions.iter().next() {
+                assert_eq!(session.operation, Operation::PickColor);
+                assert_eq!(session.title.as_deref(), Some("Pick a color"));
+                let request = st.requests.iter().next().unwrap();
+                assert_eq!(request.session_id.as_deref(), Some(session.id.as_str()));
+                break (session.dir.clone(), Arc::clone(&session.control));
+            }
+            drop(st);
+            assert!(Instant::now() < deadline, "no session started");
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let (dir, control) = session;
+        libportty::files::write_lines(&dir.join("submission"), &["#ff8800".to_string()]).unwrap();
+        control.submit(false, false, None);
+
+        assert_eq!(pick.join().unwrap().unwrap(), "#ff8800");
+        assert!(ctx.state().read().unwrap().requests.iter().next().is_none());
+    }
+}
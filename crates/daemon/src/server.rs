@@ -3,31 +3,110 @@ use std::sync::{Arc, RwLock};
 use tracing::{info, warn};
 use zbus::connection::Builder;
 
-use crate::config::Config;
+use crate::config::{Config, ConfigHandle};
 use crate::daemon_socket::{DaemonCtl, DaemonSocket, DaemonState};
-use crate::dbus::file_chooser::FileChooserPortal;
-use crate::dbus::screenshot::ScreenshotPortal;
-use crate::portal::{TtyFileChooser, TtyScreenshot};
+use crate::dbus::daemon::DaemonInfo;
+use crate::dbus::events::{EVENTS_PATH, Events};
+use crate::inline::InlineTty;
+use crate::registry::{PortalContext, Registry};
+use crate::shutdown::{self, ShutdownHandle};
 
 const SERVICE_NAME: &str = "org.freedesktop.impl.portal.desktop.tty";
 const OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
 
+/// The portal backend: the D-Bus portals, the control socket and FIFO, and
+/// the sessions they run
+///
+/// `porttyd` runs one from [`main_with`](crate::main_with); another process
+/// can serve the portals in-process instead:
+///
+/// ```ignore
+/// let daemon = porttyd::Daemon::builder()
+///     .config(Config::builder().root(|s| s.exec(["foot"])).build()?)
+///     .portals(porttyd::registry::Registry::builtin())
+///     .build();
+/// let shutdown = daemon.shutdown_handle();
+/// // ... later, from anywhere: shutdown.shutdown()
+/// daemon.run().await?;
+/// ```
 pub struct Daemon {
-    config: Arc<Config>,
+    config: Arc<ConfigHandle>,
     state: Arc<RwLock<DaemonState>>,
+    portals: Registry,
+}
+
+/// Options of a [`Daemon`], from [`Daemon::builder`]
+#[derive(Default)]
+pub struct DaemonBuilder {
+    config: Option<Config>,
+    portals: Option<Registry>,
+    inline: Option<InlineTty>,
+}
+
+impl DaemonBuilder {
+    /// Config to serve with, instead of the config file porttyd would load.
+    /// `portty daemon reload` still reads the file.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Portals to serve, instead of [`Registry::builtin`]
+    pub fn portals(mut self, portals: Registry) -> Self {
+        self.portals = Some(portals);
+        self
+    }
+
+    /// Run sessions on this terminal instead of spawning `exec`, as
+    /// `porttyd --inline` does
+    pub fn inline(mut self, tty: InlineTty) -> Self {
+        self.inline = Some(tty);
+        self
+    }
+
+    pub fn build(self) -> Daemon {
+        let portals = self.portals.unwrap_or_else(Registry::builtin);
+        let config = self
+            .config
+            .unwrap_or_else(|| crate::config::load(&portals.config_sections()));
+        Daemon::new(config, self.inline, portals)
+    }
+
+    /// Build the daemon and [run](Daemon::run) it
+    pub async fn run(self) -> Result<(), zbus::Error> {
+        self.build().run().await
+    }
 }
 
 impl Daemon {
-    pub fn new(config: Config) -> Self {
+    pub fn builder() -> DaemonBuilder {
+        DaemonBuilder::default()
+    }
+
+    /// `inline`: run sessions on this terminal instead of spawning `exec`
+    pub(crate) fn new(config: Config, inline: Option<InlineTty>, portals: Registry) -> Self {
+        let cli = config.resolve_cli();
         Self {
-            config: Arc::new(config),
-            state: Arc::new(RwLock::new(DaemonState::new())),
+            config: Arc::new(ConfigHandle::new(config).with_portals(&portals.config_sections())),
+            state: Arc::new(RwLock::new(DaemonState::new(inline.map(Arc::new), cli))),
+            portals,
         }
     }
 
+    /// Handle stopping [`run`](Self::run), as `portty daemon stop` does
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.state
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .shutdown
+            .clone()
+    }
+
+    /// Serve until shut down. The bus name is released and the socket and
+    /// FIFO removed before it returns; sessions still open are cancelled.
     pub async fn run(self) -> Result<(), zbus::Error> {
         // Start daemon socket in background thread
-        match DaemonSocket::new(Arc::clone(&self.state)) {
+        match DaemonSocket::new(Arc::clone(&self.state), Arc::clone(&self.config)) {
             Ok(daemon_socket) => {
                 daemon_socket.spawn();
                 info!("Daemon socket started");
@@ -38,7 +117,7 @@ impl Daemon {
         }
 
         // Start daemon FIFO in background thread
-        match DaemonCtl::new(Arc::clone(&self.state)) {
+        match DaemonCtl::new(Arc::clone(&self.state), Arc::clone(&self.config)) {
             Ok(daemon_ctl) => {
                 daemon_ctl.spawn();
                 info!("Daemon FIFO started");
@@ -48,35 +127,46 @@ impl Daemon {
             }
         }
 
+        // Have session dirs (and standby sessions) ready before the first request
+        self.state
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .sessions
+            .ensure_standby(&self.config.get());
+
         let builder = Builder::session()?.name(SERVICE_NAME)?;
 
-        // Register portals
         let builder = self.register_portals(builder)?;
-
-        let _connection = builder.build().await?;
+        let builder = builder
+            .serve_at(EVENTS_PATH, Events)?
+            .serve_at(EVENTS_PATH, DaemonInfo)?;
+
+        let connection = builder.build().await?;
+        self.state
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .sessions
+            .events()
+            .connect(connection.clone());
 
         info!(
             service = SERVICE_NAME,
             path = OBJECT_PATH,
             "Registered on D-Bus session bus"
         );
+        info!(path = EVENTS_PATH, "Emitting session events");
         info!("Waiting for requests...");
 
-        // Keep running
-        std::future::pending::<()>().await;
+        let shutdown = self.shutdown_handle();
+        shutdown.wait().await;
+        shutdown::wind_down(&self.state).await;
+        drop(connection);
 
         Ok(())
     }
 
     fn register_portals(&self, builder: Builder<'static>) -> Result<Builder<'static>, zbus::Error> {
-        info!("Registering FileChooser portal");
-        let file_chooser = TtyFileChooser::new(Arc::clone(&self.config), Arc::clone(&self.state));
-        let builder = builder.serve_at(OBJECT_PATH, FileChooserPortal::from(file_chooser))?;
-
-        info!("Registering Screenshot portal");
-        let screenshot = TtyScreenshot::new(Arc::clone(&self.config), Arc::clone(&self.state));
-        let builder = builder.serve_at(OBJECT_PATH, ScreenshotPortal::from(screenshot))?;
-
-        Ok(builder)
+        let ctx = PortalContext::new(Arc::clone(&self.config), Arc::clone(&self.state));
+        self.portals.serve(builder, OBJECT_PATH, &ctx)
     }
 }
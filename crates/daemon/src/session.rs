@@ -1,21 +1,43 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::os::unix::fs::PermissionsExt;
+use std::io::{BufRead, Write};
+use std::os::fd::AsRawFd;
+use std::os::linux::process::PidFd;
 use std::path::{Path, PathBuf};
-use std::process::{Child, Command};
+use std::process::{Child, Command, ExitStatus};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
 
+use libportty::config::{CLI_BIN, find_program_in};
+use libportty::portal::QueueDiff;
 use libportty::portal::intent::queue;
-use libportty::{files, paths};
-use tracing::info;
+use libportty::session_dir::{self, SessionDirs};
+use libportty::{Operation, QueueConflict, QueuePolicy, SortOrder, files, paths};
+use tracing::{info, warn};
+
+use crate::attention;
+use crate::config::{Attention, EnvFilter, OnTerminalExit, Priority};
+use crate::environ;
+use crate::inline::Lease;
+use crate::priority;
+use crate::tabs::{self, Tabs};
+use crate::umask;
 
 /// Signal sent to the session thread
 pub enum SessionSignal {
-    Submit,
+    /// `confirmed`: the user confirmed paths flagged by the `audit` config
+    Submit {
+        allow_empty: bool,
+        confirmed: bool,
+        sort: Option<SortOrder>,
+    },
     Cancel,
-    ChildExited,
+    /// The session process exited, with its status (none for a tmux window)
+    ChildExited(Option<ExitStatus>),
+    /// The app closed the request; nobody is waiting for the result
+    Closed,
 }
 
 /// Control handle held by the daemon to signal a session
@@ -28,19 +50,58 @@ impl SessionControl {
         Self { sender }
     }
 
-    pub fn submit(&self) {
-        let _ = self.sender.send(SessionSignal::Submit);
+    pub fn submit(&self, allow_empty: bool, confirmed: bool, sort: Option<SortOrder>) {
+        let _ = self.sender.send(SessionSignal::Submit {
+            allow_empty,
+            confirmed,
+            sort,
+        });
     }
 
     pub fn cancel(&self) {
         let _ = self.sender.send(SessionSignal::Cancel);
     }
+
+    pub fn close(&self) {
+        let _ = self.sender.send(SessionSignal::Closed);
+    }
+}
+
+/// Check of a submission before the session ends, used by
+/// `debug.keep_session`: `confirmed` is set for `portty submit --confirm`.
+pub type SubmissionCheck = Box<dyn Fn(&[String], bool) -> Result<(), String> + Send>;
+
+/// Debug mode (`debug.keep_session`): the session stays open when the app
+/// closes the request or a submission is refused
+struct KeepSession {
+    check: SubmissionCheck,
 }
 
-/// Monotonic counter to guarantee unique session IDs even within the same nanosecond
-static SESSION_COUNTER: AtomicU64 = AtomicU64::new(0);
+impl std::fmt::Debug for KeepSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KeepSession")
+    }
+}
 
-/// Unique session identifier
+/// Append `event` to the `events` log of the session in `dir` (read by the
+/// `why` shim), prefixed with the UTC time.
+pub fn record_event(dir: &Path, event: &str) {
+    let secs = unix_now() % 86_400;
+    let line = format!(
+        "{:02}:{:02}:{:02} {event}\n",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    );
+    let _ = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join("events"))
+        .and_then(|mut f| f.write_all(line.as_bytes()));
+}
+
+/// Unique session identifier: creation time plus a random suffix, so ids
+/// from concurrent requests (or another daemon instance) don't collide
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SessionId(String);
 
@@ -51,8 +112,7 @@ impl SessionId {
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_nanos();
-        let seq = SESSION_COUNTER.fetch_add(1, Ordering::Relaxed);
-        Self(format!("{:x}-{:x}", ts, seq))
+        Self(format!("{:x}-{:016x}", ts, fastrand::u64(..)))
     }
 
     pub fn as_str(&self) -> &str {
@@ -72,79 +132,357 @@ impl std::fmt::Display for SessionId {
     }
 }
 
+/// The `PORTTY_*` variables and `PATH` of a command run for session `id` in
+/// `dir`. `operation` is empty for a standby session.
+pub fn universal_env(
+    id: &str,
+    dir: &Path,
+    portal: &str,
+    operation: &str,
+) -> Vec<(&'static str, OsString)> {
+    let mut env = vec![
+        ("PORTTY_SESSION", OsString::from(id)),
+        ("PORTTY_DIR", dir.into()),
+        ("PORTTY_PORTAL", portal.into()),
+        // The session's `portty` calls find this daemon whatever their TMPDIR
+        ("PORTTY_BASE_DIR", paths::base_dir().into()),
+    ];
+    if operation.is_empty() {
+        // Standby: the operation is only known once a request adopts it
+        env.push(("PORTTY_STANDBY", "1".into()));
+    } else {
+        env.push(("PORTTY_OPERATION", operation.into()));
+    }
+
+    // Prepend session bin dir to PATH
+    let mut path = OsString::from(dir.join("bin"));
+    if let Some(inherited) = std::env::var_os("PATH") {
+        path.push(":");
+        path.push(inherited);
+    }
+    env.push(("PATH", path));
+    env
+}
+
+/// A process exiting with an error this soon after it was started failed to
+/// start (a terminal without a display, bad arguments)
+const STARTUP_GRACE: Duration = Duration::from_secs(2);
+
+/// How often the exit prompt checks for signals while it waits for an answer
+const PROMPT_POLL: Duration = Duration::from_millis(100);
+
+/// What came of the exit prompt (`on_terminal_exit = "ask"`)
+enum ExitAnswer {
+    /// Submit or not, as answered
+    Answered(bool),
+    /// A signal came first; it was put back for the session loop
+    Interrupted,
+}
+
+/// Told to the user when the app closes the request of a running session
+const WITHDRAWN: &str =
+    "portty: the application withdrew the request; nothing will be returned to it";
+
+/// How the session process was started, kept so it can be respawned
+#[derive(Debug)]
+struct SpawnSpec {
+    exec: Vec<String>,
+    portal: String,
+    operation: String,
+    cwd: Option<PathBuf>,
+    env: Vec<(&'static str, String)>,
+}
+
+/// Resolved per-session behavior
+#[derive(Debug, Clone)]
+pub struct SessionPolicy {
+    /// What the spawned process exiting on its own means
+    pub on_exit: OnTerminalExit,
+    /// Window for the answer to the exit prompt, or the confirming second
+    /// close, in `ask` mode
+    pub confirm_timeout: Duration,
+    /// Empty submissions are success (zero results) rather than cancellation
+    pub allow_empty: bool,
+    /// Cue for a session left waiting for input
+    pub attention: Attention,
+    /// How long the session waits before the cue
+    pub attention_delay: Duration,
+    /// Report of a dialog left open too long (`stale_after`)
+    pub stale: Option<StaleReport>,
+    /// How long a session without a terminal waits for its selection to be
+    /// used before it fails (`headless_timeout`)
+    pub headless_timeout: Option<Duration>,
+}
+
+/// What to do when a dialog has been open for `after` (`stale_after`,
+/// `on_stale`)
+#[derive(Debug, Clone)]
+pub struct StaleReport {
+    pub after: Duration,
+    /// Command run once, besides the warning in the log
+    pub command: Option<Vec<String>>,
+    pub operation: String,
+    pub app_id: Option<String>,
+}
+
 /// A running portal session
+#[derive(Debug)]
 pub struct Session {
     id: SessionId,
+    portal: String,
     dir: PathBuf,
     child: Option<Child>,
+    spawn_spec: Option<SpawnSpec>,
     sender: mpsc::Sender<SessionSignal>,
     receiver: mpsc::Receiver<SessionSignal>,
     created: u64,
+    /// Hold on the terminal the process runs on in inline mode
+    inline: Option<Lease>,
+    /// Tmux session the process runs in, in tabs mode
+    tabs: Option<Tabs>,
+    /// Process of the session's tmux window, not yet watched
+    pane: Option<PidFd>,
+    /// Set by [`Session::keep_open`]
+    keep: Option<KeepSession>,
+    /// Commands left to try when the process fails to start, set by
+    /// [`Session::fall_back`]
+    fallback: Option<VecDeque<Vec<String>>>,
+    /// Heading announced on the login terminals if no fallback starts, set
+    /// by [`Session::announce_headless`]
+    headless_heading: Option<String>,
+    /// Failed starts so far, one line each
+    failed_starts: Vec<String>,
+    /// When the process was started, until it is known to have started
+    starting: Option<Instant>,
+    /// Umask the process runs with, set by [`Session::set_umask`]
+    umask: u32,
+    /// Scheduling of the process, set by [`Session::set_priority`]
+    priority: Priority,
+    /// Inherited variables the process keeps, set by
+    /// [`Session::set_inherit_env`]
+    inherit_env: Option<EnvFilter>,
+    /// The portty CLI a `portty` command runs as (the `cli` config)
+    cli: Option<PathBuf>,
 }
 
 impl Session {
     /// Create a new session with its directory and file-based state.
     /// Use `SessionRegistry::create_session` instead of calling this directly.
+    ///
+    /// `custom_bins` is only set for sessions that spawn a process.
     pub(crate) fn new(
         portal: &str,
         operation: &str,
         options: &serde_json::Value,
         initial_entries: &[String],
-        custom_bins: &HashMap<String, String>,
-        sender: mpsc::Sender<SessionSignal>,
-        receiver: mpsc::Receiver<SessionSignal>,
+        custom_bins: Option<&HashMap<String, String>>,
+        dirs: &SessionDirs,
+        (sender, receiver): (mpsc::Sender<SessionSignal>, mpsc::Receiver<SessionSignal>),
     ) -> std::io::Result<Self> {
         let id = SessionId::new();
         paths::ensure_base_dir()?;
         let dir = paths::base_dir().join(id.as_str());
 
-        // Create session directory
-        fs::create_dir_all(&dir)?;
-
-        // Write portal type
-        fs::write(dir.join("portal"), format!("{}\n{}", portal, operation))?;
-
-        // Write options.json
-        let options_json = serde_json::to_string_pretty(options).map_err(std::io::Error::other)?;
-        fs::write(dir.join("options.json"), options_json)?;
-
-        // Build initial submission
-        let submission_content = if initial_entries.is_empty() {
-            String::new()
-        } else {
-            format!("{}\n", initial_entries.join("\n"))
-        };
-        fs::write(dir.join("submission"), &submission_content)?;
-
-        // Create bin directory with resolved shims
-        let bin_dir = dir.join("bin");
-        fs::create_dir_all(&bin_dir)?;
-
-        // Create configured bin shims (defaults are provided by Config::resolve_bin)
-        for (name, command) in custom_bins {
-            let shim_path = bin_dir.join(name);
-            let shim_content = format!("#!/bin/sh\n{}\n", command);
-            fs::write(&shim_path, shim_content)?;
-            fs::set_permissions(&shim_path, fs::Permissions::from_mode(0o755))?;
-        }
-
-        // Get creation timestamp
-        use std::time::{SystemTime, UNIX_EPOCH};
-        let created = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        // Session dir from the pool, state files, and bin shims from templates
+        // (defaults are provided by Config::resolve_bin)
+        dirs.create(
+            &dir,
+            portal,
+            operation,
+            options,
+            initial_entries,
+            custom_bins,
+        )?;
 
         Ok(Self {
             id,
+            portal: portal.to_string(),
             dir,
             child: None,
+            spawn_spec: None,
             sender,
             receiver,
-            created,
+            created: unix_now(),
+            inline: None,
+            tabs: None,
+            pane: None,
+            keep: None,
+            fallback: None,
+            headless_heading: None,
+            failed_starts: Vec::new(),
+            starting: None,
+            umask: umask::for_commands(None),
+            priority: Priority::default(),
+            inherit_env: None,
+            cli: dirs.shims.cli().map(Path::to_path_buf),
         })
     }
 
+    /// Start a standby session for `portal`: `exec` runs right away, before
+    /// any request. Its dir only holds `bin/` until a request adopts it (see
+    /// [`Session::adopt`]), and its process sees `PORTTY_STANDBY=1` instead of
+    /// `PORTTY_OPERATION`.
+    pub(crate) fn standby(
+        portal: &str,
+        exec: &[String],
+        custom_bins: &HashMap<String, String>,
+        env: Vec<(&'static str, String)>,
+        (umask, priority, inherit_env): (u32, Priority, Option<EnvFilter>),
+        dirs: &SessionDirs,
+        (sender, receiver): (mpsc::Sender<SessionSignal>, mpsc::Receiver<SessionSignal>),
+    ) -> std::io::Result<Self> {
+        let id = SessionId::new();
+        paths::ensure_base_dir()?;
+        let dir = paths::base_dir().join(id.as_str());
+        dirs.pool.take(&dir)?;
+
+        let mut session = Self {
+            id,
+            portal: portal.to_string(),
+            dir,
+            child: None,
+            spawn_spec: None,
+            sender,
+            receiver,
+            created: 0,
+            inline: None,
+            tabs: None,
+            pane: None,
+            keep: None,
+            fallback: None,
+            headless_heading: None,
+            failed_starts: Vec::new(),
+            starting: None,
+            umask,
+            priority,
+            inherit_env,
+            cli: dirs.shims.cli().map(Path::to_path_buf),
+        };
+        dirs.shims.link(&session.dir.join("bin"), custom_bins)?;
+        session.spawn(exec, portal, "", None, env)?;
+        Ok(session)
+    }
+
+    /// Turn a standby session into a session for `operation`: write its state
+    /// files and swap in the operation's shims. The process keeps running.
+    pub(crate) fn adopt(
+        &mut self,
+        operation: &str,
+        options: &serde_json::Value,
+        initial_entries: &[String],
+        custom_bins: &HashMap<String, String>,
+        dirs: &SessionDirs,
+    ) -> std::io::Result<()> {
+        let Some(spec) = self.spawn_spec.as_mut() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "session has no process to adopt",
+            ));
+        };
+        spec.operation = operation.to_string();
+        session_dir::write_state(&self.dir, &self.portal, operation, options, initial_entries)?;
+        dirs.shims.link(&self.dir.join("bin"), custom_bins)?;
+        self.created = unix_now();
+        Ok(())
+    }
+
+    /// Run the session process on the inline terminal held by `lease`
+    /// instead of in a terminal emulator.
+    pub(crate) fn attach_inline(&mut self, lease: Lease) {
+        self.inline = Some(lease);
+    }
+
+    /// Run the session process in a window of the shared tmux session
+    /// instead of in a terminal emulator.
+    pub(crate) fn attach_tabs(&mut self, tabs: Tabs) {
+        self.tabs = Some(tabs);
+    }
+
+    /// Keep the session open for a post-mortem (`debug.keep_session`):
+    /// submissions failing `check` are refused and the terminal kept (or
+    /// brought back) with an explanation, and a close by the app leaves the
+    /// terminal open until the user exits it. Events go to the session's
+    /// `events` log.
+    pub(crate) fn keep_open(&mut self, check: SubmissionCheck) {
+        self.keep = Some(KeepSession { check });
+    }
+
+    /// Try `commands` in turn when the process fails to start: it cannot be
+    /// run, or exits with an error within [`STARTUP_GRACE`]. Once none is
+    /// left the session goes on headless, driven by the `portty` CLI, with
+    /// the failures in its `headless` file. Without fallbacks a process that
+    /// cannot be run is an error.
+    pub(crate) fn fall_back(&mut self, commands: Vec<Vec<String>>) {
+        self.fallback = Some(commands.into());
+    }
+
+    /// Announce the session with `heading` on the user's login terminals
+    /// when it goes headless after no fallback started (`headless =
+    /// "announce"`)
+    pub(crate) fn announce_headless(&mut self, heading: String) {
+        self.headless_heading = Some(heading);
+    }
+
+    /// Run the process with `mask` instead of porttyd's inherited umask
+    pub(crate) fn set_umask(&mut self, mask: u32) {
+        self.umask = mask;
+    }
+
+    /// Umask the process runs with
+    pub(crate) fn umask(&self) -> u32 {
+        self.umask
+    }
+
+    /// Run the process with the niceness, I/O class, and systemd scope of
+    /// `priority` (default: porttyd's own)
+    pub(crate) fn set_priority(&mut self, priority: Priority) {
+        self.priority = priority;
+    }
+
+    /// Scheduling the process runs with
+    pub(crate) fn priority(&self) -> &Priority {
+        &self.priority
+    }
+
+    /// Run the process without the inherited variables `filter` drops
+    /// (default: with porttyd's whole environment)
+    pub(crate) fn set_inherit_env(&mut self, filter: Option<EnvFilter>) {
+        self.inherit_env = filter;
+    }
+
+    /// Inherited variables the process keeps
+    pub(crate) fn inherit_env(&self) -> Option<&EnvFilter> {
+        self.inherit_env.as_ref()
+    }
+
+    /// Command a running standby session was started with
+    pub(crate) fn standby_exec(&self) -> Option<&[String]> {
+        self.spawn_spec.as_ref().map(|spec| spec.exec.as_slice())
+    }
+
+    /// Whether the spawned process is still running
+    pub(crate) fn is_running(&mut self) -> bool {
+        self.child
+            .as_mut()
+            .is_some_and(|child| matches!(child.try_wait(), Ok(None)))
+    }
+
+    /// Kill the spawned process (if any) before [`Session::run`] took it over.
+    pub(crate) fn terminate(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if let Some(pane) = self.pane.take() {
+            let _ = pane.kill();
+        }
+    }
+
+    /// Control handle for the registry
+    pub(crate) fn control(&self) -> SessionControl {
+        SessionControl::new(self.sender.clone())
+    }
+
     pub fn id(&self) -> &SessionId {
         &self.id
     }
@@ -157,16 +495,18 @@ impl Session {
         self.created
     }
 
-    /// Spawn a terminal with the given exec command
+    /// Spawn a terminal with the given exec command.
+    ///
+    /// An adopted standby session already runs it; only the spec used for
+    /// respawns is updated then.
     pub fn spawn(
         &mut self,
         exec: &[String],
         portal: &str,
         operation: &str,
         cwd: Option<&Path>,
+        env: Vec<(&'static str, String)>,
     ) -> std::io::Result<()> {
-        use std::os::linux::process::CommandExt as _;
-
         if exec.is_empty() {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
@@ -174,7 +514,91 @@ impl Session {
             ));
         }
 
-        let (program, args) = exec.split_first().unwrap();
+        self.spawn_spec = Some(SpawnSpec {
+            exec: exec.to_vec(),
+            portal: portal.to_string(),
+            operation: operation.to_string(),
+            cwd: cwd.map(Path::to_path_buf),
+            env,
+        });
+        if self.child.is_some() {
+            return Ok(());
+        }
+        self.start()
+    }
+
+    /// Start the process, moving on to the fallback commands while it
+    /// cannot be run.
+    fn start(&mut self) -> std::io::Result<()> {
+        loop {
+            match self.respawn(&[]) {
+                Ok(()) => {
+                    self.starting = Some(Instant::now());
+                    return Ok(());
+                }
+                Err(e) if self.fallback.is_none() => return Err(e),
+                Err(e) => {
+                    if !self.next_fallback(&e.to_string()) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Note that the current command failed to start and switch to the next
+    /// fallback. Returns false when none is left: the session is headless
+    /// from then on.
+    fn next_fallback(&mut self, why: &str) -> bool {
+        let Some(spec) = self.spawn_spec.as_mut() else {
+            return false;
+        };
+        let failure = format!("{}: {why}", spec.exec.join(" "));
+        warn!(id = %self.id, "Session terminal failed to start: {failure}");
+        let next = self.fallback.as_mut().and_then(VecDeque::pop_front);
+        if let Some(exec) = &next {
+            info!(id = %self.id, ?exec, "Trying the next terminal");
+            spec.exec = exec.clone();
+        }
+        self.record(&format!("Terminal failed to start: {failure}"));
+        self.failed_starts.push(failure);
+        if next.is_some() {
+            return true;
+        }
+
+        warn!(id = %self.id, "No terminal started, session continues headless (use `portty` CLI to interact)");
+        self.record("No terminal started, continuing headless");
+        let mut report = self.failed_starts.join("\n");
+        report.push('\n');
+        if let Err(e) = files::write_atomic(&self.dir.join("headless"), report) {
+            warn!(id = %self.id, "Failed to record the failed starts: {e}");
+        }
+        if let Some(heading) = &self.headless_heading {
+            let terminals = attention::broadcast(self.id.as_str(), heading);
+            info!(id = %self.id, terminals, "Announced headless session");
+        }
+        false
+    }
+
+    /// (Re)start the process described by the stored spawn spec.
+    fn respawn(&mut self, extra_env: &[(&str, String)]) -> std::io::Result<()> {
+        use std::os::linux::process::CommandExt as _;
+
+        let Some(spec) = self.spawn_spec.as_ref() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "session has no process to spawn",
+            ));
+        };
+        let (portal, operation, cwd) = (&spec.portal, &spec.operation, spec.cwd.as_deref());
+        let mut exec = spec.exec.clone();
+        if let Some(cli) = &self.cli
+            && exec[0] == CLI_BIN
+        {
+            exec[0] = cli.display().to_string();
+        }
+        let exec = priority::scoped(&self.priority, &exec);
+        let (program, args) = exec.split_first().expect("checked non-empty in spawn");
 
         let mut cmd = Command::new(program);
         cmd.args(args);
@@ -184,22 +608,47 @@ impl Session {
             cmd.current_dir(cwd);
         }
 
-        let bin_dir = self.dir.join("bin");
+        environ::apply(&mut cmd, self.inherit_env.as_ref());
+        cmd.envs(universal_env(
+            self.id.as_str(),
+            &self.dir,
+            portal,
+            operation,
+        ));
+        for (key, value) in spec.env.iter().chain(extra_env) {
+            cmd.env(key, value);
+        }
 
-        // Set universal env vars
-        cmd.env("PORTTY_SESSION", self.id.as_str());
-        cmd.env("PORTTY_DIR", &self.dir);
-        cmd.env("PORTTY_PORTAL", portal);
-        cmd.env("PORTTY_OPERATION", operation);
+        // Fail with the program's name rather than a bare ENOENT
+        let path = cmd
+            .get_envs()
+            .find(|(key, _)| *key == "PATH")
+            .and_then(|(_, value)| value)
+            .unwrap_or_default();
+        if find_program_in(program, path).is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "`{program}` not found in the session's PATH ({})",
+                    OsStr::display(path)
+                ),
+            ));
+        }
 
-        // Prepend session bin dir to PATH
-        if let Ok(path) = std::env::var("PATH") {
-            cmd.env("PATH", format!("{}:{}", bin_dir.display(), path));
-        } else {
-            cmd.env("PATH", bin_dir);
+        if let Some(tabs) = &self.tabs {
+            self.pane = Some(tabs.open(self.id.as_str(), &cmd, self.umask, &self.priority)?);
+            return Ok(());
+        }
+        if let Some(lease) = &self.inline {
+            lease.prepare(&mut cmd)?;
         }
 
+        umask::apply(&mut cmd, self.umask);
+        priority::apply(&mut cmd, &self.priority);
         let child = cmd.spawn()?;
+        if let Some(lease) = &mut self.inline {
+            lease.started(child.id());
+        }
         self.child = Some(child);
 
         Ok(())
@@ -210,59 +659,403 @@ impl Session {
     /// Converts the child process into a `PidFd` shared between a monitor
     /// thread (that waits for exit) and this thread (that can kill on
     /// submit/cancel). The channel `recv()` blocks cleanly with no polling.
-    pub fn run(&mut self) -> std::io::Result<SessionResult> {
+    ///
+    /// When the child exits on its own, `policy` decides whether that counts as
+    /// submit, cancel, or needs confirmation (prompt on the daemon's TTY, or a
+    /// second close of a respawned terminal, within the confirmation window).
+    pub fn run(&mut self, policy: SessionPolicy) -> std::io::Result<SessionResult> {
+        let mut pidfd = self.watch_child()?;
+        let mut first_close: Option<Instant> = None;
+        let mut cue_at = (pidfd.is_some() && policy.attention != Attention::None)
+            .then(|| Instant::now() + policy.attention_delay);
+        let mut stale_at = policy.stale.as_ref().map(|stale| {
+            let waited = Duration::from_secs(unix_now().saturating_sub(self.created));
+            Instant::now() + stale.after.saturating_sub(waited)
+        });
+        let mut unattended_at = None;
+        if pidfd.is_none() {
+            unattended_at = self.unattended_deadline(&policy);
+        }
+
+        loop {
+            let deadlines = cue_at.into_iter().chain(stale_at);
+            let signal = match deadlines.chain(unattended_at.map(|(at, _)| at)).min() {
+                Some(at) => match self
+                    .receiver
+                    .recv_timeout(at.saturating_duration_since(Instant::now()))
+                {
+                    Ok(signal) => Ok(signal),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        let now = Instant::now();
+                        if cue_at.is_some_and(|at| at <= now) {
+                            cue_at = None;
+                            self.cue_waiting(policy.attention);
+                        }
+                        if stale_at.is_some_and(|at| at <= now) {
+                            stale_at = None;
+                            if let Some(stale) = &policy.stale {
+                                self.report_stale(stale);
+                            }
+                        }
+                        if let Some((at, selection)) = unattended_at
+                            && at <= now
+                        {
+                            unattended_at = None;
+                            if self.selection_written() == selection {
+                                let waited = policy.headless_timeout.unwrap_or_default();
+                                info!(id = %self.id, ?waited, "Nobody used the headless session, failing it (headless_timeout)");
+                                self.record("Nobody used the session within headless_timeout");
+                                return Ok(SessionResult::Unattended { waited });
+                            }
+                            info!(id = %self.id, "Headless session in use, waiting on");
+                        }
+                        continue;
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => Err(mpsc::RecvError),
+                },
+                None => self.receiver.recv(),
+            };
+
+            match signal {
+                Ok(SessionSignal::Submit {
+                    allow_empty,
+                    confirmed,
+                    sort,
+                }) => {
+                    let allow_empty = allow_empty || policy.allow_empty;
+                    if let Some(refusal) = self.refusal(allow_empty, confirmed) {
+                        self.record(&format!("Submission refused: {refusal}"));
+                        attention::notice(self.id.as_str(), &refusal_notice(&refusal));
+                        continue;
+                    }
+                    self.record("Submitted");
+                    kill_child(pidfd.as_deref());
+                    return self.read_result(allow_empty, confirmed, sort);
+                }
+                Ok(SessionSignal::Cancel) => {
+                    self.record("Cancelled");
+                    kill_child(pidfd.as_deref());
+                    return Ok(SessionResult::Cancelled);
+                }
+                Ok(SessionSignal::Closed) => {
+                    self.record("The app closed the request");
+                    if self.keep.is_some() && pidfd.is_some() {
+                        info!(id = %self.id, "Request closed by the app, keeping the terminal open (debug.keep_session)");
+                        attention::notice(
+                            self.id.as_str(),
+                            &[
+                                WITHDRAWN,
+                                "portty: run `why` to see what happened, then exit this terminal",
+                            ],
+                        );
+                        while let Ok(signal) = self.receiver.recv() {
+                            if matches!(signal, SessionSignal::ChildExited(_)) {
+                                break;
+                            }
+                        }
+                    } else {
+                        self.tell_withdrawn(pidfd.as_deref());
+                    }
+                    return Ok(SessionResult::ClosedByApp);
+                }
+                Ok(SessionSignal::ChildExited(status)) => {
+                    // An error right after the start: try the next terminal
+                    let failed_start = self
+                        .starting
+                        .take()
+                        .filter(|at| self.fallback.is_some() && at.elapsed() < STARTUP_GRACE)
+                        .zip(status.filter(|status| !status.success()));
+                    if let Some((at, status)) = failed_start {
+                        let why = format!("{status} after {:.1}s", at.elapsed().as_secs_f32());
+                        if self.next_fallback(&why) {
+                            self.start()?;
+                        }
+                        pidfd = self.watch_child()?;
+                        if pidfd.is_none() && unattended_at.is_none() {
+                            unattended_at = self.unattended_deadline(&policy);
+                        }
+                        continue;
+                    }
+                    let submit = match policy.on_exit {
+                        OnTerminalExit::Submit => true,
+                        OnTerminalExit::Cancel => {
+                            info!(id = %self.id, "Terminal exited, cancelling (on_terminal_exit = cancel)");
+                            false
+                        }
+                        OnTerminalExit::Ask => {
+                            let answer = self.prompt_controlling_tty(policy.confirm_timeout);
+                            if let Some(ExitAnswer::Answered(confirmed)) = answer {
+                                confirmed
+                            } else if let Some(ExitAnswer::Interrupted) = answer {
+                                // The signal is handled like any other
+                                continue;
+                            } else if first_close
+                                .is_some_and(|t| t.elapsed() <= policy.confirm_timeout)
+                            {
+                                info!(id = %self.id, "Terminal closed twice, submitting");
+                                true
+                            } else {
+                                // Bring the terminal back; closing it again quickly confirms
+                                first_close = Some(Instant::now());
+                                let timeout = policy.confirm_timeout.as_secs().to_string();
+                                match self.respawn(&[("PORTTY_CONFIRM_EXIT", timeout)]) {
+                                    Ok(()) => {
+                                        info!(id = %self.id, "Terminal exited, respawned to confirm submission");
+                                        pidfd = self.watch_child()?;
+                                        continue;
+                                    }
+                                    Err(e) => {
+                                        tracing::warn!(id = %self.id, "Failed to respawn terminal for exit confirmation: {e}");
+                                        true
+                                    }
+                                }
+                            }
+                        }
+                    };
+                    if !submit {
+                        self.record("Terminal exited, cancelled");
+                        return Ok(SessionResult::Cancelled);
+                    }
+
+                    if let Some(refusal) = self.refusal(policy.allow_empty, false) {
+                        self.record(&format!("Terminal exited, submission refused: {refusal}"));
+                        match self.respawn(&[]) {
+                            Ok(()) => {
+                                info!(id = %self.id, "Submission refused, terminal reopened (debug.keep_session)");
+                                attention::notice_when_ready(
+                                    self.id.as_str(),
+                                    refusal_notice(&refusal),
+                                );
+                                first_close = None;
+                                pidfd = self.watch_child()?;
+                                continue;
+                            }
+                            Err(e) => {
+                                tracing::warn!(id = %self.id, "Failed to reopen terminal after refused submission: {e}");
+                            }
+                        }
+                    }
+                    self.record("Terminal exited, submitted");
+                    return self.read_result(policy.allow_empty, false, None);
+                }
+                Err(_) => {
+                    // All senders dropped — session is orphaned
+                    kill_child(pidfd.as_deref());
+                    return Ok(SessionResult::Cancelled);
+                }
+            }
+        }
+    }
+
+    /// Hand the current child (or tmux window process) to a monitor thread
+    /// that signals its exit.
+    fn watch_child(&mut self) -> std::io::Result<Option<Arc<PidFd>>> {
         use std::os::linux::process::ChildExt as _;
 
-        let pidfd = if let Some(child) = self.child.take() {
-            let pidfd = Arc::new(child.into_pidfd().map_err(|_child| {
-                std::io::Error::new(std::io::ErrorKind::Unsupported, "pidfd not available")
-            })?);
+        let (pidfd, pane) = match (self.child.take(), self.pane.take()) {
+            (Some(child), _) => {
+                let pidfd = child.into_pidfd().map_err(|_child| {
+                    std::io::Error::new(std::io::ErrorKind::Unsupported, "pidfd not available")
+                })?;
+                (Arc::new(pidfd), false)
+            }
+            (None, Some(pane)) => (Arc::new(pane), true),
+            (None, None) => return Ok(None),
+        };
 
-            let monitor_pidfd = Arc::clone(&pidfd);
-            let sender = self.sender.clone();
-            std::thread::spawn(move || {
-                let _ = monitor_pidfd.wait();
-                let _ = sender.send(SessionSignal::ChildExited);
-            });
+        let monitor_pidfd = Arc::clone(&pidfd);
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            // Not our child: waitid() would fail right away
+            let status = if pane {
+                tabs::wait(&monitor_pidfd);
+                None
+            } else {
+                monitor_pidfd.wait().ok()
+            };
+            let _ = sender.send(SessionSignal::ChildExited(status));
+        });
 
-            Some(pidfd)
-        } else {
-            None
+        Ok(Some(pidfd))
+    }
+
+    /// Cue the session terminal that the dialog is still waiting for input.
+    fn cue_waiting(&self, cue: Attention) {
+        let what = self
+            .spawn_spec
+            .as_ref()
+            .map(|spec| format!("{} {}", spec.portal, spec.operation))
+            .unwrap_or_else(|| "dialog".to_string());
+        info!(id = %self.id, ?cue, "Session still waiting for input, cueing terminal");
+        attention::cue(
+            self.id.as_str(),
+            cue,
+            &format!("portty: {what} is waiting for input"),
+        );
+    }
+
+    /// Warn that the dialog has been open for long, and run `on_stale` with
+    /// the session's umask, priority, and environment. The command is not
+    /// waited for.
+    fn report_stale(&self, stale: &StaleReport) {
+        let waiting = unix_now().saturating_sub(self.created);
+        let app_id = stale.app_id.as_deref().unwrap_or("");
+        warn!(
+            id = %self.id,
+            portal = %self.portal,
+            operation = %stale.operation,
+            app_id,
+            "Dialog open for {}m, its app is still waiting",
+            waiting / 60
+        );
+        self.record(&format!("Open for {}m", waiting / 60));
+        let Some(argv) = &stale.command else {
+            return;
         };
+        let exec = priority::scoped(&self.priority, argv);
+        let mut cmd = Command::new(&exec[0]);
+        environ::apply(&mut cmd, self.inherit_env.as_ref());
+        cmd.args(&exec[1..])
+            .env("PORTTY_SESSION", self.id.as_str())
+            .env("PORTTY_PORTAL", &self.portal)
+            .env("PORTTY_OPERATION", &stale.operation)
+            .env("PORTTY_APP_ID", app_id)
+            .env("PORTTY_WAITING", waiting.to_string())
+            .stdin(std::process::Stdio::null());
+        umask::apply(&mut cmd, self.umask);
+        priority::apply(&mut cmd, &self.priority);
+        match cmd.spawn() {
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => warn!(id = %self.id, program = %argv[0], "Failed to run on_stale: {e}"),
+        }
+    }
 
-        match self.receiver.recv() {
-            Ok(SessionSignal::Submit) => {
-                if let Some(ref pidfd) = pidfd {
-                    let _ = pidfd.kill();
-                    let _ = pidfd.wait();
-                }
-                self.read_result()
+    /// End the session of a withdrawn request, saying so where someone may be
+    /// looking: on the inline terminal once it is taken back, otherwise on
+    /// the session's terminal (a joined shell outlives it)
+    fn tell_withdrawn(&mut self, pidfd: Option<&PidFd>) {
+        if self.inline.is_none() {
+            attention::notice(self.id.as_str(), &[WITHDRAWN]);
+        }
+        kill_child(pidfd);
+        if let Some(lease) = self.inline.as_mut() {
+            lease.restore();
+            let mut tty = lease.tty();
+            let _ = write!(tty, "{WITHDRAWN}\r\n");
+        }
+    }
+
+    /// Ask whether to submit on the daemon's controlling terminal, or the
+    /// inline terminal (taken back from the exited process first), for at
+    /// most `timeout`.
+    ///
+    /// Returns `None` when the daemon has no controlling TTY (the usual case
+    /// for a D-Bus activated service).
+    fn prompt_controlling_tty(&mut self, timeout: Duration) -> Option<ExitAnswer> {
+        let tty = match self.inline.as_mut() {
+            Some(lease) => {
+                lease.restore();
+                lease.tty().try_clone().ok()?
             }
-            Ok(SessionSignal::Cancel) => {
-                if let Some(ref pidfd) = pidfd {
-                    let _ = pidfd.kill();
-                    let _ = pidfd.wait();
-                }
-                Ok(SessionResult::Cancelled)
+            None => fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")
+                .ok()?,
+        };
+        self.prompt_exit(&tty, timeout)
+    }
+
+    /// Ask on `tty` whether to submit. No answer within `timeout` is no; a
+    /// submit, cancel, or close of the request meanwhile ends the prompt.
+    fn prompt_exit(&self, tty: &fs::File, timeout: Duration) -> Option<ExitAnswer> {
+        let mut writer = tty;
+        write!(
+            writer,
+            "\nportty: session {} terminal exited. Submit selection? [y/N] ({}s) ",
+            self.id,
+            timeout.as_secs()
+        )
+        .ok()?;
+        writer.flush().ok()?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Ok(signal) = self.receiver.try_recv() {
+                let _ = writeln!(writer);
+                let _ = self.sender.send(signal);
+                return Some(ExitAnswer::Interrupted);
             }
-            Ok(SessionSignal::ChildExited) => self.read_result(),
-            Err(_) => {
-                // All senders dropped — session is orphaned
-                if let Some(ref pidfd) = pidfd {
-                    let _ = pidfd.kill();
-                    let _ = pidfd.wait();
-                }
-                Ok(SessionResult::Cancelled)
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                let _ = writeln!(writer, "\nportty: no answer, not submitting");
+                info!(id = %self.id, "No answer to the exit prompt, cancelling");
+                return Some(ExitAnswer::Answered(false));
+            }
+            if wait_readable(tty, left.min(PROMPT_POLL)) {
+                break;
             }
         }
+
+        let mut answer = String::new();
+        std::io::BufReader::new(tty).read_line(&mut answer).ok()?;
+        Some(ExitAnswer::Answered(matches!(
+            answer.trim(),
+            "y" | "Y" | "yes"
+        )))
     }
 
-    fn read_result(&self) -> std::io::Result<SessionResult> {
+    /// Why the current submission would be refused, in debug mode
+    /// (`debug.keep_session`). Cancellations are never refused.
+    fn refusal(&self, allow_empty: bool, confirmed: bool) -> Option<String> {
+        let keep = self.keep.as_ref()?;
+        match self.read_result(allow_empty, confirmed, None) {
+            Ok(SessionResult::Success {
+                entries, confirmed, ..
+            }) if !entries.is_empty() => (keep.check)(&entries, confirmed).err(),
+            _ => None,
+        }
+    }
+
+    /// When a session left without a terminal fails unless its selection
+    /// changed from the one recorded with it
+    fn unattended_deadline(&self, policy: &SessionPolicy) -> Option<(Instant, Option<SystemTime>)> {
+        let after = policy.headless_timeout?;
+        Some((Instant::now() + after, self.selection_written()))
+    }
+
+    /// When the selection was last written
+    fn selection_written(&self) -> Option<SystemTime> {
+        fs::metadata(self.dir.join("submission"))
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// Record a session event, in debug mode (`debug.keep_session`)
+    fn record(&self, event: &str) {
+        if self.keep.is_some() {
+            record_event(&self.dir, event);
+        }
+    }
+
+    /// Read the submission. Empty means cancelled unless `allow_empty`.
+    fn read_result(
+        &self,
+        allow_empty: bool,
+        confirmed: bool,
+        sort: Option<SortOrder>,
+    ) -> std::io::Result<SessionResult> {
         let entries = files::read_lines(&self.dir.join("submission"));
-        if entries.is_empty() {
+        if entries.is_empty() && !allow_empty {
             Ok(SessionResult::Cancelled)
         } else {
-            Ok(SessionResult::Success { entries })
+            Ok(SessionResult::Success {
+                entries,
+                confirmed,
+                sort,
+            })
         }
     }
 
@@ -277,60 +1070,209 @@ impl Drop for Session {
     }
 }
 
+pub(crate) fn unix_now() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Lines explaining a refused submission on the session terminal
+fn refusal_notice(refusal: &str) -> Vec<String> {
+    vec![
+        format!("portty: submission refused: {refusal}"),
+        "portty: fix the selection and submit again; `why` shows what happened".to_string(),
+    ]
+}
+
+/// Kill the session process (if any) and reap it (a tmux window process is
+/// reaped by tmux).
+/// Whether `fd` has input within `timeout`
+pub(crate) fn wait_readable(fd: &impl AsRawFd, timeout: Duration) -> bool {
+    let mut fds = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let millis = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    // SAFETY: `fds` is a single valid pollfd for the duration of the call
+    unsafe { libc::poll(&mut fds, 1, millis) > 0 }
+}
+
+fn kill_child(pidfd: Option<&PidFd>) {
+    if let Some(pidfd) = pidfd {
+        let _ = pidfd.kill();
+        let _ = pidfd.wait();
+    }
+}
+
 /// Drain pending queue state into the session's submission file.
 ///
 /// Pending queue state is typed-only and materialized against the current
 /// portal request before being appended to the live session submission file.
+/// Entries the session already has, in any spelling, are not appended again
+/// (see [`QueueDiff`]). The append is all or nothing: on failure the file is cut back, the intent
+/// stays pending with the failure counted, and after
+/// [`queue::MAX_ATTEMPTS`] it is dropped. Returns the number of entries
+/// appended.
 pub fn drain_pending_to(
     session_dir: &Path,
-    portal: &str,
-    operation: &str,
+    operation: Operation,
     options: &serde_json::Value,
-) {
-    if let Some(intent) = queue::read(&paths::pending_dir()) {
-        match libportty::portal::materialize_intent(portal, operation, &intent, options) {
-            Ok(entries) => {
-                let session_sub = session_dir.join("submission");
-                let written = fs::OpenOptions::new()
-                    .append(true)
-                    .open(&session_sub)
-                    .and_then(|mut f| {
-                        use std::io::Write;
-                        for entry in &entries {
-                            writeln!(f, "{entry}")?;
-                        }
-                        f.flush()
-                    });
+) -> usize {
+    let pending = paths::pending_dir();
+    let Some(intent) = queue::read(&pending) else {
+        return 0;
+    };
+    // An intent for another portal waits for it; that is not a failure
+    let entries = match libportty::portal::materialize_intent(operation, &intent, options) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to materialize pending intent for session, preserving intent: {e}");
+            return 0;
+        }
+    };
 
-                match written {
-                    Ok(()) => {
-                        let _ = queue::clear(&paths::pending_dir());
-                        info!(entries = entries.len(), "Drained pending intent to session");
-                    }
-                    Err(e) => {
-                        tracing::warn!(
-                            "Failed to drain pending intent to session, preserving intent: {e}"
-                        );
-                    }
-                }
-            }
-            Err(e) => {
-                tracing::warn!(
-                    "Failed to materialize pending intent for session, preserving intent: {e}"
+    let sub = session_dir.join("submission");
+    let diff = QueueDiff::new(&files::read_lines(&sub), &entries);
+    let entries = diff.queued_only;
+    match append_all(&sub, &entries) {
+        Ok(()) => {
+            let _ = queue::clear(&pending);
+            info!(
+                entries = entries.len(),
+                already_selected = diff.both.len(),
+                "Drained pending intent to session"
+            );
+            entries.len()
+        }
+        Err(e) => {
+            let error = e.to_string();
+            let attempts = match queue::record_failure(&pending, &error) {
+                Ok(failures) => failures.attempts,
+                // Without a count the intent could never be dropped
+                Err(_) => queue::MAX_ATTEMPTS,
+            };
+            if attempts >= queue::MAX_ATTEMPTS {
+                let _ = queue::clear(&pending);
+                warn!(
+                    attempts,
+                    items = intent.len(),
+                    %error,
+                    "Dropping pending intent that failed to apply"
+                );
+            } else {
+                warn!(
+                    attempts,
+                    max_attempts = queue::MAX_ATTEMPTS,
+                    %error,
+                    "Failed to drain pending intent to session, preserving intent"
                 );
             }
+            0
         }
     }
 }
 
+/// Settle the pending queue with the session's selection at submit. When
+/// entries were queued while the session has a selection of its own (see
+/// [`QueueDiff::diverges`]), `prefer` decides: `merge` drains them into the
+/// session as [`drain_pending_to`] does, `session` leaves them pending,
+/// `queue` replaces the session's selection with them, and `ask` refuses
+/// with the difference. Returns the number of entries the submission took
+/// from the queue.
+pub fn settle_pending(
+    session_dir: &Path,
+    operation: Operation,
+    options: &serde_json::Value,
+    prefer: QueueConflict,
+) -> Result<usize, String> {
+    let pending = paths::pending_dir();
+    let queued = queue::read(&pending)
+        .and_then(|intent| libportty::portal::materialize_intent(operation, &intent, options).ok());
+    let Some(queued) = queued else {
+        return Ok(drain_pending_to(session_dir, operation, options));
+    };
+    let sub = session_dir.join("submission");
+    let diff = QueueDiff::new(&files::read_lines(&sub), &queued);
+    if !diff.diverges() {
+        return Ok(drain_pending_to(session_dir, operation, options));
+    }
+
+    match prefer {
+        QueueConflict::Merge => Ok(drain_pending_to(session_dir, operation, options)),
+        QueueConflict::Session => {
+            info!(
+                queued = queued.len(),
+                "Submitting the session's selection, the queue stays pending"
+            );
+            Ok(0)
+        }
+        QueueConflict::Queue => {
+            files::write_lines(&sub, &queued).map_err(|e| e.to_string())?;
+            let _ = queue::clear(&pending);
+            info!(
+                entries = queued.len(),
+                "Submitting the queued entries instead of the session's"
+            );
+            Ok(queued.len())
+        }
+        QueueConflict::Ask => Err(queue_conflict(&diff)),
+    }
+}
+
+/// Why a submit with `queue_conflict = "ask"` is refused
+pub fn queue_conflict(diff: &QueueDiff) -> String {
+    let queued = diff.queued_only.len();
+    format!(
+        "{queued} queued {} not in the session's selection of {}; \
+         `portty diff-queue` shows them, `portty submit --prefer merge|session|queue` chooses",
+        if queued == 1 {
+            "entry is"
+        } else {
+            "entries are"
+        },
+        diff.session_only.len() + diff.both.len()
+    )
+}
+
+/// Append `lines` to `path` in one write, cutting the file back to its
+/// previous length if it fails part way
+fn append_all(path: &Path, lines: &[String]) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new().append(true).open(path)?;
+    let len = file.metadata()?.len();
+    let content: String = lines.iter().map(|line| format!("{line}\n")).collect();
+    file.write_all(content.as_bytes())
+        .and_then(|()| file.flush())
+        .inspect_err(|_| {
+            let _ = file.set_len(len);
+        })
+}
+
+/// A queued submission taken off the queue
+#[derive(Debug)]
+pub struct QueuedSubmission {
+    /// Name of the submission's directory (`<time>-<portal>`)
+    pub id: String,
+    pub entries: Vec<String>,
+    pub policy: QueuePolicy,
+    /// Result order given to `portty submit --sort`
+    pub sort: Option<SortOrder>,
+}
+
 /// Pop a queued submission from the submissions directory matching the portal type.
 ///
 /// Submission dirs are named `<timestamp>-<portal>`. Scans in FIFO order.
+/// Each submission's `policy` file overrides `default_policy`; with
+/// `interactive-first`, submissions stay queued when `interactive` is set.
 pub fn pop_queued_submission(
-    portal: &str,
-    operation: &str,
+    op: Operation,
     options: &serde_json::Value,
-) -> Result<Option<Vec<String>>, String> {
+    default_policy: QueuePolicy,
+    interactive: bool,
+) -> Result<Option<QueuedSubmission>, String> {
+    let (portal, operation) = (op.portal().as_str(), op.as_str());
     let subs_dir = paths::submissions_dir();
     let mut entries: Vec<_> = fs::read_dir(&subs_dir)
         .ok()
@@ -352,6 +1294,17 @@ pub fn pop_queued_submission(
         let dir_portal = dir_name.split_once('-').map(|(_, p)| p).unwrap_or("any");
 
         if dir_portal == "any" || dir_portal == portal {
+            let policy = queue::read_policy(&sub_dir).unwrap_or(default_policy);
+            if policy == QueuePolicy::InteractiveFirst && interactive {
+                tracing::info!(
+                    queued = %sub_dir.display(),
+                    portal,
+                    operation,
+                    "Leaving queued submission for a non-interactive session"
+                );
+                continue;
+            }
+
             let Some(intent) = queue::read(&sub_dir) else {
                 tracing::info!(
                     queued = %sub_dir.display(),
@@ -362,22 +1315,27 @@ pub fn pop_queued_submission(
                 continue;
             };
 
-            let submission =
-                match libportty::portal::materialize_intent(portal, operation, &intent, options) {
-                    Ok(entries) => entries,
-                    Err(e) => {
-                        tracing::info!(
-                            queued = %sub_dir.display(),
-                            portal,
-                            operation,
-                            error = %e,
-                            "Skipping incompatible queued intent"
-                        );
-                        continue;
-                    }
-                };
+            let submission = match libportty::portal::materialize_intent(op, &intent, options) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    tracing::info!(
+                        queued = %sub_dir.display(),
+                        portal,
+                        operation,
+                        error = %e,
+                        "Skipping incompatible queued intent"
+                    );
+                    continue;
+                }
+            };
+            let sort = queue::read_sort(&sub_dir);
             let _ = fs::remove_dir_all(&sub_dir);
-            return Ok(Some(submission));
+            return Ok(Some(QueuedSubmission {
+                id: dir_name.into_owned(),
+                entries: submission,
+                policy,
+                sort,
+            }));
         }
     }
 
@@ -387,6 +1345,186 @@ pub fn pop_queued_submission(
 /// Result from a session
 #[derive(Debug)]
 pub enum SessionResult {
-    Success { entries: Vec<String> },
+    Success {
+        entries: Vec<String>,
+        /// Submitted with `--confirm`
+        confirmed: bool,
+        /// Submitted with `--sort`
+        sort: Option<SortOrder>,
+    },
+    /// The user cancelled, or the session ended without a submission
     Cancelled,
+    /// The app withdrew the request (`Request.Close`) before it was answered
+    ClosedByApp,
+    /// Nobody used the session, which has no terminal, for `waited`
+    /// (`headless_timeout`)
+    Unattended { waited: Duration },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::OwnedFd;
+    use std::os::unix::net::UnixStream;
+
+    use libportty::session_dir::{DirPool, ShimTemplates};
+
+    use super::*;
+
+    const SELECTION: &str = "file:///tmp/a";
+
+    /// A file chooser session running `exec`, with one entry selected
+    fn session(exec: &[&str]) -> Session {
+        let root = std::env::temp_dir().join(format!("porttyd-test-{}", std::process::id()));
+        paths::configure_base_dir(root.clone());
+        let dirs = SessionDirs {
+            pool: DirPool::new(root.join(".pool"), 0),
+            shims: ShimTemplates::new(root.join(".shims")),
+        };
+        let mut session = Session::new(
+            "file-chooser",
+            "open-file",
+            &serde_json::json!({}),
+            &[SELECTION.to_string()],
+            None,
+            &dirs,
+            mpsc::channel(),
+        )
+        .unwrap();
+        let exec: Vec<String> = exec.iter().map(|arg| arg.to_string()).collect();
+        session
+            .spawn(&exec, "file-chooser", "open-file", None, Vec::new())
+            .unwrap();
+        session
+    }
+
+    fn policy(on_exit: OnTerminalExit) -> SessionPolicy {
+        SessionPolicy {
+            on_exit,
+            confirm_timeout: Duration::from_secs(5),
+            allow_empty: false,
+            attention: Attention::None,
+            attention_delay: Duration::ZERO,
+            stale: None,
+            headless_timeout: None,
+        }
+    }
+
+    #[test]
+    fn queued_entries_the_session_has_are_not_added_again() {
+        use libportty::portal::file_chooser::{SelectionMode, SessionOptions};
+        use libportty::portal::intent::{Intent, IntentFamily, IntentItem};
+
+        let session = session(&["true"]);
+        let options = serde_json::to_value(SessionOptions {
+            mode: SelectionMode::Pick {
+                multiple: true,
+                directory: false,
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        let pending = paths::pending_dir();
+        let enqueue = |paths: &[&str]| {
+            let items = paths.iter().map(|p| IntentItem::Path(p.to_string()));
+            let intent = Intent::multi(IntentFamily::Path, items.collect()).unwrap();
+            queue::write(&pending, &intent).unwrap();
+        };
+        let submission = || files::read_lines(&session.dir().join("submission"));
+
+        // The queue holds what the session has, spelled differently
+        enqueue(&["/tmp/a"]);
+        let settled = settle_pending(
+            session.dir(),
+            Operation::OpenFile,
+            &options,
+            QueueConflict::Ask,
+        );
+        assert_eq!(settled, Ok(0));
+        assert_eq!(submission(), [SELECTION]);
+        assert!(queue::read(&pending).is_none());
+
+        // Merging adds only what the session does not have
+        enqueue(&["/tmp/a", "/tmp/b"]);
+        let settled = settle_pending(
+            session.dir(),
+            Operation::OpenFile,
+            &options,
+            QueueConflict::Merge,
+        );
+        assert_eq!(settled, Ok(1));
+        let entries = submission();
+        assert_eq!(entries.len(), 2, "{entries:?}");
+        assert!(
+            libportty::uri::same_entry(&entries[1], "/tmp/b"),
+            "{entries:?}"
+        );
+        assert!(queue::read(&pending).is_none());
+    }
+
+    /// A terminal for the exit prompt, and the user's end of it
+    fn tty() -> (fs::File, UnixStream) {
+        let (tty, user) = UnixStream::pair().unwrap();
+        (fs::File::from(OwnedFd::from(tty)), user)
+    }
+
+    #[test]
+    fn terminal_exit_submits_or_cancels_as_configured() {
+        let result = session(&["true"]).run(policy(OnTerminalExit::Submit));
+        assert!(
+            matches!(&result, Ok(SessionResult::Success { entries, .. }) if entries == &[SELECTION]),
+            "{result:?}"
+        );
+
+        let result = session(&["true"]).run(policy(OnTerminalExit::Cancel));
+        assert!(matches!(result, Ok(SessionResult::Cancelled)), "{result:?}");
+    }
+
+    #[test]
+    fn exit_prompt_takes_an_answer_or_gives_up() {
+        let session = session(&["true"]);
+        let (tty, mut user) = tty();
+
+        user.write_all(b"y\n").unwrap();
+        let answer = session.prompt_exit(&tty, Duration::from_secs(5));
+        assert!(matches!(answer, Some(ExitAnswer::Answered(true))));
+        user.write_all(b"n\n").unwrap();
+        let answer = session.prompt_exit(&tty, Duration::from_secs(5));
+        assert!(matches!(answer, Some(ExitAnswer::Answered(false))));
+
+        // Nobody answers: not submitted, once the confirmation window is over
+        let started = Instant::now();
+        let answer = session.prompt_exit(&tty, Duration::from_millis(300));
+        assert!(matches!(answer, Some(ExitAnswer::Answered(false))));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn exit_prompt_gives_way_to_submit_and_cancel() {
+        for (signal, expected) in [("submit", true), ("cancel", false)] {
+            let mut session = session(&["true"]);
+            let (tty, _user) = tty();
+            let control = session.control();
+            let sent = std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                match signal {
+                    "submit" => control.submit(false, false, None),
+                    _ => control.cancel(),
+                }
+            });
+
+            let started = Instant::now();
+            let answer = session.prompt_exit(&tty, Duration::from_secs(30));
+            sent.join().unwrap();
+            assert!(matches!(answer, Some(ExitAnswer::Interrupted)), "{signal}");
+            assert!(started.elapsed() < Duration::from_secs(5), "{signal}");
+
+            // The signal is left for the session to act on
+            let result = session.run(policy(OnTerminalExit::Ask)).unwrap();
+            assert_eq!(
+                matches!(result, SessionResult::Success { .. }),
+                expected,
+                "{signal}: {result:?}"
+            );
+        }
+    }
 }
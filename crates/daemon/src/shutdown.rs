@@ -0,0 +1,123 @@
+//! Stopping the daemon without exiting the process
+//!
+//! `portty daemon stop` and a [`ShutdownHandle`] held by an embedding process
+//! both end up in [`Daemon::run`](crate::Daemon::run): it cancels the
+//! sessions, gives them a moment to wind down, removes the socket and FIFO
+//! (waking the threads serving them), and returns.
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use futures_util::task::AtomicWaker;
+use libportty::paths;
+use libportty::socket::{self, SocketAddress};
+use tracing::info;
+
+use crate::daemon_socket::DaemonState;
+
+/// How long sessions get to unregister once cancelled
+const WIND_DOWN: Duration = Duration::from_secs(2);
+
+/// Asks a running daemon to shut down; clones stop the same daemon
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    requested: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl ShutdownHandle {
+    /// Stop the daemon: [`Daemon::run`](crate::Daemon::run) returns once its
+    /// sessions are cancelled. Later calls do nothing.
+    pub fn shutdown(&self) {
+        if !self.inner.requested.swap(true, Ordering::SeqCst) {
+            info!("Shutdown requested");
+        }
+        self.inner.waker.wake();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Resolve once shutdown is requested; one waiter at a time
+    pub(crate) async fn wait(&self) {
+        std::future::poll_fn(|cx| {
+            self.inner.waker.register(cx.waker());
+            if self.is_requested() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// Cancel all sessions, standby ones included
+pub(crate) fn cancel_sessions(state: &Arc<RwLock<DaemonState>>) {
+    let mut st = state.write().unwrap_or_else(|e| e.into_inner());
+    st.sessions.reconcile_standby(None);
+    for session in st.sessions.iter() {
+        session.control.cancel();
+    }
+}
+
+/// Cancel all sessions, wait briefly for them to unregister, then remove the
+/// socket and FIFO after waking the threads blocked on them
+pub(crate) async fn wind_down(state: &Arc<RwLock<DaemonState>>) {
+    cancel_sessions(state);
+
+    // Sessions unregister themselves once their terminal has been killed
+    let state = Arc::clone(state);
+    blocking::unblock(move || {
+        let deadline = Instant::now() + WIND_DOWN;
+        while Instant::now() < deadline
+            && !state
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .sessions
+                .is_empty()
+        {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        // The accept loop and the FIFO reader check for shutdown after each
+        // connection and line
+        let _ = UnixStream::connect(paths::daemon_socket_path());
+        for address in socket::listen_addresses() {
+            let _ = address.connect();
+        }
+        if let Ok(mut fifo) = fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(paths::daemon_ctl_path())
+        {
+            let _ = fifo.write_all(b"\n");
+        }
+        let _ = fs::remove_file(paths::daemon_socket_path());
+        let _ = fs::remove_file(paths::daemon_ctl_path());
+        remove_socket_files();
+    })
+    .await;
+    info!("Shut down");
+}
+
+/// Remove the socket files of the `listen` addresses
+pub(crate) fn remove_socket_files() {
+    for address in socket::listen_addresses() {
+        if let SocketAddress::Path(path) = address {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
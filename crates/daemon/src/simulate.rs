@@ -0,0 +1,223 @@
+//! `porttyd --simulate`: what a request would give a picker script.
+//!
+//! Builds the request options from the command line as `--oneshot` does,
+//! then creates the session directory (state files, shims) and resolves the
+//! command, environment, umask, and priority the way a portal request would,
+//! without D-Bus and without running anything. Entries after the options are checked
+//! as a submit of the session would be, `audit` and `rewrite` included.
+//!
+//! The session directory lives in a private root under the base dir and is
+//! removed on exit, so a running porttyd never sees it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use libportty::portal::file_chooser::{FilterPattern, SelectionMode, SessionOptions};
+use libportty::session_dir::{DirPool, POOL_DIR, SHIMS_DIR, SessionDirs, ShimTemplates};
+use libportty::{files, paths};
+
+use crate::config::Config;
+use crate::oneshot::Request;
+use crate::portal;
+use crate::priority;
+use crate::session::{SessionId, universal_env};
+use crate::umask;
+
+pub const USAGE: &str = "  --simulate PORTAL OPERATION [OPTION...] [--] [ENTRY...]
+                  print the command, environment, shims, and constraints of
+                  a session for the request without D-Bus or running it,
+                  and check ENTRYs as a submit would (options as for
+                  --oneshot)";
+
+/// Root of the simulated session, removed when dropped
+struct Scratch(PathBuf);
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Print what a session for `request` would get. Returns whether `entries`
+/// were accepted (always, when there are none).
+pub fn run(request: Request, entries: &[String], config: &Config) -> Result<bool, String> {
+    let op = request.operation;
+    let (portal, operation) = (op.portal().as_str(), op.as_str());
+    let (options, mut initial_entries) = portal::file_chooser::prepare(
+        &request.options,
+        config.resolve_default_filters(portal, operation),
+    );
+    let options_json =
+        serde_json::to_value(&options).map_err(|e| format!("failed to serialize options: {e}"))?;
+
+    let exec = config.resolve_exec(portal, operation);
+    let bins = config.resolve_bin(portal, operation);
+    if initial_entries.is_empty() {
+        initial_entries = config.resolve_defaults(portal, operation);
+    }
+
+    paths::ensure_base_dir().map_err(|e| format!("failed to create base dir: {e}"))?;
+    let scratch = Scratch(paths::base_dir().join(format!(".simulate-{}", std::process::id())));
+    fs::create_dir_all(&scratch.0).map_err(|e| format!("failed to create session dir: {e}"))?;
+    let dirs = SessionDirs {
+        pool: DirPool::new(scratch.0.join(POOL_DIR), 0),
+        shims: ShimTemplates::new(scratch.0.join(SHIMS_DIR)).with_cli(config.resolve_cli()),
+    };
+    let id = SessionId::new();
+    let dir = scratch.0.join(id.as_str());
+    dirs.create(
+        &dir,
+        portal,
+        operation,
+        &options_json,
+        &initial_entries,
+        exec.is_some().then_some(&bins),
+    )
+    .map_err(|e| format!("failed to create session dir: {e}"))?;
+
+    println!("Request: {portal} {operation}");
+    print_constraints(config, &request, &options);
+
+    println!("\nSession:");
+    match &exec {
+        Some(exec) => println!("  Command: {}", exec.join(" ")),
+        None => println!("  Command: none (headless, answered with the portty CLI)"),
+    }
+    let fallback: Vec<_> = config
+        .resolve_exec_fallback(portal, operation)
+        .into_iter()
+        .filter(|command| Some(command) != exec.as_ref())
+        .collect();
+    for command in &fallback {
+        println!("  Fallback: {}", command.join(" "));
+    }
+    println!("  Directory: {} (removed on exit)", dir.display());
+    if let Some(cwd) = portal::session_cwd(portal, &options_json) {
+        println!("  Working directory: {}", cwd.display());
+    }
+    let mask = umask::for_commands(config.resolve_umask(portal, operation));
+    println!("  Umask: {mask:03o}");
+    if let Some(priority) = priority::describe(&config.resolve_priority(portal, operation)) {
+        println!("  Priority: {priority}");
+    }
+    if let Some(filter) = config.resolve_inherit_env(portal, operation) {
+        let dropped: Vec<_> = filter
+            .dropped(std::env::vars_os())
+            .iter()
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        println!("  Not inherited: {}", dropped.join(" "));
+    }
+    print_list("Pre-selected", &files::read_lines(&dir.join("submission")));
+
+    println!("\nEnvironment:");
+    for (key, value) in universal_env(id.as_str(), &dir, portal, operation) {
+        println!("  {key}={}", value.to_string_lossy());
+    }
+    for (key, value) in config.session_env(portal, operation, &options_json) {
+        println!("  {key}={value}");
+    }
+
+    if exec.is_some() {
+        println!("\nShims ({}):", dir.join("bin").display());
+        for (name, command) in bins.iter().collect::<BTreeMap<_, _>>() {
+            println!("  {name}: {command}");
+        }
+    }
+
+    if entries.is_empty() {
+        return Ok(true);
+    }
+    println!("\nSubmitting {} entry(s):", entries.len());
+    match portal::review(config, op, &options_json, entries, None, false) {
+        Ok(uris) => {
+            uris.iter().for_each(|uri| println!("  {uri}"));
+            Ok(true)
+        }
+        Err(e) => {
+            println!("  refused: {e}");
+            Ok(false)
+        }
+    }
+}
+
+/// What the session accepts, from the options and config
+fn print_constraints(config: &Config, request: &Request, options: &SessionOptions) {
+    let (portal, operation) = (
+        request.operation.portal().as_str(),
+        request.operation.as_str(),
+    );
+    let accepts = match options.mode {
+        SelectionMode::Pick {
+            multiple: false,
+            directory: false,
+        } => "one file".to_string(),
+        SelectionMode::Pick {
+            multiple: true,
+            directory: false,
+        } => "one or more files".to_string(),
+        SelectionMode::Pick {
+            multiple: false,
+            directory: true,
+        } => "one folder".to_string(),
+        SelectionMode::Pick {
+            multiple: true,
+            directory: true,
+        } => "one or more folders".to_string(),
+        SelectionMode::Save => "a path to save to".to_string(),
+        SelectionMode::SaveMultiple => {
+            format!("a folder to save {} file(s) into", options.candidates.len())
+        }
+    };
+    println!("  Title: {}", options.title);
+    if !options.app_id.is_empty() {
+        println!("  App: {}", options.app_id);
+    }
+    println!("  Accepts: {accepts}");
+    if let Some(folder) = &options.current_folder {
+        println!("  Current folder: {folder}");
+    }
+    print_list("Candidates", &options.candidates);
+    let filters: Vec<_> = options
+        .filters
+        .iter()
+        .map(|filter| {
+            let patterns: Vec<_> = filter.patterns.iter().map(FilterPattern::as_str).collect();
+            format!("{} ({})", filter.name, patterns.join(", "))
+        })
+        .collect();
+    print_list("Filters", &filters);
+    if let Some(hint) = &options.hint {
+        println!("  Hint: {hint}");
+    }
+
+    let allow_empty = config.resolve_allow_empty(portal, operation)
+        && libportty::portal::allows_empty(request.operation);
+    println!(
+        "  Empty submit: {}",
+        if allow_empty {
+            "returns no files"
+        } else {
+            "cancels the dialog"
+        }
+    );
+    if config.resolve_audit(portal, operation).is_some() {
+        println!("  Audit: results are checked against the configured rules");
+    }
+    let rewrites = config.resolve_rewrite(portal, operation).len();
+    if rewrites > 0 {
+        println!("  Rewrite: {rewrites} rule(s) applied to the results");
+    }
+}
+
+fn print_list(label: &str, items: &[String]) {
+    match items {
+        [] => {}
+        [item] => println!("  {label}: {item}"),
+        items => {
+            println!("  {label}:");
+            items.iter().for_each(|item| println!("    {item}"));
+        }
+    }
+}
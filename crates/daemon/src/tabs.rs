@@ -0,0 +1,184 @@
+//! Tabs mode: concurrent sessions share one terminal.
+//!
+//! With `[tabs] enabled`, each session runs its `inline_exec` (the user's
+//! shell by default) in a new window of a tmux session on a private server
+//! (`tmux -L portty`), instead of in a terminal emulator of its own. A
+//! terminal attached to it is opened when none is. New windows are selected,
+//! so the terminal shows the most recent request; `portty focus <id>` switches
+//! to another session.
+//!
+//! The pane process is not a child of the daemon: it is watched through a
+//! pidfd that becomes readable when it exits, and killed through the same fd.
+
+use std::ffi::OsString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::linux::process::PidFd;
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+
+use tracing::{debug, info, warn};
+
+use crate::config::Priority;
+
+/// tmux server socket name (`tmux -L`)
+const SOCKET: &str = "portty";
+
+/// tmux session holding the session windows
+const SESSION: &str = "portty";
+
+/// Serializes window creation, so concurrent requests don't both create the
+/// tmux session
+static OPEN: Mutex<()> = Mutex::new(());
+
+/// Where a session's process runs in tabs mode
+#[derive(Debug, Clone)]
+pub struct Tabs {
+    /// Terminal to open when none shows the tmux session
+    terminal: Option<Vec<String>>,
+}
+
+impl Tabs {
+    pub fn new(terminal: Option<Vec<String>>) -> Self {
+        Self { terminal }
+    }
+
+    /// Run `cmd` (program, arguments, environment, and working directory) in
+    /// a new selected window named `name` with `umask` and `priority`, and
+    /// open a terminal on the tmux session if none is attached. Returns a
+    /// pidfd of the pane process.
+    pub fn open(
+        &self,
+        name: &str,
+        cmd: &Command,
+        umask: u32,
+        priority: &Priority,
+    ) -> io::Result<PidFd> {
+        let _open = OPEN.lock().unwrap_or_else(|e| e.into_inner());
+
+        let exists = tmux()
+            .args(["has-session", "-t", &format!("={SESSION}")])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?
+            .success();
+        let mut new = tmux();
+        if exists {
+            new.args(["new-window", "-t", &format!("={SESSION}:")]);
+        } else {
+            new.args(["new-session", "-d", "-s", SESSION]);
+        }
+        new.args(["-n", name, "-P", "-F", "#{pane_pid}"]);
+        if let Some(dir) = cmd.get_current_dir() {
+            new.arg("-c").arg(dir);
+        }
+        for (key, value) in cmd.get_envs() {
+            if let Some(value) = value {
+                let mut var = OsString::from(key);
+                var.push("=");
+                var.push(value);
+                new.arg("-e").arg(var);
+            }
+        }
+        // Panes get the tmux server's umask, priority, and environment, not
+        // the ones of this command
+        let argv = std::iter::once(cmd.get_program())
+            .chain(cmd.get_args())
+            .map(OsString::from);
+        new.arg("--").args(crate::umask::wrap(
+            umask,
+            crate::priority::wrap(priority, crate::environ::wrap(cmd, argv)),
+        ));
+
+        let output = run(&mut new)?;
+        let pid = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<libc::pid_t>()
+            .map_err(|_| io::Error::other("tmux did not report the pane process"))?;
+        debug!(name, pid, "Opened tmux window");
+        let pidfd = pidfd_open(pid)?;
+
+        if !self.attached() {
+            self.open_terminal();
+        }
+        Ok(pidfd)
+    }
+
+    /// Whether a terminal shows the tmux session
+    fn attached(&self) -> bool {
+        tmux()
+            .args(["list-clients", "-t", &format!("={SESSION}")])
+            .output()
+            .is_ok_and(|out| out.status.success() && !out.stdout.is_empty())
+    }
+
+    /// Open the terminal attached to the tmux session. It exits on its own
+    /// once the last session window has closed.
+    fn open_terminal(&self) {
+        let Some((program, args)) = self.terminal.as_deref().and_then(<[String]>::split_first)
+        else {
+            warn!("No terminal to show the tmux session; attach with `tmux -L {SOCKET} attach`");
+            return;
+        };
+        let spawned = Command::new(program)
+            .args(args)
+            .args(["tmux", "-L", SOCKET, "attach-session", "-t"])
+            .arg(format!("={SESSION}"))
+            .stdin(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(mut child) => {
+                info!(terminal = program, "Opened terminal for the tmux session");
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => warn!(
+                terminal = program,
+                "Failed to open terminal for the tmux session: {e}"
+            ),
+        }
+    }
+}
+
+/// Select the window of session `name` in the shared terminal.
+pub fn focus(name: &str) -> io::Result<()> {
+    run(tmux().args(["select-window", "-t", &format!("={SESSION}:={name}")])).map(|_| ())
+}
+
+/// Block until the pane process behind `pidfd` has exited.
+pub fn wait(pidfd: &PidFd) {
+    let mut fds = libc::pollfd {
+        fd: pidfd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: `fds` is a single valid pollfd for the duration of the call
+    while unsafe { libc::poll(&mut fds, 1, -1) } < 0
+        && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted
+    {}
+}
+
+fn tmux() -> Command {
+    let mut cmd = Command::new("tmux");
+    cmd.args(["-L", SOCKET]);
+    cmd
+}
+
+/// Run a tmux command, turning a failure into an error with its message
+fn run(cmd: &mut Command) -> io::Result<Output> {
+    let output = cmd.stdin(Stdio::null()).output()?;
+    if output.status.success() {
+        return Ok(output);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(io::Error::other(format!("tmux: {}", stderr.trim())))
+}
+
+fn pidfd_open(pid: libc::pid_t) -> io::Result<PidFd> {
+    // SAFETY: plain syscall; a non-negative return is a new fd we own
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fd` was just returned by pidfd_open and is not owned elsewhere
+    Ok(PidFd::from(unsafe { OwnedFd::from_raw_fd(fd as i32) }))
+}
@@ -0,0 +1,59 @@
+//! File mode creation masks.
+//!
+//! porttyd itself runs with umask 077, so the session directories, queued
+//! submissions, and other state it writes are never group or world readable,
+//! whatever umask it was started with. The commands it runs for a session
+//! (the terminal, `post_process`) save files in places the user picks and get
+//! the configured `umask` instead, or the one porttyd was started with.
+
+use std::ffi::OsString;
+use std::process::Command;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use libportty::config::Umask;
+
+/// Umask of porttyd's own files
+const PRIVATE: libc::mode_t = 0o077;
+
+/// Umask porttyd was started with, saved by [`restrict`]
+static INHERITED: AtomicU32 = AtomicU32::new(0o022);
+
+/// Switch porttyd to the private umask, remembering the inherited one for
+/// the commands it runs. Called once at startup, before any thread exists.
+pub fn restrict() {
+    // SAFETY: umask has no preconditions and cannot fail
+    let inherited = unsafe { libc::umask(PRIVATE) };
+    INHERITED.store(inherited as u32, Ordering::Relaxed);
+}
+
+/// Umask of the commands run for a session: `configured`, else the inherited
+/// one
+pub fn for_commands(configured: Option<Umask>) -> u32 {
+    configured.map_or_else(|| INHERITED.load(Ordering::Relaxed), |umask| umask.0)
+}
+
+/// Make `cmd` run with `mask`
+pub fn apply(cmd: &mut Command, mask: u32) {
+    use std::os::unix::process::CommandExt as _;
+
+    // SAFETY: umask is async-signal-safe, as required between fork and exec
+    unsafe {
+        cmd.pre_exec(move || {
+            libc::umask(mask as libc::mode_t);
+            Ok(())
+        });
+    }
+}
+
+/// `argv` wrapped to run with `mask`, for commands started by another process
+/// (a tmux server) that keeps its own umask
+pub fn wrap(mask: u32, argv: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut wrapped: Vec<OsString> = vec![
+        "/bin/sh".into(),
+        "-c".into(),
+        "umask \"$0\" && exec \"$@\"".into(),
+        format!("{mask:03o}").into(),
+    ];
+    wrapped.extend(argv);
+    wrapped
+}
@@ -0,0 +1,142 @@
+//! External validators (`validator`)
+//!
+//! After the built-in checks, a submission is handed to the configured
+//! command as JSON on stdin:
+//!
+//! ```json
+//! {"portal": "file-chooser", "operation": "open-file", "app_id": "org.example.App",
+//!  "options": {...}, "results": ["file:///home/me/a.pdf"]}
+//! ```
+//!
+//! A nonzero exit refuses the submission, with the first line of stderr as
+//! the reason. On success, non-empty stdout replaces the results, one per
+//! line; empty stdout accepts them as they are. The command gets the
+//! session's `umask` and `priority` and is killed after [`TIMEOUT`].
+
+use std::io::{Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+use libportty::config::{Priority, Umask};
+
+use crate::{priority, umask};
+
+/// How long a validator may take before the submission is refused
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run `argv` on `results`, returning the results to deliver or why they
+/// were refused
+pub fn run(
+    argv: &[String],
+    portal: &str,
+    operation: &str,
+    options: &serde_json::Value,
+    results: Vec<String>,
+    mask: Option<Umask>,
+    priority: &Priority,
+) -> Result<Vec<String>, String> {
+    let app_id = options
+        .get("app_id")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+    let input = serde_json::json!({
+        "portal": portal,
+        "operation": operation,
+        "app_id": app_id,
+        "options": options,
+        "results": results,
+    })
+    .to_string();
+
+    let exec = priority::scoped(priority, argv);
+    let mut cmd = Command::new(&exec[0]);
+    cmd.args(&exec[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    umask::apply(&mut cmd, umask::for_commands(mask));
+    priority::apply(&mut cmd, priority);
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("failed to run validator `{}`: {e}", argv[0]))?;
+
+    // Write and read on threads so a validator that answers before reading
+    // all of its input cannot deadlock against us
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || {
+        // A validator may exit without reading its input; that is its choice
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let stdout = read_to_string(child.stdout.take().expect("stdout is piped"));
+    let stderr = read_to_string(child.stderr.take().expect("stderr is piped"));
+
+    let status = wait(&mut child, argv);
+    let _ = writer.join();
+    let (stdout, stderr) = (join(stdout), join(stderr));
+    let status = status?;
+
+    if !status.success() {
+        let reason = stderr
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map_or_else(
+                || format!("refused by validator ({status})"),
+                |line| format!("refused by validator: {line}"),
+            );
+        warn!(portal, operation, app_id, %status, "{reason}");
+        return Err(reason);
+    }
+    let replaced: Vec<String> = stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect();
+    if replaced.is_empty() {
+        return Ok(results);
+    }
+    debug!(
+        portal,
+        operation,
+        ?replaced,
+        "Validator replaced the results"
+    );
+    Ok(replaced)
+}
+
+/// Wait for `child`, killing it after [`TIMEOUT`]
+fn wait(child: &mut Child, argv: &[String]) -> Result<std::process::ExitStatus, String> {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Ok(status),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                warn!(program = %argv[0], "Validator timed out");
+                return Err(format!(
+                    "validator `{}` did not finish within {}s",
+                    argv[0],
+                    TIMEOUT.as_secs()
+                ));
+            }
+            Err(e) => return Err(format!("failed to wait for validator `{}`: {e}", argv[0])),
+        }
+    }
+}
+
+fn read_to_string(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        String::from_utf8_lossy(&buf).into_owned()
+    })
+}
+
+fn join(reader: thread::JoinHandle<String>) -> String {
+    reader.join().unwrap_or_default()
+}
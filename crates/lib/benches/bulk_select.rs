@@ -0,0 +1,111 @@
+//! Selecting many entries: one `portty add` per entry against one call for
+//! all of them.
+//!
+//! Run with `cargo bench -p libportty --bench bulk_select`. Each `single`
+//! iteration does what one CLI invocation does (read the session's portal
+//! and options, then append; or read, merge, and rewrite the pending queue),
+//! so the gap to `batched` is the work `add --stdin`, `select --from-file`,
+//! and `portty batch` save, before process startup is even counted.
+
+use std::hint::black_box;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use libportty::portal::file_chooser::{SelectionMode, SessionOptions};
+use libportty::portal::intent::queue;
+use libportty::portal::{Intent, IntentItem, MergeOp, SessionContext};
+use libportty::session_dir::write_state;
+use tempfile::TempDir;
+
+const ENTRIES: [usize; 2] = [100, 1000];
+
+fn entries(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("/tmp/bulk/file-{i}.txt")).collect()
+}
+
+/// A multi-select file chooser session dir
+fn session() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    let options = serde_json::to_value(SessionOptions {
+        mode: SelectionMode::Pick {
+            multiple: true,
+            directory: false,
+        },
+        ..Default::default()
+    })
+    .unwrap();
+    write_state(dir.path(), "file-chooser", "open-file", &options, &[]).unwrap();
+    dir
+}
+
+fn session_adds(c: &mut Criterion) {
+    let mut group = c.benchmark_group("session");
+    group.sample_size(10);
+    for n in ENTRIES {
+        let entries = entries(n);
+        group.bench_with_input(BenchmarkId::new("single", n), &entries, |b, entries| {
+            b.iter_batched(
+                session,
+                |dir| {
+                    for entry in entries {
+                        let ctx = SessionContext::from_session_dir(dir.path()).unwrap();
+                        black_box(ctx.add_entries(std::slice::from_ref(entry)).unwrap());
+                    }
+                    dir
+                },
+                BatchSize::PerIteration,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("batched", n), &entries, |b, entries| {
+            b.iter_batched(
+                session,
+                |dir| {
+                    let ctx = SessionContext::from_session_dir(dir.path()).unwrap();
+                    black_box(ctx.add_entries(entries).unwrap());
+                    dir
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn queue_adds(c: &mut Criterion) {
+    let mut group = c.benchmark_group("queue");
+    group.sample_size(10);
+    for n in ENTRIES {
+        let items: Vec<IntentItem> = entries(n).into_iter().map(IntentItem::Path).collect();
+        group.bench_with_input(BenchmarkId::new("single", n), &items, |b, items| {
+            b.iter_batched(
+                || TempDir::new().unwrap(),
+                |pending| {
+                    for item in items {
+                        let mut intent = queue::read(pending.path()).unwrap_or_default();
+                        intent
+                            .apply(std::slice::from_ref(item), MergeOp::Add)
+                            .unwrap();
+                        queue::write(pending.path(), &intent).unwrap();
+                    }
+                    pending
+                },
+                BatchSize::PerIteration,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("batched", n), &items, |b, items| {
+            b.iter_batched(
+                || TempDir::new().unwrap(),
+                |pending| {
+                    let mut intent = Intent::default();
+                    intent.apply(items, MergeOp::Add).unwrap();
+                    queue::write(pending.path(), &intent).unwrap();
+                    pending
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, session_adds, queue_adds);
+criterion_main!(benches);
@@ -0,0 +1,201 @@
+//! Session spawn latency: the steps between a portal request and the terminal
+//! prompt.
+//!
+//! Run with `cargo bench -p libportty --bench session_spawn`. The `cold`
+//! variants are the naive path (create everything per session), the others
+//! use the daemon's pool and shim templates.
+
+use std::collections::HashMap;
+use std::fs;
+use std::hint::black_box;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use libportty::session_dir::{DirPool, SessionDirs, ShimTemplates, write_state};
+use serde_json::json;
+use tempfile::TempDir;
+
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A fresh session path under `base`
+fn session_path(base: &TempDir) -> std::path::PathBuf {
+    base.path()
+        .join(format!("s-{}", SEQ.fetch_add(1, Ordering::Relaxed)))
+}
+
+/// Shims of a file chooser session with default config
+fn default_bins() -> HashMap<String, String> {
+    [
+        ("submit", "portty submit"),
+        ("cancel", "portty cancel"),
+        ("info", "portty info"),
+        ("clear", "portty clear"),
+        ("reset", "portty reset"),
+        ("sel", "portty add path \"$@\""),
+        ("desel", "portty remove path \"$@\""),
+    ]
+    .into_iter()
+    .map(|(name, command)| (name.to_string(), command.to_string()))
+    .collect()
+}
+
+fn options() -> serde_json::Value {
+    json!({
+        "title": "Open File",
+        "mode": "open",
+        "multiple": true,
+        "directory": false,
+        "filters": [],
+        "current_folder": "/tmp",
+    })
+}
+
+/// Shims written file by file, as before templates
+fn write_shims(bin_dir: &Path, bins: &HashMap<String, String>) {
+    fs::create_dir_all(bin_dir).unwrap();
+    for (name, command) in bins {
+        let path = bin_dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{command}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+fn dir_creation(c: &mut Criterion) {
+    let base = TempDir::new().unwrap();
+    let mut group = c.benchmark_group("dir");
+
+    group.bench_function("cold", |b| {
+        b.iter(|| fs::create_dir_all(session_path(&base)).unwrap())
+    });
+
+    let pool = DirPool::new(base.path().join(".pool"), 4);
+    group.bench_function("pool", |b| {
+        // Refilled off the request path in the daemon, so not measured
+        b.iter_batched(
+            || pool.refill().unwrap(),
+            |()| pool.take(&session_path(&base)).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+fn shims(c: &mut Criterion) {
+    let base = TempDir::new().unwrap();
+    let bins = default_bins();
+    let mut group = c.benchmark_group("shims");
+
+    group.bench_function("cold", |b| {
+        b.iter(|| write_shims(&session_path(&base).join("bin"), &bins))
+    });
+
+    let templates = ShimTemplates::new(base.path().join(".shims"));
+    group.bench_function("template", |b| {
+        b.iter(|| templates.link(&session_path(&base), &bins).unwrap())
+    });
+
+    group.finish();
+}
+
+fn state_files(c: &mut Criterion) {
+    let base = TempDir::new().unwrap();
+    let options = options();
+    c.bench_function("state", |b| {
+        b.iter(|| {
+            let dir = session_path(&base);
+            fs::create_dir(&dir).unwrap();
+            write_state(&dir, "file-chooser", "open-file", &options, &[]).unwrap();
+        })
+    });
+}
+
+fn socket_bind(c: &mut Criterion) {
+    let base = TempDir::new().unwrap();
+    let path = base.path().join("daemon.sock");
+    c.bench_function("socket_bind", |b| {
+        b.iter(|| {
+            let _ = fs::remove_file(&path);
+            black_box(UnixListener::bind(&path).unwrap());
+        })
+    });
+}
+
+fn spawn(c: &mut Criterion) {
+    let base = TempDir::new().unwrap();
+    let bin_dir = base.path().join("bin");
+    write_shims(&bin_dir, &default_bins());
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    c.bench_function("spawn", |b| {
+        b.iter(|| {
+            Command::new("true")
+                .env("PORTTY_SESSION", "bench")
+                .env("PORTTY_DIR", base.path())
+                .env("PATH", &path)
+                .status()
+                .unwrap()
+        })
+    });
+}
+
+/// Everything before `exec`, end to end
+fn session(c: &mut Criterion) {
+    let base = TempDir::new().unwrap();
+    let bins = default_bins();
+    let options = options();
+    let mut group = c.benchmark_group("session");
+
+    group.bench_function("cold", |b| {
+        b.iter(|| {
+            let dir = session_path(&base);
+            fs::create_dir_all(&dir).unwrap();
+            write_state(&dir, "file-chooser", "open-file", &options, &[]).unwrap();
+            write_shims(&dir.join("bin"), &bins);
+        })
+    });
+
+    let dirs = SessionDirs {
+        pool: DirPool::new(base.path().join(".pool"), 4),
+        shims: ShimTemplates::new(base.path().join(".shims")),
+    };
+    group.bench_function("warm", |b| {
+        b.iter_batched(
+            || dirs.pool.refill().unwrap(),
+            |()| {
+                let dir = session_path(&base);
+                dirs.create(
+                    &dir,
+                    "file-chooser",
+                    "open-file",
+                    &options,
+                    &[],
+                    Some(&bins),
+                )
+                .unwrap();
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    dir_creation,
+    shims,
+    state_files,
+    socket_bind,
+    spawn,
+    session
+);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+//! How a binary was built, for `portty version` and porttyd's `status`
+//!
+//! Packagers can record the source revision by setting
+//! `PORTTY_BUILD_COMMIT` when building.
+
+/// `<profile>; portals: ...; features: ...[; commit <hash>]`, with the
+/// binary's own `features` after the library's
+pub fn describe(features: &[&str]) -> String {
+    let profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    let portals: Vec<&str> = [
+        (cfg!(feature = "portal-file-chooser"), "file-chooser"),
+        (cfg!(feature = "portal-screenshot"), "screenshot"),
+        (cfg!(feature = "portal-remote-desktop"), "remote-desktop"),
+        (
+            cfg!(feature = "portal-dynamic-launcher"),
+            "dynamic-launcher",
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, name)| enabled.then_some(name))
+    .collect();
+    let features: Vec<&str> = cfg!(feature = "collation")
+        .then_some("collation")
+        .into_iter()
+        .chain(features.iter().copied())
+        .collect();
+
+    let mut out = format!("{profile}; portals: {}", list(&portals));
+    out.push_str(&format!("; features: {}", list(&features)));
+    if let Some(commit) = option_env!("PORTTY_BUILD_COMMIT").filter(|c| !c.is_empty()) {
+        out.push_str(&format!("; commit {commit}"));
+    }
+    out
+}
+
+fn list(names: &[&str]) -> String {
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    }
+}
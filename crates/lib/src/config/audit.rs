@@ -0,0 +1,224 @@
+//! Audit rules for selected paths (`[<portal>.audit]`)
+//!
+//! A web page can ask for a file upload and hope the user picks a credential.
+//! Audit rules flag results that touch sensitive paths before they reach the
+//! app:
+//!
+//! ```toml
+//! [file-chooser.audit]
+//! deny = ["~/.ssh/id_*", "~/.gnupg"]
+//! confirm = ["~/.ssh", "~/.aws", "/etc"]
+//! log = ["~/.config"]
+//! allow = ["~/.ssh/*.pub"]
+//! ```
+//!
+//! Patterns are globs (`*` stays within one path component, `**` crosses
+//! them) with `~/` expanding to the home directory. A pattern matches a path
+//! and everything below it, and paths are checked both as selected and with
+//! symlinks resolved. `allow` exempts a path from the other lists; otherwise
+//! the strictest matching list wins.
+
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Deserializer};
+
+use super::expand_home;
+
+/// What happens to a result that matches an audit list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AuditAction {
+    /// Logged, then returned
+    Log,
+    /// Only returned after an explicit confirmation (`portty submit --confirm`)
+    Confirm,
+    /// Never returned
+    Deny,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Log => "log",
+            Self::Confirm => "confirm",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+/// A result path that matched an audit list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub path: PathBuf,
+    pub action: AuditAction,
+    /// The pattern that matched, as written in the config
+    pub pattern: String,
+}
+
+/// Patterns of one audit list
+#[derive(Clone, Default)]
+struct PatternList {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl std::fmt::Debug for PatternList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.patterns.fmt(f)
+    }
+}
+
+impl PatternList {
+    /// First pattern matching `path` or one of its ancestors
+    fn find(&self, path: &Path) -> Option<&str> {
+        path.ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .find_map(|p| self.set.matches(p).first().copied())
+            .map(|i| self.patterns[i].as_str())
+    }
+
+    fn new(patterns: Vec<String>) -> Result<Self, String> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &patterns {
+            let expanded = expand_home(pattern);
+            let glob = GlobBuilder::new(expanded.trim_end_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("invalid glob {pattern:?}: {}", e.kind()))?;
+            builder.add(glob);
+        }
+        let set = builder.build().map_err(|e| e.to_string())?;
+        Ok(Self { patterns, set })
+    }
+}
+
+impl<'de> Deserialize<'de> for PatternList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::new(Vec::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Audit lists of one config level
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditRules {
+    /// Exempt from the other lists
+    #[serde(default)]
+    allow: PatternList,
+    /// Log matching results
+    #[serde(default)]
+    log: PatternList,
+    /// Require `portty submit --confirm`
+    #[serde(default)]
+    confirm: PatternList,
+    /// Refuse matching results
+    #[serde(default)]
+    deny: PatternList,
+}
+
+impl AuditRules {
+    /// Rules from the glob lists of an `audit` table
+    pub fn new(
+        allow: &[&str],
+        log: &[&str],
+        confirm: &[&str],
+        deny: &[&str],
+    ) -> Result<Self, String> {
+        let list = |patterns: &[&str]| {
+            PatternList::new(patterns.iter().map(ToString::to_string).collect())
+        };
+        Ok(Self {
+            allow: list(allow)?,
+            log: list(log)?,
+            confirm: list(confirm)?,
+            deny: list(deny)?,
+        })
+    }
+
+    /// Check one result path. Returns the strictest matching list, unless
+    /// the path is allowed.
+    pub fn check(&self, path: &Path) -> Option<Finding> {
+        let canonical = path.canonicalize().ok().filter(|c| c != path);
+        let candidates: Vec<&Path> = std::iter::once(path).chain(canonical.as_deref()).collect();
+
+        if candidates.iter().any(|p| self.allow.find(p).is_some()) {
+            return None;
+        }
+
+        [
+            (AuditAction::Deny, &self.deny),
+            (AuditAction::Confirm, &self.confirm),
+            (AuditAction::Log, &self.log),
+        ]
+        .into_iter()
+        .find_map(|(action, list)| {
+            candidates.iter().find_map(|p| {
+                list.find(p).map(|pattern| Finding {
+                    path: path.to_path_buf(),
+                    action,
+                    pattern: pattern.to_string(),
+                })
+            })
+        })
+    }
+
+    /// Check all result paths
+    pub fn audit<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) -> Vec<Finding> {
+        paths.into_iter().filter_map(|p| self.check(p)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(toml: &str) -> AuditRules {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn strictest_list_wins_and_allow_exempts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().display();
+        let rules = rules(&format!(
+            r#"
+            deny = ["{root}/ssh/id_*"]
+            confirm = ["{root}/ssh", "{root}/etc/"]
+            log = ["{root}/**/*.conf"]
+            allow = ["{root}/ssh/*.pub"]
+            "#
+        ));
+        let action = |rel: &str| rules.check(&tmp.path().join(rel)).map(|f| f.action);
+
+        assert_eq!(action("ssh/id_ed25519"), Some(AuditAction::Deny));
+        assert_eq!(action("ssh/id_ed25519.pub"), None);
+        assert_eq!(action("ssh/known_hosts"), Some(AuditAction::Confirm));
+        assert_eq!(action("ssh"), Some(AuditAction::Confirm));
+        assert_eq!(action("etc/nested/passwd"), Some(AuditAction::Confirm));
+        assert_eq!(action("docs/app.conf"), Some(AuditAction::Log));
+        assert_eq!(action("docs/readme.txt"), None);
+        // `*` does not cross directories
+        assert_eq!(action("sshx/id_rsa"), None);
+    }
+
+    #[test]
+    fn symlinks_are_resolved() {
+        let tmp = tempfile::tempdir().unwrap();
+        let secret = tmp.path().join("secret");
+        std::fs::create_dir(&secret).unwrap();
+        std::fs::write(secret.join("key"), "").unwrap();
+        let link = tmp.path().join("innocent.txt");
+        std::os::unix::fs::symlink(secret.join("key"), &link).unwrap();
+
+        let rules = rules(&format!("deny = [\"{}\"]", secret.display()));
+        let finding = rules.check(&link).unwrap();
+        assert_eq!(finding.action, AuditAction::Deny);
+        assert_eq!(finding.path, link);
+    }
+
+    #[test]
+    fn invalid_glob_is_a_type_error() {
+        let err = toml::from_str::<AuditRules>("deny = [\"a[\"]").unwrap_err();
+        assert!(err.message().contains("invalid glob"), "{err}");
+    }
+}
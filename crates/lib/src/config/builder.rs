@@ -0,0 +1,503 @@
+//! Config built in code ([`Config::builder`])
+//!
+//! For programs embedding porttyd or its portals, which would otherwise
+//! have to write TOML and parse it back. Settings are typed, so a builder
+//! cannot produce the wrong-type errors a file can; the same levels apply:
+//!
+//! ```
+//! use libportty::config::{Attention, Config};
+//! use libportty::Operation;
+//!
+//! let config = Config::builder()
+//!     .root(|s| s.exec(["foot"]).attention(Attention::Bell))
+//!     .operation(Operation::SaveFile, |s| s.allow_empty(true))
+//!     .session_quota(64 << 20)
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(config.resolve_exec("file-chooser", "open-file"), Some(vec!["foot".into()]));
+//! ```
+
+use std::time::Duration;
+
+use super::{
+    Attention, AuditRules, BaseConfig, ByteSize, Config, Diagnostic, EnvFilter, ExecCommand,
+    Headless, IoNice, OnTerminalExit, Picker, RewriteRule, SystemdScope, Umask,
+};
+use crate::portal::file_chooser::Filter;
+use crate::remote::Remote;
+use crate::{Operation, Portal, QueueConflict, QueuePolicy};
+
+/// Settings of one level (root, portal, or operation), the keys a config
+/// file allows at every level
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    base: BaseConfig,
+}
+
+fn argv<S: Into<String>>(argv: impl IntoIterator<Item = S>) -> ExecCommand {
+    ExecCommand::Argv(argv.into_iter().map(Into::into).collect())
+}
+
+impl Settings {
+    /// Terminal command the session runs in (`exec`); an empty argv runs
+    /// headless
+    pub fn exec<S: Into<String>>(mut self, command: impl IntoIterator<Item = S>) -> Self {
+        self.base.exec = Some(argv(command));
+        self
+    }
+
+    /// Commands tried in turn when `exec` cannot be run (`exec_fallback`)
+    pub fn exec_fallback<S: Into<String>>(
+        mut self,
+        commands: impl IntoIterator<Item = Vec<S>>,
+    ) -> Self {
+        self.base.exec_fallback = Some(commands.into_iter().map(argv).collect());
+        self
+    }
+
+    /// A custom shim on the session's `PATH` (`bin`)
+    pub fn bin(mut self, name: impl Into<String>, command: impl Into<String>) -> Self {
+        self.base.bin.insert(name.into(), command.into());
+        self
+    }
+
+    pub fn on_terminal_exit(mut self, action: OnTerminalExit) -> Self {
+        self.base.on_terminal_exit = Some(action);
+        self
+    }
+
+    /// Whole seconds; the rest is dropped, as a config file can't give it
+    pub fn exit_confirm_timeout(mut self, timeout: Duration) -> Self {
+        self.base.exit_confirm_timeout = Some(timeout.as_secs());
+        self
+    }
+
+    pub fn allow_empty(mut self, allow: bool) -> Self {
+        self.base.allow_empty = Some(allow);
+        self
+    }
+
+    pub fn queue_policy(mut self, policy: QueuePolicy) -> Self {
+        self.base.queue_policy = Some(policy);
+        self
+    }
+
+    /// `queue_conflict`
+    pub fn queue_conflict(mut self, choice: QueueConflict) -> Self {
+        self.base.queue_conflict = Some(choice);
+        self
+    }
+
+    /// Command run on the result file before its URI is returned; `{}` is
+    /// the file path (`post_process`)
+    pub fn post_process<S: Into<String>>(mut self, command: impl IntoIterator<Item = S>) -> Self {
+        self.base.post_process = Some(argv(command));
+        self
+    }
+
+    /// Folder keeping the captures of a screenshot series after the first
+    /// (`series_dir`)
+    pub fn series_dir(mut self, folder: impl Into<String>) -> Self {
+        self.base.series_dir = Some(folder.into());
+        self
+    }
+
+    /// Command receiving each submission as JSON after the built-in
+    /// validation, which can refuse or replace it (`validator`)
+    pub fn validator<S: Into<String>>(mut self, command: impl IntoIterator<Item = S>) -> Self {
+        self.base.validator = Some(argv(command));
+        self
+    }
+
+    pub fn attention(mut self, attention: Attention) -> Self {
+        self.base.attention = Some(attention);
+        self
+    }
+
+    /// Whole seconds, as for [`exit_confirm_timeout`](Self::exit_confirm_timeout)
+    pub fn attention_delay(mut self, delay: Duration) -> Self {
+        self.base.attention_delay = Some(delay.as_secs());
+        self
+    }
+
+    /// Whole minutes a dialog may stay open before it is reported as
+    /// forgotten (`stale_after`)
+    pub fn stale_after(mut self, after: Duration) -> Self {
+        self.base.stale_after = Some(after.as_secs() / 60);
+        self
+    }
+
+    /// Command run for a forgotten dialog (`on_stale`)
+    pub fn on_stale<S: Into<String>>(mut self, command: impl IntoIterator<Item = S>) -> Self {
+        self.base.on_stale = Some(argv(command));
+        self
+    }
+
+    pub fn headless(mut self, headless: Headless) -> Self {
+        self.base.headless = Some(headless);
+        self
+    }
+
+    /// Whole seconds a session without a terminal waits to be used
+    /// (`headless_timeout`)
+    pub fn headless_timeout(mut self, timeout: Duration) -> Self {
+        self.base.headless_timeout = Some(timeout.as_secs());
+        self
+    }
+
+    pub fn picker(mut self, picker: Picker) -> Self {
+        self.base.picker = Some(picker);
+        self
+    }
+
+    /// Add a rule after the ones of this level (`rewrite`)
+    pub fn rewrite(mut self, rule: RewriteRule) -> Self {
+        self.base.rewrite.push(rule);
+        self
+    }
+
+    pub fn export_documents(mut self, export: bool) -> Self {
+        self.base.export_documents = Some(export);
+        self
+    }
+
+    pub fn record_recent(mut self, record: bool) -> Self {
+        self.base.record_recent = Some(record);
+        self
+    }
+
+    pub fn standby(mut self, standby: bool) -> Self {
+        self.base.standby = Some(standby);
+        self
+    }
+
+    pub fn audit(mut self, rules: AuditRules) -> Self {
+        self.base.audit = Some(rules);
+        self
+    }
+
+    /// Command run in inline and tabs mode instead of a terminal
+    /// (`inline_exec`)
+    pub fn inline_exec<S: Into<String>>(mut self, command: impl IntoIterator<Item = S>) -> Self {
+        self.base.inline_exec = Some(argv(command));
+        self
+    }
+
+    /// Entries pre-selected when the request brings none; none disables
+    /// the defaults of the levels above (`defaults`)
+    pub fn defaults<S: Into<String>>(mut self, entries: impl IntoIterator<Item = S>) -> Self {
+        self.base.defaults = Some(entries.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// File chooser filters offered when the request brings none; none
+    /// disables the filters of the levels above (`default_filters`)
+    pub fn default_filters(mut self, filters: impl IntoIterator<Item = Filter>) -> Self {
+        self.base.default_filters = Some(filters.into_iter().collect());
+        self
+    }
+
+    pub fn remote(mut self, remote: Remote) -> Self {
+        self.base.remote = Some(remote);
+        self
+    }
+
+    /// Folder file chooser results must lie in (`restrict_to`)
+    pub fn restrict_to(mut self, folder: impl Into<String>) -> Self {
+        self.base.restrict_to = Some(folder.into());
+        self
+    }
+
+    /// Umask of the commands a session runs; [`ConfigBuilder::build`]
+    /// refuses one above `0o777`
+    pub fn umask(mut self, mask: u32) -> Self {
+        self.base.umask = Some(Umask(mask));
+        self
+    }
+
+    /// Niceness added to the commands a session runs; [`ConfigBuilder::build`]
+    /// refuses one outside 0 to 19
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.base.nice = Some(nice);
+        self
+    }
+
+    pub fn ionice(mut self, ionice: IoNice) -> Self {
+        self.base.ionice = Some(ionice);
+        self
+    }
+
+    pub fn systemd_scope(mut self, scope: SystemdScope) -> Self {
+        self.base.systemd_scope = Some(scope);
+        self
+    }
+
+    /// Which of porttyd's environment variables the commands a session runs
+    /// keep (`inherit_env`)
+    pub fn inherit_env(mut self, filter: EnvFilter) -> Self {
+        self.base.inherit_env = Some(filter);
+        self
+    }
+}
+
+/// Builds a [`Config`] level by level, starting from the settings of an
+/// empty config file. Unlike [`Config::detected`], that has no `exec`, so
+/// sessions run headless until one is set.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+impl ConfigBuilder {
+    /// Root-level settings, the defaults of every portal
+    pub fn root(mut self, settings: impl FnOnce(Settings) -> Settings) -> Self {
+        let base = std::mem::take(&mut self.config.base);
+        self.config.base = settings(Settings { base }).base;
+        self
+    }
+
+    /// Settings of a portal table (`[file-chooser]`)
+    pub fn portal(mut self, portal: Portal, settings: impl FnOnce(Settings) -> Settings) -> Self {
+        let table = self
+            .config
+            .portals
+            .entry(portal.as_str().to_string())
+            .or_default();
+        let base = std::mem::take(&mut table.base);
+        table.base = settings(Settings { base }).base;
+        self
+    }
+
+    /// Settings of an operation table (`[file-chooser.save-file]`)
+    pub fn operation(
+        mut self,
+        operation: Operation,
+        settings: impl FnOnce(Settings) -> Settings,
+    ) -> Self {
+        let table = self
+            .config
+            .portals
+            .entry(operation.portal().as_str().to_string())
+            .or_default()
+            .operations
+            .entry(operation.as_str().to_string())
+            .or_default();
+        let base = std::mem::take(&mut table.base);
+        table.base = settings(Settings { base }).base;
+        self
+    }
+
+    /// `cli`
+    pub fn cli(mut self, path: impl Into<String>) -> Self {
+        self.config.root.cli = Some(path.into());
+        self
+    }
+
+    pub fn max_message_size(mut self, bytes: usize) -> Self {
+        self.config.root.max_message_size = Some(bytes);
+        self
+    }
+
+    pub fn max_entry_length(mut self, bytes: usize) -> Self {
+        self.config.root.max_entry_length = Some(bytes);
+        self
+    }
+
+    pub fn session_quota(mut self, bytes: u64) -> Self {
+        self.config.root.session_quota = Some(ByteSize(bytes));
+        self
+    }
+
+    pub fn total_quota(mut self, bytes: u64) -> Self {
+        self.config.root.total_quota = Some(ByteSize(bytes));
+        self
+    }
+
+    pub fn payload_threshold(mut self, bytes: u64) -> Self {
+        self.config.root.payload_threshold = Some(ByteSize(bytes));
+        self
+    }
+
+    /// `debug.keep_session`
+    pub fn keep_session(mut self, keep: bool) -> Self {
+        self.config.root.debug.keep_session = keep;
+        self
+    }
+
+    /// `tabs.enabled`
+    pub fn tabs(mut self, enabled: bool) -> Self {
+        self.config.root.tabs.enabled = enabled;
+        self
+    }
+
+    /// `tabs.terminal`
+    pub fn tabs_terminal<S: Into<String>>(mut self, command: impl IntoIterator<Item = S>) -> Self {
+        self.config.root.tabs.terminal = Some(argv(command));
+        self
+    }
+
+    /// The config, or an error for a value a config file could not hold
+    /// either (a umask above `0o777`, a negative nice, a weight of 0)
+    pub fn build(self) -> Result<Config, Diagnostic> {
+        let levels = std::iter::once((String::new(), &self.config.base)).chain(
+            self.config.portals.iter().flat_map(|(portal, table)| {
+                std::iter::once((portal.clone(), &table.base)).chain(
+                    table
+                        .operations
+                        .iter()
+                        .map(move |(op, table)| (format!("{portal}.{op}"), &table.base)),
+                )
+            }),
+        );
+        for (path, base) in levels {
+            let location = |key: &str| {
+                if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{path}.{key}")
+                }
+            };
+            if let Some(Umask(mask)) = base.umask
+                && mask > 0o777
+            {
+                return Err(Diagnostic::error(
+                    location("umask"),
+                    format!("invalid umask {mask:#o} (at most 0o777)"),
+                ));
+            }
+            if let Some(nice) = base.nice
+                && !(0..=19).contains(&nice)
+            {
+                return Err(Diagnostic::error(
+                    location("nice"),
+                    format!("invalid nice {nice} (expected 0 to 19)"),
+                ));
+            }
+            if let Some(IoNice::BestEffort(level @ 8..)) = base.ionice {
+                return Err(Diagnostic::error(
+                    location("ionice"),
+                    format!("invalid ionice level {level} (expected 0 to 7)"),
+                ));
+            }
+            let invalid_weight = base
+                .systemd_scope
+                .iter()
+                .flat_map(|scope| [scope.cpu_weight, scope.io_weight])
+                .flatten()
+                .find(|weight| !(1..=10000).contains(weight));
+            if let Some(weight) = invalid_weight {
+                return Err(Diagnostic::error(
+                    location("systemd_scope"),
+                    format!("invalid weight {weight} (expected 1 to 10000)"),
+                ));
+            }
+        }
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_resolve_like_a_file() {
+        let config = Config::builder()
+            .root(|s| s.exec(["foot"]).bin("ll", "ls -l"))
+            .portal(Portal::FileChooser, |s| s.exec(["kitty", "-T", "pick"]))
+            .operation(Operation::SaveFile, |s| {
+                s.exec::<&str>([]).allow_empty(true)
+            })
+            .total_quota(1 << 30)
+            .tabs(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            config.resolve_exec("screenshot", "screenshot"),
+            Some(vec!["foot".to_string()])
+        );
+        assert_eq!(
+            config.resolve_exec("file-chooser", "open-file"),
+            Some(vec!["kitty".to_string(), "-T".into(), "pick".into()])
+        );
+        assert_eq!(config.resolve_exec("file-chooser", "save-file"), None);
+        assert!(config.resolve_allow_empty("file-chooser", "save-file"));
+        assert!(!config.resolve_allow_empty("file-chooser", "open-file"));
+        assert_eq!(
+            config.resolve_bin("file-chooser", "open-file")["ll"],
+            "ls -l"
+        );
+        assert_eq!(config.quota().total, Some(1 << 30));
+        assert!(config.tabs());
+    }
+
+    #[test]
+    fn priority_keys_resolve_separately() {
+        let scope = SystemdScope {
+            cpu_weight: Some(20),
+            ..Default::default()
+        };
+        let config = Config::builder()
+            .root(|s| s.nice(5).systemd_scope(scope.clone()))
+            .portal(Portal::Screenshot, |s| s.ionice(IoNice::Idle))
+            .operation(Operation::Screenshot, |s| s.nice(15))
+            .build()
+            .unwrap();
+
+        let priority = config.resolve_priority("screenshot", "screenshot");
+        assert_eq!(priority.nice, Some(15));
+        assert_eq!(priority.ionice, Some(IoNice::Idle));
+        assert_eq!(priority.scope, Some(scope));
+        assert_eq!(
+            config.resolve_priority("file-chooser", "open-file").ionice,
+            None
+        );
+        assert_eq!(
+            config
+                .resolve_priority("file-chooser", "open-file")
+                .scope
+                .unwrap()
+                .properties(),
+            ["CPUWeight=20"]
+        );
+    }
+
+    #[test]
+    fn menu_picker_replaces_inline_exec_for_file_chooser() {
+        let config = Config::builder()
+            .root(|s| s.inline_exec(["yazi"]).picker(Picker::Menu))
+            .operation(Operation::SaveFile, |s| s.picker(Picker::Shell))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            config.resolve_inline_exec("file-chooser", "open-file"),
+            ["portty", "pick"]
+        );
+        assert_eq!(
+            config.resolve_inline_exec("file-chooser", "save-file"),
+            ["yazi"]
+        );
+        assert_eq!(
+            config.resolve_inline_exec("screenshot", "screenshot"),
+            ["yazi"]
+        );
+    }
+
+    #[test]
+    fn out_of_range_umask() {
+        let error = Config::builder()
+            .operation(Operation::OpenFile, |s| s.umask(0o1022))
+            .build()
+            .unwrap_err();
+        assert!(error.is_error());
+        assert_eq!(error.location, "file-chooser.open-file.umask");
+    }
+}
@@ -0,0 +1,692 @@
+//! Config validation with key-path diagnostics
+//!
+//! Deserializing the whole file at once stops at the first error, and
+//! `#[serde(flatten)]` turns any misspelled table into a portal or operation.
+//! Instead the raw TOML table is walked level by level: keys are classified
+//! as fields, portals, or operations, and each level's fields are
+//! deserialized on their own through `serde_path_to_error` so errors carry
+//! their full key path (e.g. `file-chooser.open-file.exit_confirm_timeout`).
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use serde::de::{self, DeserializeOwned, Visitor};
+use toml::{Table, Value};
+
+use super::{
+    BaseConfig, CLI_BIN, Config, ExecCommand, RootConfig, expand_home, find_cli, find_program,
+};
+use crate::Portal;
+use crate::socket::SocketAddress;
+
+/// Below this, ordinary control messages (e.g. `add` with a few long paths)
+/// start getting rejected
+const MIN_MESSAGE_SIZE: usize = 1024;
+
+/// Below this, ordinary paths start getting rejected
+const MIN_ENTRY_LENGTH: usize = 256;
+
+/// How serious a [`Diagnostic`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Config still loads; the setting is ignored or probably wrong
+    Warning,
+    /// Config cannot be loaded
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A single finding about a config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Dotted key path (`screenshot.exec`) or source position (`line 3, column 7`);
+    /// empty when the finding is about the file as a whole
+    pub location: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub(super) fn error(location: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            location: location.into(),
+            message: message.into(),
+        }
+    }
+
+    fn warning(location: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            location: location.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.location.is_empty() {
+            write!(f, "{}: {}", self.severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", self.severity, self.location, self.message)
+        }
+    }
+}
+
+/// Validate config source without loading it
+pub fn check(content: &str) -> Vec<Diagnostic> {
+    check_with(content, &[])
+}
+
+/// Validate config source, taking the tables named in `portals` (plugin
+/// portals registered with porttyd) for portals as well
+pub fn check_with(content: &str, portals: &[&str]) -> Vec<Diagnostic> {
+    validate(content, portals).1
+}
+
+/// Parse `content` into a table and validate it.
+///
+/// Unknown non-table keys are reported and removed from the table, so the
+/// flattened portal/operation maps don't reject the whole file over them.
+/// The table is `None` if the source is not valid TOML. Tables named in
+/// `portals` are plugin portals, known besides the built-in ones.
+pub(super) fn validate(content: &str, portals: &[&str]) -> (Option<Table>, Vec<Diagnostic>) {
+    let mut table = match content.parse::<Table>() {
+        Ok(table) => table,
+        Err(e) => return (None, vec![syntax_error(content, &e)]),
+    };
+
+    let mut out = Vec::new();
+    let base_fields = field_names::<BaseConfig>();
+    let root_fields = field_names::<RootConfig>();
+    let shims = shim_names(&table);
+
+    if let Some(base) = check_fields::<BaseConfig>(&table, "", &mut out) {
+        check_base(&base, "", &shims, &mut out);
+    }
+    if let Some(root) = check_fields::<RootConfig>(&table, "", &mut out) {
+        check_root(&root, &mut out);
+    }
+
+    let mut unknown = Vec::new();
+    for (name, value) in table.iter_mut() {
+        if base_fields.contains(&name.as_str()) || root_fields.contains(&name.as_str()) {
+            continue;
+        }
+        let Value::Table(portal) = value else {
+            out.push(Diagnostic::warning(name.as_str(), "unknown key"));
+            unknown.push(name.clone());
+            continue;
+        };
+        let plugin = portals.contains(&name.as_str());
+        check_portal(name, portal, plugin, &shims, &mut out);
+    }
+    for name in unknown {
+        table.remove(&name);
+    }
+
+    (Some(table), out)
+}
+
+/// `plugin`: the table is a plugin portal's, known to porttyd though not
+/// built in (its operations are not checked)
+fn check_portal(
+    name: &str,
+    portal: &mut Table,
+    plugin: bool,
+    shims: &HashSet<String>,
+    out: &mut Vec<Diagnostic>,
+) {
+    let operations = name.parse::<Portal>().map(Portal::operations);
+    if operations.is_err() && !plugin {
+        let known: Vec<_> = Portal::ALL.iter().map(|portal| portal.as_str()).collect();
+        out.push(Diagnostic::warning(
+            name,
+            format!("unknown portal (expected one of: {})", known.join(", ")),
+        ));
+    }
+
+    if let Some(base) = check_fields::<BaseConfig>(portal, name, out) {
+        check_base(&base, name, shims, out);
+    }
+
+    let base_fields = field_names::<BaseConfig>();
+    let mut unknown = Vec::new();
+    for (op_name, value) in portal.iter() {
+        if base_fields.contains(&op_name.as_str()) {
+            continue;
+        }
+        let path = join(name, op_name);
+        let Value::Table(operation) = value else {
+            out.push(Diagnostic::warning(path, "unknown key"));
+            unknown.push(op_name.clone());
+            continue;
+        };
+        if let Ok(operations) = operations
+            && !operations.iter().any(|op| op.as_str() == op_name)
+        {
+            let known: Vec<_> = operations.iter().map(|op| op.as_str()).collect();
+            out.push(Diagnostic::warning(
+                path.as_str(),
+                format!("unknown operation (expected one of: {})", known.join(", ")),
+            ));
+        }
+        check_operation(&path, operation, shims, out);
+    }
+    for op_name in unknown {
+        portal.remove(&op_name);
+    }
+}
+
+fn check_operation(
+    path: &str,
+    operation: &Table,
+    shims: &HashSet<String>,
+    out: &mut Vec<Diagnostic>,
+) {
+    if let Some(base) = check_fields::<BaseConfig>(operation, path, out) {
+        check_base(&base, path, shims, out);
+    }
+    let base_fields = field_names::<BaseConfig>();
+    for key in operation.keys() {
+        if !base_fields.contains(&key.as_str()) {
+            out.push(Diagnostic::warning(join(path, key), "unknown key"));
+        }
+    }
+}
+
+/// Suspicious values in settings shared by every level
+fn check_base(base: &BaseConfig, path: &str, shims: &HashSet<String>, out: &mut Vec<Diagnostic>) {
+    // `exec` runs with the session's bin/ first in $PATH, so shims count, and
+    // `portty` runs as the CLI porttyd found
+    let session_argv = |exec: &Option<ExecCommand>| {
+        exec.as_ref()
+            .and_then(ExecCommand::as_argv)
+            .filter(|argv| !shims.contains(&argv[0]))
+            .filter(|argv| argv[0] != CLI_BIN || find_cli().is_none())
+    };
+    let exec = session_argv(&base.exec);
+    let inline_exec = session_argv(&base.inline_exec);
+    let post_process = base.post_process.as_ref().and_then(ExecCommand::as_argv);
+    let validator = base.validator.as_ref().and_then(ExecCommand::as_argv);
+    let on_stale = base.on_stale.as_ref().and_then(ExecCommand::as_argv);
+
+    for (field, argv) in [
+        ("exec", exec),
+        ("inline_exec", inline_exec),
+        ("post_process", post_process),
+        ("validator", validator),
+        ("on_stale", on_stale),
+    ] {
+        if let Some(argv) = argv
+            && find_program(&argv[0]).is_none()
+        {
+            out.push(Diagnostic::warning(
+                join(path, field),
+                format!("`{}` not found in PATH", argv[0]),
+            ));
+        }
+    }
+    for argv in base
+        .exec_fallback
+        .iter()
+        .flatten()
+        .filter_map(ExecCommand::as_argv)
+    {
+        if !shims.contains(&argv[0]) && find_program(&argv[0]).is_none() {
+            out.push(Diagnostic::warning(
+                join(path, "exec_fallback"),
+                format!("`{}` not found in PATH", argv[0]),
+            ));
+        }
+    }
+    if let Some(remote) = base.remote
+        && find_program(remote.as_str()).is_none()
+    {
+        out.push(Diagnostic::warning(
+            join(path, "remote"),
+            format!("`{}` not found in PATH", remote.as_str()),
+        ));
+    }
+    if let Some(folder) = &base.series_dir
+        && let Ok(meta) = std::fs::metadata(expand_home(folder))
+        && !meta.is_dir()
+    {
+        out.push(Diagnostic::warning(
+            join(path, "series_dir"),
+            format!("{folder} is not a directory"),
+        ));
+    }
+    if let Some(folder) = &base.restrict_to
+        && !Path::new(&expand_home(folder)).is_dir()
+    {
+        out.push(Diagnostic::warning(
+            join(path, "restrict_to"),
+            format!("{folder} is not a directory; file chooser requests will be refused"),
+        ));
+    }
+    if base.systemd_scope.is_some() && find_program("systemd-run").is_none() {
+        out.push(Diagnostic::warning(
+            join(path, "systemd_scope"),
+            "`systemd-run` not found in PATH",
+        ));
+    }
+}
+
+/// Suspicious values in root-only settings
+fn check_root(root: &RootConfig, out: &mut Vec<Diagnostic>) {
+    if let Some(dir) = root.base_dir.as_deref().filter(|dir| !dir.is_empty())
+        && !Path::new(&expand_home(dir)).is_absolute()
+    {
+        out.push(Diagnostic::warning(
+            "base_dir",
+            format!("{dir} is not an absolute path and is ignored"),
+        ));
+    }
+    for address in &root.listen {
+        if let SocketAddress::Path(path) = address
+            && !path.parent().is_some_and(Path::is_dir)
+        {
+            out.push(Diagnostic::warning(
+                "listen",
+                format!("the folder of {address} does not exist; porttyd will not listen there"),
+            ));
+        }
+    }
+    if let Some(cli) = root.cli.as_deref().filter(|cli| !cli.is_empty())
+        && find_program(&expand_home(cli)).is_none()
+    {
+        out.push(Diagnostic::warning(
+            "cli",
+            format!("`{cli}` is not an executable file"),
+        ));
+    }
+    if let Some(size) = root.max_message_size
+        && size < MIN_MESSAGE_SIZE
+    {
+        out.push(Diagnostic::warning(
+            "max_message_size",
+            format!("{size} bytes is too small for typical requests (minimum {MIN_MESSAGE_SIZE})"),
+        ));
+    }
+    if let Some(len) = root.max_entry_length
+        && len < MIN_ENTRY_LENGTH
+    {
+        out.push(Diagnostic::warning(
+            "max_entry_length",
+            format!("{len} bytes is too short for typical paths (minimum {MIN_ENTRY_LENGTH})"),
+        ));
+    }
+    if root.tabs.enabled && find_program("tmux").is_none() {
+        out.push(Diagnostic::warning(
+            "tabs.enabled",
+            "`tmux` not found in PATH",
+        ));
+    }
+    if let Some(argv) = root.tabs.terminal.as_ref().and_then(ExecCommand::as_argv)
+        && find_program(&argv[0]).is_none()
+    {
+        out.push(Diagnostic::warning(
+            "tabs.terminal",
+            format!("`{}` not found in PATH", argv[0]),
+        ));
+    }
+    if let (Some(session), Some(total)) = (root.session_quota, root.total_quota)
+        && session.0 > total.0
+    {
+        out.push(Diagnostic::warning(
+            "session_quota",
+            format!(
+                "larger than total_quota ({} > {} bytes)",
+                session.0, total.0
+            ),
+        ));
+    }
+}
+
+/// Names of the built-in shims plus every `bin` entry in the file
+fn shim_names(root: &Table) -> HashSet<String> {
+    let bin_keys = |table: &Table| {
+        table
+            .get("bin")
+            .and_then(Value::as_table)
+            .map(|bin| bin.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+
+    let mut names: HashSet<String> = Portal::ALL
+        .iter()
+        .flat_map(|portal| portal.operations())
+        .flat_map(|op| Config::default_bin(op.portal().as_str(), op.as_str()).into_keys())
+        .collect();
+    names.extend(bin_keys(root));
+    for portal in root.values().filter_map(Value::as_table) {
+        names.extend(bin_keys(portal));
+        for operation in portal.values().filter_map(Value::as_table) {
+            names.extend(bin_keys(operation));
+        }
+    }
+    names
+}
+
+/// Deserialize the fields of `T` found in `table`, reporting the first type
+/// error with its full key path
+fn check_fields<T: DeserializeOwned>(
+    table: &Table,
+    path: &str,
+    out: &mut Vec<Diagnostic>,
+) -> Option<T> {
+    let fields = field_names::<T>();
+    let own: Table = table
+        .iter()
+        .filter(|(key, _)| fields.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+
+    serde_path_to_error::deserialize(Value::Table(own))
+        .map_err(|e| {
+            let location = join(path, &e.path().to_string());
+            out.push(Diagnostic::error(location, e.inner().message()));
+        })
+        .ok()
+}
+
+fn syntax_error(content: &str, error: &toml::de::Error) -> Diagnostic {
+    let location = error
+        .span()
+        .map(|span| {
+            let before = &content[..span.start.min(content.len())];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+            format!("line {line}, column {column}")
+        })
+        .unwrap_or_default();
+    Diagnostic::error(location, error.message().trim())
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+/// Field names a derived `Deserialize` struct declares.
+///
+/// Keeps the checker in sync with the structs: every field added to
+/// [`BaseConfig`] or [`RootConfig`] is a known key without further changes.
+pub(super) fn field_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldProbe(&mut fields));
+    fields
+}
+
+/// Deserializer that records the field list passed to `deserialize_struct`
+struct FieldProbe<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> de::Deserializer<'de> for FieldProbe<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("field probe"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(content: &str) -> Vec<String> {
+        check(content).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn field_names_come_from_structs() {
+        let base = field_names::<BaseConfig>();
+        assert!(base.contains(&"exec"));
+        assert!(base.contains(&"queue_policy"));
+        assert!(field_names::<RootConfig>().contains(&"max_message_size"));
+    }
+
+    #[test]
+    fn valid_config_has_no_findings() {
+        let content = r#"
+            exec = ""
+            listen = ["@portty", "/tmp/portty.sock"]
+            on_terminal_exit = "ask"
+            session_quota = "64M"
+            umask = 0o022
+            headless_timeout = 60
+
+            [file-chooser]
+            queue_policy = "merge"
+            queue_conflict = "session"
+            umask = "077"
+            nice = 10
+            ionice = "idle"
+            default_filters = ["Images=*.png,*.jpg;image/webp"]
+            inherit_env = { allow = ["HOME", "XDG_*"], deny = ["XDG_CONFIG_DIRS"] }
+
+            [file-chooser.save-file]
+            allow_empty = true
+            bin = { sel = "portty add path" }
+        "#;
+        assert_eq!(messages(content), Vec::<String>::new());
+    }
+
+    #[test]
+    fn type_errors_carry_key_path() {
+        let diags = check("[screenshot.pick-color]\nexit_confirm_timeout = \"soon\"\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert_eq!(
+            diags[0].location,
+            "screenshot.pick-color.exit_confirm_timeout"
+        );
+
+        let diags = check("[file-chooser]\nbin = { sel = 1 }\n");
+        assert_eq!(diags[0].location, "file-chooser.bin.sel");
+
+        let diags = check("[debug]\nkeep_sesion = true\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert_eq!(diags[0].location, "debug.keep_sesion");
+
+        let diags = check("[file-chooser]\numask = \"0o999\"\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert_eq!(diags[0].location, "file-chooser.umask");
+
+        let diags = check("[file-chooser]\ndefault_filters = [\"Images\"]\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert_eq!(diags[0].location, "file-chooser.default_filters");
+
+        let diags = check("listen = [\"portty.sock\"]\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert_eq!(diags[0].location, "listen");
+
+        let diags = check("[inherit_env]\ndeny = [\"A=B\"]\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert_eq!(diags[0].location, "inherit_env.deny");
+
+        let diags = check("[remote-desktop]\nheadless_timeout = \"1m\"\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert_eq!(diags[0].location, "remote-desktop.headless_timeout");
+
+        let diags = check("[screenshot]\nnice = -5\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert_eq!(diags[0].location, "screenshot.nice");
+
+        let diags = check("ionice = 9\n");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].location, "ionice");
+
+        let diags = check("[systemd_scope]\ncpu_weight = 0\n");
+        assert!(diags[0].is_error());
+        assert_eq!(diags[0].location, "systemd_scope.cpu_weight");
+
+        let diags = check("[tabs]\nenable = true\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert_eq!(diags[0].location, "tabs.enable");
+    }
+
+    #[test]
+    fn unknown_keys_are_warnings() {
+        let content = r#"
+            exce = "foot"
+            [file-choser]
+            [file-chooser]
+            verbose = true
+            [file-chooser.open-files]
+            [file-chooser.open-file]
+            typo = 1
+        "#;
+        assert_eq!(
+            messages(content),
+            // Keys are visited in sorted order
+            vec![
+                "warning: exce: unknown key",
+                "warning: file-chooser.open-file.typo: unknown key",
+                "warning: file-chooser.open-files: unknown operation (expected one of: open-file, save-file, save-files)",
+                "warning: file-chooser.verbose: unknown key",
+                "warning: file-choser: unknown portal (expected one of: file-chooser, screenshot, remote-desktop, dynamic-launcher)",
+            ]
+        );
+    }
+
+    #[test]
+    fn plugin_portals_are_known() {
+        let content = r#"
+            [my-portal]
+            verbose = true
+            [my-portal.pick]
+            typo = 1
+        "#;
+        let typos = [
+            "warning: my-portal.pick.typo: unknown key",
+            "warning: my-portal.verbose: unknown key",
+        ];
+        let plugin: Vec<String> = check_with(content, &["my-portal"])
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(plugin, typos);
+        assert_eq!(
+            messages(content),
+            vec![
+                "warning: my-portal: unknown portal (expected one of: file-chooser, screenshot, remote-desktop, dynamic-launcher)",
+                typos[0],
+                typos[1],
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors_report_position() {
+        let diags = check("exec = \"foot\"\nbin = {\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].is_error());
+        assert!(diags[0].location.starts_with("line 2"), "{:?}", diags[0]);
+    }
+
+    #[test]
+    fn suspicious_values() {
+        let content = r#"
+            exec = ["/nonexistent/portty-terminal", "-e"]
+            listen = ["/nonexistent/portty.sock"]
+            max_message_size = 16
+            session_quota = "2G"
+            total_quota = "1G"
+        "#;
+        assert_eq!(
+            messages(content),
+            vec![
+                "warning: exec: `/nonexistent/portty-terminal` not found in PATH",
+                "warning: listen: the folder of /nonexistent/portty.sock does not exist; porttyd will not listen there",
+                "warning: max_message_size: 16 bytes is too small for typical requests (minimum 1024)",
+                "warning: session_quota: larger than total_quota (2147483648 > 1073741824 bytes)",
+            ]
+        );
+    }
+
+    #[test]
+    fn exec_may_name_a_shim() {
+        let content = r#"
+            [file-chooser.save-file]
+            exec = "submit"
+            [screenshot]
+            exec = "snap"
+            bin = { snap = "grim /tmp/s.png && sel /tmp/s.png && submit" }
+        "#;
+        assert_eq!(messages(content), Vec::<String>::new());
+    }
+
+    #[test]
+    fn exec_fallback_commands() {
+        let content = r#"
+            [file-chooser]
+            exec_fallback = ["/nonexistent/portty-terminal", ["submit"], ["/nonexistent/other", "-e"], ""]
+        "#;
+        assert_eq!(
+            messages(content),
+            vec![
+                "warning: file-chooser.exec_fallback: `/nonexistent/portty-terminal` not found in PATH",
+                "warning: file-chooser.exec_fallback: `/nonexistent/other` not found in PATH",
+            ]
+        );
+        let (config, _) = super::super::Config::parse(content).unwrap();
+        assert_eq!(
+            config.resolve_exec_fallback("file-chooser", "open-file"),
+            vec![
+                vec!["/nonexistent/portty-terminal".to_string()],
+                vec!["submit".to_string()],
+                vec!["/nonexistent/other".to_string(), "-e".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_scalar_keys_do_not_break_loading() {
+        let (config, warnings) =
+            super::super::Config::parse("exce = \"foot\"\nexec = \"\"\n").unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(config.portals.is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+//! Environment the commands of a session inherit (`inherit_env`)
+//!
+//! The terminal and the other commands a session runs start with porttyd's
+//! environment, which may hold credentials a picker script has no business
+//! seeing. `inherit_env` narrows it:
+//!
+//! ```toml
+//! [inherit_env]
+//! allow = ["HOME", "USER", "LANG", "LC_*", "TERM", "DISPLAY", "WAYLAND_DISPLAY", "XDG_*"]
+//!
+//! [screenshot.inherit_env]
+//! deny = ["AWS_*", "*_TOKEN"]
+//! ```
+//!
+//! With `allow`, only the variables it names are inherited; `deny` drops the
+//! ones it names (from what `allow` let through, when both are given). Names
+//! may contain `*`, matching any run of characters. `PATH`, with the
+//! session's shims first, and the `PORTTY_*` variables are set either way.
+
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+
+use serde::{Deserialize, Deserializer};
+
+/// Names every session command keeps: the session sets `PATH` from
+/// porttyd's anyway
+const ALWAYS: &[&str] = &["PATH", "PORTTY_*"];
+
+/// Which inherited variables the commands of a session keep
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvFilter {
+    /// Keep only these; `None` keeps everything not denied
+    #[serde(default, deserialize_with = "some_names")]
+    allow: Option<Vec<String>>,
+    /// Drop these
+    #[serde(default, deserialize_with = "names")]
+    deny: Vec<String>,
+}
+
+impl EnvFilter {
+    /// A filter from the name lists of an `inherit_env` table
+    pub fn new(allow: Option<&[&str]>, deny: &[&str]) -> Result<Self, String> {
+        let list = |names: &[&str]| -> Result<Vec<String>, String> {
+            names.iter().map(|name| check_name(name)).collect()
+        };
+        Ok(Self {
+            allow: allow.map(list).transpose()?,
+            deny: list(deny)?,
+        })
+    }
+
+    /// Whether an inherited variable called `name` is passed on
+    pub fn passes(&self, name: &OsStr) -> bool {
+        let name = name.as_bytes();
+        let named = |patterns: &[String]| patterns.iter().any(|p| matches(p.as_bytes(), name));
+        if ALWAYS.iter().any(|p| matches(p.as_bytes(), name)) {
+            return true;
+        }
+        self.allow.as_deref().is_none_or(named) && !named(&self.deny)
+    }
+
+    /// Names among `vars` that are not passed on, for the caller to remove
+    pub fn dropped(&self, vars: impl IntoIterator<Item = (OsString, OsString)>) -> Vec<OsString> {
+        vars.into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| !self.passes(name))
+            .collect()
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of bytes
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        Some((&byte, rest)) => name.first() == Some(&byte) && matches(rest, &name[1..]),
+    }
+}
+
+fn check_name(name: &str) -> Result<String, String> {
+    if name.is_empty() || name.contains('=') || name.contains('\0') {
+        return Err(format!("invalid variable name {name:?}"));
+    }
+    Ok(name.to_string())
+}
+
+fn names<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|name| check_name(name).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn some_names<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    names(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allow_and_deny_lists() {
+        let passes = |filter: &EnvFilter, name: &str| filter.passes(OsStr::new(name));
+
+        let everything = EnvFilter::default();
+        assert!(passes(&everything, "AWS_SECRET_ACCESS_KEY"));
+
+        let filter = EnvFilter::new(Some(&["HOME", "XDG_*", "LC_*"]), &["XDG_*_DIR"]).unwrap();
+        assert!(passes(&filter, "HOME"));
+        assert!(!passes(&filter, "XDG_RUNTIME_DIR"));
+        assert!(passes(&filter, "XDG_SESSION_TYPE"));
+        assert!(passes(&filter, "LC_ALL"));
+        assert!(!passes(&filter, "HOMEDIR"));
+        assert!(!passes(&filter, "GITHUB_TOKEN"));
+        assert!(passes(&filter, "PORTTY_CONFIG"));
+        assert!(passes(&filter, "PATH"));
+
+        let deny = EnvFilter::new(None, &["*_TOKEN", "AWS_*"]).unwrap();
+        assert!(!passes(&deny, "GITHUB_TOKEN"));
+        assert!(!passes(&deny, "AWS_PROFILE"));
+        assert!(passes(&deny, "TOKENS"));
+        assert_eq!(
+            deny.dropped([
+                ("HOME".into(), "/home/u".into()),
+                ("NPM_TOKEN".into(), "x".into()),
+            ]),
+            [OsString::from("NPM_TOKEN")]
+        );
+
+        assert!(EnvFilter::new(None, &["A=B"]).is_err());
+        assert!(EnvFilter::new(Some(&[""]), &[]).is_err());
+    }
+}
@@ -0,0 +1,1212 @@
+//! Daemon configuration (`~/.config/portty/config.toml`)
+//!
+//! Settings resolve per portal operation: operation-specific, then
+//! portal-level, then root. Loading goes through [`check`], which reports
+//! unknown keys, type errors, and suspicious values with their key path
+//! instead of silently falling back to defaults.
+
+pub mod audit;
+mod builder;
+pub mod check;
+pub mod inherit_env;
+pub mod rewrite;
+pub mod schema;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::portal::file_chooser::Filter;
+use crate::quota::Quota;
+use crate::remote::Remote;
+use crate::socket::SocketAddress;
+use crate::{Operation, Portal, QueueConflict, QueuePolicy};
+
+pub use audit::{AuditAction, AuditRules};
+pub use builder::{ConfigBuilder, Settings};
+pub use check::{Diagnostic, Severity};
+pub use inherit_env::EnvFilter;
+pub use rewrite::RewriteRule;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged, expecting = "a program name or an argv array")]
+enum ExecCommand {
+    Program(String),
+    Argv(Vec<String>),
+}
+
+impl ExecCommand {
+    fn as_argv(&self) -> Option<Vec<String>> {
+        match self {
+            Self::Program(program) => {
+                if program.is_empty() {
+                    None
+                } else {
+                    Some(vec![program.clone()])
+                }
+            }
+            Self::Argv(argv) => {
+                if argv.is_empty() || argv[0].is_empty() {
+                    None
+                } else {
+                    Some(argv.clone())
+                }
+            }
+        }
+    }
+}
+
+/// Byte size given as an integer or a string with a unit suffix ("64M")
+#[derive(Debug, Clone, Copy)]
+struct ByteSize(u64);
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(u64),
+            Human(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Bytes(bytes) => Ok(Self(bytes)),
+            Raw::Human(s) => crate::quota::parse_size(&s).map(Self).ok_or_else(|| {
+                serde::de::Error::custom(format!(
+                    "invalid size {s:?} (expected e.g. 65536, 512K, 64M)"
+                ))
+            }),
+        }
+    }
+}
+
+/// File mode creation mask given as an integer (`0o022`) or an octal string
+/// ("022")
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Umask(pub u32);
+
+impl<'de> Deserialize<'de> for Umask {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Mask(u32),
+            Octal(String),
+        }
+
+        let mask = match Raw::deserialize(deserializer)? {
+            Raw::Mask(mask) => Some(mask),
+            Raw::Octal(s) => {
+                let digits = s.strip_prefix("0o").unwrap_or(&s);
+                u32::from_str_radix(digits, 8).ok()
+            }
+        };
+        mask.filter(|mask| *mask <= 0o777).map(Self).ok_or_else(|| {
+            serde::de::Error::custom("invalid umask (expected e.g. 0o022 or \"077\")")
+        })
+    }
+}
+
+/// What to do when the spawned process exits without an explicit submit/cancel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnTerminalExit {
+    /// Treat exit as confirmation (historical behavior)
+    #[default]
+    Submit,
+    /// Treat exit as cancellation
+    Cancel,
+    /// Ask on the daemon's controlling TTY, or require a second close
+    Ask,
+}
+
+/// Default window for answering the exit prompt or closing the terminal twice
+const DEFAULT_EXIT_CONFIRM_TIMEOUT: u64 = 5;
+
+/// How the session terminal is cued when it needs attention
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Attention {
+    /// No cue (default)
+    #[default]
+    None,
+    /// Ring the terminal bell (BEL)
+    Bell,
+    /// Briefly flash the terminal by toggling reverse video
+    Flash,
+    /// Desktop notification via OSC 9, where the terminal supports it
+    Notify,
+}
+
+/// Default seconds a session may wait for input before it is cued
+const DEFAULT_ATTENTION_DELAY: u64 = 30;
+
+/// How a session without a terminal (headless) is made known
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Headless {
+    /// Wait for the `portty` CLI, announced only in the log (default)
+    #[default]
+    Wait,
+    /// Also print the session and the command joining it on the user's
+    /// login terminals (from utmp), as `write` would
+    Announce,
+}
+
+/// What the session's terminal runs in inline and tabs mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Picker {
+    /// `menu` for file chooser sessions when porttyd's terminal is `TERM=dumb`,
+    /// `shell` otherwise (default)
+    #[default]
+    Auto,
+    /// `inline_exec`, or the user's shell
+    Shell,
+    /// `portty pick`: numbered listings and plain prompts, for serial
+    /// consoles and speech output (file chooser)
+    Menu,
+}
+
+/// Niceness added to the commands a session runs, as `nice -n` would; only
+/// lowering their priority needs no privileges
+fn niceness<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<i32>, D::Error> {
+    let nice = i32::deserialize(deserializer)?;
+    if (0..=19).contains(&nice) {
+        Ok(Some(nice))
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "invalid nice {nice} (expected 0 to 19)"
+        )))
+    }
+}
+
+/// File filters written `NAME=PATTERN[,PATTERN...]` (see [`Filter`])
+fn filter_specs<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<Filter>>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|spec| spec.parse().map_err(serde::de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Daemon addresses, `@NAME` or a path with `~` expanded (see
+/// [`SocketAddress`])
+fn socket_addresses<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<SocketAddress>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|address| {
+            expand_home(address)
+                .parse()
+                .map_err(serde::de::Error::custom)
+        })
+        .collect()
+}
+
+/// I/O scheduling class of the commands a session runs (`ionice`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoNice {
+    /// Disk time only when no other process wants it
+    Idle,
+    /// Normal scheduling at a level from 0 (highest) to 7 (lowest)
+    BestEffort(u8),
+}
+
+impl<'de> Deserialize<'de> for IoNice {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Level(u8),
+            Class(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Level(level @ 0..=7) => Ok(Self::BestEffort(level)),
+            Raw::Class(class) if class == "idle" => Ok(Self::Idle),
+            Raw::Class(class) if class == "best-effort" => Ok(Self::BestEffort(4)),
+            _ => Err(serde::de::Error::custom(
+                "invalid ionice (expected \"idle\", \"best-effort\", or a level 0 to 7)",
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for IoNice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Idle => f.write_str("idle"),
+            Self::BestEffort(level) => write!(f, "best-effort {level}"),
+        }
+    }
+}
+
+/// Cgroup properties of the transient systemd scope the commands of a
+/// session run in (`systemd_scope`)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SystemdScope {
+    /// `CPUWeight`, 1 to 10000 (systemd's default is 100)
+    #[serde(default, deserialize_with = "weight")]
+    pub cpu_weight: Option<u16>,
+    /// `IOWeight`, 1 to 10000 (systemd's default is 100)
+    #[serde(default, deserialize_with = "weight")]
+    pub io_weight: Option<u16>,
+    /// `MemoryHigh`, in bytes: usage above it is throttled and reclaimed
+    #[serde(default, deserialize_with = "byte_size")]
+    pub memory_high: Option<u64>,
+}
+
+impl SystemdScope {
+    /// The properties as `systemd-run --property` values
+    pub fn properties(&self) -> Vec<String> {
+        [
+            self.cpu_weight.map(|w| format!("CPUWeight={w}")),
+            self.io_weight.map(|w| format!("IOWeight={w}")),
+            self.memory_high.map(|bytes| format!("MemoryHigh={bytes}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+fn weight<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u16>, D::Error> {
+    match u16::deserialize(deserializer)? {
+        weight @ 1..=10000 => Ok(Some(weight)),
+        weight => Err(serde::de::Error::custom(format!(
+            "invalid weight {weight} (expected 1 to 10000)"
+        ))),
+    }
+}
+
+fn byte_size<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    ByteSize::deserialize(deserializer).map(|size| Some(size.0))
+}
+
+/// How the commands of a session are scheduled, resolved from `nice`,
+/// `ionice`, and `systemd_scope`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Priority {
+    pub nice: Option<i32>,
+    pub ionice: Option<IoNice>,
+    pub scope: Option<SystemdScope>,
+}
+
+/// Base config fields shared at every level (root, portal, operation)
+#[derive(Debug, Clone, Default, Deserialize)]
+struct BaseConfig {
+    /// Command to execute
+    #[serde(default)]
+    exec: Option<ExecCommand>,
+
+    /// Commands tried in turn when `exec` fails to start
+    #[serde(default)]
+    exec_fallback: Option<Vec<ExecCommand>>,
+
+    /// Custom bin shims
+    #[serde(default)]
+    bin: HashMap<String, String>,
+
+    /// Behavior when the spawned process exits on its own
+    #[serde(default)]
+    on_terminal_exit: Option<OnTerminalExit>,
+
+    /// Seconds within which the exit prompt is answered, or a second close
+    /// confirms submission (`ask` mode)
+    #[serde(default)]
+    exit_confirm_timeout: Option<u64>,
+
+    /// Return an empty submission as success instead of cancelling
+    #[serde(default)]
+    allow_empty: Option<bool>,
+
+    /// How queued submissions compete with an interactive session
+    #[serde(default)]
+    queue_policy: Option<QueuePolicy>,
+
+    /// What a submit does when the pending queue and the session's
+    /// selection differ
+    #[serde(default)]
+    queue_conflict: Option<QueueConflict>,
+
+    /// Command run on the result file before it is returned (screenshot)
+    #[serde(default)]
+    post_process: Option<ExecCommand>,
+
+    /// Folder keeping the captures of a screenshot series after the first
+    #[serde(default)]
+    series_dir: Option<String>,
+
+    /// Cue for a session left waiting or a failed `verify`
+    #[serde(default)]
+    attention: Option<Attention>,
+
+    /// Seconds a session waits for input before the attention cue
+    #[serde(default)]
+    attention_delay: Option<u64>,
+
+    /// Minutes a dialog may stay open before it is reported as forgotten
+    #[serde(default)]
+    stale_after: Option<u64>,
+
+    /// Command run when a dialog has been open for `stale_after` minutes
+    #[serde(default)]
+    on_stale: Option<ExecCommand>,
+
+    /// How a session without a terminal is made known
+    #[serde(default)]
+    headless: Option<Headless>,
+
+    /// Seconds a session without a terminal waits to be used before the
+    /// request fails
+    #[serde(default)]
+    headless_timeout: Option<u64>,
+
+    /// Rules rewriting results before they are returned to the app
+    #[serde(default)]
+    rewrite: Vec<RewriteRule>,
+
+    /// Export files a sandboxed app cannot access through the document portal
+    #[serde(default)]
+    export_documents: Option<bool>,
+
+    /// Add file chooser results to the recently used files
+    #[serde(default)]
+    record_recent: Option<bool>,
+
+    /// Keep an idle session process running, ready to adopt the next request
+    #[serde(default)]
+    standby: Option<bool>,
+
+    /// Lists of sensitive paths to log, confirm, or refuse in results
+    #[serde(default)]
+    audit: Option<AuditRules>,
+
+    /// Built-in picker run in place of `inline_exec`
+    #[serde(default)]
+    picker: Option<Picker>,
+
+    /// Command run on the daemon's terminal in inline mode (`porttyd --inline`),
+    /// or in the session's tmux window in tabs mode (`[tabs]`)
+    #[serde(default)]
+    inline_exec: Option<ExecCommand>,
+
+    /// Entries pre-selected when the request brings none
+    #[serde(default)]
+    defaults: Option<Vec<String>>,
+
+    /// File chooser filters offered when the request brings none
+    #[serde(default, deserialize_with = "filter_specs")]
+    default_filters: Option<Vec<Filter>>,
+
+    /// Tool staging `host:path` entries locally (file chooser)
+    #[serde(default)]
+    remote: Option<Remote>,
+
+    /// Folder file chooser results must lie in
+    #[serde(default)]
+    restrict_to: Option<String>,
+
+    /// Command checking submissions after the built-in validation
+    #[serde(default)]
+    validator: Option<ExecCommand>,
+
+    /// Umask of the commands the daemon runs (terminal, post-processing)
+    #[serde(default)]
+    umask: Option<Umask>,
+
+    /// Niceness of the commands the daemon runs
+    #[serde(default, deserialize_with = "niceness")]
+    nice: Option<i32>,
+
+    /// I/O scheduling class of the commands the daemon runs
+    #[serde(default)]
+    ionice: Option<IoNice>,
+
+    /// Transient systemd scope the commands the daemon runs are placed in
+    #[serde(default)]
+    systemd_scope: Option<SystemdScope>,
+
+    /// Which of porttyd's environment variables the commands it runs keep
+    #[serde(default)]
+    inherit_env: Option<EnvFilter>,
+}
+
+/// Fields that only exist at the root level
+#[derive(Debug, Clone, Default, Deserialize)]
+struct RootConfig {
+    /// Directory for sockets, sessions, and queues instead of
+    /// `$TMPDIR/portty/<uid>`
+    #[serde(default)]
+    base_dir: Option<String>,
+
+    /// Addresses the daemon listens on besides `daemon.sock`: socket paths
+    /// and `@NAME` abstract sockets
+    #[serde(default, deserialize_with = "socket_addresses")]
+    listen: Vec<SocketAddress>,
+
+    /// The portty CLI that shims and session commands run, instead of the
+    /// one found next to porttyd or in `$PATH`
+    #[serde(default)]
+    cli: Option<String>,
+
+    /// Largest accepted control message (one protocol line), in bytes
+    #[serde(default)]
+    max_message_size: Option<usize>,
+
+    /// Longest accepted entry, session id, or portal name, in bytes
+    #[serde(default)]
+    max_entry_length: Option<usize>,
+
+    /// Advisory disk usage budget per session (directory plus payloads)
+    #[serde(default)]
+    session_quota: Option<ByteSize>,
+
+    /// Advisory disk usage budget for all sessions, queues, and cached payloads
+    #[serde(default)]
+    total_quota: Option<ByteSize>,
+
+    /// Payloads above this size are placed in `$XDG_CACHE_HOME/portty`
+    #[serde(default)]
+    payload_threshold: Option<ByteSize>,
+
+    /// Troubleshooting switches (`[debug]`)
+    #[serde(default)]
+    debug: DebugConfig,
+
+    /// One terminal for concurrent sessions (`[tabs]`)
+    #[serde(default)]
+    tabs: TabsConfig,
+}
+
+/// Troubleshooting switches (`[debug]`)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DebugConfig {
+    /// Keep the session terminal open when the app closes the request or the
+    /// submission is refused, with an event log for the `why` shim
+    #[serde(default)]
+    keep_session: bool,
+}
+
+/// One terminal for concurrent sessions (`[tabs]`)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TabsConfig {
+    /// Run sessions as windows of one tmux session instead of a terminal
+    /// each
+    #[serde(default)]
+    enabled: bool,
+
+    /// Terminal opened to show the tmux session, with the attach command
+    /// appended (defaults to the session's `exec`)
+    #[serde(default)]
+    terminal: Option<ExecCommand>,
+}
+
+/// Operation-level config (leaf)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OperationConfig {
+    #[serde(flatten)]
+    base: BaseConfig,
+}
+
+/// Portal-level config with nested operations
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PortalConfig {
+    #[serde(flatten)]
+    base: BaseConfig,
+
+    /// Operation-specific configs (unknown keys become operations)
+    #[serde(flatten)]
+    pub operations: HashMap<String, OperationConfig>,
+}
+
+/// Root configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    #[serde(flatten)]
+    base: BaseConfig,
+
+    #[serde(flatten)]
+    root: RootConfig,
+
+    /// Portal-specific configs (unknown keys become portals)
+    #[serde(flatten)]
+    pub portals: HashMap<String, PortalConfig>,
+}
+
+/// Expand a leading `~` or `~/` to the home directory
+pub(crate) fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => format!("{}/{rest}", home.display()),
+        _ if path == "~" => dirs::home_dir()
+            .map(|home| home.display().to_string())
+            .unwrap_or_else(|| path.to_string()),
+        _ => path.to_string(),
+    }
+}
+
+/// Look up `program` like the shell would: paths containing `/` are used
+/// as-is, bare names are searched in `$PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    find_program_in(program, &std::env::var_os("PATH")?)
+}
+
+/// [`find_program`] with `path` as `$PATH`, for a command started with
+/// another environment
+pub fn find_program_in(program: &str, path: &std::ffi::OsStr) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+    std::env::split_paths(path)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// Name of the portty CLI executable
+pub const CLI_BIN: &str = "portty";
+
+/// The portty CLI: this executable when it is the CLI, `portty` next to it
+/// (porttyd is installed beside it), or `portty` in `$PATH`. Shims and
+/// session commands run it by absolute path, so they work in environments
+/// with another or no `$PATH`.
+pub fn find_cli() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok();
+    let this = exe
+        .as_ref()
+        .filter(|exe| exe.file_name().is_some_and(|name| name == CLI_BIN))
+        .cloned();
+    let sibling = exe
+        .as_ref()
+        .and_then(|exe| Some(exe.parent()?.join(CLI_BIN)))
+        .filter(|path| is_executable(path));
+    this.or(sibling).or_else(|| find_program(CLI_BIN))
+}
+
+/// Installed terminal emulators, in order of preference
+fn installed_terminals() -> impl Iterator<Item = &'static str> {
+    let terminals = ["foot", "alacritty", "kitty", "wezterm", "ghostty", "xterm"];
+
+    terminals
+        .into_iter()
+        .filter(|term| find_program(term).is_some())
+}
+
+/// Try to find a terminal emulator
+fn detect_terminal() -> Option<ExecCommand> {
+    installed_terminals()
+        .next()
+        .map(|term| ExecCommand::Program(term.to_string()))
+}
+
+impl Config {
+    fn default_bin(portal: &str, operation: &str) -> HashMap<String, String> {
+        let mut bin = HashMap::from([
+            ("submit".to_string(), "portty submit".to_string()),
+            ("cancel".to_string(), "portty cancel".to_string()),
+            ("info".to_string(), "portty info".to_string()),
+            ("clear".to_string(), "portty clear".to_string()),
+            ("reset".to_string(), "portty reset".to_string()),
+            // Shells with a `help` builtin run that instead; `?` always works
+            ("?".to_string(), "portty guide".to_string()),
+            ("help".to_string(), "portty guide".to_string()),
+        ]);
+
+        // `operation` is empty for portal-level settings (standby sessions)
+        let portal = portal.parse::<Portal>().ok();
+        let operation = portal.and_then(|portal| Operation::of(portal.as_str(), operation).ok());
+
+        if portal == Some(Portal::FileChooser) {
+            bin.insert(
+                "sel".to_string(),
+                "if [ $# -eq 0 ]; then portty show; else portty add path \"$@\"; fi".to_string(),
+            );
+            bin.insert("desel".to_string(), "portty remove path \"$@\"".to_string());
+            bin.insert("choices".to_string(), "portty choices \"$@\"".to_string());
+        }
+
+        if matches!(operation, Some(Operation::OpenFile | Operation::SaveFiles)) {
+            bin.insert(
+                "folder".to_string(),
+                "if [ $# -eq 0 ]; then portty get folders; else portty add directory \"$@\"; fi"
+                    .to_string(),
+            );
+        }
+
+        if portal == Some(Portal::RemoteDesktop)
+            || operation == Some(Operation::RequestInstallToken)
+        {
+            bin.insert(
+                "approve".to_string(),
+                "printf 'approve\\n' > \"$PORTTY_DIR/submission\" && portty submit".to_string(),
+            );
+            bin.insert("deny".to_string(), "portty cancel".to_string());
+        }
+
+        match operation {
+            Some(Operation::Screenshot) => {
+                bin.insert("shot".to_string(), "portty shot \"$@\"".to_string());
+            }
+            Some(Operation::SaveFiles) => {
+                bin.insert(
+                    "conflicts".to_string(),
+                    "portty conflicts \"$@\"".to_string(),
+                );
+                bin.insert(
+                    "next-file".to_string(),
+                    "portty next-file \"$@\"".to_string(),
+                );
+            }
+            Some(Operation::PrepareInstall) => {
+                bin.insert(
+                    "rename".to_string(),
+                    "printf '%s\\n' \"$*\" > \"$PORTTY_DIR/submission\"".to_string(),
+                );
+            }
+            _ => {}
+        }
+
+        bin
+    }
+
+    /// Load config from `PORTTY_CONFIG` or the default location
+    /// (`~/.config/portty/config.toml`), together with any warnings.
+    ///
+    /// A missing config file is not an error and yields the auto-detected
+    /// defaults. Unreadable files, invalid TOML, and type errors are.
+    pub fn try_load() -> Result<(Self, Vec<Diagnostic>), String> {
+        Self::try_load_with(&[])
+    }
+
+    /// [`try_load`](Self::try_load), taking the tables named in `portals`
+    /// (plugin portals) for portals as well
+    pub fn try_load_with(portals: &[&str]) -> Result<(Self, Vec<Diagnostic>), String> {
+        let Some(path) = Self::path() else {
+            return Ok((Self::detected(), Vec::new()));
+        };
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok((Self::detected(), Vec::new()));
+            }
+            Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+        };
+        Self::parse_with(&content, portals).map_err(|diagnostics| {
+            let errors: Vec<String> = diagnostics
+                .iter()
+                .filter(|d| d.is_error())
+                .map(ToString::to_string)
+                .collect();
+            format!("invalid config {}: {}", path.display(), errors.join("; "))
+        })
+    }
+
+    /// Parse and validate config source.
+    ///
+    /// On success returns the config with its warnings; if anything is an
+    /// error, returns all diagnostics instead.
+    pub fn parse(content: &str) -> Result<(Self, Vec<Diagnostic>), Vec<Diagnostic>> {
+        Self::parse_with(content, &[])
+    }
+
+    /// [`parse`](Self::parse), taking the tables named in `portals` (plugin
+    /// portals) for portals as well
+    pub fn parse_with(
+        content: &str,
+        portals: &[&str],
+    ) -> Result<(Self, Vec<Diagnostic>), Vec<Diagnostic>> {
+        let (table, mut diagnostics) = check::validate(content, portals);
+        let Some(table) = table.filter(|_| !diagnostics.iter().any(Diagnostic::is_error)) else {
+            return Err(diagnostics);
+        };
+        match Self::deserialize(toml::Value::Table(table)) {
+            Ok(config) => Ok((config, diagnostics)),
+            Err(e) => {
+                diagnostics.push(Diagnostic::error("", e.message()));
+                Err(diagnostics)
+            }
+        }
+    }
+
+    /// Defaults used when no config file exists (or it failed to load)
+    pub fn detected() -> Self {
+        Self {
+            base: BaseConfig {
+                exec: detect_terminal(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Get config file path.
+    ///
+    /// `PORTTY_CONFIG` overrides the default config location when set.
+    pub fn path() -> Option<PathBuf> {
+        std::env::var_os("PORTTY_CONFIG")
+            .map(PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|p| p.join("portty/config.toml")))
+    }
+
+    /// Resolve a scalar setting for a portal operation.
+    /// Priority: operation-specific -> portal-specific -> root default
+    fn resolve<'a, T>(
+        &'a self,
+        portal: &str,
+        operation: &str,
+        field: impl Fn(&'a BaseConfig) -> Option<&'a T>,
+    ) -> Option<&'a T> {
+        let portal_cfg = self.portals.get(portal);
+
+        // Check operation-specific
+        let op_value = portal_cfg
+            .and_then(|p| p.operations.get(operation))
+            .and_then(|o| field(&o.base));
+
+        // Check portal-level
+        let portal_value = portal_cfg.and_then(|p| field(&p.base));
+
+        // Fall back to root
+        op_value.or(portal_value).or_else(|| field(&self.base))
+    }
+
+    /// Resolve exec command for a portal operation.
+    /// Priority: operation-specific -> portal-specific -> root default
+    pub fn resolve_exec(&self, portal: &str, operation: &str) -> Option<Vec<String>> {
+        self.resolve(portal, operation, |b| b.exec.as_ref())
+            .and_then(ExecCommand::as_argv)
+    }
+
+    /// Resolve the commands tried in turn when `exec` fails to start.
+    ///
+    /// Defaults to the installed terminal emulators; the caller skips the
+    /// one `exec` already names.
+    pub fn resolve_exec_fallback(&self, portal: &str, operation: &str) -> Vec<Vec<String>> {
+        match self.resolve(portal, operation, |b| b.exec_fallback.as_ref()) {
+            Some(commands) => commands.iter().filter_map(ExecCommand::as_argv).collect(),
+            None => installed_terminals()
+                .map(|term| vec![term.to_string()])
+                .collect(),
+        }
+    }
+
+    /// Resolve the command run on the daemon's terminal in inline mode.
+    ///
+    /// Defaults to the user's shell (`$SHELL`, else `/bin/sh`): the session UI
+    /// is the shell with the session's shims, in place of a terminal emulator.
+    /// File chooser sessions run `portty pick` instead when [`Picker`] asks
+    /// for the menu.
+    pub fn resolve_inline_exec(&self, portal: &str, operation: &str) -> Vec<String> {
+        let menu = match self.resolve_picker(portal, operation) {
+            Picker::Auto => std::env::var("TERM").is_ok_and(|term| term == "dumb"),
+            Picker::Shell => false,
+            Picker::Menu => true,
+        };
+        if menu && portal == Portal::FileChooser.as_str() {
+            return vec!["portty".to_string(), "pick".to_string()];
+        }
+        self.resolve(portal, operation, |b| b.inline_exec.as_ref())
+            .and_then(ExecCommand::as_argv)
+            .unwrap_or_else(|| {
+                let shell = std::env::var("SHELL")
+                    .ok()
+                    .filter(|shell| !shell.is_empty())
+                    .unwrap_or_else(|| "/bin/sh".to_string());
+                vec![shell]
+            })
+    }
+
+    /// Resolve the post-processing command for a portal operation.
+    pub fn resolve_post_process(&self, portal: &str, operation: &str) -> Option<Vec<String>> {
+        self.resolve(portal, operation, |b| b.post_process.as_ref())
+            .and_then(ExecCommand::as_argv)
+    }
+
+    /// Resolve the command checking submissions after the built-in
+    /// validation.
+    pub fn resolve_validator(&self, portal: &str, operation: &str) -> Option<Vec<String>> {
+        self.resolve(portal, operation, |b| b.validator.as_ref())
+            .and_then(ExecCommand::as_argv)
+    }
+
+    /// Resolve the folder for screenshot series captures, `~` expanded.
+    pub fn resolve_series_dir(&self, portal: &str, operation: &str) -> Option<PathBuf> {
+        self.resolve(portal, operation, |b| b.series_dir.as_ref())
+            .filter(|path| !path.is_empty())
+            .map(|path| PathBuf::from(expand_home(path)))
+    }
+
+    /// Resolve what happens when the session process exits on its own.
+    pub fn resolve_on_terminal_exit(&self, portal: &str, operation: &str) -> OnTerminalExit {
+        self.resolve(portal, operation, |b| b.on_terminal_exit.as_ref())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Resolve the default queue policy (queued submissions may override it).
+    pub fn resolve_queue_policy(&self, portal: &str, operation: &str) -> QueuePolicy {
+        self.resolve(portal, operation, |b| b.queue_policy.as_ref())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Resolve what a submit does when the pending queue and the session's
+    /// selection differ (`submit --prefer` overrides it).
+    pub fn resolve_queue_conflict(&self, portal: &str, operation: &str) -> QueueConflict {
+        self.resolve(portal, operation, |b| b.queue_conflict.as_ref())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Resolve whether empty submissions count as success by default.
+    pub fn resolve_allow_empty(&self, portal: &str, operation: &str) -> bool {
+        self.resolve(portal, operation, |b| b.allow_empty.as_ref())
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Resolve the double-close confirmation window for `on_terminal_exit = "ask"`.
+    pub fn resolve_exit_confirm_timeout(&self, portal: &str, operation: &str) -> Duration {
+        let secs = self
+            .resolve(portal, operation, |b| b.exit_confirm_timeout.as_ref())
+            .copied()
+            .unwrap_or(DEFAULT_EXIT_CONFIRM_TIMEOUT);
+        Duration::from_secs(secs)
+    }
+
+    /// Resolve the attention cue for a portal operation.
+    pub fn resolve_attention(&self, portal: &str, operation: &str) -> Attention {
+        self.resolve(portal, operation, |b| b.attention.as_ref())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Resolve how long a session may wait for input before it is cued.
+    pub fn resolve_attention_delay(&self, portal: &str, operation: &str) -> Duration {
+        let secs = self
+            .resolve(portal, operation, |b| b.attention_delay.as_ref())
+            .copied()
+            .unwrap_or(DEFAULT_ATTENTION_DELAY);
+        Duration::from_secs(secs)
+    }
+
+    /// Resolve how long a dialog may stay open before it is reported as
+    /// forgotten; `None` (the default, or 0) never reports it.
+    pub fn resolve_stale_after(&self, portal: &str, operation: &str) -> Option<Duration> {
+        self.resolve(portal, operation, |b| b.stale_after.as_ref())
+            .copied()
+            .filter(|&minutes| minutes > 0)
+            .map(|minutes| Duration::from_secs(minutes * 60))
+    }
+
+    /// Resolve the command run for a forgotten dialog.
+    pub fn resolve_on_stale(&self, portal: &str, operation: &str) -> Option<Vec<String>> {
+        self.resolve(portal, operation, |b| b.on_stale.as_ref())
+            .and_then(ExecCommand::as_argv)
+    }
+
+    /// Resolve the built-in picker a session's terminal runs.
+    pub fn resolve_picker(&self, portal: &str, operation: &str) -> Picker {
+        self.resolve(portal, operation, |b| b.picker.as_ref())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Resolve how a session without a terminal is made known.
+    pub fn resolve_headless(&self, portal: &str, operation: &str) -> Headless {
+        self.resolve(portal, operation, |b| b.headless.as_ref())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Resolve how long a session without a terminal waits for its
+    /// selection to change before the request fails; `None` (the default,
+    /// or 0) waits until the app gives up.
+    pub fn resolve_headless_timeout(&self, portal: &str, operation: &str) -> Option<Duration> {
+        self.resolve(portal, operation, |b| b.headless_timeout.as_ref())
+            .copied()
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Point [`paths::base_dir`](crate::paths::base_dir) at the config's
+    /// `base_dir`, for porttyd and `portty` to call at startup.
+    ///
+    /// Only that key is read, so both agree on the directory even when
+    /// another part of the config is invalid. A relative path is ignored.
+    pub fn configure_base_dir() {
+        let dir = Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| content.parse::<toml::Table>().ok())
+            .and_then(|table| Some(expand_home(table.get("base_dir")?.as_str()?)))
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute());
+        if let Some(dir) = dir {
+            crate::paths::configure_base_dir(dir);
+        }
+    }
+
+    /// Set the extra daemon addresses (see
+    /// [`socket::listen_addresses`](crate::socket::listen_addresses)) from
+    /// the config's `listen`, for porttyd and `portty` to call at startup.
+    ///
+    /// Like [`configure_base_dir`](Self::configure_base_dir), only that key
+    /// is read. Invalid addresses are left out.
+    pub fn configure_listen() {
+        let addresses = Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| content.parse::<toml::Table>().ok())
+            .and_then(|table| {
+                let listen = table.get("listen")?.as_array()?;
+                Some(
+                    listen
+                        .iter()
+                        .filter_map(|address| expand_home(address.as_str()?).parse().ok())
+                        .collect(),
+                )
+            });
+        if let Some(addresses) = addresses {
+            crate::socket::configure_listen(addresses);
+        }
+    }
+
+    /// The portty CLI shims and session commands run: `cli` with `~`
+    /// expanded (looked up in `$PATH` when it is a bare name), or else
+    /// [`find_cli`]
+    pub fn resolve_cli(&self) -> Option<PathBuf> {
+        match self.root.cli.as_deref().filter(|cli| !cli.is_empty()) {
+            Some(cli) => {
+                let cli = expand_home(cli);
+                Some(find_program(&cli).unwrap_or_else(|| cli.into()))
+            }
+            None => find_cli(),
+        }
+    }
+
+    /// Upper bound for a single control socket / FIFO message.
+    pub fn max_message_size(&self) -> usize {
+        self.root
+            .max_message_size
+            .unwrap_or(crate::codec::DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    /// Upper bound for a single entry or identifier sent by a client.
+    pub fn max_entry_length(&self) -> usize {
+        self.root
+            .max_entry_length
+            .unwrap_or(crate::input::DEFAULT_MAX_ENTRY_LENGTH)
+    }
+
+    /// Whether sessions stay open for a post-mortem (`debug.keep_session`)
+    pub fn keep_session(&self) -> bool {
+        self.root.debug.keep_session
+    }
+
+    /// Whether sessions run as windows of one tmux session (`tabs.enabled`)
+    pub fn tabs(&self) -> bool {
+        self.root.tabs.enabled
+    }
+
+    /// Resolve the terminal opened to show the tmux session in tabs mode:
+    /// `tabs.terminal`, else the `exec` of the operation that needs it.
+    /// The attach command is appended to it.
+    pub fn resolve_tabs_terminal(&self, portal: &str, operation: &str) -> Option<Vec<String>> {
+        self.root
+            .tabs
+            .terminal
+            .as_ref()
+            .and_then(ExecCommand::as_argv)
+            .or_else(|| self.resolve_exec(portal, operation))
+    }
+
+    /// Disk usage limits for session state and payloads
+    pub fn quota(&self) -> Quota {
+        Quota {
+            session: self.root.session_quota.map(|s| s.0),
+            total: self.root.total_quota.map(|s| s.0),
+            payload_threshold: self.root.payload_threshold.map(|s| s.0),
+        }
+    }
+
+    /// Resolve whether result files are exported through the document portal.
+    pub fn resolve_export_documents(&self, portal: &str, operation: &str) -> bool {
+        self.resolve(portal, operation, |b| b.export_documents.as_ref())
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Resolve whether file chooser results are added to the recently used
+    /// files.
+    pub fn resolve_record_recent(&self, portal: &str, operation: &str) -> bool {
+        self.resolve(portal, operation, |b| b.record_recent.as_ref())
+            .copied()
+            .unwrap_or(true)
+    }
+
+    /// Resolve whether a portal keeps a standby session, or (with an
+    /// operation) whether that operation may adopt it.
+    ///
+    /// The standby process itself is started before any operation is known,
+    /// so it uses the portal-level settings (`operation = ""`).
+    pub fn resolve_standby(&self, portal: &str, operation: &str) -> bool {
+        self.resolve(portal, operation, |b| b.standby.as_ref())
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Resolve the entries pre-selected in a session whose request brings
+    /// none, with `~` expanded (the nearest level with `defaults` wins).
+    pub fn resolve_defaults(&self, portal: &str, operation: &str) -> Vec<String> {
+        self.resolve(portal, operation, |b| b.defaults.as_ref())
+            .map(|entries| entries.iter().map(|e| expand_home(e)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Resolve the filters of a file chooser session whose request brings
+    /// none (the nearest level with `default_filters` wins).
+    pub fn resolve_default_filters(&self, portal: &str, operation: &str) -> Vec<Filter> {
+        self.resolve(portal, operation, |b| b.default_filters.as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Resolve the folder file chooser results must lie in, `~` expanded.
+    pub fn resolve_restrict_to(&self, portal: &str, operation: &str) -> Option<PathBuf> {
+        self.resolve(portal, operation, |b| b.restrict_to.as_ref())
+            .filter(|path| !path.is_empty())
+            .map(|path| PathBuf::from(expand_home(path)))
+    }
+
+    /// Resolve the tool staging `host:path` entries, if remote paths are
+    /// enabled for a portal operation.
+    pub fn resolve_remote(&self, portal: &str, operation: &str) -> Option<Remote> {
+        self.resolve(portal, operation, |b| b.remote.as_ref())
+            .copied()
+    }
+
+    /// Environment a session command gets from the config and the request
+    /// `options`, besides the `PORTTY_*` variables of the session itself
+    #[cfg(feature = "portal")]
+    pub fn session_env(
+        &self,
+        portal: &str,
+        operation: &str,
+        options: &serde_json::Value,
+    ) -> Vec<(&'static str, String)> {
+        let mut env = self.quota().env();
+        env.extend(caller_env(options));
+        env.extend(self.resolve_remote(portal, operation).map(Remote::env));
+        env
+    }
+
+    /// Resolve the umask of the commands run for a portal operation; `None`
+    /// keeps the one porttyd was started with.
+    pub fn resolve_umask(&self, portal: &str, operation: &str) -> Option<Umask> {
+        self.resolve(portal, operation, |b| b.umask.as_ref())
+            .copied()
+    }
+
+    /// Resolve how the commands run for a portal operation are scheduled;
+    /// each of `nice`, `ionice`, and `systemd_scope` comes from the nearest
+    /// level setting it.
+    pub fn resolve_priority(&self, portal: &str, operation: &str) -> Priority {
+        Priority {
+            nice: self
+                .resolve(portal, operation, |b| b.nice.as_ref())
+                .copied(),
+            ionice: self
+                .resolve(portal, operation, |b| b.ionice.as_ref())
+                .copied(),
+            scope: self
+                .resolve(portal, operation, |b| b.systemd_scope.as_ref())
+                .cloned(),
+        }
+    }
+
+    /// Resolve which inherited variables the commands run for a portal
+    /// operation keep (the nearest level with an `inherit_env` table wins);
+    /// `None` keeps all of them.
+    pub fn resolve_inherit_env(&self, portal: &str, operation: &str) -> Option<&EnvFilter> {
+        self.resolve(portal, operation, |b| b.inherit_env.as_ref())
+    }
+
+    /// Resolve the audit lists for a portal operation (the nearest level
+    /// with an `audit` table wins).
+    pub fn resolve_audit(&self, portal: &str, operation: &str) -> Option<&AuditRules> {
+        self.resolve(portal, operation, |b| b.audit.as_ref())
+    }
+
+    /// Resolve rewrite rules for a portal operation, in the order they are
+    /// tried: operation-specific, then portal-level, then root rules.
+    pub fn resolve_rewrite(&self, portal: &str, operation: &str) -> Vec<&RewriteRule> {
+        let portal_cfg = self.portals.get(portal);
+        let levels = [
+            portal_cfg
+                .and_then(|p| p.operations.get(operation))
+                .map(|o| &o.base),
+            portal_cfg.map(|p| &p.base),
+            Some(&self.base),
+        ];
+        levels
+            .into_iter()
+            .flatten()
+            .flat_map(|base| &base.rewrite)
+            .collect()
+    }
+
+    /// Resolve bin shims for a portal operation (merged from all levels).
+    /// Priority: operation-specific overrides portal-level overrides root.
+    pub fn resolve_bin(&self, portal: &str, operation: &str) -> HashMap<String, String> {
+        let mut bin = Self::default_bin(portal, operation);
+        if self.keep_session() {
+            bin.insert("why".to_string(), "cat \"$PORTTY_DIR/events\"".to_string());
+        }
+        bin.extend(self.base.bin.clone());
+
+        if let Some(portal_cfg) = self.portals.get(portal) {
+            bin.extend(portal_cfg.base.bin.clone());
+
+            if let Some(op_cfg) = portal_cfg.operations.get(operation) {
+                bin.extend(op_cfg.base.bin.clone());
+            }
+        }
+
+        bin
+    }
+}
+
+/// Environment describing the requesting app, for portals that record it.
+///
+/// `app_id` is empty for unsandboxed apps and `parent_window` is empty when
+/// the app did not pass one; both are left unset in that case.
+#[cfg(feature = "portal")]
+fn caller_env(options: &serde_json::Value) -> Vec<(&'static str, String)> {
+    [
+        ("PORTTY_APP_ID", "app_id"),
+        ("PORTTY_PARENT_WINDOW", "parent_window"),
+    ]
+    .into_iter()
+    .filter_map(|(var, key)| {
+        options
+            .get(key)
+            .and_then(serde_json::Value::as_str)
+            .filter(|value| !value.is_empty())
+            .map(|value| (var, value.to_string()))
+    })
+    .collect()
+}
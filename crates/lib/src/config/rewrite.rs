@@ -0,0 +1,196 @@
+//! Result rewriting rules (`[[<portal>.rewrite]]`)
+//!
+//! Rules map result URIs (file chooser and screenshot) after validation,
+//! right before they are returned to the app, for setups where the picker's
+//! view of the filesystem differs from the app's (network mounts, bind
+//! mounts, sandboxes):
+//!
+//! ```toml
+//! [[file-chooser.rewrite]]
+//! match = "^file:///mnt/nas/"
+//! replace = "file:///home/me/nas/"
+//!
+//! [[file-chooser.rewrite]]
+//! app_id = 'org\.mozilla\..*'
+//! match = "^file:///var/home/(?<rest>.*)"
+//! replace = "file:///home/${rest}"
+//! ```
+//!
+//! `match` is a regular expression searched in each result; the first match is
+//! replaced by `replace`, where `$1`/`${name}` expand to capture groups and
+//! `$$` is a literal `$`. The first rule that matches a result wins. `app_id`
+//! optionally limits a rule to callers whose whole app id matches it.
+
+use regex_automata::meta::Regex;
+use serde::{Deserialize, Deserializer};
+
+/// A single rewrite rule
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteRule {
+    /// Pattern searched in each result
+    #[serde(rename = "match", deserialize_with = "pattern")]
+    pattern: Regex,
+    /// Replacement for the matched part
+    replace: String,
+    /// Only apply to callers whose app id matches (whole id)
+    #[serde(default, deserialize_with = "app_id_pattern")]
+    app_id: Option<Regex>,
+}
+
+impl RewriteRule {
+    /// A rule as a `[[rewrite]]` table gives it: `pattern` is `match`, and
+    /// `app_id` is matched against the whole app id
+    pub fn new(pattern: &str, replace: &str, app_id: Option<&str>) -> Result<Self, String> {
+        let compile =
+            |pattern: &str| compile::<serde::de::value::Error>(pattern).map_err(|e| e.to_string());
+        Ok(Self {
+            pattern: compile(pattern)?,
+            replace: replace.to_string(),
+            app_id: app_id
+                .map(|app_id| compile(&format!("^(?:{app_id})$")))
+                .transpose()?,
+        })
+    }
+
+    /// Rewrite `entry` if this rule applies to `app_id` and matches it.
+    pub fn apply(&self, app_id: &str, entry: &str) -> Option<String> {
+        if let Some(filter) = &self.app_id
+            && !filter.is_match(app_id)
+        {
+            return None;
+        }
+
+        let mut caps = self.pattern.create_captures();
+        self.pattern.captures(entry, &mut caps);
+        let m = caps.get_match()?;
+
+        let mut out = String::with_capacity(entry.len() + self.replace.len());
+        out.push_str(&entry[..m.start()]);
+        caps.interpolate_string_into(entry, &self.replace, &mut out);
+        out.push_str(&entry[m.end()..]);
+        Some(out)
+    }
+}
+
+/// Apply the first matching rule of `rules` to each entry.
+pub fn rewrite<'a>(
+    rules: impl IntoIterator<Item = &'a RewriteRule> + Clone,
+    app_id: &str,
+    entries: Vec<String>,
+) -> Vec<String> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            rules
+                .clone()
+                .into_iter()
+                .find_map(|rule| rule.apply(app_id, &entry))
+                .unwrap_or(entry)
+        })
+        .collect()
+}
+
+fn compile<E: serde::de::Error>(pattern: &str) -> Result<Regex, E> {
+    Regex::new(pattern).map_err(|e| {
+        // Syntax errors render as a multi-line snippet; the last line says what's wrong
+        let detail = std::error::Error::source(&e)
+            .and_then(|source| {
+                let source = source.to_string();
+                source
+                    .lines()
+                    .last()
+                    .map(|line| line.trim_start_matches("error: ").to_string())
+            })
+            .unwrap_or_else(|| e.to_string());
+        E::custom(format!("invalid regex {pattern:?}: {detail}"))
+    })
+}
+
+fn pattern<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    compile(&String::deserialize(deserializer)?)
+}
+
+fn app_id_pattern<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    compile(&format!("^(?:{pattern})$")).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(toml: &str) -> Vec<RewriteRule> {
+        #[derive(Deserialize)]
+        struct Rules {
+            rewrite: Vec<RewriteRule>,
+        }
+        toml::from_str::<Rules>(toml).unwrap().rewrite
+    }
+
+    #[test]
+    fn rewrites_first_match_with_captures() {
+        let rules = rules(
+            r#"
+            [[rewrite]]
+            match = "^file:///mnt/nas/"
+            replace = "file:///home/me/nas/"
+
+            [[rewrite]]
+            match = "^file:///var/home/(?<rest>.*)$"
+            replace = "file:///home/${rest}"
+
+            [[rewrite]]
+            match = "^file:///home/"
+            replace = "never-reached"
+            "#,
+        );
+
+        let out = rewrite(
+            &rules,
+            "",
+            vec![
+                "file:///mnt/nas/a.txt".into(),
+                "file:///var/home/me/b.txt".into(),
+                "file:///tmp/c.txt".into(),
+            ],
+        );
+        assert_eq!(
+            out,
+            [
+                "file:///home/me/nas/a.txt",
+                "file:///home/me/b.txt",
+                "file:///tmp/c.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn app_id_filter_matches_whole_id() {
+        let rules = rules(
+            r#"
+            [[rewrite]]
+            app_id = 'org\.mozilla\..*'
+            match = "^file:///run/host"
+            replace = "file://"
+            "#,
+        );
+        let entries = || vec!["file:///run/host/tmp/x".to_string()];
+
+        assert_eq!(
+            rewrite(&rules, "org.mozilla.firefox", entries()),
+            ["file:///tmp/x"]
+        );
+        assert_eq!(
+            rewrite(&rules, "com.example.org.mozilla.x", entries()),
+            entries()
+        );
+        assert_eq!(rewrite(&rules, "", entries()), entries());
+    }
+
+    #[test]
+    fn invalid_pattern_is_a_type_error() {
+        let err = toml::from_str::<RewriteRule>("match = \"(\"\nreplace = \"\"").unwrap_err();
+        assert!(err.message().contains("invalid regex"), "{err}");
+    }
+}
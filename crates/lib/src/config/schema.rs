@@ -0,0 +1,507 @@
+//! Documented config keys
+//!
+//! The reference behind `portty-config(5)`. The tests check it against the
+//! config structs, so a key added to `BaseConfig` or `RootConfig` without
+//! documentation fails them instead of going missing from the man page.
+
+/// Where a key may appear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Root, portal table, or operation table; the nearest level wins
+    Any,
+    /// Root level only
+    Root,
+}
+
+/// One config key
+#[derive(Debug, Clone, Copy)]
+pub struct Key {
+    /// Key name; keys of a root-level table are dotted (`tabs.enabled`)
+    pub name: &'static str,
+    pub scope: Scope,
+    /// Accepted values
+    pub value: &'static str,
+    /// A valid TOML value, shown in the generated config file
+    pub example: &'static str,
+    /// What the key does
+    pub doc: &'static str,
+}
+
+/// Every config key
+pub const KEYS: &[Key] = &[
+    Key {
+        name: "exec",
+        scope: Scope::Any,
+        value: "program or argv array",
+        example: r#"["foot", "--app-id", "portty"]"#,
+        doc: "Terminal command the session runs in. Auto-detected (foot, alacritty, kitty, ...) when unset; \"\" runs headless, for the portty CLI only.",
+    },
+    Key {
+        name: "exec_fallback",
+        scope: Scope::Any,
+        value: "array of programs or argv arrays",
+        example: r#"[["kitty"], ["xterm", "-e"]]"#,
+        doc: "Commands tried in turn when exec cannot be run or exits with an error right away (default: the installed terminals); when none starts the session runs headless. [] goes headless directly.",
+    },
+    Key {
+        name: "on_terminal_exit",
+        scope: Scope::Any,
+        value: "submit | cancel | ask",
+        example: r#""ask""#,
+        doc: "What closing the terminal means. ask prompts on porttyd's controlling TTY, or reopens the terminal and submits when it is closed again within exit_confirm_timeout.",
+    },
+    Key {
+        name: "exit_confirm_timeout",
+        scope: Scope::Any,
+        value: "seconds (default 5)",
+        example: "5",
+        doc: "Window for answering the exit prompt, or for confirming a submit by closing the terminal twice (ask).",
+    },
+    Key {
+        name: "attention",
+        scope: Scope::Any,
+        value: "none | bell | flash | notify",
+        example: r#""bell""#,
+        doc: "Cue the session terminal when a dialog waits for input or portty verify fails.",
+    },
+    Key {
+        name: "attention_delay",
+        scope: Scope::Any,
+        value: "seconds (default 30)",
+        example: "30",
+        doc: "How long a dialog waits for input before the attention cue.",
+    },
+    Key {
+        name: "stale_after",
+        scope: Scope::Any,
+        value: "minutes (default 0, off)",
+        example: "15",
+        doc: "Log a warning, and run on_stale, when a dialog has been open this long. Finds dialogs forgotten on another workspace that block their app.",
+    },
+    Key {
+        name: "on_stale",
+        scope: Scope::Any,
+        value: "command",
+        example: r#"["notify-send", "portty: a dialog is waiting"]"#,
+        doc: "Command run once when a dialog reaches stale_after, with PORTTY_SESSION, PORTTY_PORTAL, PORTTY_OPERATION, PORTTY_APP_ID, and PORTTY_WAITING (seconds) set.",
+    },
+    Key {
+        name: "headless",
+        scope: Scope::Any,
+        value: "wait | announce",
+        example: r#""announce""#,
+        doc: "What a session without a terminal does besides waiting for the portty CLI. announce prints it, with the command joining it, on your login terminals (utmp) that accept messages.",
+    },
+    Key {
+        name: "headless_timeout",
+        scope: Scope::Any,
+        value: "seconds (default 0, off)",
+        example: "60",
+        doc: "Fail the request, with an error telling the app why, when nobody changes the selection of a session without a terminal for this long. For unattended systems, where nobody would answer.",
+    },
+    Key {
+        name: "standby",
+        scope: Scope::Any,
+        value: "bool",
+        example: "true",
+        doc: "Keep an idle session running exec, adopted by the next request to hide terminal startup.",
+    },
+    Key {
+        name: "picker",
+        scope: Scope::Any,
+        value: "auto | shell | menu",
+        example: r#""menu""#,
+        doc: "What file chooser sessions run in inline and tabs mode. menu runs portty pick, numbered listings with plain prompts for serial consoles and screen readers; auto (default) does so when porttyd's TERM is dumb, else runs inline_exec.",
+    },
+    Key {
+        name: "inline_exec",
+        scope: Scope::Any,
+        value: "program or argv array",
+        example: r#"["yazi"]"#,
+        doc: "Command run on the daemon's terminal with porttyd --inline, or in the tmux window in tabs mode (default $SHELL).",
+    },
+    Key {
+        name: "allow_empty",
+        scope: Scope::Any,
+        value: "bool (default false)",
+        example: "true",
+        doc: "Return an empty submission as success with zero results instead of cancelling.",
+    },
+    Key {
+        name: "queue_policy",
+        scope: Scope::Any,
+        value: "queued-first | interactive-first | merge",
+        example: r#""merge""#,
+        doc: "How a queued submission competes with the terminal when a dialog opens.",
+    },
+    Key {
+        name: "queue_conflict",
+        scope: Scope::Any,
+        value: "ask | merge | session | queue",
+        example: r#""merge""#,
+        doc: "What a submit does when entries were queued outside the session while it has a selection of its own: refuse until `submit --prefer` chooses (ask), add them, or submit either side alone. `portty diff-queue` shows the difference.",
+    },
+    Key {
+        name: "base_dir",
+        scope: Scope::Root,
+        value: "path (default: $TMPDIR/portty/UID)",
+        example: r#""~/.local/state/portty-run""#,
+        doc: "Directory for the daemon socket, sessions, and queues, for systems where /tmp is noexec or too small. PORTTY_BASE_DIR overrides it; porttyd reads it at startup.",
+    },
+    Key {
+        name: "listen",
+        scope: Scope::Root,
+        value: "list of paths and @NAME abstract sockets",
+        example: r#"["@portty", "/run/user/1000/portty.sock"]"#,
+        doc: "More addresses for the daemon socket, for containers that share the runtime dir or the network namespace but not /tmp. The CLI tries PORTTY_SOCKET, daemon.sock, then these, in order. porttyd reads it at startup.",
+    },
+    Key {
+        name: "cli",
+        scope: Scope::Root,
+        value: "path (default: portty next to porttyd, else in $PATH)",
+        example: r#""/usr/bin/portty""#,
+        doc: "The portty CLI that shims and session commands such as `portty pick` run, by absolute path so they work without it in PATH. porttyd reads it at startup.",
+    },
+    Key {
+        name: "max_message_size",
+        scope: Scope::Root,
+        value: "bytes (default 65536)",
+        example: "65536",
+        doc: "Largest accepted control socket or FIFO message.",
+    },
+    Key {
+        name: "max_entry_length",
+        scope: Scope::Root,
+        value: "bytes (default 4096)",
+        example: "4096",
+        doc: "Longest accepted submission entry, session id, or portal name.",
+    },
+    Key {
+        name: "session_quota",
+        scope: Scope::Root,
+        value: "bytes or K/M/G suffix",
+        example: r#""64M""#,
+        doc: "Advisory disk usage budget per session, directory plus payloads, checked by portty payload-dir and remote fetches.",
+    },
+    Key {
+        name: "total_quota",
+        scope: Scope::Root,
+        value: "bytes or K/M/G suffix",
+        example: r#""1G""#,
+        doc: "Advisory disk usage budget for all sessions, queues, and cached payloads. New dialogs are refused while it is exceeded.",
+    },
+    Key {
+        name: "payload_threshold",
+        scope: Scope::Root,
+        value: "bytes or K/M/G suffix",
+        example: r#""16M""#,
+        doc: "Payloads above this size go to $XDG_CACHE_HOME/portty instead of /tmp.",
+    },
+    Key {
+        name: "debug.keep_session",
+        scope: Scope::Root,
+        value: "bool",
+        example: "true",
+        doc: "Keep the session terminal open when the app closes the request or a submission is refused, with a why shim printing the event log.",
+    },
+    Key {
+        name: "tabs.enabled",
+        scope: Scope::Root,
+        value: "bool",
+        example: "true",
+        doc: "Run concurrent sessions as windows of one tmux session instead of a terminal each.",
+    },
+    Key {
+        name: "tabs.terminal",
+        scope: Scope::Root,
+        value: "program or argv array",
+        example: r#"["foot"]"#,
+        doc: "Terminal opened to show the tmux session, with the attach command appended (default: the session's exec).",
+    },
+    Key {
+        name: "rewrite",
+        scope: Scope::Any,
+        value: "array of {match, replace, app_id} tables",
+        example: r#"[{ match = "^file:///mnt/nas/", replace = "file:///home/me/nas/" }]"#,
+        doc: "Regex rules rewriting result URIs; operation rules are tried first, then portal, then root, and the first match wins.",
+    },
+    Key {
+        name: "export_documents",
+        scope: Scope::Any,
+        value: "bool (default false)",
+        example: "true",
+        doc: "Export results a sandboxed app cannot see through the document portal.",
+    },
+    Key {
+        name: "record_recent",
+        scope: Scope::Any,
+        value: "bool (default true)",
+        example: "false",
+        doc: "Add file chooser results to $XDG_DATA_HOME/recently-used.xbel, listed by portty recent.",
+    },
+    Key {
+        name: "audit",
+        scope: Scope::Any,
+        value: "table of deny, confirm, log, allow glob lists",
+        example: r#"{ deny = ["~/.ssh/id_*"], confirm = ["~/.ssh", "~/.aws"], allow = ["~/.ssh/*.pub"] }"#,
+        doc: "Flag results touching sensitive paths: deny refuses, confirm requires portty submit --confirm, log only logs, allow exempts.",
+    },
+    Key {
+        name: "defaults",
+        scope: Scope::Any,
+        value: "array of entries",
+        example: r#"["~/Downloads/"]"#,
+        doc: "Entries pre-selected when the request brings none; [] disables inherited defaults.",
+    },
+    Key {
+        name: "default_filters",
+        scope: Scope::Any,
+        value: "array of \"NAME=PATTERN[,PATTERN...]\" (patterns with a / are MIME types)",
+        example: r#"["Images=*.png,*.jpg;image/webp"]"#,
+        doc: "File chooser filters offered when the request brings none; [] disables inherited ones.",
+    },
+    Key {
+        name: "remote",
+        scope: Scope::Any,
+        value: "scp | rsync",
+        example: r#""scp""#,
+        doc: "Accept scp-style host:path entries and stage them into the session's payload directory (file chooser).",
+    },
+    Key {
+        name: "restrict_to",
+        scope: Scope::Any,
+        value: "path",
+        example: r#""~/public""#,
+        doc: "Only accept file chooser results inside this folder (symlinks resolved); sessions start there, and other paths are refused when selected and when submitted.",
+    },
+    Key {
+        name: "bin",
+        scope: Scope::Any,
+        value: "table of name = command",
+        example: r#"{ ll = "ls -l" }"#,
+        doc: "Custom shims added to the session's PATH alongside the builtins.",
+    },
+    Key {
+        name: "post_process",
+        scope: Scope::Any,
+        value: "program or argv array",
+        example: r#"["swappy", "-f", "{}", "-o", "{}"]"#,
+        doc: "Command run on the result file before its URI is returned (screenshot); {} is the file path, a nonzero exit cancels.",
+    },
+    Key {
+        name: "series_dir",
+        scope: Scope::Any,
+        value: "path",
+        example: r#""~/Pictures/series""#,
+        doc: "Lets interactive screenshot sessions submit a series (shot --series N): the first capture is returned to the app, the rest are moved into this folder.",
+    },
+    Key {
+        name: "validator",
+        scope: Scope::Any,
+        value: "program or argv array",
+        example: r#""/etc/portty/policy""#,
+        doc: "Command given each submission as JSON on stdin after the built-in checks; a nonzero exit refuses it (stderr is the reason), result lines on stdout replace it.",
+    },
+    Key {
+        name: "umask",
+        scope: Scope::Any,
+        value: "octal integer or string (default: porttyd's umask)",
+        example: r#""077""#,
+        doc: "Umask of the terminal, post_process, and other commands a session runs, so files they save get the modes you expect. porttyd's own session and queue files are always owner-only.",
+    },
+    Key {
+        name: "nice",
+        scope: Scope::Any,
+        value: "0 to 19",
+        example: "10",
+        doc: "Niceness added to the terminal, post_process, and other commands a session runs, so a heavy preview or capture does not starve the app that asked.",
+    },
+    Key {
+        name: "ionice",
+        scope: Scope::Any,
+        value: "idle | best-effort | level 0 to 7",
+        example: r#""idle""#,
+        doc: "I/O scheduling class of the commands a session runs; idle only gets the disk when nothing else wants it.",
+    },
+    Key {
+        name: "systemd_scope",
+        scope: Scope::Any,
+        value: "table of cpu_weight, io_weight (1 to 10000), memory_high (bytes or K/M/G suffix)",
+        example: r#"{ cpu_weight = 20, io_weight = 20, memory_high = "2G" }"#,
+        doc: "Run the commands of a session in a transient systemd user scope (systemd-run --user --scope) with these cgroup limits.",
+    },
+    Key {
+        name: "inherit_env",
+        scope: Scope::Any,
+        value: "table of allow, deny lists of variable names (* matches any run)",
+        example: r#"{ allow = ["HOME", "LANG", "LC_*", "TERM", "DISPLAY", "WAYLAND_DISPLAY", "XDG_*"] }"#,
+        doc: "Environment variables of porttyd the terminal and other commands of a session inherit: allow keeps only those named, deny drops those named. PATH and PORTTY_* are always set.",
+    },
+];
+
+/// Column the comments of the generated config file wrap at
+const WIDTH: usize = 78;
+
+/// A config file with every key commented out under its documentation, as
+/// `portty config init` writes it. Uncommenting a key line sets it to the
+/// example value.
+///
+/// `exec` is set when given: without a file porttyd detects the terminal,
+/// but a file without `exec` runs sessions headless.
+pub fn default_file(exec: Option<&[String]>) -> String {
+    let mut out = String::new();
+    comment(
+        &mut out,
+        "portty configuration, read by porttyd at startup and on `portty daemon reload`.",
+    );
+    out.push_str("#\n");
+    comment(
+        &mut out,
+        "Every key is optional and commented out; uncomment a line to set it to the \
+         example value. Keys apply at the root, in a portal table ([file-chooser]), or \
+         in an operation table ([file-chooser.save-file]), and the most specific level \
+         wins. `portty config check` validates the file; portty-config(5) has the full \
+         reference.",
+    );
+
+    let (tables, keys): (Vec<&Key>, Vec<&Key>) =
+        KEYS.iter().partition(|key| key.name.contains('.'));
+    for key in keys {
+        render(&mut out, key, key.name);
+        if key.name == "exec"
+            && let Some(exec) = exec
+        {
+            let argv = exec.iter().map(|arg| toml::Value::from(arg.as_str()));
+            out.push_str(&format!("exec = {}\n", toml::Value::Array(argv.collect())));
+        }
+    }
+    let mut current = "";
+    for key in tables {
+        let (table, name) = key.name.split_once('.').unwrap_or_default();
+        if table != current {
+            out.push_str(&format!("\n#[{table}]\n"));
+            current = table;
+        }
+        render(&mut out, key, name);
+    }
+
+    out.push('\n');
+    comment(
+        &mut out,
+        "Portal and operation tables take every key not marked root level only:",
+    );
+    out.push_str("#[file-chooser]\n#exec = [\"kitty\", \"--title\", \"Choose Files\"]\n");
+    out.push_str("#\n#[file-chooser.save-file]\n#allow_empty = true\n");
+    out
+}
+
+fn render(out: &mut String, key: &Key, name: &str) {
+    out.push('\n');
+    comment(out, key.doc);
+    let scope = match key.scope {
+        Scope::Any => "",
+        Scope::Root => "; root level only",
+    };
+    comment(out, &format!("Value: {}{scope}", key.value));
+    out.push_str(&format!("#{name} = {}\n", key.example));
+}
+
+/// `text` as `#` comment lines wrapped at [`WIDTH`]
+fn comment(out: &mut String, text: &str) {
+    let mut line = String::from("#");
+    for word in text.split_whitespace() {
+        if line.len() > 1 && line.len() + 1 + word.len() > WIDTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.truncate(1);
+        }
+        line.push(' ');
+        line.push_str(word);
+    }
+    out.push_str(&line);
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::check::field_names;
+    use super::super::{BaseConfig, Config, DebugConfig, RootConfig, TabsConfig};
+    use super::*;
+
+    fn documented(name: &str, scope: Scope) -> bool {
+        KEYS.iter()
+            .any(|key| key.name == name && key.scope == scope)
+    }
+
+    #[test]
+    fn every_field_is_documented() {
+        for name in field_names::<BaseConfig>() {
+            assert!(documented(name, Scope::Any), "{name} is not documented");
+        }
+        for name in field_names::<RootConfig>() {
+            let table = match *name {
+                "debug" => field_names::<DebugConfig>(),
+                "tabs" => field_names::<TabsConfig>(),
+                _ => {
+                    assert!(documented(name, Scope::Root), "{name} is not documented");
+                    continue;
+                }
+            };
+            for field in table {
+                let key = format!("{name}.{field}");
+                assert!(documented(&key, Scope::Root), "{key} is not documented");
+            }
+        }
+    }
+
+    #[test]
+    fn default_file_examples_are_valid() {
+        let file = default_file(None);
+        for key in KEYS {
+            let name = key.name.rsplit('.').next().unwrap_or_default();
+            assert!(
+                file.contains(&format!("\n#{name} = {}\n", key.example)),
+                "{} is missing from the default file",
+                key.name
+            );
+        }
+
+        // Commented out, it is an empty config
+        let (_, diagnostics) = Config::parse(&file).unwrap();
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+
+        // Uncommented, every example parses (programs may be missing here)
+        let uncommented: String = file
+            .lines()
+            .map(|line| match line.strip_prefix('#') {
+                Some(rest) if !rest.is_empty() && !rest.starts_with(' ') => rest,
+                _ => line,
+            })
+            .flat_map(|line| [line, "\n"])
+            .collect();
+        if let Err(diagnostics) = Config::parse(&uncommented) {
+            panic!("{diagnostics:?}");
+        }
+    }
+
+    #[test]
+    fn default_file_keeps_exec() {
+        let exec = vec!["foot".to_string(), "--title".into(), "a \"b\"".into()];
+        let (config, _) = Config::parse(&default_file(Some(&exec))).unwrap();
+        assert_eq!(config.resolve_exec("screenshot", "screenshot"), Some(exec));
+    }
+
+    #[test]
+    fn every_key_is_a_field() {
+        let base = field_names::<BaseConfig>();
+        let root = field_names::<RootConfig>();
+        for key in KEYS {
+            let field = key.name.split('.').next().unwrap_or_default();
+            let fields = match key.scope {
+                Scope::Any => base,
+                Scope::Root => root,
+            };
+            assert!(fields.contains(&field), "{} is not a config key", key.name);
+        }
+    }
+}
@@ -1,6 +1,36 @@
-use std::fs;
-use std::io::Write;
-use std::path::Path;
+//! Line files of session and queue state.
+//!
+//! Everything here is created owner-only (0o600): session state lives in the
+//! user's runtime dir, but the process writing it (a `portty` run from the
+//! user's shell) may have any umask.
+//!
+//! Files are replaced atomically ([`replace_with`]): a crash or power cut
+//! leaves the old contents or the new, never a half-written file the daemon
+//! would later fail to parse. [`copy_atomic`] copies a user's file the same
+//! way, keeping its metadata instead of making it owner-only.
+//!
+//! Names of the files portty writes for the user (renamed saves, captures)
+//! are built from bytes ([`with_stem_suffix`], [`path_args`]), so a path that
+//! is not UTF-8 stays the path the user chose.
+
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, FileTimes};
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::uri;
+
+/// Mode of files created for session and queue state
+pub const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Mode of directories created for session and queue state
+pub const PRIVATE_DIR_MODE: u32 = 0o700;
 
 /// Read non-empty lines from a file. Returns an empty vec on any error.
 pub fn read_lines(path: &Path) -> Vec<String> {
@@ -12,13 +42,189 @@ pub fn read_lines(path: &Path) -> Vec<String> {
         .collect()
 }
 
+/// Replace the contents of a file atomically, leaving it owner-only (see
+/// [`replace_with`])
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    replace_with(path, |file| file.write_all(contents.as_ref()))
+}
+
+/// Replace `path` with an owner-only file written by `fill`.
+///
+/// `fill` writes a temporary file next to `path`, which is synced to disk
+/// and renamed over `path`; the directory is synced last, so the rename
+/// survives a power cut too. If anything fails, `path` keeps its old
+/// contents and the temporary file is removed.
+pub fn replace_with(
+    path: &Path,
+    fill: impl FnOnce(&mut fs::File) -> io::Result<()>,
+) -> io::Result<()> {
+    replace_in(path, PRIVATE_FILE_MODE, fill)
+}
+
+/// Copy `from` to `to` atomically, keeping its permissions, timestamps, and
+/// extended attributes.
+///
+/// `to` is replaced as in [`replace_with`]: it is the old file or a full
+/// copy, never part of one. Attributes the target file system does not
+/// support, or this process may not set (`security.*` ones), are left out.
+pub fn copy_atomic(from: &Path, to: &Path) -> io::Result<()> {
+    let mut source = fs::File::open(from)?;
+    let meta = source.metadata()?;
+    replace_in(to, meta.permissions().mode() & 0o7777, |file| {
+        io::copy(&mut source, file)?;
+        file.set_permissions(meta.permissions())?;
+        copy_xattrs(source.as_raw_fd(), file.as_raw_fd())?;
+        file.set_times(
+            FileTimes::new()
+                .set_accessed(meta.accessed()?)
+                .set_modified(meta.modified()?),
+        )
+    })
+}
+
+/// Copy the extended attributes of file `from` to file `to`
+fn copy_xattrs(from: RawFd, to: RawFd) -> io::Result<()> {
+    let names = xattr_read(|buf, len| {
+        // SAFETY: `buf` holds `len` writable bytes (or is null with `len` 0)
+        unsafe { libc::flistxattr(from, buf.cast(), len) }
+    });
+    let names = match names {
+        Ok(names) => names,
+        Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let name = CString::new(name).expect("split at NUL");
+        let value = xattr_read(|buf, len| {
+            // SAFETY: `name` is a C string; `buf` holds `len` writable bytes
+            unsafe { libc::fgetxattr(from, name.as_ptr(), buf.cast(), len) }
+        })?;
+        // SAFETY: `name` is a C string and `value` is readable for its length
+        let set =
+            unsafe { libc::fsetxattr(to, name.as_ptr(), value.as_ptr().cast(), value.len(), 0) };
+        if set < 0 {
+            let e = io::Error::last_os_error();
+            if !matches!(e.raw_os_error(), Some(libc::ENOTSUP | libc::EPERM)) {
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read a list or value of extended attributes with `call`, which fills a
+/// buffer like `flistxattr` does. The size is asked first and asked again if
+/// it grew meanwhile.
+fn xattr_read(call: impl Fn(*mut u8, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let len = call(std::ptr::null_mut(), 0);
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; len as usize];
+        let read = call(buf.as_mut_ptr(), buf.len());
+        if read >= 0 {
+            buf.truncate(read as usize);
+            return Ok(buf);
+        }
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(libc::ERANGE) {
+            return Err(e);
+        }
+    }
+}
+
+/// [`replace_with`], creating the file with `mode`
+fn replace_in(
+    path: &Path,
+    mode: u32,
+    fill: impl FnOnce(&mut fs::File) -> io::Result<()>,
+) -> io::Result<()> {
+    // Unique per write, as the daemon's threads write concurrently
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} does not name a file", path.display()),
+        )
+    })?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let tmp = dir.join(format!(
+        ".{}.{}-{}.tmp",
+        name.to_string_lossy(),
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(&tmp)
+        .and_then(|mut file| {
+            fill(&mut file)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    fs::File::open(dir)?.sync_all()
+}
+
+/// `path` with `suffix` after its file stem (`a.txt` to `a (2).txt`)
+pub fn with_stem_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().as_bytes().to_vec();
+    name.extend_from_slice(suffix.as_bytes());
+    if let Some(ext) = path.extension() {
+        name.push(b'.');
+        name.extend_from_slice(ext.as_bytes());
+    }
+    path.with_file_name(OsStr::from_bytes(&name))
+}
+
+/// Arguments of a command run on `path`: `args` with `{}` replaced by it, or
+/// with it appended when none has a placeholder
+pub fn path_args(args: &[String], path: &Path) -> Vec<OsString> {
+    let mut out: Vec<OsString> = args
+        .iter()
+        .map(|arg| {
+            let mut replaced = OsString::new();
+            for (i, part) in arg.split("{}").enumerate() {
+                if i > 0 {
+                    replaced.push(path);
+                }
+                replaced.push(part);
+            }
+            replaced
+        })
+        .collect();
+    if !args.iter().any(|arg| arg.contains("{}")) {
+        out.push(path.into());
+    }
+    out
+}
+
+/// Create a directory and its missing parents owner-only
+pub fn create_private_dir_all(path: &Path) -> std::io::Result<()> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(PRIVATE_DIR_MODE)
+        .create(path)
+}
+
 /// Write lines to a file (one per line). Empty slice writes an empty file.
 pub fn write_lines(path: &Path, lines: &[String]) -> std::io::Result<()> {
     if lines.is_empty() {
-        fs::write(path, "")
+        write_atomic(path, "")
     } else {
         let content = format!("{}\n", lines.join("\n"));
-        fs::write(path, content)
+        write_atomic(path, content)
     }
 }
 
@@ -27,6 +233,7 @@ pub fn append_lines(path: &Path, lines: &[String]) -> std::io::Result<()> {
     let mut file = fs::OpenOptions::new()
         .create(true)
         .append(true)
+        .mode(PRIVATE_FILE_MODE)
         .open(path)?;
     for line in lines {
         writeln!(file, "{}", line)?;
@@ -34,12 +241,141 @@ pub fn append_lines(path: &Path, lines: &[String]) -> std::io::Result<()> {
     Ok(())
 }
 
-/// Remove specific lines from a file.
+/// Remove specific lines from a file. A line naming the same file as one of
+/// `to_remove` in another spelling (path or `file://` URI) is removed too,
+/// see [`uri::key`].
 pub fn remove_lines(path: &Path, to_remove: &[String]) -> std::io::Result<()> {
     let existing = read_lines(path);
+    let to_remove: HashSet<_> = to_remove.iter().map(|e| uri::key(e)).collect();
     let remaining: Vec<String> = existing
         .into_iter()
-        .filter(|e| !to_remove.contains(e))
+        .filter(|e| !to_remove.contains(&uri::key(e)))
         .collect();
     write_lines(path, &remaining)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Files in `dir` other than `keep`
+    fn leftovers(dir: &Path, keep: &str) -> Vec<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name != keep)
+            .collect()
+    }
+
+    #[test]
+    fn names_keep_bytes_that_are_not_utf8() {
+        let path = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9.tar.gz"));
+        assert_eq!(
+            with_stem_suffix(path, " (2)").as_os_str().as_bytes(),
+            b"/tmp/caf\xe9.tar (2).gz"
+        );
+        assert_eq!(
+            with_stem_suffix(Path::new("/tmp/.bashrc"), "-1"),
+            Path::new("/tmp/.bashrc-1")
+        );
+
+        let args = ["-f".to_string(), "{}".to_string(), "-o={}".to_string()];
+        let args = path_args(&args, path);
+        assert_eq!(args[1].as_bytes(), b"/tmp/caf\xe9.tar.gz");
+        assert_eq!(args[2].as_bytes(), b"-o=/tmp/caf\xe9.tar.gz");
+        let appended = path_args(&["-f".to_string()], path);
+        assert_eq!(appended, [OsStr::new("-f"), path.as_os_str()]);
+    }
+
+    #[test]
+    fn atomic_copy_keeps_metadata() {
+        use std::os::unix::fs::MetadataExt;
+        use std::time::{Duration, SystemTime};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let (from, to) = (tmp.path().join("shot.png"), tmp.path().join("kept.png"));
+        fs::write(&from, "pixels").unwrap();
+        fs::write(&to, "old").unwrap();
+        fs::set_permissions(&from, fs::Permissions::from_mode(0o640)).unwrap();
+        let then = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&from)
+            .unwrap()
+            .set_times(FileTimes::new().set_accessed(then).set_modified(then))
+            .unwrap();
+        let name = CString::new("user.portty.test").unwrap();
+        let path = CString::new(from.as_os_str().as_bytes()).unwrap();
+        // SAFETY: both are C strings and the value is 3 readable bytes
+        let xattrs =
+            unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), b"yes".as_ptr().cast(), 3, 0) }
+                == 0;
+
+        copy_atomic(&from, &to).unwrap();
+        // Before reading it, which may touch the access time
+        let meta = fs::metadata(&to).unwrap();
+        assert_eq!(meta.mode() & 0o777, 0o640);
+        assert_eq!(meta.modified().unwrap(), then);
+        assert_eq!(meta.accessed().unwrap(), then);
+        assert_eq!(fs::read_to_string(&to).unwrap(), "pixels");
+        if xattrs {
+            let file = fs::File::open(&to).unwrap();
+            let value = xattr_read(|buf, len| {
+                // SAFETY: as in `copy_xattrs`
+                unsafe { libc::fgetxattr(file.as_raw_fd(), name.as_ptr(), buf.cast(), len) }
+            })
+            .unwrap();
+            assert_eq!(value, b"yes");
+        }
+        // Only the source and the copy: no temporary file is left
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 2);
+
+        assert!(copy_atomic(&tmp.path().join("missing"), &to).is_err());
+        assert_eq!(fs::read_to_string(&to).unwrap(), "pixels");
+    }
+
+    #[test]
+    fn atomic_write_replaces_contents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("intent.json");
+        write_atomic(&path, "old").unwrap();
+        write_atomic(&path, "new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, PRIVATE_FILE_MODE);
+        assert!(leftovers(tmp.path(), "intent.json").is_empty());
+    }
+
+    #[test]
+    fn failed_write_keeps_old_contents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("options.json");
+        write_atomic(&path, r#"{"title": "old"}"#).unwrap();
+
+        // Fails part way, as a full disk or a crash would
+        let err = replace_with(&path, |file| {
+            file.write_all(br#"{"title": "#)?;
+            Err(io::Error::new(io::ErrorKind::StorageFull, "injected"))
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), r#"{"title": "old"}"#);
+        assert!(leftovers(tmp.path(), "options.json").is_empty());
+    }
+
+    #[test]
+    fn failed_rename_removes_temporary_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        // A directory cannot be replaced by a file
+        let path = tmp.path().join("submission");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("entry"), "").unwrap();
+
+        assert!(write_lines(&path, &["/a".to_string()]).is_err());
+        assert!(path.join("entry").exists());
+        assert!(leftovers(tmp.path(), "submission").is_empty());
+    }
+}
@@ -1,9 +1,22 @@
-pub mod client;
-pub mod codec;
+pub mod build_info;
+#[cfg(feature = "config")]
+pub mod config;
 pub mod files;
-pub mod paths;
+pub mod locale;
 #[cfg(feature = "portal")]
 pub mod portal;
-pub mod protocol;
+pub mod quota;
+pub mod remote;
+#[cfg(feature = "portal")]
+pub mod session_dir;
+
+#[cfg(feature = "jsonrpc")]
+pub use portty_client::jsonrpc;
+pub use portty_client::{client, codec, exit, input, operation, paths, protocol, socket, uri};
 
-pub use protocol::{Request, Response, SessionInfo};
+pub use exit::ExitStatus;
+pub use operation::{Operation, Portal};
+pub use protocol::{
+    DaemonStatus, Decision, PermissionInfo, QueueConflict, QueuePolicy, Request, RequestInfo,
+    RequestState, Response, SessionInfo, SessionMode, SortOrder, SubmitOptions,
+};
@@ -0,0 +1,125 @@
+//! The user's locale, from `LC_ALL`, `LC_COLLATE` / `LC_CTYPE`, and `LANG`
+//!
+//! File names sort by the collation rules of the locale when libportty is
+//! built with the `collation` feature (ICU), so `Ärger.txt` sorts next to
+//! `Anfang.txt` in German instead of after `Zeitung.txt`. Without the
+//! feature, and in the C locale, names sort by code point.
+//!
+//! Listings replace non-ASCII characters when the locale's character set is
+//! not UTF-8 (`LANG=C`, `de_DE.ISO-8859-1`), as `ls` does.
+
+use std::cmp::Ordering;
+
+/// The locale set for `category`: `LC_ALL`, then the category, then `LANG`,
+/// skipping empty ones
+fn locale_var(category: &str) -> Option<String> {
+    ["LC_ALL", category, "LANG"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// `language_TERRITORY` of a locale name like `de_DE.UTF-8@euro`
+fn base_name(locale: &str) -> &str {
+    locale.split(['.', '@']).next().unwrap_or_default()
+}
+
+fn is_posix(locale: &str) -> bool {
+    matches!(base_name(locale), "C" | "POSIX")
+}
+
+/// Whether text written in `locale` can be UTF-8: a UTF-8 codeset, or no
+/// codeset outside the C locale
+fn is_utf8(locale: &str) -> bool {
+    let codeset = locale
+        .split_once('.')
+        .map(|(_, rest)| rest.split('@').next().unwrap_or_default());
+    match codeset {
+        Some(codeset) => matches!(codeset.to_ascii_lowercase().as_str(), "utf-8" | "utf8"),
+        None => !is_posix(locale),
+    }
+}
+
+/// Whether non-ASCII names can be shown as they are. True when no locale is
+/// set, so a bare environment does not lose them.
+pub fn utf8_output() -> bool {
+    locale_var("LC_CTYPE").is_none_or(|locale| is_utf8(&locale))
+}
+
+/// Orders file names for display and `--sort name`
+pub struct Collation {
+    #[cfg(feature = "collation")]
+    collator: Option<icu_collator::Collator>,
+}
+
+impl Collation {
+    /// The collation of `LC_COLLATE`. Locales ICU does not know fall back to
+    /// its root collation; the C locale keeps code point order.
+    pub fn from_env() -> Self {
+        let locale = locale_var("LC_COLLATE").filter(|locale| !is_posix(locale));
+        Self::for_locale(locale.as_deref())
+    }
+
+    #[cfg(feature = "collation")]
+    fn for_locale(locale: Option<&str>) -> Self {
+        let collator = locale.and_then(|locale| {
+            let tag: icu_locid::Locale = base_name(locale).replace('_', "-").parse().ok()?;
+            icu_collator::Collator::try_new(&(&tag).into(), Default::default()).ok()
+        });
+        Self { collator }
+    }
+
+    #[cfg(not(feature = "collation"))]
+    fn for_locale(_locale: Option<&str>) -> Self {
+        Self {}
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        #[cfg(feature = "collation")]
+        if let Some(collator) = &self.collator {
+            // Names equal to the collator keep a stable order
+            return collator.compare(a, b).then_with(|| a.cmp(b));
+        }
+        a.cmp(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_names() {
+        assert_eq!(base_name("de_DE.UTF-8@euro"), "de_DE");
+        assert!(is_posix("C"));
+        assert!(is_posix("C.UTF-8"));
+        assert!(!is_posix("en_US.UTF-8"));
+
+        assert!(is_utf8("en_US.UTF-8"));
+        assert!(is_utf8("C.utf8"));
+        assert!(is_utf8("sv_SE"));
+        assert!(!is_utf8("C"));
+        assert!(!is_utf8("POSIX"));
+        assert!(!is_utf8("de_DE.ISO-8859-1@euro"));
+    }
+
+    #[test]
+    fn code_point_order_in_c_locale() {
+        let collation = Collation::for_locale(None);
+        assert_eq!(collation.compare("Zeitung", "Ärger"), Ordering::Less);
+        assert_eq!(collation.compare("b", "a"), Ordering::Greater);
+    }
+
+    #[cfg(feature = "collation")]
+    #[test]
+    fn locale_order_with_icu() {
+        let collation = Collation::for_locale(Some("de_DE.UTF-8"));
+        assert_eq!(collation.compare("Ärger", "Zeitung"), Ordering::Less);
+        assert_eq!(collation.compare("Ärger", "Anfang"), Ordering::Greater);
+        assert_eq!(collation.compare("apfel", "Birne"), Ordering::Less);
+
+        // Swedish sorts Ä after Z
+        let collation = Collation::for_locale(Some("sv_SE.UTF-8"));
+        assert_eq!(collation.compare("Ärlig", "Zebra"), Ordering::Greater);
+    }
+}
@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Operation, files};
+
+use super::AddResult;
+
+pub use super::APPROVE;
+
+/// Launcher type bits from the DynamicLauncher portal (`launcher_type` /
+/// `SupportedLauncherTypes`)
+pub const APPLICATION: u32 = 1;
+pub const WEBAPP: u32 = 2;
+
+/// Session options for dynamic launcher portal
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionOptions {
+    pub app_id: String,
+    /// Parent window identifier, e.g. `wayland:<handle>` or `x11:<xid>` (may be empty)
+    #[serde(default)]
+    pub parent_window: String,
+    /// Launcher name proposed by the app (empty for `request-install-token`)
+    #[serde(default)]
+    pub name: String,
+    /// `application` or `webapp`
+    #[serde(default)]
+    pub launcher_type: String,
+    /// URL a web app launcher opens
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Whether the user may change the name
+    #[serde(default)]
+    pub editable_name: bool,
+    /// Copy of the launcher icon for review, if the app sent image bytes
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+/// Name of a `launcher_type` value
+pub fn launcher_type_name(launcher_type: u32) -> &'static str {
+    if launcher_type == WEBAPP {
+        "webapp"
+    } else {
+        "application"
+    }
+}
+
+/// Validate and transform dynamic launcher submission.
+///
+/// For prepare-install: a single non-empty launcher name, which must match the
+/// proposed one unless the name is editable.
+/// For request-install-token: a single `approve` entry.
+pub fn validate(
+    operation: Operation,
+    entries: &[String],
+    options: &SessionOptions,
+) -> Result<Vec<String>, String> {
+    let [entry] = entries else {
+        return Err(match entries.len() {
+            0 => "No entries in submission".to_string(),
+            n => format!("{operation} expects 1 entry, got {n}"),
+        });
+    };
+
+    match operation {
+        Operation::PrepareInstall => {
+            let name = entry.trim();
+            if name.is_empty() {
+                return Err("launcher name is empty".to_string());
+            }
+            if !options.editable_name && name != options.name {
+                return Err(format!(
+                    "launcher name is not editable (expected '{}')",
+                    options.name
+                ));
+            }
+            Ok(vec![name.to_string()])
+        }
+        Operation::RequestInstallToken if entry == APPROVE => Ok(entries.to_vec()),
+        Operation::RequestInstallToken => Err(format!(
+            "{operation} expects a single '{APPROVE}' entry (cancel to deny)"
+        )),
+        _ => Err(format!(
+            "unsupported dynamic launcher operation: {operation}"
+        )),
+    }
+}
+
+/// Smart add entries: the name or approval always replaces (single entry).
+pub fn add_entries(sub_path: &Path, entries: &[String]) -> std::io::Result<AddResult> {
+    files::write_lines(sub_path, entries)?;
+    Ok(AddResult::Replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(editable_name: bool) -> SessionOptions {
+        SessionOptions {
+            app_id: "org.example.Browser".into(),
+            name: "Example".into(),
+            launcher_type: "webapp".into(),
+            editable_name,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prepare_install_name() {
+        let entries = |name: &str| vec![name.to_string()];
+        assert_eq!(
+            validate(
+                Operation::PrepareInstall,
+                &entries(" Example "),
+                &options(false)
+            ),
+            Ok(entries("Example"))
+        );
+        assert!(
+            validate(
+                Operation::PrepareInstall,
+                &entries("Renamed"),
+                &options(false)
+            )
+            .is_err()
+        );
+        assert_eq!(
+            validate(
+                Operation::PrepareInstall,
+                &entries("Renamed"),
+                &options(true)
+            ),
+            Ok(entries("Renamed"))
+        );
+        assert!(validate(Operation::PrepareInstall, &entries("  "), &options(true)).is_err());
+        assert!(validate(Operation::PrepareInstall, &[], &options(true)).is_err());
+    }
+
+    #[test]
+    fn install_token_requires_explicit_approval() {
+        let opts = SessionOptions::default();
+        assert!(
+            validate(
+                Operation::RequestInstallToken,
+                &[APPROVE.to_string()],
+                &opts
+            )
+            .is_ok()
+        );
+        assert!(validate(Operation::RequestInstallToken, &["yes".to_string()], &opts).is_err());
+    }
+}
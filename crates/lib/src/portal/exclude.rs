@@ -0,0 +1,116 @@
+//! Client-side filtering for `portty select` (`--exclude`, `--exclude-dir`)
+//!
+//! Excludes are file name globs compiled by the same matcher as file chooser
+//! filters ([`FilterMatcher`]): `--exclude` drops files whose name matches,
+//! `--exclude-dir` directories. With `--recursive`, directories are expanded
+//! to the files below them and excluded directories are not entered.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::filter_hints::FilterMatcher;
+
+/// Compiled `--exclude` and `--exclude-dir` globs
+#[derive(Debug)]
+pub struct Excludes {
+    files: FilterMatcher,
+    dirs: FilterMatcher,
+}
+
+impl Excludes {
+    pub fn new(files: &[String], dirs: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            files: FilterMatcher::from_globs(files)?,
+            dirs: FilterMatcher::from_globs(dirs)?,
+        })
+    }
+
+    /// Whether `path` is a file or directory an exclude names
+    pub fn excludes(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name() else {
+            return false;
+        };
+        if path.is_dir() {
+            self.dirs.is_match(name)
+        } else {
+            self.files.is_match(name)
+        }
+    }
+
+    /// `paths` without excluded entries, directories replaced by the files
+    /// below them when `recursive`. Symlinked directories are not followed
+    /// and unreadable ones are skipped.
+    pub fn expand(&self, paths: Vec<PathBuf>, recursive: bool) -> Vec<PathBuf> {
+        let mut out = Vec::new();
+        for path in paths {
+            if self.excludes(&path) {
+                continue;
+            }
+            if recursive && path.is_dir() {
+                self.walk(&path, &mut out);
+            } else {
+                out.push(path);
+            }
+        }
+        out
+    }
+
+    fn walk(&self, dir: &Path, out: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = entry.path();
+            if self.excludes(&path) {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => self.walk(&path, out),
+                Ok(_) => out.push(path),
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excludes_files_and_prunes_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        for dir in ["src/.git", "src/nested"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "a.txt",
+            "b.tmp",
+            "src/main.rs",
+            "src/cache.tmp",
+            "src/.git/HEAD",
+            "src/nested/lib.rs",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let excludes = Excludes::new(&["*.tmp".into()], &[".git".into()]).unwrap();
+        let given = vec![root.join("a.txt"), root.join("b.tmp"), root.join("src")];
+
+        assert_eq!(
+            excludes.expand(given.clone(), false),
+            [root.join("a.txt"), root.join("src")]
+        );
+        assert_eq!(
+            excludes.expand(given, true),
+            [
+                root.join("a.txt"),
+                root.join("src/main.rs"),
+                root.join("src/nested/lib.rs"),
+            ]
+        );
+        assert!(Excludes::new(&["[".into()], &[]).is_err());
+    }
+}
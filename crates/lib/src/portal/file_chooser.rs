@@ -1,9 +1,13 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
 use std::fmt::Display;
 use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use crate::files;
+use crate::locale::Collation;
+use crate::{Operation, SortOrder, files, input, uri};
 
 use super::AddResult;
 use super::intent::{Cardinality, Intent, IntentFamily};
@@ -16,6 +20,21 @@ pub enum SelectionMode {
     SaveMultiple,
 }
 
+impl SelectionMode {
+    /// Whether the session picks folders: save-files, or open-file with
+    /// `directory`
+    pub fn picks_folder(self) -> bool {
+        matches!(
+            self,
+            Self::SaveMultiple
+                | Self::Pick {
+                    directory: true,
+                    ..
+                }
+        )
+    }
+}
+
 impl Default for SelectionMode {
     fn default() -> Self {
         Self::Pick {
@@ -50,58 +69,436 @@ impl Display for SelectionMode {
     }
 }
 
+/// What happens to SaveFiles targets that already exist in the chosen folder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    /// Return them anyway; the app overwrites them
+    #[default]
+    Overwrite,
+    /// Leave them out of the result
+    Skip,
+    /// Save them under a free name with a ` (N)` suffix
+    Rename,
+    /// Refuse the submission until another strategy is chosen
+    Ask,
+}
+
+impl ConflictStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Overwrite => "overwrite",
+            Self::Skip => "skip",
+            Self::Rename => "rename",
+            Self::Ask => "ask",
+        }
+    }
+}
+
+impl Display for ConflictStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ConflictStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            "rename" => Ok(Self::Rename),
+            "ask" => Ok(Self::Ask),
+            _ => Err(format!(
+                "unknown conflict strategy: {s} (expected skip, overwrite, rename, or ask)"
+            )),
+        }
+    }
+}
+
 /// File filter
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Written as `NAME=PATTERN[,PATTERN...]` on command lines and in the
+/// config (`default_filters`): patterns are separated by `,` or `;`, and
+/// those with a `/` are MIME types, others globs. `NAME:PATTERN...` is read
+/// too: `Images=*.png,*.jpg;image/webp`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Filter {
     pub name: String,
     pub patterns: Vec<FilterPattern>,
 }
 
 /// Filter pattern type
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FilterPattern {
     Glob(String),
     MimeType(String),
 }
 
+impl Filter {
+    /// A filter without patterns yet
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            patterns: Vec::new(),
+        }
+    }
+
+    pub fn glob(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(FilterPattern::Glob(pattern.into()));
+        self
+    }
+
+    pub fn mime_type(mut self, mime: impl Into<String>) -> Self {
+        self.patterns.push(FilterPattern::MimeType(mime.into()));
+        self
+    }
+}
+
+impl FilterPattern {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Glob(pattern) | Self::MimeType(pattern) => pattern,
+        }
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}=", self.name)?;
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(pattern.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, patterns) = s
+            .split_once(['=', ':'])
+            .ok_or_else(|| format!("invalid filter {s:?} (expected NAME=PATTERN[,PATTERN...])"))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("invalid filter {s:?}: no name"));
+        }
+        let filter = patterns
+            .split([',', ';'])
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .fold(Self::new(name), |filter, pattern| {
+                if pattern.contains('/') {
+                    filter.mime_type(pattern)
+                } else {
+                    filter.glob(pattern)
+                }
+            });
+        if filter.patterns.is_empty() {
+            return Err(format!("invalid filter {s:?}: no patterns"));
+        }
+        Ok(filter)
+    }
+}
+
 /// Session options for file chooser
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SessionOptions {
     pub title: String,
     pub mode: SelectionMode,
+    /// Folder the dialog starts in; see [`SessionOptions::folder`]
     pub current_folder: Option<String>,
+    /// Names to save as; see [`SessionOptions::candidate_name`]
     pub candidates: Vec<String>,
     pub filters: Vec<Filter>,
     pub current_filter: Option<usize>,
+    /// ID of the requesting application (may be empty)
+    #[serde(default)]
+    pub app_id: String,
+    /// Parent window identifier, e.g. `wayland:<handle>` or `x11:<xid>` (may be empty)
+    #[serde(default)]
+    pub parent_window: String,
+    /// How to spell a target for this operation, for shims to show the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    /// SaveFiles: what to do with candidates that already exist, chosen in
+    /// the session with the `conflicts` shim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflicts: Option<ConflictStrategy>,
+    /// SaveFiles: set once the session places the files one at a time
+    /// (`next-file`) instead of saving them all into one folder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_file: Option<PerFile>,
+    /// Folder the results must lie in (`restrict_to`), without symlinks;
+    /// `None` accepts any path. Written like `current_folder`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restrict_to: Option<String>,
+    /// Combo and check boxes the app adds to the dialog
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<Choice>,
+    /// Values the session picked for `choices`, by choice ID, with the
+    /// `choices` shim; the others keep their defaults
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub chosen: BTreeMap<String, String>,
+}
+
+impl SessionOptions {
+    /// The folder the dialog starts in. A folder that is not UTF-8 is
+    /// stored as its URI (see [`uri::encode_os`]).
+    pub fn folder(&self) -> Option<PathBuf> {
+        self.current_folder
+            .as_deref()
+            .map(|folder| PathBuf::from(uri::decode_os(folder).into_owned()))
+    }
+
+    /// Candidate `name` as the app gave it: names that are not UTF-8 are
+    /// stored escaped (see [`uri::encode_os`])
+    pub fn candidate_name(name: &str) -> Cow<'_, OsStr> {
+        uri::decode_os(name)
+    }
+
+    /// The choice with ID `id`
+    pub fn choice(&self, id: &str) -> Option<&Choice> {
+        self.choices.iter().find(|choice| choice.id == id)
+    }
+
+    /// Pick `value` for the choice `id`
+    pub fn choose(&mut self, id: &str, value: &str) -> Result<(), String> {
+        let choice = self
+            .choice(id)
+            .ok_or_else(|| format!("the dialog has no choice {id:?}"))?;
+        if !choice.accepts(value) {
+            return Err(format!(
+                "{id} is one of {}, not {value:?}",
+                choice.values().join(", ")
+            ));
+        }
+        self.chosen.insert(id.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// The value of each choice, as returned to the app: the one the
+    /// session picked, else the default
+    pub fn choice_values(&self) -> Vec<(String, String)> {
+        self.choices
+            .iter()
+            .map(|choice| {
+                let value = self
+                    .chosen
+                    .get(&choice.id)
+                    .filter(|value| choice.accepts(value))
+                    .map_or_else(|| choice.initial(), String::as_str);
+                (choice.id.clone(), value.to_string())
+            })
+            .collect()
+    }
+}
+
+/// A combo box, or a check box when it has no options, that the app adds to
+/// the dialog
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Choice {
+    pub id: String,
+    pub label: String,
+    /// `(id, label)` of each option
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<(String, String)>,
+    /// Option the dialog starts with (may be empty)
+    #[serde(default)]
+    pub default: String,
+}
+
+impl Choice {
+    /// A check box, whose value is `true` or `false`
+    pub fn is_check_box(&self) -> bool {
+        self.options.is_empty()
+    }
+
+    /// The values the choice takes
+    pub fn values(&self) -> Vec<&str> {
+        if self.is_check_box() {
+            return vec!["true", "false"];
+        }
+        self.options.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    pub fn accepts(&self, value: &str) -> bool {
+        self.values().contains(&value)
+    }
+
+    /// The value the dialog starts with: the default when it is one of the
+    /// values, else the first option (`false` for a check box)
+    pub fn initial(&self) -> &str {
+        if self.accepts(&self.default) {
+            &self.default
+        } else if self.is_check_box() {
+            "false"
+        } else {
+            &self.options[0].0
+        }
+    }
+}
+
+/// Per-file decisions of a save-files session: the submission holds a
+/// `DIR/NAME` target for each placed candidate, and the skipped ones are
+/// listed here
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PerFile {
+    #[serde(default)]
+    pub skipped: Vec<String>,
+}
+
+/// Where a save-files candidate goes when files are placed one at a time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placement {
+    Target(PathBuf),
+    Skipped,
+    Undecided,
+}
+
+/// Usage hint for a session that picks a folder
+pub const FOLDER_HINT: &str = "folder DIR picks DIR; folder alone lists suggestions";
+
+/// Folders to suggest when the session picks one: the current folder, the
+/// XDG user dirs (Documents, Downloads, ...), and home. Only existing ones,
+/// each once.
+pub fn candidate_folders(current_folder: Option<&Path>) -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut folders = Vec::new();
+    for folder in current_folder
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(user_dirs(home.as_deref()))
+        .chain(home.clone())
+    {
+        if folder.is_dir() && !folders.contains(&folder) {
+            folders.push(folder);
+        }
+    }
+    folders
+}
+
+/// Folders named in `$XDG_CONFIG_HOME/user-dirs.dirs`, in file order
+fn user_dirs(home: Option<&Path>) -> Vec<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| home.join(".config")));
+    let Some(content) =
+        config.and_then(|dir| std::fs::read_to_string(dir.join("user-dirs.dirs")).ok())
+    else {
+        return Vec::new();
+    };
+    parse_user_dirs(&content, home)
+}
+
+/// `XDG_*_DIR="$HOME/..."` lines of a user-dirs.dirs file
+fn parse_user_dirs(content: &str, home: Option<&Path>) -> Vec<PathBuf> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| key.trim().starts_with("XDG_") && key.trim().ends_with("_DIR"))
+        .filter_map(|(_, value)| {
+            let value = value.trim().trim_matches('"');
+            match value.strip_prefix("$HOME") {
+                Some(rest) => Some(home?.join(rest.trim_start_matches('/'))),
+                None if value.starts_with('/') => Some(PathBuf::from(value)),
+                None => None,
+            }
+        })
+        .collect()
+}
+
+/// Usage hint for a save-file session with the suggested name `candidate`
+pub fn save_file_hint(candidate: Option<&str>) -> String {
+    match candidate.filter(|name| !name.is_empty()) {
+        Some(name) => {
+            format!("sel DIR/ saves as '{name}' in DIR; sel DIR/NAME saves under a new name")
+        }
+        None => "sel DIR/NAME chooses the file to save".to_string(),
+    }
 }
 
 /// Validate and transform file chooser submission entries into file:// URIs.
 ///
 /// Resolves relative paths against `current_folder` from options.
 /// For save-files with candidates, builds URIs from selected folder + candidate filenames.
+/// With `restrict_to`, refuses results outside that folder.
 pub fn validate(
-    operation: &str,
+    operation: Operation,
+    entries: &[String],
+    options: &SessionOptions,
+) -> Result<Vec<String>, String> {
+    let uris = resolve_submission(operation, entries, options)?;
+    if let Some(root) = &options.restrict_to {
+        for uri in &uris {
+            let path = resolve_path_as_given(uri, None);
+            check_within(&path, Path::new(&uri::decode_os(root)))?;
+        }
+    }
+    Ok(uris)
+}
+
+/// Refuse `path` unless it lies in `root` (a path without symlinks) once
+/// symlinks are resolved, so a link in `root` pointing out of it is refused.
+/// A path that does not exist yet, like a save target, is checked through
+/// its nearest existing ancestor.
+pub fn check_within(path: &Path, root: &Path) -> Result<(), String> {
+    fn real_path(path: &Path) -> Option<PathBuf> {
+        if let Ok(real) = path.canonicalize() {
+            return Some(real);
+        }
+        // `None` for a trailing `..`, which cannot be resolved without the
+        // missing directory before it
+        let name = path.file_name()?;
+        Some(real_path(path.parent()?)?.join(name))
+    }
+
+    match real_path(path) {
+        Some(real) if real.starts_with(root) => Ok(()),
+        _ => Err(format!(
+            "'{}' is outside {}; this dialog only accepts files in there",
+            path.display(),
+            root.display()
+        )),
+    }
+}
+
+fn resolve_submission(
+    operation: Operation,
     entries: &[String],
     options: &SessionOptions,
 ) -> Result<Vec<String>, String> {
-    if entries.is_empty() {
+    // Placed one at a time, the undecided files are the better message
+    let per_file = operation == Operation::SaveFiles && options.per_file.is_some();
+    if entries.is_empty() && !per_file {
         return Err("No entries in submission".to_string());
     }
 
-    let current_folder = options.current_folder.as_deref().map(Path::new);
+    let current_folder = options.folder();
+    let current_folder = current_folder.as_deref();
 
     match operation {
-        "save-file" => {
+        Operation::SaveFile => {
             if entries.len() > 1 {
                 return Err(format!("Save mode expects 1 entry, got {}", entries.len()));
             }
-            let candidate_name = options.candidates.first().map(String::as_str);
-            Ok(entries
+            let candidate_name = options
+                .candidates
+                .first()
+                .map(|name| SessionOptions::candidate_name(name));
+            entries
                 .iter()
-                .map(|e| resolve_save_file_to_uri(e, current_folder, candidate_name))
-                .collect())
+                .map(|e| resolve_save_file_to_uri(e, current_folder, candidate_name.as_deref()))
+                .collect()
         }
-        "save-files" => {
+        Operation::SaveFiles => {
             if options.candidates.is_empty() {
                 return Ok(entries
                     .iter()
@@ -109,21 +506,9 @@ pub fn validate(
                     .collect());
             }
 
-            // User selected a folder; build URIs from folder + candidate filenames
-            let folder_entry = entries.first().ok_or("No folder selected for save-files")?;
-            let folder = resolve_path(folder_entry, current_folder);
-            let folder = if folder.is_file() {
-                folder.parent().unwrap_or(&folder).to_path_buf()
-            } else {
-                folder
-            };
-            Ok(options
-                .candidates
-                .iter()
-                .map(|name| path_to_file_uri(&folder.join(name)))
-                .collect())
+            build_save_files_uris(entries, options)
         }
-        "open-file" => {
+        Operation::OpenFile => {
             if let SelectionMode::Pick { multiple, .. } = options.mode
                 && !multiple
                 && entries.len() > 1
@@ -145,17 +530,230 @@ pub fn validate(
     }
 }
 
+/// Folder a save-files submission saves into: the first entry, or the
+/// folder of a file given instead. A picked folder is stored as its target
+/// URIs (`DIR/candidate`), which need not exist yet.
+fn save_files_folder(entries: &[String], options: &SessionOptions) -> Result<PathBuf, String> {
+    let folder_entry = entries.first().ok_or("No folder selected for save-files")?;
+    let folder = resolve_path(folder_entry, options.folder().as_deref());
+    let is_target = || {
+        !folder.is_dir()
+            && folder.parent().is_some_and(Path::is_dir)
+            && folder.file_name().is_some_and(|name| {
+                options
+                    .candidates
+                    .iter()
+                    .any(|c| name == SessionOptions::candidate_name(c))
+            })
+    };
+    Ok(if folder.is_file() || is_target() {
+        folder.parent().unwrap_or(&folder).to_path_buf()
+    } else {
+        folder
+    })
+}
+
+/// Each candidate of a per-file save-files submission (see [`PerFile`])
+/// with where it goes, in candidate order. Every entry must be the
+/// `DIR/NAME` target of a candidate, in an existing folder.
+pub fn save_files_placements(
+    entries: &[String],
+    options: &SessionOptions,
+) -> Result<Vec<(String, Placement)>, String> {
+    let current_folder = options.folder();
+    let mut placements: Vec<(String, Placement)> = options
+        .candidates
+        .iter()
+        .map(|name| (name.clone(), Placement::Undecided))
+        .collect();
+
+    for entry in entries {
+        let target = resolve_path(entry, current_folder.as_deref());
+        if target.is_dir() {
+            return Err(format!(
+                "{} is a folder, but the files are placed one at a time; `next-file DIR` puts the next one there",
+                target.display()
+            ));
+        }
+        let slot = target.file_name().and_then(|name| {
+            placements.iter_mut().find(|(candidate, placement)| {
+                *placement == Placement::Undecided
+                    && name == SessionOptions::candidate_name(candidate)
+            })
+        });
+        let Some((_, placement)) = slot else {
+            return Err(format!(
+                "{} is not where one of the files goes (DIR/NAME, one per file)",
+                target.display()
+            ));
+        };
+        if !target.parent().is_some_and(Path::is_dir) {
+            return Err(format!("folder of {} does not exist", target.display()));
+        }
+        *placement = Placement::Target(target);
+    }
+
+    let skipped = options
+        .per_file
+        .as_ref()
+        .map_or(&[][..], |p| &p.skipped[..]);
+    for name in skipped {
+        if let Some((_, placement)) = placements
+            .iter_mut()
+            .find(|(candidate, placement)| *placement == Placement::Undecided && candidate == name)
+        {
+            *placement = Placement::Skipped;
+        }
+    }
+    Ok(placements)
+}
+
+/// Where the files of a save-files submission with candidates go, in
+/// candidate order: the chosen folder joined with each name, or, placed one
+/// at a time, each placed file's target (skipped ones have none)
+fn save_files_targets(
+    entries: &[String],
+    options: &SessionOptions,
+) -> Result<Vec<PathBuf>, String> {
+    if options.per_file.is_none() {
+        let folder = save_files_folder(entries, options)?;
+        return Ok(options
+            .candidates
+            .iter()
+            .map(|name| folder.join(SessionOptions::candidate_name(name)))
+            .collect());
+    }
+
+    let placements = save_files_placements(entries, options)?;
+    let undecided: Vec<&str> = placements
+        .iter()
+        .filter(|(_, placement)| *placement == Placement::Undecided)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if let Some(next) = undecided.first() {
+        return Err(format!(
+            "{} of {} files have no folder yet, next {next}; place it with `next-file DIR` or `next-file --skip`",
+            undecided.len(),
+            placements.len()
+        ));
+    }
+    let targets: Vec<PathBuf> = placements
+        .into_iter()
+        .filter_map(|(_, placement)| match placement {
+            Placement::Target(target) => Some(target),
+            _ => None,
+        })
+        .collect();
+    if targets.is_empty() {
+        return Err("all files are skipped; cancel to save none".to_string());
+    }
+    Ok(targets)
+}
+
+/// Targets of a save-files submission that already exist
+pub fn save_files_conflicts(
+    entries: &[String],
+    options: &SessionOptions,
+) -> Result<Vec<PathBuf>, String> {
+    Ok(save_files_targets(entries, options)?
+        .into_iter()
+        .filter(|target| target.exists())
+        .collect())
+}
+
+/// Build the URIs of a save-files submission with candidates: the chosen
+/// folder joined with each candidate name, or the per-file targets, with
+/// existing files handled by the session's [`ConflictStrategy`].
+fn build_save_files_uris(
+    entries: &[String],
+    options: &SessionOptions,
+) -> Result<Vec<String>, String> {
+    let targets = save_files_targets(entries, options)?;
+    let existing: HashSet<&PathBuf> = targets.iter().filter(|target| target.exists()).collect();
+    let strategy = options.conflicts.unwrap_or_default();
+    if existing.is_empty() || strategy == ConflictStrategy::Overwrite {
+        return Ok(targets
+            .iter()
+            .map(|target| path_to_file_uri(target))
+            .collect());
+    }
+    if strategy == ConflictStrategy::Ask {
+        let listed: Vec<&PathBuf> = targets
+            .iter()
+            .filter(|target| existing.contains(target))
+            .collect();
+        return Err(format!(
+            "{} of {} files already exist {}; choose with `conflicts skip|overwrite|rename`",
+            existing.len(),
+            targets.len(),
+            list_targets(&listed)
+        ));
+    }
+
+    // Names other files will take, so a renamed file doesn't land on one
+    let mut taken: HashSet<PathBuf> = targets.iter().cloned().collect();
+    let mut uris = Vec::with_capacity(targets.len());
+    for target in &targets {
+        if !existing.contains(target) {
+            uris.push(path_to_file_uri(target));
+        } else if strategy == ConflictStrategy::Rename {
+            let renamed = free_name(target, &taken);
+            taken.insert(renamed.clone());
+            uris.push(path_to_file_uri(&renamed));
+        }
+    }
+    if uris.is_empty() {
+        return Err(format!(
+            "all {} files already exist and are skipped; choose with `conflicts overwrite|rename`",
+            existing.len()
+        ));
+    }
+    Ok(uris)
+}
+
+/// `in DIR: a, b` for targets in one folder, `at DIR/a, DIR2/b` otherwise
+fn list_targets(targets: &[&PathBuf]) -> String {
+    let folder = targets.first().and_then(|target| target.parent());
+    if targets.iter().all(|target| target.parent() == folder)
+        && let Some(folder) = folder
+    {
+        let names: Vec<_> = targets
+            .iter()
+            .map(|target| target.file_name().unwrap_or_default().to_string_lossy())
+            .collect();
+        return format!("in {}: {}", folder.display(), names.join(", "));
+    }
+    let paths: Vec<_> = targets
+        .iter()
+        .map(|target| target.display().to_string())
+        .collect();
+    format!("at {}", paths.join(", "))
+}
+
+/// First `stem (N).ext` next to `path` that neither exists nor is `taken`
+fn free_name(path: &Path, taken: &HashSet<PathBuf>) -> PathBuf {
+    (1..)
+        .map(|n| files::with_stem_suffix(path, &format!(" ({n})")))
+        .find(|candidate| !candidate.exists() && !taken.contains(candidate))
+        .expect("unbounded range")
+}
+
 pub fn materialize_intent(
-    operation: &str,
+    operation: Operation,
     intent: &Intent,
     options: &SessionOptions,
 ) -> Result<Vec<String>, String> {
     match operation {
-        "open-file" => {
+        Operation::OpenFile => {
             if options.mode == SelectionMode::Save || options.mode == SelectionMode::SaveMultiple {
                 return Err("open-file cannot use save mode options".to_string());
             }
-            if intent.family != IntentFamily::Path {
+            let accepts = match intent.family {
+                IntentFamily::Path => true,
+                IntentFamily::Directory => options.mode.picks_folder(),
+                IntentFamily::Color => false,
+            };
+            if !accepts {
                 return Err(format!(
                     "open-file expects path intent, got {}",
                     intent.family
@@ -166,9 +764,21 @@ pub fn materialize_intent(
             {
                 return Err("open-file single-pick mode does not accept multiple paths".to_string());
             }
-            validate(operation, &intent.values(), options)
+            let values = intent.values();
+            let folder = options.folder();
+            // Refuse files here rather than when the dialog is submitted
+            if options.mode.picks_folder()
+                && let Some(file) = values
+                    .iter()
+                    .find(|v| !resolve_path(v, folder.as_deref()).is_dir())
+            {
+                return Err(format!(
+                    "'{file}' is not a directory; this dialog picks folders"
+                ));
+            }
+            validate(operation, &values, options)
         }
-        "save-file" => {
+        Operation::SaveFile => {
             if intent.family != IntentFamily::Path {
                 return Err(format!(
                     "save-file expects path intent, got {}",
@@ -180,10 +790,10 @@ pub fn materialize_intent(
             }
             validate(operation, &intent.values(), options)
         }
-        "save-files" => {
+        Operation::SaveFiles => {
             if intent.family != IntentFamily::Directory {
                 return Err(format!(
-                    "save-files expects directory intent, got {}",
+                    "save-files expects directory intent, got {} (pick the folder with `folder DIR`)",
                     intent.family
                 ));
             }
@@ -193,8 +803,7 @@ pub fn materialize_intent(
 
             let values = intent.values();
             let dir = values.first().ok_or("No directory in intent")?;
-            let path = Path::new(dir);
-            if !path.is_dir() {
+            if !resolve_path_as_given(dir, None).is_dir() {
                 return Err(format!("save-files expects a directory, got '{}'", dir));
             }
 
@@ -206,31 +815,40 @@ pub fn materialize_intent(
 
 /// Resolve a save-file entry to a file:// URI.
 ///
-/// If the selected path is a directory and a candidate filename exists,
-/// append that filename to produce the final target file path.
+/// `DIR/` (trailing slash) keeps the candidate filename and places it in
+/// `DIR`, whether or not `DIR` exists yet; `DIR/NAME` saves under `NAME`. An
+/// existing directory given without the slash is treated like `DIR/`.
 fn resolve_save_file_to_uri(
     entry: &str,
     current_folder: Option<&Path>,
-    candidate_name: Option<&str>,
-) -> String {
+    candidate_name: Option<&OsStr>,
+) -> Result<String, String> {
     let selected = resolve_path(entry, current_folder);
-
-    if let Some(name) = candidate_name
-        && !name.is_empty()
-        && selected.is_dir()
-    {
-        return path_to_file_uri(&selected.join(name));
+    let names_dir = entry.ends_with('/') || selected.is_dir();
+    if !names_dir {
+        return Ok(path_to_file_uri(&selected));
     }
 
-    path_to_file_uri(&selected)
+    match candidate_name.filter(|name| !name.is_empty()) {
+        Some(name) => Ok(path_to_file_uri(&selected.join(name))),
+        None => Err(format!(
+            "'{entry}' is a directory and there is no suggested file name; use DIR/NAME"
+        )),
+    }
 }
 
-/// Resolve an entry string to an absolute path, using current_folder for relative paths.
+/// Resolve an entry string to an absolute path, using current_folder for
+/// relative paths, in NFC (see [`input::normalize_path`]).
 fn resolve_path(entry: &str, current_folder: Option<&Path>) -> PathBuf {
-    if let Some(rest) = entry.strip_prefix("file://")
-        && let Ok(url) = url::Url::parse(&format!("file://{rest}"))
-        && let Ok(path) = url.to_file_path()
-    {
+    let path = resolve_path_as_given(entry, current_folder);
+    match path.to_str().map(input::normalize_path) {
+        Some(Cow::Owned(normalized)) => PathBuf::from(normalized),
+        _ => path,
+    }
+}
+
+pub(crate) fn resolve_path_as_given(entry: &str, current_folder: Option<&Path>) -> PathBuf {
+    if let Some(path) = file_uri_to_path(entry) {
         return path;
     }
 
@@ -244,11 +862,45 @@ fn resolve_path(entry: &str, current_folder: Option<&Path>) -> PathBuf {
     }
 }
 
-/// Convert an absolute path to a file:// URI with proper percent-encoding.
-fn path_to_file_uri(path: &Path) -> String {
-    url::Url::from_file_path(path)
-        .map(|u| u.to_string())
-        .unwrap_or_else(|()| format!("file://{}", path.display()))
+/// The path of a `file://` URI entry, `None` for other entries (see
+/// [`uri::to_path`])
+pub fn file_uri_to_path(entry: &str) -> Option<PathBuf> {
+    uri::to_path(entry)
+}
+
+/// Convert an absolute path to a file:// URI with proper percent-encoding
+/// (see [`uri::from_path`]).
+pub fn path_to_file_uri(path: &Path) -> String {
+    uri::from_path(path)
+}
+
+/// Put result URIs in `order`. Names sort in the locale's collation order
+/// (see [`Collation`]). Sorting is stable, so files with the same name or
+/// time keep their selection order; files whose time cannot be read go last.
+pub fn sort_uris(uris: &mut [String], order: SortOrder) {
+    let path = |uri: &str| resolve_path_as_given(uri, None);
+    match order {
+        SortOrder::Selection => {}
+        SortOrder::Name => {
+            let collation = Collation::from_env();
+            let mut named: Vec<_> = uris
+                .iter()
+                .map(|uri| {
+                    let path = path(uri);
+                    let name = path.file_name().unwrap_or(path.as_os_str());
+                    (name.to_string_lossy().into_owned(), uri.clone())
+                })
+                .collect();
+            named.sort_by(|(a, _), (b, _)| collation.compare(a, b));
+            for (slot, (_, uri)) in uris.iter_mut().zip(named) {
+                *slot = uri;
+            }
+        }
+        SortOrder::Mtime => uris.sort_by_cached_key(|uri| {
+            let mtime = std::fs::metadata(path(uri)).and_then(|m| m.modified());
+            (mtime.is_err(), mtime.ok())
+        }),
+    }
 }
 
 /// Resolve an entry to a file:// URI, resolving relative paths against current_folder.
@@ -285,7 +937,8 @@ fn resolve_entries_to_absolute(entries: &[String]) -> Vec<String> {
 /// Smart add entries: respects single/multi-select constraints.
 ///
 /// Resolves relative paths against CWD at edit time.
-/// In multi-pick mode, appends entries. In all other modes (single-pick, save, save-multiple),
+/// In multi-pick mode, appends the entries not already selected (in any
+/// spelling, see [`uri::key`]). In all other modes (single-pick, save, save-multiple),
 /// replaces the submission.
 pub fn add_entries(
     sub_path: &Path,
@@ -295,6 +948,15 @@ pub fn add_entries(
     let resolved = resolve_entries_to_absolute(entries);
     let is_multi = matches!(options.mode, SelectionMode::Pick { multiple: true, .. });
     if is_multi {
+        // Entries already selected, in any spelling, are not added again
+        let mut selected: HashSet<_> = files::read_lines(sub_path)
+            .iter()
+            .map(|entry| uri::key(entry).into_owned())
+            .collect();
+        let resolved: Vec<String> = resolved
+            .into_iter()
+            .filter(|entry| selected.insert(uri::key(entry).into_owned()))
+            .collect();
         files::append_lines(sub_path, &resolved)?;
         Ok(AddResult::Appended(resolved.len()))
     } else {
@@ -306,6 +968,171 @@ pub fn add_entries(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::portal::IntentItem;
+
+    #[test]
+    fn filters_parse_from_specs() {
+        let images: Filter = "Images=*.png,*.jpg;image/webp".parse().unwrap();
+        assert_eq!(
+            images,
+            Filter::new("Images")
+                .glob("*.png")
+                .glob("*.jpg")
+                .mime_type("image/webp")
+        );
+        assert_eq!(images.to_string(), "Images=*.png,*.jpg,image/webp");
+        assert_eq!(images.to_string().parse::<Filter>().unwrap(), images);
+        assert_eq!(
+            "Text: *.txt ,".parse::<Filter>().unwrap(),
+            Filter::new("Text").glob("*.txt")
+        );
+        for invalid in ["Images", "=*.png", "Images=", "Images=;,"] {
+            assert!(invalid.parse::<Filter>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn folder_picks_refuse_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("a.txt");
+        std::fs::File::create(&file).unwrap();
+        let options = SessionOptions {
+            mode: SelectionMode::Pick {
+                multiple: false,
+                directory: true,
+            },
+            ..Default::default()
+        };
+        let pick = |item: IntentItem| {
+            materialize_intent(Operation::OpenFile, &Intent::single(item), &options)
+        };
+
+        let dir = tmp.path().display().to_string();
+        assert!(pick(IntentItem::Directory(dir.clone())).is_ok());
+        assert!(pick(IntentItem::Path(dir)).is_ok());
+        let err = pick(IntentItem::Path(file.display().to_string())).unwrap_err();
+        assert!(err.contains("is not a directory"), "{err}");
+
+        // File picks still refuse directory intents
+        let files = SessionOptions::default();
+        let dir = IntentItem::Directory(tmp.path().display().to_string());
+        assert!(materialize_intent(Operation::OpenFile, &Intent::single(dir), &files).is_err());
+    }
+
+    #[test]
+    fn restrict_to_refuses_paths_outside() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap().join("public");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "").unwrap();
+        std::fs::write(tmp.path().join("secret"), "").unwrap();
+        std::os::unix::fs::symlink(tmp.path().join("secret"), root.join("link")).unwrap();
+        let options = SessionOptions {
+            mode: SelectionMode::Save,
+            restrict_to: Some(root.display().to_string()),
+            ..Default::default()
+        };
+        let save = |entry: PathBuf| {
+            validate(
+                Operation::SaveFile,
+                &[entry.display().to_string()],
+                &options,
+            )
+        };
+
+        assert!(save(root.join("a.txt")).is_ok());
+        // Save targets need not exist yet
+        assert!(save(root.join("new/b.txt")).is_ok());
+        let err = save(tmp.path().join("secret")).unwrap_err();
+        assert!(err.contains("is outside"), "{err}");
+        assert!(save(root.join("link")).is_err());
+        assert!(save(root.join("../secret")).is_err());
+        assert!(save(root.join("new/../../secret")).is_err());
+    }
+
+    #[test]
+    fn save_files_picked_folder_is_stable() {
+        let tmp = tempfile::tempdir().unwrap();
+        let options = SessionOptions {
+            mode: SelectionMode::SaveMultiple,
+            candidates: vec!["a.txt".to_string(), "b.txt".to_string()],
+            ..Default::default()
+        };
+        let dir = IntentItem::Directory(tmp.path().display().to_string());
+        let picked =
+            materialize_intent(Operation::SaveFiles, &Intent::single(dir), &options).unwrap();
+        // The session stores the materialized targets; submitting them
+        // must not join the names again
+        assert_eq!(
+            validate(Operation::SaveFiles, &picked, &options).unwrap(),
+            picked
+        );
+        assert_eq!(picked[0], path_to_file_uri(&tmp.path().join("a.txt")));
+    }
+
+    #[test]
+    fn non_utf8_folders_and_names_survive() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let folder = tmp.path().join(OsString::from_vec(b"caf\xe9".to_vec()));
+        std::fs::create_dir(&folder).unwrap();
+        let name = OsString::from_vec(b"r\xe9sum\xe9.txt".to_vec());
+        let encoded = |s: &OsStr| uri::encode_os(s).into_owned();
+
+        let options = SessionOptions {
+            mode: SelectionMode::Save,
+            current_folder: Some(encoded(folder.as_os_str())),
+            candidates: vec![encoded(&name)],
+            ..Default::default()
+        };
+        assert_eq!(options.folder().as_ref(), Some(&folder));
+        assert_eq!(
+            validate(
+                Operation::SaveFile,
+                &[encoded(folder.as_os_str())],
+                &options
+            )
+            .unwrap(),
+            [path_to_file_uri(&folder.join(&name))]
+        );
+
+        let options = SessionOptions {
+            mode: SelectionMode::SaveMultiple,
+            ..options
+        };
+        let dir = crate::portal::parse_item("directory", &encoded(folder.as_os_str())).unwrap();
+        let picked =
+            materialize_intent(Operation::SaveFiles, &Intent::single(dir), &options).unwrap();
+        assert_eq!(picked, [path_to_file_uri(&folder.join(&name))]);
+
+        // Renaming around a conflict keeps the bytes of the name
+        std::fs::write(folder.join(&name), "").unwrap();
+        let renamed = free_name(&folder.join(&name), &HashSet::new());
+        assert_eq!(
+            renamed,
+            folder.join(OsString::from_vec(b"r\xe9sum\xe9 (1).txt".to_vec()))
+        );
+    }
+
+    #[test]
+    fn user_dirs_expand_home() {
+        let content = "# comment\nXDG_DOCUMENTS_DIR=\"$HOME/Documents\"\n\
+                       XDG_DOWNLOAD_DIR=\"/srv/downloads\"\nXDG_DESKTOP_DIR=\"$HOME/\"\n";
+        assert_eq!(
+            parse_user_dirs(content, Some(Path::new("/home/u"))),
+            [
+                PathBuf::from("/home/u/Documents"),
+                PathBuf::from("/srv/downloads"),
+                PathBuf::from("/home/u/"),
+            ]
+        );
+        assert_eq!(
+            parse_user_dirs(content, None),
+            [PathBuf::from("/srv/downloads")]
+        );
+    }
 
     #[test]
     fn resolve_file_uri_decodes_spaces() {
@@ -324,7 +1151,7 @@ mod tests {
         };
 
         let entries = validate(
-            "open-file",
+            Operation::OpenFile,
             &["file:///tmp/bear%20test.jpg".to_string()],
             &options,
         )
@@ -332,4 +1159,244 @@ mod tests {
 
         assert_eq!(entries, vec!["file:///tmp/bear%20test.jpg"]);
     }
+
+    #[test]
+    fn sort_orders_results() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = |name: &str, age: u64| {
+            let path = tmp.path().join(name);
+            let f = std::fs::File::create(&path).unwrap();
+            let mtime = std::time::SystemTime::now() - std::time::Duration::from_secs(age);
+            f.set_modified(mtime).unwrap();
+            path_to_file_uri(&path)
+        };
+        let selected = vec![
+            file("b.jpg", 10),
+            file("c d.jpg", 30),
+            path_to_file_uri(&tmp.path().join("missing.jpg")),
+            file("a.jpg", 20),
+        ];
+        let sorted = |order| {
+            let mut uris = selected.clone();
+            sort_uris(&mut uris, order);
+            uris.iter()
+                .map(|uri| uri.rsplit('/').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sorted(SortOrder::Selection),
+            ["b.jpg", "c%20d.jpg", "missing.jpg", "a.jpg"]
+        );
+        assert_eq!(
+            sorted(SortOrder::Name),
+            ["a.jpg", "b.jpg", "c%20d.jpg", "missing.jpg"]
+        );
+        assert_eq!(
+            sorted(SortOrder::Mtime),
+            ["c%20d.jpg", "a.jpg", "b.jpg", "missing.jpg"]
+        );
+    }
+
+    #[test]
+    fn save_file_dir_shorthand_keeps_candidate_name() {
+        let options = |candidate: &str| SessionOptions {
+            mode: SelectionMode::Save,
+            current_folder: Some("/home/me".into()),
+            candidates: vec![candidate.to_string()]
+                .into_iter()
+                .filter(|c| !c.is_empty())
+                .collect(),
+            ..Default::default()
+        };
+        let save = |entry: &str, candidate: &str| {
+            validate(
+                Operation::SaveFile,
+                &[entry.to_string()],
+                &options(candidate),
+            )
+        };
+
+        // Trailing slash: keep the name, even if the directory does not exist yet
+        assert_eq!(
+            save("/nonexistent/portty/out/", "report.pdf"),
+            Ok(vec![
+                "file:///nonexistent/portty/out/report.pdf".to_string()
+            ])
+        );
+        assert_eq!(
+            save("file:///nonexistent/portty/out/", "report.pdf"),
+            Ok(vec![
+                "file:///nonexistent/portty/out/report.pdf".to_string()
+            ])
+        );
+        assert_eq!(
+            save("docs/", "report.pdf"),
+            Ok(vec!["file:///home/me/docs/report.pdf".to_string()])
+        );
+        // Existing directory without the slash behaves the same
+        assert_eq!(
+            save("/", "report.pdf"),
+            Ok(vec!["file:///report.pdf".to_string()])
+        );
+        // DIR/NAME renames
+        assert_eq!(
+            save("/nonexistent/portty/out/final.pdf", "report.pdf"),
+            Ok(vec!["file:///nonexistent/portty/out/final.pdf".to_string()])
+        );
+        // Nothing to keep
+        assert!(save("/nonexistent/portty/out/", "").is_err());
+    }
+
+    #[test]
+    fn save_files_conflict_strategies() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("a.txt"), "").unwrap();
+        std::fs::write(tmp.path().join("c (1).txt"), "").unwrap();
+        std::fs::write(tmp.path().join("c.txt"), "").unwrap();
+        let folder = tmp.path().to_str().unwrap().to_string();
+        let save = |conflicts| {
+            let options = SessionOptions {
+                mode: SelectionMode::SaveMultiple,
+                candidates: vec![
+                    "a.txt".into(),
+                    "b.txt".into(),
+                    "c.txt".into(),
+                    "a (1).txt".into(),
+                ],
+                conflicts,
+                ..Default::default()
+            };
+            validate(
+                Operation::SaveFiles,
+                std::slice::from_ref(&folder),
+                &options,
+            )
+            .map(|uris| {
+                uris.iter()
+                    .map(|uri| uri.rsplit('/').next().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            save(None).unwrap(),
+            ["a.txt", "b.txt", "c.txt", "a%20(1).txt"]
+        );
+        assert_eq!(
+            save(Some(ConflictStrategy::Skip)).unwrap(),
+            ["b.txt", "a%20(1).txt"]
+        );
+        // `a (1).txt` is another candidate and `c (1).txt` exists
+        assert_eq!(
+            save(Some(ConflictStrategy::Rename)).unwrap(),
+            ["a%20(2).txt", "b.txt", "c%20(2).txt", "a%20(1).txt"]
+        );
+        let err = save(Some(ConflictStrategy::Ask)).unwrap_err();
+        assert!(err.contains("2 of 4 files already exist"), "{err}");
+        assert!(err.contains("a.txt, c.txt"), "{err}");
+    }
+
+    #[test]
+    fn save_files_placed_one_at_a_time() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (docs, pics) = (tmp.path().join("docs"), tmp.path().join("pics"));
+        std::fs::create_dir(&docs).unwrap();
+        std::fs::create_dir(&pics).unwrap();
+        std::fs::write(pics.join("b.png"), "").unwrap();
+        let mut options = SessionOptions {
+            mode: SelectionMode::SaveMultiple,
+            candidates: vec!["a.txt".into(), "b.png".into(), "c.txt".into()],
+            per_file: Some(PerFile::default()),
+            ..Default::default()
+        };
+        let target = |dir: &Path, name| path_to_file_uri(&dir.join(name));
+        let mut entries = vec![target(&pics, "b.png")];
+
+        let placements = save_files_placements(&entries, &options).unwrap();
+        assert_eq!(placements[0], ("a.txt".into(), Placement::Undecided));
+        assert_eq!(placements[1].1, Placement::Target(pics.join("b.png")));
+        let err = validate(Operation::SaveFiles, &entries, &options).unwrap_err();
+        assert!(
+            err.contains("2 of 3 files have no folder yet, next a.txt"),
+            "{err}"
+        );
+        let err = validate(Operation::SaveFiles, &[], &options).unwrap_err();
+        assert!(err.contains("3 of 3 files"), "{err}");
+
+        entries.insert(0, target(&docs, "a.txt"));
+        options.per_file = Some(PerFile {
+            skipped: vec!["c.txt".into()],
+        });
+        assert_eq!(
+            validate(Operation::SaveFiles, &entries, &options).unwrap(),
+            [target(&docs, "a.txt"), target(&pics, "b.png")]
+        );
+        options.conflicts = Some(ConflictStrategy::Rename);
+        assert_eq!(
+            validate(Operation::SaveFiles, &entries, &options).unwrap(),
+            [target(&docs, "a.txt"), target(&pics, "b (1).png")]
+        );
+        assert_eq!(
+            save_files_conflicts(&entries, &options).unwrap(),
+            [pics.join("b.png")]
+        );
+
+        // A folder for all, or a name that is not a candidate, is refused
+        let folder = docs.display().to_string();
+        assert!(validate(Operation::SaveFiles, &[folder], &options).is_err());
+        let stray = [target(&docs, "d.txt")];
+        assert!(validate(Operation::SaveFiles, &stray, &options).is_err());
+    }
+
+    #[test]
+    fn choices_take_picked_or_default_values() {
+        let mut options = SessionOptions {
+            choices: vec![
+                Choice {
+                    id: "encoding".into(),
+                    label: "Encoding".into(),
+                    options: vec![
+                        ("utf8".into(), "Unicode".into()),
+                        ("latin15".into(), "Western".into()),
+                    ],
+                    default: "latin15".into(),
+                },
+                Choice {
+                    id: "reencode".into(),
+                    label: "Reencode".into(),
+                    options: Vec::new(),
+                    default: String::new(),
+                },
+                Choice {
+                    id: "eol".into(),
+                    label: "Line endings".into(),
+                    options: vec![("lf".into(), "Unix".into())],
+                    default: "cr".into(),
+                },
+            ],
+            ..Default::default()
+        };
+        let values = |options: &SessionOptions| options.choice_values();
+        assert_eq!(
+            values(&options),
+            [
+                ("encoding".to_string(), "latin15".to_string()),
+                ("reencode".to_string(), "false".to_string()),
+                ("eol".to_string(), "lf".to_string()),
+            ]
+        );
+
+        options.choose("encoding", "utf8").unwrap();
+        options.choose("reencode", "true").unwrap();
+        assert!(options.choose("reencode", "yes").is_err());
+        assert!(options.choose("eol", "cr").is_err());
+        assert!(options.choose("missing", "lf").is_err());
+        assert_eq!(values(&options)[0].1, "utf8");
+        assert_eq!(values(&options)[1].1, "true");
+
+        // A stale pick in options.json falls back to the default
+        options.chosen.insert("eol".into(), "crlf".into());
+        assert_eq!(values(&options)[2].1, "lf");
+    }
 }
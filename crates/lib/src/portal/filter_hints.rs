@@ -0,0 +1,313 @@
+//! Listing hints for file chooser filters, for `portty get filter-hints`.
+//!
+//! GUI dialogs show what a filter matches and how many files in the folder
+//! pass it. A filter's globs are used as written; MIME types are expanded to
+//! the globs shared-mime-info lists for them (`mime/globs2` in the XDG data
+//! dirs). Counting lists the session folder once, only when asked.
+//!
+//! [`describe`] gives the same filters as structured JSON, for
+//! `portty filters --json`.
+
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde_json::json;
+
+use super::file_chooser::{Filter, FilterPattern};
+
+/// File name globs per MIME type, from shared-mime-info
+#[derive(Debug, Default)]
+pub struct MimeGlobs {
+    /// `(mime type, glob, case-sensitive)`
+    entries: Vec<(String, String, bool)>,
+}
+
+impl MimeGlobs {
+    /// Load `mime/globs2` from `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`. Missing
+    /// databases leave MIME type patterns without globs.
+    pub fn load() -> Self {
+        let mut globs = Self::default();
+        for dir in data_dirs() {
+            if let Ok(content) = fs::read_to_string(dir.join("mime/globs2")) {
+                globs.entries.extend(Self::parse(&content).entries);
+            }
+        }
+        globs
+    }
+
+    /// Parse a `globs2` file: `weight:type/subtype:glob[:flags]` lines.
+    pub fn parse(content: &str) -> Self {
+        let entries = content
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut fields = line.split(':');
+                let (_weight, mime, glob) = (fields.next()?, fields.next()?, fields.next()?);
+                let case_sensitive = fields.next().is_some_and(|flags| flags.contains("cs"));
+                Some((mime.to_string(), glob.to_string(), case_sensitive))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Globs of `mime`; `type/*` covers every subtype.
+    fn globs<'a>(&'a self, mime: &'a str) -> impl Iterator<Item = (&'a str, bool)> + 'a {
+        let prefix = mime.strip_suffix('*');
+        self.entries
+            .iter()
+            .filter(move |(m, ..)| match prefix {
+                Some(prefix) => m.starts_with(prefix),
+                None => m == mime,
+            })
+            .map(|(_, glob, cs)| (glob.as_str(), *cs))
+    }
+}
+
+/// A filter compiled for matching file names
+#[derive(Debug)]
+pub struct FilterMatcher {
+    /// Patterns for display: globs as written, MIME types expanded
+    summary: Vec<String>,
+    set: GlobSet,
+}
+
+impl FilterMatcher {
+    pub fn new(filter: &Filter, mime: &MimeGlobs) -> Self {
+        let mut summary = Vec::new();
+        let mut seen = HashSet::new();
+        let mut builder = GlobSetBuilder::new();
+        let mut add = |glob: &str, case_sensitive: bool, summary: &mut Vec<String>| {
+            if !seen.insert(glob.to_string()) {
+                return;
+            }
+            if let Ok(compiled) = GlobBuilder::new(glob)
+                .case_insensitive(!case_sensitive)
+                .build()
+            {
+                builder.add(compiled);
+                summary.push(glob.to_string());
+            }
+        };
+
+        for pattern in &filter.patterns {
+            match pattern {
+                FilterPattern::Glob(glob) => add(glob, true, &mut summary),
+                FilterPattern::MimeType(mime_type) => {
+                    let before = summary.len();
+                    for (glob, cs) in mime.globs(mime_type) {
+                        add(glob, cs, &mut summary);
+                    }
+                    if summary.len() == before {
+                        summary.push(mime_type.clone());
+                    }
+                }
+            }
+        }
+        Self {
+            summary,
+            set: builder.build().unwrap_or_else(|_| GlobSet::empty()),
+        }
+    }
+
+    /// Case-sensitive file name globs given by the user, e.g. `portty select
+    /// --exclude`. Unlike a filter's, invalid globs are an error.
+    pub fn from_globs(globs: &[String]) -> Result<Self, String> {
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+            let compiled = GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("invalid glob {glob:?}: {}", e.kind()))?;
+            builder.add(compiled);
+        }
+        Ok(Self {
+            summary: globs.to_vec(),
+            set: builder.build().map_err(|e| e.to_string())?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// The filter's patterns, e.g. `*.png, *.jpg`
+    pub fn summary(&self) -> String {
+        self.summary.join(", ")
+    }
+
+    pub fn is_match(&self, name: &OsStr) -> bool {
+        self.set.is_match(Path::new(name))
+    }
+}
+
+/// Number of files in `folder` each matcher accepts. Hidden files and
+/// directories are not counted.
+pub fn count_matches(folder: &Path, matchers: &[FilterMatcher]) -> std::io::Result<Vec<usize>> {
+    let mut counts = vec![0; matchers.len()];
+    for entry in fs::read_dir(folder)?.flatten() {
+        let name = entry.file_name();
+        if name.as_encoded_bytes().starts_with(b".")
+            || !fs::metadata(entry.path()).is_ok_and(|m| m.is_file())
+        {
+            continue;
+        }
+        for (count, matcher) in counts.iter_mut().zip(matchers) {
+            if matcher.is_match(&name) {
+                *count += 1;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// One line per filter: `Name (patterns) — N matches here`, the count only
+/// when `folder` is given and readable. The current filter is marked.
+pub fn hints(
+    filters: &[Filter],
+    current: Option<usize>,
+    folder: Option<&Path>,
+    mime: &MimeGlobs,
+) -> Vec<String> {
+    let matchers: Vec<_> = filters
+        .iter()
+        .map(|f| FilterMatcher::new(f, mime))
+        .collect();
+    let counts = folder.and_then(|folder| count_matches(folder, &matchers).ok());
+
+    filters
+        .iter()
+        .zip(&matchers)
+        .enumerate()
+        .map(|(i, (filter, matcher))| {
+            let mut line = filter.name.clone();
+            if !matcher.summary.is_empty() {
+                line.push_str(&format!(" ({})", matcher.summary()));
+            }
+            if let Some(count) = counts.as_ref().map(|counts| counts[i]) {
+                let noun = if count == 1 { "match" } else { "matches" };
+                line.push_str(&format!(" — {count} {noun} here"));
+            }
+            if current == Some(i) {
+                line.push_str(" [current]");
+            }
+            line
+        })
+        .collect()
+}
+
+/// The filters as a JSON array, one object per filter:
+/// `{"index", "name", "patterns": [{"type": "glob" | "mime", "pattern"}],
+/// "globs", "current"}`, where `globs` has MIME types expanded as in
+/// [`hints`]
+pub fn describe(filters: &[Filter], current: Option<usize>, mime: &MimeGlobs) -> serde_json::Value {
+    filters
+        .iter()
+        .enumerate()
+        .map(|(i, filter)| {
+            let patterns: Vec<_> = filter
+                .patterns
+                .iter()
+                .map(|pattern| match pattern {
+                    FilterPattern::Glob(glob) => json!({"type": "glob", "pattern": glob}),
+                    FilterPattern::MimeType(mime) => json!({"type": "mime", "pattern": mime}),
+                })
+                .collect();
+            json!({
+                "index": i,
+                "name": filter.name,
+                "patterns": patterns,
+                "globs": FilterMatcher::new(filter, mime).summary,
+                "current": current == Some(i),
+            })
+        })
+        .collect()
+}
+
+/// `$XDG_DATA_HOME` followed by `$XDG_DATA_DIRS`
+fn data_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    let dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    home.into_iter()
+        .chain(dirs.split(':').map(PathBuf::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_summarize_and_count() {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in ["a.png", "b.PNG", "c.jpg", "notes.txt", ".hidden.png"] {
+            fs::write(tmp.path().join(name), "").unwrap();
+        }
+        fs::create_dir(tmp.path().join("dir.png")).unwrap();
+
+        let mime = MimeGlobs::parse(
+            "# comment\n50:image/png:*.png\n50:image/jpeg:*.jpg\n50:image/jpeg:*.jpeg\n",
+        );
+        let filters = vec![
+            Filter {
+                name: "Images".into(),
+                patterns: vec![
+                    FilterPattern::MimeType("image/*".into()),
+                    FilterPattern::Glob("*.png".into()),
+                ],
+            },
+            Filter {
+                name: "Text".into(),
+                patterns: vec![FilterPattern::Glob("*.txt".into())],
+            },
+            Filter {
+                name: "Fonts".into(),
+                patterns: vec![FilterPattern::MimeType("font/ttf".into())],
+            },
+        ];
+
+        assert_eq!(
+            hints(&filters, Some(1), Some(tmp.path()), &mime),
+            [
+                "Images (*.png, *.jpg, *.jpeg) — 3 matches here",
+                "Text (*.txt) — 1 match here [current]",
+                "Fonts (font/ttf) — 0 matches here",
+            ]
+        );
+        assert_eq!(hints(&filters[1..2], None, None, &mime), ["Text (*.txt)"]);
+    }
+
+    #[test]
+    fn describe_keeps_pattern_types() {
+        let mime = MimeGlobs::parse("50:image/png:*.png\n");
+        let filters = vec![Filter {
+            name: "Images".into(),
+            patterns: vec![
+                FilterPattern::MimeType("image/png".into()),
+                FilterPattern::Glob("*.webp".into()),
+            ],
+        }];
+
+        assert_eq!(
+            describe(&filters, Some(0), &mime),
+            json!([{
+                "index": 0,
+                "name": "Images",
+                "patterns": [
+                    {"type": "mime", "pattern": "image/png"},
+                    {"type": "glob", "pattern": "*.webp"},
+                ],
+                "globs": ["*.png", "*.webp"],
+                "current": true,
+            }])
+        );
+    }
+}
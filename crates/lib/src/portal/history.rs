@@ -0,0 +1,196 @@
+//! Results log, for `portty history`.
+//!
+//! porttyd appends a line of JSON to `$XDG_STATE_HOME/portty/history.jsonl`
+//! for every request it answers with results: what the app got and how the
+//! results were produced, so a wrong upload can be traced to a session, a
+//! stale queued submission, or a remembered decision. Cancelled requests
+//! leave no entry. The log keeps the last [`MAX_ENTRIES`] requests.
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Entries kept when the log is trimmed
+pub const MAX_ENTRIES: usize = 1000;
+
+/// How the results of a request were produced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "kebab-case")]
+pub enum Provenance {
+    /// Submitted in a session, from its terminal or the portty CLI
+    Session {
+        session_id: String,
+        /// Queued submission the session was pre-populated with
+        /// (`queue_policy = "merge"`)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        queue_id: Option<String>,
+        /// Results that were pre-selected from `defaults`
+        #[serde(default, skip_serializing_if = "is_zero")]
+        defaults: usize,
+    },
+    /// A queued submission (`<time>-<portal>`, as `portty queue` lists it)
+    /// applied when the dialog opened, without a session
+    Queued { queue_id: String },
+    /// A remembered allow decision (`portty permissions`), without a session
+    Decision {
+        /// The results came from `defaults`, the request brought none
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        defaults: bool,
+    },
+    /// Clicked on screen with porttyd's built-in color picker, without a
+    /// session
+    Picked,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl Provenance {
+    /// Short name of the source
+    pub fn source(&self) -> &'static str {
+        match self {
+            Self::Session { .. } => "session",
+            Self::Queued { .. } => "queued",
+            Self::Decision { .. } => "decision",
+            Self::Picked => "picked",
+        }
+    }
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Session {
+                session_id,
+                queue_id,
+                defaults,
+            } => {
+                write!(f, "session {session_id}")?;
+                if let Some(queue_id) = queue_id {
+                    write!(f, ", merged queued submission {queue_id}")?;
+                }
+                if *defaults > 0 {
+                    write!(f, ", {defaults} from defaults")?;
+                }
+                Ok(())
+            }
+            Self::Queued { queue_id } => write!(f, "queued submission {queue_id}"),
+            Self::Decision { defaults: false } => f.write_str("remembered decision"),
+            Self::Decision { defaults: true } => {
+                f.write_str("remembered decision, results from defaults")
+            }
+            Self::Picked => f.write_str("built-in color picker"),
+        }
+    }
+}
+
+/// One answered request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the results were returned, as an ISO 8601 UTC timestamp
+    pub time: String,
+    pub portal: String,
+    pub operation: String,
+    /// Empty for unsandboxed apps
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub app_id: String,
+    pub provenance: Provenance,
+    /// What the app got, after `rewrite` and document portal exports
+    pub results: Vec<String>,
+}
+
+/// The results log
+pub fn path() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".local/state")
+        })
+        .join("portty/history.jsonl")
+}
+
+/// Append `entry` to the log (created owner-only if missing), trimming it to
+/// the last [`MAX_ENTRIES`] once it holds twice as many
+pub fn append(entry: &Entry) -> io::Result<()> {
+    let path = path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(entry).map_err(io::Error::other)?;
+    line.push('\n');
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(&path)?
+        .write_all(line.as_bytes())?;
+
+    let content = fs::read_to_string(&path)?;
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.len() >= 2 * MAX_ENTRIES {
+        let mut kept = lines[lines.len() - MAX_ENTRIES..].join("\n");
+        kept.push('\n');
+        crate::files::write_atomic(&path, kept)?;
+    }
+    Ok(())
+}
+
+/// Logged requests, oldest first. Lines that do not parse (a write cut
+/// short) are skipped; a missing log has none.
+pub fn read() -> io::Result<Vec<Entry>> {
+    let path = path();
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(parse(&content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(io::Error::new(e.kind(), format!("{}: {e}", path.display()))),
+    }
+}
+
+fn parse(content: &str) -> Vec<Entry> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provenance_lines() {
+        let entry = Entry {
+            time: "2024-05-01T12:00:00Z".into(),
+            portal: "file-chooser".into(),
+            operation: "open-file".into(),
+            app_id: String::new(),
+            provenance: Provenance::Queued {
+                queue_id: "1714564800000-file-chooser".into(),
+            },
+            results: vec!["file:///tmp/a".into()],
+        };
+        let line = serde_json::to_string(&entry).unwrap();
+        assert_eq!(
+            line,
+            r#"{"time":"2024-05-01T12:00:00Z","portal":"file-chooser","operation":"open-file","provenance":{"source":"queued","queue_id":"1714564800000-file-chooser"},"results":["file:///tmp/a"]}"#
+        );
+
+        let session = r#"{"time":"t","portal":"screenshot","operation":"screenshot","app_id":"org.example.App","provenance":{"source":"session","session_id":"s1","defaults":1},"results":[]}"#;
+        let parsed = parse(&format!("{line}\n{{\"time\":\n{session}\n"));
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0], entry);
+        assert_eq!(
+            parsed[1].provenance.to_string(),
+            "session s1, 1 from defaults"
+        );
+        assert_eq!(
+            Provenance::Decision { defaults: true }.to_string(),
+            "remembered decision, results from defaults"
+        );
+    }
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::path::PathBuf;
+
+use crate::uri;
 
 /// Top-level family for an intent.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -53,6 +56,17 @@ impl IntentItem {
             Self::Color(_) => IntentFamily::Color,
         }
     }
+
+    /// Whether both items name the same thing; paths compare as files,
+    /// whatever their spelling (see [`uri::key`])
+    pub fn same_as(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Path(a), Self::Path(b)) | (Self::Directory(a), Self::Directory(b)) => {
+                uri::same_entry(a, b)
+            }
+            _ => self == other,
+        }
+    }
 }
 
 impl fmt::Display for IntentItem {
@@ -68,7 +82,8 @@ impl fmt::Display for IntentItem {
 /// Merge operation for applying items to an intent.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MergeOp {
-    /// Merge new items into the intent (compatible families only, promotes cardinality).
+    /// Merge new items into the intent (compatible families only, promotes cardinality),
+    /// collapsing repeats (see [`Intent::normalize`]).
     Add,
     /// Replace the entire intent with the given items.
     Set,
@@ -98,6 +113,10 @@ pub struct Intent {
     pub family: IntentFamily,
     pub cardinality: Cardinality,
     pub items: Vec<IntentItem>,
+    /// Keep items queued more than once (`--keep-repeats`) instead of
+    /// collapsing them (see [`Intent::normalize`])
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_repeats: bool,
 }
 
 impl Intent {
@@ -108,6 +127,7 @@ impl Intent {
             family: item.family(),
             cardinality: Cardinality::Single,
             items: vec![item],
+            keep_repeats: false,
         }
     }
 
@@ -130,6 +150,7 @@ impl Intent {
             family,
             cardinality: Cardinality::Multi,
             items,
+            keep_repeats: false,
         })
     }
 
@@ -140,6 +161,7 @@ impl Intent {
             family,
             cardinality: Cardinality::Single,
             items: Vec::new(),
+            keep_repeats: false,
         }
     }
 
@@ -206,16 +228,42 @@ impl Intent {
                         self.family
                     ));
                 } else {
-                    self.items.extend_from_slice(new_items);
+                    for item in new_items {
+                        if self.keep_repeats || !self.items.iter().any(|held| held.same_as(item)) {
+                            self.items.push(item.clone());
+                        }
+                    }
                     if self.items.len() > 1 {
                         self.cardinality = Cardinality::Multi;
                     }
                 }
+                self.normalize();
             }
         }
         Ok(())
     }
 
+    /// Collapse items queued more than once, in any spelling, to their first
+    /// occurrence, unless [`keep_repeats`](Self::keep_repeats) is set.
+    /// Returns the number of items dropped.
+    pub fn normalize(&mut self) -> usize {
+        if self.keep_repeats {
+            return 0;
+        }
+        let before = self.items.len();
+        let mut kept: Vec<IntentItem> = Vec::with_capacity(before);
+        for item in self.items.drain(..) {
+            if !kept.iter().any(|held| held.same_as(&item)) {
+                kept.push(item);
+            }
+        }
+        self.items = kept;
+        if self.items.len() <= 1 {
+            self.cardinality = Cardinality::Single;
+        }
+        before - self.items.len()
+    }
+
     /// Remove matching items from the intent.
     pub fn remove(&mut self, items: &[IntentItem]) -> Result<usize, String> {
         if items.is_empty() {
@@ -245,7 +293,8 @@ impl Intent {
         }
 
         let original_len = self.items.len();
-        self.items.retain(|item| !items.contains(item));
+        self.items
+            .retain(|item| !items.iter().any(|removed| removed.same_as(item)));
         self.cardinality = if self.items.len() > 1 {
             Cardinality::Multi
         } else {
@@ -305,15 +354,68 @@ impl Default for Intent {
             family: IntentFamily::Path,
             cardinality: Cardinality::Single,
             items: Vec::new(),
+            keep_repeats: false,
         }
     }
 }
 
+/// How the pending queue and an open session's selection differ, entries
+/// compared in any spelling (see [`uri::key`])
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueDiff {
+    /// Queued entries the session does not have: what merging adds
+    pub queued_only: Vec<String>,
+    /// Session entries the queue does not have
+    pub session_only: Vec<String>,
+    /// Entries in both, as the session has them
+    pub both: Vec<String>,
+}
+
+impl QueueDiff {
+    pub fn new(session: &[String], queued: &[String]) -> Self {
+        let mut diff = Self::default();
+        for entry in session {
+            if queued.iter().any(|q| uri::same_entry(q, entry)) {
+                diff.both.push(entry.clone());
+            } else {
+                diff.session_only.push(entry.clone());
+            }
+        }
+        diff.queued_only = queued
+            .iter()
+            .filter(|q| !session.iter().any(|entry| uri::same_entry(q, entry)))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    /// Whether a submit has to choose: the session has a selection and the
+    /// queue would add to it
+    pub fn diverges(&self) -> bool {
+        let has_selection = !self.session_only.is_empty() || !self.both.is_empty();
+        has_selection && !self.queued_only.is_empty()
+    }
+}
+
 /// Queue storage for pending intent.
 pub mod queue {
     use super::Intent;
+    use crate::protocol::{QueuePolicy, SortOrder};
+    use serde::{Deserialize, Serialize};
     use std::path::{Path, PathBuf};
 
+    /// Failed attempts to apply a pending intent after which it is dropped
+    pub const MAX_ATTEMPTS: u32 = 3;
+
+    /// Failed attempts to apply a pending intent to a session
+    /// (`failures.json`)
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Failures {
+        pub attempts: u32,
+        /// Why the last attempt failed
+        pub last_error: String,
+    }
+
     /// Read pending intent from a directory (looks for `intent.json`).
     pub fn read(pending_dir: &Path) -> Option<Intent> {
         let path = pending_dir.join("intent.json");
@@ -321,13 +423,14 @@ pub mod queue {
         serde_json::from_str(&content).ok()
     }
 
-    /// Write pending intent to a directory.
+    /// Write pending intent to a directory. A new intent starts without
+    /// failed attempts.
     pub fn write(pending_dir: &Path, intent: &Intent) -> std::io::Result<()> {
-        std::fs::create_dir_all(pending_dir)?;
+        crate::files::create_private_dir_all(pending_dir)?;
         let path = pending_dir.join("intent.json");
         let content = serde_json::to_string_pretty(intent)?;
-        std::fs::write(path, content)?;
-        Ok(())
+        crate::files::write_atomic(&path, content)?;
+        clear_failures(pending_dir)
     }
 
     /// Clear pending intent.
@@ -336,13 +439,60 @@ pub mod queue {
         if path.exists() {
             std::fs::remove_file(&path)?;
         }
-        Ok(())
+        clear_failures(pending_dir)
+    }
+
+    /// Failed attempts to apply the pending intent, if any
+    pub fn read_failures(pending_dir: &Path) -> Option<Failures> {
+        let content = std::fs::read_to_string(pending_dir.join("failures.json")).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Count a failed attempt to apply the pending intent. Returns the
+    /// failures so far, this one included.
+    pub fn record_failure(pending_dir: &Path, error: &str) -> std::io::Result<Failures> {
+        let failures = Failures {
+            attempts: read_failures(pending_dir).map_or(0, |f| f.attempts) + 1,
+            last_error: error.to_string(),
+        };
+        let content = serde_json::to_string_pretty(&failures)?;
+        crate::files::write_atomic(&pending_dir.join("failures.json"), content)?;
+        Ok(failures)
+    }
+
+    fn clear_failures(pending_dir: &Path) -> std::io::Result<()> {
+        match std::fs::remove_file(pending_dir.join("failures.json")) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
     }
 
     /// Path to the intent file.
     pub fn intent_path(pending_dir: &Path) -> PathBuf {
         pending_dir.join("intent.json")
     }
+
+    /// Read the per-submission queue policy override (`policy` file).
+    pub fn read_policy(submission_dir: &Path) -> Option<QueuePolicy> {
+        let content = std::fs::read_to_string(submission_dir.join("policy")).ok()?;
+        content.trim().parse().ok()
+    }
+
+    /// Record a queue policy override for a queued submission.
+    pub fn write_policy(submission_dir: &Path, policy: QueuePolicy) -> std::io::Result<()> {
+        crate::files::write_atomic(&submission_dir.join("policy"), format!("{policy}\n"))
+    }
+
+    /// Read the result order requested for a queued submission (`sort` file).
+    pub fn read_sort(submission_dir: &Path) -> Option<SortOrder> {
+        let content = std::fs::read_to_string(submission_dir.join("sort")).ok()?;
+        content.trim().parse().ok()
+    }
+
+    /// Record the result order for a queued submission.
+    pub fn write_sort(submission_dir: &Path, sort: SortOrder) -> std::io::Result<()> {
+        crate::files::write_atomic(&submission_dir.join("sort"), format!("{sort}\n"))
+    }
 }
 
 /// Parse a string value into a typed intent item, resolving relative paths.
@@ -357,23 +507,42 @@ pub fn parse_item(family: &str, value: &str) -> Result<IntentItem, String> {
     }
 }
 
+/// `value` as an absolute path in NFC, or as its URI when it is not UTF-8
+/// (see [`uri::encode_os`])
 fn resolve_path_value(value: &str) -> String {
-    let value = value.strip_prefix("file://").unwrap_or(value);
-    let path = std::path::Path::new(value);
+    let path = PathBuf::from(uri::decode_os(value).into_owned());
     let resolved = if path.is_absolute() {
-        path.to_path_buf()
+        path
     } else if let Ok(cwd) = std::env::current_dir() {
         cwd.join(path)
     } else {
-        path.to_path_buf()
+        path
     };
-    resolved.to_string_lossy().into_owned()
+    match resolved.to_str() {
+        Some(text) => crate::input::normalize_path(text).into_owned(),
+        None => uri::from_path(&resolved),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn queue_diff_compares_spellings() {
+        let session = vec!["/tmp/a b".to_string(), "/tmp/c".to_string()];
+        let queued = vec!["file:///tmp/a%20b".to_string(), "/tmp/d".to_string()];
+        let diff = QueueDiff::new(&session, &queued);
+        assert_eq!(diff.both, ["/tmp/a b"]);
+        assert_eq!(diff.session_only, ["/tmp/c"]);
+        assert_eq!(diff.queued_only, ["/tmp/d"]);
+        assert!(diff.diverges());
+
+        // Nothing selected in the session, or nothing new in the queue
+        assert!(!QueueDiff::new(&[], &queued).diverges());
+        assert!(!QueueDiff::new(&session, &session[..1]).diverges());
+    }
+
     #[test]
     fn single_path_intent() {
         let mut intent = Intent::single(IntentItem::Path("/home/me/foo.txt".into()));
@@ -432,6 +601,42 @@ mod tests {
         assert_eq!(intent.len(), 2);
     }
 
+    #[test]
+    fn repeats_collapse_unless_kept() {
+        let path = |p: &str| IntentItem::Path(p.into());
+        let mut intent = Intent::empty(IntentFamily::Path);
+        intent
+            .apply(&[path("/tmp/a"), path("file:///tmp/a")], MergeOp::Add)
+            .unwrap();
+        assert_eq!(intent.items, [path("/tmp/a")]);
+        assert_eq!(intent.cardinality, Cardinality::Single);
+
+        intent.set(path("/tmp/b"));
+        intent.add(path("/tmp/b")).unwrap();
+        assert_eq!(intent.len(), 1);
+
+        // Written by something else, e.g. a script
+        let mut written =
+            Intent::multi(IntentFamily::Path, vec![path("/tmp/a"), path("/tmp/a")]).unwrap();
+        assert_eq!(written.normalize(), 1);
+        assert_eq!(written.cardinality, Cardinality::Single);
+
+        let mut repeated = Intent {
+            keep_repeats: true,
+            ..Intent::single(path("/tmp/a"))
+        };
+        repeated.add(path("/tmp/a")).unwrap();
+        assert_eq!(repeated.normalize(), 0);
+        assert_eq!(repeated.len(), 2);
+        let json = serde_json::to_string(&repeated).unwrap();
+        assert_eq!(serde_json::from_str::<Intent>(&json).unwrap(), repeated);
+        assert!(
+            !serde_json::to_string(&written)
+                .unwrap()
+                .contains("keep_repeats")
+        );
+    }
+
     #[test]
     fn queue_roundtrip() {
         use tempfile::tempdir;
@@ -447,6 +652,27 @@ mod tests {
         assert_eq!(loaded, intent);
     }
 
+    #[test]
+    fn queue_failures_count_until_rewritten() {
+        use tempfile::tempdir;
+
+        let dir = tempdir().unwrap();
+        let intent = Intent::single(IntentItem::Path("/tmp/test.png".into()));
+        queue::write(dir.path(), &intent).unwrap();
+        assert_eq!(queue::read_failures(dir.path()), None);
+
+        queue::record_failure(dir.path(), "first").unwrap();
+        let failures = queue::record_failure(dir.path(), "second").unwrap();
+        assert_eq!(failures.attempts, 2);
+        assert_eq!(queue::read_failures(dir.path()), Some(failures));
+
+        queue::write(dir.path(), &intent).unwrap();
+        assert_eq!(queue::read_failures(dir.path()), None);
+        queue::record_failure(dir.path(), "third").unwrap();
+        queue::clear(dir.path()).unwrap();
+        assert_eq!(queue::read_failures(dir.path()), None);
+    }
+
     #[test]
     fn parse_path_resolves_relative() {
         let item = parse_item("path", "foo.txt").unwrap();
@@ -477,4 +703,29 @@ mod tests {
             _ => panic!("expected directory item"),
         }
     }
+
+    #[test]
+    fn paths_match_across_spellings() {
+        let mut intent = Intent::single(IntentItem::Path("/tmp/a b".into()));
+        intent
+            .apply(
+                &[
+                    IntentItem::Path("file:///tmp/a%20b".into()),
+                    IntentItem::Path("/tmp/c".into()),
+                ],
+                MergeOp::Add,
+            )
+            .unwrap();
+        assert_eq!(intent.values(), ["/tmp/a b", "/tmp/c"]);
+
+        let removed = intent
+            .remove(&[IntentItem::Path("file://localhost/tmp/c".into())])
+            .unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(intent.cardinality, Cardinality::Single);
+        assert_eq!(
+            parse_item("path", "file:///tmp/a%20b").unwrap(),
+            IntentItem::Path("/tmp/a b".into())
+        );
+    }
 }
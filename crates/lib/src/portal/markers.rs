@@ -0,0 +1,142 @@
+//! Markers on file chooser selection entries, for `portty show`
+//!
+//! Each entry is resolved like a submission resolves it (`file://` URIs,
+//! absolute paths, or names relative to the current folder) and checked
+//! against the file system: a file that does not exist, a directory, a path
+//! outside the current folder, or a second entry for a file already in the
+//! selection (after symlinks and `..` are resolved).
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+
+use super::file_chooser::{SelectionMode, SessionOptions, resolve_path_as_given};
+
+/// Something worth knowing about one selection entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    /// Nothing exists at the path
+    Missing,
+    /// The path is a directory
+    Directory,
+    /// The path is not inside the current folder
+    Outside,
+    /// An earlier entry names the same file
+    Duplicate,
+}
+
+impl Marker {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::Directory => "dir",
+            Self::Outside => "outside",
+            Self::Duplicate => "duplicate",
+        }
+    }
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Marks selection entries one at a time, remembering earlier ones to spot
+/// duplicates
+#[derive(Debug)]
+pub struct Inspector {
+    mode: SelectionMode,
+    current_folder: Option<PathBuf>,
+    /// Canonical form of the folder, to compare canonical paths against
+    canonical_folder: Option<PathBuf>,
+    seen: HashSet<PathBuf>,
+}
+
+impl Inspector {
+    pub fn new(options: &SessionOptions) -> Self {
+        let current_folder = options
+            .current_folder
+            .as_deref()
+            .filter(|folder| !folder.is_empty())
+            .map(PathBuf::from);
+        let canonical_folder = current_folder
+            .as_deref()
+            .and_then(|folder| folder.canonicalize().ok());
+        Self {
+            mode: options.mode,
+            current_folder,
+            canonical_folder,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Markers of `entry`, in [`Marker`] order
+    pub fn inspect(&mut self, entry: &str) -> Vec<Marker> {
+        let path = resolve_path_as_given(entry, self.current_folder.as_deref());
+        let canonical = path.canonicalize().ok();
+        let mut markers = Vec::new();
+
+        match path.metadata() {
+            Ok(meta) if meta.is_dir() => markers.push(Marker::Directory),
+            Ok(_) => {}
+            // A save target is created by the app
+            Err(_) if self.mode == SelectionMode::Save => {}
+            Err(_) => markers.push(Marker::Missing),
+        }
+        if let Some(folder) = &self.canonical_folder
+            && !canonical.as_deref().unwrap_or(&path).starts_with(folder)
+        {
+            markers.push(Marker::Outside);
+        }
+        if !self.seen.insert(canonical.unwrap_or(path)) {
+            markers.push(Marker::Duplicate);
+        }
+        markers
+    }
+
+    /// Whether `marker` may keep the selection from being accepted as
+    /// intended: anything but a directory in a session that picks folders
+    pub fn is_problem(&self, marker: Marker) -> bool {
+        marker != Marker::Directory || !self.mode.picks_folder()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_missing_dirs_outside_and_duplicates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::write(root.join("a.txt"), "").unwrap();
+        std::os::unix::fs::symlink(root.join("a.txt"), root.join("link")).unwrap();
+
+        let options = SessionOptions {
+            current_folder: Some(root.display().to_string()),
+            ..Default::default()
+        };
+        let mut inspector = Inspector::new(&options);
+        assert!(inspector.inspect("a.txt").is_empty());
+        assert_eq!(inspector.inspect("sub"), [Marker::Directory]);
+        assert_eq!(inspector.inspect("gone"), [Marker::Missing]);
+        assert_eq!(
+            inspector.inspect(&root.join("link").display().to_string()),
+            [Marker::Duplicate]
+        );
+        assert_eq!(inspector.inspect("/"), [Marker::Directory, Marker::Outside]);
+        assert!(inspector.is_problem(Marker::Directory));
+
+        let save = SessionOptions {
+            mode: SelectionMode::Save,
+            ..Default::default()
+        };
+        assert!(
+            Inspector::new(&save)
+                .inspect("/nonexistent/new.txt")
+                .is_empty()
+        );
+    }
+}
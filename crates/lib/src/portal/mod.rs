@@ -1,14 +1,35 @@
+#[cfg(feature = "portal-dynamic-launcher")]
+pub mod dynamic_launcher;
+#[cfg(feature = "portal-file-chooser")]
+pub mod exclude;
 #[cfg(feature = "portal-file-chooser")]
 pub mod file_chooser;
+#[cfg(feature = "portal-file-chooser")]
+pub mod filter_hints;
+pub mod history;
 pub mod intent;
+#[cfg(feature = "portal-file-chooser")]
+pub mod markers;
+pub mod options;
+#[cfg(feature = "portal-file-chooser")]
+pub mod recent;
+#[cfg(feature = "portal-remote-desktop")]
+pub mod remote_desktop;
+#[cfg(feature = "portal-file-chooser")]
+pub mod reset;
 #[cfg(feature = "portal-screenshot")]
 pub mod screenshot;
+#[cfg(feature = "portal-file-chooser")]
+pub mod selection_file;
+pub mod snapshot;
 
-pub use intent::{Cardinality, Intent, IntentFamily, IntentItem, MergeOp, parse_item};
+pub use intent::{Cardinality, Intent, IntentFamily, IntentItem, MergeOp, QueueDiff, parse_item};
 
+use std::collections::HashSet;
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::files;
+use crate::{Operation, Portal, files, uri};
 
 /// Result of adding entries to a submission
 pub enum AddResult {
@@ -23,8 +44,7 @@ pub enum AddResult {
 /// Auto-detects the portal type and applies smart edit behavior
 /// (e.g. replace vs append) based on portal constraints.
 pub struct SessionContext {
-    pub portal: String,
-    pub operation: String,
+    pub operation: Operation,
     session_dir: PathBuf,
 }
 
@@ -32,55 +52,58 @@ impl SessionContext {
     /// Build from session dir. Detects portal/operation:
     /// 1. Try PORTTY_PORTAL + PORTTY_OPERATION env vars (zero I/O, always set in session terminals)
     /// 2. Fallback: read <session_dir>/portal file (for headless mode / external tools)
+    ///
+    /// Fails for a standby session that no request has adopted yet.
     pub fn from_session_dir(dir: impl Into<PathBuf>) -> std::io::Result<Self> {
         let session_dir = dir.into();
 
         // Try env vars first (zero I/O)
-        if let (Ok(portal), Ok(operation)) = (
+        let (portal, operation) = match (
             std::env::var("PORTTY_PORTAL"),
             std::env::var("PORTTY_OPERATION"),
         ) {
-            return Ok(Self {
-                portal,
-                operation,
-                session_dir,
-            });
-        }
-
-        // Fallback: read portal file
-        let portal_file = session_dir.join("portal");
-        let content = std::fs::read_to_string(&portal_file)?;
-        let mut lines = content.lines();
-        let portal = lines.next().unwrap_or("unknown").to_string();
-        let operation = lines.next().unwrap_or("unknown").to_string();
+            (Ok(portal), Ok(operation)) => (portal, operation),
+            _ => {
+                // Fallback: read portal file
+                let content = std::fs::read_to_string(session_dir.join("portal"))?;
+                let mut lines = content.lines();
+                let portal = lines.next().unwrap_or_default().to_string();
+                let operation = lines.next().unwrap_or_default().to_string();
+                (portal, operation)
+            }
+        };
+        let operation = Operation::of(&portal, &operation)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
 
         Ok(Self {
-            portal,
             operation,
             session_dir,
         })
     }
 
-    /// Read and parse options.json
+    /// Read options.json (with lists from sidecar files loaded back in)
     pub fn read_options(&self) -> std::io::Result<serde_json::Value> {
-        let path = self.session_dir.join("options.json");
-        let content = std::fs::read_to_string(&path)?;
-        serde_json::from_str(&content).map_err(std::io::Error::other)
+        options::read(&self.session_dir)
     }
 
     /// Portal-aware add: auto-detects single-select -> replace
     pub fn add_entries(&self, entries: &[String]) -> std::io::Result<AddResult> {
         let sub_path = self.submission_path();
-        match self.portal.as_str() {
+        match self.operation.portal() {
             #[cfg(feature = "portal-file-chooser")]
-            "file-chooser" => {
+            Portal::FileChooser => {
                 let options_json = self.read_options()?;
                 let opts: file_chooser::SessionOptions =
                     serde_json::from_value(options_json).map_err(std::io::Error::other)?;
                 file_chooser::add_entries(&sub_path, entries, &opts)
             }
             #[cfg(feature = "portal-screenshot")]
-            "screenshot" => screenshot::add_entries(&sub_path, entries),
+            Portal::Screenshot => screenshot::add_entries(&sub_path, entries),
+            #[cfg(feature = "portal-remote-desktop")]
+            Portal::RemoteDesktop => remote_desktop::add_entries(&sub_path, entries),
+            #[cfg(feature = "portal-dynamic-launcher")]
+            Portal::DynamicLauncher => dynamic_launcher::add_entries(&sub_path, entries),
+            #[allow(unreachable_patterns)]
             _ => {
                 files::append_lines(&sub_path, entries)?;
                 Ok(AddResult::Appended(entries.len()))
@@ -88,11 +111,12 @@ impl SessionContext {
         }
     }
 
-    /// Materialize a typed intent for this session.
+    /// Materialize a typed intent for this session. An intent the session
+    /// does not accept is an `InvalidInput` error.
     pub fn materialize_intent(&self, intent: &Intent) -> std::io::Result<Vec<String>> {
         let options = self.read_options()?;
-        materialize_intent(&self.portal, &self.operation, intent, &options)
-            .map_err(std::io::Error::other)
+        materialize_intent(self.operation, intent, &options)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
     }
 
     /// Portal-aware add for typed intent.
@@ -119,7 +143,7 @@ impl SessionContext {
         let options = self
             .read_options()
             .map_err(|e| format!("failed to read options: {e}"))?;
-        validate(&self.portal, &self.operation, &entries, &options)
+        validate(self.operation, &entries, &options)
     }
 
     /// Path to the submission file
@@ -133,50 +157,117 @@ impl SessionContext {
 /// Dispatches to per-portal validate functions that both check constraints
 /// and transform entries to their final form (e.g. resolving relative paths to URIs).
 pub fn validate(
-    portal: &str,
-    operation: &str,
+    operation: Operation,
     entries: &[String],
     options: &serde_json::Value,
 ) -> Result<Vec<String>, String> {
-    match portal {
+    match operation.portal() {
         #[cfg(feature = "portal-file-chooser")]
-        "file-chooser" => {
+        Portal::FileChooser => {
             let opts: file_chooser::SessionOptions = serde_json::from_value(options.clone())
                 .map_err(|e| format!("invalid options: {e}"))?;
             file_chooser::validate(operation, entries, &opts)
         }
         #[cfg(feature = "portal-screenshot")]
-        "screenshot" => screenshot::validate(operation, entries),
+        Portal::Screenshot => {
+            screenshot::validate(operation, entries, screenshot::accepts_series(options))
+        }
+        #[cfg(feature = "portal-remote-desktop")]
+        Portal::RemoteDesktop => remote_desktop::validate(operation, entries),
+        #[cfg(feature = "portal-dynamic-launcher")]
+        Portal::DynamicLauncher => {
+            let opts: dynamic_launcher::SessionOptions = serde_json::from_value(options.clone())
+                .map_err(|e| format!("invalid options: {e}"))?;
+            dynamic_launcher::validate(operation, entries, &opts)
+        }
+        #[allow(unreachable_patterns)]
         _ => Ok(entries.to_vec()),
     }
 }
 
+/// Entry that approves a permission-style request (remote desktop sessions,
+/// launcher install tokens).
+///
+/// Approval has to be explicit: an empty submission (e.g. closing the
+/// terminal) denies the request.
+pub const APPROVE: &str = "approve";
+
+/// Whether an empty submission is a meaningful result for an operation.
+///
+/// For these operations an explicit empty submit (`submit --allow-empty` or the
+/// `allow_empty` config option) returns success with zero results instead of
+/// cancelling. Operations that need a target (save, screenshot) never allow it.
+pub fn allows_empty(operation: Operation) -> bool {
+    operation == Operation::OpenFile
+}
+
 /// Materialize a typed intent into final portal submission entries.
 pub fn materialize_intent(
-    portal: &str,
-    operation: &str,
+    operation: Operation,
     intent: &Intent,
     options: &serde_json::Value,
 ) -> Result<Vec<String>, String> {
-    match portal {
+    let mut entries = match operation.portal() {
         #[cfg(feature = "portal-file-chooser")]
-        "file-chooser" => {
+        Portal::FileChooser => {
             let opts: file_chooser::SessionOptions = serde_json::from_value(options.clone())
                 .map_err(|e| format!("invalid options: {e}"))?;
             file_chooser::materialize_intent(operation, intent, &opts)
         }
         #[cfg(feature = "portal-screenshot")]
-        "screenshot" => screenshot::materialize_intent(operation, intent),
-        _ => Err(format!(
+        Portal::Screenshot => {
+            screenshot::materialize_intent(operation, intent, screenshot::accepts_series(options))
+        }
+        portal => Err(format!(
             "unsupported portal for intent materialization: {portal}"
         )),
+    }?;
+    // Items in different spellings, or relative to different folders, may
+    // still resolve to the same entry
+    if !intent.keep_repeats {
+        let mut seen = HashSet::new();
+        entries.retain(|entry| seen.insert(uri::key(entry).into_owned()));
     }
+    Ok(entries)
+}
+
+/// `time` as an ISO 8601 UTC timestamp (`2024-05-01T12:00:00Z`)
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, rest) = (secs / 86400, secs % 86400);
+    // Days to civil date, after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn timestamps() {
+        let at = |secs| utc_timestamp(UNIX_EPOCH + std::time::Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_714_564_800), "2024-05-01T12:00:00Z");
+    }
+
     #[cfg(feature = "portal-file-chooser")]
     #[test]
     fn materialize_open_file_multi_path_intent() {
@@ -190,6 +281,7 @@ mod tests {
             candidates: vec![],
             filters: vec![],
             current_filter: None,
+            ..Default::default()
         })
         .unwrap();
         let intent = Intent::multi(
@@ -201,18 +293,25 @@ mod tests {
         )
         .unwrap();
 
-        let entries = materialize_intent("file-chooser", "open-file", &intent, &options).unwrap();
+        let entries = materialize_intent(Operation::OpenFile, &intent, &options).unwrap();
         assert_eq!(entries.len(), 2);
         assert!(entries[0].starts_with("file:///"));
     }
 
+    #[test]
+    fn empty_submission_only_for_open_file() {
+        assert!(allows_empty(Operation::OpenFile));
+        assert!(!allows_empty(Operation::SaveFile));
+        assert!(!allows_empty(Operation::Screenshot));
+    }
+
     #[cfg(feature = "portal-screenshot")]
     #[test]
     fn materialize_pick_color_requires_single_color() {
         let intent = Intent::single(IntentItem::Color("#ff00aa".into()));
         let options = serde_json::json!({});
 
-        let entries = materialize_intent("screenshot", "pick-color", &intent, &options).unwrap();
+        let entries = materialize_intent(Operation::PickColor, &intent, &options).unwrap();
         assert_eq!(entries, vec!["#ff00aa"]);
     }
 }
@@ -0,0 +1,395 @@
+//! Session option files.
+//!
+//! `options.json` holds the portal options of a session. Long string lists
+//! (e.g. the candidate filenames of a SaveFiles request with thousands of
+//! files) are moved out into a sidecar file with one item per line, and
+//! `options.json` refers to it as `{"$lines": "options.<key>"}`. This keeps
+//! `options.json` small enough to print and edit, and lets shell tools stream
+//! the list directly (`fzf < "$PORTTY_DIR/options.candidates"`).
+//!
+//! [`read`] resolves the references again, so consumers always see the full
+//! options.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+/// Options file in the session dir
+pub const OPTIONS_FILE: &str = "options.json";
+
+/// Lists with more items than this are moved to a sidecar file
+pub const SPILL_THRESHOLD: usize = 256;
+
+/// Key of the object that marks a list moved to a sidecar file
+const LINES_REF: &str = "$lines";
+
+/// Options a session may change in its options.json, with `portty
+/// conflicts`, `portty next-file`, and `portty choices`; the rest are the
+/// request's
+pub const SESSION_CHOICES: &[&str] = &["conflicts", "per_file", "chosen"];
+
+/// Write `options` into `dir`, moving long string lists to sidecar files.
+///
+/// Only top-level lists of non-empty, single-line strings are moved, so that
+/// reading them back line by line is lossless.
+pub fn write(dir: &Path, options: &Value) -> io::Result<()> {
+    let json = match options {
+        Value::Object(fields) => {
+            let mut out = Map::with_capacity(fields.len());
+            for (key, value) in fields {
+                let value = match spillable(key, value) {
+                    Some(items) => {
+                        let file = format!("options.{key}");
+                        write_items(&dir.join(&file), items)?;
+                        Value::Object(Map::from_iter([(LINES_REF.to_string(), file.into())]))
+                    }
+                    None => value.clone(),
+                };
+                out.insert(key.clone(), value);
+            }
+            serde_json::to_string_pretty(&out)
+        }
+        other => serde_json::to_string_pretty(other),
+    }
+    .map_err(io::Error::other)?;
+    crate::files::write_atomic(&dir.join(OPTIONS_FILE), json)
+}
+
+/// Read the options of the session in `dir`, loading lists stored in sidecar
+/// files back in place.
+pub fn read(dir: &Path) -> io::Result<Value> {
+    let content = fs::read_to_string(dir.join(OPTIONS_FILE))?;
+    let mut options: Value = serde_json::from_str(&content).map_err(io::Error::other)?;
+    if let Value::Object(fields) = &mut options {
+        for value in fields.values_mut() {
+            if let Some(file) = lines_ref(value) {
+                let items = read_items(&dir.join(file))?;
+                *value = Value::Array(items);
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Items of `value` if it should be stored in a sidecar file
+fn spillable<'a>(key: &str, value: &'a Value) -> Option<&'a [Value]> {
+    let Value::Array(items) = value else {
+        return None;
+    };
+    let safe_key = !key.is_empty()
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+    let line_items = items
+        .iter()
+        .all(|item| matches!(item, Value::String(s) if !s.is_empty() && !s.contains(['\n', '\r'])));
+    (safe_key && items.len() > SPILL_THRESHOLD && line_items).then_some(items)
+}
+
+/// Sidecar file name if `value` is a `{"$lines": "<file>"}` reference
+fn lines_ref(value: &Value) -> Option<&str> {
+    let Value::Object(map) = value else {
+        return None;
+    };
+    match map.get(LINES_REF) {
+        Some(Value::String(file)) if map.len() == 1 && !file.contains('/') => Some(file),
+        _ => None,
+    }
+}
+
+fn write_items(path: &Path, items: &[Value]) -> io::Result<()> {
+    crate::files::replace_with(path, |file| {
+        let mut out = io::BufWriter::new(file);
+        for item in items {
+            if let Value::String(s) = item {
+                writeln!(out, "{s}")?;
+            }
+        }
+        out.flush()
+    })
+}
+
+fn read_items(path: &Path) -> io::Result<Vec<Value>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| Value::String(line.to_string()))
+        .collect())
+}
+
+/// A single option field, as printed by `portty get`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Mode,
+    Multiple,
+    Directory,
+    Folder,
+    Candidates,
+    Filters,
+    FilterHints,
+    Folders,
+}
+
+impl Field {
+    pub const ALL: [Self; 9] = [
+        Self::Title,
+        Self::Mode,
+        Self::Multiple,
+        Self::Directory,
+        Self::Folder,
+        Self::Candidates,
+        Self::Filters,
+        Self::FilterHints,
+        Self::Folders,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Mode => "mode",
+            Self::Multiple => "multiple",
+            Self::Directory => "directory",
+            Self::Folder => "folder",
+            Self::Candidates => "candidates",
+            Self::Filters => "filters",
+            Self::FilterHints => "filter-hints",
+            Self::Folders => "folders",
+        }
+    }
+
+    /// Value of this field in `options`, one line per item, or `None` if the
+    /// request does not set it.
+    ///
+    /// - `title`, `folder`: the string
+    /// - `mode`: `pick`, `save`, or `save-multiple`
+    /// - `multiple`, `directory`: `true` or `false` (save-multiple picks
+    ///   several files into a directory)
+    /// - `candidates`: one file name per line
+    /// - `filters`: one filter per line, its name and patterns separated by
+    ///   tabs (globs as written, MIME types as `type/subtype`)
+    /// - `filter-hints`: one filter per line with its globs and the number of
+    ///   files matching it in the current folder, e.g. `Images (*.png, *.jpg)
+    ///   — 42 matches here`; the current filter is marked `[current]`
+    /// - `folders`: folders to suggest when the session picks one (the
+    ///   current folder, XDG user dirs, home), one per line
+    pub fn get(self, options: &Value) -> Option<Vec<String>> {
+        let non_empty = |s: &str| (!s.is_empty()).then(|| vec![s.to_string()]);
+        let flag = |value: bool| Some(vec![value.to_string()]);
+        match self {
+            Self::Title => options.get("title")?.as_str().and_then(non_empty),
+            Self::Folder => options.get("current_folder")?.as_str().and_then(non_empty),
+            Self::Mode => {
+                let mode = match options.get("mode")? {
+                    Value::String(mode) if mode == "Save" => "save",
+                    Value::String(mode) if mode == "SaveMultiple" => "save-multiple",
+                    Value::Object(mode) if mode.contains_key("Pick") => "pick",
+                    _ => return None,
+                };
+                Some(vec![mode.to_string()])
+            }
+            Self::Multiple | Self::Directory => match options.get("mode")? {
+                Value::String(mode) if mode == "Save" => flag(false),
+                Value::String(mode) if mode == "SaveMultiple" => flag(true),
+                Value::Object(mode) => mode
+                    .get("Pick")?
+                    .get(self.as_str())?
+                    .as_bool()
+                    .and_then(flag),
+                _ => None,
+            },
+            Self::Candidates => {
+                let items: Vec<String> = options
+                    .get("candidates")?
+                    .as_array()?
+                    .iter()
+                    .filter_map(|item| item.as_str().map(String::from))
+                    .collect();
+                (!items.is_empty()).then_some(items)
+            }
+            Self::Filters => {
+                let filters: Vec<String> = options
+                    .get("filters")?
+                    .as_array()?
+                    .iter()
+                    .map(|filter| {
+                        let name = filter.get("name").and_then(Value::as_str).unwrap_or("");
+                        let patterns = filter
+                            .get("patterns")
+                            .and_then(Value::as_array)
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|pattern| {
+                                pattern
+                                    .get("Glob")
+                                    .or_else(|| pattern.get("MimeType"))
+                                    .and_then(Value::as_str)
+                            });
+                        std::iter::once(name)
+                            .chain(patterns)
+                            .collect::<Vec<_>>()
+                            .join("\t")
+                    })
+                    .collect();
+                (!filters.is_empty()).then_some(filters)
+            }
+            #[cfg(feature = "portal-file-chooser")]
+            Self::FilterHints => {
+                use super::file_chooser::Filter;
+                use super::filter_hints::{MimeGlobs, hints};
+
+                let filters: Vec<Filter> =
+                    serde_json::from_value(options.get("filters")?.clone()).ok()?;
+                if filters.is_empty() {
+                    return None;
+                }
+                let current = options
+                    .get("current_filter")
+                    .and_then(Value::as_u64)
+                    .map(|i| i as usize);
+                let folder = options.get("current_folder").and_then(Value::as_str);
+                Some(hints(
+                    &filters,
+                    current,
+                    folder.filter(|f| !f.is_empty()).map(Path::new),
+                    &MimeGlobs::load(),
+                ))
+            }
+            #[cfg(not(feature = "portal-file-chooser"))]
+            Self::FilterHints => None,
+            #[cfg(feature = "portal-file-chooser")]
+            Self::Folders => {
+                use super::file_chooser::{SelectionMode, candidate_folders};
+
+                let mode: SelectionMode =
+                    serde_json::from_value(options.get("mode")?.clone()).ok()?;
+                if !mode.picks_folder() {
+                    return None;
+                }
+                let folder = options.get("current_folder").and_then(Value::as_str);
+                let folders: Vec<String> =
+                    candidate_folders(folder.filter(|f| !f.is_empty()).map(Path::new))
+                        .iter()
+                        .map(|folder| folder.display().to_string())
+                        .collect();
+                (!folders.is_empty()).then_some(folders)
+            }
+            #[cfg(not(feature = "portal-file-chooser"))]
+            Self::Folders => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|field| field.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|f| f.as_str()).collect();
+                format!("unknown field: {s} (expected {})", names.join(", "))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn large_candidate_list_moves_to_sidecar() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path();
+        let candidates: Vec<String> = (0..50_000).map(|i| format!("file-{i}.txt")).collect();
+        let options = json!({
+            "title": "Save",
+            "mode": "save_multiple",
+            "candidates": candidates,
+            "filters": [],
+        });
+
+        write(dir, &options).unwrap();
+
+        let json = fs::read_to_string(dir.join(OPTIONS_FILE)).unwrap();
+        assert!(json.len() < 1024, "options.json is {} bytes", json.len());
+        let raw: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(raw["candidates"], json!({"$lines": "options.candidates"}));
+        assert_eq!(read(dir).unwrap(), options);
+    }
+
+    #[test]
+    fn short_or_multiline_lists_stay_inline() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path();
+        let mut many: Vec<String> = (0..=SPILL_THRESHOLD).map(|i| i.to_string()).collect();
+        many[0] = "line\nbreak".to_string();
+        let options = json!({"candidates": ["a.txt"], "names": many});
+
+        write(dir, &options).unwrap();
+
+        assert!(!dir.join("options.candidates").exists());
+        assert!(!dir.join("options.names").exists());
+        assert_eq!(read(dir).unwrap(), options);
+    }
+    #[test]
+    #[cfg(feature = "portal-file-chooser")]
+    fn fields_print_stable_values() {
+        use crate::portal::file_chooser::{Filter, FilterPattern, SelectionMode, SessionOptions};
+
+        let options = serde_json::to_value(SessionOptions {
+            title: "Open".to_string(),
+            mode: SelectionMode::Pick {
+                multiple: true,
+                directory: false,
+            },
+            current_folder: Some("/home/me".to_string()),
+            filters: vec![Filter {
+                name: "Images".to_string(),
+                patterns: vec![
+                    FilterPattern::Glob("*.png".to_string()),
+                    FilterPattern::MimeType("image/jpeg".to_string()),
+                ],
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        let get = |field: &str| field.parse::<Field>().unwrap().get(&options);
+
+        assert_eq!(get("title"), Some(vec!["Open".to_string()]));
+        assert_eq!(get("mode"), Some(vec!["pick".to_string()]));
+        assert_eq!(get("multiple"), Some(vec!["true".to_string()]));
+        assert_eq!(get("directory"), Some(vec!["false".to_string()]));
+        assert_eq!(get("folder"), Some(vec!["/home/me".to_string()]));
+        assert_eq!(get("candidates"), None);
+        assert_eq!(
+            get("filters"),
+            Some(vec!["Images\t*.png\timage/jpeg".to_string()])
+        );
+
+        let save = serde_json::to_value(SessionOptions {
+            mode: SelectionMode::SaveMultiple,
+            candidates: vec!["a.txt".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            Field::Mode.get(&save),
+            Some(vec!["save-multiple".to_string()])
+        );
+        assert_eq!(Field::Directory.get(&save), Some(vec!["true".to_string()]));
+        assert_eq!(Field::Title.get(&save), None);
+        assert!("size".parse::<Field>().is_err());
+    }
+}
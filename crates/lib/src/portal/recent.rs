@@ -0,0 +1,293 @@
+//! Recently used files, for `portty recent` and `portty select @recent:N`.
+//!
+//! The store is the XBEL file GTK and other toolkits share,
+//! `$XDG_DATA_HOME/recently-used.xbel`. porttyd adds the results of file
+//! chooser dialogs to it, as a GUI chooser would.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::uri;
+
+use super::selection_file::data_dir;
+use super::utc_timestamp;
+
+const BOOKMARK_NS: &str = "http://www.freedesktop.org/standards/desktop-bookmarks";
+
+/// Name recorded for requests of unsandboxed apps, which have no app id
+pub const DEFAULT_APP: &str = "portty";
+
+/// One recently used file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recent {
+    pub uri: String,
+    /// Last use, as an ISO 8601 timestamp
+    pub modified: String,
+    /// Apps that used it
+    pub apps: Vec<String>,
+}
+
+impl Recent {
+    /// Local path of the file; `None` for other URI schemes
+    pub fn path(&self) -> Option<PathBuf> {
+        uri::to_path(&self.uri)
+    }
+}
+
+/// The recently used store
+pub fn store_path() -> PathBuf {
+    data_dir().join("recently-used.xbel")
+}
+
+/// Recently used files, most recent first; only those used by `app` if
+/// given. A missing store has none.
+pub fn read(app: Option<&str>) -> io::Result<Vec<Recent>> {
+    let path = store_path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {e}", path.display()))),
+    };
+    let mut recent = parse(&content).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })?;
+    if let Some(app) = app {
+        recent.retain(|r| r.apps.iter().any(|a| a == app));
+    }
+    Ok(recent)
+}
+
+/// Bookmarks of an XBEL document, most recent first
+pub fn parse(xml: &str) -> Result<Vec<Recent>, String> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| e.to_string())?;
+    let mut recent: Vec<Recent> = doc
+        .root_element()
+        .children()
+        .filter(|node| node.has_tag_name("bookmark"))
+        .filter_map(|bookmark| {
+            let apps = bookmark
+                .descendants()
+                .filter(|node| node.has_tag_name((BOOKMARK_NS, "application")))
+                .filter_map(|app| app.attribute("name"))
+                .map(String::from)
+                .collect();
+            Some(Recent {
+                uri: bookmark.attribute("href")?.to_string(),
+                modified: bookmark
+                    .attribute("modified")
+                    .or_else(|| bookmark.attribute("added"))
+                    .unwrap_or_default()
+                    .to_string(),
+                apps,
+            })
+        })
+        .collect();
+    // ISO 8601 UTC timestamps sort as text
+    recent.sort_by(|a, b| b.modified.cmp(&a.modified));
+    Ok(recent)
+}
+
+/// The paths `spec` picks from `recent`: `@recent` is the most recent file,
+/// `@recent:N` the Nth (as numbered by `portty recent`), and `@recent:N-M`
+/// the Nth through the Mth.
+pub fn pick(spec: &str, recent: &[Recent]) -> Result<Vec<PathBuf>, String> {
+    let range = spec
+        .strip_prefix("@recent")
+        .ok_or_else(|| format!("expected @recent[:N[-M]], got {spec:?}"))?;
+    let (first, last) = match range.strip_prefix(':') {
+        None if range.is_empty() => (1, 1),
+        None => return Err(format!("expected @recent[:N[-M]], got {spec:?}")),
+        Some(range) => {
+            let number = |n: &str| match n.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => Err(format!("{spec}: {n:?} is not an entry number")),
+            };
+            match range.split_once('-') {
+                Some((first, last)) => (number(first)?, number(last)?),
+                None => (number(range)?, number(range)?),
+            }
+        }
+    };
+    if first > last {
+        return Err(format!("{spec}: empty range"));
+    }
+    if last > recent.len() {
+        return Err(format!(
+            "{spec}: only {} recently used file(s)",
+            recent.len()
+        ));
+    }
+    recent[first - 1..last]
+        .iter()
+        .map(|r| {
+            r.path()
+                .ok_or_else(|| format!("{} is not a local file", r.uri))
+        })
+        .collect()
+}
+
+/// Record `uris` as used now by `app`, adding them to the store (created
+/// if missing) or updating their time
+pub fn record(uris: &[String], app: &str) -> io::Result<()> {
+    let path = store_path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) if content.contains("</xbel>") => content,
+        Ok(_) => empty_store(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => empty_store(),
+        Err(e) => return Err(e),
+    };
+    let content = add_bookmarks(content, uris, app, &utc_timestamp(SystemTime::now()));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Other apps read the store at any time, so never leave it half written
+    crate::files::write_atomic(&path, content)
+}
+
+fn empty_store() -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <xbel version=\"1.0\" xmlns:bookmark=\"{BOOKMARK_NS}\" \
+         xmlns:mime=\"http://www.freedesktop.org/standards/shared-mime-info\">\n\
+         </xbel>\n"
+    )
+}
+
+/// `content` with a bookmark per URI: existing ones get `now` as their
+/// modified and visited time, new ones go before `</xbel>`
+fn add_bookmarks(mut content: String, uris: &[String], app: &str, now: &str) -> String {
+    for uri in uris {
+        let start = format!("<bookmark href=\"{}\"", escape(uri));
+        if let Some(at) = content.find(&start) {
+            let end = content[at..]
+                .find('>')
+                .map_or(content.len(), |end| at + end);
+            // Keep the `/` of a `<bookmark .../>`
+            let end = if content[..end].ends_with('/') {
+                end - 1
+            } else {
+                end
+            };
+            let tag = set_attribute(&content[at..end], "modified", now);
+            let tag = set_attribute(&tag, "visited", now);
+            content.replace_range(at..end, &tag);
+            continue;
+        }
+        let app = escape(app);
+        let bookmark = format!(
+            "  {start} added=\"{now}\" modified=\"{now}\" visited=\"{now}\">\n    \
+             <info>\n      <metadata owner=\"http://freedesktop.org\">\n        \
+             <bookmark:applications>\n          \
+             <bookmark:application name=\"{app}\" exec=\"&apos;{app} %u&apos;\" \
+             modified=\"{now}\" count=\"1\"/>\n        \
+             </bookmark:applications>\n      </metadata>\n    </info>\n  </bookmark>\n"
+        );
+        let end = content.rfind("</xbel>").unwrap_or(content.len());
+        content.insert_str(end, &bookmark);
+    }
+    content
+}
+
+/// `tag` with `name="value"`, replacing the attribute's old value
+fn set_attribute(tag: &str, name: &str, value: &str) -> String {
+    let key = format!(" {name}=\"");
+    match tag.find(&key) {
+        Some(at) => {
+            let from = at + key.len();
+            let to = tag[from..].find('"').map_or(tag.len(), |end| from + end);
+            format!("{}{value}{}", &tag[..from], &tag[to..])
+        }
+        None => format!("{tag}{key}{value}\""),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn at(secs: u64) -> String {
+        utc_timestamp(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn records_and_reads_back() {
+        let store = add_bookmarks(
+            empty_store(),
+            &["file:///a/one".into(), "file:///b/two%20words".into()],
+            "org.example.App",
+            &at(100),
+        );
+        let store = add_bookmarks(store, &["file:///a/one".into()], "portty", &at(200));
+
+        let recent = parse(&store).unwrap();
+        let uris: Vec<_> = recent.iter().map(|r| r.uri.as_str()).collect();
+        assert_eq!(uris, ["file:///a/one", "file:///b/two%20words"]);
+        assert_eq!(recent[0].modified, at(200));
+        assert_eq!(recent[1].apps, ["org.example.App"]);
+        assert_eq!(recent[1].path(), Some(PathBuf::from("/b/two words")));
+    }
+
+    #[test]
+    fn reads_gtk_store() {
+        let store = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xbel version="1.0"
+      xmlns:bookmark="http://www.freedesktop.org/standards/desktop-bookmarks"
+      xmlns:mime="http://www.freedesktop.org/standards/shared-mime-info"
+>
+  <bookmark href="file:///home/u/notes.txt" added="2024-01-01T10:00:00.123456Z" modified="2024-01-02T10:00:00.123456Z" visited="2024-01-02T10:00:00.123456Z">
+    <info>
+      <metadata owner="http://freedesktop.org">
+        <mime:mime-type type="text/plain"/>
+        <bookmark:applications>
+          <bookmark:application name="gedit" exec="&apos;gedit %u&apos;" modified="2024-01-02T10:00:00.123456Z" count="2"/>
+        </bookmark:applications>
+      </metadata>
+    </info>
+  </bookmark>
+  <bookmark href="https://example.com/" added="2024-01-03T10:00:00Z" modified="2024-01-03T10:00:00Z" visited="2024-01-03T10:00:00Z"/>
+</xbel>"#;
+        let recent = parse(store).unwrap();
+        assert_eq!(recent[0].uri, "https://example.com/");
+        assert_eq!(recent[1].apps, ["gedit"]);
+
+        assert_eq!(
+            pick("@recent:2", &recent).unwrap(),
+            [PathBuf::from("/home/u/notes.txt")]
+        );
+        assert!(pick("@recent", &recent).is_err());
+        assert!(pick("@recent:3", &recent).is_err());
+        assert!(pick("@recent:0", &recent).is_err());
+        assert!(pick("@recently", &recent).is_err());
+    }
+
+    #[test]
+    fn picks_ranges() {
+        let recent: Vec<_> = (1..=4)
+            .map(|i| Recent {
+                uri: format!("file:///f{i}"),
+                modified: at(100 - i),
+                apps: Vec::new(),
+            })
+            .collect();
+        assert_eq!(pick("@recent", &recent).unwrap(), [PathBuf::from("/f1")]);
+        assert_eq!(
+            pick("@recent:2-3", &recent).unwrap(),
+            [PathBuf::from("/f2"), PathBuf::from("/f3")]
+        );
+        assert!(pick("@recent:3-2", &recent).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Operation, files};
+
+use super::AddResult;
+
+pub use super::APPROVE;
+
+/// Device type bits from the RemoteDesktop portal (`types` / `AvailableDeviceTypes`)
+pub const KEYBOARD: u32 = 1;
+pub const POINTER: u32 = 2;
+pub const TOUCHSCREEN: u32 = 4;
+
+/// Names of the device types set in `types`
+pub fn device_names(types: u32) -> Vec<String> {
+    [
+        (KEYBOARD, "keyboard"),
+        (POINTER, "pointer"),
+        (TOUCHSCREEN, "touchscreen"),
+    ]
+    .into_iter()
+    .filter(|(bit, _)| types & bit != 0)
+    .map(|(_, name)| name.to_string())
+    .collect()
+}
+
+/// Session options for remote desktop portal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionOptions {
+    pub app_id: String,
+    /// Parent window identifier, e.g. `wayland:<handle>` or `x11:<xid>` (may be empty)
+    #[serde(default)]
+    pub parent_window: String,
+    /// Device types the app asked to control (`keyboard`, `pointer`, `touchscreen`)
+    pub devices: Vec<String>,
+}
+
+/// Validate a remote desktop submission: a single `approve` entry.
+pub fn validate(operation: Operation, entries: &[String]) -> Result<Vec<String>, String> {
+    match entries {
+        [] => Err("No entries in submission".to_string()),
+        [entry] if entry == APPROVE => Ok(entries.to_vec()),
+        _ => Err(format!(
+            "{operation} expects a single '{APPROVE}' entry (cancel to deny)"
+        )),
+    }
+}
+
+/// Smart add entries: the approval always replaces (single entry).
+pub fn add_entries(sub_path: &Path, entries: &[String]) -> std::io::Result<AddResult> {
+    files::write_lines(sub_path, entries)?;
+    Ok(AddResult::Replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_names_from_bits() {
+        assert_eq!(
+            device_names(KEYBOARD | TOUCHSCREEN),
+            ["keyboard", "touchscreen"]
+        );
+        assert!(device_names(0).is_empty());
+    }
+
+    #[test]
+    fn validate_requires_explicit_approval() {
+        assert_eq!(
+            validate(Operation::Start, &[APPROVE.to_string()]),
+            Ok(vec![APPROVE.to_string()])
+        );
+        assert!(validate(Operation::Start, &[]).is_err());
+        assert!(validate(Operation::Start, &["yes".to_string()]).is_err());
+        assert!(
+            validate(
+                Operation::Start,
+                &[APPROVE.to_string(), APPROVE.to_string()]
+            )
+            .is_err()
+        );
+    }
+}
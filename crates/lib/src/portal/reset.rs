@@ -0,0 +1,114 @@
+//! Partial resets (`portty reset --keep`)
+//!
+//! A reset returns the selection to the entries the session started with.
+//! `--keep` patterns exempt the entries they match: those stay as the user
+//! left them, selected or deselected, while everything else is reverted.
+//! A pattern without a `/` matches the file name (`*.pdf`), one with a `/`
+//! the whole path (`~/Downloads/**`). `file://` entries are matched by their
+//! path, other entries as written.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use super::file_chooser::file_uri_to_path;
+
+/// Entries a reset leaves alone
+#[derive(Debug, Clone, Default)]
+pub struct KeepPatterns {
+    /// Patterns matching the file name
+    names: GlobSet,
+    /// Patterns matching the whole path
+    paths: GlobSet,
+}
+
+impl KeepPatterns {
+    /// Compile `patterns`, with a leading `~/` standing for the home
+    /// directory
+    pub fn new(patterns: &[String]) -> Result<Self, String> {
+        let (mut names, mut paths) = (GlobSetBuilder::new(), GlobSetBuilder::new());
+        for pattern in patterns {
+            let expanded = match (pattern.strip_prefix("~/"), std::env::var_os("HOME")) {
+                (Some(rest), Some(home)) => format!("{}/{rest}", Path::new(&home).display()),
+                _ => pattern.clone(),
+            };
+            let glob = GlobBuilder::new(expanded.trim_end_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("invalid glob {pattern:?}: {}", e.kind()))?;
+            if expanded.contains('/') {
+                paths.add(glob);
+            } else {
+                names.add(glob);
+            }
+        }
+        Ok(Self {
+            names: names.build().map_err(|e| e.to_string())?,
+            paths: paths.build().map_err(|e| e.to_string())?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.paths.is_empty()
+    }
+
+    /// Whether `entry` is exempt from the reset
+    pub fn matches(&self, entry: &str) -> bool {
+        let path = file_uri_to_path(entry).unwrap_or_else(|| PathBuf::from(entry));
+        path.file_name()
+            .is_some_and(|name| self.names.is_match(name))
+            || self.paths.is_match(&path)
+    }
+}
+
+/// The selection after resetting `live` to `initial`, except for entries
+/// `keep` matches: the initial entries in order (without kept ones the user
+/// deselected), then the kept entries the user added
+pub fn reset(initial: &[String], live: &[String], keep: &KeepPatterns) -> Vec<String> {
+    if keep.is_empty() {
+        return initial.to_vec();
+    }
+    let live_set: HashSet<&str> = live.iter().map(String::as_str).collect();
+    let initial_set: HashSet<&str> = initial.iter().map(String::as_str).collect();
+    let restored = initial
+        .iter()
+        .filter(|entry| !keep.matches(entry) || live_set.contains(entry.as_str()));
+    let kept = live
+        .iter()
+        .filter(|entry| !initial_set.contains(entry.as_str()) && keep.matches(entry));
+    restored.chain(kept).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_leaves_matching_entries_as_they_are() {
+        let initial = vec!["file:///tmp/a.pdf".to_string(), "/tmp/b.txt".to_string()];
+        let live = vec![
+            "/tmp/b.txt".to_string(),
+            "file:///tmp/c.pdf".to_string(),
+            "/tmp/d.txt".to_string(),
+            "/srv/e.txt".to_string(),
+        ];
+
+        let all = KeepPatterns::new(&[]).unwrap();
+        assert_eq!(reset(&initial, &live, &all), initial);
+
+        // a.pdf stays deselected, c.pdf stays selected, d.txt is reverted
+        let pdfs = KeepPatterns::new(&["*.pdf".to_string()]).unwrap();
+        assert_eq!(
+            reset(&initial, &live, &pdfs),
+            ["/tmp/b.txt", "file:///tmp/c.pdf"]
+        );
+
+        let srv = KeepPatterns::new(&["/srv/**".to_string()]).unwrap();
+        assert_eq!(
+            reset(&initial, &live, &srv),
+            ["file:///tmp/a.pdf", "/tmp/b.txt", "/srv/e.txt"]
+        );
+        assert!(KeepPatterns::new(&["[".to_string()]).is_err());
+    }
+}
@@ -1,9 +1,9 @@
 use std::fmt::Display;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use crate::files;
+use crate::{Operation, files, uri};
 
 use super::AddResult;
 use super::intent::{Cardinality, Intent, IntentFamily};
@@ -31,22 +31,46 @@ pub struct SessionOptions {
     pub mode: ScreenshotMode,
     pub app_id: String,
     pub modal: bool,
+    /// Parent window identifier, e.g. `wayland:<handle>` or `x11:<xid>` (may be empty)
+    #[serde(default)]
+    pub parent_window: String,
+    /// More than one capture may be submitted (interactive screenshots with
+    /// `series_dir` set): the first is returned, the rest kept in
+    /// `series_dir`
+    #[serde(default)]
+    pub series: bool,
+}
+
+/// Whether a session with `options` accepts a screenshot series
+pub fn accepts_series(options: &serde_json::Value) -> bool {
+    options
+        .get("series")
+        .and_then(serde_json::Value::as_bool)
+        .unwrap_or(false)
 }
 
 /// Validate and transform screenshot submission.
 ///
-/// For screenshot: validates single entry, passes through as-is.
+/// For screenshot: validates single entry (any number with `series`), passes
+/// through as-is.
 /// For pick-color: validates single entry and color format, strips file:// prefix.
-pub fn validate(operation: &str, entries: &[String]) -> Result<Vec<String>, String> {
+pub fn validate(
+    operation: Operation,
+    entries: &[String],
+    series: bool,
+) -> Result<Vec<String>, String> {
     if entries.is_empty() {
         return Err("No entries in submission".to_string());
     }
+    if operation == Operation::Screenshot && series {
+        return Ok(entries.to_vec());
+    }
     if entries.len() > 1 {
         return Err(format!("Screenshot expects 1 entry, got {}", entries.len()));
     }
 
     match operation {
-        "pick-color" => {
+        Operation::PickColor => {
             let color_str = entries[0].strip_prefix("file://").unwrap_or(&entries[0]);
             parse_color(color_str).ok_or_else(|| {
                 format!(
@@ -60,21 +84,25 @@ pub fn validate(operation: &str, entries: &[String]) -> Result<Vec<String>, Stri
     }
 }
 
-pub fn materialize_intent(operation: &str, intent: &Intent) -> Result<Vec<String>, String> {
+pub fn materialize_intent(
+    operation: Operation,
+    intent: &Intent,
+    series: bool,
+) -> Result<Vec<String>, String> {
     match operation {
-        "screenshot" => {
+        Operation::Screenshot => {
             if intent.family != IntentFamily::Path {
                 return Err(format!(
                     "screenshot expects path intent, got {}",
                     intent.family
                 ));
             }
-            if intent.cardinality != Cardinality::Single {
+            if intent.cardinality != Cardinality::Single && !series {
                 return Err("screenshot expects a single path".to_string());
             }
-            validate(operation, &intent.values())
+            validate(operation, &intent.values(), series)
         }
-        "pick-color" => {
+        Operation::PickColor => {
             if intent.family != IntentFamily::Color {
                 return Err(format!(
                     "pick-color expects color intent, got {}",
@@ -84,7 +112,7 @@ pub fn materialize_intent(operation: &str, intent: &Intent) -> Result<Vec<String
             if intent.cardinality != Cardinality::Single {
                 return Err("pick-color expects a single color".to_string());
             }
-            validate(operation, &intent.values())
+            validate(operation, &intent.values(), false)
         }
         _ => Err(format!("unsupported screenshot operation: {operation}")),
     }
@@ -135,6 +163,17 @@ pub fn parse_color(s: &str) -> Option<(f64, f64, f64)> {
     None
 }
 
+/// Local path of a screenshot entry (a `file://` URI or a plain path).
+///
+/// Percent-escapes in URIs are decoded; returns `None` for non-file URIs.
+pub fn screenshot_path(entry: &str) -> Option<PathBuf> {
+    if entry.starts_with("file://") {
+        uri::to_path(entry)
+    } else {
+        (!entry.contains("://")).then(|| PathBuf::from(entry))
+    }
+}
+
 /// Smart add entries: screenshot always replaces (single entry).
 pub fn add_entries(sub_path: &Path, entries: &[String]) -> std::io::Result<AddResult> {
     files::write_lines(sub_path, entries)?;
@@ -208,4 +247,36 @@ mod tests {
         assert!(parse_color("#fff").is_none());
         assert!(parse_color("").is_none());
     }
+
+    #[test]
+    fn series_accepts_more_than_one_screenshot() {
+        let shots = vec!["/tmp/a.png".to_string(), "/tmp/b.png".to_string()];
+        assert!(validate(Operation::Screenshot, &shots, false).is_err());
+        assert_eq!(
+            validate(Operation::Screenshot, &shots, true).unwrap(),
+            shots
+        );
+        let colors = vec!["#ff0000".to_string(), "#00ff00".to_string()];
+        assert!(validate(Operation::PickColor, &colors, true).is_err());
+        assert!(accepts_series(&serde_json::json!({ "series": true })));
+        assert!(!accepts_series(&serde_json::json!({})));
+    }
+
+    #[test]
+    fn screenshot_path_from_uri_and_plain_path() {
+        assert_eq!(
+            screenshot_path("file:///tmp/shot%201.png"),
+            Some(PathBuf::from("/tmp/shot 1.png"))
+        );
+        assert_eq!(
+            screenshot_path("file://localhost/tmp/a.png"),
+            Some(PathBuf::from("/tmp/a.png"))
+        );
+        assert_eq!(
+            screenshot_path("/tmp/a.png"),
+            Some(PathBuf::from("/tmp/a.png"))
+        );
+        assert_eq!(screenshot_path("file://host/tmp/a.png"), None);
+        assert_eq!(screenshot_path("https://example.com/a.png"), None);
+    }
 }
@@ -0,0 +1,108 @@
+//! Selections saved by terminal file managers, for `portty select`.
+//!
+//! - lf: `$XDG_DATA_HOME/lf/files`, `copy` or `move` followed by one path per
+//!   line
+//! - ranger: `$XDG_DATA_HOME/ranger/copy_buffer`, one path per line
+//! - any other file: one path or `file://` URI per line, or NUL-separated
+//!   (nnn's selection, `find -print0`). A leading `copy`/`move`/`cut` line is
+//!   skipped.
+
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use crate::uri;
+
+/// Where to read a selection from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// lf's selection (`y`/`d` in lf)
+    Lf,
+    /// ranger's saved copy buffer
+    Ranger,
+    /// A selection file in any supported format
+    File(PathBuf),
+}
+
+impl Source {
+    /// File holding the selection
+    pub fn path(&self) -> PathBuf {
+        match self {
+            Self::Lf => data_dir().join("lf/files"),
+            Self::Ranger => data_dir().join("ranger/copy_buffer"),
+            Self::File(path) => path.clone(),
+        }
+    }
+
+    /// Read the selected paths.
+    pub fn read(&self) -> io::Result<Vec<PathBuf>> {
+        let path = self.path();
+        std::fs::read(&path)
+            .map(|content| parse(&content))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+    }
+}
+
+/// `$XDG_DATA_HOME`, or `~/.local/share`
+pub(crate) fn data_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".local/share")
+        })
+}
+
+/// Paths in a selection file. Entries that are neither paths nor `file://`
+/// URIs are skipped.
+pub fn parse(content: &[u8]) -> Vec<PathBuf> {
+    let separator = if content.contains(&0) { b'\0' } else { b'\n' };
+    let mut entries = content
+        .split(|&b| b == separator)
+        .map(|entry| entry.strip_suffix(b"\r").unwrap_or(entry))
+        .filter(|entry| !entry.is_empty())
+        .peekable();
+
+    // lf and GNOME-style clipboard lists start with the pending operation
+    entries.next_if(|first| matches!(*first, b"copy" | b"move" | b"cut"));
+
+    entries.filter_map(entry_path).collect()
+}
+
+fn entry_path(entry: &[u8]) -> Option<PathBuf> {
+    if entry.starts_with(b"file://") {
+        return uri::to_path(std::str::from_utf8(entry).ok()?);
+    }
+    if entry.windows(3).any(|w| w == b"://") {
+        return None;
+    }
+    Some(PathBuf::from(OsStr::from_bytes(entry)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_formats() {
+        let expected = vec![PathBuf::from("/a/one"), PathBuf::from("/b/two words")];
+
+        // lf
+        assert_eq!(parse(b"copy\n/a/one\n/b/two words\n"), expected);
+        // ranger, CRLF tolerated
+        assert_eq!(parse(b"/a/one\r\n/b/two words\r\n"), expected);
+        // NUL-separated (nnn)
+        assert_eq!(parse(b"/a/one\0/b/two words\0"), expected);
+        // URIs, other schemes skipped
+        assert_eq!(
+            parse(b"cut\nfile:///a/one\nsftp://host/x\nfile:///b/two%20words\n"),
+            expected
+        );
+        // Newlines inside NUL-separated names are kept
+        assert_eq!(
+            parse(b"/a/line\nbreak\0"),
+            vec![PathBuf::from("/a/line\nbreak")]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Session snapshots (`portty export` / `portty import`)
+//!
+//! A snapshot is what was done in a session, kept in a JSON file: the
+//! selection and the options the session changed ([`SESSION_CHOICES`]: the
+//! conflict strategy, the files placed one at a time, and the dialog's
+//! choices). Importing it into another session of the same operation, say
+//! after a dialog was cancelled by accident and opened again, picks up where
+//! the first left off. What the new request decides itself (its folder,
+//! candidates, filters) stays as it is.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::options::{self, SESSION_CHOICES};
+use crate::{Operation, files};
+
+/// Version written into new snapshots
+const VERSION: u32 = 1;
+
+/// The state of a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub portal: String,
+    pub operation: String,
+    /// The selection, one entry per submission line
+    pub entries: Vec<String>,
+    /// The [`SESSION_CHOICES`] the session made
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub options: Map<String, Value>,
+}
+
+impl Snapshot {
+    /// Take a snapshot of the `operation` session in `session_dir`
+    pub fn take(session_dir: &Path, operation: Operation) -> std::io::Result<Self> {
+        let session_options = options::read(session_dir)?;
+        let options = SESSION_CHOICES
+            .iter()
+            .filter_map(|key| Some((key.to_string(), session_options.get(*key)?.clone())))
+            .collect();
+        Ok(Self {
+            version: VERSION,
+            portal: operation.portal().as_str().to_string(),
+            operation: operation.as_str().to_string(),
+            entries: files::read_lines(&session_dir.join("submission")),
+            options,
+        })
+    }
+
+    /// Replace the state of the `operation` session in `session_dir` with
+    /// this snapshot. Refuses a snapshot of another operation, or one the
+    /// session could not submit; returns notes on what did not carry over.
+    pub fn restore(&self, session_dir: &Path, operation: Operation) -> Result<Vec<String>, String> {
+        if self.version > VERSION {
+            return Err(format!(
+                "snapshot version {} is newer than this portty reads ({VERSION})",
+                self.version
+            ));
+        }
+        if self.portal != operation.portal().as_str() || self.operation != operation.as_str() {
+            return Err(format!(
+                "the snapshot is of a {} {} session, not {} {}",
+                self.portal,
+                self.operation,
+                operation.portal(),
+                operation
+            ));
+        }
+
+        let mut session_options =
+            options::read(session_dir).map_err(|e| format!("reading options: {e}"))?;
+        let Some(fields) = session_options.as_object_mut() else {
+            return Err("options.json is not an object".to_string());
+        };
+        for key in SESSION_CHOICES {
+            match self.options.get(*key) {
+                Some(value) => fields.insert(key.to_string(), value.clone()),
+                None => fields.remove(*key),
+            };
+        }
+        let notes = self.fit(operation, &mut session_options)?;
+
+        files::write_lines(&session_dir.join("submission"), &self.entries)
+            .map_err(|e| format!("writing the selection: {e}"))?;
+        options::write(session_dir, &session_options)
+            .map_err(|e| format!("writing options: {e}"))?;
+        Ok(notes)
+    }
+
+    /// Check the snapshot against the new session's `options` (with the
+    /// snapshot's choices in), dropping choices it no longer offers
+    #[cfg(feature = "portal-file-chooser")]
+    fn fit(&self, operation: Operation, options: &mut Value) -> Result<Vec<String>, String> {
+        use super::file_chooser::{SelectionMode, SessionOptions};
+
+        if operation.portal() != crate::Portal::FileChooser {
+            return Ok(Vec::new());
+        }
+        let mut opts: SessionOptions =
+            serde_json::from_value(options.clone()).map_err(|e| format!("invalid options: {e}"))?;
+        let single = matches!(
+            opts.mode,
+            SelectionMode::Pick {
+                multiple: false,
+                ..
+            } | SelectionMode::Save
+        );
+        if single && self.entries.len() > 1 {
+            return Err(format!(
+                "the dialog takes one entry, the snapshot has {}",
+                self.entries.len()
+            ));
+        }
+
+        let mut notes = Vec::new();
+        let stale: Vec<String> = opts
+            .chosen
+            .iter()
+            .filter(|(id, value)| opts.choice(id).is_none_or(|choice| !choice.accepts(value)))
+            .map(|(id, value)| format!("choice {id}={value} is not offered by this dialog"))
+            .collect();
+        if !stale.is_empty() {
+            notes.extend(stale);
+            let offered =
+                opts.chosen.clone().into_iter().filter(|(id, value)| {
+                    opts.choice(id).is_some_and(|choice| choice.accepts(value))
+                });
+            opts.chosen = offered.collect();
+            options["chosen"] = serde_json::to_value(&opts.chosen).map_err(|e| e.to_string())?;
+        }
+        if let Some(per_file) = &mut opts.per_file {
+            let candidates = &opts.candidates;
+            let before = per_file.skipped.len();
+            per_file.skipped.retain(|name| candidates.contains(name));
+            if per_file.skipped.len() < before {
+                notes.push(format!(
+                    "{} skipped file(s) are not among this dialog's files",
+                    before - per_file.skipped.len()
+                ));
+                options["per_file"] =
+                    serde_json::to_value(&*per_file).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(notes)
+    }
+
+    #[cfg(not(feature = "portal-file-chooser"))]
+    fn fit(&self, _operation: Operation, _options: &mut Value) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+
+    /// Read a snapshot written by [`Snapshot::to_json`]
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("not a portty snapshot: {e}"))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+#[cfg(all(test, feature = "portal-file-chooser"))]
+mod tests {
+    use super::*;
+    use crate::portal::file_chooser::{Choice, SelectionMode, SessionOptions};
+
+    fn session(dir: &Path, options: &SessionOptions, entries: &[&str]) {
+        options::write(dir, &serde_json::to_value(options).unwrap()).unwrap();
+        let entries: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+        files::write_lines(&dir.join("submission"), &entries).unwrap();
+    }
+
+    #[test]
+    fn snapshots_move_between_sessions() {
+        let (first, second) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let encoding = Choice {
+            id: "encoding".into(),
+            label: "Encoding".into(),
+            options: vec![("utf8".into(), "Unicode".into())],
+            default: String::new(),
+        };
+        let mut options = SessionOptions {
+            mode: SelectionMode::Pick {
+                multiple: true,
+                directory: false,
+            },
+            choices: vec![encoding],
+            ..Default::default()
+        };
+        options.choose("encoding", "utf8").unwrap();
+        session(first.path(), &options, &["/tmp/a", "/tmp/b"]);
+
+        let snapshot = Snapshot::take(first.path(), Operation::OpenFile).unwrap();
+        assert_eq!(snapshot.entries, ["/tmp/a", "/tmp/b"]);
+        let snapshot = Snapshot::from_json(&snapshot.to_json()).unwrap();
+
+        // The dialog opened again, without the choice this time
+        let reopened = SessionOptions {
+            mode: options.mode,
+            ..Default::default()
+        };
+        session(second.path(), &reopened, &[]);
+        let notes = snapshot
+            .restore(second.path(), Operation::OpenFile)
+            .unwrap();
+        assert_eq!(notes.len(), 1, "{notes:?}");
+        assert_eq!(
+            files::read_lines(&second.path().join("submission")),
+            ["/tmp/a", "/tmp/b"]
+        );
+        let restored: SessionOptions =
+            serde_json::from_value(options::read(second.path()).unwrap()).unwrap();
+        assert!(restored.chosen.is_empty());
+
+        // Another operation, or a dialog taking one file, is refused
+        assert!(
+            snapshot
+                .restore(second.path(), Operation::SaveFile)
+                .is_err()
+        );
+        let single = SessionOptions::default();
+        session(second.path(), &single, &[]);
+        assert!(
+            snapshot
+                .restore(second.path(), Operation::OpenFile)
+                .is_err()
+        );
+        assert!(files::read_lines(&second.path().join("submission")).is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+//! Disk usage budgets for session state and payloads
+//!
+//! `/tmp/portty` usually lives on RAM-backed tmpfs. Sessions can be given a
+//! budget individually and in total, and payloads above a size threshold (e.g.
+//! large screenshots) are placed under `$XDG_CACHE_HOME/portty` instead.
+//!
+//! The budgets are advisory: [`Quota::check`] runs when a session starts and
+//! before a payload directory is handed out, with the size the caller
+//! announces. What is written afterwards is not watched.
+//!
+//! The daemon passes the configured limits to spawned processes through
+//! environment variables (see [`Quota::env`]); the CLI reads them back with
+//! [`Quota::from_env`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::paths;
+
+/// Env var carrying the per-session cap in bytes
+pub const SESSION_QUOTA_ENV: &str = "PORTTY_SESSION_QUOTA";
+/// Env var carrying the global cap in bytes
+pub const TOTAL_QUOTA_ENV: &str = "PORTTY_TOTAL_QUOTA";
+/// Env var carrying the payload size above which the cache dir is used
+pub const PAYLOAD_THRESHOLD_ENV: &str = "PORTTY_PAYLOAD_THRESHOLD";
+
+/// Disk usage limits, in bytes. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Cap for one session (its directory plus its payloads)
+    pub session: Option<u64>,
+    /// Cap for everything under the base directory plus cached payloads
+    pub total: Option<u64>,
+    /// Payloads larger than this go to `$XDG_CACHE_HOME/portty`
+    pub payload_threshold: Option<u64>,
+}
+
+/// Quota violations
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum QuotaError {
+    #[error("session quota exceeded: {used} + {incoming} bytes > {limit} bytes")]
+    Session {
+        used: u64,
+        incoming: u64,
+        limit: u64,
+    },
+    #[error("total quota exceeded: {used} + {incoming} bytes > {limit} bytes (try `portty gc`)")]
+    Total {
+        used: u64,
+        incoming: u64,
+        limit: u64,
+    },
+}
+
+impl Quota {
+    /// Read limits from the session environment
+    pub fn from_env() -> Self {
+        let read = |key| std::env::var(key).ok().and_then(|v| parse_size(&v));
+        Self {
+            session: read(SESSION_QUOTA_ENV),
+            total: read(TOTAL_QUOTA_ENV),
+            payload_threshold: read(PAYLOAD_THRESHOLD_ENV),
+        }
+    }
+
+    /// Environment variables describing these limits
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        [
+            (SESSION_QUOTA_ENV, self.session),
+            (TOTAL_QUOTA_ENV, self.total),
+            (PAYLOAD_THRESHOLD_ENV, self.payload_threshold),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|v| (key, v.to_string())))
+        .collect()
+    }
+
+    /// Check whether `incoming` more bytes fit.
+    ///
+    /// With `session_id`, the session's directory and payloads count against
+    /// the per-session cap as well.
+    pub fn check(&self, session_id: Option<&str>, incoming: u64) -> Result<(), QuotaError> {
+        if let (Some(limit), Some(id)) = (self.session, session_id) {
+            let used = session_usage(id);
+            if used.saturating_add(incoming) > limit {
+                return Err(QuotaError::Session {
+                    used,
+                    incoming,
+                    limit,
+                });
+            }
+        }
+        if let Some(limit) = self.total {
+            let used = total_usage();
+            if used.saturating_add(incoming) > limit {
+                return Err(QuotaError::Total {
+                    used,
+                    incoming,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Directory a payload of `size` bytes for `session_id` should be written to.
+    ///
+    /// Small payloads stay on the (fast, RAM-backed) base dir; larger ones go to
+    /// the disk-backed cache. Payload dirs outlive their session so the app can
+    /// still read the returned URI; `portty gc` removes them later.
+    pub fn payload_dir(&self, session_id: &str, size: u64) -> PathBuf {
+        match self.payload_threshold {
+            Some(threshold) if size > threshold => cache_payloads_dir().join(session_id),
+            _ => payloads_dir().join(session_id),
+        }
+    }
+}
+
+/// Payload directory on the base (tmpfs) dir
+pub fn payloads_dir() -> PathBuf {
+    paths::base_dir().join("payloads")
+}
+
+/// Payload directory on disk (`$XDG_CACHE_HOME/portty/payloads`)
+pub fn cache_payloads_dir() -> PathBuf {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(|| paths::base_dir().join("cache"));
+    cache.join("portty").join("payloads")
+}
+
+/// Bytes used by a session: its directory plus both payload locations
+pub fn session_usage(session_id: &str) -> u64 {
+    dir_usage(&paths::base_dir().join(session_id))
+        + dir_usage(&payloads_dir().join(session_id))
+        + dir_usage(&cache_payloads_dir().join(session_id))
+}
+
+/// Bytes used by all portty state (base dir plus cached payloads)
+pub fn total_usage() -> u64 {
+    dir_usage(&paths::base_dir()) + dir_usage(&cache_payloads_dir())
+}
+
+/// Recursive size of regular files under `path`. Symlinks are not followed.
+pub fn dir_usage(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if meta.is_file() {
+        return meta.len();
+    }
+    if !meta.is_dir() {
+        return 0;
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| dir_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Parse a byte size: plain bytes or a `K`/`M`/`G` suffix (powers of 1024,
+/// optional trailing `B`/`iB`), e.g. `65536`, `512K`, `64MiB`, `1G`.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let s = s
+        .strip_suffix("iB")
+        .or_else(|| s.strip_suffix('B'))
+        .unwrap_or(s);
+    let (digits, shift) = match s.char_indices().last()? {
+        (i, 'k' | 'K') => (&s[..i], 10),
+        (i, 'm' | 'M') => (&s[..i], 20),
+        (i, 'g' | 'G') => (&s[..i], 30),
+        _ => (s, 0),
+    };
+    digits.trim().parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("65536"), Some(65536));
+        assert_eq!(parse_size("512K"), Some(512 * 1024));
+        assert_eq!(parse_size("64MiB"), Some(64 << 20));
+        assert_eq!(parse_size(" 1G "), Some(1 << 30));
+        assert_eq!(parse_size("2kb"), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("lots"), None);
+    }
+
+    #[test]
+    fn env_roundtrip_skips_unlimited() {
+        let quota = Quota {
+            session: Some(10),
+            total: None,
+            payload_threshold: Some(5),
+        };
+        assert_eq!(
+            quota.env(),
+            vec![
+                (SESSION_QUOTA_ENV, "10".to_string()),
+                (PAYLOAD_THRESHOLD_ENV, "5".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn dir_usage_counts_nested_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("a"), [0u8; 10]).unwrap();
+        fs::create_dir(tmp.path().join("sub")).unwrap();
+        fs::write(tmp.path().join("sub/b"), [0u8; 5]).unwrap();
+        assert_eq!(dir_usage(tmp.path()), 15);
+        assert_eq!(dir_usage(&tmp.path().join("missing")), 0);
+    }
+}
@@ -0,0 +1,134 @@
+//! Remote paths (`host:path`) staged with scp or rsync
+//!
+//! With `remote = "scp"` (or `"rsync"`) configured for an operation, the CLI
+//! accepts scp-style `host:path` entries in a session: the file is copied into
+//! the session's payload directory and the local copy is selected instead.
+//! Copies keep the remote file's modification time and mode, and with rsync
+//! its extended attributes too, unless the caller asks for plain copies.
+//! Payload directories outlive the session, so the app can still read the
+//! file after the dialog closes; `portty gc` removes them later.
+//!
+//! The daemon passes the configured tool to spawned processes through an
+//! environment variable (see [`Remote::env`]); the CLI reads it back with
+//! [`Remote::from_env`].
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::Deserialize;
+
+/// Env var carrying the tool used to stage remote paths
+pub const REMOTE_ENV: &str = "PORTTY_REMOTE";
+
+/// Tool that copies a remote path to the local machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Remote {
+    Scp,
+    Rsync,
+}
+
+impl Remote {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Scp => "scp",
+            Self::Rsync => "rsync",
+        }
+    }
+
+    /// The configured tool, if the daemon enabled remote paths for this session
+    pub fn from_env() -> Option<Self> {
+        match std::env::var(REMOTE_ENV).ok()?.as_str() {
+            "scp" => Some(Self::Scp),
+            "rsync" => Some(Self::Rsync),
+            _ => None,
+        }
+    }
+
+    /// Environment variable enabling remote paths with this tool
+    pub fn env(self) -> (&'static str, String) {
+        (REMOTE_ENV, self.as_str().to_string())
+    }
+
+    /// Copy `host:path` into `dir`, returning the local copy. Directories are
+    /// copied recursively. With `preserve`, times and modes are kept (scp
+    /// cannot copy extended attributes, rsync does); without, the copies get
+    /// the current time and the default mode. The tool's own prompts
+    /// (passwords, host keys) go to the terminal.
+    pub fn fetch(self, host: &str, path: &str, dir: &Path, preserve: bool) -> io::Result<PathBuf> {
+        let name = Path::new(path.trim_end_matches('/'))
+            .file_name()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{host}:{path} does not name a file"),
+                )
+            })?
+            .to_owned();
+        let source = format!("{host}:{path}");
+
+        let mut cmd = Command::new(self.as_str());
+        match (self, preserve) {
+            (Self::Scp, true) => cmd.args(["-r", "-p", "-q", "--"]),
+            (Self::Scp, false) => cmd.args(["-r", "-q", "--"]),
+            // Without the trailing slash rsync copies a directory itself,
+            // not its contents
+            (Self::Rsync, true) => cmd.args(["-a", "-X", "--"]),
+            (Self::Rsync, false) => cmd.args(["-r", "-l", "--"]),
+        };
+        let status = cmd
+            .arg(source.trim_end_matches('/'))
+            .arg(dir)
+            .stdin(Stdio::inherit())
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{} {source} failed ({status})",
+                self.as_str()
+            )));
+        }
+        Ok(dir.join(name))
+    }
+}
+
+/// Split an scp-style `host:path` entry (`user@host:path`, `[::1]:path`).
+///
+/// Like scp, an entry is remote when a colon comes before any slash. URIs
+/// (`scheme://...`) and `file:` entries are local.
+pub fn split_remote(entry: &str) -> Option<(&str, &str)> {
+    let (host, path) = if let Some(rest) = entry.strip_prefix('[') {
+        let (addr, path) = rest.split_once("]:")?;
+        (&entry[..addr.len() + 2], path)
+    } else {
+        entry.split_once(':')?
+    };
+    if host.is_empty() || host.contains('/') || path.starts_with("//") || host == "file" {
+        return None;
+    }
+    Some((host, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_remote_entries() {
+        assert_eq!(split_remote("box:notes.txt"), Some(("box", "notes.txt")));
+        assert_eq!(
+            split_remote("me@box:/srv/a b.pdf"),
+            Some(("me@box", "/srv/a b.pdf"))
+        );
+        assert_eq!(split_remote("[::1]:~/x"), Some(("[::1]", "~/x")));
+        assert_eq!(split_remote("box:"), Some(("box", "")));
+
+        assert_eq!(split_remote("/home/me/a:b"), None);
+        assert_eq!(split_remote("./a:b"), None);
+        assert_eq!(split_remote("notes.txt"), None);
+        assert_eq!(split_remote("file:///home/me/a"), None);
+        assert_eq!(split_remote("file:a"), None);
+        assert_eq!(split_remote("https://example.org/a"), None);
+        assert_eq!(split_remote(":a"), None);
+    }
+}
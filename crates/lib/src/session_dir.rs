@@ -0,0 +1,427 @@
+//! Session directory preparation.
+//!
+//! A session lives in `<base_dir>/<id>/`:
+//!
+//! - `portal`: portal and operation, one per line
+//! - `options.json`: portal options (see [`crate::portal::options`])
+//! - `submission`: current entries, one per line
+//! - `bin/`: command shims prepended to the session's `PATH`
+//!
+//! Creating it sits between the D-Bus request and the terminal prompt, so the
+//! two costly parts are moved out of that path. [`DirPool`] keeps a few empty
+//! private directories ready to be renamed into place, and [`ShimTemplates`]
+//! renders each distinct shim set once and links `bin/` to it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::paths;
+
+/// Pool directory under the base dir
+pub const POOL_DIR: &str = ".pool";
+
+/// Shim template directory under the base dir
+pub const SHIMS_DIR: &str = ".shims";
+
+/// Directories the daemon keeps ready
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+/// Write the session state files into an existing session dir.
+pub fn write_state(
+    dir: &Path,
+    portal: &str,
+    operation: &str,
+    options: &serde_json::Value,
+    initial_entries: &[String],
+) -> io::Result<()> {
+    crate::files::write_atomic(&dir.join("portal"), format!("{portal}\n{operation}"))?;
+    crate::portal::options::write(dir, options)?;
+    crate::files::write_lines(&dir.join("submission"), initial_entries)
+}
+
+/// Directory pool and shim templates of a daemon
+#[derive(Debug, Default)]
+pub struct SessionDirs {
+    pub pool: DirPool,
+    pub shims: ShimTemplates,
+}
+
+impl SessionDirs {
+    /// Create the session dir `dir` with its state files. `bin/` is only
+    /// created when the session runs a command (`bins` is set); headless
+    /// sessions have no `PATH` to extend.
+    pub fn create(
+        &self,
+        dir: &Path,
+        portal: &str,
+        operation: &str,
+        options: &serde_json::Value,
+        initial_entries: &[String],
+        bins: Option<&HashMap<String, String>>,
+    ) -> io::Result<()> {
+        self.pool.take(dir)?;
+        write_state(dir, portal, operation, options, initial_entries)?;
+        if let Some(bins) = bins {
+            self.shims.link(&dir.join("bin"), bins)?;
+        }
+        Ok(())
+    }
+}
+
+/// Create a private (0o700) directory
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    fs::DirBuilder::new().mode(0o700).create(dir)
+}
+
+/// Rename `from` to `to`, failing with `AlreadyExists` instead of replacing
+/// an empty directory at `to`
+fn rename_noreplace(from: &Path, to: &Path) -> io::Result<()> {
+    let from = CString::new(from.as_os_str().as_bytes())?;
+    let to = CString::new(to.as_os_str().as_bytes())?;
+    // SAFETY: both paths are NUL-terminated and outlive the call
+    let ret = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            from.as_ptr(),
+            libc::AT_FDCWD,
+            to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Empty session directories created ahead of time.
+///
+/// [`take`](Self::take) renames a ready directory to the session path (falling
+/// back to creating it), and [`refill`](Self::refill) tops the pool up again
+/// off the request path. Pool directories live next to the sessions, so the
+/// rename never crosses filesystems.
+#[derive(Debug)]
+pub struct DirPool {
+    root: PathBuf,
+    size: usize,
+    ready: Mutex<Vec<PathBuf>>,
+    primed: AtomicBool,
+    seq: AtomicU64,
+}
+
+impl Default for DirPool {
+    fn default() -> Self {
+        Self::new(paths::base_dir().join(POOL_DIR), DEFAULT_POOL_SIZE)
+    }
+}
+
+impl DirPool {
+    /// Pool of `size` directories in `root`. Nothing is created until the
+    /// first [`refill`](Self::refill).
+    pub fn new(root: PathBuf, size: usize) -> Self {
+        Self {
+            root,
+            size,
+            ready: Mutex::new(Vec::new()),
+            primed: AtomicBool::new(false),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Create the directory `dest`, from the pool if one is ready. Fails with
+    /// `AlreadyExists` if `dest` exists.
+    pub fn take(&self, dest: &Path) -> io::Result<()> {
+        let pooled = self.ready.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        if let Some(pooled) = pooled {
+            match rename_noreplace(&pooled, dest) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    self.ready
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push(pooled);
+                    return Err(e);
+                }
+                // Gone from the pool, or a filesystem without RENAME_NOREPLACE
+                Err(_) => {}
+            }
+        }
+        create_private_dir(dest)
+    }
+
+    /// Create directories until the pool is full.
+    ///
+    /// The first refill clears directories left behind by a previous process.
+    pub fn refill(&self) -> io::Result<()> {
+        if !self.primed.swap(true, Ordering::Relaxed) {
+            match fs::remove_dir_all(&self.root) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            create_private_dir(&self.root)?;
+        }
+
+        while self.len() < self.size {
+            let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+            let dir = self.root.join(seq.to_string());
+            create_private_dir(&dir)?;
+            self.ready
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(dir);
+        }
+        Ok(())
+    }
+
+    /// Number of ready directories
+    pub fn len(&self) -> usize {
+        self.ready.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Rendered shim sets shared by sessions.
+///
+/// Sessions of the same portal and config resolve the same shims, so each
+/// distinct set is written once to `<root>/<hash>/` and a session's `bin/` is
+/// a symlink to it. Templates are shared: a session that edits its shims edits
+/// them for later sessions with the same set too.
+///
+/// With [`ShimTemplates::with_cli`], shims run that `portty` whatever the
+/// `PATH` of the shell calling them.
+#[derive(Debug)]
+pub struct ShimTemplates {
+    root: PathBuf,
+    cli: Option<PathBuf>,
+    rendered: Mutex<HashMap<u64, PathBuf>>,
+}
+
+impl Default for ShimTemplates {
+    fn default() -> Self {
+        Self::new(paths::base_dir().join(SHIMS_DIR))
+    }
+}
+
+impl ShimTemplates {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            cli: None,
+            rendered: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run `portty` in shims as the executable at `cli` (an absolute path)
+    pub fn with_cli(mut self, cli: Option<PathBuf>) -> Self {
+        self.cli = cli;
+        self
+    }
+
+    /// The `portty` shims run, when set with [`ShimTemplates::with_cli`]
+    pub fn cli(&self) -> Option<&Path> {
+        self.cli.as_deref()
+    }
+
+    /// Make `bin_dir` provide `bins` (shim name -> shell command).
+    ///
+    /// An existing link is replaced atomically, so a shell with `bin_dir` on
+    /// its `PATH` switches to the new set without ever missing a shim.
+    pub fn link(&self, bin_dir: &Path, bins: &HashMap<String, String>) -> io::Result<()> {
+        let template = self.template(bins)?;
+        let name = bin_dir.file_name().unwrap_or_default().to_string_lossy();
+        let tmp = bin_dir.with_file_name(format!(".{name}.tmp"));
+        let _ = fs::remove_file(&tmp);
+        std::os::unix::fs::symlink(template, &tmp)?;
+        fs::rename(&tmp, bin_dir)
+    }
+
+    /// Directory holding the rendered `bins`, rendering it on first use.
+    fn template(&self, bins: &HashMap<String, String>) -> io::Result<PathBuf> {
+        let sorted: BTreeMap<_, _> = bins.iter().collect();
+        let mut hasher = std::hash::DefaultHasher::new();
+        sorted.hash(&mut hasher);
+        self.cli.hash(&mut hasher);
+        let key = hasher.finish();
+
+        let mut rendered = self.rendered.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(dir) = rendered.get(&key)
+            && dir.is_dir()
+        {
+            return Ok(dir.clone());
+        }
+
+        let dir = self.root.join(format!("{key:016x}"));
+        render(&dir, &sorted, self.cli.as_deref())?;
+        rendered.insert(key, dir.clone());
+        Ok(dir)
+    }
+}
+
+/// Write `bins` as executable shims into `dir`, replacing it. With `cli`,
+/// a `portty` function running it comes first in each.
+///
+/// The set is written to a temporary directory first, so a session never sees
+/// a partial template.
+fn render(dir: &Path, bins: &BTreeMap<&String, &String>, cli: Option<&Path>) -> io::Result<()> {
+    let root = dir.parent().unwrap_or(Path::new("."));
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(root)?;
+
+    let tmp = dir.with_extension(format!("tmp-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&tmp);
+    fs::DirBuilder::new().mode(0o700).create(&tmp)?;
+    let prelude = cli
+        .map(|cli| {
+            let quoted = cli.to_string_lossy().replace('\'', r"'\''");
+            format!("portty() {{ '{quoted}' \"$@\"; }}\n")
+        })
+        .unwrap_or_default();
+    for (name, command) in bins {
+        let mut shim = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o700)
+            .open(tmp.join(name))?;
+        write!(shim, "#!/bin/sh\n{prelude}{command}\n")?;
+    }
+
+    let _ = fs::remove_dir_all(dir);
+    fs::rename(&tmp, dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    #[test]
+    fn pool_hands_out_private_dirs() {
+        let tmp = tempdir().unwrap();
+        let pool = DirPool::new(tmp.path().join(POOL_DIR), 2);
+        pool.refill().unwrap();
+        assert_eq!(pool.len(), 2);
+
+        for name in ["a", "b", "c"] {
+            let dest = tmp.path().join(name);
+            pool.take(&dest).unwrap();
+            let mode = fs::metadata(&dest).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode, 0o700);
+        }
+        assert!(pool.is_empty());
+
+        // Taken paths are never reused
+        pool.refill().unwrap();
+        let err = pool.take(&tmp.path().join("a")).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(pool.len(), 2);
+
+        // A new pool over the same root starts clean
+        let pool = DirPool::new(tmp.path().join(POOL_DIR), 1);
+        pool.refill().unwrap();
+        assert_eq!(fs::read_dir(tmp.path().join(POOL_DIR)).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn session_files_are_private() {
+        let tmp = tempdir().unwrap();
+        let dirs = SessionDirs {
+            pool: DirPool::new(tmp.path().join(POOL_DIR), 1),
+            shims: ShimTemplates::new(tmp.path().join(SHIMS_DIR)),
+        };
+        let dir = tmp.path().join("s1");
+        // Enough candidates for a sidecar file
+        let many: Vec<_> = (0..=crate::portal::options::SPILL_THRESHOLD)
+            .map(|i| format!("f{i}.txt"))
+            .collect();
+        let options = serde_json::json!({ "candidates": many });
+        let bins = HashMap::from([("submit".to_string(), "portty submit".to_string())]);
+        dirs.create(
+            &dir,
+            "file-chooser",
+            "save-files",
+            &options,
+            &["/tmp/a".to_string()],
+            Some(&bins),
+        )
+        .unwrap();
+        crate::portal::intent::queue::write_sort(&dir, crate::SortOrder::Name).unwrap();
+
+        fn check(path: &Path) {
+            let meta = fs::metadata(path).unwrap();
+            let mode = meta.permissions().mode() & 0o777;
+            assert_eq!(mode & 0o077, 0, "{} is mode {mode:o}", path.display());
+            if meta.is_dir() {
+                for entry in fs::read_dir(path).unwrap() {
+                    check(&entry.unwrap().path());
+                }
+            }
+        }
+        // The base dir itself is made private by paths::ensure_base_dir
+        for entry in fs::read_dir(tmp.path()).unwrap() {
+            check(&entry.unwrap().path());
+        }
+    }
+
+    #[test]
+    fn shim_sets_are_rendered_once() {
+        let tmp = tempdir().unwrap();
+        let templates = ShimTemplates::new(tmp.path().join(SHIMS_DIR));
+        let bins = HashMap::from([
+            ("submit".to_string(), "portty submit".to_string()),
+            ("sel".to_string(), "portty add path \"$@\"".to_string()),
+        ]);
+
+        for session in ["s1", "s2"] {
+            let dir = tmp.path().join(session);
+            fs::create_dir(&dir).unwrap();
+            templates.link(&dir.join("bin"), &bins).unwrap();
+            let shim = dir.join("bin/sel");
+            assert_eq!(
+                fs::read_to_string(&shim).unwrap(),
+                "#!/bin/sh\nportty add path \"$@\"\n"
+            );
+            assert_eq!(
+                fs::metadata(&shim).unwrap().permissions().mode() & 0o777,
+                0o700
+            );
+        }
+        assert_eq!(fs::read_dir(tmp.path().join(SHIMS_DIR)).unwrap().count(), 1);
+
+        // Relinking swaps the set in place
+        let other = HashMap::from([("cancel".to_string(), "portty cancel".to_string())]);
+        let bin = tmp.path().join("s1/bin");
+        templates.link(&bin, &other).unwrap();
+        assert!(!bin.join("submit").exists());
+        assert!(bin.join("cancel").exists());
+    }
+
+    #[test]
+    fn shims_run_the_given_cli() {
+        let tmp = tempdir().unwrap();
+        let templates = ShimTemplates::new(tmp.path().join(SHIMS_DIR))
+            .with_cli(Some(PathBuf::from("/opt/it's/portty")));
+        let bins = HashMap::from([("submit".to_string(), "portty submit".to_string())]);
+        let dir = tmp.path().join("s1");
+        fs::create_dir(&dir).unwrap();
+        templates.link(&dir.join("bin"), &bins).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("bin/submit")).unwrap(),
+            "#!/bin/sh\nportty() { '/opt/it'\\''s/portty' \"$@\"; }\nportty submit\n"
+        );
+    }
+}
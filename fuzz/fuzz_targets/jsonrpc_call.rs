@@ -0,0 +1,17 @@
+//! JSON-RPC call decoding: must never panic, and decoded calls must roundtrip.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use libportty::jsonrpc::{decode_call, encode_call};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(value) = serde_json::from_slice::<serde_json::Value>(data) else {
+        return;
+    };
+    if let Ok(call) = decode_call(&value) {
+        let id = call.id.clone().unwrap_or(serde_json::Value::Null);
+        let again = decode_call(&encode_call(id, &call.request)).expect("re-encoded call decodes");
+        assert_eq!(again.request, call.request);
+    }
+});
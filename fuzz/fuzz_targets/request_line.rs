@@ -0,0 +1,17 @@
+//! Text protocol request decoding: must never panic, and anything that
+//! decodes must survive an encode/decode roundtrip.
+
+#![no_main]
+
+use std::io::{BufReader, Cursor};
+
+use libfuzzer_sys::fuzz_target;
+use libportty::Request;
+use libportty::codec::read_request_limited;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = BufReader::with_capacity(16, Cursor::new(data));
+    if let Ok(req) = read_request_limited(&mut reader, 4096) {
+        assert_eq!(Request::decode(&req.encode()), Ok(req));
+    }
+});
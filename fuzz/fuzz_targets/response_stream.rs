@@ -0,0 +1,17 @@
+//! Multi-line response decoding (session listings, status, streamed
+//! selections): must never panic.
+
+#![no_main]
+
+use std::io::{BufReader, Cursor};
+
+use libfuzzer_sys::fuzz_target;
+use libportty::codec::{read_response, read_selection};
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = BufReader::with_capacity(16, Cursor::new(data));
+    let _ = read_response(&mut reader);
+
+    let mut reader = BufReader::with_capacity(16, Cursor::new(data));
+    let _ = read_selection(&mut reader, |_| {});
+});